@@ -0,0 +1,625 @@
+//! Instruction builders for off-chain Rust clients (`no-entrypoint` builds)
+//!
+//! Each builder returns a complete `Instruction`: the account metas in the
+//! order the processor reads them, with every PDA derived here, and the
+//! Borsh-encoded `StakingInstruction`. Callers only pass the keys that can't
+//! be derived (mint, authority, owner, ...).
+//!
+//! Trailing accounts that depend on pool state (authority proofs for
+//! InitializePool, the depositor's allowlist entry, stake account or the
+//! current audit log page for DepositRewards) are not added; push them onto
+//! `accounts`.
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+
+use crate::{
+    pfee::FeeOwnershipAccounts,
+    state::{
+        derive_pool_pda, derive_user_stake_pda, derive_user_stake_position_pda, derive_vault_pda,
+        AuditLogPage, DepositorAllowlistEntry, PFEE_PROGRAM_ID,
+    },
+    DepositReferral, InitialPoolSettings, StakingInstruction,
+};
+
+/// InitializePool for `mint`, paid by `authority`
+///
+/// `token_program` is the mint's token program (SPL Token or Token 2022).
+/// With `enable_audit_log`, audit log page 0 is appended. `settings`, if
+/// any, replaces the default min stake, lock duration and cooldown.
+pub fn initialize_pool_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    token_program: &Pubkey,
+    tau_seconds: u64,
+    enable_audit_log: bool,
+    settings: Option<InitialPoolSettings>,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (vault, _) = derive_vault_pda(&pool, program_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(pool, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new(*authority, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    if enable_audit_log {
+        let (page, _) = AuditLogPage::derive_pda(&pool, 0, program_id);
+        accounts.push(AccountMeta::new(page, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::InitializePool {
+            tau_seconds,
+            enable_audit_log: Some(enable_audit_log).into(),
+            initial_settings: settings.into(),
+        },
+        accounts,
+    )
+}
+
+/// DepositRewards of `amount` lamports from `depositor` into `mint`'s pool,
+/// paying `referral`'s cut to its referrer if given
+pub fn deposit_rewards_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    depositor: &Pubkey,
+    amount: u64,
+    nonce: Option<u64>,
+    display_value: Option<u64>,
+    referral: Option<DepositReferral>,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(pool, false),
+        AccountMeta::new(*depositor, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(referral) = &referral {
+        accounts.push(AccountMeta::new(referral.referrer, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::DepositRewards {
+            amount,
+            nonce: nonce.into(),
+            display_value: display_value.into(),
+            referral: referral.into(),
+        },
+        accounts,
+    )
+}
+
+/// DepositRewardsStreamed of `amount` lamports from `depositor`, released
+/// over `duration_seconds`
+pub fn deposit_rewards_streamed_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    depositor: &Pubkey,
+    amount: u64,
+    duration_seconds: u64,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::DepositRewardsStreamed {
+            amount,
+            duration_seconds,
+        },
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(*depositor, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// InitializeUserStake opening `owner`'s stake position `index` in `mint`'s
+/// pool (above 0, the default stake account is passed to record the index)
+pub fn initialize_user_stake_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    index: u16,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (position, _) = derive_user_stake_position_pda(owner, &pool, index, program_id);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(pool, false),
+        AccountMeta::new(position, false),
+        AccountMeta::new(*owner, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if index > 0 {
+        let (default_stake, _) = derive_user_stake_pda(owner, &pool, program_id);
+        accounts.push(AccountMeta::new(default_stake, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::InitializeUserStake { index },
+        accounts,
+    )
+}
+
+/// GetPoolStats for `mint`'s pool (read-only, decode the return data with
+/// the layout documented on `process_get_pool_stats`)
+pub fn get_pool_stats_ix(program_id: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::GetPoolStats,
+        vec![AccountMeta::new_readonly(pool, false)],
+    )
+}
+
+/// AddDepositor allowlisting `depositor` in `mint`'s pool, signed (and paid)
+/// by the pool `authority` or a settings delegate
+pub fn add_depositor_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    depositor: &Pubkey,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (entry, _) = DepositorAllowlistEntry::derive_pda(&pool, depositor, program_id);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::AddDepositor {
+            depositor: *depositor,
+        },
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(entry, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// RemoveDepositor closing `depositor`'s allowlist entry in `mint`'s pool,
+/// the rent going back to the signing `authority`
+pub fn remove_depositor_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    depositor: &Pubkey,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (entry, _) = DepositorAllowlistEntry::derive_pda(&pool, depositor, program_id);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::RemoveDepositor {
+            depositor: *depositor,
+        },
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(entry, false),
+            AccountMeta::new(*authority, true),
+        ],
+    )
+}
+
+/// ApplySettings for `mint`'s pool, signed by the pool `authority` or a
+/// settings delegate, once the pending change's timelock has run
+pub fn apply_settings_ix(program_id: &Pubkey, mint: &Pubkey, authority: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::ApplySettings,
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// QueryPendingSettings for `mint`'s pool (read-only, decode the return data
+/// with the layout documented on `process_query_pending_settings`)
+pub fn query_pending_settings_ix(program_id: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::QueryPendingSettings,
+        vec![AccountMeta::new_readonly(pool, false)],
+    )
+}
+
+/// ReclaimDeferredRewards for `mint`'s pool, sending the lamports to
+/// `recipient` (authority only)
+pub fn reclaim_deferred_rewards_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::ReclaimDeferredRewards,
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*recipient, false),
+        ],
+    )
+}
+
+/// WithdrawFeeSplit paying `recipient` its unpaid fee split shares from
+/// `mint`'s pool (permissionless)
+pub fn withdraw_fee_split_ix(program_id: &Pubkey, mint: &Pubkey, recipient: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::WithdrawFeeSplit,
+        vec![AccountMeta::new(pool, false), AccountMeta::new(*recipient, false)],
+    )
+}
+
+/// RequestUnstake of `amount` tokens from `owner`'s stake in `mint`'s pool
+pub fn request_unstake_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (user_stake, _) = derive_user_stake_pda(owner, &pool, program_id);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::RequestUnstake { amount },
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(user_stake, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+/// TakeFeeOwnership for `mint`'s pool (permissionless, no signer)
+///
+/// All 18 accounts are derived from the mint, the same way the processor
+/// checks them.
+pub fn take_fee_ownership_ix(program_id: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::TakeFeeOwnership,
+        pfee_account_metas(AccountMeta::new_readonly(pool, false), mint),
+    )
+}
+
+/// HarvestCreatorFees for `mint`'s pool (permissionless, no signer)
+///
+/// TakeFeeOwnership's 18 accounts with the pool writable. Pools with an
+/// audit log also need the current page pushed onto `accounts`.
+pub fn harvest_creator_fees_ix(program_id: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::HarvestCreatorFees,
+        pfee_account_metas(AccountMeta::new(pool, false), mint),
+    )
+}
+
+/// SetFeeShares for `mint`'s pool, signed by the pool `authority`
+///
+/// TakeFeeOwnership's 18 accounts followed by the authority.
+pub fn set_fee_shares_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    shareholders: Vec<(Pubkey, u16)>,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let mut accounts = pfee_account_metas(AccountMeta::new_readonly(pool, false), mint);
+    accounts.push(AccountMeta::new_readonly(*authority, true));
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::SetFeeShares { shareholders },
+        accounts,
+    )
+}
+
+/// ClosePool for `mint`'s pool, signed by `authority` (any signer once the
+/// authority is renounced), sending the rent to `destination`
+///
+/// `token_program` is the mint's token program (SPL Token or Token 2022).
+pub fn close_pool_ix(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (pool, _) = derive_pool_pda(mint, program_id);
+    let (token_vault, _) = derive_vault_pda(&pool, program_id);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakingInstruction::ClosePool,
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(token_vault, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*token_program, false),
+        ],
+    )
+}
+
+/// Pool, mint and the pfee / pump / PumpSwap accounts, in TakeFeeOwnership order
+fn pfee_account_metas(pool: AccountMeta, mint: &Pubkey) -> Vec<AccountMeta> {
+    let pfee = FeeOwnershipAccounts::expected(mint);
+    vec![
+        pool,
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(PFEE_PROGRAM_ID, false),
+        AccountMeta::new_readonly(pfee.pfee_event_authority, false),
+        AccountMeta::new_readonly(pfee.pump_global, false),
+        AccountMeta::new(pfee.sharing_config, false),
+        AccountMeta::new_readonly(pfee.bonding_curve, false),
+        AccountMeta::new(pfee.pump_creator_vault, false),
+        AccountMeta::new_readonly(pfee.system_program, false),
+        AccountMeta::new_readonly(pfee.pump_program, false),
+        AccountMeta::new_readonly(pfee.pump_event_authority, false),
+        AccountMeta::new_readonly(pfee.pump_amm_program, false),
+        AccountMeta::new_readonly(pfee.amm_event_authority, false),
+        AccountMeta::new_readonly(pfee.wsol_mint, false),
+        AccountMeta::new_readonly(pfee.token_program, false),
+        AccountMeta::new_readonly(pfee.associated_token_program, false),
+        AccountMeta::new(pfee.coin_creator_vault_auth, false),
+        AccountMeta::new(pfee.coin_creator_vault_ata, false),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+
+    #[test]
+    fn test_initialize_pool_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+
+        let settings = InitialPoolSettings {
+            min_stake_amount: 10,
+            lock_duration_seconds: 0,
+            unstake_cooldown_seconds: 600,
+        };
+        let ix = initialize_pool_ix(
+            &program_id,
+            &mint,
+            &authority,
+            &spl_token_2022::id(),
+            60,
+            true,
+            Some(settings),
+        );
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(ix.accounts.len(), 8);
+        assert_eq!(ix.accounts[0], AccountMeta::new(pool, false));
+        assert_eq!(ix.accounts[2].pubkey, derive_vault_pda(&pool, &program_id).0);
+        assert_eq!(ix.accounts[3], AccountMeta::new(authority, true));
+        assert_eq!(ix.accounts[7].pubkey, AuditLogPage::derive_pda(&pool, 0, &program_id).0);
+
+        match StakingInstruction::try_from_slice(&ix.data).unwrap() {
+            StakingInstruction::InitializePool {
+                tau_seconds,
+                enable_audit_log,
+                initial_settings,
+            } => {
+                assert_eq!(tau_seconds, 60);
+                assert_eq!(enable_audit_log.0, Some(true));
+                assert_eq!(initial_settings.0, Some(settings));
+            }
+            _ => panic!("Wrong instruction variant"),
+        }
+
+        let ix =
+            initialize_pool_ix(&program_id, &mint, &authority, &spl_token_2022::id(), 60, false, None);
+        assert_eq!(ix.accounts.len(), 7);
+    }
+
+    #[test]
+    fn test_request_unstake_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let ix = request_unstake_ix(&program_id, &mint, &owner, 500);
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(ix.accounts[1].pubkey, derive_user_stake_pda(&owner, &pool, &program_id).0);
+        assert_eq!(ix.accounts[2], AccountMeta::new_readonly(owner, true));
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&ix.data).unwrap(),
+            StakingInstruction::RequestUnstake { amount: 500 }
+        ));
+    }
+
+    #[test]
+    fn test_initialize_user_stake_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let ix = initialize_user_stake_ix(&program_id, &mint, &owner, 3);
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(
+            ix.accounts[1].pubkey,
+            derive_user_stake_position_pda(&owner, &pool, 3, &program_id).0
+        );
+        assert_ne!(ix.accounts[1].pubkey, derive_user_stake_pda(&owner, &pool, &program_id).0);
+        assert_eq!(ix.accounts[2], AccountMeta::new(owner, true));
+        assert_eq!(
+            ix.accounts[4],
+            AccountMeta::new(derive_user_stake_pda(&owner, &pool, &program_id).0, false)
+        );
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&ix.data).unwrap(),
+            StakingInstruction::InitializeUserStake { index: 3 }
+        ));
+        assert_eq!(initialize_user_stake_ix(&program_id, &mint, &owner, 0).accounts.len(), 4);
+    }
+
+    #[test]
+    fn test_get_pool_stats_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+
+        let ix = get_pool_stats_ix(&program_id, &mint);
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(ix.accounts, vec![AccountMeta::new_readonly(pool, false)]);
+        assert_eq!(ix.data, [72]);
+    }
+
+    #[test]
+    fn test_depositor_allowlist_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let depositor = Pubkey::new_unique();
+
+        let ix = add_depositor_ix(&program_id, &mint, &authority, &depositor);
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        let (entry, _) = DepositorAllowlistEntry::derive_pda(&pool, &depositor, &program_id);
+        assert_eq!(ix.accounts[0], AccountMeta::new_readonly(pool, false));
+        assert_eq!(ix.accounts[1], AccountMeta::new(entry, false));
+        assert_eq!(ix.accounts[2], AccountMeta::new(authority, true));
+        assert_eq!(ix.accounts[3].pubkey, system_program::id());
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&ix.data).unwrap(),
+            StakingInstruction::AddDepositor { depositor: key } if key == depositor
+        ));
+
+        let ix = remove_depositor_ix(&program_id, &mint, &authority, &depositor);
+        assert_eq!(ix.accounts.len(), 3);
+        assert_eq!(ix.accounts[1], AccountMeta::new(entry, false));
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&ix.data).unwrap(),
+            StakingInstruction::RemoveDepositor { depositor: key } if key == depositor
+        ));
+    }
+
+    #[test]
+    fn test_settings_timelock_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+
+        let ix = apply_settings_ix(&program_id, &mint, &authority);
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(ix.accounts[0], AccountMeta::new(pool, false));
+        assert_eq!(ix.accounts[1], AccountMeta::new(authority, true));
+        assert_eq!(ix.data, [75]);
+
+        let ix = query_pending_settings_ix(&program_id, &mint);
+        assert_eq!(ix.accounts, vec![AccountMeta::new_readonly(pool, false)]);
+        assert_eq!(ix.data, [76]);
+    }
+
+    #[test]
+    fn test_close_pool_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let ix = close_pool_ix(&program_id, &mint, &authority, &destination, &spl_token_2022::id());
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(ix.accounts[0], AccountMeta::new(pool, false));
+        assert_eq!(ix.accounts[1], AccountMeta::new(derive_vault_pda(&pool, &program_id).0, false));
+        assert_eq!(ix.accounts[2], AccountMeta::new_readonly(authority, true));
+        assert_eq!(ix.accounts[3], AccountMeta::new(destination, false));
+        assert_eq!(ix.accounts[4].pubkey, spl_token_2022::id());
+        assert_eq!(ix.data, [70]);
+    }
+
+    #[test]
+    fn test_take_fee_ownership_ix_passes_checks() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+
+        let ix = take_fee_ownership_ix(&program_id, &mint);
+        assert_eq!(ix.accounts.len(), 18);
+        assert!(ix.accounts.iter().all(|meta| !meta.is_signer));
+        let writable: Vec<usize> = (0..18).filter(|&i| ix.accounts[i].is_writable).collect();
+        assert_eq!(writable, [5, 7, 16, 17]);
+
+        // Accounts 3-17 are exactly what the processor checks against
+        let key = |i: usize| ix.accounts[i].pubkey;
+        let accounts = FeeOwnershipAccounts {
+            pfee_event_authority: key(3),
+            pump_global: key(4),
+            sharing_config: key(5),
+            bonding_curve: key(6),
+            pump_creator_vault: key(7),
+            system_program: key(8),
+            pump_program: key(9),
+            pump_event_authority: key(10),
+            pump_amm_program: key(11),
+            amm_event_authority: key(12),
+            wsol_mint: key(13),
+            token_program: key(14),
+            associated_token_program: key(15),
+            coin_creator_vault_auth: key(16),
+            coin_creator_vault_ata: key(17),
+        };
+        assert!(accounts.check(&mint).is_ok());
+    }
+
+    #[test]
+    fn test_harvest_creator_fees_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+
+        // Same accounts as TakeFeeOwnership, but the pool receives lamports
+        let take = take_fee_ownership_ix(&program_id, &mint);
+        let ix = harvest_creator_fees_ix(&program_id, &mint);
+        assert_eq!(ix.accounts.len(), 18);
+        assert_eq!(ix.accounts[0], AccountMeta::new(derive_pool_pda(&mint, &program_id).0, false));
+        assert_eq!(ix.accounts[1..], take.accounts[1..]);
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&ix.data).unwrap(),
+            StakingInstruction::HarvestCreatorFees
+        ));
+    }
+
+    #[test]
+    fn test_set_fee_shares_ix() {
+        let program_id = crate::id();
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (pool, _) = derive_pool_pda(&mint, &program_id);
+        let shares = vec![(pool, 8_000), (Pubkey::new_unique(), 2_000)];
+
+        let take = take_fee_ownership_ix(&program_id, &mint);
+        let ix = set_fee_shares_ix(&program_id, &mint, &authority, shares.clone());
+        assert_eq!(ix.accounts.len(), 19);
+        assert_eq!(ix.accounts[..18], take.accounts[..]);
+        assert_eq!(ix.accounts[18], AccountMeta::new_readonly(authority, true));
+        match StakingInstruction::try_from_slice(&ix.data).unwrap() {
+            StakingInstruction::SetFeeShares { shareholders } => assert_eq!(shareholders, shares),
+            _ => panic!("Wrong instruction variant"),
+        }
+    }
+}
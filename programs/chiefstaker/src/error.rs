@@ -12,13 +12,12 @@ pub enum StakingError {
     #[error("Account not initialized")]
     NotInitialized,
 
-    #[error("Invalid pool mint")]
+    #[error("Mint account does not match the pool mint")]
     InvalidPoolMint,
 
     #[error("Invalid token vault")]
     InvalidTokenVault,
 
-    /// UNUSED: Retained for ABI stability (error code numbering).
     #[error("Invalid reward vault")]
     InvalidRewardVault,
 
@@ -108,6 +107,168 @@ pub enum StakingError {
 
     #[error("New total_reward_debt exceeds maximum accumulated rewards")]
     RewardDebtExceedsBound,
+
+    #[error("Pool accounting invariant violated")]
+    InvariantViolation,
+
+    #[error("Too many rewards deferred while no tokens are staked")]
+    TooMuchDeferred,
+
+    #[error("Stake time must be earlier than the current start and not before pool creation")]
+    InvalidStakeTime,
+
+    #[error("Stake has already been grandfathered")]
+    AlreadyGrandfathered,
+
+    #[error("Every depositor stake position is required when depositors are excluded")]
+    DepositorStakeRequired,
+
+    #[error("No stakers other than the depositor to distribute to")]
+    NoEligibleStakers,
+
+    #[error("Timestamp is in the past")]
+    TimestampInPast,
+
+    #[error("Pool is quarantined: new stakes are disabled")]
+    PoolQuarantined,
+
+    #[error("Too many accounts in batch")]
+    BatchTooLarge,
+
+    #[error("Stake owner has not enabled third-party claims")]
+    ClaimForNotEnabled,
+
+    #[error("Invalid reward fee split")]
+    InvalidFeeSplit,
+
+    #[error("Fee split recipient accounts do not match the pool")]
+    FeeRecipientMismatch,
+
+    #[error("Pool lamport balance would overflow")]
+    RewardOverflow,
+
+    #[error("No completed unstake to reverse, or its grace window has passed")]
+    UnstakeNotReversible,
+
+    #[error("Pool balance does not match the expected balance")]
+    BalanceMismatch,
+
+    #[error("Token vault does not hold the pool mint")]
+    InvalidVaultMint,
+
+    #[error("Current audit log page account is required")]
+    AuditLogPageRequired,
+
+    #[error("Pool does not allow unstaking to this token account")]
+    DestinationNotAllowed,
+
+    #[error("Invalid referrer")]
+    InvalidReferrer,
+
+    #[error("Pool is not quarantined")]
+    PoolNotQuarantined,
+
+    #[error("Pool settings are locked")]
+    SettingsLocked,
+
+    #[error("Display unit label too long, contains NUL, or too many decimals")]
+    InvalidDisplayUnit,
+
+    #[error("Pool still has tokens staked")]
+    PoolHasStake,
+
+    #[error("Too many fee shareholders")]
+    TooManyShareholders,
+
+    #[error("Rewards have already been distributed")]
+    RewardsAlreadyDistributed,
+
+    #[error("Invalid delegate key or unknown permission bits")]
+    InvalidDelegate,
+
+    #[error("All delegate slots are in use")]
+    TooManyDelegates,
+
+    #[error("Stake account still has rewards to claim or withdraw")]
+    OutstandingRewards,
+
+    #[error("Reward recipient must be a writable system-owned account")]
+    InvalidRewardRecipient,
+
+    #[error("Pending rewards are less than the requested amount")]
+    InsufficientRewards,
+
+    #[error("Tau can't be changed any more times")]
+    TooManyTauChanges,
+
+    #[error("Instruction does not match the pool's reward mode (SOL or SPL token)")]
+    RewardModeMismatch,
+
+    #[error("Pool already pays rewards in an SPL token")]
+    RewardMintAlreadySet,
+
+    #[error("Claim token rewards before a partial unstake")]
+    UnclaimedTokenRewards,
+
+    #[error("Stake would exceed the pool's max_total_staked")]
+    StakeCapExceeded,
+
+    #[error("Deposit is below the pool's min_reward_deposit")]
+    DepositBelowMinimum,
+
+    #[error("Pool is paused: new stakes and reward deposits are disabled")]
+    PoolPaused,
+
+    #[error("Instant unstake is disabled: early_exit_penalty_bps is 0")]
+    InstantUnstakeDisabled,
+
+    #[error("No authority transfer is pending")]
+    NoPendingAuthority,
+
+    #[error("Pool is not the sole pump.fun fee recipient")]
+    PoolNotFeeRecipient,
+
+    #[error("Token vault holds less than the amount being withdrawn")]
+    VaultBalanceMismatch,
+
+    #[error("Fee shares must be distinct non-zero entries, include the pool and sum to 10000 bps")]
+    InvalidFeeShares,
+
+    #[error("Pool is not the pump.fun fee sharing admin, or the admin was revoked")]
+    NotFeeSharingAdmin,
+
+    #[error("Reward stream duration must be between 1 second and 365 days")]
+    InvalidStreamDuration,
+
+    #[error("Pool still has staked tokens, vault tokens or lamports above rent")]
+    PoolNotEmpty,
+
+    #[error("Unstake exceeds the pool's total_staked; pool stake accounting is inconsistent")]
+    StakeAccountingUnderflow,
+
+    #[error("Pool only accepts rewards from allowlisted depositors")]
+    DepositorNotAllowlisted,
+
+    #[error("No settings change is pending")]
+    NoPendingSettings,
+
+    #[error("Pending settings change is still timelocked")]
+    SettingsTimelockActive,
+
+    #[error("Referral cut exceeds the maximum of 1000 bps")]
+    ReferralExceedsMaximum,
+
+    #[error("Open the default stake account (position 0) and pass it to open another position")]
+    DefaultStakeRequired,
+
+    #[error("Default stake account tracks the owner's other positions and stays open")]
+    StakeTracksPositions,
+
+    #[error("Tau can change by at most 2x (up or down) at a time")]
+    TauChangeTooLarge,
+
+    #[error("Position index out of range: open the next index, below the position limit")]
+    InvalidPositionIndex,
 }
 
 impl From<StakingError> for ProgramError {
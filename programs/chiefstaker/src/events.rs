@@ -1,5 +1,20 @@
 //! Structured binary log events emitted via sol_log_data
+//!
+//! Besides the fixed-layout RewardPayout, the Borsh events below are logged
+//! the way Anchor's `emit!` logs them: an 8-byte sha256("event:<Name>")
+//! prefix followed by the Borsh-encoded struct, so Anchor event parsers can
+//! decode them from the "Program data:" lines.
+//!
+//! `acc_reward_per_weighted_share` is logged as stored, divided by
+//! 2^`acc_scale_shift`; events carry the shift so values stay comparable
+//! across a rescale.
+//!
+//! Logs are best effort: the runtime truncates a transaction's logs once
+//! they pass its log limit, and RPC nodes may drop them, so an event can be
+//! missing. Indexers should treat events as hints and read account state
+//! (or the audit log) for a complete record.
 
+use borsh::BorshSerialize;
 use solana_program::{log::sol_log_data, pubkey::Pubkey};
 
 /// sha256("event:RewardPayout")[..8]
@@ -10,6 +25,7 @@ pub enum RewardPayoutType {
     Claim = 0,
     Unstake = 1,
     AutoClaimStake = 2,
+    WithdrawClaimed = 3,
 }
 
 /// Emit a structured RewardPayout event (81 bytes).
@@ -29,3 +45,164 @@ pub fn emit_reward_payout(
     data[80] = payout_type as u8;
     sol_log_data(&[&data]);
 }
+
+/// A Borsh event with its 8-byte discriminator
+pub trait Event: BorshSerialize {
+    /// sha256("event:<Name>")[..8]
+    const DISCRIMINATOR: [u8; 8];
+}
+
+/// Log `event` as discriminator + Borsh encoding
+pub fn emit<E: Event>(event: &E) {
+    let mut data = E::DISCRIMINATOR.to_vec();
+    // Writing fixed-size fields into a Vec can't fail
+    if event.serialize(&mut data).is_ok() {
+        sol_log_data(&[&data]);
+    }
+}
+
+/// DepositRewards accepted `amount` lamports, of which `distributed`
+/// (including previously undistributed lamports) reached the accumulator.
+/// `distributed` is 0 when the deposit was deferred or left pending.
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct DepositEvent {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub distributed: u64,
+    pub total_staked: u128,
+    pub acc_reward_per_weighted_share: u128,
+    /// acc_reward_per_weighted_share is stored divided by 2^acc_scale_shift
+    pub acc_scale_shift: u8,
+}
+
+impl Event for DepositEvent {
+    const DISCRIMINATOR: [u8; 8] = [0x78, 0xf8, 0x3d, 0x53, 0x1f, 0x8e, 0x6b, 0x90];
+}
+
+/// `amount` tokens were added to `user`'s position (Stake or StakeOnBehalf)
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct StakeEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub position_amount: u64,
+    pub total_staked: u128,
+    pub acc_reward_per_weighted_share: u128,
+    /// acc_reward_per_weighted_share is stored divided by 2^acc_scale_shift
+    pub acc_scale_shift: u8,
+}
+
+impl Event for StakeEvent {
+    const DISCRIMINATOR: [u8; 8] = [0xe2, 0x86, 0xbc, 0xad, 0x13, 0x21, 0x4b, 0xaf];
+}
+
+/// `amount` tokens left `user`'s position, with `rewards_paid` lamports
+/// paid out alongside
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct UnstakeEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub rewards_paid: u64,
+    pub position_amount: u64,
+    pub total_staked: u128,
+    pub acc_reward_per_weighted_share: u128,
+    /// acc_reward_per_weighted_share is stored divided by 2^acc_scale_shift
+    pub acc_scale_shift: u8,
+}
+
+impl Event for UnstakeEvent {
+    const DISCRIMINATOR: [u8; 8] = [0xa2, 0x68, 0x89, 0xe4, 0x51, 0x03, 0x4f, 0xc5];
+}
+
+/// A claim settled `amount` lamports for `user`, paid to `recipient` or,
+/// with `to_balance`, credited to the stake's claimed balance
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClaimEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub protocol_fee: u64,
+    pub to_balance: bool,
+    pub acc_reward_per_weighted_share: u128,
+    /// acc_reward_per_weighted_share is stored divided by 2^acc_scale_shift
+    pub acc_scale_shift: u8,
+}
+
+impl Event for ClaimEvent {
+    const DISCRIMINATOR: [u8; 8] = [0x5d, 0x0f, 0x46, 0xaa, 0x30, 0x8c, 0xd4, 0xdb];
+}
+
+/// `previous_authority` renounced the pool's authority; its settings can
+/// no longer be changed by anyone
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuthorityRenouncedEvent {
+    pub pool: Pubkey,
+    pub previous_authority: Pubkey,
+}
+
+impl Event for AuthorityRenouncedEvent {
+    const DISCRIMINATOR: [u8; 8] = [0x08, 0xb9, 0x80, 0xcd, 0xb2, 0xd3, 0x04, 0x85];
+}
+
+/// The pool's tau changed from `old_tau_seconds` to `new_tau_seconds` at
+/// `change_time` (UpdateTau, or ApplySettings for a timelocked raise)
+#[derive(BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct TauChangedEvent {
+    pub pool: Pubkey,
+    pub old_tau_seconds: u64,
+    pub new_tau_seconds: u64,
+    pub change_time: i64,
+}
+
+impl Event for TauChangedEvent {
+    const DISCRIMINATOR: [u8; 8] = [0x54, 0x9e, 0x21, 0x8b, 0x79, 0x28, 0x67, 0xad];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::hash::hash;
+
+    fn discriminator(name: &str) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        out.copy_from_slice(&hash(format!("event:{}", name).as_bytes()).to_bytes()[..8]);
+        out
+    }
+
+    #[test]
+    fn test_event_discriminators() {
+        assert_eq!(DepositEvent::DISCRIMINATOR, discriminator("DepositEvent"));
+        assert_eq!(StakeEvent::DISCRIMINATOR, discriminator("StakeEvent"));
+        assert_eq!(UnstakeEvent::DISCRIMINATOR, discriminator("UnstakeEvent"));
+        assert_eq!(ClaimEvent::DISCRIMINATOR, discriminator("ClaimEvent"));
+        assert_eq!(
+            AuthorityRenouncedEvent::DISCRIMINATOR,
+            discriminator("AuthorityRenouncedEvent")
+        );
+        assert_eq!(TauChangedEvent::DISCRIMINATOR, discriminator("TauChangedEvent"));
+    }
+
+    #[test]
+    fn test_event_encoding() {
+        let event = ClaimEvent {
+            pool: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: 1_000,
+            protocol_fee: 10,
+            to_balance: true,
+            acc_reward_per_weighted_share: 7,
+            acc_scale_shift: 3,
+        };
+        let data = borsh::to_vec(&event).unwrap();
+        // 3 pubkeys + 2 u64 + bool + u128 + u8
+        assert_eq!(data.len(), 32 * 3 + 8 * 2 + 1 + 16 + 1);
+        assert_eq!(&data[..32], event.pool.as_ref());
+        assert_eq!(&data[96..104], &1_000u64.to_le_bytes());
+        assert_eq!(data[112], 1);
+        assert_eq!(data[129], 3);
+    }
+}
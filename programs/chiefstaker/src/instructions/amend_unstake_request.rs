@@ -0,0 +1,109 @@
+//! Amend unstake request instruction (change the amount of a pending request)
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UserStake},
+};
+
+/// Change a pending unstake request to `amount` without cancelling it
+///
+/// Lowering keeps the request time; raising restarts the cooldown for the
+/// whole request (see `UserStake::amend_unstake_request`).
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[writable]` User stake account
+/// 2. `[signer]` User/owner
+/// 3. `[]` System program (optional, required for legacy accounts)
+pub fn process_amend_unstake_request(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Realloc legacy accounts to current size (payer = user)
+    // System program is optional trailing account, only needed for legacy accounts
+    let system_program_info = account_info_iter.next();
+    UserStake::maybe_realloc(user_stake_info, user_info, system_program_info)?;
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Lazily adjust exp_start_factor if pool has been rebased
+    user_stake.sync_to_pool(&pool)?;
+
+    let previous_amount = user_stake.unstake_request_amount;
+    let current_time = Clock::get()?.unix_timestamp;
+    // Tokens added to the request must be out of their lock too
+    if amount > previous_amount {
+        user_stake.check_unlocked(&pool, amount, current_time)?;
+    }
+    user_stake.amend_unstake_request(amount, current_time)?;
+
+    // Save user stake
+    user_stake.save(user_stake_info)?;
+
+    msg!(
+        "Unstake request amended from {} to {} tokens, cooldown ends at {}",
+        previous_amount,
+        amount,
+        user_stake.unstake_cooldown_end(&pool)
+    );
+
+    Ok(())
+}
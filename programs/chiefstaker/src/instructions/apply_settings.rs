@@ -0,0 +1,114 @@
+//! Apply settings instruction - finalize a timelocked settings change
+//!
+//! With a settings timelock, UpdatePoolSettings, SetRewardFeeSplit and
+//! UpdateTau only record changes that make leaving slower or costlier, or
+//! that cut what stakers earn. Stakers see them coming (pool fields or
+//! QueryPendingSettings) and can exit before this applies them. A queued
+//! tau raise is applied here too, even by a delegate, since the authority
+//! already chose it.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, DELEGATE_PERM_FEES, DELEGATE_PERM_SETTINGS, PENDING_TAU},
+};
+
+use super::{
+    update_settings::{apply_settings_update, PoolSettingsUpdate},
+    update_tau::apply_tau_change,
+};
+
+/// Apply the pending settings change once its timelock has run (authority,
+/// settings delegate or fees delegate, since SetRewardFeeSplit queues too)
+///
+/// Fails with NoPendingSettings if nothing is pending and with
+/// SettingsTimelockActive before pending_settings_effective_time.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority, settings or fees delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_apply_settings(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a settings or fees delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SETTINGS)
+        && !pool.is_authorized(authority_info.key, DELEGATE_PERM_FEES)
+    {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    if pool.pending_settings_mask == 0 {
+        return Err(StakingError::NoPendingSettings.into());
+    }
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time < pool.pending_settings_effective_time {
+        msg!(
+            "Settings change is timelocked for {} more seconds",
+            pool.pending_settings_effective_time - current_time
+        );
+        return Err(StakingError::SettingsTimelockActive.into());
+    }
+
+    // Caps are checked again, including the claim fee plus fee split total
+    // and the tau change limit, so nothing applies over them even if the
+    // pool changed meanwhile
+    let pending = PoolSettingsUpdate::pending(&pool);
+    let pending_tau =
+        (pool.pending_settings_mask & PENDING_TAU != 0).then_some(pool.pending_tau_seconds);
+    pool.clear_pending_settings(u16::MAX);
+    apply_settings_update(&mut pool, &pending)?;
+    if let Some(tau_seconds) = pending_tau {
+        apply_tau_change(pool_info.key, &mut pool, tau_seconds, current_time)?;
+    }
+
+    // Grow legacy pools if needed (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    msg!("Pending settings change applied");
+    Ok(())
+}
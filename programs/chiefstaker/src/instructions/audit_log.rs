@@ -0,0 +1,142 @@
+//! Reward distribution audit log
+//!
+//! Pools that opt in at InitializePool get one AuditRecord per distribution
+//! (DepositRewards, SyncRewards, ForceSyncWithBalance), appended to PDA
+//! pages of AUDIT_LOG_PAGE_CAPACITY records. When a page fills, the pool
+//! moves on to the next index and the next distribution creates that page.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{AuditLogPage, AuditRecord, StakingPool, AUDIT_LOG_SEED},
+};
+
+/// Create an empty audit log page, rent paid by `payer`
+pub(crate) fn create_audit_log_page<'a>(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    page: u32,
+    page_info: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (expected_page, bump) = AuditLogPage::derive_pda(pool_key, page, program_id);
+    if *page_info.key != expected_page {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let page_bytes = page.to_le_bytes();
+    let page_seeds = &[AUDIT_LOG_SEED, pool_key.as_ref(), &page_bytes, &[bump]];
+    let page_rent = Rent::get()?.minimum_balance(AuditLogPage::LEN);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            page_info.key,
+            page_rent,
+            AuditLogPage::LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            page_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[page_seeds],
+    )?;
+
+    let log_page = AuditLogPage::new(*pool_key, page, bump);
+    let mut page_data = page_info.try_borrow_mut_data()?;
+    log_page.serialize(&mut &mut page_data[..])?;
+
+    msg!("Created audit log page {}", page);
+
+    Ok(())
+}
+
+/// Append `record` to the pool's current audit log page (no-op unless the
+/// pool opted in)
+///
+/// The page is found by key among `accounts`; if it doesn't exist yet it is
+/// created, which needs `payer` and the system program among `accounts`.
+/// Advances `pool.audit_log_page` when the page fills, so call this before
+/// saving the pool.
+pub(crate) fn record_distribution<'a>(
+    program_id: &Pubkey,
+    pool: &mut StakingPool,
+    pool_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    payer: Option<&AccountInfo<'a>>,
+    record: AuditRecord,
+) -> ProgramResult {
+    if !pool.audit_log_enabled {
+        return Ok(());
+    }
+
+    let (expected_page, _) =
+        AuditLogPage::derive_pda(pool_info.key, pool.audit_log_page, program_id);
+    let page_info = accounts
+        .iter()
+        .find(|info| *info.key == expected_page)
+        .ok_or_else(|| {
+            msg!("Pass audit log page {} ({})", pool.audit_log_page, expected_page);
+            StakingError::AuditLogPageRequired
+        })?;
+
+    if page_info.data_is_empty() {
+        let payer = payer.ok_or(StakingError::MissingRequiredSigner)?;
+        let system_program_info = accounts
+            .iter()
+            .find(|info| *info.key == system_program::ID)
+            .ok_or(StakingError::MissingSystemProgram)?;
+        create_audit_log_page(
+            program_id,
+            pool_info.key,
+            pool.audit_log_page,
+            page_info,
+            payer,
+            system_program_info,
+        )?;
+    }
+
+    if page_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut log_page = AuditLogPage::try_from_slice(&page_info.try_borrow_data()?)?;
+    if !log_page.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if log_page.pool != *pool_info.key || log_page.page != pool.audit_log_page {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // The index moves on as soon as a page fills, so a full page here means
+    // the account is corrupt; never overwrite records
+    if !log_page.push(record) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mut page_data = page_info.try_borrow_mut_data()?;
+    log_page.serialize(&mut &mut page_data[..])?;
+
+    if log_page.is_full() {
+        pool.audit_log_page = pool
+            .audit_log_page
+            .checked_add(1)
+            .ok_or(StakingError::MathOverflow)?;
+        msg!("Audit log page {} full", log_page.page);
+    }
+
+    Ok(())
+}
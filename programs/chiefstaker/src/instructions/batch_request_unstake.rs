@@ -0,0 +1,84 @@
+//! Batch request unstake instruction - start cooldowns on several stakes at once
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, instructions::request_unstake::request_unstake};
+
+/// Maximum (pool, stake) pairs per batch, keeping the instruction well within
+/// the compute budget even when every stake account needs a realloc.
+pub const MAX_BATCH_REQUEST_UNSTAKE: usize = 8;
+
+/// Request unstake of the full balance on each of an owner's stakes.
+///
+/// Each pair goes through the same checks as RequestUnstake (cooldown
+/// configured, lock elapsed, ownership). Stakes with nothing staked are
+/// skipped. Stakes with a pending request are skipped unless
+/// `fail_on_pending` is set, in which case the whole batch fails.
+///
+/// Accounts:
+/// 0. `[writable, signer]` Owner (pays rent if a legacy stake account grows)
+/// 1. `[]` System program
+/// 2. `[writable]` Pool account #1
+/// 3. `[writable]` User stake account #1
+///
+/// followed by further (pool, user stake) pairs, at most
+/// MAX_BATCH_REQUEST_UNSTAKE in total.
+pub fn process_batch_request_unstake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fail_on_pending: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let pairs = account_info_iter.as_slice().chunks_exact(2);
+    if pairs.len() == 0 || !pairs.remainder().is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if pairs.len() > MAX_BATCH_REQUEST_UNSTAKE {
+        return Err(StakingError::BatchTooLarge.into());
+    }
+
+    let total = pairs.len();
+    let mut requested = 0usize;
+    for pair in pairs {
+        let (pool_info, user_stake_info) = (&pair[0], &pair[1]);
+
+        match request_unstake(
+            program_id,
+            pool_info,
+            user_stake_info,
+            owner_info,
+            Some(system_program_info),
+            None,
+        ) {
+            Ok(_) => requested += 1,
+            Err(e) if e == StakingError::ZeroAmount.into() => {
+                msg!("Skipping {}: nothing staked", user_stake_info.key);
+            }
+            Err(e)
+                if !fail_on_pending
+                    && e == StakingError::PendingUnstakeRequestExists.into() =>
+            {
+                msg!("Skipping {}: unstake request already pending", user_stake_info.key);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    msg!(
+        "Batch unstake requested on {} of {} stakes",
+        requested,
+        total
+    );
+
+    Ok(())
+}
@@ -1,6 +1,6 @@
 //! Cancel unstake request instruction
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
@@ -72,8 +72,7 @@ pub fn process_cancel_unstake_request(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
@@ -93,8 +92,7 @@ pub fn process_cancel_unstake_request(
     user_stake.unstake_request_time = 0;
 
     // Save user stake
-    let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-    user_stake.serialize(&mut &mut stake_data[..])?;
+    user_stake.save(user_stake_info)?;
 
     msg!("Cancelled unstake request for {} tokens", cancelled_amount);
 
@@ -1,44 +1,250 @@
 //! Claim rewards instruction
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
+    program::set_return_data,
+    program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
+    system_program,
     sysvar::Sysvar,
 };
 
 use crate::{
     error::StakingError,
-    events::{emit_reward_payout, RewardPayoutType},
-    math::{calculate_user_weighted_stake, wad_div, wad_mul, WAD},
-    state::{StakingPool, UserStake},
+    events::{emit, emit_reward_payout, ClaimEvent, RewardPayoutType},
+    math::WAD,
+    state::{StakingPool, UserStake, FEE_SPLIT_SLOTS},
 };
 
+use super::sync_rewards::process_sync_rewards;
+
+/// Where settled rewards go
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClaimDestination {
+    /// Transfer SOL to the user's wallet
+    Wallet,
+    /// Credit the user's `claimed_balance`; SOL stays in the pool until WithdrawClaimed
+    Balance,
+}
+
 /// Claim accumulated SOL rewards
 ///
+/// Every claim instruction returns the lamports paid out (after the claim fee
+/// and fee split, or credited for ClaimToBalance) as a u64 LE via return
+/// data, 0 when there was nothing to claim.
+///
+/// With `amount`, exactly that many lamports are claimed (before the claim
+/// fee and fee split) and the rest stays claimable: the claim is recorded in
+/// claimed_rewards_wad (or, for residual rewards, taken off reward_debt) like
+/// any other. Lamports sent straight to the pool are synced first so the
+/// pending figure is current, and asking for more than is pending fails with
+/// InsufficientRewards. Asking for exactly the pending amount is a full claim.
+///
 /// Accounts:
 /// 0. `[writable]` Pool account (holds SOL rewards)
 /// 1. `[writable]` User stake account
 /// 2. `[writable, signer]` User/owner
+/// 3. `[]` System program (optional; required for legacy accounts or fee splits)
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the
+/// pool has a split, and for a partial claim on a pool with an audit log, the
+/// `[writable]` current audit log page (matched by key).
 pub fn process_claim_rewards(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    amount: Option<u64>,
+) -> ProgramResult {
+    if amount == Some(0) {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
+    // A partial claim is measured against rewards that include direct deposits
+    if amount.is_some() {
+        process_sync_rewards(program_id, accounts)?;
+    }
+
+    claim_rewards(program_id, accounts, ClaimDestination::Wallet, amount)
+}
+
+/// Claim accumulated SOL rewards to a wallet other than the owner's
+///
+/// The owner still signs; only the payout goes to the recipient, which must
+/// be a writable system-owned account so the SOL can't land in an account
+/// another program controls.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` User stake account
+/// 2. `[writable, signer]` User/owner (pays rent if the stake account needs to grow)
+/// 3. `[writable]` Reward recipient
+/// 4. `[]` System program (optional; required for legacy accounts or fee splits)
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+pub fn process_claim_rewards_to(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let pool_info = next_account_info(account_info_iter)?;
     let user_stake_info = next_account_info(account_info_iter)?;
     let user_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
 
     // Validate user is signer
     if !user_info.is_signer {
         return Err(StakingError::MissingRequiredSigner.into());
     }
 
+    // Only plain wallets: lamports sent to a PDA of a foreign program may
+    // never come back out
+    if !recipient_info.is_writable || *recipient_info.owner != system_program::ID {
+        return Err(StakingError::InvalidRewardRecipient.into());
+    }
+
+    let payout = settle_rewards(
+        program_id,
+        pool_info,
+        user_stake_info,
+        user_info,
+        None,
+        Some(recipient_info),
+        account_info_iter.as_slice(),
+        ClaimDestination::Wallet,
+        None,
+    )?;
+    set_payout_return_data(payout);
+    Ok(())
+}
+
+/// Settle accumulated rewards into the user's `claimed_balance` without moving SOL
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` User stake account
+/// 2. `[writable, signer]` User/owner (pays rent if accounts need to grow)
+/// 3. `[]` System program (required if pool or stake account is legacy-sized, or for fee splits)
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+pub fn process_claim_to_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    claim_rewards(program_id, accounts, ClaimDestination::Balance, None)
+}
+
+/// Claim a user's rewards on their behalf (permissionless if the owner opted in)
+///
+/// The SOL always goes to the stake owner, never to the caller.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` User stake account
+/// 2. `[writable]` Stake owner (receives the rewards)
+/// 3. `[writable, signer]` Caller (pays rent if the stake account needs to grow)
+/// 4. `[]` System program (optional, required if the stake account is legacy-sized, or for fee splits)
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+pub fn process_claim_for(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let caller_info = next_account_info(account_info_iter)?;
+
+    if !caller_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    let payout = settle_rewards(
+        program_id,
+        pool_info,
+        user_stake_info,
+        owner_info,
+        Some(caller_info),
+        None,
+        account_info_iter.as_slice(),
+        ClaimDestination::Wallet,
+        None,
+    )?;
+    set_payout_return_data(payout);
+    Ok(())
+}
+
+fn claim_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    destination: ClaimDestination,
+    requested: Option<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    let payout = settle_rewards(
+        program_id,
+        pool_info,
+        user_stake_info,
+        user_info,
+        None,
+        None,
+        account_info_iter.as_slice(),
+        destination,
+        requested,
+    )?;
+    set_payout_return_data(payout);
+    Ok(())
+}
+
+/// Return a claim's payout in lamports (u64 LE), 0 included, so a relayer
+/// can always read it with get_return_data
+pub(crate) fn set_payout_return_data(payout: u64) {
+    set_return_data(&payout.to_le_bytes());
+}
+
+/// Settle `user_info`'s pending rewards. With a `caller`, a third party is
+/// claiming on the owner's behalf: it pays any realloc rent, and the owner
+/// must have opted in with SetClaimForOptIn. With a `recipient`, wallet
+/// payouts go there instead of to `user_info`. With `requested`, exactly
+/// that many lamports are settled, or nothing if less is pending.
+///
+/// Returns the lamports paid to the wallet (or credited to the claimed
+/// balance) after the claim fee and fee split; 0 if nothing was pending.
+///
+/// `trailing` holds the optional system program (only needed for legacy
+/// accounts) followed by the fee split recipients.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn settle_rewards<'a>(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo<'a>,
+    user_stake_info: &AccountInfo<'a>,
+    user_info: &AccountInfo<'a>,
+    caller: Option<&AccountInfo<'a>>,
+    recipient: Option<&AccountInfo<'a>>,
+    trailing: &[AccountInfo<'a>],
+    destination: ClaimDestination,
+    requested: Option<u64>,
+) -> Result<u64, ProgramError> {
+    let payer_info = caller.unwrap_or(user_info);
+    let system_program_info = trailing.first();
+    let fee_recipients = trailing.get(1..).unwrap_or_default();
+
     // Load and validate pool
     if pool_info.owner != program_id {
         return Err(StakingError::InvalidAccountOwner.into());
@@ -54,15 +260,19 @@ pub fn process_claim_rewards(
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
+
     // Check if pool needs rebasing
     if pool.get_sum_stake_exp().needs_rebase() {
         return Err(StakingError::PoolRequiresSync.into());
     }
 
-    // Realloc legacy accounts to current size (payer = user)
-    // System program is optional trailing account, only needed for legacy accounts
-    let system_program_info = account_info_iter.next();
-    UserStake::maybe_realloc(user_stake_info, user_info, system_program_info)?;
+    // Realloc legacy accounts to current size (payer = user, or the caller)
+    UserStake::maybe_realloc(user_stake_info, payer_info, system_program_info)?;
+    if destination == ClaimDestination::Balance {
+        StakingPool::maybe_realloc(pool_info, payer_info, system_program_info)?;
+    }
 
     // Load and validate user stake
     if user_stake_info.owner != program_id {
@@ -82,12 +292,30 @@ pub fn process_claim_rewards(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
 
+    if caller.is_some() && !user_stake.claim_for_enabled {
+        return Err(StakingError::ClaimForNotEnabled.into());
+    }
+
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    // Nothing to settle is fine for a full claim, but not when an amount was
+    // asked for. It still counts as a claim, so its time is recorded.
+    let nothing_to_claim = |user_stake: &mut UserStake, reason: &str| -> Result<u64, ProgramError> {
+        msg!("{}", reason);
+        if requested.is_some() {
+            return Err(StakingError::InsufficientRewards.into());
+        }
+        user_stake.record_claim(current_time);
+        user_stake.save(user_stake_info)?;
+        Ok(0)
+    };
+
     // Handle two claim paths:
     // 1. amount > 0: normal claim using snapshot-delta formula
     // 2. amount == 0 with reward_debt > 0: residual rewards from full unstake
@@ -95,8 +323,7 @@ pub fn process_claim_rewards(
     let (pending, is_residual_claim) = if user_stake.amount == 0 {
         // Post-full-unstake: reward_debt stores unclaimed WAD-scaled rewards
         if user_stake.reward_debt == 0 {
-            msg!("No rewards to claim");
-            return Ok(());
+            return nothing_to_claim(&mut user_stake, "No rewards to claim");
         }
         (user_stake.reward_debt, true)
     } else {
@@ -105,37 +332,17 @@ pub fn process_claim_rewards(
         // Lazily adjust exp_start_factor if pool has been rebased
         user_stake.sync_to_pool(&pool)?;
 
-        let clock = Clock::get()?;
-        let current_time = clock.unix_timestamp;
-
         // Calculate user's current weighted stake
-        let user_weighted = calculate_user_weighted_stake(
-            user_stake.amount,
-            user_stake.exp_start_factor,
-            current_time,
-            pool.base_time,
-            pool.tau_seconds,
-        )?;
+        let user_weighted = user_stake.weighted_stake(&pool, current_time)?;
         if user_weighted == 0 {
-            msg!("No rewards to claim (stake too new)");
-            return Ok(());
+            return nothing_to_claim(&mut user_stake, "No rewards to claim (stake too new)");
         }
 
-        // Full entitlement: user_weighted * (acc_rps - snapshot)
-        // where snapshot = reward_debt / (amount * WAD)
-        let amount_wad = (user_stake.amount as u128)
-            .checked_mul(WAD)
-            .ok_or(StakingError::MathOverflow)?;
-        let snapshot = wad_div(user_stake.reward_debt, amount_wad)?;
-        let delta_rps = pool.acc_reward_per_weighted_share.saturating_sub(snapshot);
-        let full_entitlement = wad_mul(user_weighted, delta_rps)?;
-
-        // Subtract already-claimed amount to get pending (frequency-independent)
-        let p = full_entitlement.saturating_sub(user_stake.claimed_rewards_wad);
+        // Full entitlement minus already-claimed amount (frequency-independent)
+        let p = user_stake.pending_rewards_wad(&pool, user_weighted)?;
 
         if p == 0 {
-            msg!("No pending rewards to claim");
-            return Ok(());
+            return nothing_to_claim(&mut user_stake, "No pending rewards to claim");
         }
         (p, false)
     };
@@ -144,8 +351,14 @@ pub fn process_claim_rewards(
     let pending_lamports = pending / WAD;
 
     if pending_lamports == 0 {
-        msg!("Pending rewards too small to claim");
-        return Ok(());
+        return nothing_to_claim(&mut user_stake, "Pending rewards too small to claim");
+    }
+
+    if let Some(amount) = requested {
+        if amount as u128 > pending_lamports {
+            msg!("Requested {} lamports but only {} are pending", amount, pending_lamports);
+            return Err(StakingError::InsufficientRewards.into());
+        }
     }
 
     // Check pool has sufficient balance (keep rent-exempt minimum)
@@ -153,17 +366,63 @@ pub fn process_claim_rewards(
     let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
     let pool_lamports = pool_info.lamports();
 
-    let available_rewards = pool_lamports.saturating_sub(rent_exempt_minimum);
+    // Lamports already credited to claimed balances belong to their owners
+    let available_rewards = pool_lamports
+        .saturating_sub(rent_exempt_minimum)
+        .saturating_sub(pool.reserved_lamports());
 
     if available_rewards == 0 {
         return Err(StakingError::InsufficientRewardBalance.into());
     }
 
-    let transfer_amount = pending_lamports.min(available_rewards as u128) as u64;
+    let transfer_amount = match requested {
+        // A partial claim is paid in full or not at all
+        Some(amount) if amount > available_rewards => {
+            return Err(StakingError::InsufficientRewardBalance.into());
+        }
+        Some(amount) => amount,
+        None => pending_lamports.min(available_rewards as u128) as u64,
+    };
+
+    // Protocol fee stays in the pool, reserved until CollectProtocolFees
+    let fee = pool.claim_fee(transfer_amount);
+    pool.accrued_protocol_fees = pool
+        .accrued_protocol_fees
+        .checked_add(fee)
+        .ok_or(StakingError::MathOverflow)?;
 
-    // Transfer SOL from pool to user
-    **pool_info.try_borrow_mut_lamports()? -= transfer_amount;
-    **user_info.try_borrow_mut_lamports()? += transfer_amount;
+    // Fee split portions are paid out right away; recipients follow the
+    // system program in the pool's order
+    let split_amounts = pool.fee_split_amounts(transfer_amount);
+    let split_total: u64 = split_amounts.iter().sum();
+    let split_paid = pay_fee_splits(&mut pool, pool_info, fee_recipients, &split_amounts, &rent)?;
+    pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(split_paid);
+
+    let payout = transfer_amount - fee - split_total;
+
+    match destination {
+        ClaimDestination::Wallet => {
+            // Transfer SOL from pool to user (or the chosen recipient)
+            let recipient_info = recipient.unwrap_or(user_info);
+            **pool_info.try_borrow_mut_lamports()? -= payout;
+            **recipient_info.try_borrow_mut_lamports()? += payout;
+
+            // Update last_synced_lamports so sync_rewards doesn't miss new deposits
+            pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(payout);
+        }
+        ClaimDestination::Balance => {
+            // SOL stays in the pool (last_synced_lamports unchanged) but is
+            // reserved for this user until WithdrawClaimed
+            user_stake.claimed_balance = user_stake
+                .claimed_balance
+                .checked_add(payout)
+                .ok_or(StakingError::MathOverflow)?;
+            pool.total_claimed_balance = pool
+                .total_claimed_balance
+                .checked_add(payout)
+                .ok_or(StakingError::MathOverflow)?;
+        }
+    }
 
     let paid_wad = (transfer_amount as u128)
         .checked_mul(WAD)
@@ -183,31 +442,152 @@ pub fn process_claim_rewards(
             .ok_or(StakingError::MathOverflow)?;
     }
 
-    // Update last_synced_lamports so sync_rewards doesn't miss new deposits
-    pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(transfer_amount);
+    // Restarts the loyalty bonus ramp (if enabled)
+    user_stake.record_claim(current_time);
 
-    // Increment cumulative rewards counter
-    user_stake.total_rewards_claimed = user_stake.total_rewards_claimed.saturating_add(transfer_amount);
+    // Increment cumulative rewards counter (what the user actually received)
+    user_stake.total_rewards_claimed = user_stake.total_rewards_claimed.saturating_add(payout);
 
     // Save user stake
-    {
-        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
-    }
+    user_stake.save(user_stake_info)?;
 
-    // Save pool state
-    {
-        let mut pool_data = pool_info.try_borrow_mut_data()?;
-        pool.serialize(&mut &mut pool_data[..])?;
-    }
+    // Save pool state (a legacy pool grows to hold unpaid fee split shares)
+    pool.ensure_capacity(pool_info, payer_info, system_program_info)?;
+    pool.save(pool_info)?;
 
     if is_residual_claim {
-        msg!("Claimed {} lamports in residual rewards", transfer_amount);
+        msg!("Claimed {} lamports in residual rewards", payout);
+    } else {
+        msg!("Claimed {} lamports in rewards", payout);
+    }
+    if fee > 0 {
+        msg!("Protocol fee: {} lamports", fee);
+    }
+
+    emit(&ClaimEvent {
+        pool: *pool_info.key,
+        user: *user_info.key,
+        recipient: *recipient.unwrap_or(user_info).key,
+        amount: payout,
+        protocol_fee: fee,
+        to_balance: destination == ClaimDestination::Balance,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    if destination == ClaimDestination::Wallet {
+        emit_reward_payout(pool_info.key, user_info.key, payout, RewardPayoutType::Claim);
+        if let Some(recipient_info) = recipient {
+            msg!("Paid to {}", recipient_info.key);
+        }
     } else {
-        msg!("Claimed {} lamports in rewards", transfer_amount);
+        msg!("Credited to claimed balance (now {} lamports)", user_stake.claimed_balance);
     }
 
-    emit_reward_payout(pool_info.key, user_info.key, transfer_amount, RewardPayoutType::Claim);
+    Ok(payout)
+}
 
-    Ok(())
+/// Pay each fee split recipient its portion of a claim, returning the
+/// lamports that left the pool
+///
+/// A recipient that can't take the lamports (see `can_receive_lamports`)
+/// must not block the claim: its portion stays in the pool, reserved in
+/// `fee_split_unpaid` until WithdrawFeeSplit.
+pub(crate) fn pay_fee_splits(
+    pool: &mut StakingPool,
+    pool_info: &AccountInfo,
+    fee_recipients: &[AccountInfo],
+    split_amounts: &[u64; FEE_SPLIT_SLOTS],
+    rent: &Rent,
+) -> Result<u64, ProgramError> {
+    let mut paid = 0u64;
+    for (i, amount) in split_amounts.iter().enumerate() {
+        if pool.fee_split_bps[i] == 0 {
+            continue;
+        }
+        let recipient_info = fee_recipients
+            .get(i)
+            .ok_or(StakingError::FeeRecipientMismatch)?;
+        if *recipient_info.key != pool.fee_split_recipients[i] {
+            return Err(StakingError::FeeRecipientMismatch.into());
+        }
+        if *amount == 0 {
+            continue;
+        }
+        if can_receive_lamports(recipient_info, *amount, rent) {
+            **pool_info.try_borrow_mut_lamports()? -= amount;
+            **recipient_info.try_borrow_mut_lamports()? += amount;
+            paid += amount;
+            msg!("Fee split: {} lamports to {}", amount, recipient_info.key);
+        } else {
+            pool.fee_split_unpaid[i] = pool.fee_split_unpaid[i]
+                .checked_add(*amount)
+                .ok_or(StakingError::MathOverflow)?;
+            msg!(
+                "Fee split: {} can't receive {} lamports; reserved ({} unpaid)",
+                recipient_info.key,
+                amount,
+                pool.fee_split_unpaid[i]
+            );
+        }
+    }
+    Ok(paid)
+}
+
+/// Whether `info` can be credited `amount` lamports directly: a writable,
+/// non-executable system account that is rent-exempt afterwards
+pub(crate) fn can_receive_lamports(info: &AccountInfo, amount: u64, rent: &Rent) -> bool {
+    info.is_writable
+        && !info.executable
+        && *info.owner == system_program::ID
+        && info
+            .lamports()
+            .checked_add(amount)
+            .is_some_and(|lamports| rent.is_exempt(lamports, info.data_len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pay_fee_splits_bad_recipient() {
+        let program_id = crate::id();
+        let rent = Rent::default();
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        let (pool_key, good, drained, program_owned) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        pool.fee_split_recipients = [good, drained, program_owned];
+        pool.fee_split_bps = [300, 200, 100];
+
+        let system = system_program::ID;
+        let (mut l0, mut l1, mut l2, mut l3) = (10_000_000u64, rent.minimum_balance(0), 0u64, 5_000_000u64);
+        let (mut d0, mut d1, mut d2, mut d3) = (vec![], vec![], vec![], vec![0u8; 16]);
+        let pool_info = AccountInfo::new(&pool_key, false, true, &mut l0, &mut d0, &program_id, false, 0);
+        let recipients = [
+            AccountInfo::new(&good, false, true, &mut l1, &mut d1, &system, false, 0),
+            // Emptied by its owner: a small credit would leave it below rent exemption
+            AccountInfo::new(&drained, false, true, &mut l2, &mut d2, &system, false, 0),
+            AccountInfo::new(&program_owned, false, true, &mut l3, &mut d3, &program_id, false, 0),
+        ];
+
+        let amounts = pool.fee_split_amounts(100_000);
+        assert_eq!(amounts, [3_000, 2_000, 1_000]);
+        let paid = pay_fee_splits(&mut pool, &pool_info, &recipients, &amounts, &rent).unwrap();
+
+        // The good recipient is paid; the others are reserved, not lost
+        assert_eq!(paid, 3_000);
+        assert_eq!(recipients[0].lamports(), rent.minimum_balance(0) + 3_000);
+        assert_eq!(recipients[1].lamports(), 0);
+        assert_eq!(recipients[2].lamports(), 5_000_000);
+        assert_eq!(pool_info.lamports(), 10_000_000 - 3_000);
+        assert_eq!(pool.fee_split_unpaid, [0, 2_000, 1_000]);
+        assert_eq!(pool.reserved_lamports(), 3_000);
+
+        // A missing or swapped recipient is still rejected
+        let mismatch: ProgramError = StakingError::FeeRecipientMismatch.into();
+        assert_eq!(pay_fee_splits(&mut pool, &pool_info, &recipients[..2], &amounts, &rent), Err(mismatch.clone()));
+        let swapped = [recipients[1].clone(), recipients[0].clone(), recipients[2].clone()];
+        assert_eq!(pay_fee_splits(&mut pool, &pool_info, &swapped, &amounts, &rent), Err(mismatch));
+    }
 }
@@ -0,0 +1,85 @@
+//! Claim and close instruction - collect what's left, then clean up
+//!
+//! For a position that has already been fully unstaked: claims any pending
+//! (or residual) rewards to the owner's wallet and, if the stake account is
+//! then empty, closes it and returns its rent. Anything still held (tokens,
+//! a pending request, a claimed balance, unpaid residual rewards) keeps the
+//! account open and the call is just a claim.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::UserStake,
+};
+
+use super::{
+    claim::{set_payout_return_data, settle_rewards, ClaimDestination},
+    close_stake::close_empty_stake,
+};
+
+/// Claim pending rewards and close the stake account if it is then empty
+/// (a default stake account tracking other positions is left open)
+///
+/// Returns the lamports claimed (u64 LE) via return data, like ClaimRewards.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` User stake account
+/// 2. `[writable, signer]` User/owner (receives rewards and rent)
+/// 3. `[]` System program (optional; required for legacy accounts or fee splits)
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the
+/// pool has a split, and optionally the `[writable]` pool metadata account
+/// (matched by key) to keep its member_count in step. Closing a position
+/// above index 0 also takes the `[writable]` default stake account (matched
+/// by key), which stops counting it.
+pub fn process_claim_and_close_if_empty(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    let trailing = account_info_iter.as_slice();
+
+    // Validates the pool and stake and pays out what the pool can cover
+    let payout = settle_rewards(
+        program_id,
+        pool_info,
+        user_stake_info,
+        user_info,
+        None,
+        None,
+        trailing,
+        ClaimDestination::Wallet,
+        None,
+    )?;
+    set_payout_return_data(payout);
+
+    let user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_empty() {
+        msg!("Stake account still in use, left open");
+        return Ok(());
+    }
+    if user_stake.tracks_positions() {
+        msg!("Default stake account tracks other positions, left open");
+        return Ok(());
+    }
+
+    close_empty_stake(program_id, pool_info, user_stake_info, &user_stake, user_info, trailing)
+}
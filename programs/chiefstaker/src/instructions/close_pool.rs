@@ -0,0 +1,154 @@
+//! Close a fully drained pool to reclaim its rent
+//!
+//! Once nothing is staked, the vault is empty and every reward lamport has
+//! been paid out, the pool account and its token vault only hold rent. The
+//! authority can close both; after the authority is renounced anyone can,
+//! since nobody else could ever do it. Stake accounts left behind can still
+//! be closed with CloseStakeAccount.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, POOL_SEED},
+};
+
+/// Close an empty SOL reward pool and its token vault, sending both rents to
+/// the destination
+///
+/// Fails with `PoolNotEmpty` while tokens are staked or left in the vault,
+/// or while the pool holds any lamports above its rent exemption (claimed
+/// balances, protocol fees, a reward stream or undistributed deposits).
+/// SPL-token reward pools fail with `RewardModeMismatch`.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` Token vault
+/// 2. `[signer]` Pool authority (any account once the authority is renounced)
+/// 3. `[writable]` Rent destination
+/// 4. `[]` Token program (the vault's)
+pub fn process_close_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Authority only, until nobody holds it
+    if !pool.is_authority_renounced() {
+        if !authority_info.is_signer {
+            return Err(StakingError::MissingRequiredSigner.into());
+        }
+        if pool.authority != *authority_info.key {
+            return Err(StakingError::InvalidAuthority.into());
+        }
+    }
+
+    // Closing the pool would strand the reward vault
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Verify token vault
+    if pool.token_vault != *token_vault_info.key {
+        return Err(StakingError::InvalidTokenVault.into());
+    }
+    pool.check_token_program(token_program_info.key, token_vault_info.owner)?;
+
+    // Rent must not land back in an account being closed
+    if destination_info.key == pool_info.key || destination_info.key == token_vault_info.key {
+        return Err(StakingError::InvalidRewardRecipient.into());
+    }
+
+    if pool.total_staked > 0 {
+        msg!("{} tokens still staked", pool.total_staked);
+        return Err(StakingError::PoolNotEmpty.into());
+    }
+
+    let vault_amount = {
+        let vault_data = token_vault_info.try_borrow_data()?;
+        StateWithExtensions::<Account>::unpack(&vault_data)?.base.amount
+    };
+    if vault_amount > 0 {
+        msg!(
+            "Token vault still holds {} tokens ({} of them penalties)",
+            vault_amount,
+            pool.penalty_tokens
+        );
+        return Err(StakingError::PoolNotEmpty.into());
+    }
+
+    let rent_exempt = Rent::get()?.minimum_balance(pool_info.data_len());
+    let surplus = pool_info.lamports().saturating_sub(rent_exempt);
+    if surplus > 0 {
+        msg!(
+            "{} lamports above rent still in the pool ({} claimed balances, {} protocol fees)",
+            surplus,
+            pool.total_claimed_balance,
+            pool.accrued_protocol_fees
+        );
+        return Err(StakingError::PoolNotEmpty.into());
+    }
+
+    // Close the vault first, while the pool can still sign for it
+    let vault_lamports = token_vault_info.lamports();
+    let pool_seeds = &[POOL_SEED, pool.mint.as_ref(), &[pool.bump]];
+    invoke_signed(
+        &spl_token_2022::instruction::close_account(
+            token_program_info.key,
+            token_vault_info.key,
+            destination_info.key,
+            pool_info.key,
+            &[],
+        )?,
+        &[
+            token_vault_info.clone(),
+            destination_info.clone(),
+            pool_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[pool_seeds],
+    )?;
+
+    // Transfer all lamports from the pool to the destination (closes it)
+    let pool_lamports = pool_info.lamports();
+    **pool_info.try_borrow_mut_lamports()? = 0;
+    **destination_info.try_borrow_mut_lamports()? += pool_lamports;
+
+    // Zero out the account data so it can't be re-read as a valid pool
+    pool_info.try_borrow_mut_data()?.fill(0);
+
+    msg!(
+        "Closed pool {} for mint {}, returned {} lamports",
+        pool_info.key,
+        pool.mint,
+        vault_lamports.saturating_add(pool_lamports)
+    );
+
+    Ok(())
+}
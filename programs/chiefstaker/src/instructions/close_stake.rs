@@ -16,10 +16,23 @@ use crate::{
 
 /// Close a zero-balance user stake account, returning rent to the user.
 ///
+/// Fails with AccountNotEmpty while tokens are staked or an unstake request
+/// is pending, and with OutstandingRewards while a claimed balance or
+/// residual rewards of at least a lamport remain, and with
+/// StakeTracksPositions for a default stake account while its owner holds
+/// other open positions. Also works once the pool itself has been closed
+/// with ClosePool.
+///
 /// Accounts:
 /// 0. `[]` Pool account
-/// 1. `[writable]` User stake account (PDA: ["stake", pool, owner])
+/// 1. `[writable]` User stake account (PDA: ["stake", pool, owner], or
+///    ["stake", pool, owner, index (u16 LE)] for a position)
 /// 2. `[writable, signer]` User/owner (receives rent)
+///
+/// Optional trailing accounts (matched by key):
+/// - `[writable]` Pool metadata (member_count is decremented)
+/// - `[writable]` Default stake account (PDA: ["stake", pool, owner]);
+///   required to close a position above index 0, which it stops counting
 pub fn process_close_stake_account(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -35,19 +48,23 @@ pub fn process_close_stake_account(
         return Err(StakingError::MissingRequiredSigner.into());
     }
 
-    // Load and validate pool
-    if pool_info.owner != program_id {
-        return Err(StakingError::InvalidAccountOwner.into());
-    }
-    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
-    if !pool.is_initialized() {
-        return Err(StakingError::NotInitialized.into());
-    }
+    // Load and validate pool. A pool removed by ClosePool is empty; the stake
+    // account's own pool key (checked below) vouches for the address.
+    let pool_closed = pool_info.data_is_empty() || pool_info.lamports() == 0;
+    if !pool_closed {
+        if pool_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+        if !pool.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
 
-    // Verify pool PDA
-    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
-    if *pool_info.key != expected_pool {
-        return Err(StakingError::InvalidPDA.into());
+        // Verify pool PDA
+        let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+        if *pool_info.key != expected_pool {
+            return Err(StakingError::InvalidPDA.into());
+        }
     }
 
     // Load and validate user stake
@@ -68,22 +85,77 @@ pub fn process_close_stake_account(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
 
-    // Account must be empty: no staked tokens, no pending unstake request,
-    // and no residual unclaimed rewards worth >= 1 lamport.
-    // reward_debt stores unclaimed WAD-scaled rewards after a full unstake
-    // when the pool lacked SOL. Sub-WAD dust (< 1 lamport) is forgiven
-    // to prevent permanent lock of the account.
-    if user_stake.amount > 0
-        || user_stake.has_pending_unstake_request()
-        || user_stake.reward_debt / WAD > 0
-    {
-        return Err(StakingError::AccountNotEmpty.into());
+    // Spell out what is left: closing right after a partial unstake is the
+    // usual mistake
+    if let Some(err) = user_stake.close_blocker() {
+        if user_stake.amount > 0 {
+            msg!("{} tokens still staked; unstake them before closing", user_stake.amount);
+        } else if user_stake.has_pending_unstake_request() {
+            msg!("Unstake request pending; complete or cancel it before closing");
+        } else if user_stake.claimed_balance > 0 {
+            msg!(
+                "{} claimed lamports not withdrawn; call WithdrawClaimed first",
+                user_stake.claimed_balance
+            );
+        } else {
+            msg!(
+                "{} lamports of residual rewards unclaimed; claim them first",
+                user_stake.reward_debt / WAD
+            );
+        }
+        return Err(err.into());
+    }
+    if user_stake.tracks_positions() {
+        msg!(
+            "Default stake account tracks {} open positions; close them first",
+            user_stake.open_positions
+        );
+        return Err(StakingError::StakeTracksPositions.into());
+    }
+
+    close_empty_stake(
+        program_id,
+        pool_info,
+        user_stake_info,
+        &user_stake,
+        user_info,
+        account_info_iter.as_slice(),
+    )
+}
+
+/// Close a validated, empty stake account: its lamports go to `user_info`,
+/// its data is zeroed, and the pool metadata's member_count (if the
+/// metadata account is among `trailing`) is decremented. Closing a position
+/// above index 0 requires the owner's default stake account among
+/// `trailing` (DefaultStakeRequired otherwise) to stop counting it.
+pub(crate) fn close_empty_stake(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    user_stake_info: &AccountInfo,
+    user_stake: &UserStake,
+    user_info: &AccountInfo,
+    trailing: &[AccountInfo],
+) -> ProgramResult {
+    // The default stake account stops counting a closed position (once
+    // closed itself, it has none left to count)
+    if user_stake.position_index > 0 {
+        let (expected_default, _) = UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+        let default_info = trailing
+            .iter()
+            .find(|info| *info.key == expected_default)
+            .ok_or(StakingError::DefaultStakeRequired)?;
+        if default_info.owner == program_id && !default_info.data_is_empty() {
+            let mut default_stake = UserStake::try_from_slice(&default_info.try_borrow_data()?)?;
+            if default_stake.is_initialized() && default_stake.open_positions > 0 {
+                default_stake.close_position();
+                default_stake.save(default_info)?;
+            }
+        }
     }
 
     // Transfer all lamports from stake account to user (closes the account)
@@ -96,18 +168,14 @@ pub fn process_close_stake_account(
     stake_data.fill(0);
 
     // Optional metadata account: decrement member_count on close
-    if let Some(metadata_info) = account_info_iter.next() {
+    let (expected_metadata, _) = PoolMetadata::derive_pda(pool_info.key, program_id);
+    if let Some(metadata_info) = trailing.iter().find(|info| *info.key == expected_metadata) {
         if metadata_info.owner == program_id && !metadata_info.data_is_empty() {
-            let (expected_metadata, _) =
-                PoolMetadata::derive_pda(pool_info.key, program_id);
-            if *metadata_info.key == expected_metadata {
-                let mut metadata =
-                    PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
-                if metadata.is_initialized() && metadata.pool == *pool_info.key {
-                    metadata.member_count = metadata.member_count.saturating_sub(1);
-                    let mut metadata_data = metadata_info.try_borrow_mut_data()?;
-                    metadata.serialize(&mut &mut metadata_data[..])?;
-                }
+            let mut metadata = PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
+            if metadata.is_initialized() && metadata.pool == *pool_info.key {
+                metadata.member_count = metadata.member_count.saturating_sub(1);
+                let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+                metadata.serialize(&mut &mut metadata_data[..])?;
             }
         }
     }
@@ -0,0 +1,98 @@
+//! Collect protocol fees instruction (authority or fees delegate)
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, DELEGATE_PERM_FEES},
+};
+
+/// Withdraw exactly the accrued protocol fees and reset them to zero.
+///
+/// Fees are withheld from reward claims and reserved in the pool, separate
+/// from staker-owed lamports, so collecting them never reduces what stakers
+/// can claim. Calling again before new fees accrue transfers nothing.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or fees delegate
+/// 2. `[writable]` Fee recipient
+pub fn process_collect_protocol_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a fees delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_FEES) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    let amount = pool.accrued_protocol_fees;
+    if amount == 0 {
+        msg!("No protocol fees to collect");
+        return Ok(());
+    }
+
+    // Accrued fees are reserved out of the pool's lamports, so the pool must
+    // always be able to cover them on top of its rent-exempt minimum
+    let rent = Rent::get()?;
+    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
+    let available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
+    if available < amount {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
+    // Transfer SOL from pool to recipient
+    **pool_info.try_borrow_mut_lamports()? -= amount;
+    **recipient_info.try_borrow_mut_lamports()? += amount;
+
+    pool.accrued_protocol_fees = 0;
+
+    // The lamports were synced as rewards when deposited; they now leave the pool
+    pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(amount);
+
+    // Save pool state
+    pool.save(pool_info)?;
+
+    msg!("Collected {} lamports of protocol fees to {}", amount, recipient_info.key);
+
+    Ok(())
+}
@@ -5,6 +5,7 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
+    msg,
     pubkey::Pubkey,
     sysvar::Sysvar,
 };
@@ -14,18 +15,23 @@ use crate::{
     state::{is_valid_token_program, StakingPool, UserStake},
 };
 
-use super::unstake::execute_unstake;
+use super::unstake::{execute_unstake, validate_destination_token_account};
 
 /// Complete unstake after cooldown has elapsed
 ///
+/// Like Unstake, this also pays the stake's pending SOL rewards, so an exit
+/// needs no separate ClaimRewards.
+///
 /// Accounts (same as Unstake):
 /// 0. `[writable]` Pool account
 /// 1. `[writable]` User stake account
 /// 2. `[writable]` Token vault
-/// 3. `[writable]` User token account
+/// 3. `[writable]` User token account (any account of the pool mint owned by the user)
 /// 4. `[]` Token mint
 /// 5. `[writable, signer]` User/owner
 /// 6. `[]` Token 2022 program
+/// 7. `[]` System program (optional; grows a legacy stake account, needed to
+///    record a reversible unstake on one)
 pub fn process_complete_unstake(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -75,11 +81,17 @@ pub fn process_complete_unstake(
         return Err(StakingError::InvalidPoolMint.into());
     }
 
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
     // Verify token vault
     if pool.token_vault != *token_vault_info.key {
         return Err(StakingError::InvalidTokenVault.into());
     }
 
+    // Verify destination token account
+    validate_destination_token_account(user_token_info, &pool, user_info.key)?;
+
     // Load and validate user stake
     if user_stake_info.owner != program_id {
         return Err(StakingError::InvalidAccountOwner.into());
@@ -98,8 +110,7 @@ pub fn process_complete_unstake(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
@@ -112,8 +123,7 @@ pub fn process_complete_unstake(
     // Check cooldown has elapsed
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
-    let elapsed = current_time.saturating_sub(user_stake.unstake_request_time).max(0) as u64;
-    if elapsed < pool.unstake_cooldown_seconds {
+    if !user_stake.is_unstake_completable(&pool, current_time) {
         return Err(StakingError::CooldownNotElapsed.into());
     }
 
@@ -129,6 +139,16 @@ pub fn process_complete_unstake(
     // Optional trailing system program for legacy account reallocation
     let system_program_info = account_info_iter.next();
 
+    // Remember the exit so ReverseUnstake can undo it within the grace window
+    if pool.unstake_reversal_window_seconds > 0 {
+        if system_program_info.is_some() || user_stake_info.data_len() >= UserStake::LEN {
+            user_stake.last_unstake_completion_time = current_time;
+            user_stake.last_unstake_completion_amount = amount;
+        } else {
+            msg!("Pass the system program to grow the stake account and make this unstake reversible");
+        }
+    }
+
     // Execute the shared unstake logic
     execute_unstake(
         program_id,
@@ -141,6 +161,7 @@ pub fn process_complete_unstake(
         mint_info,
         user_info,
         amount,
+        0,
         current_time,
         system_program_info,
     )
@@ -1,11 +1,12 @@
-//! Deposit rewards instruction
+//! Deposit rewards instructions (instant and streamed)
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
+    program_error::ProgramError,
     program::invoke,
     pubkey::Pubkey,
     rent::Rent,
@@ -15,21 +16,59 @@ use solana_program::{
 
 use crate::{
     error::StakingError,
-    math::{wad_div, WAD},
-    state::StakingPool,
+    events::{emit, DepositEvent},
+    math::{wad_mul, BPS_DENOMINATOR, WAD},
+    state::{derive_user_stake_position_pda, AuditRecord, StakingPool, UserStake},
+    DepositReferral,
 };
 
+use super::{
+    audit_log::record_distribution,
+    depositor_allowlist::check_allowlisted_depositor,
+};
+
+/// Most of a deposit a referrer may take (10%)
+pub const MAX_REFERRAL_BPS: u64 = 1_000;
+
 /// Deposit SOL rewards into the pool
 /// Anyone can call this (permissionless)
 ///
+/// With a `nonce`, the deposit is idempotent: if the same depositor used the
+/// same nonce within the last `DEPOSIT_NONCE_SLOTS` nonced deposits, this
+/// returns Ok without transferring anything, so relayers can safely retry.
+///
+/// A `display_value` (the deposit priced in the pool's display unit by the
+/// client) is added to display_total_distributed; it never affects the
+/// lamport distribution.
+///
+/// With a `referral`, `amount * referral_bps / 10000` (rounded down) goes
+/// straight from the depositor to the referrer and only the rest is
+/// deposited, checked against min_reward_deposit and distributed. A nonced
+/// retry pays the referrer nothing.
+///
 /// Accounts:
 /// 0. `[writable]` Pool account (receives SOL)
 /// 1. `[writable, signer]` Depositor
 /// 2. `[]` System program
+///
+/// Optional trailing accounts (matched by key):
+/// - `[]` Depositor's allowlist entry (PDA: ["depositor", pool, depositor]);
+///   required when the pool requires allowlisted depositors
+/// - `[writable]` Depositor's stake accounts: the default one (PDA:
+///   ["stake", pool, depositor]) and every position up to its
+///   max_position_index, closed ones included; required when the pool
+///   excludes depositors from their own deposits
+/// - `[writable]` Current audit log page (PDA: ["audit_log", pool, page]);
+///   required when the pool keeps an audit log, created (paid by the
+///   depositor) when the previous page filled
+/// - `[writable]` Referrer; required with a referral
 pub fn process_deposit_rewards(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount: u64,
+    nonce: Option<u64>,
+    display_value: Option<u64>,
+    referral: Option<DepositReferral>,
 ) -> ProgramResult {
     if amount == 0 {
         return Err(StakingError::ZeroAmount.into());
@@ -61,21 +100,139 @@ pub fn process_deposit_rewards(
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Permissioned pools only take rewards from allowlisted depositors
+    check_allowlisted_depositor(
+        program_id,
+        &pool,
+        pool_info,
+        depositor_info,
+        account_info_iter.as_slice(),
+    )?;
+
+    // Paused pools take no new rewards (SyncRewards still sweeps transfers)
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
+    // The referrer's cut never reaches the pool; the rest is the deposit
+    let referrer = match &referral {
+        Some(referral) => {
+            let cut = referral_cut(referral, depositor_info.key, pool_info.key, amount)?;
+            let referrer_info = account_info_iter
+                .as_slice()
+                .iter()
+                .find(|info| *info.key == referral.referrer)
+                .ok_or_else(|| {
+                    msg!("Referrer {} account not passed", referral.referrer);
+                    StakingError::InvalidReferrer
+                })?;
+            Some((referrer_info, cut))
+        }
+        None => None,
+    };
+    let referral_amount = referrer.map_or(0, |(_, cut)| cut);
+    let amount = amount - referral_amount;
+
+    // Dust deposits would only churn the accumulator (and audit log)
+    pool.check_min_reward_deposit(amount)?;
+
+    // The pool balance after the deposit must still fit the u64
+    // last_synced_lamports watermark
+    if pool_info.lamports().checked_add(amount).is_none() {
+        msg!("Deposit of {} lamports would overflow the pool balance", amount);
+        return Err(StakingError::RewardOverflow.into());
+    }
+
+    // Skip retried deposits; otherwise remember the nonce (a legacy pool
+    // grows to hold the ring, paid by the depositor)
+    if let Some(nonce) = nonce {
+        let key = StakingPool::deposit_nonce_key(depositor_info.key, nonce);
+        if !pool.record_deposit_nonce(key) {
+            msg!("Deposit nonce {} already used; skipping", nonce);
+            return Ok(());
+        }
+        pool.ensure_capacity(pool_info, depositor_info, Some(system_program_info))?;
+    }
+
+    // Display-unit accounting (a legacy pool grows, paid by the depositor)
+    if let Some(value) = display_value {
+        pool.display_total_distributed = pool.display_total_distributed.saturating_add(value);
+        pool.ensure_capacity(pool_info, depositor_info, Some(system_program_info))?;
+    }
+
+    // Pay the referrer straight from the depositor
+    if let Some((referrer_info, cut)) = referrer {
+        if cut > 0 {
+            invoke(
+                &system_instruction::transfer(depositor_info.key, referrer_info.key, cut),
+                &[
+                    depositor_info.clone(),
+                    referrer_info.clone(),
+                    system_program_info.clone(),
+                ],
+            )?;
+        }
+        msg!("Paid {} lamports to referrer {}", cut, referrer_info.key);
+    }
+
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
 
     let rent = Rent::get()?;
     let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
 
-    // Denominator: total_staked * WAD (max weight, not time-varying)
+    // Sweep lamports sent straight to the pool (and deposits left pending)
+    // first, exactly as SyncRewards would, so this deposit only ever adds its
+    // own amount to the accumulator
+    let (swept, swept_per_share) = pool
+        .sync_accumulator(pool_info.lamports(), rent_exempt_minimum, current_time)?
+        .unwrap_or((0, 0));
+
+    // Denominator: total_staked * WAD (max weight; each stake's time weight
+    // is applied to its share when it claims)
     let total_staked_wad = (pool.total_staked as u128)
         .checked_mul(WAD)
         .ok_or(StakingError::MathOverflow)?;
 
+    // Optionally exclude the depositor's own stake from this distribution
+    // (prevents self-funded reward loops)
+    let depositor_stakes = excluded_depositor_stakes(
+        program_id,
+        &pool,
+        pool_info,
+        depositor_info,
+        account_info_iter.as_slice(),
+    )?;
+    let excluded_wad = excluded_stake_wad(&depositor_stakes)?;
+    let eligible_wad = total_staked_wad.saturating_sub(excluded_wad);
+    if total_staked_wad > 0 && eligible_wad == 0 {
+        msg!("Depositor is the only staker; nothing to distribute to");
+        return Err(StakingError::NoEligibleStakers.into());
+    }
+
     if total_staked_wad == 0 {
         // No stakers to distribute to.
         // Accept the deposit but do NOT update last_synced_lamports so the
         // rewards remain pending and will be distributed once someone stakes.
+
+        // Optional cap: don't let the first staker capture an unbounded pile
+        if pool.max_deferred_lamports > 0 {
+            let deferred = pool
+                .reward_lamports(pool_info.lamports(), rent_exempt_minimum, current_time)
+                .saturating_sub(pool.last_synced_lamports);
+            if deferred.saturating_add(amount) > pool.max_deferred_lamports {
+                msg!(
+                    "Deferred rewards would reach {} lamports (max {}); stake first",
+                    deferred.saturating_add(amount),
+                    pool.max_deferred_lamports
+                );
+                return Err(StakingError::TooMuchDeferred.into());
+            }
+        }
+
         invoke(
             &system_instruction::transfer(depositor_info.key, pool_info.key, amount),
             &[
@@ -85,34 +242,256 @@ pub fn process_deposit_rewards(
             ],
         )?;
 
+        // Persist the recorded nonce and display total
+        if nonce.is_some() || display_value.is_some() {
+            pool.save(pool_info)?;
+        }
+
         msg!(
             "Deposited {} lamports (deferred - no stakers)",
             amount,
         );
+        emit(&DepositEvent {
+            pool: *pool_info.key,
+            depositor: *depositor_info.key,
+            amount,
+            distributed: 0,
+            total_staked: pool.total_staked,
+            acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+            acc_scale_shift: pool.acc_scale_shift,
+        });
         return Ok(());
     }
 
-    // Include any previously undistributed rewards alongside this deposit.
-    let current_available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
-    let undistributed = current_available.saturating_sub(pool.last_synced_lamports);
-    let total_new_rewards = amount.saturating_add(undistributed);
+    let mut distributed = swept;
+    let mut reward_per_share = swept_per_share;
 
     // Calculate reward per share using max weight denominator
-    // reward_per_share = total_new_rewards * WAD / (total_staked * WAD)
-    let amount_wad = (total_new_rewards as u128)
-        .checked_mul(WAD)
-        .ok_or(StakingError::MathOverflow)?;
-    let reward_per_share = wad_div(amount_wad, total_staked_wad)?;
+    // reward_per_share = amount * WAD / (total_staked * WAD)
+    // (minus the depositor's stake when excluded), truncated to the
+    // accumulator's precision
+    let (deposit_per_share, deposit_paid) = pool.reward_per_share_for(amount, eligible_wad)?;
 
-    // Update accumulator
-    pool.acc_reward_per_weighted_share = pool
-        .acc_reward_per_weighted_share
-        .checked_add(reward_per_share)
-        .ok_or(StakingError::MathOverflow)?;
+    // Too small to distribute on its own: leave it pending (last_synced_lamports
+    // untouched) until later deposits or transfers clear the minimum
+    if pool.below_min_distributable(amount) || deposit_per_share == 0 {
+        invoke(
+            &system_instruction::transfer(depositor_info.key, pool_info.key, amount),
+            &[
+                depositor_info.clone(),
+                pool_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+
+        if deposit_per_share == 0 {
+            msg!("Deposited {} lamports (pending - too small to register per share)", amount);
+        } else {
+            msg!(
+                "Deposited {} lamports (pending - below min_distributable_per_event {})",
+                amount,
+                pool.min_distributable_per_event
+            );
+        }
+
+        if swept == 0 {
+            // Persist the recorded nonce and display total
+            if nonce.is_some() || display_value.is_some() {
+                pool.save(pool_info)?;
+            }
+
+            emit(&DepositEvent {
+                pool: *pool_info.key,
+                depositor: *depositor_info.key,
+                amount,
+                distributed: 0,
+                total_staked: pool.total_staked,
+                acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+                acc_scale_shift: pool.acc_scale_shift,
+            });
+            return Ok(());
+        }
+    } else {
+        // Update accumulator (lowering its precision if debts would overflow)
+        pool.add_reward_per_share(deposit_per_share)?;
+
+        // Advance the excluded depositor's snapshots past this deposit
+        for (stake_info, user_stake) in depositor_stakes {
+            skip_depositor_stake(&mut pool, stake_info, user_stake, deposit_per_share)?;
+        }
+
+        // Transfer SOL from depositor to pool
+        invoke(
+            &system_instruction::transfer(depositor_info.key, pool_info.key, amount),
+            &[
+                depositor_info.clone(),
+                pool_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+
+        // Count only the lamports the increment pays out as synced: the
+        // rounding remainder, and anything the sweep left pending, stays
+        // pending for SyncRewards
+        pool.last_synced_lamports = pool
+            .last_synced_lamports
+            .checked_add(deposit_paid)
+            .ok_or(StakingError::MathOverflow)?;
+
+        distributed = distributed.saturating_add(deposit_paid);
+        reward_per_share = reward_per_share.saturating_add(deposit_per_share);
+    }
 
     pool.last_update_time = current_time;
 
-    // Transfer SOL from depositor to pool (before serialization so lamports() is updated)
+    // Legacy pools only track the reward rate once they have grown, so a
+    // deposit never forces a realloc on the depositor
+    if pool_info.data_len() >= StakingPool::LEN {
+        pool.record_reward_rate(distributed, current_time);
+    }
+
+    // Record the distribution before saving (may advance the page index)
+    let record = AuditRecord {
+        timestamp: current_time,
+        amount: distributed,
+        reward_per_share,
+        total_staked: pool.total_staked,
+    };
+    record_distribution(program_id, &mut pool, pool_info, accounts, Some(depositor_info), record)?;
+
+    // Save pool state (a legacy pool grows if the accumulator was rescaled)
+    pool.ensure_capacity(pool_info, depositor_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
+
+    msg!(
+        "Deposited {} lamports (distributed {} total), total_staked: {}, reward_per_share: {}",
+        amount,
+        distributed,
+        pool.total_staked,
+        reward_per_share
+    );
+    emit(&DepositEvent {
+        pool: *pool_info.key,
+        depositor: *depositor_info.key,
+        amount,
+        distributed,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    Ok(())
+}
+
+/// Longest a streamed deposit may take to release: 365 days
+pub const MAX_REWARD_STREAM_SECONDS: u64 = 365 * 24 * 60 * 60;
+
+/// Deposit SOL rewards that are released linearly over `duration_seconds`
+/// Anyone can call this (permissionless)
+///
+/// The lamports go into the pool right away, but sync only distributes what
+/// the stream has released by then, so stakers share it in proportion to
+/// how long they stay staked during the stream rather than whoever is staked
+/// at the deposit. Every sync (SyncRewards, DepositRewards, HarvestCreatorFees)
+/// releases the share due since the previous one; nothing is distributed
+/// here. A stream still running is merged in at the sum of both rates.
+///
+/// Like SOL sent straight to the pool, streamed rewards go to every staker:
+/// exclude_depositor does not apply.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (receives SOL)
+/// 1. `[writable, signer]` Depositor (pays the realloc of a legacy pool)
+/// 2. `[]` System program
+/// 3. `[]` Depositor's allowlist entry (PDA: ["depositor", pool, depositor];
+///    optional, required when the pool requires allowlisted depositors)
+pub fn process_deposit_rewards_streamed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    duration_seconds: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
+    if duration_seconds == 0 || duration_seconds > MAX_REWARD_STREAM_SECONDS {
+        return Err(StakingError::InvalidStreamDuration.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let depositor_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate depositor is signer
+    if !depositor_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Permissioned pools only take rewards from allowlisted depositors
+    check_allowlisted_depositor(
+        program_id,
+        &pool,
+        pool_info,
+        depositor_info,
+        account_info_iter.as_slice(),
+    )?;
+
+    // Paused pools take no new rewards
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
+    pool.check_min_reward_deposit(amount)?;
+
+    // The pool balance after the deposit must still fit the u64
+    // last_synced_lamports watermark
+    if pool_info.lamports().checked_add(amount).is_none() {
+        msg!("Deposit of {} lamports would overflow the pool balance", amount);
+        return Err(StakingError::RewardOverflow.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Released lamports wait for a staker like any deferred deposit, so the
+    // whole stream counts toward the cap
+    if pool.total_staked == 0 && pool.max_deferred_lamports > 0 {
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(pool_info.data_len());
+        let deferred = pool_info
+            .lamports()
+            .saturating_sub(rent_exempt_minimum)
+            .saturating_sub(pool.last_synced_lamports);
+        if deferred.saturating_add(amount) > pool.max_deferred_lamports {
+            msg!(
+                "Deferred rewards would reach {} lamports (max {}); stake first",
+                deferred.saturating_add(amount),
+                pool.max_deferred_lamports
+            );
+            return Err(StakingError::TooMuchDeferred.into());
+        }
+    }
+
+    pool.add_reward_stream(amount, duration_seconds, current_time)?;
+
     invoke(
         &system_instruction::transfer(depositor_info.key, pool_info.key, amount),
         &[
@@ -122,22 +501,214 @@ pub fn process_deposit_rewards(
         ],
     )?;
 
-    // Update last_synced_lamports so sync_rewards doesn't double-count
-    pool.last_synced_lamports = pool_info.lamports().saturating_sub(rent_exempt_minimum);
-
-    // Save pool state
-    {
-        let mut pool_data = pool_info.try_borrow_mut_data()?;
-        pool.serialize(&mut &mut pool_data[..])?;
-    }
+    // A legacy pool grows to hold the stream, paid by the depositor
+    pool.ensure_capacity(pool_info, depositor_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
 
     msg!(
-        "Deposited {} lamports (distributed {} total), total_staked: {}, reward_per_share: {}",
+        "Streaming {} lamports; {} unreleased until {}",
         amount,
-        total_new_rewards,
-        pool.total_staked,
-        reward_per_share
+        pool.reward_stream_lamports,
+        pool.reward_stream_end
     );
+    emit(&DepositEvent {
+        pool: *pool_info.key,
+        depositor: *depositor_info.key,
+        amount,
+        distributed: 0,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
 
     Ok(())
 }
+
+/// The depositor's staked positions to leave out of a distribution, if the
+/// pool excludes depositors from their own deposits (prevents self-funded
+/// reward loops). Matched by key among `accounts`; the default stake account
+/// and every position up to its max_position_index are required in that
+/// case, so no position can be left out. Without a default stake account
+/// the depositor holds no positions (InitializeUserStake needs it first).
+pub(crate) fn excluded_depositor_stakes<'b, 'a>(
+    program_id: &Pubkey,
+    pool: &StakingPool,
+    pool_info: &AccountInfo<'a>,
+    depositor_info: &AccountInfo<'a>,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<Vec<(&'b AccountInfo<'a>, UserStake)>, ProgramError> {
+    let mut stakes = Vec::new();
+    if !pool.exclude_depositor || pool.total_staked == 0 {
+        return Ok(stakes);
+    }
+    let mut max_index = 0;
+    let mut index = 0;
+    loop {
+        let (expected_stake, _) =
+            derive_user_stake_position_pda(depositor_info.key, pool_info.key, index, program_id);
+        let stake_info = accounts
+            .iter()
+            .find(|info| *info.key == expected_stake)
+            .ok_or(StakingError::DepositorStakeRequired)?;
+        if stake_info.owner == program_id && !stake_info.data_is_empty() {
+            let user_stake = UserStake::try_from_slice(&stake_info.try_borrow_data()?)?;
+            if user_stake.is_initialized() {
+                if index == 0 {
+                    max_index = user_stake.max_position_index;
+                }
+                if user_stake.amount > 0 {
+                    stakes.push((stake_info, user_stake));
+                }
+            }
+        }
+        if index >= max_index {
+            return Ok(stakes);
+        }
+        index += 1;
+    }
+}
+
+/// Total stake (WAD-scaled) of the excluded depositor positions
+pub(crate) fn excluded_stake_wad(stakes: &[(&AccountInfo, UserStake)]) -> Result<u128, ProgramError> {
+    let excluded: u128 = stakes.iter().map(|(_, user_stake)| user_stake.amount as u128).sum();
+    Ok(excluded.checked_mul(WAD).ok_or(StakingError::MathOverflow)?)
+}
+
+/// Advance an excluded depositor's snapshot past a distribution of
+/// `reward_per_share`, so its stake earns nothing from the deposit
+pub(crate) fn skip_depositor_stake(
+    pool: &mut StakingPool,
+    stake_info: &AccountInfo,
+    mut user_stake: UserStake,
+    reward_per_share: u128,
+) -> ProgramResult {
+    let excluded_wad = (user_stake.amount as u128)
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    user_stake.sync_acc_scale(pool);
+    let skipped_debt = wad_mul(excluded_wad, pool.scale_reward_per_share(reward_per_share))?;
+    user_stake.reward_debt = user_stake
+        .reward_debt
+        .checked_add(skipped_debt)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.total_reward_debt = pool
+        .total_reward_debt
+        .checked_add(skipped_debt)
+        .ok_or(StakingError::MathOverflow)?;
+    user_stake.save(stake_info)?;
+    msg!("Excluded depositor stake of {} tokens from this deposit", user_stake.amount);
+    Ok(())
+}
+
+/// Lamports of a deposit of `amount` owed to `referral`'s referrer (rounded
+/// down). The cut is capped at MAX_REFERRAL_BPS, and neither the depositor
+/// nor the pool may refer the deposit.
+fn referral_cut(
+    referral: &DepositReferral,
+    depositor: &Pubkey,
+    pool: &Pubkey,
+    amount: u64,
+) -> Result<u64, StakingError> {
+    if referral.referral_bps > MAX_REFERRAL_BPS {
+        msg!(
+            "referral_bps {} exceeds the maximum {}",
+            referral.referral_bps,
+            MAX_REFERRAL_BPS
+        );
+        return Err(StakingError::ReferralExceedsMaximum);
+    }
+    if referral.referrer == *depositor
+        || referral.referrer == *pool
+        || referral.referrer == Pubkey::default()
+    {
+        return Err(StakingError::InvalidReferrer);
+    }
+    Ok(((amount as u128) * (referral.referral_bps as u128) / (BPS_DENOMINATOR as u128)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excluded_depositor_stakes() {
+        let program_id = crate::id();
+        let (pool_key, depositor) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        pool.exclude_depositor = true;
+        pool.total_staked = 100;
+
+        // Default stake with 10 tokens, position 1 closed, position 2 with 30
+        let key = |index| derive_user_stake_position_pda(&depositor, &pool_key, index, &program_id).0;
+        let (k0, k1, k2) = (key(0), key(1), key(2));
+        let mut default_stake = UserStake::new(depositor, pool_key, 10, 1, 0, 255, 0);
+        default_stake.max_position_index = 2;
+        let mut position = UserStake::new(depositor, pool_key, 30, 1, 0, 255, 0);
+        position.position_index = 2;
+        let system = solana_program::system_program::ID;
+        let (mut l0, mut l1, mut l2, mut lp, mut ld) = (1u64, 0u64, 1u64, 0u64, 0u64);
+        let (mut d0, mut d2) = (borsh::to_vec(&default_stake).unwrap(), borsh::to_vec(&position).unwrap());
+        let (mut d1, mut dp, mut dd) = (vec![], vec![], vec![]);
+        let pool_info = AccountInfo::new(&pool_key, false, true, &mut lp, &mut dp, &program_id, false, 0);
+        let depositor_info = AccountInfo::new(&depositor, true, true, &mut ld, &mut dd, &system, false, 0);
+        let accounts = [
+            AccountInfo::new(&k2, false, true, &mut l2, &mut d2, &program_id, false, 0),
+            AccountInfo::new(&k0, false, true, &mut l0, &mut d0, &program_id, false, 0),
+            AccountInfo::new(&k1, false, true, &mut l1, &mut d1, &system, false, 0),
+        ];
+
+        // Every position is excluded, in any account order
+        let stakes =
+            excluded_depositor_stakes(&program_id, &pool, &pool_info, &depositor_info, &accounts)
+                .unwrap();
+        assert_eq!(stakes.len(), 2);
+        assert_eq!(excluded_stake_wad(&stakes), Ok(40 * WAD));
+
+        // Leaving out any position up to max_position_index is rejected,
+        // even a closed one
+        let required: ProgramError = StakingError::DepositorStakeRequired.into();
+        for skipped in 0..accounts.len() {
+            let mut partial = accounts.to_vec();
+            partial.remove(skipped);
+            let result =
+                excluded_depositor_stakes(&program_id, &pool, &pool_info, &depositor_info, &partial);
+            assert_eq!(result.err(), Some(required.clone()));
+        }
+
+        // Nothing is required when the pool doesn't exclude depositors
+        pool.exclude_depositor = false;
+        let stakes = excluded_depositor_stakes(&program_id, &pool, &pool_info, &depositor_info, &[]);
+        assert!(stakes.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_referral_cut() {
+        let (depositor, pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut referral = DepositReferral {
+            referrer: Pubkey::new_unique(),
+            referral_bps: 250,
+        };
+        assert_eq!(referral_cut(&referral, &depositor, &pool, 1_000_000), Ok(25_000));
+        // Rounded down, in favour of stakers
+        assert_eq!(referral_cut(&referral, &depositor, &pool, 39), Ok(0));
+        assert_eq!(referral_cut(&referral, &depositor, &pool, u64::MAX), Ok(u64::MAX / 40));
+
+        referral.referral_bps = MAX_REFERRAL_BPS;
+        assert_eq!(referral_cut(&referral, &depositor, &pool, 1_000), Ok(100));
+        referral.referral_bps = MAX_REFERRAL_BPS + 1;
+        assert_eq!(
+            referral_cut(&referral, &depositor, &pool, 1_000),
+            Err(StakingError::ReferralExceedsMaximum)
+        );
+
+        // No self-referral, and the pool can't refer its own deposits
+        referral.referral_bps = 250;
+        for referrer in [depositor, pool, Pubkey::default()] {
+            referral.referrer = referrer;
+            assert_eq!(
+                referral_cut(&referral, &depositor, &pool, 1_000),
+                Err(StakingError::InvalidReferrer)
+            );
+        }
+    }
+}
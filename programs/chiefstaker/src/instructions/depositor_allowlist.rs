@@ -0,0 +1,248 @@
+//! Depositor allowlist instructions (authority or settings delegate)
+//!
+//! Reward deposits are permissionless by default. A pool with
+//! require_allowlisted_depositors set only takes DepositRewards,
+//! DepositRewardsStreamed and DepositTokenRewards from depositors holding
+//! an allowlist entry, so every deposited reward has a known source. SOL
+//! sent straight to the pool and HarvestCreatorFees can't be gated and are
+//! still distributed by the next sync.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{DepositorAllowlistEntry, StakingPool, DELEGATE_PERM_SETTINGS, DEPOSITOR_SEED},
+};
+
+/// Load the pool and check `authority_info` may manage its allowlist
+fn load_pool_for_allowlist(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    authority_info: &AccountInfo,
+) -> ProgramResult {
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a settings delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SETTINGS) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    Ok(())
+}
+
+/// Allow `depositor` to deposit rewards into the pool
+///
+/// Entries can be added whether or not the pool requires them yet, so an
+/// allowlist can be filled before require_allowlisted_depositors is set.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[writable]` Allowlist entry (PDA: ["depositor", pool, depositor])
+/// 2. `[writable, signer]` Authority or settings delegate (pays rent)
+/// 3. `[]` System program
+pub fn process_add_depositor(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    depositor: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let entry_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    load_pool_for_allowlist(program_id, pool_info, authority_info)?;
+
+    // Verify entry PDA
+    let (expected_entry, entry_bump) =
+        DepositorAllowlistEntry::derive_pda(pool_info.key, &depositor, program_id);
+    if *entry_info.key != expected_entry {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    if !entry_info.data_is_empty() {
+        return Err(StakingError::AlreadyInitialized.into());
+    }
+
+    let rent = Rent::get()?;
+    let entry_rent = rent.minimum_balance(DepositorAllowlistEntry::LEN);
+    let entry_seeds: &[&[u8]] = &[
+        DEPOSITOR_SEED,
+        pool_info.key.as_ref(),
+        depositor.as_ref(),
+        &[entry_bump],
+    ];
+
+    invoke_signed(
+        &system_instruction::create_account(
+            authority_info.key,
+            entry_info.key,
+            entry_rent,
+            DepositorAllowlistEntry::LEN as u64,
+            program_id,
+        ),
+        &[
+            authority_info.clone(),
+            entry_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[entry_seeds],
+    )?;
+
+    let entry = DepositorAllowlistEntry::new(*pool_info.key, depositor, entry_bump);
+    let mut entry_data = entry_info.try_borrow_mut_data()?;
+    entry.serialize(&mut &mut entry_data[..])?;
+
+    msg!("Depositor {} allowlisted", depositor);
+
+    Ok(())
+}
+
+/// Remove `depositor` from the allowlist, closing its entry
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[writable]` Allowlist entry (PDA: ["depositor", pool, depositor])
+/// 2. `[writable, signer]` Authority or settings delegate (receives rent)
+pub fn process_remove_depositor(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    depositor: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let entry_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    load_pool_for_allowlist(program_id, pool_info, authority_info)?;
+
+    // Verify entry PDA
+    let (expected_entry, _) =
+        DepositorAllowlistEntry::derive_pda(pool_info.key, &depositor, program_id);
+    if *entry_info.key != expected_entry {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    if entry_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let entry = DepositorAllowlistEntry::try_from_slice(&entry_info.try_borrow_data()?)?;
+    if !entry.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Transfer all lamports from the entry to the authority (closes it)
+    let entry_lamports = entry_info.lamports();
+    **entry_info.try_borrow_mut_lamports()? = 0;
+    **authority_info.try_borrow_mut_lamports()? += entry_lamports;
+
+    // Zero out the account data so it can't be re-read as a valid entry
+    entry_info.try_borrow_mut_data()?.fill(0);
+
+    msg!("Depositor {} removed from the allowlist", depositor);
+
+    Ok(())
+}
+
+/// Check `depositor_info` may deposit rewards: always on pools that don't
+/// require allowlisted depositors, otherwise only if its allowlist entry is
+/// among `accounts` (matched by key)
+pub(crate) fn check_allowlisted_depositor(
+    program_id: &Pubkey,
+    pool: &StakingPool,
+    pool_info: &AccountInfo,
+    depositor_info: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if !pool.require_allowlisted_depositors {
+        return Ok(());
+    }
+    let (expected_entry, _) =
+        DepositorAllowlistEntry::derive_pda(pool_info.key, depositor_info.key, program_id);
+    let allowlisted = match accounts.iter().find(|info| *info.key == expected_entry) {
+        Some(entry_info) if entry_info.owner == program_id && !entry_info.data_is_empty() => {
+            let entry = DepositorAllowlistEntry::try_from_slice(&entry_info.try_borrow_data()?)?;
+            entry.is_initialized()
+                && entry.pool == *pool_info.key
+                && entry.depositor == *depositor_info.key
+        }
+        _ => false,
+    };
+    if !allowlisted {
+        msg!("Depositor {} is not allowlisted for this pool", depositor_info.key);
+        return Err(StakingError::DepositorNotAllowlisted.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_error::ProgramError;
+
+    #[test]
+    fn test_check_allowlisted_depositor() {
+        let program_id = crate::id();
+        let (pool_key, depositor_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), pool_key, Pubkey::new_unique(), 86_400, 0, 255);
+        let system_program = solana_program::system_program::id();
+        let (mut l1, mut l2, mut l3) = (0, 0, 1);
+        let (mut d1, mut d2) = (vec![], vec![]);
+        let pool_info = AccountInfo::new(&pool_key, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let depositor_info = AccountInfo::new(&depositor_key, true, true, &mut l2, &mut d2, &system_program, false, 0);
+
+        // Flag off: anyone may deposit, no entry needed
+        assert!(check_allowlisted_depositor(&program_id, &pool, &pool_info, &depositor_info, &[]).is_ok());
+
+        // Flag on: the entry is required
+        pool.require_allowlisted_depositors = true;
+        let not_allowlisted: ProgramError = StakingError::DepositorNotAllowlisted.into();
+        let check = |accounts: &[AccountInfo]| {
+            check_allowlisted_depositor(&program_id, &pool, &pool_info, &depositor_info, accounts)
+        };
+        assert_eq!(check(&[]), Err(not_allowlisted.clone()));
+
+        let (entry_key, bump) = DepositorAllowlistEntry::derive_pda(&pool_key, &depositor_key, &program_id);
+        let mut d3 = borsh::to_vec(&DepositorAllowlistEntry::new(pool_key, depositor_key, bump)).unwrap();
+        let entry_info = AccountInfo::new(&entry_key, false, false, &mut l3, &mut d3, &program_id, false, 0);
+        let accounts = [entry_info];
+        assert!(check(&accounts).is_ok());
+
+        // A closed (zeroed) entry no longer counts
+        accounts[0].try_borrow_mut_data().unwrap().fill(0);
+        assert_eq!(check(&accounts), Err(not_allowlisted));
+    }
+}
@@ -0,0 +1,156 @@
+//! Distribute bonus instruction - targeted rewards for chosen stakers
+//!
+//! Campaigns that reward a subset of stakers (e.g. the top N) fund each
+//! bonus straight into the stake's claimed_balance, bypassing the weighted
+//! accumulator. The lamports stay in the pool, reserved for their
+//! recipients like ClaimToBalance credits, until WithdrawClaimed.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UserStake},
+};
+
+/// Maximum stakes credited per DistributeBonus, keeping the instruction
+/// within the compute budget even when every stake account needs a realloc.
+pub const MAX_DISTRIBUTE_BONUS_STAKES: usize = 16;
+
+/// Credit each listed stake's claimed_balance with its bonus (permissionless;
+/// the funder pays)
+///
+/// `amounts[i]` goes to the i-th user stake account. Every stake must belong
+/// to the pool; zero amounts are skipped.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable, signer]` Funder (pays the bonuses and any realloc rent)
+/// 2. `[]` System program
+/// 3. `[writable]` User stake account #1
+///
+/// followed by one user stake account per further amount, at most
+/// MAX_DISTRIBUTE_BONUS_STAKES in total.
+pub fn process_distribute_bonus(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amounts: &[u64],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let funder_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let stake_infos = account_info_iter.as_slice();
+    if amounts.is_empty() || stake_infos.len() < amounts.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if amounts.len() > MAX_DISTRIBUTE_BONUS_STAKES {
+        return Err(StakingError::BatchTooLarge.into());
+    }
+
+    // Validate funder is signer
+    if !funder_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Validate system program
+    if *system_program_info.key != system_program::id() {
+        return Err(StakingError::MissingSystemProgram.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let total = amounts
+        .iter()
+        .try_fold(0u64, |sum, amount| sum.checked_add(*amount))
+        .ok_or(StakingError::MathOverflow)?;
+    if total == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
+    for (user_stake_info, &amount) in stake_infos.iter().zip(amounts) {
+        // Load and validate user stake
+        if user_stake_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+        if !user_stake.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
+        if user_stake.pool != *pool_info.key {
+            return Err(StakingError::InvalidPool.into());
+        }
+
+        // Verify user stake PDA
+        let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+        if *user_stake_info.key != expected_stake {
+            return Err(StakingError::InvalidPDA.into());
+        }
+
+        if amount == 0 {
+            continue;
+        }
+
+        user_stake.claimed_balance = user_stake
+            .claimed_balance
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+
+        // Grow legacy stake accounts to hold claimed_balance (payer = funder)
+        UserStake::maybe_realloc(user_stake_info, funder_info, Some(system_program_info))?;
+        user_stake.save(user_stake_info)?;
+
+        msg!("Bonus of {} lamports to {}", amount, user_stake.owner);
+    }
+
+    // Reserve the bonuses for their recipients; the lamports are not new
+    // rewards, so sync must not distribute them again
+    pool.total_claimed_balance = pool
+        .total_claimed_balance
+        .checked_add(total)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.last_synced_lamports = pool
+        .last_synced_lamports
+        .checked_add(total)
+        .ok_or(StakingError::MathOverflow)?;
+
+    invoke(
+        &system_instruction::transfer(funder_info.key, pool_info.key, total),
+        &[
+            funder_info.clone(),
+            pool_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+
+    // Grow a legacy pool to hold total_claimed_balance (payer = funder)
+    pool.ensure_capacity(pool_info, funder_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
+
+    msg!("Distributed {} lamports of bonuses to {} stakes", total, amounts.len());
+
+    Ok(())
+}
@@ -8,7 +8,7 @@
 //! After the fix, the user can claim their corrected rewards via normal
 //! ClaimRewards.
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     bpf_loader_upgradeable,
@@ -140,6 +140,8 @@ pub fn process_fix_stake_account(
 
     // ── Update reward_debt ───────────────────────────────────────────
 
+    // new_reward_debt is given at the pool's current accumulator precision
+    user_stake.sync_acc_scale(&pool);
     let old_debt = user_stake.reward_debt;
     if new_reward_debt != old_debt {
         if new_reward_debt >= old_debt {
@@ -159,14 +161,8 @@ pub fn process_fix_stake_account(
 
     // ── Save ─────────────────────────────────────────────────────────
 
-    {
-        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
-    }
-    {
-        let mut pool_data = pool_info.try_borrow_mut_data()?;
-        pool.serialize(&mut &mut pool_data[..])?;
-    }
+    user_stake.save(user_stake_info)?;
+    pool.save(pool_info)?;
 
     msg!(
         "Fixed stake account for user {}",
@@ -0,0 +1,67 @@
+//! Extend pool account instruction - grow a legacy pool to the current layout
+//!
+//! Pool layouts are identified by account length: fields appended since v4
+//! read as their defaults on shorter accounts. Instructions grow a pool
+//! lazily when they first set such a field; this grows it up front, with
+//! the caller paying the extra rent, without touching the reward accounting.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Realloc the pool account to `StakingPool::LEN` (permissionless; a no-op
+/// for pools that are already at least that large, never shrinks)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable, signer]` Payer (funds the extra rent)
+/// 2. `[]` System program
+pub fn process_extend_pool_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !payer_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let old_len = pool_info.data_len();
+    if old_len >= StakingPool::LEN {
+        msg!("Pool account already {} bytes", old_len);
+        return Ok(());
+    }
+
+    StakingPool::maybe_realloc(pool_info, payer_info, Some(system_program_info))?;
+
+    // Rewrite the full layout so the appended fields hold explicit defaults
+    pool.save(pool_info)?;
+
+    msg!("Extended pool account from {} to {} bytes", old_len, StakingPool::LEN);
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! Force unstake instruction - emergency exit from a quarantined pool
+//!
+//! A quarantined mint may be under the control of a hostile mint authority,
+//! so stakers must not be held in the pool by its lock or cooldown. This
+//! exits the whole position at once, including any pending unstake request,
+//! through the same settlement as a regular unstake. Inflows stay blocked by
+//! the quarantine itself.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool, UserStake},
+};
+
+use super::unstake::{execute_unstake, validate_destination_token_account};
+
+/// Unstake the full position of a quarantined pool, ignoring the lock and
+/// the unstake cooldown
+///
+/// The amount logged as received is what reached the destination account,
+/// which is less than the amount unstaked if the mint now charges a transfer
+/// fee.
+///
+/// Accounts (same as Unstake):
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` User stake account
+/// 2. `[writable]` Token vault
+/// 3. `[writable]` User token account (any account of the pool mint owned by the user)
+/// 4. `[]` Token mint
+/// 5. `[writable, signer]` User/owner
+/// 6. `[]` Token program
+/// 7. `[]` System program (optional, grows a legacy stake account)
+pub fn process_force_unstake_quarantined(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+    let user_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate token program (SPL Token or Token 2022)
+    if !is_valid_token_program(token_program_info.key) {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Only an emergency: healthy pools keep their lock and cooldown
+    if !pool.quarantined {
+        return Err(StakingError::PoolNotQuarantined.into());
+    }
+
+    // Check if pool needs rebasing
+    if pool.get_sum_stake_exp().needs_rebase() {
+        return Err(StakingError::PoolRequiresSync.into());
+    }
+
+    // Verify mint matches pool
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
+    // Verify token vault
+    if pool.token_vault != *token_vault_info.key {
+        return Err(StakingError::InvalidTokenVault.into());
+    }
+
+    // Verify destination token account
+    validate_destination_token_account(user_token_info, &pool, user_info.key)?;
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let amount = user_stake.amount;
+    if amount == 0 {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+
+    // Lazily adjust exp_start_factor if pool has been rebased
+    user_stake.sync_to_pool(&pool)?;
+
+    // A pending request is part of the position being exited
+    user_stake.unstake_request_amount = 0;
+    user_stake.unstake_request_time = 0;
+
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    // Optional trailing system program for legacy account reallocation
+    let system_program_info = account_info_iter.next();
+
+    let balance_before = token_account_amount(user_token_info)?;
+
+    execute_unstake(
+        program_id,
+        &mut pool,
+        &mut user_stake,
+        pool_info,
+        user_stake_info,
+        token_vault_info,
+        user_token_info,
+        mint_info,
+        user_info,
+        amount,
+        0,
+        current_time,
+        system_program_info,
+    )?;
+
+    let received = token_account_amount(user_token_info)?.saturating_sub(balance_before);
+    msg!(
+        "Forced exit from quarantined pool: {} tokens unstaked, {} received",
+        amount,
+        received
+    );
+
+    Ok(())
+}
+
+/// Token balance of a (validated) token account
+fn token_account_amount(token_info: &AccountInfo) -> Result<u64, ProgramError> {
+    let data = token_info.try_borrow_data()?;
+    let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(account.base.amount)
+}
@@ -0,0 +1,119 @@
+//! Get pending rewards instruction - claimable lamports for a dashboard
+//!
+//! Mirrors the claim path on copies of the pool and stake: lamports sent to
+//! the pool but not synced yet are distributed first (as SyncRewards would),
+//! then the stake's pending rewards are capped by what the pool can pay.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::WAD,
+    state::{StakingPool, UserStake},
+};
+
+/// Return the lamports a claim would pay the stake after the next sync,
+/// before the claim fee and fee split (permissionless, read-only)
+///
+/// Returned via return data as a u64 LE, followed by the stake's
+/// last_claim_time (i64 LE) and total_rewards_claimed (u64 LE). An empty
+/// pool or a stake with nothing staked and no residual rewards returns 0
+/// pending.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_get_pending_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(pool_info.data_len());
+    let available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
+
+    // Distribute unsynced lamports on the copy, as SyncRewards would
+    let unsynced = pool
+        .sync_accumulator(pool_info.lamports(), rent_exempt_minimum, current_time)?
+        .map_or(0, |(lamports, _)| lamports);
+
+    let pending_wad = if user_stake.amount == 0 {
+        // Residual rewards from a full unstake sit in reward_debt
+        user_stake.reward_debt
+    } else if pool.total_staked == 0 {
+        0
+    } else {
+        // Adjust the copy for any rebase or rescale; nothing is written back
+        user_stake.sync_to_pool(&pool)?;
+        let weighted = user_stake.weighted_stake(&pool, current_time)?;
+        user_stake.pending_rewards_wad(&pool, weighted)?
+    };
+
+    // A claim pays at most what the pool holds beyond reserved lamports
+    let claimable = available.saturating_sub(pool.reserved_lamports());
+    let pending = (pending_wad / WAD).min(claimable as u128) as u64;
+
+    msg!(
+        "Pending rewards: {} lamports ({} unsynced lamports included in the projection)",
+        pending,
+        unsynced
+    );
+
+    let mut data = [0u8; 24];
+    data[..8].copy_from_slice(&pending.to_le_bytes());
+    data[8..16].copy_from_slice(&user_stake.last_claim_time.to_le_bytes());
+    data[16..].copy_from_slice(&user_stake.total_rewards_claimed.to_le_bytes());
+    set_return_data(&data);
+
+    Ok(())
+}
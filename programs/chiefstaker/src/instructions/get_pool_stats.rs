@@ -0,0 +1,281 @@
+//! Get pool stats instruction - headline pool metrics in a stable layout
+//!
+//! Frontends polling TVL and reward rates used to decode the StakingPool
+//! account, which breaks whenever the struct grows. This returns the same
+//! figures as a versioned, fixed-offset record: new fields are only ever
+//! appended and bump POOL_STATS_VERSION, so a decoder written against one
+//! version keeps working on later ones.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::{U256, WAD_U256},
+    state::StakingPool,
+};
+
+/// Layout version of the returned record (first byte)
+pub const POOL_STATS_VERSION: u8 = 1;
+
+/// Size of a version 1 record
+pub const POOL_STATS_LEN: usize = 91;
+
+/// Flags bit: rewards are paid in an SPL token (amounts and rates are then
+/// in reward token units, and undistributed lamports are not reported)
+pub const POOL_STATS_SPL_REWARDS: u8 = 1 << 0;
+/// Flags bit: the pool is paused
+pub const POOL_STATS_PAUSED: u8 = 1 << 1;
+/// Flags bit: the pool is quarantined
+pub const POOL_STATS_QUARANTINED: u8 = 1 << 2;
+
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Pool metrics returned by GetPoolStats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub flags: u8,
+    pub total_staked: u128,
+    pub acc_reward_per_weighted_share: u128,
+    pub acc_scale_shift: u8,
+    pub undistributed_lamports: u64,
+    pub unreleased_stream_lamports: u64,
+    pub reward_rate: u64,
+    pub reward_rate_window_seconds: u64,
+    pub annual_rewards_per_token_wad: u128,
+    pub last_update_time: i64,
+}
+
+impl PoolStats {
+    /// Compute the stats of `pool` holding `pool_lamports` (rent exemption
+    /// `rent_exempt`) at `current_time`
+    pub fn compute(
+        pool: &StakingPool,
+        pool_lamports: u64,
+        rent_exempt: u64,
+        current_time: i64,
+    ) -> Self {
+        let mut flags = 0;
+        if pool.reward_mint().is_some() {
+            flags |= POOL_STATS_SPL_REWARDS;
+        }
+        if pool.paused {
+            flags |= POOL_STATS_PAUSED;
+        }
+        if pool.quarantined {
+            flags |= POOL_STATS_QUARANTINED;
+        }
+
+        let (undistributed_lamports, unreleased_stream_lamports) =
+            if flags & POOL_STATS_SPL_REWARDS != 0 {
+                (0, 0)
+            } else {
+                let reward_lamports = pool.reward_lamports(pool_lamports, rent_exempt, current_time);
+                (
+                    reward_lamports.saturating_sub(pool.last_synced_lamports),
+                    pool.unreleased_stream_lamports(current_time),
+                )
+            };
+
+        let reward_rate = pool.reward_rate(current_time);
+        let reward_rate_window_seconds = if pool.reward_rate_window_start == 0 {
+            0
+        } else {
+            current_time.saturating_sub(pool.reward_rate_window_start).max(0) as u64
+        };
+
+        // Recent rate run for a year, per staked token base unit, WAD-scaled
+        let annual_rewards_per_token_wad = if pool.total_staked == 0 {
+            0
+        } else {
+            let annual = U256::from_u128(reward_rate as u128 * SECONDS_PER_YEAR as u128);
+            (annual * WAD_U256 / U256::from_u128(pool.total_staked))
+                .to_u128()
+                .unwrap_or(u128::MAX)
+        };
+
+        Self {
+            flags,
+            total_staked: pool.total_staked,
+            acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+            acc_scale_shift: pool.acc_scale_shift,
+            undistributed_lamports,
+            unreleased_stream_lamports,
+            reward_rate,
+            reward_rate_window_seconds,
+            annual_rewards_per_token_wad,
+            last_update_time: pool.last_update_time,
+        }
+    }
+
+    /// Encode as a version 1 record (see process_get_pool_stats)
+    pub fn to_bytes(&self) -> [u8; POOL_STATS_LEN] {
+        let mut data = [0u8; POOL_STATS_LEN];
+        data[0] = POOL_STATS_VERSION;
+        data[1] = self.flags;
+        data[2..18].copy_from_slice(&self.total_staked.to_le_bytes());
+        data[18..34].copy_from_slice(&self.acc_reward_per_weighted_share.to_le_bytes());
+        data[34] = self.acc_scale_shift;
+        data[35..43].copy_from_slice(&self.undistributed_lamports.to_le_bytes());
+        data[43..51].copy_from_slice(&self.unreleased_stream_lamports.to_le_bytes());
+        data[51..59].copy_from_slice(&self.reward_rate.to_le_bytes());
+        data[59..67].copy_from_slice(&self.reward_rate_window_seconds.to_le_bytes());
+        data[67..83].copy_from_slice(&self.annual_rewards_per_token_wad.to_le_bytes());
+        data[83..91].copy_from_slice(&self.last_update_time.to_le_bytes());
+        data
+    }
+}
+
+/// Return the pool's TVL, accumulator, undistributed rewards and reward
+/// rate (permissionless, read-only)
+///
+/// Returned via return data as a little-endian record:
+///
+/// | Offset | Type | Field |
+/// |--------|------|-------|
+/// | 0  | u8   | version (1) |
+/// | 1  | u8   | flags (`POOL_STATS_*` bits) |
+/// | 2  | u128 | total_staked (token base units) |
+/// | 18 | u128 | acc_reward_per_weighted_share (WAD, scaled down by the shift) |
+/// | 34 | u8   | acc_scale_shift |
+/// | 35 | u64  | lamports above rent and last_synced_lamports, not yet distributed |
+/// | 43 | u64  | streamed deposit lamports not yet released |
+/// | 51 | u64  | reward rate over the recent window (reward units per second) |
+/// | 59 | u64  | reward rate window length in seconds |
+/// | 67 | u128 | reward rate run for a year per staked token base unit (WAD) |
+/// | 83 | i64  | last_update_time |
+///
+/// Later versions only append fields; decoders should check the version is
+/// at least the one they know and ignore trailing bytes. An APR follows
+/// from offset 67 as `value / WAD * reward price / staked token price`,
+/// both prices per base unit.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+pub fn process_get_pool_stats(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let rent_exempt = Rent::get()?.minimum_balance(pool_info.data_len());
+    let stats = PoolStats::compute(&pool, pool_info.lamports(), rent_exempt, current_time);
+
+    msg!(
+        "Pool stats: staked: {} acc: {} undistributed: {} rate: {}/s annual_per_token_wad: {}",
+        stats.total_staked,
+        stats.acc_reward_per_weighted_share,
+        stats.undistributed_lamports,
+        stats.reward_rate,
+        stats.annual_rewards_per_token_wad
+    );
+
+    set_return_data(&stats.to_bytes());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::WAD;
+
+    #[test]
+    fn test_pool_stats_layout() {
+        let stats = PoolStats {
+            flags: POOL_STATS_PAUSED,
+            total_staked: 1,
+            acc_reward_per_weighted_share: 2,
+            acc_scale_shift: 3,
+            undistributed_lamports: 4,
+            unreleased_stream_lamports: 5,
+            reward_rate: 6,
+            reward_rate_window_seconds: 7,
+            annual_rewards_per_token_wad: 8,
+            last_update_time: -9,
+        };
+        let data = stats.to_bytes();
+        assert_eq!(data.len(), POOL_STATS_LEN);
+        assert_eq!(data[0], 1);
+        assert_eq!(data[1], POOL_STATS_PAUSED);
+        assert_eq!(u128::from_le_bytes(data[2..18].try_into().unwrap()), 1);
+        assert_eq!(u128::from_le_bytes(data[18..34].try_into().unwrap()), 2);
+        assert_eq!(data[34], 3);
+        assert_eq!(u64::from_le_bytes(data[35..43].try_into().unwrap()), 4);
+        assert_eq!(u64::from_le_bytes(data[43..51].try_into().unwrap()), 5);
+        assert_eq!(u64::from_le_bytes(data[51..59].try_into().unwrap()), 6);
+        assert_eq!(u64::from_le_bytes(data[59..67].try_into().unwrap()), 7);
+        assert_eq!(u128::from_le_bytes(data[67..83].try_into().unwrap()), 8);
+        assert_eq!(i64::from_le_bytes(data[83..91].try_into().unwrap()), -9);
+    }
+
+    #[test]
+    fn test_pool_stats_compute() {
+        let pool_key = Pubkey::new_unique();
+        let mut pool = StakingPool::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            pool_key,
+            Pubkey::new_unique(),
+            86_400,
+            0,
+            255,
+        );
+        let rent = 1_000_000;
+
+        // Nothing staked or distributed yet
+        let stats = PoolStats::compute(&pool, rent + 500, rent, 1_000);
+        assert_eq!(stats.undistributed_lamports, 500);
+        assert_eq!(stats.reward_rate, 0);
+        assert_eq!(stats.annual_rewards_per_token_wad, 0);
+
+        // 100 lamports/s over 1e9 staked base units
+        pool.total_staked = 1_000_000_000;
+        pool.last_synced_lamports = 300;
+        pool.reward_rate_window_start = 1_000;
+        pool.reward_rate_window_sum = 100 * 60;
+        let stats = PoolStats::compute(&pool, rent + 500, rent, 1_060);
+        assert_eq!(stats.undistributed_lamports, 200);
+        assert_eq!(stats.reward_rate, 100);
+        assert_eq!(stats.reward_rate_window_seconds, 60);
+        assert_eq!(
+            stats.annual_rewards_per_token_wad,
+            100 * SECONDS_PER_YEAR as u128 * WAD / 1_000_000_000
+        );
+
+        // Balance below last_synced reports nothing undistributed
+        let stats = PoolStats::compute(&pool, rent + 100, rent, 1_060);
+        assert_eq!(stats.undistributed_lamports, 0);
+
+        // SPL-token reward pools don't report lamports
+        pool.reward_mint = Pubkey::new_unique();
+        let stats = PoolStats::compute(&pool, rent + 500, rent, 1_060);
+        assert_eq!(stats.flags & POOL_STATS_SPL_REWARDS, POOL_STATS_SPL_REWARDS);
+        assert_eq!(stats.undistributed_lamports, 0);
+        assert_eq!(stats.reward_rate, 100);
+    }
+}
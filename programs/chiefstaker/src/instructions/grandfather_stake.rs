@@ -0,0 +1,172 @@
+//! Grandfather stake instruction (authority or grandfather delegate)
+//!
+//! Credits prior staking time to a user migrated from a predecessor pool by
+//! moving their effective stake start time earlier. The new start time is
+//! bounded by the pool's creation time and the current time, can only move
+//! backward, and can be applied once per stake account.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::{exp_neg_time_ratio, exp_time_ratio, wad_mul, U256, WAD},
+    state::{StakingPool, UserStake, DELEGATE_PERM_GRANDFATHER},
+};
+
+/// Set a user's effective stake start time to an earlier timestamp
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` User stake account
+/// 2. `[writable, signer]` Authority or grandfather delegate (pays rent if the stake account needs to grow)
+/// 3. `[]` System program
+pub fn process_grandfather_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    effective_stake_time: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // ── Validate authority ───────────────────────────────────────────
+
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // ── Load and validate pool ───────────────────────────────────────
+
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_GRANDFATHER) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    if pool.get_sum_stake_exp().needs_rebase() {
+        return Err(StakingError::PoolRequiresSync.into());
+    }
+
+    // ── Load and validate user stake ─────────────────────────────────
+
+    // Realloc legacy accounts to current size (payer = authority)
+    UserStake::maybe_realloc(user_stake_info, authority_info, Some(system_program_info))?;
+
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    if user_stake.amount == 0 {
+        msg!("User has no active stake");
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+    if user_stake.grandfathered {
+        return Err(StakingError::AlreadyGrandfathered.into());
+    }
+
+    // ── Validate new start time ──────────────────────────────────────
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // base_time is the creation time until the first rebase records it
+    let pool_creation_time = if pool.initial_base_time != 0 {
+        pool.initial_base_time
+    } else {
+        pool.base_time
+    };
+
+    if effective_stake_time > current_time
+        || effective_stake_time < pool_creation_time
+        || effective_stake_time >= user_stake.stake_time
+    {
+        msg!(
+            "Effective stake time {} must be in [{}, {}) (pool creation, current stake time)",
+            effective_stake_time,
+            pool_creation_time,
+            user_stake.stake_time
+        );
+        return Err(StakingError::InvalidStakeTime.into());
+    }
+
+    // Lazily adjust exp_start_factor if pool has been rebased
+    user_stake.sync_to_pool(&pool)?;
+
+    // exp_start_factor = e^((effective_stake_time - base_time) / tau),
+    // which falls below WAD when the new start predates the last rebase
+    let offset = effective_stake_time.saturating_sub(pool.base_time);
+    let new_exp_start_factor = if offset >= 0 {
+        exp_time_ratio(offset, pool.tau_seconds)?
+    } else {
+        exp_neg_time_ratio(-offset, pool.tau_seconds)?
+    };
+
+    // ── Update sum_stake_exp ─────────────────────────────────────────
+
+    let amount_wad = (user_stake.amount as u128)
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    let old_contribution = wad_mul(amount_wad, user_stake.exp_start_factor)?;
+    let new_contribution = wad_mul(amount_wad, new_exp_start_factor)?;
+
+    // Moving the start earlier can only shrink the contribution
+    let delta = U256::from_u128(old_contribution.saturating_sub(new_contribution));
+    let sum = pool.get_sum_stake_exp().saturating_sub(delta);
+    pool.set_sum_stake_exp(sum);
+
+    msg!(
+        "Grandfathered stake of {}: start {} -> {}, exp_start_factor {} -> {}",
+        user_stake.owner,
+        user_stake.stake_time,
+        effective_stake_time,
+        user_stake.exp_start_factor,
+        new_exp_start_factor
+    );
+
+    user_stake.exp_start_factor = new_exp_start_factor;
+    user_stake.stake_time = effective_stake_time;
+    user_stake.grandfathered = true;
+
+    // ── Save ─────────────────────────────────────────────────────────
+
+    user_stake.save(user_stake_info)?;
+    pool.save(pool_info)?;
+
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! HarvestCreatorFees — permissionless crank
+//!
+//! Once TakeFeeOwnership has made the pool the sole pfee shareholder, the
+//! creator fees still accrue in pump's creator vault (bonding curve trades)
+//! and in PumpSwap's wSOL creator vault ATA (AMM trades), both owned by the
+//! SharingConfig rather than the pool. This crank pulls them into the pool
+//! and syncs them, replacing an off-chain bot:
+//!
+//! 1. PumpSwap `transfer_creator_fees_to_pump` unwraps the AMM fees into
+//!    pump's creator vault (skipped while the ATA is missing or empty)
+//! 2. pump `distribute_creator_fees` pays the creator vault out to the
+//!    SharingConfig's shareholders, i.e. the pool (skipped at rent minimum)
+//! 3. the same sync as SyncRewards makes the harvest distributable
+//!
+//! Neither CPI needs a signature: the SharingConfig, not the pool, is the
+//! coin creator, and both pump instructions are permissionless. The account
+//! list is TakeFeeOwnership's, with the pool writable, and is checked the
+//! same way before the first CPI.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    pfee::{FeeOwnershipAccounts, SharingConfig},
+    state::{StakingPool, PFEE_PROGRAM_ID},
+};
+
+use super::sync_rewards::distribute_unsynced;
+
+/// PumpSwap transfer_creator_fees_to_pump discriminator
+const TRANSFER_CREATOR_FEES_TO_PUMP_DISC: [u8; 8] = [139, 52, 134, 85, 228, 229, 108, 241];
+
+/// pump distribute_creator_fees discriminator
+const DISTRIBUTE_CREATOR_FEES_DISC: [u8; 8] = [165, 114, 103, 0, 121, 206, 247, 81];
+
+/// Harvest the mint's pump.fun creator fees into the pool and sync them
+///
+/// Fails with `PoolNotFeeRecipient` unless TakeFeeOwnership left the pool
+/// as the sole shareholder, and with `RewardModeMismatch` on SPL-token
+/// reward pools. Allowed while paused, like SyncRewards.
+///
+/// Accounts (18, TakeFeeOwnership's order):
+///  0. `[W]` pool — Pool PDA ["pool", mint], receives the fees
+///  1. `[]`  mint — must match pool.mint
+///  2. `[]`  pfee_program
+///  3. `[]`  pfee_event_authority — PDA on pfee: ["__event_authority"]
+///  4. `[]`  pump_global — PDA on pump: ["global"]
+///  5. `[W]` sharing_config — PDA on pfee: ["sharing-config", mint]
+///  6. `[]`  bonding_curve — PDA on pump: ["bonding-curve", mint]
+///  7. `[W]` pump_creator_vault — PDA on pump: ["creator-vault", sharing_config]
+///  8. `[]`  system_program
+///  9. `[]`  pump_program
+/// 10. `[]`  pump_event_authority — PDA on pump: ["__event_authority"]
+/// 11. `[]`  pump_amm_program
+/// 12. `[]`  amm_event_authority — PDA on AMM: ["__event_authority"]
+/// 13. `[]`  wsol_mint
+/// 14. `[]`  token_program
+/// 15. `[]`  associated_token_program
+/// 16. `[W]` coin_creator_vault_auth — PDA on AMM: ["creator_vault", sharing_config]
+/// 17. `[W]` coin_creator_vault_ata — ATA of wSOL for #16
+///
+/// Optional trailing accounts: as SyncRewards (audit log page, payer,
+/// system program is already #8)
+pub fn process_harvest_creator_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pfee_program_info = next_account_info(account_info_iter)?;
+    let pfee_event_authority_info = next_account_info(account_info_iter)?;
+    let pump_global_info = next_account_info(account_info_iter)?;
+    let sharing_config_info = next_account_info(account_info_iter)?;
+    let bonding_curve_info = next_account_info(account_info_iter)?;
+    let pump_creator_vault_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let pump_program_info = next_account_info(account_info_iter)?;
+    let pump_event_authority_info = next_account_info(account_info_iter)?;
+    let pump_amm_program_info = next_account_info(account_info_iter)?;
+    let amm_event_authority_info = next_account_info(account_info_iter)?;
+    let wsol_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let associated_token_program_info = next_account_info(account_info_iter)?;
+    let coin_creator_vault_auth_info = next_account_info(account_info_iter)?;
+    let coin_creator_vault_ata_info = next_account_info(account_info_iter)?;
+
+    // ── Validate pool ───────────────────────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Verify mint matches pool
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    // Harvested fees are SOL
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Verify pfee program address
+    if *pfee_program_info.key != PFEE_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    FeeOwnershipAccounts {
+        pfee_event_authority: *pfee_event_authority_info.key,
+        pump_global: *pump_global_info.key,
+        sharing_config: *sharing_config_info.key,
+        bonding_curve: *bonding_curve_info.key,
+        pump_creator_vault: *pump_creator_vault_info.key,
+        system_program: *system_program_info.key,
+        pump_program: *pump_program_info.key,
+        pump_event_authority: *pump_event_authority_info.key,
+        pump_amm_program: *pump_amm_program_info.key,
+        amm_event_authority: *amm_event_authority_info.key,
+        wsol_mint: *wsol_mint_info.key,
+        token_program: *token_program_info.key,
+        associated_token_program: *associated_token_program_info.key,
+        coin_creator_vault_auth: *coin_creator_vault_auth_info.key,
+        coin_creator_vault_ata: *coin_creator_vault_ata_info.key,
+    }
+    .check(&pool.mint)?;
+
+    // distribute_creator_fees pays every shareholder, and only the pool is
+    // passed: anything but sole ownership would fail inside pump
+    if *sharing_config_info.owner != PFEE_PROGRAM_ID {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let config = SharingConfig::parse(&sharing_config_info.try_borrow_data()?)?;
+    if !config.is_sole_recipient(pool_info.key) {
+        msg!("Pool is not the sole fee recipient; run TakeFeeOwnership first");
+        return Err(StakingError::PoolNotFeeRecipient.into());
+    }
+
+    let lamports_before = pool_info.lamports();
+
+    // ── CPI 1: transfer_creator_fees_to_pump (AMM wSOL → creator vault) ─────
+    let amm_fees = if *coin_creator_vault_ata_info.owner == *token_program_info.key
+        && !coin_creator_vault_ata_info.data_is_empty()
+    {
+        spl_token_2022::state::Account::unpack(&coin_creator_vault_ata_info.try_borrow_data()?)?
+            .amount
+    } else {
+        0
+    };
+
+    if amm_fees > 0 {
+        let transfer_accounts = vec![
+            AccountMeta::new_readonly(*wsol_mint_info.key, false),             // 0 wsol_mint
+            AccountMeta::new_readonly(*token_program_info.key, false),         // 1 token_program
+            AccountMeta::new_readonly(*system_program_info.key, false),        // 2 system_program
+            AccountMeta::new_readonly(*associated_token_program_info.key, false), // 3 ata_program
+            AccountMeta::new_readonly(*sharing_config_info.key, false),        // 4 coin_creator
+            AccountMeta::new(*coin_creator_vault_auth_info.key, false),        // 5 coin_creator_vault_authority
+            AccountMeta::new(*coin_creator_vault_ata_info.key, false),         // 6 coin_creator_vault_ata
+            AccountMeta::new(*pump_creator_vault_info.key, false),             // 7 pump_creator_vault
+            AccountMeta::new_readonly(*amm_event_authority_info.key, false),   // 8 event_authority
+            AccountMeta::new_readonly(*pump_amm_program_info.key, false),      // 9 program (self)
+        ];
+
+        invoke(
+            &Instruction {
+                program_id: *pump_amm_program_info.key,
+                accounts: transfer_accounts,
+                data: TRANSFER_CREATOR_FEES_TO_PUMP_DISC.to_vec(),
+            },
+            &[
+                wsol_mint_info.clone(),
+                token_program_info.clone(),
+                system_program_info.clone(),
+                associated_token_program_info.clone(),
+                sharing_config_info.clone(),
+                coin_creator_vault_auth_info.clone(),
+                coin_creator_vault_ata_info.clone(),
+                pump_creator_vault_info.clone(),
+                amm_event_authority_info.clone(),
+                pump_amm_program_info.clone(),
+            ],
+        )?;
+
+        msg!("Moved {} lamports of AMM creator fees to the pump creator vault", amm_fees);
+    }
+
+    // ── CPI 2: distribute_creator_fees (creator vault → pool) ───────────────
+    let vault_minimum = Rent::get()?.minimum_balance(0);
+    if pump_creator_vault_info.lamports() > vault_minimum {
+        let distribute_accounts = vec![
+            AccountMeta::new_readonly(*mint_info.key, false),                  // 0 mint
+            AccountMeta::new_readonly(*bonding_curve_info.key, false),         // 1 bonding_curve
+            AccountMeta::new_readonly(*sharing_config_info.key, false),        // 2 sharing_config
+            AccountMeta::new(*pump_creator_vault_info.key, false),             // 3 creator_vault
+            AccountMeta::new_readonly(*system_program_info.key, false),        // 4 system_program
+            AccountMeta::new_readonly(*pump_event_authority_info.key, false),  // 5 event_authority
+            AccountMeta::new_readonly(*pump_program_info.key, false),          // 6 program (self)
+            AccountMeta::new(*pool_info.key, false),                           // 7 shareholder: pool
+        ];
+
+        invoke(
+            &Instruction {
+                program_id: *pump_program_info.key,
+                accounts: distribute_accounts,
+                data: DISTRIBUTE_CREATOR_FEES_DISC.to_vec(),
+            },
+            &[
+                mint_info.clone(),
+                bonding_curve_info.clone(),
+                sharing_config_info.clone(),
+                pump_creator_vault_info.clone(),
+                system_program_info.clone(),
+                pump_event_authority_info.clone(),
+                pump_program_info.clone(),
+                pool_info.clone(),
+            ],
+        )?;
+    }
+
+    let harvested = pool_info.lamports().saturating_sub(lamports_before);
+    msg!("Harvested {} lamports of creator fees into pool {}", harvested, pool_info.key);
+
+    distribute_unsynced(program_id, &mut pool, pool_info, accounts)
+}
@@ -1,12 +1,12 @@
 //! Initialize a staking pool for a Token 2022 mint
 
-use borsh::BorshSerialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
     program::invoke_signed,
+    program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
@@ -29,12 +29,63 @@ use spl_token_metadata_interface::state::TokenMetadata;
 use crate::{
     error::StakingError,
     state::{
-        is_valid_token_program, StakingPool, METAPLEX_PROGRAM_ID, METEORA_DBC_CREATOR_DISC,
+        is_valid_token_program, AuditLogPage, StakingPool, METAPLEX_PROGRAM_ID, METEORA_DBC_CREATOR_DISC,
         METEORA_DBC_CREATOR_PROGRAM_ID, PFEE_PROGRAM_ID, PFEE_SHARING_CONFIG_DISC, POOL_SEED,
         PUMP_AMM_POOL_DISC, PUMP_AMM_PROGRAM_ID, PUMP_PROGRAM_ID, TOKEN_VAULT_SEED,
     },
 };
 
+use super::audit_log::create_audit_log_page;
+use super::update_settings::{apply_settings_update, PoolSettingsUpdate};
+
+/// Minimum tau: 60s, to prevent near-instant maturation
+pub(crate) const MIN_TAU_SECONDS: u64 = 60;
+
+/// Maximum tau: ~10 years, to ensure weights eventually mature
+pub(crate) const MAX_TAU_SECONDS: u64 = 10 * 365 * 24 * 60 * 60;
+
+/// Reject Token 2022 mint extensions that break the pool's invariants.
+/// Shared with QuarantinePool, which re-checks the mint after init.
+pub fn check_mint_extensions(mint_state: &StateWithExtensions<Mint>) -> Result<(), StakingError> {
+    // Reject mints with transfer fee extension — fee-on-transfer tokens
+    // would cause total_staked to diverge from actual vault balance,
+    // eventually bricking unstakes for later users.
+    if mint_state.get_extension::<TransferFeeConfig>().is_ok() {
+        msg!("Token 2022 mints with TransferFee extension are not supported");
+        return Err(StakingError::InvalidPoolMint);
+    }
+
+    // Reject mints with PermanentDelegate — the delegate can transfer tokens
+    // out of the vault at any time, breaking the total_staked invariant and
+    // enabling theft of all staked tokens.
+    if mint_state.get_extension::<PermanentDelegate>().is_ok() {
+        msg!("Token 2022 mints with PermanentDelegate extension are not supported");
+        return Err(StakingError::UnsupportedMintExtension);
+    }
+
+    // Reject mints with TransferHook — allows arbitrary program execution
+    // during every transfer CPI (stake/unstake), which could manipulate
+    // state or extract MEV.
+    if mint_state.get_extension::<TransferHook>().is_ok() {
+        msg!("Token 2022 mints with TransferHook extension are not supported");
+        return Err(StakingError::UnsupportedMintExtension);
+    }
+
+    Ok(())
+}
+
+/// Size of the pool's token vault account under `token_program`.
+/// Shared with ReinitializeVault.
+pub fn vault_account_size(token_program: &Pubkey) -> Result<usize, ProgramError> {
+    if *token_program == spl_token_2022::id() {
+        spl_token_2022::extension::ExtensionType::try_calculate_account_len::<
+            spl_token_2022::state::Account,
+        >(&[])
+    } else {
+        Ok(spl_token_2022::state::Account::LEN)
+    }
+}
+
 /// Initialize a new staking pool
 ///
 /// Accounts:
@@ -45,10 +96,17 @@ use crate::{
 /// 4. `[]` System program
 /// 5. `[]` Token 2022 program
 /// 6. `[]` Rent sysvar
+/// 7. `[writable]` Audit log page 0 (with `enable_audit_log`; matched by key
+///    among the trailing accounts)
+///
+/// `initial_settings` is applied with UpdatePoolSettings' validation, so an
+/// out-of-range value fails the whole initialization.
 pub fn process_initialize_pool(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     tau_seconds: u64,
+    enable_audit_log: bool,
+    initial_settings: PoolSettingsUpdate,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -70,10 +128,7 @@ pub fn process_initialize_pool(
         return Err(StakingError::MissingRequiredSigner.into());
     }
 
-    // Validate tau_seconds (min 60s to prevent near-instant maturation,
-    // max ~10 years to ensure weights eventually mature)
-    const MIN_TAU_SECONDS: u64 = 60;
-    const MAX_TAU_SECONDS: u64 = 10 * 365 * 24 * 60 * 60; // ~10 years
+    // Validate tau_seconds
     if tau_seconds < MIN_TAU_SECONDS || tau_seconds > MAX_TAU_SECONDS {
         return Err(StakingError::InvalidTau.into());
     }
@@ -86,33 +141,12 @@ pub fn process_initialize_pool(
     // Verify mint is valid by trying to unpack it
     let mint_data = mint_info.try_borrow_data()?;
     let mint_state = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    let mint_decimals = mint_state.base.decimals;
 
     // Reject Token 2022 mints with dangerous extensions
     // (SPL Token mints have no extensions, so these checks are skipped naturally)
     if *token_program_info.key == spl_token_2022::id() {
-        // Reject mints with transfer fee extension — fee-on-transfer tokens
-        // would cause total_staked to diverge from actual vault balance,
-        // eventually bricking unstakes for later users.
-        if mint_state.get_extension::<TransferFeeConfig>().is_ok() {
-            msg!("Token 2022 mints with TransferFee extension are not supported");
-            return Err(StakingError::InvalidPoolMint.into());
-        }
-
-        // Reject mints with PermanentDelegate — the delegate can transfer tokens
-        // out of the vault at any time, breaking the total_staked invariant and
-        // enabling theft of all staked tokens.
-        if mint_state.get_extension::<PermanentDelegate>().is_ok() {
-            msg!("Token 2022 mints with PermanentDelegate extension are not supported");
-            return Err(StakingError::UnsupportedMintExtension.into());
-        }
-
-        // Reject mints with TransferHook — allows arbitrary program execution
-        // during every transfer CPI (stake/unstake), which could manipulate
-        // state or extract MEV.
-        if mint_state.get_extension::<TransferHook>().is_ok() {
-            msg!("Token 2022 mints with TransferHook extension are not supported");
-            return Err(StakingError::UnsupportedMintExtension.into());
-        }
+        check_mint_extensions(&mint_state)?;
     }
 
     // === Authority check: signer must match a known authority for this mint ===
@@ -314,14 +348,7 @@ pub fn process_initialize_pool(
     // Create token vault account
     let vault_seeds = &[TOKEN_VAULT_SEED, pool_info.key.as_ref(), &[vault_bump]];
 
-    // Get the size needed for a token account
-    let vault_size = if *token_program_info.key == spl_token_2022::id() {
-        spl_token_2022::extension::ExtensionType::try_calculate_account_len::<
-            spl_token_2022::state::Account,
-        >(&[])?
-    } else {
-        spl_token_2022::state::Account::LEN
-    };
+    let vault_size = vault_account_size(token_program_info.key)?;
     let vault_rent = rent.minimum_balance(vault_size);
 
     invoke_signed(
@@ -353,7 +380,7 @@ pub fn process_initialize_pool(
     )?;
 
     // Initialize pool state
-    let pool = StakingPool::new(
+    let mut pool = StakingPool::new(
         *mint_info.key,
         *token_vault_info.key,
         *pool_info.key, // Reward vault is the pool itself (stores SOL as lamports)
@@ -362,10 +389,32 @@ pub fn process_initialize_pool(
         clock.unix_timestamp,
         pool_bump,
     );
+    pool.set_mint_decimals(mint_decimals);
+    pool.token_program = *token_program_info.key;
+    apply_settings_update(&mut pool, &initial_settings)?;
+    // The reward rate averages over the pool's life until its window fills
+    pool.reward_rate_window_start = clock.unix_timestamp;
+
+    // Opt-in audit log: page 0 exists from the start
+    if enable_audit_log {
+        let (expected_page, _) = AuditLogPage::derive_pda(pool_info.key, 0, program_id);
+        let page_info = accounts[7..]
+            .iter()
+            .find(|info| *info.key == expected_page)
+            .ok_or(StakingError::AuditLogPageRequired)?;
+        create_audit_log_page(
+            program_id,
+            pool_info.key,
+            0,
+            page_info,
+            authority_info,
+            system_program_info,
+        )?;
+        pool.audit_log_enabled = true;
+    }
 
     // Serialize pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    pool.save(pool_info)?;
 
     msg!("Initialized staking pool for mint {}", mint_info.key);
     msg!("Tau: {} seconds", tau_seconds);
@@ -0,0 +1,187 @@
+//! Open an additional stake position for a wallet
+//!
+//! A wallet's default stake account (["stake", pool, owner]) is created by
+//! its first Stake. Further positions live at ["stake", pool, owner, index]
+//! and are opened empty here; Stake, Unstake, ClaimRewards and the rest take
+//! whichever position account is passed. Each position has its own amount,
+//! reward debt, maturity and lock terms, so positions never share rewards,
+//! and closing one leaves the others untouched.
+//!
+//! An owner holds at most POSITION_SLOTS positions per pool, opened in index
+//! order. The default stake account records the highest index opened and how
+//! many positions are open, so a deposit that excludes its depositor can
+//! require every one of their positions, and the default account can close
+//! once the last of them has.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{
+        derive_user_stake_position_pda, PoolMetadata, StakingPool, UserStake, POSITION_SLOTS,
+        STAKE_SEED,
+    },
+};
+
+use super::stake::count_new_member;
+
+/// Create the owner's empty stake position `index`
+///
+/// The first Stake into the position starts it exactly like a new stake
+/// (minimum stake, maturity, lock terms and referrer apply from then on).
+///
+/// For an index above 0 the owner's default stake account must already be
+/// open: it records the highest index opened and the open position count
+/// (grown to the current size, paid by the owner) and can't be closed while
+/// any position is open. Fails with `DefaultStakeRequired` otherwise, and
+/// with `InvalidPositionIndex` for an index of POSITION_SLOTS or more or one
+/// that skips past the next unused index.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[writable]` Stake position (PDA: ["stake", pool, owner, index (u16 LE)],
+///    or ["stake", pool, owner] for index 0)
+/// 2. `[writable, signer]` Owner (pays rent)
+/// 3. `[]` System program
+///
+/// Optional trailing accounts (matched by key):
+/// - `[writable]` Default stake account (PDA: ["stake", pool, owner]);
+///   required for an index above 0
+/// - `[writable]` Pool metadata (member_count is incremented)
+pub fn process_initialize_user_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Quarantined and paused pools only allow exits
+    if pool.quarantined {
+        return Err(StakingError::PoolQuarantined.into());
+    }
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
+    if index as usize >= POSITION_SLOTS {
+        return Err(StakingError::InvalidPositionIndex.into());
+    }
+
+    // Verify position PDA
+    let (expected_stake, stake_bump) =
+        derive_user_stake_position_pda(user_info.key, pool_info.key, index, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    if !user_stake_info.data_is_empty() {
+        return Err(StakingError::AlreadyInitialized.into());
+    }
+
+    let trailing = account_info_iter.as_slice();
+
+    // Record the position on the default stake account
+    if index > 0 {
+        let (expected_default, _) = UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+        let default_info = trailing
+            .iter()
+            .find(|info| *info.key == expected_default)
+            .filter(|info| info.owner == program_id && !info.data_is_empty())
+            .ok_or(StakingError::DefaultStakeRequired)?;
+        let mut default_stake = UserStake::try_from_slice(&default_info.try_borrow_data()?)?;
+        if !default_stake.is_initialized() {
+            return Err(StakingError::DefaultStakeRequired.into());
+        }
+        // Indices are opened in order (closed ones below the highest can
+        // be reopened), so the depositor positions a deposit requires stay
+        // contiguous
+        if index > default_stake.max_position_index + 1 {
+            msg!("Next unused position index is {}", default_stake.max_position_index + 1);
+            return Err(StakingError::InvalidPositionIndex.into());
+        }
+        default_stake.open_position(index);
+        UserStake::maybe_realloc(default_info, user_info, Some(system_program_info))?;
+        default_stake.save(default_info)?;
+    }
+
+    let rent = Rent::get()?;
+    let stake_rent = rent.minimum_balance(UserStake::LEN);
+    let index_bytes = index.to_le_bytes();
+    let bump_seed = [stake_bump];
+    let stake_seeds: &[&[u8]] = if index == 0 {
+        &[STAKE_SEED, pool_info.key.as_ref(), user_info.key.as_ref(), &bump_seed]
+    } else {
+        &[
+            STAKE_SEED,
+            pool_info.key.as_ref(),
+            user_info.key.as_ref(),
+            &index_bytes,
+            &bump_seed,
+        ]
+    };
+
+    invoke_signed(
+        &system_instruction::create_account(
+            user_info.key,
+            user_stake_info.key,
+            stake_rent,
+            UserStake::LEN as u64,
+            program_id,
+        ),
+        &[
+            user_info.clone(),
+            user_stake_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[stake_seeds],
+    )?;
+
+    // Empty and never staked: stake_time stays 0 until the first Stake
+    let mut user_stake =
+        UserStake::new(*user_info.key, *pool_info.key, 0, 0, 0, stake_bump, pool.base_time);
+    user_stake.acc_scale_shift = pool.acc_scale_shift;
+    user_stake.position_index = index;
+    user_stake.save(user_stake_info)?;
+
+    let (expected_metadata, _) = PoolMetadata::derive_pda(pool_info.key, program_id);
+    let metadata_info = trailing.iter().find(|info| *info.key == expected_metadata);
+    count_new_member(program_id, pool_info, metadata_info)?;
+
+    msg!("Opened stake position {} for {}", index, user_info.key);
+
+    Ok(())
+}
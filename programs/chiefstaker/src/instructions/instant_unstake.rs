@@ -0,0 +1,175 @@
+//! Instant unstake instruction - skip the unstake cooldown for a penalty
+//!
+//! For stakers who would rather pay than wait out unstake_cooldown_seconds.
+//! The pool's early_exit_penalty_bps of the unstaked amount is withheld and
+//! the rest is returned right away; rewards are settled exactly as in
+//! Unstake (pending SOL paid, forfeited immature rewards redistributed).
+//!
+//! The whole amount leaves the position and total_staked, so the withheld
+//! tokens earn nothing and never dilute the remaining stakers' reward share.
+//! They stay in the vault, counted in penalty_tokens, and no instruction pays
+//! them out: the penalty takes them out of circulation.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool, UserStake},
+};
+
+use super::unstake::{execute_unstake, validate_destination_token_account};
+
+/// Unstake `amount` tokens without the cooldown, minus the early exit penalty
+///
+/// Fails with `InstantUnstakeDisabled` while early_exit_penalty_bps is 0.
+/// The lock duration still applies, and a pending unstake request must be
+/// cancelled first.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` User stake account
+/// 2. `[writable]` Token vault
+/// 3. `[writable]` User token account (any account of the pool mint owned by the user)
+/// 4. `[]` Token mint
+/// 5. `[writable, signer]` User/owner
+/// 6. `[]` Token 2022 program
+/// 7. `[]` System program (optional, required for legacy accounts)
+pub fn process_instant_unstake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+    let user_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate token program (SPL Token or Token 2022)
+    if !is_valid_token_program(token_program_info.key) {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    if pool.early_exit_penalty_bps == 0 {
+        return Err(StakingError::InstantUnstakeDisabled.into());
+    }
+
+    // Check if pool needs rebasing
+    if pool.get_sum_stake_exp().needs_rebase() {
+        return Err(StakingError::PoolRequiresSync.into());
+    }
+
+    // Verify mint matches pool
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
+    // Verify token vault
+    if pool.token_vault != *token_vault_info.key {
+        return Err(StakingError::InvalidTokenVault.into());
+    }
+
+    // Verify destination token account
+    validate_destination_token_account(user_token_info, &pool, user_info.key)?;
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check sufficient balance
+    if user_stake.amount < amount {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+
+    // Block if pending unstake request (its tokens are already on their way out)
+    if user_stake.has_pending_unstake_request() {
+        return Err(StakingError::PendingUnstakeRequestExists.into());
+    }
+
+    // Lazily adjust exp_start_factor if pool has been rebased
+    user_stake.sync_to_pool(&pool)?;
+
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    // The penalty only buys out the cooldown, not the lock
+    user_stake.check_unlocked(&pool, amount, current_time)?;
+
+    let penalty = pool.early_exit_penalty(amount);
+
+    // Optional trailing system program for legacy account reallocation
+    let system_program_info = account_info_iter.next();
+
+    execute_unstake(
+        program_id,
+        &mut pool,
+        &mut user_stake,
+        pool_info,
+        user_stake_info,
+        token_vault_info,
+        user_token_info,
+        mint_info,
+        user_info,
+        amount,
+        penalty,
+        current_time,
+        system_program_info,
+    )
+}
@@ -0,0 +1,86 @@
+//! Lock pool settings instruction (authority only, irreversible)
+//!
+//! A middle ground between full control and renouncing authority: the
+//! economic settings (UpdatePoolSettings, SetRewardFeeSplit,
+//! SetUnstakeDestinationPolicy, SetRewardPrecision) are frozen for good,
+//! while the authority can still run ForceSyncWithBalance, collect protocol
+//! fees, grandfather stakes and transfer or renounce authority.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Permanently freeze the pool's economic settings (dropping any change
+/// still waiting for the settings timelock)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_lock_pool_settings(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    if pool.settings_locked {
+        msg!("Pool settings already locked");
+        return Ok(());
+    }
+
+    pool.settings_locked = true;
+
+    // A change still waiting for the timelock can never apply now
+    if pool.pending_settings_mask != 0 {
+        msg!("Dropping pending settings change {:#06x}", pool.pending_settings_mask);
+        pool.clear_pending_settings(u16::MAX);
+    }
+
+    // Grow legacy pools to hold the flag (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    msg!("Pool settings locked");
+
+    Ok(())
+}
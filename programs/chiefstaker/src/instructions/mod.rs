@@ -17,6 +17,56 @@ pub mod set_metadata;
 pub mod take_fee_ownership;
 pub mod stake_on_behalf;
 pub mod distribute_surplus;
+pub mod verify_vault_consistency;
+pub mod withdraw_claimed;
+pub mod grandfather_stake;
+pub mod split_stake;
+pub mod query_claimable;
+pub mod collect_protocol_fees;
+pub mod quarantine_pool;
+pub mod view_pool_health;
+pub mod batch_request_unstake;
+pub mod set_claim_for_opt_in;
+pub mod set_reward_fee_split;
+pub mod extend_pool_account;
+pub mod reverse_unstake;
+pub mod query_min_stake;
+pub mod audit_log;
+pub mod set_unstake_destination_policy;
+pub mod query_time_to_unlock;
+pub mod query_effective_settings;
+pub mod query_accumulator_snapshot;
+pub mod force_unstake_quarantined;
+pub mod lock_pool_settings;
+pub mod query_reward_rate;
+pub mod set_display_unit;
+pub mod query_is_completable_batch;
+pub mod reinitialize_vault;
+pub mod distribute_bonus;
+pub mod query_pending_unstake;
+pub mod set_reward_precision;
+pub mod claim_and_close;
+pub mod query_weighted_share;
+pub mod set_delegate;
+pub mod verify_pfee_ownership;
+pub mod get_pending_rewards;
+pub mod transfer_stake;
+pub mod update_tau;
+pub mod set_reward_mint;
+pub mod token_rewards;
+pub mod set_pause;
+pub mod instant_unstake;
+pub mod harvest_creator_fees;
+pub mod set_fee_shares;
+pub mod amend_unstake_request;
+pub mod close_pool;
+pub mod initialize_user_stake;
+pub mod get_pool_stats;
+pub mod depositor_allowlist;
+pub mod apply_settings;
+pub mod query_pending_settings;
+pub mod reclaim_deferred_rewards;
+pub mod withdraw_fee_split;
 
 pub use initialize::*;
 pub use stake::*;
@@ -35,3 +85,52 @@ pub use set_metadata::*;
 pub use take_fee_ownership::*;
 pub use stake_on_behalf::*;
 pub use distribute_surplus::*;
+pub use verify_vault_consistency::*;
+pub use withdraw_claimed::*;
+pub use grandfather_stake::*;
+pub use split_stake::*;
+pub use query_claimable::*;
+pub use collect_protocol_fees::*;
+pub use quarantine_pool::*;
+pub use view_pool_health::*;
+pub use batch_request_unstake::*;
+pub use set_claim_for_opt_in::*;
+pub use set_reward_fee_split::*;
+pub use extend_pool_account::*;
+pub use reverse_unstake::*;
+pub use query_min_stake::*;
+pub use set_unstake_destination_policy::*;
+pub use query_time_to_unlock::*;
+pub use query_effective_settings::*;
+pub use query_accumulator_snapshot::*;
+pub use force_unstake_quarantined::*;
+pub use lock_pool_settings::*;
+pub use query_reward_rate::*;
+pub use set_display_unit::*;
+pub use query_is_completable_batch::*;
+pub use reinitialize_vault::*;
+pub use distribute_bonus::*;
+pub use query_pending_unstake::*;
+pub use set_reward_precision::*;
+pub use claim_and_close::*;
+pub use query_weighted_share::*;
+pub use set_delegate::*;
+pub use verify_pfee_ownership::*;
+pub use get_pending_rewards::*;
+pub use transfer_stake::*;
+pub use update_tau::*;
+pub use set_reward_mint::*;
+pub use token_rewards::*;
+pub use set_pause::*;
+pub use instant_unstake::*;
+pub use harvest_creator_fees::*;
+pub use set_fee_shares::*;
+pub use amend_unstake_request::*;
+pub use close_pool::*;
+pub use initialize_user_stake::*;
+pub use get_pool_stats::*;
+pub use depositor_allowlist::*;
+pub use apply_settings::*;
+pub use query_pending_settings::*;
+pub use reclaim_deferred_rewards::*;
+pub use withdraw_fee_split::*;
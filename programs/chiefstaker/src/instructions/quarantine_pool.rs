@@ -0,0 +1,87 @@
+//! Quarantine pool instruction - permissionless mint re-check
+//!
+//! InitializePool rejects mints with extensions that break the total_staked
+//! invariant. This re-runs the same checks against the mint's current state
+//! and, if one of them now fails, quarantines the pool: new stakes are
+//! blocked while unstaking, claiming and cooldown exits keep working.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+
+use crate::{error::StakingError, instructions::check_mint_extensions, state::StakingPool};
+
+/// Quarantine the pool if its mint now has a forbidden extension
+/// (permissionless crank; a no-op when the mint is still acceptable)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[]` Token mint
+/// 2. `[writable, signer]` Payer (funds the pool realloc for legacy pools)
+/// 3. `[]` System program
+pub fn process_quarantine_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !payer_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // ── Load and validate pool ───────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    if pool.quarantined {
+        msg!("Pool already quarantined");
+        return Ok(());
+    }
+
+    // ── Re-check the mint ────────────────────────────────────────────
+    // SPL Token mints have no extensions
+    if *mint_info.owner != spl_token_2022::id() {
+        msg!("Mint has no extensions; pool not quarantined");
+        return Ok(());
+    }
+
+    let violation = {
+        let mint_data = mint_info.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+        check_mint_extensions(&mint_state).err()
+    };
+    let Some(err) = violation else {
+        msg!("Mint extensions OK; pool not quarantined");
+        return Ok(());
+    };
+
+    // ── Quarantine ───────────────────────────────────────────────────
+    pool.quarantined = true;
+    pool.ensure_capacity(pool_info, payer_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
+
+    msg!("Pool quarantined ({}): new stakes disabled", err);
+
+    Ok(())
+}
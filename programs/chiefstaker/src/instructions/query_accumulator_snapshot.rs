@@ -0,0 +1,118 @@
+//! Query accumulator snapshot instruction - reward inputs side by side
+//!
+//! For support and integrators chasing "why is my claim X?": returns the
+//! pool accumulator, the stake's reward debt checkpoint and the other inputs
+//! of the pending-rewards formula together with its result, so the number
+//! can be recomputed off-chain without decoding account layouts.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::WAD,
+    state::{StakingPool, UserStake},
+};
+
+/// Return the reward accounting inputs and pending rewards of a stake
+/// (permissionless, read-only)
+///
+/// The stake is synced to the pool first (rebase and accumulator precision),
+/// as a claim would. Returned via return data:
+/// acc_reward_per_weighted_share (u128 LE), reward_debt (u128 LE),
+/// amount (u64 LE), weighted stake now (u128 LE), claimed_rewards_wad
+/// (u128 LE), acc_scale_shift (u8) and pending lamports (u64 LE), where
+/// pending = (unscale(weighted * (acc - reward_debt / amount)) - claimed) / WAD.
+/// After a full exit (amount 0) pending is the residual held in reward_debt.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_query_accumulator_snapshot(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Adjust a copy for any rebase or rescale; nothing is written back
+    user_stake.sync_to_pool(&pool)?;
+
+    let (weighted, pending) = if user_stake.amount == 0 {
+        // Residual rewards from a full unstake sit in reward_debt
+        (0, user_stake.reward_debt)
+    } else {
+        let weighted = user_stake.weighted_stake(&pool, current_time)?;
+        let pending_wad = user_stake.pending_rewards_wad(&pool, weighted)?;
+        (weighted, pending_wad / WAD)
+    };
+    let pending = u64::try_from(pending).map_err(|_| StakingError::MathOverflow)?;
+
+    msg!(
+        "Accumulator: {} debt: {} amount: {} weighted: {} claimed_wad: {} shift: {} pending: {}",
+        pool.acc_reward_per_weighted_share,
+        user_stake.reward_debt,
+        user_stake.amount,
+        weighted,
+        user_stake.claimed_rewards_wad,
+        pool.acc_scale_shift,
+        pending
+    );
+
+    let mut data = [0u8; 81];
+    data[..16].copy_from_slice(&pool.acc_reward_per_weighted_share.to_le_bytes());
+    data[16..32].copy_from_slice(&user_stake.reward_debt.to_le_bytes());
+    data[32..40].copy_from_slice(&user_stake.amount.to_le_bytes());
+    data[40..56].copy_from_slice(&weighted.to_le_bytes());
+    data[56..72].copy_from_slice(&user_stake.claimed_rewards_wad.to_le_bytes());
+    data[72] = pool.acc_scale_shift;
+    data[73..81].copy_from_slice(&pending.to_le_bytes());
+    set_return_data(&data);
+
+    Ok(())
+}
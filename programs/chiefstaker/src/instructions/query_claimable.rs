@@ -0,0 +1,106 @@
+//! Query claimable instruction - read-only projection of future rewards
+//!
+//! With no further deposits, a staker's claimable amount only grows as their
+//! weight matures against rewards already distributed. This projects that
+//! amount at a future time so wallets can show what waiting would unlock.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::WAD,
+    state::{StakingPool, UserStake},
+};
+
+/// Project the lamports a stake could claim at `future_timestamp`, assuming
+/// no new deposits and no claims in between (permissionless, read-only)
+///
+/// The result is the entitlement only; an actual claim is also limited by the
+/// pool's available balance. It is logged and returned as a little-endian u64
+/// via return data.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_query_claimable_at(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    future_timestamp: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // ── Validate pool ───────────────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // ── Validate user stake ─────────────────────────────────────────
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    if future_timestamp < current_time {
+        msg!(
+            "Timestamp {} is before current time {}",
+            future_timestamp,
+            current_time
+        );
+        return Err(StakingError::TimestampInPast.into());
+    }
+
+    // ── Project ─────────────────────────────────────────────────────
+    let pending = if user_stake.amount == 0 {
+        // Residual rewards from a full unstake don't change over time
+        user_stake.reward_debt
+    } else {
+        // Adjust a copy for any rebase; nothing is written back
+        user_stake.sync_to_pool(&pool)?;
+        let user_weighted = user_stake.weighted_stake(&pool, future_timestamp)?;
+        user_stake.pending_rewards_wad(&pool, user_weighted)?
+    };
+
+    let claimable = (pending / WAD) as u64;
+
+    msg!(
+        "Claimable at {}: {} lamports",
+        future_timestamp,
+        claimable
+    );
+    set_return_data(&claimable.to_le_bytes());
+
+    Ok(())
+}
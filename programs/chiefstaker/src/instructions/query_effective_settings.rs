@@ -0,0 +1,96 @@
+//! Query effective settings instruction - the terms that apply to one stake
+//!
+//! Stamped per-stake terms, a lock commitment or grandfathered staking time
+//! can make a stake's terms differ from the pool defaults; this reports
+//! what actually applies so wallets don't show the pool's values.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UserStake},
+};
+
+/// Return the lock and cooldown that apply to a stake (permissionless,
+/// read-only)
+///
+/// Logged and returned via return data as lock_duration (u64 LE),
+/// unstake_cooldown (u64 LE), the effective last stake time the lock runs
+/// from (i64 LE) and a TERMS_FLAG_* bitmask (u8).
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_query_effective_settings(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let lock_duration = user_stake.effective_lock_duration(&pool);
+    let unstake_cooldown = user_stake.effective_unstake_cooldown(&pool);
+    let last_stake_time = user_stake.effective_last_stake_time();
+    let flags = user_stake.effective_terms_flags(&pool);
+
+    msg!(
+        "Effective settings: lock={}s cooldown={}s from={} flags={:#04x} (pool: lock={}s cooldown={}s)",
+        lock_duration,
+        unstake_cooldown,
+        last_stake_time,
+        flags,
+        pool.lock_duration_seconds,
+        pool.unstake_cooldown_seconds
+    );
+
+    let mut data = [0u8; 25];
+    data[..8].copy_from_slice(&lock_duration.to_le_bytes());
+    data[8..16].copy_from_slice(&unstake_cooldown.to_le_bytes());
+    data[16..24].copy_from_slice(&last_stake_time.to_le_bytes());
+    data[24] = flags;
+    set_return_data(&data);
+
+    Ok(())
+}
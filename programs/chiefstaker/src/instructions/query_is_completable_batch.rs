@@ -0,0 +1,105 @@
+//! Query is completable batch instruction - which unstake requests are ready
+//!
+//! One call for a UI listing a user's positions across pools: bit i of the
+//! result is set when the i-th (pool, stake) pair has a pending unstake
+//! request whose cooldown has elapsed, i.e. CompleteUnstake would go through.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UserStake},
+};
+
+/// Maximum (pool, stake) pairs per query, one bit each in the u32 result.
+pub const MAX_QUERY_COMPLETABLE_BATCH: usize = 32;
+
+/// Return a bitmask of the (pool, stake) pairs whose unstake request can be
+/// completed now (permissionless, read-only)
+///
+/// Stamped per-stake cooldowns are honored. Logged and returned as a
+/// little-endian u32 via return data; bit i is pair i.
+///
+/// Accounts:
+/// 0. `[]` Pool account #1
+/// 1. `[]` User stake account #1
+///
+/// followed by further (pool, user stake) pairs, at most
+/// MAX_QUERY_COMPLETABLE_BATCH in total.
+pub fn process_query_is_completable_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let pairs = accounts.chunks_exact(2);
+    if pairs.len() == 0 || !pairs.remainder().is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if pairs.len() > MAX_QUERY_COMPLETABLE_BATCH {
+        return Err(StakingError::BatchTooLarge.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    let total = pairs.len();
+    let mut mask = 0u32;
+    for (i, pair) in pairs.enumerate() {
+        let (pool_info, user_stake_info) = (&pair[0], &pair[1]);
+
+        // Load and validate pool
+        if pool_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+        if !pool.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
+
+        // Verify pool PDA
+        let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+        if *pool_info.key != expected_pool {
+            return Err(StakingError::InvalidPDA.into());
+        }
+
+        // Load and validate user stake
+        if user_stake_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+        if !user_stake.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
+        if user_stake.pool != *pool_info.key {
+            return Err(StakingError::InvalidPool.into());
+        }
+
+        // Verify user stake PDA
+        let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+        if *user_stake_info.key != expected_stake {
+            return Err(StakingError::InvalidPDA.into());
+        }
+
+        if user_stake.is_unstake_completable(&pool, current_time) {
+            mask |= 1 << i;
+        }
+    }
+
+    msg!(
+        "Completable: {} of {} requests (mask {:#x})",
+        mask.count_ones(),
+        total,
+        mask
+    );
+    set_return_data(&mask.to_le_bytes());
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Query min stake instruction - min_stake_amount with the mint decimals
+//!
+//! Lets a UI show the minimum stake in token units without fetching the
+//! mint: the decimals cached at InitializePool come back with the raw value.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool},
+};
+
+/// Return the pool's min_stake_amount and mint decimals (permissionless,
+/// read-only)
+///
+/// Logged and returned via return data as min_stake_amount (u64 LE)
+/// followed by decimals (u8).
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` Token mint (optional, only read for pools created before the
+///    decimals were cached)
+pub fn process_query_min_stake_ui(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let decimals = match pool.cached_mint_decimals() {
+        Some(decimals) => decimals,
+        None => {
+            // Legacy pool: read the decimals from the mint itself
+            let mint_info = account_info_iter
+                .next()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if pool.mint != *mint_info.key {
+                return Err(StakingError::InvalidPoolMint.into());
+            }
+            if !is_valid_token_program(mint_info.owner) {
+                return Err(StakingError::InvalidMintProgram.into());
+            }
+            let mint_data = mint_info.try_borrow_data()?;
+            let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+            mint.base.decimals
+        }
+    };
+
+    msg!(
+        "Min stake: {} base units ({} decimals)",
+        pool.min_stake_amount,
+        decimals
+    );
+
+    let mut data = [0u8; 9];
+    data[..8].copy_from_slice(&pool.min_stake_amount.to_le_bytes());
+    data[8] = decimals;
+    set_return_data(&data);
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Query pending settings instruction - upcoming timelocked changes
+//!
+//! Lets wallets warn stakers before a longer lock or cooldown, a higher fee,
+//! fee split or penalty, or a reweighting takes effect, without decoding the
+//! pool account.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, FEE_SPLIT_SLOTS},
+};
+
+/// Size of the returned record
+pub const PENDING_SETTINGS_LEN: usize = 82 + 34 * FEE_SPLIT_SLOTS + 8;
+
+/// Encode the pool's pending settings change (see
+/// process_query_pending_settings)
+pub fn pending_settings_bytes(pool: &StakingPool) -> [u8; PENDING_SETTINGS_LEN] {
+    let mut data = [0u8; PENDING_SETTINGS_LEN];
+    data[0..2].copy_from_slice(&pool.pending_settings_mask.to_le_bytes());
+    data[2..10].copy_from_slice(&pool.pending_settings_effective_time.to_le_bytes());
+    data[10..18].copy_from_slice(&pool.settings_timelock_seconds.to_le_bytes());
+    data[18..26].copy_from_slice(&pool.pending_lock_duration_seconds.to_le_bytes());
+    data[26..34].copy_from_slice(&pool.pending_unstake_cooldown_seconds.to_le_bytes());
+    data[34..42].copy_from_slice(&pool.pending_claim_fee_bps.to_le_bytes());
+    data[42..50].copy_from_slice(&pool.pending_early_exit_penalty_bps.to_le_bytes());
+    data[50..58].copy_from_slice(&pool.pending_settings_timelock_seconds.to_le_bytes());
+    data[58..66].copy_from_slice(&pool.pending_loyalty_bonus_bps.to_le_bytes());
+    data[66..74].copy_from_slice(&pool.pending_lock_boost_bps.to_le_bytes());
+    data[74..82].copy_from_slice(&pool.pending_min_distributable_per_event.to_le_bytes());
+    for (i, (recipient, bps)) in pool
+        .pending_fee_split_recipients
+        .iter()
+        .zip(pool.pending_fee_split_bps)
+        .enumerate()
+    {
+        let offset = 82 + 34 * i;
+        data[offset..offset + 32].copy_from_slice(recipient.as_ref());
+        data[offset + 32..offset + 34].copy_from_slice(&bps.to_le_bytes());
+    }
+    let tau_offset = 82 + 34 * FEE_SPLIT_SLOTS;
+    data[tau_offset..tau_offset + 8].copy_from_slice(&pool.pending_tau_seconds.to_le_bytes());
+    data
+}
+
+/// Return the settings change waiting for the pool's timelock
+/// (permissionless, read-only)
+///
+/// Returned via return data as a little-endian record:
+///
+/// | Offset | Type | Field |
+/// |--------|------|-------|
+/// | 0  | u16 | PENDING_* bits of the pending values (0 = nothing pending) |
+/// | 2  | i64 | when ApplySettings may apply them |
+/// | 10 | u64 | settings_timelock_seconds currently in force |
+/// | 18 | u64 | pending lock_duration_seconds |
+/// | 26 | u64 | pending unstake_cooldown_seconds |
+/// | 34 | u64 | pending claim_fee_bps |
+/// | 42 | u64 | pending early_exit_penalty_bps |
+/// | 50 | u64 | pending settings_timelock_seconds |
+/// | 58 | u64 | pending loyalty_bonus_bps |
+/// | 66 | u64 | pending lock_boost_bps |
+/// | 74 | u64 | pending min_distributable_per_event |
+/// | 82 | 3 x (Pubkey, u16) | pending fee split (recipient, bps) |
+/// | 184 | u64 | pending tau_seconds (UpdateTau) |
+///
+/// A pending value is only meaningful if its bit is set.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+pub fn process_query_pending_settings(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    msg!(
+        "Pending settings: {:#06x} effective at {} (timelock {}s)",
+        pool.pending_settings_mask,
+        pool.pending_settings_effective_time,
+        pool.settings_timelock_seconds
+    );
+
+    set_return_data(&pending_settings_bytes(&pool));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{
+        PENDING_EARLY_EXIT_PENALTY, PENDING_FEE_SPLIT, PENDING_TAU, PENDING_UNSTAKE_COOLDOWN,
+    };
+
+    #[test]
+    fn test_pending_settings_layout() {
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        pool.settings_timelock_seconds = 3;
+        pool.pending_settings_mask =
+            PENDING_UNSTAKE_COOLDOWN | PENDING_EARLY_EXIT_PENALTY | PENDING_FEE_SPLIT | PENDING_TAU;
+        pool.pending_settings_effective_time = -2;
+        pool.pending_lock_duration_seconds = 4;
+        pool.pending_unstake_cooldown_seconds = 5;
+        pool.pending_claim_fee_bps = 6;
+        pool.pending_early_exit_penalty_bps = 7;
+        pool.pending_settings_timelock_seconds = 8;
+        pool.pending_loyalty_bonus_bps = 9;
+        pool.pending_lock_boost_bps = 10;
+        pool.pending_min_distributable_per_event = 11;
+        let recipient = Pubkey::new_unique();
+        pool.pending_fee_split_recipients[1] = recipient;
+        pool.pending_fee_split_bps[1] = 250;
+        pool.pending_tau_seconds = 12;
+
+        let data = pending_settings_bytes(&pool);
+        assert_eq!(data.len(), 192);
+        assert_eq!(
+            u16::from_le_bytes(data[0..2].try_into().unwrap()),
+            PENDING_UNSTAKE_COOLDOWN | PENDING_EARLY_EXIT_PENALTY | PENDING_FEE_SPLIT | PENDING_TAU
+        );
+        assert_eq!(i64::from_le_bytes(data[2..10].try_into().unwrap()), -2);
+        for (i, expected) in (3u64..=11).enumerate() {
+            let offset = 10 + 8 * i;
+            assert_eq!(u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()), expected);
+        }
+        assert_eq!(&data[116..148], recipient.as_ref());
+        assert_eq!(u16::from_le_bytes(data[148..150].try_into().unwrap()), 250);
+        assert_eq!(u64::from_le_bytes(data[184..192].try_into().unwrap()), 12);
+    }
+}
@@ -0,0 +1,104 @@
+//! Query pending unstake instruction - everything a UI shows for a request
+//!
+//! Amount, request time, cooldown end, seconds remaining and whether
+//! CompleteUnstake would go through, computed with the same stamped-terms
+//! cooldown logic the program enforces.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UserStake},
+};
+
+/// Return the details of a stake's pending unstake request (permissionless,
+/// read-only)
+///
+/// Returned via return data as amount (u64 LE), request time (i64 LE),
+/// cooldown end (i64 LE), seconds remaining (u64 LE) and completable (u8),
+/// 33 bytes. With no request pending every field is zero.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_query_pending_unstake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let mut data = [0u8; 33];
+    if !user_stake.has_pending_unstake_request() {
+        msg!("No pending unstake request");
+        set_return_data(&data);
+        return Ok(());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let cooldown_end = user_stake.unstake_cooldown_end(&pool);
+    let remaining = cooldown_end.saturating_sub(current_time).max(0) as u64;
+    let completable = user_stake.is_unstake_completable(&pool, current_time);
+
+    msg!(
+        "Pending unstake: {} tokens, requested at {}, cooldown ends at {} ({} seconds left, completable: {})",
+        user_stake.unstake_request_amount,
+        user_stake.unstake_request_time,
+        cooldown_end,
+        remaining,
+        completable
+    );
+
+    data[..8].copy_from_slice(&user_stake.unstake_request_amount.to_le_bytes());
+    data[8..16].copy_from_slice(&user_stake.unstake_request_time.to_le_bytes());
+    data[16..24].copy_from_slice(&cooldown_end.to_le_bytes());
+    data[24..32].copy_from_slice(&remaining.to_le_bytes());
+    data[32] = completable as u8;
+    set_return_data(&data);
+
+    Ok(())
+}
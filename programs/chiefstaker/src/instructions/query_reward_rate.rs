@@ -0,0 +1,73 @@
+//! Query reward rate instruction - recent lamports distributed per second
+//!
+//! Each distribution adds to a (window_start, sum) pair on the pool, kept
+//! between one and two REWARD_RATE_WINDOW_SECONDS long. Live APR tickers can
+//! read the rate directly instead of diffing the pool balance across polls.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Return the estimated reward rate (permissionless, read-only)
+///
+/// Logged and returned via return data as lamports per second (u64 LE),
+/// the window length in seconds (u64 LE) and the lamports distributed in it
+/// (u64 LE). Legacy pools report 0 until their window starts.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+pub fn process_query_reward_rate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let rate = pool.reward_rate(current_time);
+    let window_seconds = if pool.reward_rate_window_start == 0 {
+        0
+    } else {
+        current_time.saturating_sub(pool.reward_rate_window_start).max(0) as u64
+    };
+
+    msg!(
+        "Reward rate: {} lamports/s ({} lamports over {} seconds)",
+        rate,
+        pool.reward_rate_window_sum,
+        window_seconds
+    );
+
+    let mut data = [0u8; 24];
+    data[..8].copy_from_slice(&rate.to_le_bytes());
+    data[8..16].copy_from_slice(&window_seconds.to_le_bytes());
+    data[16..24].copy_from_slice(&pool.reward_rate_window_sum.to_le_bytes());
+    set_return_data(&data);
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Query weighted share instruction - a stake's part of the reward denominator
+//!
+//! Deposits are split over total_staked * WAD (max weight), so a stake's
+//! contribution to the denominator is amount * WAD. What it earns of a
+//! deposit is its current weighted stake over that denominator; the rest of
+//! its max-weight share is the immature part it would forfeit by leaving.
+//! Returning all three lets anyone check their share ratio on-chain.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::WAD,
+    state::{StakingPool, UserStake},
+};
+
+/// Return a stake's reward denominator contribution, its current weighted
+/// stake and the pool's denominator (permissionless, read-only)
+///
+/// Returned via return data as three u128 LE values (48 bytes):
+/// amount * WAD, weighted stake (WAD-scaled, loyalty bonus included) and
+/// total_staked * WAD. weighted / denominator is the fraction of the next
+/// deposit the stake earns.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` User stake account
+pub fn process_query_weighted_share(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Bring exp_start_factor up to date with any rebase (not saved)
+    user_stake.sync_to_pool(&pool)?;
+
+    let contribution = (user_stake.amount as u128)
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    let weighted = user_stake.weighted_stake(&pool, current_time)?;
+    // Denominator: total_staked * WAD (max weight; each stake's time weight
+    // is applied to its share when it claims)
+    let denominator = pool
+        .total_staked
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+
+    msg!(
+        "Weighted share: contribution {}, weighted {}, denominator {}",
+        contribution,
+        weighted,
+        denominator
+    );
+
+    let mut data = [0u8; 48];
+    data[..16].copy_from_slice(&contribution.to_le_bytes());
+    data[16..32].copy_from_slice(&weighted.to_le_bytes());
+    data[32..].copy_from_slice(&denominator.to_le_bytes());
+    set_return_data(&data);
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Reclaim deferred rewards instruction (authority only)
+//!
+//! Rewards that arrive while nothing is staked wait in the pool for the
+//! next staker. If a pool is abandoned that SOL would be stranded, so the
+//! authority can take back exactly those lamports while the pool is empty.
+//! Lamports already distributed, claimed balances and protocol fees are
+//! all counted in last_synced_lamports and are never touched.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Withdraw the lamports deferred while no tokens are staked
+///
+/// Fails with `PoolHasStake` once anything is staked: from then on the
+/// deferred lamports belong to the stakers. Streamed deposits are only
+/// reclaimable once released. SPL-token reward pools fail with
+/// `RewardModeMismatch`. Calling again with nothing deferred transfers
+/// nothing.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Pool authority
+/// 2. `[writable]` Recipient
+pub fn process_reclaim_deferred_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Anything above last_synced_lamports is owed to stakers once there are some
+    if pool.total_staked != 0 {
+        return Err(StakingError::PoolHasStake.into());
+    }
+
+    let rent = Rent::get()?;
+    let clock = Clock::get()?;
+    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
+    let amount = pool.deferred_rewards(
+        pool_info.lamports(),
+        rent_exempt_minimum,
+        clock.unix_timestamp,
+    );
+    if amount == 0 {
+        msg!("No deferred rewards to reclaim");
+        return Ok(());
+    }
+
+    // Transfer SOL from pool to recipient. last_synced_lamports is unchanged:
+    // these lamports were never counted in it.
+    **pool_info.try_borrow_mut_lamports()? -= amount;
+    **recipient_info.try_borrow_mut_lamports()? += amount;
+
+    msg!(
+        "Reclaimed {} lamports of deferred rewards to {}",
+        amount,
+        recipient_info.key
+    );
+
+    Ok(())
+}
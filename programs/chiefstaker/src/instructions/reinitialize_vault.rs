@@ -0,0 +1,207 @@
+//! Reinitialize vault instruction - recover a pool whose vault is unusable
+//!
+//! Only allowed while nothing is staked, so no user tokens are at risk. The
+//! canonical vault PDA (["token_vault", pool]) is created if it is missing,
+//! initialized if it was allocated but never initialized, and left as is if
+//! it is already a valid vault; the pool is then pointed at it. A vault PDA
+//! that is initialized for another mint or owner can't be repaired.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    state::{Account, AccountState},
+};
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool, TOKEN_VAULT_SEED},
+};
+
+use super::initialize::vault_account_size;
+
+/// Recreate the pool's token vault at its canonical PDA (authority only,
+/// requires total_staked == 0)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` Token vault (PDA: ["token_vault", pool])
+/// 2. `[]` Token mint
+/// 3. `[writable, signer]` Authority (pays the vault's rent)
+/// 4. `[]` System program
+/// 5. `[]` Token program (the mint's owner)
+pub fn process_reinitialize_vault(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate token program (SPL Token or Token 2022)
+    if !is_valid_token_program(token_program_info.key) {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+
+    // Validate system program
+    if *system_program_info.key != system_program::id() {
+        return Err(StakingError::MissingSystemProgram.into());
+    }
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // No funds at risk: nothing may be staked
+    if pool.total_staked > 0 {
+        return Err(StakingError::PoolHasStake.into());
+    }
+
+    // Verify mint matches pool and is owned by the token program
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+    if *mint_info.owner != *token_program_info.key {
+        return Err(StakingError::InvalidMintProgram.into());
+    }
+
+    // Verify token vault PDA
+    let (expected_vault, vault_bump) =
+        StakingPool::derive_token_vault_pda(pool_info.key, program_id);
+    if *token_vault_info.key != expected_vault {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    let vault_seeds = &[TOKEN_VAULT_SEED, pool_info.key.as_ref(), &[vault_bump]];
+
+    // Create the account if it doesn't exist yet
+    if *token_vault_info.owner == system_program::id() {
+        let vault_size = vault_account_size(token_program_info.key)?;
+        let vault_rent = Rent::get()?.minimum_balance(vault_size);
+
+        if token_vault_info.lamports() == 0 {
+            invoke_signed(
+                &system_instruction::create_account(
+                    authority_info.key,
+                    token_vault_info.key,
+                    vault_rent,
+                    vault_size as u64,
+                    token_program_info.key,
+                ),
+                &[
+                    authority_info.clone(),
+                    token_vault_info.clone(),
+                    system_program_info.clone(),
+                ],
+                &[vault_seeds],
+            )?;
+        } else {
+            // Lamports were sent to the PDA: create_account would fail, so
+            // top up, allocate and assign instead
+            let shortfall = vault_rent.saturating_sub(token_vault_info.lamports());
+            if shortfall > 0 {
+                invoke(
+                    &system_instruction::transfer(authority_info.key, token_vault_info.key, shortfall),
+                    &[
+                        authority_info.clone(),
+                        token_vault_info.clone(),
+                        system_program_info.clone(),
+                    ],
+                )?;
+            }
+            invoke_signed(
+                &system_instruction::allocate(token_vault_info.key, vault_size as u64),
+                &[token_vault_info.clone(), system_program_info.clone()],
+                &[vault_seeds],
+            )?;
+            invoke_signed(
+                &system_instruction::assign(token_vault_info.key, token_program_info.key),
+                &[token_vault_info.clone(), system_program_info.clone()],
+                &[vault_seeds],
+            )?;
+        }
+        msg!("Created token vault {}", token_vault_info.key);
+    } else if token_vault_info.owner != token_program_info.key {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+
+    // Initialize the token account unless it already is one
+    let initialized = {
+        let vault_data = token_vault_info.try_borrow_data()?;
+        if vault_data.len() < Account::LEN {
+            return Err(StakingError::InvalidTokenVault.into());
+        }
+        Account::unpack_unchecked(&vault_data[..Account::LEN])?.state != AccountState::Uninitialized
+    };
+    if initialized {
+        let vault_data = token_vault_info.try_borrow_data()?;
+        let vault = StateWithExtensions::<Account>::unpack(&vault_data)?;
+        if vault.base.mint != pool.mint {
+            return Err(StakingError::InvalidVaultMint.into());
+        }
+        if vault.base.owner != *pool_info.key {
+            return Err(StakingError::InvalidTokenVault.into());
+        }
+        msg!("Token vault already valid");
+    } else {
+        invoke_signed(
+            &spl_token_2022::instruction::initialize_account3(
+                token_program_info.key,
+                token_vault_info.key,
+                mint_info.key,
+                pool_info.key, // Pool PDA is the owner of the vault
+            )?,
+            &[token_vault_info.clone(), mint_info.clone()],
+            &[vault_seeds],
+        )?;
+        msg!("Initialized token vault {}", token_vault_info.key);
+    }
+
+    if pool.token_vault != *token_vault_info.key {
+        msg!("Pool vault repointed from {} to {}", pool.token_vault, token_vault_info.key);
+        pool.token_vault = *token_vault_info.key;
+        pool.save(pool_info)?;
+    }
+
+    Ok(())
+}
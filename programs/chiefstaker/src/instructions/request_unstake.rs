@@ -1,11 +1,12 @@
 //! Request unstake instruction (starts cooldown period)
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
+    program_error::ProgramError,
     pubkey::Pubkey,
     sysvar::Sysvar,
 };
@@ -26,16 +27,43 @@ pub fn process_request_unstake(
     accounts: &[AccountInfo],
     amount: u64,
 ) -> ProgramResult {
-    if amount == 0 {
-        return Err(StakingError::ZeroAmount.into());
-    }
-
     let account_info_iter = &mut accounts.iter();
 
     let pool_info = next_account_info(account_info_iter)?;
     let user_stake_info = next_account_info(account_info_iter)?;
     let user_info = next_account_info(account_info_iter)?;
 
+    // System program is optional trailing account, only needed for legacy accounts
+    let system_program_info = account_info_iter.next();
+
+    request_unstake(
+        program_id,
+        pool_info,
+        user_stake_info,
+        user_info,
+        system_program_info,
+        Some(amount),
+    )?;
+
+    Ok(())
+}
+
+/// Validate and record an unstake request on one stake account.
+///
+/// `amount` of `None` requests the whole stake. Returns the requested amount.
+/// Shared by RequestUnstake and BatchRequestUnstake.
+pub(crate) fn request_unstake<'a>(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo<'a>,
+    user_stake_info: &AccountInfo<'a>,
+    user_info: &AccountInfo<'a>,
+    system_program_info: Option<&AccountInfo<'a>>,
+    amount: Option<u64>,
+) -> Result<u64, ProgramError> {
+    if amount == Some(0) {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
     // Validate user is signer
     if !user_info.is_signer {
         return Err(StakingError::MissingRequiredSigner.into());
@@ -61,14 +89,7 @@ pub fn process_request_unstake(
         return Err(StakingError::PoolRequiresSync.into());
     }
 
-    // Require cooldown to be configured; otherwise use direct Unstake
-    if pool.unstake_cooldown_seconds == 0 {
-        return Err(StakingError::CooldownNotConfigured.into());
-    }
-
     // Realloc legacy accounts to current size (payer = user)
-    // System program is optional trailing account, only needed for legacy accounts
-    let system_program_info = account_info_iter.next();
     UserStake::maybe_realloc(user_stake_info, user_info, system_program_info)?;
 
     // Load and validate user stake
@@ -89,12 +110,17 @@ pub fn process_request_unstake(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // Require cooldown to be configured; otherwise use direct Unstake
+    let cooldown = user_stake.effective_unstake_cooldown(&pool);
+    if cooldown == 0 {
+        return Err(StakingError::CooldownNotConfigured.into());
+    }
+
     // Check no existing pending request
     if user_stake.has_pending_unstake_request() {
         return Err(StakingError::PendingUnstakeRequestExists.into());
@@ -104,6 +130,10 @@ pub fn process_request_unstake(
     user_stake.sync_to_pool(&pool)?;
 
     // Check sufficient balance
+    let amount = amount.unwrap_or(user_stake.amount);
+    if amount == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
     if user_stake.amount < amount {
         return Err(StakingError::InsufficientStakeBalance.into());
     }
@@ -111,28 +141,21 @@ pub fn process_request_unstake(
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
 
-    // Check lock duration has elapsed
-    if pool.lock_duration_seconds > 0 {
-        let last_stake = user_stake.effective_last_stake_time();
-        let elapsed = current_time.saturating_sub(last_stake).max(0) as u64;
-        if elapsed < pool.lock_duration_seconds {
-            return Err(StakingError::StakeLocked.into());
-        }
-    }
+    // The requested tokens must be out of their lock
+    user_stake.check_unlocked(&pool, amount, current_time)?;
 
     // Set unstake request fields
     user_stake.unstake_request_amount = amount;
     user_stake.unstake_request_time = current_time;
 
     // Save user stake
-    let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-    user_stake.serialize(&mut &mut stake_data[..])?;
+    user_stake.save(user_stake_info)?;
 
     msg!(
         "Unstake request created for {} tokens, cooldown {} seconds",
         amount,
-        pool.unstake_cooldown_seconds
+        cooldown
     );
 
-    Ok(())
+    Ok(amount)
 }
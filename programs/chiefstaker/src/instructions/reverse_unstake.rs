@@ -0,0 +1,229 @@
+//! Reverse unstake instruction - undo a just-completed unstake
+//!
+//! When the pool sets an unstake reversal window, CompleteUnstake records
+//! its time and amount on the stake. Within the window the owner can put
+//! exactly those tokens back: they rejoin the position with its original
+//! exp_start_factor and without a new lock or cooldown. Rewards distributed
+//! while the tokens were out, and immature rewards forfeited on the way out,
+//! are not restored.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::StakingError,
+    math::{wad_mul, U256, WAD},
+    state::{is_valid_token_program, StakingPool, UserStake},
+};
+
+/// Re-stake the tokens withdrawn by the owner's last CompleteUnstake
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` User stake account
+/// 2. `[writable]` Token vault
+/// 3. `[writable]` User token account
+/// 4. `[]` Token mint
+/// 5. `[signer]` User/owner
+/// 6. `[]` Token program
+pub fn process_reverse_unstake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+    let user_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate token program (SPL Token or Token 2022)
+    if !is_valid_token_program(token_program_info.key) {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Quarantined pools only allow exits
+    if pool.quarantined {
+        return Err(StakingError::PoolQuarantined.into());
+    }
+
+    // Paused pools only allow exits
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
+    // Check if pool needs rebasing
+    if pool.get_sum_stake_exp().needs_rebase() {
+        return Err(StakingError::PoolRequiresSync.into());
+    }
+
+    // Verify mint matches pool
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
+    // Verify token vault
+    if pool.token_vault != *token_vault_info.key {
+        return Err(StakingError::InvalidTokenVault.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Block while another unstake request is pending
+    if user_stake.has_pending_unstake_request() {
+        return Err(StakingError::PendingUnstakeRequestExists.into());
+    }
+
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    let amount = user_stake
+        .reversible_unstake_amount(&pool, current_time)
+        .ok_or(StakingError::UnstakeNotReversible)?;
+
+    // After a full exit reward_debt holds unclaimed residual rewards, which
+    // can't be mixed with a snapshot for the returning tokens
+    if user_stake.amount == 0 && user_stake.reward_debt > 0 {
+        msg!("Claim the residual rewards before reversing the unstake");
+        return Err(StakingError::UnstakeNotReversible.into());
+    }
+
+    // Keep reward debts within u128 once the tokens are back
+    let new_total_staked = pool
+        .total_staked
+        .checked_add(amount as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.rescale_for(new_total_staked)?;
+
+    // Lazily adjust exp_start_factor if pool has been rebased
+    user_stake.sync_to_pool(&pool)?;
+
+    let old_reward_debt = user_stake.reward_debt;
+
+    // sum_stake_exp: the tokens return with the position's original maturity
+    let contribution = wad_mul(
+        (amount as u128).checked_mul(WAD).ok_or(StakingError::MathOverflow)?,
+        user_stake.exp_start_factor,
+    )?;
+    let new_sum = pool
+        .get_sum_stake_exp()
+        .checked_add(U256::from_u128(contribution))
+        .ok_or(StakingError::MathOverflow)?;
+    pool.set_sum_stake_exp(new_sum);
+
+    // reward_debt += fresh snapshot so the tokens don't earn what was
+    // distributed while they were out
+    let returning_debt = wad_mul(
+        (amount as u128).checked_mul(WAD).ok_or(StakingError::MathOverflow)?,
+        pool.acc_reward_per_weighted_share,
+    )?;
+    user_stake.reward_debt = user_stake
+        .reward_debt
+        .checked_add(returning_debt)
+        .ok_or(StakingError::MathOverflow)?;
+
+    user_stake.amount = user_stake
+        .amount
+        .checked_add(amount)
+        .ok_or(StakingError::MathOverflow)?;
+    // last_stake_time and stamped terms are UNCHANGED: no new lock or cooldown
+
+    // One-shot: the same exit can't be reversed twice
+    user_stake.last_unstake_completion_time = 0;
+    user_stake.last_unstake_completion_amount = 0;
+
+    pool.total_reward_debt = pool
+        .total_reward_debt
+        .saturating_sub(old_reward_debt)
+        .checked_add(user_stake.reward_debt)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.total_staked = new_total_staked;
+
+    pool.save(pool_info)?;
+    user_stake.save(user_stake_info)?;
+
+    // Transfer tokens from user back to the vault
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let decimals = mint.base.decimals;
+    drop(mint_data);
+
+    invoke(
+        &spl_token_2022::instruction::transfer_checked(
+            token_program_info.key,
+            user_token_info.key,
+            mint_info.key,
+            token_vault_info.key,
+            user_info.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            user_token_info.clone(),
+            mint_info.clone(),
+            token_vault_info.clone(),
+            user_info.clone(),
+        ],
+    )?;
+
+    msg!("Reversed unstake of {} tokens", amount);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Set delegate instruction (authority only)
+//!
+//! Lets the authority hand out capability-scoped keys, e.g. a crank service
+//! that may only force syncs or an ops key that may only adjust settings,
+//! without sharing the authority itself. Deposits and the plain SyncRewards
+//! crank are permissionless and need no delegation.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Grant `permissions` (DELEGATE_PERM_* bits) to `delegate`, replacing any it
+/// held; 0 revokes the delegate
+///
+/// Only the authority can manage delegates, and TransferAuthority drops them
+/// all. Delegates lose their powers if the authority is renounced.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    permissions: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (delegates can't manage delegates)
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    pool.set_delegate(delegate, permissions)?;
+
+    // Grow legacy pools if a delegate is added (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    if permissions == 0 {
+        msg!("Delegate {} revoked", delegate);
+    } else {
+        msg!("Delegate {} permissions: {:#04x}", delegate, permissions);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Set display unit instruction (authority or display delegate)
+//!
+//! Lets a pool show its rewards in a secondary unit (e.g. "$X distributed")
+//! without swapping anything: the authority names the unit, depositors attach
+//! a client-priced value to DepositRewards, and the pool keeps the running
+//! total. None of it enters the lamport reward math.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, DELEGATE_PERM_DISPLAY, DISPLAY_UNIT_LABEL_LEN},
+};
+
+/// Max decimals of the display unit
+const MAX_DISPLAY_UNIT_DECIMALS: u8 = 18;
+
+/// Set the label and decimals of the pool's display unit (an empty label
+/// clears it). The running total is kept: it is only meaningful per unit, so
+/// switching units is left to the authority's judgment.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or display delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_display_unit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    label: &str,
+    decimals: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a display delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_DISPLAY) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // The label is zero-padded, so it can't contain NUL bytes
+    if label.len() > DISPLAY_UNIT_LABEL_LEN
+        || label.bytes().any(|b| b == 0)
+        || decimals > MAX_DISPLAY_UNIT_DECIMALS
+    {
+        return Err(StakingError::InvalidDisplayUnit.into());
+    }
+
+    pool.display_unit_label = [0; DISPLAY_UNIT_LABEL_LEN];
+    pool.display_unit_label[..label.len()].copy_from_slice(label.as_bytes());
+    pool.display_unit_decimals = decimals;
+
+    // Grow legacy pools if a unit is set (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    msg!("Display unit: \"{}\" ({} decimals)", label, decimals);
+
+    Ok(())
+}
+
+/// Return the pool's display unit and the total distributed in it
+/// (permissionless, read-only)
+///
+/// Returned via return data as the zero-padded label (8 bytes), decimals
+/// (u8) and display_total_distributed (u64 LE).
+///
+/// Accounts:
+/// 0. `[]` Pool account
+pub fn process_query_display_unit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    msg!(
+        "Distributed: {} \"{}\" ({} decimals)",
+        pool.display_total_distributed,
+        pool.display_unit(),
+        pool.display_unit_decimals
+    );
+
+    let mut data = [0u8; DISPLAY_UNIT_LABEL_LEN + 9];
+    data[..DISPLAY_UNIT_LABEL_LEN].copy_from_slice(&pool.display_unit_label);
+    data[DISPLAY_UNIT_LABEL_LEN] = pool.display_unit_decimals;
+    data[DISPLAY_UNIT_LABEL_LEN + 1..].copy_from_slice(&pool.display_total_distributed.to_le_bytes());
+    set_return_data(&data);
+
+    Ok(())
+}
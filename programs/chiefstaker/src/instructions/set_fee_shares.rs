@@ -0,0 +1,150 @@
+//! SetFeeShares — authority only
+//!
+//! While the pool PDA is the pfee fee sharing admin (after
+//! `transfer_fee_sharing_authority`, before TakeFeeOwnership revokes it), the
+//! pool authority can split the creator fees between the pool and other
+//! wallets, e.g. 80% to the pool and 20% to a treasury. The split can be
+//! changed any number of times; nothing is revoked. TakeFeeOwnership later
+//! makes the pool the sole recipient and revokes the admin for good.
+//!
+//! HarvestCreatorFees only works once the pool is the sole recipient, so
+//! while a split is in place the fees are collected off-chain.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    pfee::{validate_fee_shares, FeeOwnershipAccounts, SharingConfig},
+    state::{StakingPool, PFEE_PROGRAM_ID, POOL_SEED},
+};
+
+use super::take_fee_ownership::invoke_update_fee_shares;
+
+/// Replace the mint's pfee shareholders with `shareholders`
+///
+/// The table must hold distinct non-zero shares summing to 10000 bps, one of
+/// them the pool's. Fails with `NotFeeSharingAdmin` unless the pool is the
+/// unrevoked fee sharing admin.
+///
+/// Accounts (19):
+///  0-17. as TakeFeeOwnership
+///  18. `[signer]` Pool authority
+pub fn process_set_fee_shares(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shareholders: Vec<(Pubkey, u16)>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pfee_program_info = next_account_info(account_info_iter)?;
+    let pfee_event_authority_info = next_account_info(account_info_iter)?;
+    let pump_global_info = next_account_info(account_info_iter)?;
+    let sharing_config_info = next_account_info(account_info_iter)?;
+    let bonding_curve_info = next_account_info(account_info_iter)?;
+    let pump_creator_vault_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let pump_program_info = next_account_info(account_info_iter)?;
+    let pump_event_authority_info = next_account_info(account_info_iter)?;
+    let pump_amm_program_info = next_account_info(account_info_iter)?;
+    let amm_event_authority_info = next_account_info(account_info_iter)?;
+    let wsol_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let associated_token_program_info = next_account_info(account_info_iter)?;
+    let coin_creator_vault_auth_info = next_account_info(account_info_iter)?;
+    let coin_creator_vault_ata_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // ── Validate pool ───────────────────────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Verify mint matches pool
+    if pool.mint != *mint_info.key {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // The whole creator fee stream is at stake: no delegates
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    validate_fee_shares(&shareholders, pool_info.key)?;
+
+    // Verify pfee program address
+    if *pfee_program_info.key != PFEE_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    FeeOwnershipAccounts {
+        pfee_event_authority: *pfee_event_authority_info.key,
+        pump_global: *pump_global_info.key,
+        sharing_config: *sharing_config_info.key,
+        bonding_curve: *bonding_curve_info.key,
+        pump_creator_vault: *pump_creator_vault_info.key,
+        system_program: *system_program_info.key,
+        pump_program: *pump_program_info.key,
+        pump_event_authority: *pump_event_authority_info.key,
+        pump_amm_program: *pump_amm_program_info.key,
+        amm_event_authority: *amm_event_authority_info.key,
+        wsol_mint: *wsol_mint_info.key,
+        token_program: *token_program_info.key,
+        associated_token_program: *associated_token_program_info.key,
+        coin_creator_vault_auth: *coin_creator_vault_auth_info.key,
+        coin_creator_vault_ata: *coin_creator_vault_ata_info.key,
+    }
+    .check(&pool.mint)?;
+
+    // Only the admin can update shares, and only until it is revoked
+    if *sharing_config_info.owner != PFEE_PROGRAM_ID {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let config = SharingConfig::parse(&sharing_config_info.try_borrow_data()?)?;
+    if config.admin != *pool_info.key || config.admin_revoked {
+        msg!("Transfer the fee sharing authority to the pool first; it must not be revoked");
+        return Err(StakingError::NotFeeSharingAdmin.into());
+    }
+
+    let pool_seeds: &[&[u8]] = &[POOL_SEED, pool.mint.as_ref(), &[pool.bump]];
+    invoke_update_fee_shares(&accounts[..18], &shareholders, pool_seeds)?;
+
+    for (address, share_bps) in &shareholders {
+        msg!("Fee share: {} bps to {}", share_bps, address);
+    }
+    msg!("Fee shares updated for mint {} (admin kept)", mint_info.key);
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Set pause instruction (authority only, emergency switch)
+//!
+//! Freezes new inflows during an incident without trapping anyone: while
+//! paused, Stake, StakeOnBehalf, ReverseUnstake, DepositRewards and
+//! DepositTokenRewards fail with `PoolPaused`. Unstaking, claiming and
+//! SyncRewards keep working, so stakers can always exit and SOL sent straight
+//! to the pool is still distributed. Pausing only gates those entry points;
+//! it never touches reward accounting.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+/// Pause (`paused = true`) or resume the pool's inflows
+///
+/// Allowed after LockPoolSettings, since it changes no economic setting.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_pause(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    if pool.paused == paused {
+        msg!("Pool already {}", if paused { "paused" } else { "unpaused" });
+        return Ok(());
+    }
+
+    pool.paused = paused;
+
+    // Grow legacy pools to hold the flag (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    if paused {
+        msg!("Pool paused: stakes and reward deposits disabled");
+    } else {
+        msg!("Pool unpaused");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Set reward fee split instruction (authority or fees delegate)
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, DELEGATE_PERM_FEES, FEE_SPLIT_SLOTS},
+};
+
+use super::update_settings::{apply_or_queue_settings_update, PoolSettingsUpdate};
+
+/// Replace the pool's reward fee split with `splits` (empty = no split)
+///
+/// Each (recipient, bps) entry takes that share of every claim and is paid
+/// directly to the recipient. Together with claim_fee_bps the shares are
+/// capped like the protocol fee, including any pending claim fee.
+///
+/// With a settings timelock, a split whose total share is higher than the
+/// current one is only recorded as pending and applied by ApplySettings
+/// once the timelock has run, like other changes that cost stakers. A split
+/// with the same or a lower total applies now and cancels a pending one.
+///
+/// Unpaid shares (see WithdrawFeeSplit) follow their recipient if it stays
+/// in the split. A dropped recipient's unpaid shares go back to stakers with
+/// the next sync, so pay them out first.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or fees delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_reward_fee_split(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    splits: Vec<(Pubkey, u16)>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a fees delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_FEES) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    // Validate the split: a few distinct real recipients with non-zero shares
+    if splits.len() > FEE_SPLIT_SLOTS {
+        msg!("At most {} fee split recipients", FEE_SPLIT_SLOTS);
+        return Err(StakingError::InvalidFeeSplit.into());
+    }
+    for (i, (recipient, bps)) in splits.iter().enumerate() {
+        if *bps == 0
+            || *recipient == Pubkey::default()
+            || recipient == pool_info.key
+            || splits[..i].iter().any(|(other, _)| other == recipient)
+        {
+            msg!("Invalid fee split entry {}", i);
+            return Err(StakingError::InvalidFeeSplit.into());
+        }
+    }
+    let mut recipients = [Pubkey::default(); FEE_SPLIT_SLOTS];
+    let mut shares = [0u16; FEE_SPLIT_SLOTS];
+    for (i, (recipient, bps)) in splits.iter().enumerate() {
+        recipients[i] = *recipient;
+        shares[i] = *bps;
+        msg!("Fee split {}: {} bps to {}", i, bps, recipient);
+    }
+
+    // Caps (with the claim fee, now and once pending changes apply) are
+    // checked whether the split applies now or waits for the timelock
+    let update = PoolSettingsUpdate {
+        fee_split: Some((recipients, shares)),
+        ..PoolSettingsUpdate::default()
+    };
+    apply_or_queue_settings_update(&mut pool, &update)?;
+
+    // Grow legacy pools if the split is set (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+
+    pool.save(pool_info)?;
+
+    Ok(())
+}
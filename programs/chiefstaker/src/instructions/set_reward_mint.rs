@@ -0,0 +1,162 @@
+//! Set reward mint instruction (authority only, before any distribution)
+//!
+//! Switches a pool from SOL rewards to rewards in an SPL token (e.g. USDC),
+//! for fee sources that don't pay in SOL. The accumulator then counts token
+//! base units instead of lamports, so the switch is one-way and only allowed
+//! while it is still zero. Token rewards are held by a reward vault PDA owned
+//! by the pool, deposited with DepositTokenRewards and paid with
+//! ClaimTokenRewards; the SOL deposit, sync and claim instructions reject the
+//! pool from then on. Pools that never set a reward mint are unaffected.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool, REWARD_VAULT_SEED},
+};
+
+use super::initialize::{check_mint_extensions, vault_account_size};
+
+/// Pay the pool's rewards in `reward_mint` instead of SOL
+///
+/// Rejected with `RewardsAlreadyDistributed` once the accumulator is
+/// non-zero, and with `RewardMintAlreadySet` if the pool already has one.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable, signer]` Authority (pays for the reward vault)
+/// 2. `[]` Reward mint
+/// 3. `[writable]` Reward vault (PDA: ["reward_vault", pool])
+/// 4. `[]` Token program of the reward mint
+/// 5. `[]` System program
+pub fn process_set_reward_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let reward_mint_info = next_account_info(account_info_iter)?;
+    let reward_vault_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (no delegate can change the reward mode)
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    if pool.reward_mint().is_some() {
+        return Err(StakingError::RewardMintAlreadySet.into());
+    }
+
+    // The accumulator must not hold lamport-denominated rewards yet
+    if pool.acc_reward_per_weighted_share != 0 {
+        return Err(StakingError::RewardsAlreadyDistributed.into());
+    }
+
+    // Validate the reward mint and its token program
+    if !is_valid_token_program(token_program_info.key) {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+    if reward_mint_info.owner != token_program_info.key {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    {
+        let mint_data = reward_mint_info.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+        check_mint_extensions(&mint_state)?;
+    }
+
+    // Derive and verify reward vault PDA
+    let (expected_vault, vault_bump) =
+        StakingPool::derive_reward_vault_pda(pool_info.key, program_id);
+    if *reward_vault_info.key != expected_vault {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Create the reward vault, owned by the pool PDA
+    let vault_seeds = &[REWARD_VAULT_SEED, pool_info.key.as_ref(), &[vault_bump]];
+    let vault_size = vault_account_size(token_program_info.key)?;
+    let vault_rent = Rent::get()?.minimum_balance(vault_size);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            authority_info.key,
+            reward_vault_info.key,
+            vault_rent,
+            vault_size as u64,
+            token_program_info.key,
+        ),
+        &[
+            authority_info.clone(),
+            reward_vault_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    invoke_signed(
+        &spl_token_2022::instruction::initialize_account3(
+            token_program_info.key,
+            reward_vault_info.key,
+            reward_mint_info.key,
+            pool_info.key,
+        )?,
+        &[reward_vault_info.clone(), reward_mint_info.clone()],
+        &[vault_seeds],
+    )?;
+
+    pool.reward_mint = *reward_mint_info.key;
+    pool.reward_vault = *reward_vault_info.key;
+
+    // Grow legacy pools to hold the reward mint (payer = authority)
+    pool.ensure_capacity(pool_info, authority_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
+
+    msg!("Rewards are now paid in {}", reward_mint_info.key);
+
+    Ok(())
+}
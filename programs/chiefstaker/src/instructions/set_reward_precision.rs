@@ -0,0 +1,89 @@
+//! Set reward precision instruction (authority or settings delegate, before
+//! any distribution)
+//!
+//! The accumulator stores reward-per-share at WAD precision, divided by
+//! 2^acc_scale_shift. The shift normally starts at 0 and only grows when a
+//! larger total_staked would overflow reward debts. A pool expecting very
+//! high rewards per token unit (few tokens, large deposits) can start at a
+//! coarser precision instead, trading up to a lamport of rounding per
+//! distribution for headroom that avoids later rescales. WAD is already the
+//! finest precision u128 reward debts allow, so the shift can't go below 0.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, DELEGATE_PERM_SETTINGS},
+};
+
+/// Set the accumulator's starting precision shift
+///
+/// Rejected with `RewardsAlreadyDistributed` once the accumulator is
+/// non-zero, and with `SettingExceedsMaximum` above MAX_REWARD_PRECISION_SHIFT.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_reward_precision(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shift: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a settings delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SETTINGS) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    pool.set_reward_precision_shift(shift)?;
+
+    // Grow legacy pools to hold the shift (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    msg!("Reward precision shift set to {}", shift);
+
+    Ok(())
+}
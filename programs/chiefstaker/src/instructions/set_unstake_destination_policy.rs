@@ -0,0 +1,81 @@
+//! Set unstake destination policy instruction (authority or settings delegate)
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    state::{StakingPool, UnstakeDestinationPolicy, DELEGATE_PERM_SETTINGS},
+};
+
+/// Choose which token accounts Unstake and CompleteUnstake may pay out to
+///
+/// OwnerAtaOnly restricts payouts to the owner's associated token account
+/// (e.g. for compliance); AnyOwnerAccount, the default, accepts any account
+/// of the pool mint owned by the stake owner.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_set_unstake_destination_policy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    policy: UnstakeDestinationPolicy,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a settings delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SETTINGS) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    pool.unstake_destination_policy = policy;
+
+    // Grow legacy pools if the policy is set (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    msg!("Unstake destination policy: {:?}", policy);
+
+    Ok(())
+}
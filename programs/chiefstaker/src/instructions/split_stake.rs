@@ -0,0 +1,230 @@
+//! Split stake instruction - move part of a position to a new stake account
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    math::U256,
+    state::{PoolMetadata, StakingPool, UserStake, STAKE_SEED},
+};
+
+/// Split `amount` tokens off a stake into a new stake account for `new_owner`.
+///
+/// The new position keeps the same maturity (exp_start_factor, stake_time,
+/// last_stake_time) and takes a proportional slice of reward_debt and
+/// claimed_rewards_wad, so the two positions together earn exactly what the
+/// original would have. The new account is always the new owner's default
+/// stake account, so the new owner must not already have one in this pool.
+/// Splitting to oneself fails with AlreadyInitialized: the new account is
+/// then the source itself or, for a position above index 0, the owner's
+/// default account, which stays open while that position is.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[writable]` Source stake account (PDA: ["stake", pool, owner], or
+///    ["stake", pool, owner, index (u16 LE)] for a position)
+/// 2. `[writable]` New stake account (PDA: ["stake", pool, new_owner])
+/// 3. `[writable, signer]` Owner (pays rent for the new account)
+/// 4. `[]` New owner
+/// 5. `[]` System program
+/// 6. `[writable]` (optional) Pool metadata account (member_count increment)
+pub fn process_split_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let source_stake_info = next_account_info(account_info_iter)?;
+    let new_stake_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let new_owner_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate owner is signer
+    if !owner_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Realloc legacy accounts to current size (payer = owner)
+    UserStake::maybe_realloc(source_stake_info, owner_info, Some(system_program_info))?;
+
+    // Load and validate source stake
+    if source_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut source = UserStake::try_from_slice(&source_stake_info.try_borrow_data()?)?;
+    if !source.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+    if source.owner != *owner_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if source.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+    let (expected_source, _) = source.derive_position_pda(pool_info.key, program_id);
+    if *source_stake_info.key != expected_source {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Block splitting while unstake request is pending
+    if source.has_pending_unstake_request() {
+        return Err(StakingError::PendingUnstakeRequestExists.into());
+    }
+
+    // Both halves must remain non-empty positions
+    if amount >= source.amount {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+    let remaining = source.amount - amount;
+    if pool.min_stake_amount > 0
+        && (amount < pool.min_stake_amount || remaining < pool.min_stake_amount)
+    {
+        return Err(StakingError::BelowMinimumStake.into());
+    }
+
+    // Verify new stake PDA: the new owner's default stake account, which for
+    // new_owner == owner is the source itself or, for a position above
+    // index 0, a default account that is open (either way AlreadyInitialized)
+    let (expected_new, new_bump) =
+        UserStake::derive_pda(pool_info.key, new_owner_info.key, program_id);
+    if *new_stake_info.key != expected_new {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    if !new_stake_info.data_is_empty() {
+        return Err(StakingError::AlreadyInitialized.into());
+    }
+
+    // Lazily adjust exp_start_factor if pool has been rebased, so the new
+    // position starts calibrated to the current base_time
+    source.sync_to_pool(&pool)?;
+
+    // Proportional slices; the source keeps the rounding remainder so the
+    // totals (and the pool's total_reward_debt) are unchanged
+    let split_debt = proportional(source.reward_debt, amount, source.amount)?;
+    let split_claimed = proportional(source.claimed_rewards_wad, amount, source.amount)?;
+
+    // Create the new stake account
+    let rent = Rent::get()?;
+    let stake_rent = rent.minimum_balance(UserStake::LEN);
+    let stake_seeds = &[
+        STAKE_SEED,
+        pool_info.key.as_ref(),
+        new_owner_info.key.as_ref(),
+        &[new_bump],
+    ];
+    invoke_signed(
+        &system_instruction::create_account(
+            owner_info.key,
+            new_stake_info.key,
+            stake_rent,
+            UserStake::LEN as u64,
+            program_id,
+        ),
+        &[
+            owner_info.clone(),
+            new_stake_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[stake_seeds],
+    )?;
+
+    let mut new_stake = UserStake::new(
+        *new_owner_info.key,
+        *pool_info.key,
+        amount,
+        source.stake_time,
+        source.exp_start_factor,
+        new_bump,
+        source.base_time_snapshot,
+    );
+    new_stake.last_stake_time = source.last_stake_time;
+    // Top-up locks follow both halves, capped to each one's amount, so no
+    // token leaves its lock early
+    new_stake.top_up_lock_amounts = source.top_up_lock_amounts;
+    new_stake.top_up_lock_times = source.top_up_lock_times;
+    new_stake.trim_top_up_locks();
+    new_stake.reward_debt = split_debt;
+    new_stake.claimed_rewards_wad = split_claimed;
+    new_stake.last_claim_time = source.last_claim_time;
+    new_stake.terms_stamped = source.terms_stamped;
+    new_stake.lock_duration_seconds = source.lock_duration_seconds;
+    new_stake.lock_commit_seconds = source.lock_commit_seconds;
+    new_stake.unstake_cooldown_seconds = source.unstake_cooldown_seconds;
+    new_stake.acc_scale_shift = source.acc_scale_shift;
+
+    source.amount = remaining;
+    source.trim_top_up_locks();
+    source.reward_debt -= split_debt;
+    source.claimed_rewards_wad -= split_claimed;
+
+    source.save(source_stake_info)?;
+    new_stake.save(new_stake_info)?;
+
+    // Optional metadata account: the new position is a new member
+    if let Some(metadata_info) = account_info_iter.next() {
+        if metadata_info.owner == program_id && !metadata_info.data_is_empty() {
+            let (expected_metadata, _) =
+                PoolMetadata::derive_pda(pool_info.key, program_id);
+            if *metadata_info.key == expected_metadata {
+                let mut metadata =
+                    PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
+                if metadata.is_initialized() && metadata.pool == *pool_info.key {
+                    metadata.member_count = metadata.member_count.saturating_add(1);
+                    let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+                    metadata.serialize(&mut &mut metadata_data[..])?;
+                }
+            }
+        }
+    }
+
+    msg!(
+        "Split {} tokens to {} ({} remaining)",
+        amount,
+        new_owner_info.key,
+        remaining
+    );
+
+    Ok(())
+}
+
+/// value * part / whole, rounded down
+fn proportional(value: u128, part: u64, whole: u64) -> Result<u128, StakingError> {
+    let scaled = U256::from_u128(value)
+        .checked_mul(U256::from_u128(part as u128))
+        .ok_or(StakingError::MathOverflow)?;
+    (scaled / U256::from_u128(whole as u128))
+        .to_u128()
+        .ok_or(StakingError::MathOverflow)
+}
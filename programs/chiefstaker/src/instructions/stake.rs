@@ -6,7 +6,7 @@ use solana_program::{
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
@@ -16,30 +16,55 @@ use spl_token_2022::extension::StateWithExtensions;
 
 use crate::{
     error::StakingError,
+    events::{emit, StakeEvent},
     math::{exp_time_ratio, wad_mul, MAX_EXP_INPUT, U256, WAD},
     state::{is_valid_token_program, PoolMetadata, StakingPool, UserStake, STAKE_SEED},
+    StakeParams, TopUpMode,
 };
 
+use super::update_settings::MAX_LOCK_DURATION_SECONDS;
+
 /// Stake tokens into the pool
 ///
 /// Accounts:
 /// 0. `[writable]` Pool account
-/// 1. `[writable]` User stake account (PDA: ["stake", pool, owner])
+/// 1. `[writable]` User stake account (PDA: ["stake", pool, owner], or any
+///    existing position of the owner)
 /// 2. `[writable]` Token vault
 /// 3. `[writable]` User token account
 /// 4. `[]` Token mint
 /// 5. `[writable, signer]` User/owner
 /// 6. `[]` System program
 /// 7. `[]` Token 2022 program
+///
+/// Optional params: a referrer recorded on a new stake account, a lock the
+/// owner commits to (the stake's lock never drops below it), and whether a
+/// top-up joins the running lock instead of getting its own.
+///
+/// A top-up's tokens are locked from now on their own; the tokens already
+/// staked keep their lock and can be unstaked once it ends. Extending the
+/// lock commitment restarts the lock of the whole position.
+///
+/// Return data: the resulting position amount (u64 LE) followed by its
+/// current weighted stake (u128 LE, WAD-scaled)
 pub fn process_stake(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    amount: u64,
+    params: &StakeParams,
 ) -> ProgramResult {
+    let amount = params.amount;
     if amount == 0 {
         return Err(StakingError::ZeroAmount.into());
     }
 
+    // A lock commitment is capped like the pool's lock
+    if let Some(commit) = params.lock_commit_seconds.0 {
+        if commit > MAX_LOCK_DURATION_SECONDS {
+            msg!("Lock commitment of {}s exceeds the maximum", commit);
+            return Err(StakingError::SettingExceedsMaximum.into());
+        }
+    }
+
     let account_info_iter = &mut accounts.iter();
 
     let pool_info = next_account_info(account_info_iter)?;
@@ -61,6 +86,13 @@ pub fn process_stake(
         return Err(StakingError::MissingRequiredSigner.into());
     }
 
+    // No self-referral
+    if let Some(referrer) = params.referrer.0 {
+        if referrer == *user_info.key || referrer == Pubkey::default() {
+            return Err(StakingError::InvalidReferrer.into());
+        }
+    }
+
     // Load and validate pool
     if pool_info.owner != program_id {
         return Err(StakingError::InvalidAccountOwner.into());
@@ -76,22 +108,62 @@ pub fn process_stake(
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // Quarantined pools only allow exits
+    if pool.quarantined {
+        return Err(StakingError::PoolQuarantined.into());
+    }
+
+    // Paused pools only allow exits
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
     // Verify mint matches pool
     if pool.mint != *mint_info.key {
         return Err(StakingError::InvalidPoolMint.into());
     }
 
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
     // Verify token vault
     if pool.token_vault != *token_vault_info.key {
         return Err(StakingError::InvalidTokenVault.into());
     }
 
+    // An existing stake account may be any of the owner's positions; a
+    // missing one is created as the default position
+    let is_new_account = user_stake_info.data_is_empty();
+    let existing = if is_new_account {
+        None
+    } else {
+        if user_stake_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+        if !user_stake.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
+
+        // Verify ownership
+        if user_stake.owner != *user_info.key {
+            return Err(StakingError::InvalidOwner.into());
+        }
+        if user_stake.pool != *pool_info.key {
+            return Err(StakingError::InvalidPool.into());
+        }
+        Some(user_stake)
+    };
+
     // Verify user stake PDA
-    let (expected_stake, stake_bump) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, stake_bump) = match &existing {
+        Some(user_stake) => user_stake.derive_position_pda(pool_info.key, program_id),
+        None => UserStake::derive_pda(pool_info.key, user_info.key, program_id),
+    };
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
+    let position_index = existing.as_ref().map_or(0, |stake| stake.position_index);
 
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
@@ -116,8 +188,28 @@ pub fn process_stake(
 
     let exp_start_factor = exp_time_ratio(time_since_base, pool.tau_seconds)?;
 
-    // Create or update user stake account
-    let is_new_stake = user_stake_info.data_is_empty();
+    // Capped pools only take stakes that fit under max_total_staked
+    if pool.exceeds_stake_cap(amount) {
+        msg!(
+            "Staking {} would exceed max_total_staked {} (total_staked {})",
+            amount,
+            pool.max_total_staked,
+            pool.total_staked
+        );
+        return Err(StakingError::StakeCapExceeded.into());
+    }
+
+    // Keep reward debts within u128 once this stake is added
+    let new_total_staked = pool
+        .total_staked
+        .checked_add(amount as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.rescale_for(new_total_staked)?;
+
+    // Create or update user stake account. A position opened by
+    // InitializeUserStake and never staked into starts like a new stake.
+    let is_new_stake = existing.as_ref().is_none_or(UserStake::is_unused_position);
+    let (position_amount, position_weight);
 
     if is_new_stake {
         // Check minimum stake amount
@@ -126,30 +218,32 @@ pub fn process_stake(
         }
 
         // Create new user stake account
-        let rent = Rent::get()?;
-        let stake_rent = rent.minimum_balance(UserStake::LEN);
-        let stake_seeds = &[
-            STAKE_SEED,
-            pool_info.key.as_ref(),
-            user_info.key.as_ref(),
-            &[stake_bump],
-        ];
-
-        invoke_signed(
-            &system_instruction::create_account(
-                user_info.key,
-                user_stake_info.key,
-                stake_rent,
-                UserStake::LEN as u64,
-                program_id,
-            ),
-            &[
-                user_info.clone(),
-                user_stake_info.clone(),
-                system_program_info.clone(),
-            ],
-            &[stake_seeds],
-        )?;
+        if is_new_account {
+            let rent = Rent::get()?;
+            let stake_rent = rent.minimum_balance(UserStake::LEN);
+            let stake_seeds = &[
+                STAKE_SEED,
+                pool_info.key.as_ref(),
+                user_info.key.as_ref(),
+                &[stake_bump],
+            ];
+
+            invoke_signed(
+                &system_instruction::create_account(
+                    user_info.key,
+                    user_stake_info.key,
+                    stake_rent,
+                    UserStake::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    user_info.clone(),
+                    user_stake_info.clone(),
+                    system_program_info.clone(),
+                ],
+                &[stake_seeds],
+            )?;
+        }
 
         // Initialize user stake
         let mut user_stake = UserStake::new(
@@ -161,6 +255,8 @@ pub fn process_stake(
             stake_bump,
             pool.base_time,
         );
+        user_stake.acc_scale_shift = pool.acc_scale_shift;
+        user_stake.position_index = position_index;
 
         // Set reward_debt using max weight (amount * WAD) to prevent accessing prior rewards
         user_stake.reward_debt = wad_mul(
@@ -168,14 +264,26 @@ pub fn process_stake(
             pool.acc_reward_per_weighted_share,
         )?;
 
+        // Lock/cooldown in effect now apply to this stake (if per-stake terms)
+        user_stake.stamp_terms(&pool);
+
+        if let Some(commit) = params.lock_commit_seconds.0 {
+            user_stake.lock_commit_seconds = commit;
+        }
+        if let Some(referrer) = params.referrer.0 {
+            user_stake.referrer = referrer;
+            msg!("Referred by {}", referrer);
+        }
+
         // Track in pool-level aggregate
         pool.total_reward_debt = pool
             .total_reward_debt
             .checked_add(user_stake.reward_debt)
             .ok_or(StakingError::MathOverflow)?;
 
-        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
+        user_stake.save(user_stake_info)?;
+        position_amount = user_stake.amount;
+        position_weight = user_stake.weighted_stake(&pool, current_time)?;
 
         // Update pool sum_stake_exp
         // sum_stake_exp += amount * exp_start_factor
@@ -192,22 +300,8 @@ pub fn process_stake(
         // Realloc legacy accounts to current size (payer = user)
         UserStake::maybe_realloc(user_stake_info, user_info, Some(system_program_info))?;
 
-        // Load existing stake
-        if user_stake_info.owner != program_id {
-            return Err(StakingError::InvalidAccountOwner.into());
-        }
-        let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
-        if !user_stake.is_initialized() {
-            return Err(StakingError::NotInitialized.into());
-        }
-
-        // Verify ownership
-        if user_stake.owner != *user_info.key {
-            return Err(StakingError::InvalidOwner.into());
-        }
-        if user_stake.pool != *pool_info.key {
-            return Err(StakingError::InvalidPool.into());
-        }
+        // Existing stake, loaded and validated above
+        let mut user_stake = existing.ok_or(StakingError::NotInitialized)?;
 
         // Block staking while unstake request is pending
         if user_stake.has_pending_unstake_request() {
@@ -253,7 +347,39 @@ pub fn process_stake(
             .ok_or(StakingError::MathOverflow)?;
 
         user_stake.amount = new_total;
-        user_stake.last_stake_time = current_time;
+        // Topping up accepts the current terms (owner-only; StakeOnBehalf
+        // doesn't re-stamp so third parties can't extend someone's lock)
+        user_stake.stamp_terms(&pool);
+        // A commitment can be extended, never shortened; extending it
+        // commits the whole position, so its lock restarts
+        let extends_commit = params
+            .lock_commit_seconds
+            .0
+            .is_some_and(|commit| commit > user_stake.lock_commit_seconds);
+        if let Some(commit) = params.lock_commit_seconds.0 {
+            user_stake.lock_commit_seconds = user_stake.lock_commit_seconds.max(commit);
+        }
+
+        // The new tokens get a lock of their own, and the tokens already
+        // staked keep theirs. KeepLock instead adds them to the running lock
+        // if they would still be locked at least as long as a fresh stake.
+        let keep_lock = params.top_up_mode.0 == Some(TopUpMode::KeepLock) && {
+            let lock_end = user_stake
+                .effective_last_stake_time()
+                .saturating_add(user_stake.effective_lock_duration(&pool) as i64);
+            lock_end.saturating_sub(current_time) >= pool.lock_duration_seconds as i64
+        };
+        if extends_commit || new_total == amount {
+            // (a position staked back up from zero starts its lock afresh)
+            user_stake.reset_lock(current_time);
+        } else if keep_lock {
+            msg!("Top-up keeps the running lock");
+        } else {
+            user_stake.lock_top_up(&pool, amount, current_time);
+        }
+        if params.referrer.0.is_some() {
+            msg!("Referrer is only recorded on new stakes; ignored");
+        }
         // exp_start_factor: UNCHANGED — maturity depends only on start time
         // claimed_rewards_wad: UNCHANGED — pending rewards stay exactly the same
 
@@ -264,19 +390,17 @@ pub fn process_stake(
             .checked_add(user_stake.reward_debt)
             .ok_or(StakingError::MathOverflow)?;
 
-        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
+        user_stake.save(user_stake_info)?;
+        position_amount = user_stake.amount;
+        position_weight = user_stake.weighted_stake(&pool, current_time)?;
     }
 
     // Update pool total staked
-    pool.total_staked = pool
-        .total_staked
-        .checked_add(amount as u128)
-        .ok_or(StakingError::MathOverflow)?;
+    pool.total_staked = new_total_staked;
 
-    // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    // Save pool state (a legacy pool grows if the accumulator was rescaled)
+    pool.ensure_capacity(pool_info, user_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
 
     // Transfer tokens from user to vault
     // Get decimals from mint for transfer_checked
@@ -304,26 +428,51 @@ pub fn process_stake(
         ],
     )?;
 
-    // Optional metadata account: increment member_count on new stake
-    if is_new_stake {
-        if let Some(metadata_info) = account_info_iter.next() {
-            if metadata_info.owner == program_id && !metadata_info.data_is_empty() {
-                let (expected_metadata, _) =
-                    PoolMetadata::derive_pda(pool_info.key, program_id);
-                if *metadata_info.key == expected_metadata {
-                    let mut metadata =
-                        PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
-                    if metadata.is_initialized() && metadata.pool == *pool_info.key {
-                        metadata.member_count = metadata.member_count.saturating_add(1);
-                        let mut metadata_data = metadata_info.try_borrow_mut_data()?;
-                        metadata.serialize(&mut &mut metadata_data[..])?;
-                    }
-                }
-            }
-        }
+    // Optional metadata account: increment member_count on a new account
+    if is_new_account {
+        count_new_member(program_id, pool_info, account_info_iter.next())?;
     }
 
     msg!("Staked {} tokens", amount);
+    emit(&StakeEvent {
+        pool: *pool_info.key,
+        user: *user_info.key,
+        amount,
+        position_amount,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    // Let clients confirm the outcome without re-reading the account
+    let mut return_data = [0u8; 24];
+    return_data[..8].copy_from_slice(&position_amount.to_le_bytes());
+    return_data[8..].copy_from_slice(&position_weight.to_le_bytes());
+    set_return_data(&return_data);
 
     Ok(())
 }
+
+/// Increment the pool metadata's member_count for a newly created stake
+/// account (no-op unless `metadata_info` is the pool's metadata account)
+pub(crate) fn count_new_member(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    metadata_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    if let Some(metadata_info) = metadata_info {
+        if metadata_info.owner == program_id && !metadata_info.data_is_empty() {
+            let (expected_metadata, _) = PoolMetadata::derive_pda(pool_info.key, program_id);
+            if *metadata_info.key == expected_metadata {
+                let mut metadata =
+                    PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
+                if metadata.is_initialized() && metadata.pool == *pool_info.key {
+                    metadata.member_count = metadata.member_count.saturating_add(1);
+                    let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+                    metadata.serialize(&mut &mut metadata_data[..])?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -16,6 +16,7 @@ use spl_token_2022::extension::StateWithExtensions;
 
 use crate::{
     error::StakingError,
+    events::{emit, StakeEvent},
     math::{exp_time_ratio, wad_mul, MAX_EXP_INPUT, U256, WAD},
     state::{is_valid_token_program, PoolMetadata, StakingPool, UserStake, STAKE_SEED},
 };
@@ -78,11 +79,24 @@ pub fn process_stake_on_behalf(
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // Quarantined pools only allow exits
+    if pool.quarantined {
+        return Err(StakingError::PoolQuarantined.into());
+    }
+
+    // Paused pools only allow exits
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
     // Verify mint matches pool
     if pool.mint != *mint_info.key {
         return Err(StakingError::InvalidPoolMint.into());
     }
 
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
     // Verify token vault
     if pool.token_vault != *token_vault_info.key {
         return Err(StakingError::InvalidTokenVault.into());
@@ -118,8 +132,27 @@ pub fn process_stake_on_behalf(
 
     let exp_start_factor = exp_time_ratio(time_since_base, pool.tau_seconds)?;
 
+    // Capped pools only take stakes that fit under max_total_staked
+    if pool.exceeds_stake_cap(amount) {
+        msg!(
+            "Staking {} would exceed max_total_staked {} (total_staked {})",
+            amount,
+            pool.max_total_staked,
+            pool.total_staked
+        );
+        return Err(StakingError::StakeCapExceeded.into());
+    }
+
+    // Keep reward debts within u128 once this stake is added
+    let new_total_staked = pool
+        .total_staked
+        .checked_add(amount as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    pool.rescale_for(new_total_staked)?;
+
     // Create or update beneficiary stake account
     let is_new_stake = beneficiary_stake_info.data_is_empty();
+    let position_amount;
 
     if is_new_stake {
         // Check minimum stake amount
@@ -163,6 +196,7 @@ pub fn process_stake_on_behalf(
             stake_bump,
             pool.base_time,
         );
+        user_stake.acc_scale_shift = pool.acc_scale_shift;
 
         // Set reward_debt using max weight (amount * WAD) to prevent accessing prior rewards
         user_stake.reward_debt = wad_mul(
@@ -170,14 +204,17 @@ pub fn process_stake_on_behalf(
             pool.acc_reward_per_weighted_share,
         )?;
 
+        // Lock/cooldown in effect now apply to this stake (if per-stake terms)
+        user_stake.stamp_terms(&pool);
+
         // Track in pool-level aggregate
         pool.total_reward_debt = pool
             .total_reward_debt
             .checked_add(user_stake.reward_debt)
             .ok_or(StakingError::MathOverflow)?;
 
-        let mut stake_data = beneficiary_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
+        user_stake.save(beneficiary_stake_info)?;
+        position_amount = user_stake.amount;
 
         // Update pool sum_stake_exp
         // sum_stake_exp += amount * exp_start_factor
@@ -254,7 +291,8 @@ pub fn process_stake_on_behalf(
             .ok_or(StakingError::MathOverflow)?;
 
         user_stake.amount = new_total;
-        user_stake.last_stake_time = current_time;
+        // Only the gifted tokens are locked; the owner's keep their lock
+        user_stake.lock_top_up(&pool, amount, current_time);
         // exp_start_factor: UNCHANGED — maturity depends only on start time
         // claimed_rewards_wad: UNCHANGED — pending rewards stay exactly the same
 
@@ -265,19 +303,16 @@ pub fn process_stake_on_behalf(
             .checked_add(user_stake.reward_debt)
             .ok_or(StakingError::MathOverflow)?;
 
-        let mut stake_data = beneficiary_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
+        user_stake.save(beneficiary_stake_info)?;
+        position_amount = user_stake.amount;
     }
 
     // Update pool total staked
-    pool.total_staked = pool
-        .total_staked
-        .checked_add(amount as u128)
-        .ok_or(StakingError::MathOverflow)?;
+    pool.total_staked = new_total_staked;
 
-    // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    // Save pool state (a legacy pool grows if the accumulator was rescaled)
+    pool.ensure_capacity(pool_info, staker_info, Some(system_program_info))?;
+    pool.save(pool_info)?;
 
     // Transfer tokens from staker to vault (staker signs the transfer)
     let mint_data = mint_info.try_borrow_data()?;
@@ -324,6 +359,15 @@ pub fn process_stake_on_behalf(
     }
 
     msg!("Staked {} tokens on behalf of beneficiary", amount);
+    emit(&StakeEvent {
+        pool: *pool_info.key,
+        user: *beneficiary_info.key,
+        amount,
+        position_amount,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
 
     Ok(())
 }
@@ -1,6 +1,6 @@
 //! Sync/rebase pool instruction
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -10,11 +10,7 @@ use solana_program::{
     sysvar::Sysvar,
 };
 
-use crate::{
-    error::StakingError,
-    math::{exp_neg_time_ratio, wad_mul_u256, U256},
-    state::StakingPool,
-};
+use crate::{error::StakingError, state::StakingPool};
 
 /// Sync/rebase the pool to prevent overflow
 /// This shifts base_time forward and scales down sum_stake_exp
@@ -57,31 +53,11 @@ pub fn process_sync_pool(
         return Ok(());
     }
 
-    // Record the original base_time before first rebase so legacy UserStake
-    // accounts can be lazily adjusted (their exp_start_factor is relative
-    // to the initial base_time).
-    if pool.initial_base_time == 0 {
-        pool.initial_base_time = pool.base_time;
-    }
-
-    // Calculate the decay factor: e^(-time_delta / tau)
-    // For very large time_delta (> 87*tau), exp_neg_time_ratio returns 0,
-    // meaning all stakes are fully matured and sum_stake_exp zeroes out.
-    let decay_factor = exp_neg_time_ratio(time_delta, pool.tau_seconds)?;
-
-    // Scale down sum_stake_exp by decay factor
-    // new_sum_stake_exp = old_sum_stake_exp * decay_factor / WAD
-    let sum_stake_exp = pool.get_sum_stake_exp();
-    let decay_u256 = U256::from_u128(decay_factor);
-    let new_sum_stake_exp = wad_mul_u256(sum_stake_exp, decay_u256)?;
-
-    // Update pool state
-    pool.set_sum_stake_exp(new_sum_stake_exp);
-    pool.base_time = current_time;
+    // Shift base_time to now, scaling sum_stake_exp down by e^(-time_delta / tau)
+    let decay_factor = pool.rebase(current_time)?;
 
     // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    pool.save(pool_info)?;
 
     msg!(
         "Synced pool: base_time updated to {}, sum_stake_exp reduced by factor {}",
@@ -3,12 +3,14 @@
 //! This allows external sources (like pump.fun) to send SOL directly
 //! to the pool PDA, and anyone can call this to distribute it.
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
+    program::set_return_data,
+    program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
     sysvar::Sysvar,
@@ -16,15 +18,21 @@ use solana_program::{
 
 use crate::{
     error::StakingError,
-    math::{wad_div, WAD},
-    state::StakingPool,
+    state::{AuditRecord, StakingPool, DELEGATE_PERM_SYNC},
 };
 
+use super::audit_log::record_distribution;
+
 /// Sync rewards that were sent directly to the pool account
 /// This is a permissionless crank that anyone can call
 ///
 /// Accounts:
 /// 0. `[writable]` Pool account
+///
+/// Optional trailing accounts (pools with an audit log):
+/// - `[writable]` Current audit log page (PDA: ["audit_log", pool, page])
+/// - `[writable, signer]` Payer, if the page has to be created
+/// - `[]` System program, if the page has to be created
 pub fn process_sync_rewards(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -47,57 +55,221 @@ pub fn process_sync_rewards(
         return Err(StakingError::InvalidPDA.into());
     }
 
-    let rent = Rent::get()?;
-    let clock = Clock::get()?;
-    let current_time = clock.unix_timestamp;
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
 
-    // Calculate how much SOL is available for rewards
-    let pool_lamports = pool_info.lamports();
-    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
+    distribute_unsynced(program_id, &mut pool, pool_info, accounts)
+}
+
+/// Most pools per SyncRewardsBatch, keeping the batch within the default
+/// compute budget even when each pool distributes and logs an audit record
+pub const MAX_SYNC_REWARDS_BATCH: usize = 10;
+
+/// Sync rewards on several pools at once (permissionless crank)
+///
+/// Each pool gets the same checks and sync as SyncRewards. Pools with no new
+/// rewards, no stakers or SPL-token rewards are skipped rather than failing
+/// the batch; an account that is not a valid pool fails it. Returns the
+/// number of pools that distributed rewards (u64 LE) via return data.
+///
+/// Accounts:
+/// 0..pool_count. `[writable]` Pool accounts, at most MAX_SYNC_REWARDS_BATCH
+///
+/// Optional trailing accounts (pools with an audit log): their current audit
+/// log pages, then a payer and the system program if a page has to be created
+pub fn process_sync_rewards_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_count: u8,
+) -> ProgramResult {
+    let pool_count = pool_count as usize;
+    if pool_count == 0 || accounts.len() < pool_count {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if pool_count > MAX_SYNC_REWARDS_BATCH {
+        return Err(StakingError::BatchTooLarge.into());
+    }
 
-    let last_known = pool.last_synced_lamports;
-    let current_available = pool_lamports.saturating_sub(rent_exempt_minimum);
+    let mut synced = 0u64;
+    for pool_info in &accounts[..pool_count] {
+        // Load and validate pool
+        if pool_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+        if !pool.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
 
-    // New rewards = current balance - what we knew about
-    let new_rewards = current_available.saturating_sub(last_known);
+        // Verify pool PDA
+        let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+        if *pool_info.key != expected_pool {
+            return Err(StakingError::InvalidPDA.into());
+        }
 
-    if new_rewards == 0 {
-        msg!("No new rewards to sync");
-        return Ok(());
+        if pool.reward_mint().is_some() {
+            msg!("Skipping {}: SPL-token rewards", pool_info.key);
+            continue;
+        }
+        pool.require_sol_rewards(pool_info.key)?;
+
+        msg!("Syncing pool {}", pool_info.key);
+        if try_distribute_unsynced(program_id, &mut pool, pool_info, accounts)? {
+            synced += 1;
+        }
     }
 
-    // Denominator: total_staked * WAD (max weight, not time-varying)
-    let total_staked_wad = (pool.total_staked as u128)
-        .checked_mul(WAD)
-        .ok_or(StakingError::MathOverflow)?;
+    msg!("Synced {} of {} pools", synced, pool_count);
+    set_return_data(&synced.to_le_bytes());
 
-    if total_staked_wad == 0 {
-        // No stakers to distribute to. Leave rewards pending.
+    Ok(())
+}
+
+/// Sync rewards only if the pool holds exactly `expected_balance` lamports
+/// (authority or sync delegate)
+///
+/// For reconciliation: the sync runs against the balance the caller read
+/// off-chain, or not at all if it changed in between.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority or sync delegate
+///
+/// Optional trailing accounts: as SyncRewards
+pub fn process_force_sync_with_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    expected_balance: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // SPL-token reward pools use DepositTokenRewards / ClaimTokenRewards
+    pool.require_sol_rewards(pool_info.key)?;
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (or a sync delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SYNC) {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    let pool_lamports = pool_info.lamports();
+    if pool_lamports != expected_balance {
         msg!(
-            "Rewards deferred: {} new lamports, no stakers",
-            new_rewards,
+            "Pool balance {} does not match expected {}",
+            pool_lamports,
+            expected_balance
         );
-        return Ok(());
+        return Err(StakingError::BalanceMismatch.into());
     }
 
-    // Calculate reward per share using max weight denominator
-    let amount_wad = (new_rewards as u128)
-        .checked_mul(WAD)
-        .ok_or(StakingError::MathOverflow)?;
-    let reward_per_share = wad_div(amount_wad, total_staked_wad)?;
+    distribute_unsynced(program_id, &mut pool, pool_info, accounts)
+}
+
+/// Distribute lamports above `last_synced_lamports` (and rent) to stakers
+pub(crate) fn distribute_unsynced<'a>(
+    program_id: &Pubkey,
+    pool: &mut StakingPool,
+    pool_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+) -> ProgramResult {
+    try_distribute_unsynced(program_id, pool, pool_info, accounts)?;
+    Ok(())
+}
+
+/// As `distribute_unsynced`, returning whether anything was distributed
+/// (false when the rewards are deferred or there are none)
+fn try_distribute_unsynced<'a>(
+    program_id: &Pubkey,
+    pool: &mut StakingPool,
+    pool_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+) -> Result<bool, ProgramError> {
+    let rent = Rent::get()?;
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+
+    let pool_lamports = pool_info.lamports();
+    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
 
-    // Update accumulator
-    pool.acc_reward_per_weighted_share = pool
-        .acc_reward_per_weighted_share
-        .checked_add(reward_per_share)
-        .ok_or(StakingError::MathOverflow)?;
+    let Some((new_rewards, reward_per_share)) =
+        pool.sync_accumulator(pool_lamports, rent_exempt_minimum, current_time)?
+    else {
+        // New rewards = current balance - what we knew about
+        let new_rewards = pool
+            .reward_lamports(pool_lamports, rent_exempt_minimum, current_time)
+            .saturating_sub(pool.last_synced_lamports);
+        if new_rewards == 0 {
+            msg!("No new rewards to sync");
+        } else if pool.total_staked == 0 {
+            // No stakers to distribute to. Leave rewards pending.
+            msg!(
+                "Rewards deferred: {} new lamports, no stakers",
+                new_rewards,
+            );
+        } else if !pool.below_min_distributable(new_rewards) {
+            // Rounds to nothing per share: wait for more to arrive
+            msg!(
+                "Rewards pending: {} new lamports, too small to register per share",
+                new_rewards,
+            );
+        } else {
+            // Too small to distribute on its own: wait for more to arrive
+            msg!(
+                "Rewards pending: {} new lamports, below min_distributable_per_event {}",
+                new_rewards,
+                pool.min_distributable_per_event
+            );
+        }
+        return Ok(false);
+    };
 
     pool.last_update_time = current_time;
-    pool.last_synced_lamports = current_available;
+
+    // Legacy pools only track the reward rate once they have grown
+    if pool_info.data_len() >= StakingPool::LEN {
+        pool.record_reward_rate(new_rewards, current_time);
+    }
+
+    // Record the distribution before saving (may advance the page index)
+    let record = AuditRecord {
+        timestamp: current_time,
+        amount: new_rewards,
+        reward_per_share,
+        total_staked: pool.total_staked,
+    };
+    let payer = accounts[1..]
+        .iter()
+        .find(|info| info.is_signer && info.is_writable);
+    record_distribution(program_id, pool, pool_info, accounts, payer, record)?;
 
     // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    pool.save(pool_info)?;
 
     msg!(
         "Synced {} lamports of new rewards, reward_per_share: {}",
@@ -105,5 +277,5 @@ pub fn process_sync_rewards(
         reward_per_share
     );
 
-    Ok(())
+    Ok(true)
 }
@@ -6,6 +6,10 @@
 //!
 //! Prerequisite: the fee sharing authority must already have been transferred
 //! to the pool PDA off-chain via `transfer_fee_sharing_authority`.
+//!
+//! All accounts are checked against their expected addresses before the
+//! first CPI, so a bad account fails with a specific error instead of
+//! surfacing from inside pfee.
 
 use borsh::BorshDeserialize;
 use solana_program::{
@@ -20,12 +24,10 @@ use solana_program::{
 
 use crate::{
     error::StakingError,
+    pfee::{update_fee_shares_data, FeeOwnershipAccounts},
     state::{StakingPool, PFEE_PROGRAM_ID, POOL_SEED},
 };
 
-/// update_fee_shares discriminator
-const UPDATE_FEE_SHARES_DISC: [u8; 8] = [189, 13, 136, 99, 187, 164, 237, 35];
-
 /// revoke_fee_sharing_authority discriminator
 const REVOKE_FEE_SHARING_AUTHORITY_DISC: [u8; 8] = [18, 233, 158, 39, 185, 207, 58, 104];
 
@@ -100,17 +102,107 @@ pub fn process_take_fee_ownership(
         return Err(ProgramError::IncorrectProgramId);
     }
 
+    // Verify every other account up front: both CPIs only run once the
+    // whole set is known to be right
+    FeeOwnershipAccounts {
+        pfee_event_authority: *pfee_event_authority_info.key,
+        pump_global: *pump_global_info.key,
+        sharing_config: *sharing_config_info.key,
+        bonding_curve: *bonding_curve_info.key,
+        pump_creator_vault: *pump_creator_vault_info.key,
+        system_program: *system_program_info.key,
+        pump_program: *pump_program_info.key,
+        pump_event_authority: *pump_event_authority_info.key,
+        pump_amm_program: *pump_amm_program_info.key,
+        amm_event_authority: *amm_event_authority_info.key,
+        wsol_mint: *wsol_mint_info.key,
+        token_program: *token_program_info.key,
+        associated_token_program: *associated_token_program_info.key,
+        coin_creator_vault_auth: *coin_creator_vault_auth_info.key,
+        coin_creator_vault_ata: *coin_creator_vault_ata_info.key,
+    }
+    .check(&pool.mint)?;
+
     // Pool PDA signing seeds
     let pool_seeds: &[&[u8]] = &[POOL_SEED, pool.mint.as_ref(), &[pool.bump]];
 
     // ── CPI 1: update_fee_shares ────────────────────────────────────────────
-    // Data: 8-byte discriminator + Vec<Shareholder> with 1 entry {pool_pda, 10000u16}
-    // Vec encoding: 4-byte length + 1 * (32 + 2) = 46 bytes total
-    let mut update_data = Vec::with_capacity(8 + 4 + 34);
-    update_data.extend_from_slice(&UPDATE_FEE_SHARES_DISC);
-    update_data.extend_from_slice(&1u32.to_le_bytes()); // vec length = 1
-    update_data.extend_from_slice(pool_info.key.as_ref()); // shareholder address = pool PDA
-    update_data.extend_from_slice(&10000u16.to_le_bytes()); // 10000 bps = 100%
+    // 1 entry {pool_pda, 10000u16} (10000 bps = 100%), 46 bytes of data
+    invoke_update_fee_shares(&accounts[..18], &[(*pool_info.key, 10000)], pool_seeds)?;
+
+    msg!("Fee shares updated: pool PDA = 100%");
+
+    // ── CPI 2: revoke_fee_sharing_authority ─────────────────────────────────
+    let revoke_data = REVOKE_FEE_SHARING_AUTHORITY_DISC.to_vec();
+
+    let revoke_accounts = vec![
+        AccountMeta::new_readonly(*pool_info.key, true),                    // 0  authority (signer)
+        AccountMeta::new_readonly(*pump_global_info.key, false),            // 1  global
+        AccountMeta::new_readonly(*mint_info.key, false),                   // 2  mint
+        AccountMeta::new(*sharing_config_info.key, false),                  // 3  sharing_config
+        AccountMeta::new_readonly(*pfee_event_authority_info.key, false),   // 4  event_authority
+        AccountMeta::new_readonly(*pfee_program_info.key, false),           // 5  program (self)
+    ];
+
+    let revoke_ix = Instruction {
+        program_id: PFEE_PROGRAM_ID,
+        accounts: revoke_accounts,
+        data: revoke_data,
+    };
+
+    invoke_signed(
+        &revoke_ix,
+        &[
+            pool_info.clone(),
+            pump_global_info.clone(),
+            mint_info.clone(),
+            sharing_config_info.clone(),
+            pfee_event_authority_info.clone(),
+            pfee_program_info.clone(),
+        ],
+        &[pool_seeds],
+    )?;
+
+    msg!("Fee sharing authority revoked — pool {} owns fees for mint {}", pool_info.key, mint_info.key);
+
+    Ok(())
+}
+
+/// CPI pfee update_fee_shares with `shareholders` as the new table, signed
+/// by the pool PDA (the fee sharing admin)
+///
+/// `accounts` are TakeFeeOwnership's 18, already checked by the caller.
+pub(crate) fn invoke_update_fee_shares(
+    accounts: &[AccountInfo],
+    shareholders: &[(Pubkey, u16)],
+    pool_seeds: &[&[u8]],
+) -> ProgramResult {
+    let [
+        pool_info,
+        mint_info,
+        pfee_program_info,
+        pfee_event_authority_info,
+        pump_global_info,
+        sharing_config_info,
+        bonding_curve_info,
+        pump_creator_vault_info,
+        system_program_info,
+        pump_program_info,
+        pump_event_authority_info,
+        pump_amm_program_info,
+        amm_event_authority_info,
+        wsol_mint_info,
+        token_program_info,
+        associated_token_program_info,
+        coin_creator_vault_auth_info,
+        coin_creator_vault_ata_info,
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    // Data: 8-byte discriminator + Vec<Shareholder>
+    let update_data = update_fee_shares_data(shareholders)?;
 
     // 19 account metas matching the pfee update_fee_shares IDL order
     let update_accounts = vec![
@@ -164,42 +256,5 @@ pub fn process_take_fee_ownership(
             coin_creator_vault_ata_info.clone(),
         ],
         &[pool_seeds],
-    )?;
-
-    msg!("Fee shares updated: pool PDA = 100%");
-
-    // ── CPI 2: revoke_fee_sharing_authority ─────────────────────────────────
-    let revoke_data = REVOKE_FEE_SHARING_AUTHORITY_DISC.to_vec();
-
-    let revoke_accounts = vec![
-        AccountMeta::new_readonly(*pool_info.key, true),                    // 0  authority (signer)
-        AccountMeta::new_readonly(*pump_global_info.key, false),            // 1  global
-        AccountMeta::new_readonly(*mint_info.key, false),                   // 2  mint
-        AccountMeta::new(*sharing_config_info.key, false),                  // 3  sharing_config
-        AccountMeta::new_readonly(*pfee_event_authority_info.key, false),   // 4  event_authority
-        AccountMeta::new_readonly(*pfee_program_info.key, false),           // 5  program (self)
-    ];
-
-    let revoke_ix = Instruction {
-        program_id: PFEE_PROGRAM_ID,
-        accounts: revoke_accounts,
-        data: revoke_data,
-    };
-
-    invoke_signed(
-        &revoke_ix,
-        &[
-            pool_info.clone(),
-            pump_global_info.clone(),
-            mint_info.clone(),
-            sharing_config_info.clone(),
-            pfee_event_authority_info.clone(),
-            pfee_program_info.clone(),
-        ],
-        &[pool_seeds],
-    )?;
-
-    msg!("Fee sharing authority revoked — pool {} owns fees for mint {}", pool_info.key, mint_info.key);
-
-    Ok(())
+    )
 }
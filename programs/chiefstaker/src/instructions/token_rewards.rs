@@ -0,0 +1,418 @@
+//! Deposit and claim instructions for SPL-token reward pools
+//!
+//! Once SetRewardMint switched a pool to token rewards, the accumulator
+//! counts reward token base units and the pool's reward vault holds them.
+//! Unlike SOL, tokens can't arrive unnoticed (the vault is only credited by
+//! deposits), so there is no sync: a deposit is distributed right away and
+//! needs at least one staker. Claims are paid in full from the vault, without
+//! the SOL claim fee or fee split.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::Sysvar,
+};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::StakingError,
+    events::{emit, ClaimEvent, DepositEvent},
+    math::WAD,
+    state::{is_valid_token_program, AuditRecord, StakingPool, UserStake, POOL_SEED},
+};
+
+use super::{
+    audit_log::record_distribution,
+    deposit::{excluded_depositor_stakes, excluded_stake_wad, skip_depositor_stake},
+    depositor_allowlist::check_allowlisted_depositor,
+};
+
+/// Load a token reward pool and check its reward vault, mint and token program
+fn load_token_reward_pool(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    reward_vault_info: &AccountInfo,
+    reward_mint_info: &AccountInfo,
+    token_program_info: &AccountInfo,
+) -> Result<StakingPool, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // SOL reward pools use DepositRewards / ClaimRewards
+    let reward_mint = pool.reward_mint().ok_or(StakingError::RewardModeMismatch)?;
+    if *reward_mint_info.key != reward_mint {
+        msg!("Reward mint does not match the pool's ({})", reward_mint);
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+    if *reward_vault_info.key != pool.reward_vault {
+        return Err(StakingError::InvalidRewardVault.into());
+    }
+    if !is_valid_token_program(token_program_info.key)
+        || reward_mint_info.owner != token_program_info.key
+    {
+        return Err(StakingError::InvalidTokenProgram.into());
+    }
+
+    Ok(pool)
+}
+
+/// Decimals of the reward mint, for transfer_checked
+fn mint_decimals(mint_info: &AccountInfo) -> Result<u8, ProgramError> {
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    Ok(mint.base.decimals)
+}
+
+/// Deposit SPL-token rewards into a token reward pool
+/// Anyone can call this (permissionless)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Depositor
+/// 2. `[writable]` Depositor's reward token account
+/// 3. `[writable]` Reward vault (PDA: ["reward_vault", pool])
+/// 4. `[]` Reward mint
+/// 5. `[]` Token program of the reward mint
+///
+/// Optional trailing accounts (matched by key), as DepositRewards:
+/// - `[]` Depositor's allowlist entry; required when the pool requires
+///   allowlisted depositors
+/// - `[writable]` Depositor's stake accounts (the default one and every
+///   position up to its max_position_index); required when the pool
+///   excludes depositors from their own deposits
+/// - `[writable]` Current audit log page; required when the pool keeps an
+///   audit log, created (paid by a writable depositor, with the system
+///   program) when the previous page filled
+pub fn process_deposit_token_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Err(StakingError::ZeroAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let depositor_info = next_account_info(account_info_iter)?;
+    let depositor_token_info = next_account_info(account_info_iter)?;
+    let reward_vault_info = next_account_info(account_info_iter)?;
+    let reward_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate depositor is signer
+    if !depositor_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    let mut pool = load_token_reward_pool(
+        program_id,
+        pool_info,
+        reward_vault_info,
+        reward_mint_info,
+        token_program_info,
+    )?;
+
+    // Permissioned pools only take rewards from allowlisted depositors
+    check_allowlisted_depositor(
+        program_id,
+        &pool,
+        pool_info,
+        depositor_info,
+        account_info_iter.as_slice(),
+    )?;
+
+    // Paused pools take no new rewards
+    if pool.paused {
+        return Err(StakingError::PoolPaused.into());
+    }
+
+    // Dust deposits would only churn the accumulator (and audit log)
+    pool.check_min_reward_deposit(amount)?;
+
+    // Denominator: total_staked * WAD (max weight; each stake's time weight
+    // is applied to its share when it claims)
+    let total_staked_wad = pool
+        .total_staked
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    if total_staked_wad == 0 {
+        msg!("No stakers to distribute token rewards to");
+        return Err(StakingError::NoEligibleStakers.into());
+    }
+
+    // Optionally exclude the depositor's own stake, as DepositRewards does
+    let depositor_stakes = excluded_depositor_stakes(
+        program_id,
+        &pool,
+        pool_info,
+        depositor_info,
+        account_info_iter.as_slice(),
+    )?;
+    let excluded_wad = excluded_stake_wad(&depositor_stakes)?;
+    let eligible_wad = total_staked_wad.saturating_sub(excluded_wad);
+    if eligible_wad == 0 {
+        msg!("Depositor is the only staker; nothing to distribute to");
+        return Err(StakingError::NoEligibleStakers.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // reward_per_share = amount * WAD / (total_staked * WAD), in token units,
+    // truncated to the accumulator's precision. There is no pending balance
+    // to leave dust in, so a deposit that registers nothing is rejected.
+    let (reward_per_share, _) = pool.reward_per_share_for(amount, eligible_wad)?;
+    if reward_per_share == 0 {
+        msg!("Deposit of {} reward tokens is too small to register per share", amount);
+        return Err(StakingError::DepositBelowMinimum.into());
+    }
+
+    // Update accumulator (lowering its precision if debts would overflow)
+    pool.add_reward_per_share(reward_per_share)?;
+
+    for (stake_info, user_stake) in depositor_stakes {
+        skip_depositor_stake(&mut pool, stake_info, user_stake, reward_per_share)?;
+    }
+
+    pool.last_update_time = current_time;
+    pool.record_reward_rate(amount, current_time);
+
+    // Record the distribution before saving (may advance the page index)
+    let record = AuditRecord {
+        timestamp: current_time,
+        amount,
+        reward_per_share,
+        total_staked: pool.total_staked,
+    };
+    let payer = Some(depositor_info).filter(|info| info.is_writable);
+    record_distribution(program_id, &mut pool, pool_info, accounts, payer, record)?;
+
+    pool.save(pool_info)?;
+
+    // Transfer the reward tokens into the vault (SetRewardMint rejects
+    // transfer-fee mints, so the vault receives exactly `amount`)
+    invoke(
+        &spl_token_2022::instruction::transfer_checked(
+            token_program_info.key,
+            depositor_token_info.key,
+            reward_mint_info.key,
+            reward_vault_info.key,
+            depositor_info.key,
+            &[],
+            amount,
+            mint_decimals(reward_mint_info)?,
+        )?,
+        &[
+            depositor_token_info.clone(),
+            reward_mint_info.clone(),
+            reward_vault_info.clone(),
+            depositor_info.clone(),
+        ],
+    )?;
+
+    msg!(
+        "Deposited {} reward tokens, total_staked: {}, reward_per_share: {}",
+        amount,
+        pool.total_staked,
+        reward_per_share
+    );
+    emit(&DepositEvent {
+        pool: *pool_info.key,
+        depositor: *depositor_info.key,
+        amount,
+        distributed: amount,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    Ok(())
+}
+
+/// Claim pending rewards of a token reward pool
+///
+/// Pays what is pending, or what the reward vault holds if less; the rest
+/// stays claimable. Also pays residual rewards kept after a full unstake.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` User stake account (PDA: ["stake", pool, user])
+/// 2. `[signer]` User/owner (writable if the stake account needs to grow)
+/// 3. `[writable]` Reward vault (PDA: ["reward_vault", pool])
+/// 4. `[writable]` Destination reward token account
+/// 5. `[]` Reward mint
+/// 6. `[]` Token program of the reward mint
+/// 7. `[]` System program (optional, required if the stake account needs to grow)
+pub fn process_claim_token_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let reward_vault_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let reward_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = account_info_iter
+        .next()
+        .filter(|info| *info.key == system_program::ID);
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    let mut pool = load_token_reward_pool(
+        program_id,
+        pool_info,
+        reward_vault_info,
+        reward_mint_info,
+        token_program_info,
+    )?;
+
+    // Check if pool needs rebasing
+    if pool.get_sum_stake_exp().needs_rebase() {
+        return Err(StakingError::PoolRequiresSync.into());
+    }
+
+    // Realloc legacy accounts to current size (payer = user)
+    UserStake::maybe_realloc(user_stake_info, user_info, system_program_info)?;
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Same two claim paths as ClaimRewards: residual rewards kept after a
+    // full unstake, or the snapshot-delta formula
+    let (pending, is_residual_claim) = if user_stake.amount == 0 {
+        (user_stake.reward_debt, true)
+    } else {
+        // Lazily adjust exp_start_factor if pool has been rebased
+        user_stake.sync_to_pool(&pool)?;
+        let user_weighted = user_stake.weighted_stake(&pool, current_time)?;
+        (user_stake.pending_rewards_wad(&pool, user_weighted)?, false)
+    };
+
+    let pending_tokens = pending / WAD;
+    if pending_tokens == 0 {
+        // Still a claim: record its time
+        msg!("No token rewards to claim");
+        user_stake.record_claim(current_time);
+        return user_stake.save(user_stake_info);
+    }
+
+    let vault_balance = {
+        let vault_data = reward_vault_info.try_borrow_data()?;
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&vault_data)?
+            .base
+            .amount
+    };
+    if vault_balance == 0 {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+    let transfer_amount = pending_tokens.min(vault_balance as u128) as u64;
+
+    let paid_wad = (transfer_amount as u128)
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    if is_residual_claim {
+        // Residual claim (amount==0): reward_debt IS the unclaimed amount
+        user_stake.reward_debt = user_stake.reward_debt.saturating_sub(paid_wad);
+        pool.total_residual_unpaid = pool.total_residual_unpaid.saturating_sub(transfer_amount);
+    } else {
+        // Track cumulative claimed amount (no snapshot reset)
+        user_stake.claimed_rewards_wad = user_stake
+            .claimed_rewards_wad
+            .checked_add(paid_wad)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+    user_stake.record_claim(current_time);
+    user_stake.total_rewards_claimed = user_stake.total_rewards_claimed.saturating_add(transfer_amount);
+
+    user_stake.save(user_stake_info)?;
+    pool.save(pool_info)?;
+
+    // Transfer the reward tokens out of the vault (pool PDA signs)
+    let pool_seeds = &[POOL_SEED, pool.mint.as_ref(), &[pool.bump]];
+    invoke_signed(
+        &spl_token_2022::instruction::transfer_checked(
+            token_program_info.key,
+            reward_vault_info.key,
+            reward_mint_info.key,
+            destination_info.key,
+            pool_info.key,
+            &[],
+            transfer_amount,
+            mint_decimals(reward_mint_info)?,
+        )?,
+        &[
+            reward_vault_info.clone(),
+            reward_mint_info.clone(),
+            destination_info.clone(),
+            pool_info.clone(),
+        ],
+        &[pool_seeds],
+    )?;
+
+    if is_residual_claim {
+        msg!("Claimed {} reward tokens in residual rewards", transfer_amount);
+    } else {
+        msg!("Claimed {} reward tokens", transfer_amount);
+    }
+
+    emit(&ClaimEvent {
+        pool: *pool_info.key,
+        user: *user_info.key,
+        recipient: *destination_info.key,
+        amount: transfer_amount,
+        protocol_fee: 0,
+        to_balance: false,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    Ok(())
+}
@@ -1,6 +1,12 @@
-//! Transfer authority instruction
+//! Transfer, propose, accept and renounce authority instructions
+//!
+//! TransferAuthority hands the pool over in one step, so a mistyped key
+//! loses it for good. ProposeAuthority / AcceptAuthority is the safe path:
+//! the proposed key has to sign AcceptAuthority before it takes over, and
+//! until then the current authority keeps full control and can replace or
+//! cancel the proposal.
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
@@ -10,11 +16,13 @@ use solana_program::{
 
 use crate::{
     error::StakingError,
+    events::{emit, AuthorityRenouncedEvent},
     state::StakingPool,
 };
 
 /// Transfer pool authority to a new address
 /// Setting new_authority to Pubkey::default() renounces authority (irreversible)
+/// Delegates appointed by the old authority are dropped
 ///
 /// Accounts:
 /// 0. `[writable]` Pool account
@@ -23,6 +31,30 @@ pub fn process_transfer_authority(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     new_authority: Pubkey,
+) -> ProgramResult {
+    set_authority(program_id, accounts, new_authority)
+}
+
+/// Renounce pool authority (irreversible)
+///
+/// Same as TransferAuthority to Pubkey::default(), without relying on the
+/// caller to pass the sentinel. Rejected with `AuthorityRenounced` if the
+/// pool has no authority left.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Current authority
+pub fn process_renounce_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    set_authority(program_id, accounts, Pubkey::default())
+}
+
+fn set_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -54,23 +86,234 @@ pub fn process_transfer_authority(
         return Err(StakingError::AuthorityRenounced.into());
     }
 
+    // Nobody could unpause a renounced pool
+    if new_authority == Pubkey::default() && pool.paused {
+        msg!("Unpause the pool before renouncing authority");
+        return Err(StakingError::PoolPaused.into());
+    }
+
     // Verify current authority
     if pool.authority != *authority_info.key {
         return Err(StakingError::InvalidAuthority.into());
     }
 
-    // Transfer authority
+    // Transfer authority; the new one appoints its own delegates, and a
+    // pending proposal is superseded
     pool.authority = new_authority;
+    pool.clear_delegates();
+    pool.pending_authority = Pubkey::default();
 
     // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    pool.save(pool_info)?;
 
-    if new_authority == Pubkey::default() {
-        msg!("Authority renounced (irreversible)");
+    if pool.is_authority_renounced() {
+        msg!(
+            "Authority renounced (irreversible): pool {} is now immutable",
+            pool_info.key
+        );
+        emit(&AuthorityRenouncedEvent {
+            pool: *pool_info.key,
+            previous_authority: *authority_info.key,
+        });
     } else {
         msg!("Authority transferred to {}", new_authority);
     }
 
     Ok(())
 }
+
+/// Propose `new_authority` as the next pool authority (current authority
+/// only); it takes over once it signs AcceptAuthority
+///
+/// A new proposal replaces any pending one. Proposing Pubkey::default()
+/// cancels the pending proposal, like CancelAuthorityProposal; it never
+/// renounces (see RenounceAuthority).
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Current authority (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify current authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    pool.pending_authority = new_authority;
+
+    // Grow legacy pools to hold the proposal (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    if new_authority == Pubkey::default() {
+        msg!("Pending authority transfer cancelled");
+    } else {
+        msg!("Authority transfer to {} proposed; waiting for it to accept", new_authority);
+    }
+
+    Ok(())
+}
+
+/// Become the pool authority, as proposed by ProposeAuthority (the proposed
+/// key signs)
+///
+/// Clears the proposal and, as TransferAuthority does, the old authority's
+/// delegates. Fails with `NoPendingAuthority` when nothing is pending.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Pending authority
+pub fn process_accept_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let new_authority_info = next_account_info(account_info_iter)?;
+
+    // Validate the pending authority is signer
+    if !new_authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    if pool.pending_authority == Pubkey::default() {
+        return Err(StakingError::NoPendingAuthority.into());
+    }
+    if pool.pending_authority != *new_authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Transfer authority; the new one appoints its own delegates
+    let previous_authority = pool.authority;
+    pool.authority = pool.pending_authority;
+    pool.pending_authority = Pubkey::default();
+    pool.clear_delegates();
+
+    pool.save(pool_info)?;
+
+    msg!(
+        "Authority transferred from {} to {}",
+        previous_authority,
+        pool.authority
+    );
+
+    Ok(())
+}
+
+/// Withdraw a pending authority proposal (current authority only), e.g.
+/// one made to the wrong address
+///
+/// The previously proposed key can no longer AcceptAuthority. Fails with
+/// `NoPendingAuthority` when nothing is pending.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Current authority
+pub fn process_cancel_authority_proposal(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify current authority
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    if pool.pending_authority == Pubkey::default() {
+        return Err(StakingError::NoPendingAuthority.into());
+    }
+    let cancelled = pool.pending_authority;
+    pool.pending_authority = Pubkey::default();
+
+    // A pending proposal means the pool already holds the field
+    pool.save(pool_info)?;
+
+    msg!("Authority transfer to {} cancelled", cancelled);
+
+    Ok(())
+}
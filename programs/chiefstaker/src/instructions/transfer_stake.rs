@@ -0,0 +1,229 @@
+//! Transfer stake instruction - hand a whole position to a new owner
+//!
+//! Unlike unstaking and re-staking, the position keeps its maturity
+//! (exp_start_factor, stake_time, last_stake_time), its stamped terms and
+//! any pending unstake request. Pending rewards are claimed to the current
+//! owner first; the reward snapshot then moves with the tokens, so neither
+//! owner can claim the same rewards twice.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    state::{PoolMetadata, StakingPool, UserStake, STAKE_SEED},
+};
+
+use super::claim::{settle_rewards, ClaimDestination};
+
+/// Move a whole stake to `to_owner`, claiming pending rewards to the
+/// current owner first.
+///
+/// The destination stake account is created if it doesn't exist; an existing
+/// one must be empty (see CloseStakeAccount), since positions of different
+/// maturity can't be merged. Anything the pool couldn't pay out in the
+/// claim stays with the position. The emptied source account remains open
+/// for CloseStakeAccount.
+///
+/// The destination is always the new owner's default stake account. An owner
+/// can name themselves to move a position above index 0 onto their own empty
+/// default account; the default account keeps its position counts and the
+/// source position stays open and counted until it is closed.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` Source stake account (PDA: ["stake", pool, from_owner], or
+///    ["stake", pool, from_owner, index (u16 LE)] for a position)
+/// 2. `[writable]` Destination stake account (PDA: ["stake", pool, to_owner])
+/// 3. `[writable, signer]` Current owner (receives rewards, pays rent)
+/// 4. `[]` New owner
+/// 5. `[]` System program
+///
+/// followed by the `[writable]` fee split recipients in pool order, if the
+/// pool has a split, and optionally the `[writable]` pool metadata account
+/// (matched by key) to count a newly created destination as a member.
+pub fn process_transfer_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let source_stake_info = next_account_info(account_info_iter)?;
+    let dest_stake_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let new_owner_info = next_account_info(account_info_iter)?;
+
+    // System program followed by the fee recipients, as settle_rewards expects
+    let trailing = account_info_iter.as_slice();
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate owner is signer
+    if !owner_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Verify destination stake PDA: the new owner's default stake account.
+    // For new_owner == owner that is the source when moving position 0
+    // (rejected below, the source is not empty) and the owner's own default
+    // account when moving a higher position
+    let (expected_dest, dest_bump) =
+        UserStake::derive_pda(pool_info.key, new_owner_info.key, program_id);
+    if *dest_stake_info.key != expected_dest {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check there is a position to move before paying anything out
+    if source_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let source = UserStake::try_from_slice(&source_stake_info.try_borrow_data()?)?;
+    if source.is_initialized() && source.amount == 0 {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+
+    // Claim to the current owner; validates the pool and the source stake
+    settle_rewards(
+        program_id,
+        pool_info,
+        source_stake_info,
+        owner_info,
+        None,
+        None,
+        trailing,
+        ClaimDestination::Wallet,
+        None,
+    )?;
+
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    let mut source = UserStake::try_from_slice(&source_stake_info.try_borrow_data()?)?;
+
+    // Lazily adjust exp_start_factor if pool has been rebased, so the moved
+    // position is calibrated to the current base_time
+    source.sync_to_pool(&pool)?;
+
+    let is_new_stake = dest_stake_info.data_is_empty();
+    let mut dest = if is_new_stake {
+        let rent = Rent::get()?;
+        let stake_rent = rent.minimum_balance(UserStake::LEN);
+        let stake_seeds = &[
+            STAKE_SEED,
+            pool_info.key.as_ref(),
+            new_owner_info.key.as_ref(),
+            &[dest_bump],
+        ];
+        invoke_signed(
+            &system_instruction::create_account(
+                owner_info.key,
+                dest_stake_info.key,
+                stake_rent,
+                UserStake::LEN as u64,
+                program_id,
+            ),
+            &[
+                owner_info.clone(),
+                dest_stake_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[stake_seeds],
+        )?;
+        UserStake::new(
+            *new_owner_info.key,
+            *pool_info.key,
+            0,
+            0,
+            0,
+            dest_bump,
+            0,
+        )
+    } else {
+        // Realloc a legacy destination to current size (payer = owner)
+        UserStake::maybe_realloc(dest_stake_info, owner_info, Some(system_program_info))?;
+
+        if dest_stake_info.owner != program_id {
+            return Err(StakingError::InvalidAccountOwner.into());
+        }
+        let existing = UserStake::try_from_slice(&dest_stake_info.try_borrow_data()?)?;
+        if !existing.is_initialized() {
+            return Err(StakingError::NotInitialized.into());
+        }
+        if existing.owner != *new_owner_info.key {
+            return Err(StakingError::InvalidOwner.into());
+        }
+        if existing.pool != *pool_info.key {
+            return Err(StakingError::InvalidPool.into());
+        }
+        if let Some(err) = existing.close_blocker() {
+            msg!("Destination stake account is in use; positions can't be merged");
+            return Err(err.into());
+        }
+        existing
+    };
+
+    // The position, its reward snapshot and its terms move as a whole, so
+    // pool totals (total_staked, sum_stake_exp, total_reward_debt) are unchanged
+    dest.amount = source.amount;
+    dest.stake_time = source.stake_time;
+    dest.exp_start_factor = source.exp_start_factor;
+    dest.base_time_snapshot = source.base_time_snapshot;
+    dest.last_stake_time = source.last_stake_time;
+    dest.top_up_lock_amounts = source.top_up_lock_amounts;
+    dest.top_up_lock_times = source.top_up_lock_times;
+    dest.reward_debt = source.reward_debt;
+    dest.claimed_rewards_wad = source.claimed_rewards_wad;
+    dest.acc_scale_shift = source.acc_scale_shift;
+    dest.unstake_request_amount = source.unstake_request_amount;
+    dest.unstake_request_time = source.unstake_request_time;
+    dest.last_claim_time = source.last_claim_time;
+    dest.grandfathered = source.grandfathered;
+    dest.terms_stamped = source.terms_stamped;
+    dest.lock_duration_seconds = source.lock_duration_seconds;
+    dest.lock_commit_seconds = source.lock_commit_seconds;
+    dest.unstake_cooldown_seconds = source.unstake_cooldown_seconds;
+    // The new owner's own earlier exit can't be reversed into this position
+    dest.last_unstake_completion_time = 0;
+    dest.last_unstake_completion_amount = 0;
+
+    let amount = source.amount;
+    source.amount = 0;
+    source.trim_top_up_locks();
+    source.reward_debt = 0;
+    source.claimed_rewards_wad = 0;
+    source.unstake_request_amount = 0;
+    source.unstake_request_time = 0;
+
+    source.save(source_stake_info)?;
+    dest.save(dest_stake_info)?;
+
+    // Optional metadata account: a newly created destination is a new member
+    if is_new_stake {
+        let (expected_metadata, _) = PoolMetadata::derive_pda(pool_info.key, program_id);
+        if let Some(metadata_info) = trailing
+            .iter()
+            .find(|info| *info.key == expected_metadata)
+        {
+            if metadata_info.owner == program_id && !metadata_info.data_is_empty() {
+                let mut metadata =
+                    PoolMetadata::try_from_slice(&metadata_info.try_borrow_data()?)?;
+                if metadata.is_initialized() && metadata.pool == *pool_info.key {
+                    metadata.member_count = metadata.member_count.saturating_add(1);
+                    let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+                    metadata.serialize(&mut &mut metadata_data[..])?;
+                }
+            }
+        }
+    }
+
+    msg!("Transferred stake of {} tokens to {}", amount, new_owner_info.key);
+
+    Ok(())
+}
@@ -1,6 +1,6 @@
 //! Unstake tokens instruction
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -14,16 +14,27 @@ use spl_token_2022::extension::StateWithExtensions;
 
 use crate::{
     error::StakingError,
-    events::{emit_reward_payout, RewardPayoutType},
-    math::{calculate_user_weighted_stake, wad_div, wad_mul, U256, WAD},
-    state::{is_valid_token_program, StakingPool, UserStake, POOL_SEED},
+    events::{emit, emit_reward_payout, RewardPayoutType, UnstakeEvent},
+    math::{wad_div, wad_mul, U256, WAD},
+    pfee::ASSOCIATED_TOKEN_PROGRAM_ID,
+    state::{
+        is_valid_token_program, StakingPool, UnstakeDestinationPolicy, UserStake, POOL_SEED,
+    },
 };
 
+use super::verify_vault_consistency::read_vault_amount;
+
 /// Shared unstake logic used by both process_unstake and process_complete_unstake.
 /// Handles: reward claiming, pool math updates (sum_stake_exp, total_staked),
 /// reward_debt recalculation, and token transfer.
 ///
-/// Assumes all account validation has been done by the caller.
+/// `penalty` tokens of `amount` (InstantUnstake's early exit penalty) stay in
+/// the vault as penalty_tokens; the rest is transferred to the user.
+///
+/// Assumes all account validation has been done by the caller. Fails with
+/// `VaultBalanceMismatch`, before touching any state, if the vault holds
+/// less than the payout, and with `StakeAccountingUnderflow` if the pool's
+/// total_staked is below `amount`.
 pub fn execute_unstake<'a>(
     _program_id: &Pubkey,
     pool: &mut StakingPool,
@@ -35,9 +46,14 @@ pub fn execute_unstake<'a>(
     mint_info: &AccountInfo<'a>,
     user_info: &AccountInfo<'a>,
     amount: u64,
+    penalty: u64,
     current_time: i64,
     system_program_info: Option<&AccountInfo<'a>>,
 ) -> ProgramResult {
+    // Withheld tokens leave total_staked (they earn nothing) but stay in the vault
+    let payout = amount.checked_sub(penalty).ok_or(StakingError::MathUnderflow)?;
+    check_vault_covers(pool, pool_info.key, token_vault_info, payout)?;
+    let new_total_staked = pool.total_staked_after_unstake(amount)?;
 
     // Capture old reward_debt for total_reward_debt bookkeeping
     let old_reward_debt = user_stake.reward_debt;
@@ -47,13 +63,7 @@ pub fn execute_unstake<'a>(
     // is not a CPI account)
     let mut reward_transfer_amount: u64 = 0;
 
-    let user_weighted = calculate_user_weighted_stake(
-        user_stake.amount,
-        user_stake.exp_start_factor,
-        current_time,
-        pool.base_time,
-        pool.tau_seconds,
-    )?;
+    let user_weighted = user_stake.weighted_stake(pool, current_time)?;
 
     // Track unpaid rewards (WAD-scaled) to carry forward in reward_debt
     let mut unpaid_rewards_wad: u128 = 0;
@@ -71,19 +81,30 @@ pub fn execute_unstake<'a>(
     };
 
     if user_weighted > 0 && delta_rps > 0 {
-        let full_entitlement = wad_mul(user_weighted, delta_rps)?;
+        let full_entitlement = pool.unscale_rewards(wad_mul(user_weighted, delta_rps)?)?;
         // Subtract already-claimed amount (frequency-independent)
         let pending = full_entitlement.saturating_sub(user_stake.claimed_rewards_wad);
 
         if pending > 0 {
             let pending_lamports = pending / WAD;
 
-            if pending_lamports > 0 {
+            if pending_lamports > 0 && pool.reward_mint().is_some() {
+                // Token rewards are only paid by ClaimTokenRewards. A full exit
+                // keeps them as residual; a partial one would lose them when the
+                // snapshot is reset below.
+                if amount < user_stake.amount {
+                    msg!("{} token reward units pending", pending_lamports);
+                    return Err(StakingError::UnclaimedTokenRewards.into());
+                }
+                unpaid_rewards_wad = pending;
+            } else if pending_lamports > 0 {
                 let pool_lamports = pool_info.lamports();
                 let rent_exempt_minimum = solana_program::rent::Rent::get()?
                     .minimum_balance(pool_info.data_len());
 
-                let available_rewards = pool_lamports.saturating_sub(rent_exempt_minimum);
+                let available_rewards = pool_lamports
+                    .saturating_sub(rent_exempt_minimum)
+                    .saturating_sub(pool.reserved_lamports());
                 reward_transfer_amount = pending_lamports.min(available_rewards as u128) as u64;
 
                 // Track unpaid portion so it remains claimable later
@@ -109,10 +130,11 @@ pub fn execute_unstake<'a>(
     //
     // For partial unstakes we scale the forfeiture to the unstaked fraction so
     // the remaining position keeps its future maturity benefit.
+    let mut forfeited_tokens: u64 = 0;
     if delta_rps > 0 {
-        let max_entitlement_wad = wad_mul(amount_wad, delta_rps)?;
+        let max_entitlement_wad = pool.unscale_rewards(wad_mul(amount_wad, delta_rps)?)?;
         let weighted_entitlement_wad = if user_weighted > 0 {
-            wad_mul(user_weighted, delta_rps)?
+            pool.unscale_rewards(wad_mul(user_weighted, delta_rps)?)?
         } else {
             0u128
         };
@@ -128,7 +150,10 @@ pub fn execute_unstake<'a>(
                 .ok_or(StakingError::MathOverflow)?
         };
         let forfeited_lamports = (forfeited_wad / WAD) as u64;
-        if forfeited_lamports > 0 {
+        if pool.reward_mint().is_some() {
+            // Distributed to the remaining stakers once total_staked is updated
+            forfeited_tokens = forfeited_lamports;
+        } else if forfeited_lamports > 0 {
             pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(forfeited_lamports);
             msg!("Redistributing {} lamports of forfeited immature rewards", forfeited_lamports);
         }
@@ -147,17 +172,20 @@ pub fn execute_unstake<'a>(
         .saturating_sub(U256::from_u128(unstake_contribution));
     pool.set_sum_stake_exp(new_sum);
 
-    // Update pool total staked
-    pool.total_staked = pool
-        .total_staked
-        .checked_sub(amount as u128)
-        .ok_or(StakingError::MathUnderflow)?;
+    // Update pool total staked (checked above)
+    pool.total_staked = new_total_staked;
+
+    pool.penalty_tokens = pool
+        .penalty_tokens
+        .checked_add(penalty)
+        .ok_or(StakingError::MathOverflow)?;
 
     // Update user stake
     user_stake.amount = user_stake
         .amount
         .checked_sub(amount)
         .ok_or(StakingError::MathUnderflow)?;
+    user_stake.trim_top_up_locks();
 
     // Recalculate reward debt for remaining stake
     if user_stake.amount > 0 {
@@ -197,23 +225,37 @@ pub fn execute_unstake<'a>(
             .ok_or(StakingError::MathOverflow)?;
     }
 
+    // Token reward pools have no unsynced balance for a later sync to pick
+    // up, so forfeited immature rewards go to the remaining stakers right away
+    // (after the snapshot reset above, so a remaining position shares in them)
+    if forfeited_tokens > 0 {
+        if pool.total_staked > 0 {
+            let forfeited_wad = (forfeited_tokens as u128)
+                .checked_mul(WAD)
+                .ok_or(StakingError::MathOverflow)?;
+            let total_staked_wad = pool
+                .total_staked
+                .checked_mul(WAD)
+                .ok_or(StakingError::MathOverflow)?;
+            pool.add_reward_per_share(wad_div(forfeited_wad, total_staked_wad)?)?;
+            msg!("Redistributing {} token units of forfeited immature rewards", forfeited_tokens);
+        } else {
+            msg!("{} token units of forfeited immature rewards stay in the reward vault", forfeited_tokens);
+        }
+    }
+
     // Increment cumulative rewards counter
     if reward_transfer_amount > 0 {
         user_stake.total_rewards_claimed = user_stake.total_rewards_claimed.saturating_add(reward_transfer_amount);
+        user_stake.record_claim(current_time);
     }
 
     // Realloc legacy accounts to current size (payer = user)
     UserStake::maybe_realloc(user_stake_info, user_info, system_program_info)?;
 
     // Save states (before CPI — pool data includes pre-updated last_synced_lamports)
-    {
-        let mut pool_data = pool_info.try_borrow_mut_data()?;
-        pool.serialize(&mut &mut pool_data[..])?;
-    }
-    {
-        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
-        user_stake.serialize(&mut &mut stake_data[..])?;
-    }
+    pool.save(pool_info)?;
+    user_stake.save(user_stake_info)?;
 
     // Transfer tokens from vault to user (CPI)
     let mint_data = mint_info.try_borrow_data()?;
@@ -231,7 +273,7 @@ pub fn execute_unstake<'a>(
             user_token_info.key,
             pool_info.key,
             &[],
-            amount,
+            payout,
             decimals,
         )?,
         &[
@@ -252,8 +294,83 @@ pub fn execute_unstake<'a>(
         emit_reward_payout(pool_info.key, user_info.key, reward_transfer_amount, RewardPayoutType::Unstake);
     }
 
-    msg!("Unstaked {} tokens", amount);
+    if penalty > 0 {
+        msg!("Unstaked {} tokens ({} withheld as early exit penalty)", amount, penalty);
+    } else {
+        msg!("Unstaked {} tokens", amount);
+    }
+    emit(&UnstakeEvent {
+        pool: *pool_info.key,
+        user: *user_info.key,
+        amount,
+        rewards_paid: reward_transfer_amount,
+        position_amount: user_stake.amount,
+        total_staked: pool.total_staked,
+        acc_reward_per_weighted_share: pool.acc_reward_per_weighted_share,
+        acc_scale_shift: pool.acc_scale_shift,
+    });
+
+    Ok(())
+}
 
+/// Fail with `VaultBalanceMismatch` unless the pool's vault actually holds
+/// `amount` tokens.
+///
+/// total_staked + penalty_tokens should always equal the vault balance; if
+/// they ever drift apart, refuse to pay out of other stakers' tokens (or to
+/// fail inside the token program) and name both numbers instead.
+pub(crate) fn check_vault_covers(
+    pool: &StakingPool,
+    pool_key: &Pubkey,
+    token_vault_info: &AccountInfo,
+    amount: u64,
+) -> Result<(), StakingError> {
+    let vault_amount = read_vault_amount(pool, pool_key, token_vault_info)?;
+    if vault_amount < amount {
+        msg!(
+            "Vault holds {} tokens, less than the {} being withdrawn (total_staked={}, penalty_tokens={})",
+            vault_amount,
+            amount,
+            pool.total_staked,
+            pool.penalty_tokens
+        );
+        return Err(StakingError::VaultBalanceMismatch);
+    }
+    Ok(())
+}
+
+/// Check the token account receiving unstaked tokens: it must hold the pool
+/// mint and belong to `owner`. Any such account works, not only the owner's
+/// associated token account, unless the pool's unstake destination policy is
+/// OwnerAtaOnly.
+pub(crate) fn validate_destination_token_account(
+    user_token_info: &AccountInfo,
+    pool: &StakingPool,
+    owner: &Pubkey,
+) -> ProgramResult {
+    if !is_valid_token_program(user_token_info.owner) {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let token_data = user_token_info.try_borrow_data()?;
+    let token_account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&token_data)?;
+    if token_account.base.mint != pool.mint {
+        msg!("Destination token account is not for the pool mint");
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+    if token_account.base.owner != *owner {
+        msg!("Destination token account is not owned by the stake owner");
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if pool.unstake_destination_policy == UnstakeDestinationPolicy::OwnerAtaOnly {
+        let (owner_ata, _) = Pubkey::find_program_address(
+            &[owner.as_ref(), user_token_info.owner.as_ref(), pool.mint.as_ref()],
+            &ASSOCIATED_TOKEN_PROGRAM_ID,
+        );
+        if *user_token_info.key != owner_ata {
+            msg!("Pool only allows unstaking to the owner's associated token account");
+            return Err(StakingError::DestinationNotAllowed.into());
+        }
+    }
     Ok(())
 }
 
@@ -263,7 +380,7 @@ pub fn execute_unstake<'a>(
 /// 0. `[writable]` Pool account
 /// 1. `[writable]` User stake account
 /// 2. `[writable]` Token vault
-/// 3. `[writable]` User token account
+/// 3. `[writable]` User token account (any account of the pool mint owned by the user)
 /// 4. `[]` Token mint
 /// 5. `[writable, signer]` User/owner
 /// 6. `[]` Token 2022 program
@@ -316,21 +433,22 @@ pub fn process_unstake(
         return Err(StakingError::PoolRequiresSync.into());
     }
 
-    // If pool has a cooldown, reject direct unstake
-    if pool.unstake_cooldown_seconds > 0 {
-        return Err(StakingError::CooldownRequired.into());
-    }
-
     // Verify mint matches pool
     if pool.mint != *mint_info.key {
         return Err(StakingError::InvalidPoolMint.into());
     }
 
+    // Only the pool's own token program (SPL Token or Token 2022)
+    pool.check_token_program(token_program_info.key, mint_info.owner)?;
+
     // Verify token vault
     if pool.token_vault != *token_vault_info.key {
         return Err(StakingError::InvalidTokenVault.into());
     }
 
+    // Verify destination token account
+    validate_destination_token_account(user_token_info, &pool, user_info.key)?;
+
     // Load and validate user stake
     if user_stake_info.owner != program_id {
         return Err(StakingError::InvalidAccountOwner.into());
@@ -349,12 +467,16 @@ pub fn process_unstake(
     }
 
     // Verify user stake PDA
-    let (expected_stake, _) =
-        UserStake::derive_pda(pool_info.key, user_info.key, program_id);
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
     if *user_stake_info.key != expected_stake {
         return Err(StakingError::InvalidPDA.into());
     }
 
+    // If this stake has a cooldown, reject direct unstake
+    if user_stake.effective_unstake_cooldown(&pool) > 0 {
+        return Err(StakingError::CooldownRequired.into());
+    }
+
     // Check sufficient balance
     if user_stake.amount < amount {
         return Err(StakingError::InsufficientStakeBalance.into());
@@ -371,14 +493,8 @@ pub fn process_unstake(
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
 
-    // Check lock duration
-    if pool.lock_duration_seconds > 0 {
-        let last_stake = user_stake.effective_last_stake_time();
-        let elapsed = current_time.saturating_sub(last_stake).max(0) as u64;
-        if elapsed < pool.lock_duration_seconds {
-            return Err(StakingError::StakeLocked.into());
-        }
-    }
+    // The unstaked tokens must be out of their lock
+    user_stake.check_unlocked(&pool, amount, current_time)?;
 
     // Optional trailing system program for legacy account reallocation
     let system_program_info = account_info_iter.next();
@@ -395,7 +511,132 @@ pub fn process_unstake(
         mint_info,
         user_info,
         amount,
+        0,
         current_time,
         system_program_info,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_pack::Pack;
+    use spl_token_2022::state::{Account, AccountState};
+
+    #[test]
+    fn test_vault_shortfall_blocks_withdrawal() {
+        let pool_key = Pubkey::new_unique();
+        let vault_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut pool = StakingPool::new(mint, vault_key, pool_key, Pubkey::new_unique(), 2592000, 0, 255);
+        let token_program = spl_token_2022::id();
+
+        // The books say 5_000 tokens are staked, the vault only holds 1_000
+        pool.total_staked = 5_000;
+        let account = Account {
+            mint,
+            owner: pool_key,
+            amount: 1_000,
+            state: AccountState::Initialized,
+            ..Account::default()
+        };
+        let mut data = vec![0u8; Account::LEN];
+        Account::pack(account, &mut data).unwrap();
+        let mut lamports = 0;
+        let vault = AccountInfo::new(&vault_key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+
+        assert_eq!(check_vault_covers(&pool, &pool_key, &vault, 1_000), Ok(()));
+        assert_eq!(
+            check_vault_covers(&pool, &pool_key, &vault, 1_001),
+            Err(StakingError::VaultBalanceMismatch)
+        );
+
+        // The guard runs before any bookkeeping changes
+        let mut user_stake = UserStake::new(Pubkey::new_unique(), pool_key, 5_000, 0, WAD, 255, 0);
+        let (user_key, stake_key, token_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let program_id = crate::id();
+        let (mut l1, mut l2, mut l3, mut l4, mut l5) = (0, 0, 0, 0, 0);
+        let (mut d1, mut d2, mut d3, mut d4) = (vec![], vec![], vec![], vec![]);
+        let pool_info = AccountInfo::new(&pool_key, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let stake_info = AccountInfo::new(&stake_key, false, true, &mut l2, &mut d2, &program_id, false, 0);
+        let token_info = AccountInfo::new(&token_key, false, true, &mut l3, &mut d3, &token_program, false, 0);
+        let mint_info = AccountInfo::new(&mint, false, false, &mut l4, &mut d4, &token_program, false, 0);
+        let mut user_data = vec![];
+        let user_info = AccountInfo::new(&user_key, true, true, &mut l5, &mut user_data, &program_id, false, 0);
+        let before = borsh::to_vec(&pool).unwrap();
+        let result = execute_unstake(
+            &program_id,
+            &mut pool,
+            &mut user_stake,
+            &pool_info,
+            &stake_info,
+            &vault,
+            &token_info,
+            &mint_info,
+            &user_info,
+            5_000,
+            0,
+            0,
+            None,
+        );
+        assert_eq!(result, Err(StakingError::VaultBalanceMismatch.into()));
+        assert_eq!(borsh::to_vec(&pool).unwrap(), before);
+        assert_eq!(user_stake.amount, 5_000);
+    }
+
+    #[test]
+    fn test_total_staked_underflow_blocks_unstake() {
+        let pool_key = Pubkey::new_unique();
+        let vault_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut pool = StakingPool::new(mint, vault_key, pool_key, Pubkey::new_unique(), 2592000, 0, 255);
+        let token_program = spl_token_2022::id();
+
+        // The vault covers the withdrawal, but the books are a token short
+        pool.total_staked = 4_999;
+        let account = Account {
+            mint,
+            owner: pool_key,
+            amount: 5_000,
+            state: AccountState::Initialized,
+            ..Account::default()
+        };
+        let mut data = vec![0u8; Account::LEN];
+        Account::pack(account, &mut data).unwrap();
+        let mut lamports = 0;
+        let vault = AccountInfo::new(&vault_key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+
+        let mut user_stake = UserStake::new(Pubkey::new_unique(), pool_key, 5_000, 0, WAD, 255, 0);
+        let (user_key, stake_key, token_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let program_id = crate::id();
+        let (mut l1, mut l2, mut l3, mut l4, mut l5) = (0, 0, 0, 0, 0);
+        let (mut d1, mut d2, mut d3, mut d4) = (vec![], vec![], vec![], vec![]);
+        let pool_info = AccountInfo::new(&pool_key, false, true, &mut l1, &mut d1, &program_id, false, 0);
+        let stake_info = AccountInfo::new(&stake_key, false, true, &mut l2, &mut d2, &program_id, false, 0);
+        let token_info = AccountInfo::new(&token_key, false, true, &mut l3, &mut d3, &token_program, false, 0);
+        let mint_info = AccountInfo::new(&mint, false, false, &mut l4, &mut d4, &token_program, false, 0);
+        let mut user_data = vec![];
+        let user_info = AccountInfo::new(&user_key, true, true, &mut l5, &mut user_data, &program_id, false, 0);
+        let before = borsh::to_vec(&pool).unwrap();
+        let result = execute_unstake(
+            &program_id,
+            &mut pool,
+            &mut user_stake,
+            &pool_info,
+            &stake_info,
+            &vault,
+            &token_info,
+            &mint_info,
+            &user_info,
+            5_000,
+            0,
+            0,
+            None,
+        );
+
+        // Rejected before any bookkeeping changes, instead of wrapping
+        assert_eq!(result, Err(StakingError::StakeAccountingUnderflow.into()));
+        assert_eq!(borsh::to_vec(&pool).unwrap(), before);
+        assert_eq!(user_stake.amount, 5_000);
+    }
+}
@@ -1,20 +1,29 @@
 //! Update pool settings instruction
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint::ProgramResult,
     msg,
     pubkey::Pubkey,
+    sysvar::Sysvar,
 };
 
 use crate::{
     error::StakingError,
-    state::StakingPool,
+    math::BPS_DENOMINATOR,
+    state::{
+        StakingPool, DELEGATE_PERM_SETTINGS, FEE_SPLIT_SLOTS, PENDING_CLAIM_FEE,
+        PENDING_EARLY_EXIT_PENALTY, PENDING_FEE_SPLIT, PENDING_LOCK_BOOST, PENDING_LOCK_DURATION,
+        PENDING_LOYALTY_BONUS, PENDING_MIN_DISTRIBUTABLE, PENDING_SETTINGS_TIMELOCK,
+        PENDING_UNSTAKE_COOLDOWN,
+    },
+    InitialPoolSettings,
 };
 
 /// Maximum lock duration: 365 days. Prevents authority from trapping stakers indefinitely.
-const MAX_LOCK_DURATION_SECONDS: u64 = 365 * 24 * 60 * 60;
+pub(crate) const MAX_LOCK_DURATION_SECONDS: u64 = 365 * 24 * 60 * 60;
 
 /// Maximum unstake cooldown: 30 days.
 const MAX_UNSTAKE_COOLDOWN_SECONDS: u64 = 30 * 24 * 60 * 60;
@@ -24,17 +33,206 @@ const MAX_UNSTAKE_COOLDOWN_SECONDS: u64 = 30 * 24 * 60 * 60;
 /// (10^15 = 1M tokens at 9 decimals, generous for any realistic mint.)
 const MAX_MIN_STAKE_AMOUNT: u64 = 1_000_000_000_000_000;
 
-/// Update pool settings (authority only)
+/// Maximum loyalty bonus: 20% extra weight (still capped at max weight).
+const MAX_LOYALTY_BONUS_BPS: u64 = 2_000;
+
+/// Maximum unstake reversal window: 1 hour. It's a safety net for
+/// accidental exits, not a second cooldown.
+const MAX_UNSTAKE_REVERSAL_WINDOW_SECONDS: u64 = 60 * 60;
+
+/// Maximum total fee on reward claims (protocol fee plus fee splits): 10%.
+pub(crate) const MAX_CLAIM_FEE_BPS: u64 = 1_000;
+
+/// Maximum early exit penalty: the whole exit (InstantUnstake then returns
+/// nothing).
+const MAX_EARLY_EXIT_PENALTY_BPS: u64 = BPS_DENOMINATOR;
+
+/// Maximum lock boost: double weight for a full-year lock commitment (still
+/// capped at max weight).
+const MAX_LOCK_BOOST_BPS: u64 = BPS_DENOMINATOR;
+
+/// Maximum settings timelock: 30 days.
+const MAX_SETTINGS_TIMELOCK_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+/// Requested setting changes; `None` leaves a setting unchanged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolSettingsUpdate {
+    pub min_stake_amount: Option<u64>,
+    pub lock_duration_seconds: Option<u64>,
+    pub unstake_cooldown_seconds: Option<u64>,
+    pub max_deferred_lamports: Option<u64>,
+    pub loyalty_bonus_bps: Option<u64>,
+    pub exclude_depositor: Option<bool>,
+    pub per_stake_terms: Option<bool>,
+    pub claim_fee_bps: Option<u64>,
+    pub unstake_reversal_window_seconds: Option<u64>,
+    pub min_distributable_per_event: Option<u64>,
+    pub max_total_staked: Option<u64>,
+    pub min_reward_deposit: Option<u64>,
+    pub early_exit_penalty_bps: Option<u64>,
+    pub lock_boost_bps: Option<u64>,
+    pub require_allowlisted_depositors: Option<bool>,
+    pub settings_timelock_seconds: Option<u64>,
+    /// Fee split recipients and shares (set by SetRewardFeeSplit)
+    pub fee_split: Option<([Pubkey; FEE_SPLIT_SLOTS], [u16; FEE_SPLIT_SLOTS])>,
+}
+
+impl From<InitialPoolSettings> for PoolSettingsUpdate {
+    fn from(settings: InitialPoolSettings) -> Self {
+        Self {
+            min_stake_amount: Some(settings.min_stake_amount),
+            lock_duration_seconds: Some(settings.lock_duration_seconds),
+            unstake_cooldown_seconds: Some(settings.unstake_cooldown_seconds),
+            ..Self::default()
+        }
+    }
+}
+
+impl PoolSettingsUpdate {
+    /// Split into the changes that apply at once and the ones that wait for
+    /// the pool's settings timelock
+    ///
+    /// Changes that make leaving slower or costlier, or that cut what
+    /// stakers already in earn, wait: raising the lock duration, unstake
+    /// cooldown, claim fee, early exit penalty, min_distributable_per_event
+    /// or the fee split's total share, any change to the loyalty bonus or
+    /// lock boost (either way it moves rewards between stakers who can't all
+    /// leave at once), and cutting the timelock itself. Everything else,
+    /// including lowering any of those, only helps or doesn't concern stakers
+    /// already in, and applies at once. Without a timelock nothing waits.
+    pub fn split_timelocked(&self, pool: &StakingPool) -> (Self, Self) {
+        let mut immediate = self.clone();
+        let mut delayed = Self::default();
+        if pool.settings_timelock_seconds == 0 {
+            return (immediate, delayed);
+        }
+        if immediate.lock_duration_seconds.is_some_and(|v| v > pool.lock_duration_seconds) {
+            delayed.lock_duration_seconds = immediate.lock_duration_seconds.take();
+        }
+        if immediate.unstake_cooldown_seconds.is_some_and(|v| v > pool.unstake_cooldown_seconds) {
+            delayed.unstake_cooldown_seconds = immediate.unstake_cooldown_seconds.take();
+        }
+        if immediate.claim_fee_bps.is_some_and(|v| v > pool.claim_fee_bps) {
+            delayed.claim_fee_bps = immediate.claim_fee_bps.take();
+        }
+        if immediate.early_exit_penalty_bps.is_some_and(|v| v > pool.early_exit_penalty_bps) {
+            delayed.early_exit_penalty_bps = immediate.early_exit_penalty_bps.take();
+        }
+        if immediate
+            .settings_timelock_seconds
+            .is_some_and(|v| v < pool.settings_timelock_seconds)
+        {
+            delayed.settings_timelock_seconds = immediate.settings_timelock_seconds.take();
+        }
+        if immediate.loyalty_bonus_bps.is_some_and(|v| v != pool.loyalty_bonus_bps) {
+            delayed.loyalty_bonus_bps = immediate.loyalty_bonus_bps.take();
+        }
+        if immediate.lock_boost_bps.is_some_and(|v| v != pool.lock_boost_bps) {
+            delayed.lock_boost_bps = immediate.lock_boost_bps.take();
+        }
+        if immediate
+            .min_distributable_per_event
+            .is_some_and(|v| v > pool.min_distributable_per_event)
+        {
+            delayed.min_distributable_per_event = immediate.min_distributable_per_event.take();
+        }
+        if immediate
+            .fee_split
+            .is_some_and(|(_, shares)| total_bps(&shares) > pool.fee_split_total_bps())
+        {
+            delayed.fee_split = immediate.fee_split.take();
+        }
+        (immediate, delayed)
+    }
+
+    /// PENDING_* bits of the timelockable settings this update sets
+    pub fn pending_mask(&self) -> u16 {
+        let mut mask = 0;
+        if self.lock_duration_seconds.is_some() {
+            mask |= PENDING_LOCK_DURATION;
+        }
+        if self.unstake_cooldown_seconds.is_some() {
+            mask |= PENDING_UNSTAKE_COOLDOWN;
+        }
+        if self.claim_fee_bps.is_some() {
+            mask |= PENDING_CLAIM_FEE;
+        }
+        if self.early_exit_penalty_bps.is_some() {
+            mask |= PENDING_EARLY_EXIT_PENALTY;
+        }
+        if self.settings_timelock_seconds.is_some() {
+            mask |= PENDING_SETTINGS_TIMELOCK;
+        }
+        if self.loyalty_bonus_bps.is_some() {
+            mask |= PENDING_LOYALTY_BONUS;
+        }
+        if self.lock_boost_bps.is_some() {
+            mask |= PENDING_LOCK_BOOST;
+        }
+        if self.min_distributable_per_event.is_some() {
+            mask |= PENDING_MIN_DISTRIBUTABLE;
+        }
+        if self.fee_split.is_some() {
+            mask |= PENDING_FEE_SPLIT;
+        }
+        mask
+    }
+
+    /// The pool's pending (timelocked) change
+    pub fn pending(pool: &StakingPool) -> Self {
+        let mask = pool.pending_settings_mask;
+        let pick = |bit: u16, val: u64| (mask & bit != 0).then_some(val);
+        Self {
+            lock_duration_seconds: pick(PENDING_LOCK_DURATION, pool.pending_lock_duration_seconds),
+            unstake_cooldown_seconds: pick(
+                PENDING_UNSTAKE_COOLDOWN,
+                pool.pending_unstake_cooldown_seconds,
+            ),
+            claim_fee_bps: pick(PENDING_CLAIM_FEE, pool.pending_claim_fee_bps),
+            early_exit_penalty_bps: pick(
+                PENDING_EARLY_EXIT_PENALTY,
+                pool.pending_early_exit_penalty_bps,
+            ),
+            settings_timelock_seconds: pick(
+                PENDING_SETTINGS_TIMELOCK,
+                pool.pending_settings_timelock_seconds,
+            ),
+            loyalty_bonus_bps: pick(PENDING_LOYALTY_BONUS, pool.pending_loyalty_bonus_bps),
+            lock_boost_bps: pick(PENDING_LOCK_BOOST, pool.pending_lock_boost_bps),
+            min_distributable_per_event: pick(
+                PENDING_MIN_DISTRIBUTABLE,
+                pool.pending_min_distributable_per_event,
+            ),
+            fee_split: (mask & PENDING_FEE_SPLIT != 0)
+                .then_some((pool.pending_fee_split_recipients, pool.pending_fee_split_bps)),
+            ..Self::default()
+        }
+    }
+}
+
+/// Sum of fee split shares (bps)
+fn total_bps(shares: &[u16; FEE_SPLIT_SLOTS]) -> u64 {
+    shares.iter().map(|bps| *bps as u64).sum()
+}
+
+/// Update pool settings (authority or settings delegate)
+///
+/// With a settings timelock, changes that make leaving slower or costlier,
+/// or that cut what stakers already in earn (see
+/// `PoolSettingsUpdate::split_timelocked`), are only recorded as pending
+/// and applied by ApplySettings once the timelock has run; the rest apply
+/// now. A newer value for a setting replaces its pending one, so proposing
+/// the current value cancels a pending change, and queueing a change
+/// restarts the timer of everything pending.
 ///
 /// Accounts:
 /// 0. `[writable]` Pool account
-/// 1. `[signer]` Authority
+/// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
 pub fn process_update_pool_settings(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    min_stake_amount: Option<u64>,
-    lock_duration_seconds: Option<u64>,
-    unstake_cooldown_seconds: Option<u64>,
+    update: PoolSettingsUpdate,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -66,38 +264,363 @@ pub fn process_update_pool_settings(
         return Err(StakingError::AuthorityRenounced.into());
     }
 
-    // Verify authority
-    if pool.authority != *authority_info.key {
+    // Verify authority (or a settings delegate)
+    if !pool.is_authorized(authority_info.key, DELEGATE_PERM_SETTINGS) {
         return Err(StakingError::InvalidAuthority.into());
     }
 
-    // Apply settings (with caps to prevent authority abuse)
-    if let Some(val) = min_stake_amount {
-        if val > MAX_MIN_STAKE_AMOUNT {
-            return Err(StakingError::SettingExceedsMaximum.into());
-        }
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    apply_or_queue_settings_update(&mut pool, &update)?;
+
+    // Grow legacy pools if a newer setting was changed (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+
+    // Save pool state
+    pool.save(pool_info)?;
+
+    msg!("Pool settings updated");
+    Ok(())
+}
+
+/// Apply the part of `update` that takes effect now and queue the rest
+/// behind the pool's settings timelock (see
+/// `PoolSettingsUpdate::split_timelocked`). A newer value for a setting
+/// replaces its pending one. Shared by UpdatePoolSettings and
+/// SetRewardFeeSplit.
+pub(crate) fn apply_or_queue_settings_update(
+    pool: &mut StakingPool,
+    update: &PoolSettingsUpdate,
+) -> ProgramResult {
+    let (immediate, delayed) = update.split_timelocked(pool);
+    apply_settings_update(pool, &immediate)?;
+    pool.clear_pending_settings(update.pending_mask());
+    if delayed != PoolSettingsUpdate::default() {
+        let current_time = Clock::get()?.unix_timestamp;
+        queue_settings_update(pool, &delayed, current_time)?;
+    }
+    Ok(())
+}
+
+/// Check `update` against the caps that keep an authority from abusing
+/// stakers (`SettingExceedsMaximum` otherwise)
+///
+/// The claim fee and fee split together stay within MAX_CLAIM_FEE_BPS both
+/// with `update` applied now and once everything pending has applied too,
+/// so queueing, applying now and ApplySettings all check the same limit.
+pub(crate) fn check_settings_update(pool: &StakingPool, update: &PoolSettingsUpdate) -> ProgramResult {
+    let exceeds = |val: Option<u64>, max: u64| val.is_some_and(|v| v > max);
+
+    let pending = PoolSettingsUpdate::pending(pool);
+    let fee_split_bps = |split: Option<([Pubkey; FEE_SPLIT_SLOTS], [u16; FEE_SPLIT_SLOTS])>| {
+        split.map_or(pool.fee_split_total_bps(), |(_, shares)| total_bps(&shares))
+    };
+    let claim_fee_now = update.claim_fee_bps.unwrap_or(pool.claim_fee_bps);
+    let claim_fee_later = update
+        .claim_fee_bps
+        .or(pending.claim_fee_bps)
+        .unwrap_or(pool.claim_fee_bps);
+    let total_fee_now = claim_fee_now.saturating_add(fee_split_bps(update.fee_split));
+    let total_fee_later =
+        claim_fee_later.saturating_add(fee_split_bps(update.fee_split.or(pending.fee_split)));
+
+    if exceeds(update.min_stake_amount, MAX_MIN_STAKE_AMOUNT)
+        || exceeds(update.lock_duration_seconds, MAX_LOCK_DURATION_SECONDS)
+        || exceeds(update.unstake_cooldown_seconds, MAX_UNSTAKE_COOLDOWN_SECONDS)
+        || exceeds(update.loyalty_bonus_bps, MAX_LOYALTY_BONUS_BPS)
+        || exceeds(Some(total_fee_now.max(total_fee_later)), MAX_CLAIM_FEE_BPS)
+        || exceeds(update.unstake_reversal_window_seconds, MAX_UNSTAKE_REVERSAL_WINDOW_SECONDS)
+        || exceeds(update.early_exit_penalty_bps, MAX_EARLY_EXIT_PENALTY_BPS)
+        || exceeds(update.lock_boost_bps, MAX_LOCK_BOOST_BPS)
+        || exceeds(update.settings_timelock_seconds, MAX_SETTINGS_TIMELOCK_SECONDS)
+    {
+        return Err(StakingError::SettingExceedsMaximum.into());
+    }
+    Ok(())
+}
+
+/// Validate and apply `update` to `pool` (see `check_settings_update`).
+/// Shared by UpdatePoolSettings, SetRewardFeeSplit, ApplySettings and
+/// InitializePool's initial settings.
+pub(crate) fn apply_settings_update(
+    pool: &mut StakingPool,
+    update: &PoolSettingsUpdate,
+) -> ProgramResult {
+    check_settings_update(pool, update)?;
+
+    if let Some(val) = update.min_stake_amount {
         pool.min_stake_amount = val;
         msg!("Updated min_stake_amount to {}", val);
     }
-    if let Some(val) = lock_duration_seconds {
-        if val > MAX_LOCK_DURATION_SECONDS {
-            return Err(StakingError::SettingExceedsMaximum.into());
-        }
+    if let Some(val) = update.lock_duration_seconds {
         pool.lock_duration_seconds = val;
         msg!("Updated lock_duration_seconds to {}", val);
     }
-    if let Some(val) = unstake_cooldown_seconds {
-        if val > MAX_UNSTAKE_COOLDOWN_SECONDS {
-            return Err(StakingError::SettingExceedsMaximum.into());
-        }
+    if let Some(val) = update.unstake_cooldown_seconds {
         pool.unstake_cooldown_seconds = val;
         msg!("Updated unstake_cooldown_seconds to {}", val);
     }
+    if let Some(val) = update.max_deferred_lamports {
+        pool.max_deferred_lamports = val;
+        msg!("Updated max_deferred_lamports to {}", val);
+    }
+    if let Some(val) = update.loyalty_bonus_bps {
+        pool.loyalty_bonus_bps = val;
+        msg!("Updated loyalty_bonus_bps to {}", val);
+    }
+    if let Some(val) = update.exclude_depositor {
+        pool.exclude_depositor = val;
+        msg!("Updated exclude_depositor to {}", val);
+    }
+    if let Some(val) = update.per_stake_terms {
+        pool.per_stake_terms = val;
+        msg!("Updated per_stake_terms to {}", val);
+    }
+    if let Some(val) = update.claim_fee_bps {
+        pool.claim_fee_bps = val;
+        msg!("Updated claim_fee_bps to {}", val);
+    }
+    if let Some(val) = update.unstake_reversal_window_seconds {
+        pool.unstake_reversal_window_seconds = val;
+        msg!("Updated unstake_reversal_window_seconds to {}", val);
+    }
+    if let Some(val) = update.min_distributable_per_event {
+        pool.min_distributable_per_event = val;
+        msg!("Updated min_distributable_per_event to {}", val);
+    }
+    if let Some(val) = update.max_total_staked {
+        // May be below total_staked: existing stakes stay, new ones are blocked
+        pool.max_total_staked = val;
+        msg!("Updated max_total_staked to {}", val);
+    }
+    if let Some(val) = update.min_reward_deposit {
+        pool.min_reward_deposit = val;
+        msg!("Updated min_reward_deposit to {}", val);
+    }
+    if let Some(val) = update.early_exit_penalty_bps {
+        pool.early_exit_penalty_bps = val;
+        msg!("Updated early_exit_penalty_bps to {}", val);
+    }
+    if let Some(val) = update.lock_boost_bps {
+        pool.lock_boost_bps = val;
+        msg!("Updated lock_boost_bps to {}", val);
+    }
+    if let Some(val) = update.require_allowlisted_depositors {
+        pool.require_allowlisted_depositors = val;
+        msg!("Updated require_allowlisted_depositors to {}", val);
+    }
+    if let Some(val) = update.settings_timelock_seconds {
+        pool.settings_timelock_seconds = val;
+        msg!("Updated settings_timelock_seconds to {}", val);
+    }
+    if let Some((recipients, shares)) = update.fee_split {
+        pool.set_fee_split(recipients, shares);
+        msg!("Updated reward fee split ({} bps total)", total_bps(&shares));
+    }
 
-    // Save pool state
-    let mut pool_data = pool_info.try_borrow_mut_data()?;
-    pool.serialize(&mut &mut pool_data[..])?;
+    Ok(())
+}
 
-    msg!("Pool settings updated");
+/// Record `delayed` as pending until the pool's timelock has run from
+/// `current_time`, merged into anything already pending (whose timer
+/// restarts). Caps are checked now so a queued change can't be refused
+/// only once it is due.
+pub(crate) fn queue_settings_update(
+    pool: &mut StakingPool,
+    delayed: &PoolSettingsUpdate,
+    current_time: i64,
+) -> ProgramResult {
+    check_settings_update(pool, delayed)?;
+
+    if let Some(val) = delayed.lock_duration_seconds {
+        pool.pending_lock_duration_seconds = val;
+    }
+    if let Some(val) = delayed.unstake_cooldown_seconds {
+        pool.pending_unstake_cooldown_seconds = val;
+    }
+    if let Some(val) = delayed.claim_fee_bps {
+        pool.pending_claim_fee_bps = val;
+    }
+    if let Some(val) = delayed.early_exit_penalty_bps {
+        pool.pending_early_exit_penalty_bps = val;
+    }
+    if let Some(val) = delayed.settings_timelock_seconds {
+        pool.pending_settings_timelock_seconds = val;
+    }
+    if let Some(val) = delayed.loyalty_bonus_bps {
+        pool.pending_loyalty_bonus_bps = val;
+    }
+    if let Some(val) = delayed.lock_boost_bps {
+        pool.pending_lock_boost_bps = val;
+    }
+    if let Some(val) = delayed.min_distributable_per_event {
+        pool.pending_min_distributable_per_event = val;
+    }
+    if let Some((recipients, shares)) = delayed.fee_split {
+        pool.pending_fee_split_recipients = recipients;
+        pool.pending_fee_split_bps = shares;
+    }
+    pool.pending_settings_mask |= delayed.pending_mask();
+    pool.pending_settings_effective_time =
+        current_time.saturating_add(pool.settings_timelock_seconds as i64);
+
+    msg!(
+        "Settings change {:#06x} pending until {}",
+        pool.pending_settings_mask,
+        pool.pending_settings_effective_time
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_error::ProgramError;
+
+    #[test]
+    fn test_settings_timelock() {
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        pool.lock_duration_seconds = 3_600;
+        pool.unstake_cooldown_seconds = 3_600;
+
+        let update = PoolSettingsUpdate {
+            lock_duration_seconds: Some(7_200),
+            unstake_cooldown_seconds: Some(600),
+            claim_fee_bps: Some(100),
+            min_stake_amount: Some(5),
+            ..PoolSettingsUpdate::default()
+        };
+
+        // Without a timelock everything applies at once
+        let (immediate, delayed) = update.split_timelocked(&pool);
+        assert_eq!(immediate, update);
+        assert_eq!(delayed, PoolSettingsUpdate::default());
+
+        // With one, raises wait and cuts (and unrelated settings) don't
+        pool.settings_timelock_seconds = 86_400;
+        let (immediate, delayed) = update.split_timelocked(&pool);
+        assert_eq!(immediate.lock_duration_seconds, None);
+        assert_eq!(immediate.unstake_cooldown_seconds, Some(600));
+        assert_eq!(immediate.min_stake_amount, Some(5));
+        assert_eq!(delayed.lock_duration_seconds, Some(7_200));
+        assert_eq!(delayed.claim_fee_bps, Some(100));
+        assert_eq!(delayed.pending_mask(), PENDING_LOCK_DURATION | PENDING_CLAIM_FEE);
+
+        apply_settings_update(&mut pool, &immediate).unwrap();
+        queue_settings_update(&mut pool, &delayed, 1_000).unwrap();
+        assert_eq!(pool.lock_duration_seconds, 3_600);
+        assert_eq!(pool.unstake_cooldown_seconds, 600);
+        assert_eq!(pool.pending_settings_effective_time, 87_400);
+        assert_eq!(PoolSettingsUpdate::pending(&pool), delayed);
+
+        // Shortening the timelock waits too; queueing restarts the timer
+        let cut = PoolSettingsUpdate { settings_timelock_seconds: Some(0), ..PoolSettingsUpdate::default() };
+        let (immediate, delayed) = cut.split_timelocked(&pool);
+        assert_eq!(immediate, PoolSettingsUpdate::default());
+        queue_settings_update(&mut pool, &delayed, 2_000).unwrap();
+        assert_eq!(pool.pending_settings_effective_time, 88_400);
+        assert_eq!(pool.settings_timelock_seconds, 86_400);
+
+        // Proposing the current value cancels that setting's pending change
+        let keep = PoolSettingsUpdate { lock_duration_seconds: Some(3_600), ..PoolSettingsUpdate::default() };
+        let (immediate, _) = keep.split_timelocked(&pool);
+        assert_eq!(immediate, keep);
+        pool.clear_pending_settings(keep.pending_mask());
+        assert_eq!(pool.pending_settings_mask, PENDING_CLAIM_FEE | PENDING_SETTINGS_TIMELOCK);
+        assert_eq!(pool.pending_lock_duration_seconds, 0);
+
+        // Caps are checked when queueing, not only when applying
+        let too_long = PoolSettingsUpdate { unstake_cooldown_seconds: Some(MAX_UNSTAKE_COOLDOWN_SECONDS + 1), ..PoolSettingsUpdate::default() };
+        let (_, delayed) = too_long.split_timelocked(&pool);
+        let exceeds: ProgramError = StakingError::SettingExceedsMaximum.into();
+        assert_eq!(queue_settings_update(&mut pool, &delayed, 3_000), Err(exceeds));
+
+        // Applying everything pending clears it
+        let pending = PoolSettingsUpdate::pending(&pool);
+        pool.clear_pending_settings(u16::MAX);
+        apply_settings_update(&mut pool, &pending).unwrap();
+        assert_eq!(pool.claim_fee_bps, 100);
+        assert_eq!(pool.settings_timelock_seconds, 0);
+        assert_eq!(pool.pending_settings_mask, 0);
+        assert_eq!(pool.pending_settings_effective_time, 0);
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+
+        // Pools from before the timelock existed (884 bytes) have none
+        let legacy = StakingPool::try_from_slice(&serialized[..884]).unwrap();
+        assert_eq!(legacy.settings_timelock_seconds, 0);
+        assert_eq!(legacy.pending_settings_mask, 0);
+    }
+
+    #[test]
+    fn test_settings_timelock_rewards_and_fees() {
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        pool.settings_timelock_seconds = 86_400;
+        pool.loyalty_bonus_bps = 1_000;
+        pool.lock_boost_bps = 1_000;
+        pool.min_distributable_per_event = 500;
+        pool.claim_fee_bps = 300;
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        pool.set_fee_split([a, Pubkey::default(), Pubkey::default()], [200, 0, 0]);
+
+        // Any reweighting and a higher minimum distribution wait
+        let update = PoolSettingsUpdate {
+            loyalty_bonus_bps: Some(500),
+            lock_boost_bps: Some(2_000),
+            min_distributable_per_event: Some(1_000),
+            ..PoolSettingsUpdate::default()
+        };
+        let (immediate, delayed) = update.split_timelocked(&pool);
+        assert_eq!(immediate, PoolSettingsUpdate::default());
+        assert_eq!(delayed, update);
+        assert_eq!(
+            delayed.pending_mask(),
+            PENDING_LOYALTY_BONUS | PENDING_LOCK_BOOST | PENDING_MIN_DISTRIBUTABLE
+        );
+        let lower = PoolSettingsUpdate { min_distributable_per_event: Some(0), ..PoolSettingsUpdate::default() };
+        assert_eq!(lower.split_timelocked(&pool).0, lower);
+
+        // A fee split with a higher total waits; the same total doesn't
+        let raise = PoolSettingsUpdate {
+            fee_split: Some(([a, b, Pubkey::default()], [200, 300, 0])),
+            ..PoolSettingsUpdate::default()
+        };
+        let (_, delayed) = raise.split_timelocked(&pool);
+        assert_eq!(delayed, raise);
+        queue_settings_update(&mut pool, &delayed, 1_000).unwrap();
+        assert_eq!(pool.fee_split_total_bps(), 200);
+        assert_eq!(PoolSettingsUpdate::pending(&pool), raise);
+        let swap = PoolSettingsUpdate {
+            fee_split: Some(([b, Pubkey::default(), Pubkey::default()], [200, 0, 0])),
+            ..PoolSettingsUpdate::default()
+        };
+        assert_eq!(swap.split_timelocked(&pool).0, swap);
+
+        // Claim fee plus fee split is capped against what is pending too,
+        // when queueing and when applying
+        let exceeds: ProgramError = StakingError::SettingExceedsMaximum.into();
+        let fee = PoolSettingsUpdate { claim_fee_bps: Some(600), ..PoolSettingsUpdate::default() };
+        let (_, delayed) = fee.split_timelocked(&pool);
+        assert_eq!(queue_settings_update(&mut pool, &delayed, 2_000), Err(exceeds.clone()));
+        let fee = PoolSettingsUpdate { claim_fee_bps: Some(500), ..PoolSettingsUpdate::default() };
+        let (_, delayed) = fee.split_timelocked(&pool);
+        queue_settings_update(&mut pool, &delayed, 2_000).unwrap();
+
+        // The pool's current split and fee still count as well
+        pool.clear_pending_settings(PENDING_CLAIM_FEE);
+        pool.claim_fee_bps = 600;
+        let pending = PoolSettingsUpdate::pending(&pool);
+        pool.clear_pending_settings(u16::MAX);
+        assert_eq!(apply_settings_update(&mut pool, &pending), Err(exceeds));
+
+        pool.claim_fee_bps = 500;
+        apply_settings_update(&mut pool, &pending).unwrap();
+        assert_eq!(pool.fee_split_recipients, [a, b, Pubkey::default()]);
+        assert_eq!(pool.fee_split_total_bps(), 500);
+    }
+}
@@ -0,0 +1,190 @@
+//! Update tau instruction (authority only)
+//!
+//! What happens to weight maturation in flight: a stake's weight is
+//! amount * (1 - r), where r = e^(-age / tau) is its remaining immaturity.
+//! The pool is rebased at the moment of the change, so every r up to then is
+//! computed with the old tau, and from then on r decays with the new one:
+//!
+//! - no stake's weight moves at the change itself (no jump, no reset)
+//! - a stake that was 50% mature stays 50% mature, then closes the remaining
+//!   gap at the new speed: faster with a smaller tau, slower with a larger one
+//! - every stake is treated alike, whatever its age, so the change can't
+//!   favor early or late stakers beyond the speed-up or slow-down itself
+//!
+//! Stakes are brought forward lazily (`UserStake::sync_to_pool`), so the old
+//! taus are kept in the pool's tau history: a stake last synced before a
+//! change decays at each span's own tau. The history has TAU_HISTORY_SLOTS
+//! entries; a full one is compacted (`StakingPool::change_tau`) rather than
+//! refusing the change. The loyalty bonus ramp and GrandfatherStake credits
+//! use the current tau.
+//!
+//! A change moves tau by at most MAX_TAU_CHANGE_FACTOR either way. A raise
+//! slows every stake's remaining maturation, so with a settings timelock it
+//! is queued like the other changes that hurt stakers and applied by
+//! ApplySettings; a cut applies at once.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    events::{emit, TauChangedEvent},
+    state::{StakingPool, PENDING_TAU},
+};
+
+use super::initialize::{MAX_TAU_SECONDS, MIN_TAU_SECONDS};
+
+/// Largest factor one change may move tau by, up or down
+pub(crate) const MAX_TAU_CHANGE_FACTOR: u64 = 2;
+
+/// Check `tau_seconds` against the bounds InitializePool enforces
+/// (`InvalidTau`) and the per-change limit from the pool's current tau
+/// (`TauChangeTooLarge`)
+pub(crate) fn check_tau_change(pool: &StakingPool, tau_seconds: u64) -> ProgramResult {
+    if !(MIN_TAU_SECONDS..=MAX_TAU_SECONDS).contains(&tau_seconds) {
+        return Err(StakingError::InvalidTau.into());
+    }
+    if tau_seconds > pool.tau_seconds.saturating_mul(MAX_TAU_CHANGE_FACTOR)
+        || tau_seconds.saturating_mul(MAX_TAU_CHANGE_FACTOR) < pool.tau_seconds
+    {
+        return Err(StakingError::TauChangeTooLarge.into());
+    }
+    Ok(())
+}
+
+/// Switch `pool` to `tau_seconds` at `current_time` and log a
+/// TauChangedEvent. Shared by UpdateTau and ApplySettings.
+pub(crate) fn apply_tau_change(
+    pool_key: &Pubkey,
+    pool: &mut StakingPool,
+    tau_seconds: u64,
+    current_time: i64,
+) -> ProgramResult {
+    check_tau_change(pool, tau_seconds)?;
+    let old_tau = pool.change_tau(tau_seconds, current_time)?;
+
+    emit(&TauChangedEvent {
+        pool: *pool_key,
+        old_tau_seconds: old_tau,
+        new_tau_seconds: tau_seconds,
+        change_time: pool.base_time,
+    });
+    msg!("Tau changed from {} to {} seconds", old_tau, tau_seconds);
+    Ok(())
+}
+
+/// Change the pool's tau from now on (authority only)
+///
+/// Rejected with `InvalidTau` outside the bounds InitializePool enforces,
+/// with `TauChangeTooLarge` beyond MAX_TAU_CHANGE_FACTOR from the current
+/// tau, and with `SettingsLocked` after LockPoolSettings. With a settings
+/// timelock a raise is only recorded as pending (PENDING_TAU) for
+/// ApplySettings. A new value replaces a pending raise, so proposing the
+/// current tau cancels it.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[signer]` Authority (writable if the pool needs to grow)
+/// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+pub fn process_update_tau(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tau_seconds: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Validate authority is signer
+    if !authority_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Check authority is not renounced
+    if pool.is_authority_renounced() {
+        return Err(StakingError::AuthorityRenounced.into());
+    }
+
+    // Verify authority (no delegate can change tau)
+    if pool.authority != *authority_info.key {
+        return Err(StakingError::InvalidAuthority.into());
+    }
+
+    // Economic settings are frozen once locked
+    if pool.settings_locked {
+        return Err(StakingError::SettingsLocked.into());
+    }
+
+    check_tau_change(&pool, tau_seconds)?;
+    pool.clear_pending_settings(PENDING_TAU);
+
+    let clock = Clock::get()?;
+    if tau_seconds == pool.tau_seconds {
+        msg!("Tau is already {} seconds", tau_seconds);
+    } else if tau_seconds > pool.tau_seconds && pool.settings_timelock_seconds > 0 {
+        // Queued like UpdatePoolSettings' delayed changes: the timer of
+        // everything pending restarts
+        pool.pending_tau_seconds = tau_seconds;
+        pool.pending_settings_mask |= PENDING_TAU;
+        pool.pending_settings_effective_time =
+            clock.unix_timestamp.saturating_add(pool.settings_timelock_seconds as i64);
+        msg!(
+            "Tau change to {} seconds pending until {}",
+            tau_seconds,
+            pool.pending_settings_effective_time
+        );
+    } else {
+        apply_tau_change(pool_info.key, &mut pool, tau_seconds, clock.unix_timestamp)?;
+    }
+
+    // Grow legacy pools to hold the tau history (payer = authority)
+    let system_program_info = account_info_iter.next();
+    pool.ensure_capacity(pool_info, authority_info, system_program_info)?;
+    pool.save(pool_info)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_error::ProgramError;
+
+    #[test]
+    fn test_tau_change_limit() {
+        let pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 86_400, 0, 255);
+        let too_large = Err(ProgramError::from(StakingError::TauChangeTooLarge));
+
+        assert_eq!(check_tau_change(&pool, 172_800), Ok(()));
+        assert_eq!(check_tau_change(&pool, 43_200), Ok(()));
+        assert_eq!(check_tau_change(&pool, 172_801), too_large);
+        assert_eq!(check_tau_change(&pool, 43_199), too_large);
+        assert_eq!(
+            check_tau_change(&pool, MAX_TAU_SECONDS + 1),
+            Err(ProgramError::from(StakingError::InvalidTau))
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Verify pfee ownership instruction - check TakeFeeOwnership's result
+//!
+//! TakeFeeOwnership is irreversible, so operators want proof that it took
+//! effect without trusting the crank's logs: this reads the mint's pfee
+//! SharingConfig and reports whether the pool is the only fee recipient and
+//! whether the sharing authority is revoked.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    pfee::{SharingConfig, SHARING_CONFIG_SEED},
+    state::{StakingPool, PFEE_PROGRAM_ID},
+};
+
+/// Report whether the pool owns the mint's pump.fun creator fees
+/// (permissionless, read-only)
+///
+/// Returned via return data as two bytes: pool is the sole recipient with
+/// 10000 bps (u8 bool), sharing authority revoked (u8 bool). Both are 1
+/// once TakeFeeOwnership has succeeded.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` pfee SharingConfig (PDA on pfee: ["sharing-config", mint])
+pub fn process_verify_pfee_ownership(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let sharing_config_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Verify the SharingConfig is pfee's account for this pool's mint
+    let (expected_config, _) = Pubkey::find_program_address(
+        &[SHARING_CONFIG_SEED, pool.mint.as_ref()],
+        &PFEE_PROGRAM_ID,
+    );
+    if *sharing_config_info.key != expected_config {
+        return Err(StakingError::InvalidPDA.into());
+    }
+    if *sharing_config_info.owner != PFEE_PROGRAM_ID {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+
+    let config = SharingConfig::parse(&sharing_config_info.try_borrow_data()?)?;
+    if config.mint != pool.mint {
+        return Err(StakingError::InvalidPoolMint.into());
+    }
+
+    let sole_recipient = config.is_sole_recipient(pool_info.key);
+    msg!(
+        "pfee ownership: pool sole recipient {}, authority revoked {} ({} shareholders)",
+        sole_recipient,
+        config.admin_revoked,
+        config.shareholders.len()
+    );
+
+    set_return_data(&[sole_recipient as u8, config.admin_revoked as u8]);
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+//! Verify vault consistency instruction - read-only invariant canary
+//!
+//! Checks that the pool's bookkeeping agrees with the balances it actually
+//! holds. Intended to be run periodically by monitoring bots: it never
+//! mutates state and fails with `InvariantViolation` when drift is detected.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::StakingError,
+    state::{is_valid_token_program, StakingPool},
+};
+
+/// Read the token balance of the pool's vault after validating that the
+/// account is the pool's vault, is owned by a token program and holds the
+/// pool's mint (`InvalidVaultMint` otherwise).
+pub fn read_vault_amount(
+    pool: &StakingPool,
+    pool_key: &Pubkey,
+    token_vault_info: &AccountInfo,
+) -> Result<u64, StakingError> {
+    read_vault(pool, pool_key, token_vault_info).map(|vault| vault.amount)
+}
+
+/// Unpack the pool's vault token account, validated as in
+/// `read_vault_amount`
+pub fn read_vault(
+    pool: &StakingPool,
+    pool_key: &Pubkey,
+    token_vault_info: &AccountInfo,
+) -> Result<spl_token_2022::state::Account, StakingError> {
+    if pool.token_vault != *token_vault_info.key {
+        return Err(StakingError::InvalidTokenVault);
+    }
+    if !is_valid_token_program(token_vault_info.owner) {
+        return Err(StakingError::InvalidAccountOwner);
+    }
+
+    let vault_data = token_vault_info
+        .try_borrow_data()
+        .map_err(|_| StakingError::InvalidTokenVault)?;
+    let vault = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&vault_data)
+        .map_err(|_| StakingError::InvalidTokenVault)?;
+    if vault.base.mint != pool.mint {
+        return Err(StakingError::InvalidVaultMint);
+    }
+    if vault.base.owner != *pool_key {
+        return Err(StakingError::InvalidTokenVault);
+    }
+
+    Ok(vault.base)
+}
+
+/// Balances the pool's invariants are checked against
+pub struct PoolBalances {
+    /// Token balance of the pool's vault
+    pub vault_amount: u64,
+    /// The vault is frozen (its mint's freeze authority froze it), which
+    /// blocks every unstake
+    pub vault_frozen: bool,
+    /// Pool PDA lamports
+    pub lamports: u64,
+    /// Rent-exempt minimum for the pool account's current size
+    pub rent_exempt_minimum: u64,
+}
+
+impl PoolBalances {
+    /// Read the vault and pool balances (validates the vault account)
+    pub fn read(
+        pool: &StakingPool,
+        pool_info: &AccountInfo,
+        token_vault_info: &AccountInfo,
+    ) -> Result<Self, ProgramError> {
+        let vault = read_vault(pool, pool_info.key, token_vault_info)?;
+        let rent = Rent::get()?;
+        Ok(Self {
+            vault_amount: vault.amount,
+            vault_frozen: vault.is_frozen(),
+            lamports: pool_info.lamports(),
+            rent_exempt_minimum: rent.minimum_balance(pool_info.data_len()),
+        })
+    }
+
+    /// Lamports above the rent-exempt minimum
+    pub fn available(&self) -> u64 {
+        self.lamports.saturating_sub(self.rent_exempt_minimum)
+    }
+
+    /// vault token balance == total_staked + penalty_tokens (pools only
+    /// accept mints without transfer fees, so every staked token sits in the
+    /// vault; tokens sent directly to the vault also trip this check)
+    pub fn vault_matches_total_staked(&self, pool: &StakingPool) -> bool {
+        self.vault_amount as u128 == pool.total_staked + pool.penalty_tokens as u128
+    }
+
+    /// last_synced_lamports <= lamports available above the rent-exempt minimum
+    pub fn last_synced_consistent(&self, pool: &StakingPool) -> bool {
+        pool.last_synced_lamports <= self.available()
+    }
+}
+
+/// Verify the pool's accounting invariants (permissionless, read-only)
+///
+/// Checks:
+/// - vault token balance == total_staked + penalty_tokens (pools only accept
+///   mints without transfer fees, so every staked token sits in the vault;
+///   tokens sent directly to the vault also trip this check)
+/// - last_synced_lamports <= lamports available above the rent-exempt minimum
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` Token vault
+pub fn process_verify_vault_consistency(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+
+    // ── Validate pool ───────────────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let balances = PoolBalances::read(&pool, pool_info, token_vault_info)?;
+
+    // ── Token invariant ─────────────────────────────────────────────
+    let tokens_ok = balances.vault_matches_total_staked(&pool);
+    if !tokens_ok {
+        msg!(
+            "Vault balance mismatch: vault={}, total_staked={}, penalty_tokens={}",
+            balances.vault_amount,
+            pool.total_staked,
+            pool.penalty_tokens
+        );
+    }
+
+    // ── Lamport invariant ───────────────────────────────────────────
+    let current_available = balances.available();
+    let lamports_ok = balances.last_synced_consistent(&pool);
+    if !lamports_ok {
+        msg!(
+            "Reward accounting drift: last_synced_lamports={}, available={}",
+            pool.last_synced_lamports,
+            current_available
+        );
+    }
+
+    if !tokens_ok || !lamports_ok {
+        return Err(StakingError::InvariantViolation.into());
+    }
+
+    msg!(
+        "Pool consistent: total_staked={}, last_synced_lamports={}, available={}",
+        pool.total_staked,
+        pool.last_synced_lamports,
+        current_available
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_pack::Pack;
+    use spl_token_2022::state::{Account, AccountState};
+
+    fn vault_data(mint: Pubkey, owner: Pubkey) -> Vec<u8> {
+        vault_data_in_state(mint, owner, AccountState::Initialized)
+    }
+
+    fn vault_data_in_state(mint: Pubkey, owner: Pubkey, state: AccountState) -> Vec<u8> {
+        let account = Account {
+            mint,
+            owner,
+            amount: 1_000,
+            state,
+            ..Account::default()
+        };
+        let mut data = vec![0u8; Account::LEN];
+        Account::pack(account, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_read_vault_amount_errors() {
+        let pool_key = Pubkey::new_unique();
+        let vault_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let pool = StakingPool::new(mint, vault_key, pool_key, Pubkey::new_unique(), 2592000, 0, 255);
+        let token_program = spl_token_2022::id();
+
+        let read = |key: Pubkey, data: &mut Vec<u8>| {
+            let mut lamports = 0;
+            let info = AccountInfo::new(&key, false, false, &mut lamports, data, &token_program, false, 0);
+            read_vault_amount(&pool, &pool_key, &info)
+        };
+
+        assert_eq!(read(vault_key, &mut vault_data(mint, pool_key)), Ok(1_000));
+        assert_eq!(
+            read(Pubkey::new_unique(), &mut vault_data(mint, pool_key)),
+            Err(StakingError::InvalidTokenVault)
+        );
+        assert_eq!(
+            read(vault_key, &mut vault_data(Pubkey::new_unique(), pool_key)),
+            Err(StakingError::InvalidVaultMint)
+        );
+        assert_eq!(
+            read(vault_key, &mut vault_data(mint, Pubkey::new_unique())),
+            Err(StakingError::InvalidTokenVault)
+        );
+    }
+
+    #[test]
+    fn test_read_vault_frozen() {
+        let pool_key = Pubkey::new_unique();
+        let vault_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let pool = StakingPool::new(mint, vault_key, pool_key, Pubkey::new_unique(), 2592000, 0, 255);
+        let token_program = spl_token_2022::id();
+
+        for (state, frozen) in [(AccountState::Initialized, false), (AccountState::Frozen, true)] {
+            let mut data = vault_data_in_state(mint, pool_key, state);
+            let mut lamports = 0;
+            let info = AccountInfo::new(&vault_key, false, false, &mut lamports, &mut data, &token_program, false, 0);
+            let vault = read_vault(&pool, &pool_key, &info).unwrap();
+            assert_eq!(vault.is_frozen(), frozen);
+            assert_eq!(vault.amount, 1_000);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! View pool health instruction - read-only status bitmask
+//!
+//! Combines the pool's invariant checks and status flags into one value so
+//! monitoring dashboards can poll a single instruction per pool. Unlike
+//! VerifyVaultConsistency it never fails on a violated invariant; the result
+//! is returned via return data.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::StakingError,
+    instructions::PoolBalances,
+    state::StakingPool,
+};
+
+/// Vault token balance equals total_staked
+pub const HEALTH_VAULT_MATCHES_TOTAL_STAKED: u8 = 1 << 0;
+/// Pool account holds at least its rent-exempt minimum
+pub const HEALTH_RENT_OK: u8 = 1 << 1;
+/// last_synced_lamports does not exceed the available lamports
+pub const HEALTH_LAST_SYNCED_CONSISTENT: u8 = 1 << 2;
+/// Pool is quarantined (new stakes blocked)
+pub const HEALTH_QUARANTINED: u8 = 1 << 3;
+/// Pool is paused (new stakes and reward deposits blocked)
+pub const HEALTH_PAUSED: u8 = 1 << 4;
+/// Token vault is frozen by its mint's freeze authority (every unstake
+/// fails until it is thawed)
+pub const HEALTH_FROZEN: u8 = 1 << 5;
+
+/// Flags of a healthy pool: all invariant bits set, no status bits
+pub const HEALTH_ALL_GREEN: u8 =
+    HEALTH_VAULT_MATCHES_TOTAL_STAKED | HEALTH_RENT_OK | HEALTH_LAST_SYNCED_CONSISTENT;
+
+/// Compute the health bitmask for a pool
+pub fn pool_health_flags(pool: &StakingPool, balances: &PoolBalances) -> u8 {
+    let mut flags = 0;
+    if balances.vault_matches_total_staked(pool) {
+        flags |= HEALTH_VAULT_MATCHES_TOTAL_STAKED;
+    }
+    if balances.lamports >= balances.rent_exempt_minimum {
+        flags |= HEALTH_RENT_OK;
+    }
+    if balances.last_synced_consistent(pool) {
+        flags |= HEALTH_LAST_SYNCED_CONSISTENT;
+    }
+    if pool.quarantined {
+        flags |= HEALTH_QUARANTINED;
+    }
+    if pool.paused {
+        flags |= HEALTH_PAUSED;
+    }
+    if balances.vault_frozen {
+        flags |= HEALTH_FROZEN;
+    }
+    flags
+}
+
+/// Return the pool's health bitmask (permissionless, read-only)
+///
+/// The result is a single u8 in return data (see the `HEALTH_*` bits);
+/// `HEALTH_ALL_GREEN` means every invariant holds and no status bit is set.
+///
+/// Accounts:
+/// 0. `[]` Pool account
+/// 1. `[]` Token vault
+pub fn process_view_pool_health(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_vault_info = next_account_info(account_info_iter)?;
+
+    // ── Validate pool ───────────────────────────────────────────────
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let balances = PoolBalances::read(&pool, pool_info, token_vault_info)?;
+    let flags = pool_health_flags(&pool, &balances);
+
+    msg!(
+        "Pool health: {:#08b} ({})",
+        flags,
+        if flags == HEALTH_ALL_GREEN { "all green" } else { "attention" }
+    );
+    set_return_data(&[flags]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_health_flags() {
+        let mut pool = StakingPool::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 2592000, 0, 255);
+        pool.total_staked = 1_000;
+        let mut balances = PoolBalances {
+            vault_amount: 1_000,
+            vault_frozen: false,
+            lamports: 2_000_000,
+            rent_exempt_minimum: 1_000_000,
+        };
+        assert_eq!(pool_health_flags(&pool, &balances), HEALTH_ALL_GREEN);
+
+        // A frozen vault is reported on top of otherwise healthy invariants
+        balances.vault_frozen = true;
+        assert_eq!(pool_health_flags(&pool, &balances), HEALTH_ALL_GREEN | HEALTH_FROZEN);
+
+        pool.paused = true;
+        balances.vault_frozen = false;
+        assert_eq!(pool_health_flags(&pool, &balances), HEALTH_ALL_GREEN | HEALTH_PAUSED);
+    }
+}
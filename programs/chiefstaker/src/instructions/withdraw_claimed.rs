@@ -0,0 +1,114 @@
+//! Withdraw claimed balance instruction
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::StakingError,
+    events::{emit_reward_payout, RewardPayoutType},
+    state::{StakingPool, UserStake},
+};
+
+/// Pay out the user's accumulated `claimed_balance` (credited by ClaimToBalance)
+///
+/// Accounts:
+/// 0. `[writable]` Pool account (holds SOL rewards)
+/// 1. `[writable]` User stake account
+/// 2. `[writable, signer]` User/owner
+pub fn process_withdraw_claimed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+
+    // Validate user is signer
+    if !user_info.is_signer {
+        return Err(StakingError::MissingRequiredSigner.into());
+    }
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    // Load and validate user stake
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut user_stake = UserStake::try_from_slice(&user_stake_info.try_borrow_data()?)?;
+    if !user_stake.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify ownership
+    if user_stake.owner != *user_info.key {
+        return Err(StakingError::InvalidOwner.into());
+    }
+    if user_stake.pool != *pool_info.key {
+        return Err(StakingError::InvalidPool.into());
+    }
+
+    // Verify user stake PDA
+    let (expected_stake, _) = user_stake.derive_position_pda(pool_info.key, program_id);
+    if *user_stake_info.key != expected_stake {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let amount = user_stake.claimed_balance;
+    if amount == 0 {
+        msg!("No claimed balance to withdraw");
+        return Ok(());
+    }
+
+    // Claimed balances are reserved out of the pool's lamports, so the pool
+    // must always be able to cover them on top of its rent-exempt minimum
+    let rent = Rent::get()?;
+    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
+    let available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
+    if available < amount {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
+    // Transfer SOL from pool to user
+    **pool_info.try_borrow_mut_lamports()? -= amount;
+    **user_info.try_borrow_mut_lamports()? += amount;
+
+    user_stake.claimed_balance = 0;
+    pool.total_claimed_balance = pool.total_claimed_balance.saturating_sub(amount);
+
+    // The lamports were synced as rewards when deposited; they now leave the pool
+    pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(amount);
+
+    // Save user stake
+    user_stake.save(user_stake_info)?;
+
+    // Save pool state
+    pool.save(pool_info)?;
+
+    msg!("Withdrew {} lamports of claimed rewards", amount);
+
+    emit_reward_payout(pool_info.key, user_info.key, amount, RewardPayoutType::WithdrawClaimed);
+
+    Ok(())
+}
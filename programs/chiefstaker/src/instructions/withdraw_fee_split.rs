@@ -0,0 +1,93 @@
+//! Withdraw fee split instruction - pay a recipient its unpaid shares
+//!
+//! A claim never fails because a fee split recipient can't take lamports
+//! (emptied below rent exemption, executable or program-owned); its share
+//! is reserved in the pool instead. Once the recipient can receive again,
+//! anyone can push the reserved lamports to it.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{error::StakingError, state::StakingPool};
+
+use super::claim::can_receive_lamports;
+
+/// Pay a fee split recipient the shares claims couldn't pay it
+/// (permissionless; the lamports only ever go to the configured recipient)
+///
+/// Fails with `FeeRecipientMismatch` if the account is not a current fee
+/// split recipient and with `InvalidRewardRecipient` if it still can't
+/// receive the lamports. Nothing unpaid transfers nothing.
+///
+/// Accounts:
+/// 0. `[writable]` Pool account
+/// 1. `[writable]` Fee split recipient
+pub fn process_withdraw_fee_split(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
+
+    // Load and validate pool
+    if pool_info.owner != program_id {
+        return Err(StakingError::InvalidAccountOwner.into());
+    }
+    let mut pool = StakingPool::try_from_slice(&pool_info.try_borrow_data()?)?;
+    if !pool.is_initialized() {
+        return Err(StakingError::NotInitialized.into());
+    }
+
+    // Verify pool PDA
+    let (expected_pool, _) = StakingPool::derive_pda(&pool.mint, program_id);
+    if *pool_info.key != expected_pool {
+        return Err(StakingError::InvalidPDA.into());
+    }
+
+    let slot = pool
+        .fee_split_recipients
+        .iter()
+        .zip(pool.fee_split_bps.iter())
+        .position(|(recipient, bps)| *bps > 0 && recipient == recipient_info.key)
+        .ok_or(StakingError::FeeRecipientMismatch)?;
+
+    let amount = pool.fee_split_unpaid[slot];
+    if amount == 0 {
+        msg!("No unpaid fee split for {}", recipient_info.key);
+        return Ok(());
+    }
+
+    let rent = Rent::get()?;
+    if !can_receive_lamports(recipient_info, amount, &rent) {
+        msg!("{} still can't receive {} lamports", recipient_info.key, amount);
+        return Err(StakingError::InvalidRewardRecipient.into());
+    }
+
+    // Unpaid shares are reserved out of the pool's lamports, so the pool
+    // must always be able to cover them on top of its rent-exempt minimum
+    let rent_exempt_minimum = rent.minimum_balance(pool_info.data_len());
+    let available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
+    if available < amount {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
+    **pool_info.try_borrow_mut_lamports()? -= amount;
+    **recipient_info.try_borrow_mut_lamports()? += amount;
+
+    pool.fee_split_unpaid[slot] = 0;
+
+    // The lamports were synced as rewards when deposited; they now leave the pool
+    pool.last_synced_lamports = pool.last_synced_lamports.saturating_sub(amount);
+
+    pool.save(pool_info)?;
+
+    msg!("Paid {} lamports of unpaid fee split to {}", amount, recipient_info.key);
+
+    Ok(())
+}
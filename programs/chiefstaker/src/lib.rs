@@ -15,17 +15,106 @@ use solana_program::{
     program_error::ProgramError, pubkey::Pubkey,
 };
 
+#[cfg(any(feature = "no-entrypoint", test))]
+pub mod client;
 pub mod error;
 pub mod events;
 pub mod instructions;
 pub mod math;
+pub mod pfee;
 pub mod state;
 
 use instructions::*;
+use state::UnstakeDestinationPolicy;
 
 // Declare program ID - matches target/deploy/chiefstaker-keypair.json
 solana_program::declare_id!("3Ecf8gyRURyrBtGHS1XAVXyQik5PqgDch4VkxrH4ECcr");
 
+/// Optional instruction argument appended after a variant's original fields.
+///
+/// Encodes like `Option<T>`, but decodes as `None` when the instruction data
+/// ends before it, so clients built against the older layout keep working.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrailingOption<T>(pub Option<T>);
+
+impl<T> From<Option<T>> for TrailingOption<T> {
+    fn from(value: Option<T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: BorshSerialize> BorshSerialize for TrailingOption<T> {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.0.serialize(writer)
+    }
+}
+
+impl<T: BorshDeserialize> BorshDeserialize for TrailingOption<T> {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut tag = [0u8; 1];
+        if reader.read(&mut tag)? == 0 {
+            return Ok(Self(None));
+        }
+        match tag[0] {
+            0 => Ok(Self(None)),
+            1 => Ok(Self(Some(T::deserialize_reader(reader)?))),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid Option tag",
+            )),
+        }
+    }
+}
+
+/// How a top-up treats the stake's running lock
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TopUpMode {
+    /// Lock the new tokens from now; the tokens already staked keep their
+    /// own lock and can be unstaked once it ends
+    #[default]
+    ResetLock,
+    /// Add the new tokens to the running lock if at least the pool's lock
+    /// duration is left on it (otherwise they get their own lock)
+    KeepLock,
+}
+
+/// Settings InitializePool can apply up front, so a pool launches with its
+/// policy in one transaction. Validated like UpdatePoolSettings; 0 is each
+/// setting's default.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InitialPoolSettings {
+    /// Minimum stake amount (at most 10^15)
+    pub min_stake_amount: u64,
+    /// Lock duration in seconds (at most 365 days)
+    pub lock_duration_seconds: u64,
+    /// Unstake cooldown in seconds (at most 30 days)
+    pub unstake_cooldown_seconds: u64,
+}
+
+/// Cut of a DepositRewards deposit paid to a referrer instead of the pool
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DepositReferral {
+    /// Account receiving the cut (passed as a trailing account)
+    pub referrer: Pubkey,
+    /// Cut of the deposit in basis points (at most 1000)
+    pub referral_bps: u64,
+}
+
+/// Stake arguments. Only `amount` is required; the rest trail it, so the
+/// original `Stake { amount }` encoding is still a valid StakeParams.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StakeParams {
+    /// Amount of tokens to stake
+    pub amount: u64,
+    /// Referrer recorded on a new stake account (ignored on top-ups)
+    pub referrer: TrailingOption<Pubkey>,
+    /// Minimum lock the owner commits to, counted from the lock start; earns
+    /// the pool's lock boost while it runs
+    pub lock_commit_seconds: TrailingOption<u64>,
+    /// Whether a top-up's tokens get their own lock or join the running one
+    pub top_up_mode: TrailingOption<TopUpMode>,
+}
+
 /// Program instructions
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub enum StakingInstruction {
@@ -39,12 +128,19 @@ pub enum StakingInstruction {
     /// 4. `[]` System program
     /// 5. `[]` Token 2022 program
     /// 6. `[]` Rent sysvar
+    /// 7. `[writable]` Audit log page 0 (PDA: ["audit_log", pool, 0u32 LE];
+    ///    required with `enable_audit_log`)
     InitializePool {
         /// Time constant in seconds (e.g., 2592000 for 30 days)
         tau_seconds: u64,
+        /// Record every reward distribution in audit log pages
+        enable_audit_log: TrailingOption<bool>,
+        /// Settings to start with instead of the defaults
+        initial_settings: TrailingOption<InitialPoolSettings>,
     },
 
-    /// Stake tokens into the pool
+    /// Stake tokens into the pool (return data: resulting amount as u64 LE,
+    /// then its current weighted stake as WAD-scaled u128 LE)
     ///
     /// Accounts:
     /// 0. `[writable]` Pool account
@@ -56,8 +152,7 @@ pub enum StakingInstruction {
     /// 6. `[]` System program
     /// 7. `[]` Token 2022 program
     Stake {
-        /// Amount of tokens to stake
-        amount: u64,
+        params: StakeParams,
     },
 
     /// Unstake tokens from the pool
@@ -66,7 +161,7 @@ pub enum StakingInstruction {
     /// 0. `[writable]` Pool account
     /// 1. `[writable]` User stake account
     /// 2. `[writable]` Token vault
-    /// 3. `[writable]` User token account
+    /// 3. `[writable]` User token account (any account of the pool mint owned by the user)
     /// 4. `[]` Token mint
     /// 5. `[writable, signer]` User/owner
     /// 6. `[]` Token 2022 program
@@ -75,23 +170,47 @@ pub enum StakingInstruction {
         amount: u64,
     },
 
-    /// Claim accumulated SOL rewards
+    /// Claim accumulated SOL rewards, or exactly `amount` lamports of them
+    ///
+    /// Returns the lamports paid out (u64 LE) via return data, 0 if nothing
+    /// was pending; so do ClaimRewardsTo, ClaimToBalance, ClaimFor and
+    /// ClaimAndCloseIfEmpty.
     ///
     /// Accounts:
     /// 0. `[writable]` Pool account (holds SOL rewards)
     /// 1. `[writable]` User stake account
     /// 2. `[writable, signer]` User/owner
-    ClaimRewards,
+    /// 3. `[]` System program (optional; required for legacy accounts or fee splits)
+    ///
+    /// followed by the `[writable]` fee split recipients in pool order, if the pool has a split,
+    /// and for a partial claim on a pool with an audit log, the current audit log page.
+    ClaimRewards {
+        /// Lamports to claim (before the claim fee and fee split); the rest
+        /// stays claimable. None claims everything.
+        amount: TrailingOption<u64>,
+    },
 
-    /// Deposit SOL rewards into the pool (permissionless)
+    /// Deposit SOL rewards into the pool (permissionless, unless the pool
+    /// requires allowlisted depositors)
     ///
     /// Accounts:
     /// 0. `[writable]` Pool account (receives SOL)
     /// 1. `[writable, signer]` Depositor
     /// 2. `[]` System program
+    ///
+    /// followed, when the pool needs them, by the depositor's allowlist
+    /// entry, stake accounts (every position) and the current audit log
+    /// page, and by the `[writable]` referrer when a referral is given.
     DepositRewards {
         /// Amount of lamports to deposit
         amount: u64,
+        /// Idempotency nonce: a repeat within the pool's recent window is a no-op
+        nonce: TrailingOption<u64>,
+        /// Value of the deposit in the pool's display unit, priced by the
+        /// client (display only, added to display_total_distributed)
+        display_value: TrailingOption<u64>,
+        /// Pay part of the deposit to a referrer; the rest goes to stakers
+        referral: TrailingOption<DepositReferral>,
     },
 
     /// Sync/rebase the pool to prevent overflow (permissionless crank)
@@ -107,19 +226,51 @@ pub enum StakingInstruction {
     /// 0. `[writable]` Pool account
     SyncRewards,
 
-    /// Update pool settings (authority only)
+    /// Update pool settings (authority or settings delegate)
     ///
     /// Accounts:
     /// 0. `[writable]` Pool account
-    /// 1. `[signer]` Authority
+    /// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
     UpdatePoolSettings {
         min_stake_amount: Option<u64>,
         lock_duration_seconds: Option<u64>,
         unstake_cooldown_seconds: Option<u64>,
+        /// Max lamports deposits may defer while nobody is staked (0 = unlimited)
+        max_deferred_lamports: TrailingOption<u64>,
+        /// Max weight bonus in bps for leaving rewards unclaimed (0 = off, max 2000)
+        loyalty_bonus_bps: TrailingOption<u64>,
+        /// Exclude depositors' own stake from their deposits
+        exclude_depositor: TrailingOption<bool>,
+        /// Stamp lock/cooldown on new stakes so later changes aren't retroactive
+        per_stake_terms: TrailingOption<bool>,
+        /// Protocol fee in bps withheld from reward claims (max 1000)
+        claim_fee_bps: TrailingOption<u64>,
+        /// Seconds after CompleteUnstake during which ReverseUnstake is allowed (0 = off, max 3600)
+        unstake_reversal_window_seconds: TrailingOption<u64>,
+        /// Lamports a deposit or sync must distribute at once; smaller amounts
+        /// wait for more (0 = no minimum)
+        min_distributable_per_event: TrailingOption<u64>,
+        /// Most tokens the pool may hold staked (0 = unlimited); may be set
+        /// below total_staked to stop new stakes
+        max_total_staked: TrailingOption<u64>,
+        /// Smallest reward deposit accepted (0 = any)
+        min_reward_deposit: TrailingOption<u64>,
+        /// Early exit penalty for InstantUnstake, in bps (max 10000, 0 = disabled)
+        early_exit_penalty_bps: TrailingOption<u64>,
+        /// Max weight boost in bps for a voluntary lock commitment, reached
+        /// at a one-year lock (0 = off, max 10000 = 2x)
+        lock_boost_bps: TrailingOption<u64>,
+        /// Only accept reward deposits from depositors added with AddDepositor
+        require_allowlisted_depositors: TrailingOption<bool>,
+        /// Seconds a change that makes leaving slower or costlier waits for
+        /// ApplySettings (0 = off, max 30 days)
+        settings_timelock_seconds: TrailingOption<u64>,
     },
 
     /// Transfer pool authority to a new address
     /// Setting to Pubkey::default() renounces authority (irreversible)
+    /// Delegates appointed by the old authority are dropped
     ///
     /// Accounts:
     /// 0. `[writable]` Pool account
@@ -138,16 +289,18 @@ pub enum StakingInstruction {
         amount: u64,
     },
 
-    /// Complete unstake after cooldown elapsed
+    /// Complete unstake after cooldown elapsed, paying pending SOL rewards
     ///
     /// Accounts (same as Unstake):
     /// 0. `[writable]` Pool account
     /// 1. `[writable]` User stake account
     /// 2. `[writable]` Token vault
-    /// 3. `[writable]` User token account
+    /// 3. `[writable]` User token account (any account of the pool mint owned by the user)
     /// 4. `[]` Token mint
     /// 5. `[writable, signer]` User/owner
     /// 6. `[]` Token 2022 program
+    /// 7. `[]` System program (optional; grows a legacy stake account, needed to
+    ///    record a reversible unstake on one)
     CompleteUnstake,
 
     /// Cancel a pending unstake request
@@ -164,6 +317,11 @@ pub enum StakingInstruction {
     /// 0. `[]` Pool account
     /// 1. `[writable]` User stake account
     /// 2. `[writable, signer]` User/owner
+    ///
+    /// Optional trailing accounts (matched by key):
+    /// - `[writable]` Pool metadata
+    /// - `[writable]` Default stake account; required to close a position
+    ///   above index 0
     CloseStakeAccount,
 
     /// DEPRECATED: Slot 13 reserved for ABI compatibility (was FixTotalRewardDebt).
@@ -241,164 +399,1572 @@ pub enum StakingInstruction {
         new_exp_start_factor: u128,
         new_reward_debt: u128,
     },
-}
 
-#[cfg(not(feature = "no-entrypoint"))]
-entrypoint!(process_instruction);
+    /// Verify pool accounting invariants (permissionless, read-only)
+    ///
+    /// Fails with InvariantViolation if the vault balance differs from
+    /// total_staked or last_synced_lamports exceeds the available lamports.
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` Token vault
+    VerifyVaultConsistency,
 
-#[cfg(not(feature = "no-entrypoint"))]
-use solana_security_txt::security_txt;
+    /// Settle accumulated rewards into the stake's claimed_balance instead of
+    /// transferring SOL (the lamports stay reserved in the pool)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (holds SOL rewards)
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` User/owner (pays rent if accounts need to grow)
+    /// 3. `[]` System program (required if pool or stake account is legacy-sized, or for fee splits)
+    ///
+    /// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+    ClaimToBalance,
 
-#[cfg(not(feature = "no-entrypoint"))]
-security_txt! {
-    name: "ChiefStaker",
-    project_url: "https://github.com/KarpelesLab/chiefstaker",
-    contacts: "link:https://github.com/KarpelesLab/chiefstaker/security/advisories",
-    policy: "https://github.com/KarpelesLab/chiefstaker/security/policy",
-    source_code: "https://github.com/KarpelesLab/chiefstaker"
-}
+    /// Withdraw the full claimed_balance accumulated by ClaimToBalance
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (holds SOL rewards)
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` User/owner
+    WithdrawClaimed,
 
-/// Program entrypoint
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    // Verify this is the correct program
-    if program_id != &crate::id() {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    /// Credit prior staking time to a migrated staker (authority or
+    /// grandfather delegate, once per stake)
+    ///
+    /// Moves the stake's effective start time earlier, never into the future
+    /// and never before pool creation.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` Authority or grandfather delegate
+    /// 3. `[]` System program
+    GrandfatherStake {
+        /// New effective stake start time (Unix timestamp)
+        effective_stake_time: i64,
+    },
 
-    // Deserialize instruction
-    let instruction = StakingInstruction::try_from_slice(instruction_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    /// Split part of a stake into a new stake account for another owner,
+    /// preserving maturity and a proportional share of reward accounting
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` Source stake account (PDA: ["stake", pool, owner])
+    /// 2. `[writable]` New stake account (PDA: ["stake", pool, new_owner])
+    /// 3. `[writable, signer]` Owner (pays rent for the new account)
+    /// 4. `[]` New owner
+    /// 5. `[]` System program
+    /// 6. `[writable]` (optional) Pool metadata account
+    SplitStake {
+        /// Amount of tokens to move to the new stake account
+        amount: u64,
+    },
 
-    // Dispatch to appropriate handler
-    match instruction {
-        StakingInstruction::InitializePool { tau_seconds } => {
-            msg!("Instruction: InitializePool (tau={}s)", tau_seconds);
-            process_initialize_pool(program_id, accounts, tau_seconds)
-        }
-        StakingInstruction::Stake { amount } => {
-            msg!("Instruction: Stake (amount={})", amount);
-            process_stake(program_id, accounts, amount)
-        }
-        StakingInstruction::Unstake { amount } => {
-            msg!("Instruction: Unstake (amount={})", amount);
-            process_unstake(program_id, accounts, amount)
-        }
-        StakingInstruction::ClaimRewards => {
-            msg!("Instruction: ClaimRewards");
-            process_claim_rewards(program_id, accounts)
-        }
-        StakingInstruction::DepositRewards { amount } => {
-            msg!("Instruction: DepositRewards (amount={})", amount);
-            process_deposit_rewards(program_id, accounts, amount)
-        }
-        StakingInstruction::SyncPool => {
-            msg!("Instruction: SyncPool");
-            process_sync_pool(program_id, accounts)
-        }
-        StakingInstruction::SyncRewards => {
-            msg!("Instruction: SyncRewards");
-            process_sync_rewards(program_id, accounts)
-        }
-        StakingInstruction::UpdatePoolSettings {
-            min_stake_amount,
-            lock_duration_seconds,
-            unstake_cooldown_seconds,
-        } => {
-            msg!("Instruction: UpdatePoolSettings");
-            process_update_pool_settings(
-                program_id,
-                accounts,
-                min_stake_amount,
-                lock_duration_seconds,
-                unstake_cooldown_seconds,
-            )
-        }
-        StakingInstruction::TransferAuthority { new_authority } => {
-            msg!("Instruction: TransferAuthority");
-            process_transfer_authority(program_id, accounts, new_authority)
-        }
-        StakingInstruction::RequestUnstake { amount } => {
-            msg!("Instruction: RequestUnstake (amount={})", amount);
-            process_request_unstake(program_id, accounts, amount)
-        }
-        StakingInstruction::CompleteUnstake => {
-            msg!("Instruction: CompleteUnstake");
-            process_complete_unstake(program_id, accounts)
-        }
-        StakingInstruction::CancelUnstakeRequest => {
-            msg!("Instruction: CancelUnstakeRequest");
-            process_cancel_unstake_request(program_id, accounts)
-        }
-        StakingInstruction::CloseStakeAccount => {
-            msg!("Instruction: CloseStakeAccount");
-            process_close_stake_account(program_id, accounts)
-        }
-        StakingInstruction::DeprecatedFixTotalRewardDebt { .. } => {
-            msg!("Instruction: FixTotalRewardDebt (deprecated, no-op)");
-            Err(ProgramError::InvalidInstructionData)
-        }
-        StakingInstruction::SetPoolMetadata => {
-            msg!("Instruction: SetPoolMetadata");
-            process_set_pool_metadata(program_id, accounts)
-        }
-        StakingInstruction::TakeFeeOwnership => {
-            msg!("Instruction: TakeFeeOwnership");
-            process_take_fee_ownership(program_id, accounts)
-        }
-        StakingInstruction::StakeOnBehalf { amount } => {
-            msg!("Instruction: StakeOnBehalf (amount={})", amount);
-            process_stake_on_behalf(program_id, accounts, amount)
-        }
-        StakingInstruction::FixStakeAccount {
-            new_exp_start_factor,
-            new_reward_debt,
-        } => {
-            msg!("Instruction: FixStakeAccount");
-            process_fix_stake_account(
-                program_id,
-                accounts,
-                new_exp_start_factor,
-                new_reward_debt,
-            )
-        }
-    }
-}
+    /// Project the lamports a stake could claim at a future time, assuming no
+    /// new deposits (read-only; result in return data as u64 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryClaimableAt {
+        /// Unix timestamp to project to (must not be in the past)
+        future_timestamp: i64,
+    },
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Withdraw the protocol fees accrued from reward claims (authority or
+    /// fees delegate)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or fees delegate
+    /// 2. `[writable]` Fee recipient
+    CollectProtocolFees,
 
-    #[test]
-    fn test_instruction_serialization() {
-        let instruction = StakingInstruction::InitializePool {
-            tau_seconds: 2592000,
-        };
-        let serialized = borsh::to_vec(&instruction).unwrap();
-        let deserialized: StakingInstruction =
-            BorshDeserialize::try_from_slice(&serialized).unwrap();
+    /// Quarantine the pool if its mint gained a forbidden extension after
+    /// init (permissionless crank): new stakes are blocked, exits still work
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[]` Token mint
+    /// 2. `[writable, signer]` Payer
+    /// 3. `[]` System program
+    QuarantinePool,
 
-        match deserialized {
-            StakingInstruction::InitializePool { tau_seconds } => {
-                assert_eq!(tau_seconds, 2592000);
-            }
-            _ => panic!("Wrong instruction type"),
-        }
-    }
+    /// Return the pool's health bitmask via return data (read-only)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` Token vault
+    ViewPoolHealth,
 
-    #[test]
-    fn test_stake_instruction() {
-        let instruction = StakingInstruction::Stake { amount: 1_000_000 };
+    /// Request unstake of the full balance on several stakes of one owner
+    ///
+    /// Stakes with a pending request are skipped unless `fail_on_pending`.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` Owner
+    /// 1. `[]` System program
+    /// 2. `[writable]` Pool account #1
+    /// 3. `[writable]` User stake account #1
+    ///
+    /// followed by further (pool, user stake) pairs, at most 8 in total.
+    BatchRequestUnstake {
+        fail_on_pending: bool,
+    },
+
+    /// Claim rewards on behalf of a stake owner (permissionless once the
+    /// owner has opted in via SetClaimForOptIn). The SOL always goes to
+    /// the owner.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (holds SOL rewards)
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable]` Stake owner (receives the rewards)
+    /// 3. `[writable, signer]` Caller (pays rent if the stake account needs to grow)
+    /// 4. `[]` System program (optional, required for legacy-sized stake accounts, or for fee splits)
+    ///
+    /// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+    ClaimFor,
+
+    /// Allow or forbid third parties to trigger ClaimFor on the owner's stake
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` Owner (pays rent if the stake account needs to grow)
+    /// 3. `[]` System program (optional, required if the stake account needs to grow)
+    SetClaimForOptIn {
+        enabled: bool,
+    },
+
+    /// Replace the reward fee split: up to 3 (recipient, bps) entries paid
+    /// directly on every claim (authority or fees delegate, empty list = none)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or fees delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetRewardFeeSplit {
+        splits: Vec<(Pubkey, u16)>,
+    },
+
+    /// Grow a legacy pool account to the current layout (permissionless,
+    /// caller pays the rent; a no-op for current-size pools)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable, signer]` Payer
+    /// 2. `[]` System program
+    ExtendPoolAccount,
+
+    /// Undo the owner's last CompleteUnstake within the pool's reversal
+    /// window: the tokens go back into the vault with the stake's original
+    /// maturity and no new lock or cooldown
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable]` Token vault
+    /// 3. `[writable]` User token account
+    /// 4. `[]` Token mint
+    /// 5. `[signer]` User/owner
+    /// 6. `[]` Token program
+    ReverseUnstake,
+
+    /// Return min_stake_amount and the mint decimals so a UI can format the
+    /// minimum (read-only; return data: u64 LE followed by u8)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` Token mint (optional, only read for pools created before the
+    ///    decimals were cached)
+    QueryMinStakeUi,
+
+    /// Sync rewards only if the pool's lamport balance equals
+    /// `expected_balance` (authority or sync delegate, for audited reconciliation)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or sync delegate
+    ForceSyncWithBalance {
+        /// Exact pool lamport balance the caller expects
+        expected_balance: u64,
+    },
+
+    /// Choose which token accounts Unstake and CompleteUnstake may pay out
+    /// to (authority or settings delegate)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetUnstakeDestinationPolicy {
+        policy: UnstakeDestinationPolicy,
+    },
+
+    /// Seconds until a stake's tokens can be withdrawn: remaining lock plus
+    /// remaining (or, with no request yet, full) cooldown (read-only; return
+    /// data: u64 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryTimeToUnlock,
+
+    /// Lock and cooldown that apply to a stake, after stamped terms, lock
+    /// commitment and grandfathering (read-only; return data: lock u64 LE,
+    /// cooldown u64 LE, last stake time i64 LE, TERMS_FLAG_* u8)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryEffectiveSettings,
+
+    /// Pool accumulator, the stake's reward debt, amount, weight and claimed
+    /// rewards next to the resulting pending lamports, for debugging reward
+    /// discrepancies (read-only; return data: see
+    /// process_query_accumulator_snapshot)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryAccumulatorSnapshot,
+
+    /// Unstake the whole position of a quarantined pool right away, ignoring
+    /// the lock and unstake cooldown (owner only, quarantined pools only)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable]` Token vault
+    /// 3. `[writable]` User token account (any account of the pool mint owned by the user)
+    /// 4. `[]` Token mint
+    /// 5. `[writable, signer]` User/owner
+    /// 6. `[]` Token program
+    /// 7. `[]` System program (optional, grows a legacy stake account)
+    ForceUnstakeQuarantined,
+
+    /// Permanently freeze the economic settings: UpdatePoolSettings,
+    /// SetRewardFeeSplit and SetUnstakeDestinationPolicy then fail with
+    /// SettingsLocked (authority only, irreversible; other authority
+    /// instructions keep working)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    LockPoolSettings,
+
+    /// Estimated lamports distributed per second over the last one to two
+    /// hours (read-only; return data: rate u64 LE, window seconds u64 LE,
+    /// window lamports u64 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    QueryRewardRate,
+
+    /// Name the unit DepositRewards display values are tracked in, e.g.
+    /// "USDC" with 6 decimals (authority or display delegate; display only,
+    /// max 8 bytes, empty = none)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or display delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetDisplayUnit {
+        label: String,
+        decimals: u8,
+    },
+
+    /// Display unit label, decimals and display_total_distributed
+    /// (read-only; return data: label [u8; 8], decimals u8, total u64 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    QueryDisplayUnit,
+
+    /// Bitmask of the (pool, stake) pairs whose unstake request can be
+    /// completed now (read-only; return data: u32 LE, bit i = pair i)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account #1
+    /// 1. `[]` User stake account #1
+    ///
+    /// followed by further (pool, user stake) pairs, at most 32 in total.
+    QueryIsCompletableBatch,
+
+    /// Recreate the token vault at its canonical PDA and point the pool at
+    /// it (authority only, only while total_staked == 0). Creates a missing
+    /// vault, initializes an allocated-but-uninitialized one, and leaves a
+    /// valid one as is.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` Token vault (PDA: ["token_vault", pool])
+    /// 2. `[]` Token mint
+    /// 3. `[writable, signer]` Authority (pays the vault's rent)
+    /// 4. `[]` System program
+    /// 5. `[]` Token program (the mint's owner)
+    ReinitializeVault,
+
+    /// Credit chosen stakes' claimed_balance with a bonus paid by the funder,
+    /// bypassing the weighted accumulator (permissionless). `amounts[i]` goes
+    /// to user stake account #i; withdrawn with WithdrawClaimed.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable, signer]` Funder
+    /// 2. `[]` System program
+    /// 3. `[writable]` User stake account #1
+    ///
+    /// followed by one user stake account per further amount, at most 16 in total.
+    DistributeBonus {
+        amounts: Vec<u64>,
+    },
+
+    /// Details of a stake's pending unstake request (read-only; return data:
+    /// amount u64, request time i64, cooldown end i64, seconds remaining u64,
+    /// completable u8 — all zero with no request pending)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryPendingUnstake,
+
+    /// Start the reward accumulator at a coarser precision, 2^-shift of WAD
+    /// (authority or settings delegate; shift <= 32, only before any reward
+    /// is distributed)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetRewardPrecision {
+        shift: u8,
+    },
+
+    /// Claim pending rewards to the wallet, then close the stake account and
+    /// return its rent if it is empty (otherwise it stays open)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` User/owner
+    /// 3. `[]` System program (optional; required for legacy accounts or fee splits)
+    ///
+    /// followed by the fee split recipients, if any, optionally the pool
+    /// metadata account, and the default stake account when closing a
+    /// position above index 0.
+    ClaimAndCloseIfEmpty,
+
+    /// A stake's reward denominator contribution, its current weighted stake
+    /// and the pool's denominator (read-only; return data: amount * WAD,
+    /// weighted, total_staked * WAD as u128 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    QueryWeightedShare,
+
+    /// Grant a delegate the DELEGATE_PERM_* instructions in `permissions`,
+    /// replacing what it held; 0 revokes it (authority only, at most 4
+    /// delegates, dropped on TransferAuthority)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetDelegate {
+        delegate: Pubkey,
+        permissions: u8,
+    },
+
+    /// Report whether the pool is the sole pump.fun fee recipient and the
+    /// pfee sharing authority is revoked, i.e. TakeFeeOwnership took effect
+    /// (read-only; return data: two u8 bools)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` pfee SharingConfig (PDA on pfee: ["sharing-config", mint])
+    VerifyPfeeOwnership,
+
+    /// Lamports a claim would pay the stake after the next sync, unsynced
+    /// pool lamports included (read-only; return data: u64 LE, before the
+    /// claim fee and fee split, then the stake's last_claim_time as i64 LE
+    /// and total_rewards_claimed as u64 LE)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[]` User stake account
+    GetPendingRewards,
+
+    /// Claim accumulated SOL rewards to a recipient other than the owner,
+    /// who still signs (the recipient must be system-owned)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (holds SOL rewards)
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable, signer]` User/owner
+    /// 3. `[writable]` Reward recipient
+    /// 4. `[]` System program (optional; required for legacy accounts or fee splits)
+    ///
+    /// followed by the `[writable]` fee split recipients in pool order, if the pool has a split.
+    ClaimRewardsTo,
+
+    /// Move a whole stake to a new owner, keeping its maturity and any
+    /// pending unstake request; pending rewards are claimed to the current
+    /// owner first. The destination must not exist or be empty.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (holds SOL rewards)
+    /// 1. `[writable]` Source stake account
+    /// 2. `[writable]` Destination stake account (PDA: ["stake", pool, to_owner])
+    /// 3. `[writable, signer]` Current owner (receives rewards, pays rent)
+    /// 4. `[]` New owner
+    /// 5. `[]` System program
+    ///
+    /// followed by the fee split recipients, if any, and optionally the pool
+    /// metadata account.
+    TransferStake,
+
+    /// Change the pool's tau from now on (authority only). Weights are
+    /// continuous across the change; remaining maturation runs at the new
+    /// speed. Each change is at most 2x up or down; with a settings
+    /// timelock, raises wait for ApplySettings. Logs a TauChangedEvent.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    UpdateTau {
+        tau_seconds: u64,
+    },
+
+    /// Pay the pool's rewards in an SPL token instead of SOL (authority only,
+    /// before any distribution). Creates the reward vault; one-way.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable, signer]` Authority (pays for the reward vault)
+    /// 2. `[]` Reward mint
+    /// 3. `[writable]` Reward vault (PDA: ["reward_vault", pool])
+    /// 4. `[]` Token program of the reward mint
+    /// 5. `[]` System program
+    SetRewardMint,
+
+    /// Deposit SPL-token rewards into a token reward pool (permissionless,
+    /// unless the pool requires allowlisted depositors)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Depositor
+    /// 2. `[writable]` Depositor's reward token account
+    /// 3. `[writable]` Reward vault
+    /// 4. `[]` Reward mint
+    /// 5. `[]` Token program of the reward mint
+    ///
+    /// followed, as for DepositRewards, by the depositor's allowlist entry,
+    /// stake accounts (every position) and the current audit log page when
+    /// the pool needs them.
+    DepositTokenRewards {
+        /// Amount of reward tokens (base units) to deposit
+        amount: u64,
+    },
+
+    /// Claim accumulated rewards of a token reward pool
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[signer]` User/owner (writable if the stake account needs to grow)
+    /// 3. `[writable]` Reward vault
+    /// 4. `[writable]` Destination reward token account
+    /// 5. `[]` Reward mint
+    /// 6. `[]` Token program of the reward mint
+    /// 7. `[]` System program (optional, required for legacy accounts)
+    ClaimTokenRewards,
+
+    /// Renounce pool authority (irreversible; the pool's settings can no
+    /// longer be changed and its delegates are dropped)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Current authority
+    RenounceAuthority,
+
+    /// Pause or resume new stakes and reward deposits (authority only).
+    /// Unstaking, claiming and SyncRewards keep working while paused.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    SetPause { paused: bool },
+
+    /// Unstake without waiting out the unstake cooldown, paying the pool's
+    /// early_exit_penalty_bps of `amount` in tokens (kept in the vault, out of
+    /// total_staked). Rewards are settled as in Unstake; the lock still applies.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[writable]` Token vault
+    /// 3. `[writable]` User token account
+    /// 4. `[]` Token mint
+    /// 5. `[writable, signer]` User/owner
+    /// 6. `[]` Token 2022 program
+    /// 7. `[]` System program (optional, required for legacy accounts)
+    InstantUnstake { amount: u64 },
+
+    /// Propose a new pool authority (current authority only). The proposed
+    /// key takes over once it signs AcceptAuthority; a new proposal replaces
+    /// a pending one, and Pubkey::default() cancels it (it never renounces).
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Current authority (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    ProposeAuthority { new_authority: Pubkey },
+
+    /// Accept a pending authority proposal (the proposed key signs)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Pending authority
+    AcceptAuthority,
+
+    /// Harvest the mint's pump.fun creator fees into the pool and sync them
+    /// (permissionless crank, after TakeFeeOwnership)
+    ///
+    /// Moves PumpSwap's wSOL creator fees into pump's creator vault, has pump
+    /// distribute the vault to the pool, then syncs as SyncRewards does.
+    ///
+    /// Accounts: as TakeFeeOwnership, with the pool writable
+    /// 0. `[writable]` Pool account (PDA: ["pool", mint])
+    /// 1. `[]` Token mint
+    /// 2. `[]` pfee program
+    /// 3. `[]` pfee event authority
+    /// 4. `[]` pump global
+    /// 5. `[writable]` sharing config
+    /// 6. `[]` bonding curve
+    /// 7. `[writable]` pump creator vault
+    /// 8. `[]` system program
+    /// 9. `[]` pump program
+    /// 10. `[]` pump event authority
+    /// 11. `[]` pump AMM program
+    /// 12. `[]` AMM event authority
+    /// 13. `[]` wSOL mint
+    /// 14. `[]` token program
+    /// 15. `[]` associated token program
+    /// 16. `[writable]` coin creator vault authority
+    /// 17. `[writable]` coin creator vault ATA
+    ///
+    /// Optional trailing accounts: as SyncRewards
+    HarvestCreatorFees,
+
+    /// Split the mint's pump.fun creator fees between the pool and other
+    /// wallets, without revoking the fee sharing authority (authority only)
+    ///
+    /// The pool PDA must be the fee sharing admin. `shareholders` must hold
+    /// distinct non-zero shares summing to 10000 bps, one of them the pool's.
+    /// TakeFeeOwnership can still make the pool the sole recipient later.
+    ///
+    /// Accounts: as TakeFeeOwnership, then
+    /// 18. `[signer]` Pool authority
+    SetFeeShares {
+        /// (address, share_bps) entries, at most 10
+        shareholders: Vec<(Pubkey, u16)>,
+    },
+
+    /// Change the amount of a pending unstake request without cancelling it
+    ///
+    /// Lowering keeps the request time, so the wait never gets longer.
+    /// Raising restarts the cooldown for the whole request.
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` User stake account
+    /// 2. `[signer]` User/owner
+    /// 3. `[]` System program (optional, required for legacy accounts)
+    AmendUnstakeRequest {
+        amount: u64,
+    },
+
+    /// Sync rewards on several pools at once (permissionless crank)
+    ///
+    /// Pools with nothing to distribute are skipped; returns the number of
+    /// pools synced (u64 LE) via return data.
+    ///
+    /// Accounts:
+    /// 0..pool_count. `[writable]` Pool accounts, at most 10
+    ///
+    /// Optional trailing accounts: the pools' current audit log pages, then
+    /// as SyncRewards
+    SyncRewardsBatch {
+        pool_count: u8,
+    },
+
+    /// Deposit SOL rewards released linearly over `duration_seconds`
+    /// (permissionless, unless the pool requires allowlisted depositors)
+    ///
+    /// Each sync distributes what has been released since the last one; a
+    /// stream still running is merged in at the sum of both rates.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account (receives SOL)
+    /// 1. `[writable, signer]` Depositor
+    /// 2. `[]` System program
+    /// 3. `[]` Depositor's allowlist entry (optional, required when the pool
+    ///    requires allowlisted depositors)
+    DepositRewardsStreamed {
+        /// Amount of lamports to deposit
+        amount: u64,
+        /// Seconds over which the deposit is released (max 365 days)
+        duration_seconds: u64,
+    },
+
+    /// Close a fully drained SOL reward pool and its token vault
+    ///
+    /// Authority only, or permissionless once the authority is renounced.
+    /// Fails with PoolNotEmpty while tokens are staked or in the vault, or
+    /// while the pool holds lamports above its rent exemption.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` Token vault
+    /// 2. `[signer]` Pool authority (any account once renounced)
+    /// 3. `[writable]` Rent destination
+    /// 4. `[]` Token program
+    ClosePool,
+
+    /// Open an empty stake position at `index` for the signer
+    ///
+    /// Positions are independent stake accounts of the same wallet, each with
+    /// its own amount, rewards and lock terms. Stake and the other stake
+    /// instructions take whichever position account is passed.
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` Stake position (PDA: ["stake", pool, owner, index (u16 LE)];
+    ///    index 0 is the default ["stake", pool, owner])
+    /// 2. `[writable, signer]` Owner (pays rent)
+    /// 3. `[]` System program
+    ///
+    /// Optional trailing accounts (matched by key):
+    /// - `[writable]` Default stake account (PDA: ["stake", pool, owner]);
+    ///   required for an index above 0, it counts the open positions
+    /// - `[writable]` Pool metadata
+    ///
+    /// `index` must be below POSITION_SLOTS and at most one past the highest
+    /// index opened.
+    InitializeUserStake {
+        /// Position index
+        index: u16,
+    },
+
+    /// TVL, accumulator, undistributed lamports and reward rate in a
+    /// versioned, fixed-layout record (read-only; return data: see
+    /// process_get_pool_stats)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    GetPoolStats,
+
+    /// Allowlist `depositor` for reward deposits (authority or settings
+    /// delegate). Only enforced while require_allowlisted_depositors is set.
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` Allowlist entry (PDA: ["depositor", pool, depositor])
+    /// 2. `[writable, signer]` Authority or settings delegate (pays rent)
+    /// 3. `[]` System program
+    AddDepositor {
+        depositor: Pubkey,
+    },
+
+    /// Remove `depositor` from the allowlist, closing its entry (authority
+    /// or settings delegate)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    /// 1. `[writable]` Allowlist entry (PDA: ["depositor", pool, depositor])
+    /// 2. `[writable, signer]` Authority or settings delegate (receives rent)
+    RemoveDepositor {
+        depositor: Pubkey,
+    },
+
+    /// Apply the settings change pending behind the settings timelock once
+    /// it has run (authority or settings delegate)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Authority or settings delegate (writable if the pool needs to grow)
+    /// 2. `[]` System program (optional, required if a legacy pool needs to grow)
+    ApplySettings,
+
+    /// Settings change pending behind the settings timelock and when it may
+    /// apply (read-only; return data: see process_query_pending_settings)
+    ///
+    /// Accounts:
+    /// 0. `[]` Pool account
+    QueryPendingSettings,
+
+    /// Withdraw the rewards deferred while nothing is staked (authority
+    /// only; fails once tokens are staked)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Pool authority
+    /// 2. `[writable]` Recipient
+    ReclaimDeferredRewards,
+
+    /// Pay a fee split recipient the shares claims reserved because it
+    /// couldn't receive them (permissionless)
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[writable]` Fee split recipient
+    WithdrawFeeSplit,
+
+    /// Cancel a pending authority proposal (current authority only); the
+    /// proposed key can no longer accept
+    ///
+    /// Accounts:
+    /// 0. `[writable]` Pool account
+    /// 1. `[signer]` Current authority
+    CancelAuthorityProposal,
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
+entrypoint!(process_instruction);
+
+#[cfg(not(feature = "no-entrypoint"))]
+use solana_security_txt::security_txt;
+
+#[cfg(not(feature = "no-entrypoint"))]
+security_txt! {
+    name: "ChiefStaker",
+    project_url: "https://github.com/KarpelesLab/chiefstaker",
+    contacts: "link:https://github.com/KarpelesLab/chiefstaker/security/advisories",
+    policy: "https://github.com/KarpelesLab/chiefstaker/security/policy",
+    source_code: "https://github.com/KarpelesLab/chiefstaker"
+}
+
+/// Program entrypoint
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    // Verify this is the correct program
+    if program_id != &crate::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Deserialize instruction
+    let instruction = StakingInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    // Dispatch to appropriate handler
+    match instruction {
+        StakingInstruction::InitializePool {
+            tau_seconds,
+            enable_audit_log,
+            initial_settings,
+        } => {
+            msg!("Instruction: InitializePool (tau={}s)", tau_seconds);
+            process_initialize_pool(
+                program_id,
+                accounts,
+                tau_seconds,
+                enable_audit_log.0.unwrap_or(false),
+                initial_settings.0.map(PoolSettingsUpdate::from).unwrap_or_default(),
+            )
+        }
+        StakingInstruction::Stake { params } => {
+            msg!("Instruction: Stake (amount={})", params.amount);
+            process_stake(program_id, accounts, &params)
+        }
+        StakingInstruction::Unstake { amount } => {
+            msg!("Instruction: Unstake (amount={})", amount);
+            process_unstake(program_id, accounts, amount)
+        }
+        StakingInstruction::ClaimRewards { amount } => {
+            msg!("Instruction: ClaimRewards");
+            process_claim_rewards(program_id, accounts, amount.0)
+        }
+        StakingInstruction::DepositRewards {
+            amount,
+            nonce,
+            display_value,
+            referral,
+        } => {
+            msg!("Instruction: DepositRewards (amount={})", amount);
+            process_deposit_rewards(
+                program_id,
+                accounts,
+                amount,
+                nonce.0,
+                display_value.0,
+                referral.0,
+            )
+        }
+        StakingInstruction::SyncPool => {
+            msg!("Instruction: SyncPool");
+            process_sync_pool(program_id, accounts)
+        }
+        StakingInstruction::SyncRewards => {
+            msg!("Instruction: SyncRewards");
+            process_sync_rewards(program_id, accounts)
+        }
+        StakingInstruction::UpdatePoolSettings {
+            min_stake_amount,
+            lock_duration_seconds,
+            unstake_cooldown_seconds,
+            max_deferred_lamports,
+            loyalty_bonus_bps,
+            exclude_depositor,
+            per_stake_terms,
+            claim_fee_bps,
+            unstake_reversal_window_seconds,
+            min_distributable_per_event,
+            max_total_staked,
+            min_reward_deposit,
+            early_exit_penalty_bps,
+            lock_boost_bps,
+            require_allowlisted_depositors,
+            settings_timelock_seconds,
+        } => {
+            msg!("Instruction: UpdatePoolSettings");
+            process_update_pool_settings(
+                program_id,
+                accounts,
+                PoolSettingsUpdate {
+                    min_stake_amount,
+                    lock_duration_seconds,
+                    unstake_cooldown_seconds,
+                    max_deferred_lamports: max_deferred_lamports.0,
+                    loyalty_bonus_bps: loyalty_bonus_bps.0,
+                    exclude_depositor: exclude_depositor.0,
+                    per_stake_terms: per_stake_terms.0,
+                    claim_fee_bps: claim_fee_bps.0,
+                    unstake_reversal_window_seconds: unstake_reversal_window_seconds.0,
+                    min_distributable_per_event: min_distributable_per_event.0,
+                    max_total_staked: max_total_staked.0,
+                    min_reward_deposit: min_reward_deposit.0,
+                    early_exit_penalty_bps: early_exit_penalty_bps.0,
+                    lock_boost_bps: lock_boost_bps.0,
+                    require_allowlisted_depositors: require_allowlisted_depositors.0,
+                    settings_timelock_seconds: settings_timelock_seconds.0,
+                    fee_split: None,
+                },
+            )
+        }
+        StakingInstruction::TransferAuthority { new_authority } => {
+            msg!("Instruction: TransferAuthority");
+            process_transfer_authority(program_id, accounts, new_authority)
+        }
+        StakingInstruction::RequestUnstake { amount } => {
+            msg!("Instruction: RequestUnstake (amount={})", amount);
+            process_request_unstake(program_id, accounts, amount)
+        }
+        StakingInstruction::CompleteUnstake => {
+            msg!("Instruction: CompleteUnstake");
+            process_complete_unstake(program_id, accounts)
+        }
+        StakingInstruction::CancelUnstakeRequest => {
+            msg!("Instruction: CancelUnstakeRequest");
+            process_cancel_unstake_request(program_id, accounts)
+        }
+        StakingInstruction::CloseStakeAccount => {
+            msg!("Instruction: CloseStakeAccount");
+            process_close_stake_account(program_id, accounts)
+        }
+        StakingInstruction::DeprecatedFixTotalRewardDebt { .. } => {
+            msg!("Instruction: FixTotalRewardDebt (deprecated, no-op)");
+            Err(ProgramError::InvalidInstructionData)
+        }
+        StakingInstruction::SetPoolMetadata => {
+            msg!("Instruction: SetPoolMetadata");
+            process_set_pool_metadata(program_id, accounts)
+        }
+        StakingInstruction::TakeFeeOwnership => {
+            msg!("Instruction: TakeFeeOwnership");
+            process_take_fee_ownership(program_id, accounts)
+        }
+        StakingInstruction::StakeOnBehalf { amount } => {
+            msg!("Instruction: StakeOnBehalf (amount={})", amount);
+            process_stake_on_behalf(program_id, accounts, amount)
+        }
+        StakingInstruction::FixStakeAccount {
+            new_exp_start_factor,
+            new_reward_debt,
+        } => {
+            msg!("Instruction: FixStakeAccount");
+            process_fix_stake_account(
+                program_id,
+                accounts,
+                new_exp_start_factor,
+                new_reward_debt,
+            )
+        }
+        StakingInstruction::VerifyVaultConsistency => {
+            msg!("Instruction: VerifyVaultConsistency");
+            process_verify_vault_consistency(program_id, accounts)
+        }
+        StakingInstruction::ClaimToBalance => {
+            msg!("Instruction: ClaimToBalance");
+            process_claim_to_balance(program_id, accounts)
+        }
+        StakingInstruction::WithdrawClaimed => {
+            msg!("Instruction: WithdrawClaimed");
+            process_withdraw_claimed(program_id, accounts)
+        }
+        StakingInstruction::GrandfatherStake { effective_stake_time } => {
+            msg!("Instruction: GrandfatherStake (effective_stake_time={})", effective_stake_time);
+            process_grandfather_stake(program_id, accounts, effective_stake_time)
+        }
+        StakingInstruction::SplitStake { amount } => {
+            msg!("Instruction: SplitStake (amount={})", amount);
+            process_split_stake(program_id, accounts, amount)
+        }
+        StakingInstruction::QueryClaimableAt { future_timestamp } => {
+            msg!("Instruction: QueryClaimableAt (future_timestamp={})", future_timestamp);
+            process_query_claimable_at(program_id, accounts, future_timestamp)
+        }
+        StakingInstruction::CollectProtocolFees => {
+            msg!("Instruction: CollectProtocolFees");
+            process_collect_protocol_fees(program_id, accounts)
+        }
+        StakingInstruction::QuarantinePool => {
+            msg!("Instruction: QuarantinePool");
+            process_quarantine_pool(program_id, accounts)
+        }
+        StakingInstruction::ViewPoolHealth => {
+            msg!("Instruction: ViewPoolHealth");
+            process_view_pool_health(program_id, accounts)
+        }
+        StakingInstruction::BatchRequestUnstake { fail_on_pending } => {
+            msg!("Instruction: BatchRequestUnstake (fail_on_pending={})", fail_on_pending);
+            process_batch_request_unstake(program_id, accounts, fail_on_pending)
+        }
+        StakingInstruction::ClaimFor => {
+            msg!("Instruction: ClaimFor");
+            process_claim_for(program_id, accounts)
+        }
+        StakingInstruction::SetClaimForOptIn { enabled } => {
+            msg!("Instruction: SetClaimForOptIn (enabled={})", enabled);
+            process_set_claim_for_opt_in(program_id, accounts, enabled)
+        }
+        StakingInstruction::SetRewardFeeSplit { splits } => {
+            msg!("Instruction: SetRewardFeeSplit ({} recipients)", splits.len());
+            process_set_reward_fee_split(program_id, accounts, splits)
+        }
+        StakingInstruction::ExtendPoolAccount => {
+            msg!("Instruction: ExtendPoolAccount");
+            process_extend_pool_account(program_id, accounts)
+        }
+        StakingInstruction::ReverseUnstake => {
+            msg!("Instruction: ReverseUnstake");
+            process_reverse_unstake(program_id, accounts)
+        }
+        StakingInstruction::QueryMinStakeUi => {
+            msg!("Instruction: QueryMinStakeUi");
+            process_query_min_stake_ui(program_id, accounts)
+        }
+        StakingInstruction::ForceSyncWithBalance { expected_balance } => {
+            msg!("Instruction: ForceSyncWithBalance (expected_balance={})", expected_balance);
+            process_force_sync_with_balance(program_id, accounts, expected_balance)
+        }
+        StakingInstruction::SetUnstakeDestinationPolicy { policy } => {
+            msg!("Instruction: SetUnstakeDestinationPolicy ({:?})", policy);
+            process_set_unstake_destination_policy(program_id, accounts, policy)
+        }
+        StakingInstruction::QueryTimeToUnlock => {
+            msg!("Instruction: QueryTimeToUnlock");
+            process_query_time_to_unlock(program_id, accounts)
+        }
+        StakingInstruction::QueryEffectiveSettings => {
+            msg!("Instruction: QueryEffectiveSettings");
+            process_query_effective_settings(program_id, accounts)
+        }
+        StakingInstruction::QueryAccumulatorSnapshot => {
+            msg!("Instruction: QueryAccumulatorSnapshot");
+            process_query_accumulator_snapshot(program_id, accounts)
+        }
+        StakingInstruction::ForceUnstakeQuarantined => {
+            msg!("Instruction: ForceUnstakeQuarantined");
+            process_force_unstake_quarantined(program_id, accounts)
+        }
+        StakingInstruction::LockPoolSettings => {
+            msg!("Instruction: LockPoolSettings");
+            process_lock_pool_settings(program_id, accounts)
+        }
+        StakingInstruction::QueryRewardRate => {
+            msg!("Instruction: QueryRewardRate");
+            process_query_reward_rate(program_id, accounts)
+        }
+        StakingInstruction::SetDisplayUnit { label, decimals } => {
+            msg!("Instruction: SetDisplayUnit");
+            process_set_display_unit(program_id, accounts, &label, decimals)
+        }
+        StakingInstruction::QueryDisplayUnit => {
+            msg!("Instruction: QueryDisplayUnit");
+            process_query_display_unit(program_id, accounts)
+        }
+        StakingInstruction::QueryIsCompletableBatch => {
+            msg!("Instruction: QueryIsCompletableBatch");
+            process_query_is_completable_batch(program_id, accounts)
+        }
+        StakingInstruction::ReinitializeVault => {
+            msg!("Instruction: ReinitializeVault");
+            process_reinitialize_vault(program_id, accounts)
+        }
+        StakingInstruction::DistributeBonus { amounts } => {
+            msg!("Instruction: DistributeBonus");
+            process_distribute_bonus(program_id, accounts, &amounts)
+        }
+        StakingInstruction::QueryPendingUnstake => {
+            msg!("Instruction: QueryPendingUnstake");
+            process_query_pending_unstake(program_id, accounts)
+        }
+        StakingInstruction::SetRewardPrecision { shift } => {
+            msg!("Instruction: SetRewardPrecision");
+            process_set_reward_precision(program_id, accounts, shift)
+        }
+        StakingInstruction::ClaimAndCloseIfEmpty => {
+            msg!("Instruction: ClaimAndCloseIfEmpty");
+            process_claim_and_close_if_empty(program_id, accounts)
+        }
+        StakingInstruction::QueryWeightedShare => {
+            msg!("Instruction: QueryWeightedShare");
+            process_query_weighted_share(program_id, accounts)
+        }
+        StakingInstruction::SetDelegate {
+            delegate,
+            permissions,
+        } => {
+            msg!("Instruction: SetDelegate");
+            process_set_delegate(program_id, accounts, delegate, permissions)
+        }
+        StakingInstruction::VerifyPfeeOwnership => {
+            msg!("Instruction: VerifyPfeeOwnership");
+            process_verify_pfee_ownership(program_id, accounts)
+        }
+        StakingInstruction::GetPendingRewards => {
+            msg!("Instruction: GetPendingRewards");
+            process_get_pending_rewards(program_id, accounts)
+        }
+        StakingInstruction::ClaimRewardsTo => {
+            msg!("Instruction: ClaimRewardsTo");
+            process_claim_rewards_to(program_id, accounts)
+        }
+        StakingInstruction::TransferStake => {
+            msg!("Instruction: TransferStake");
+            process_transfer_stake(program_id, accounts)
+        }
+        StakingInstruction::UpdateTau { tau_seconds } => {
+            msg!("Instruction: UpdateTau (tau={}s)", tau_seconds);
+            process_update_tau(program_id, accounts, tau_seconds)
+        }
+        StakingInstruction::SetRewardMint => {
+            msg!("Instruction: SetRewardMint");
+            process_set_reward_mint(program_id, accounts)
+        }
+        StakingInstruction::DepositTokenRewards { amount } => {
+            msg!("Instruction: DepositTokenRewards");
+            process_deposit_token_rewards(program_id, accounts, amount)
+        }
+        StakingInstruction::ClaimTokenRewards => {
+            msg!("Instruction: ClaimTokenRewards");
+            process_claim_token_rewards(program_id, accounts)
+        }
+        StakingInstruction::RenounceAuthority => {
+            msg!("Instruction: RenounceAuthority");
+            process_renounce_authority(program_id, accounts)
+        }
+        StakingInstruction::SetPause { paused } => {
+            msg!("Instruction: SetPause");
+            process_set_pause(program_id, accounts, paused)
+        }
+        StakingInstruction::InstantUnstake { amount } => {
+            msg!("Instruction: InstantUnstake");
+            process_instant_unstake(program_id, accounts, amount)
+        }
+        StakingInstruction::ProposeAuthority { new_authority } => {
+            msg!("Instruction: ProposeAuthority");
+            process_propose_authority(program_id, accounts, new_authority)
+        }
+        StakingInstruction::AcceptAuthority => {
+            msg!("Instruction: AcceptAuthority");
+            process_accept_authority(program_id, accounts)
+        }
+        StakingInstruction::HarvestCreatorFees => {
+            msg!("Instruction: HarvestCreatorFees");
+            process_harvest_creator_fees(program_id, accounts)
+        }
+        StakingInstruction::SetFeeShares { shareholders } => {
+            msg!("Instruction: SetFeeShares ({} shareholders)", shareholders.len());
+            process_set_fee_shares(program_id, accounts, shareholders)
+        }
+        StakingInstruction::AmendUnstakeRequest { amount } => {
+            msg!("Instruction: AmendUnstakeRequest (amount={})", amount);
+            process_amend_unstake_request(program_id, accounts, amount)
+        }
+        StakingInstruction::SyncRewardsBatch { pool_count } => {
+            msg!("Instruction: SyncRewardsBatch (pool_count={})", pool_count);
+            process_sync_rewards_batch(program_id, accounts, pool_count)
+        }
+        StakingInstruction::DepositRewardsStreamed {
+            amount,
+            duration_seconds,
+        } => {
+            msg!(
+                "Instruction: DepositRewardsStreamed (amount={}, duration_seconds={})",
+                amount,
+                duration_seconds
+            );
+            process_deposit_rewards_streamed(program_id, accounts, amount, duration_seconds)
+        }
+        StakingInstruction::ClosePool => {
+            msg!("Instruction: ClosePool");
+            process_close_pool(program_id, accounts)
+        }
+        StakingInstruction::InitializeUserStake { index } => {
+            msg!("Instruction: InitializeUserStake (index={})", index);
+            process_initialize_user_stake(program_id, accounts, index)
+        }
+        StakingInstruction::GetPoolStats => {
+            msg!("Instruction: GetPoolStats");
+            process_get_pool_stats(program_id, accounts)
+        }
+        StakingInstruction::AddDepositor { depositor } => {
+            msg!("Instruction: AddDepositor");
+            process_add_depositor(program_id, accounts, depositor)
+        }
+        StakingInstruction::RemoveDepositor { depositor } => {
+            msg!("Instruction: RemoveDepositor");
+            process_remove_depositor(program_id, accounts, depositor)
+        }
+        StakingInstruction::ApplySettings => {
+            msg!("Instruction: ApplySettings");
+            process_apply_settings(program_id, accounts)
+        }
+        StakingInstruction::QueryPendingSettings => {
+            msg!("Instruction: QueryPendingSettings");
+            process_query_pending_settings(program_id, accounts)
+        }
+        StakingInstruction::ReclaimDeferredRewards => {
+            msg!("Instruction: ReclaimDeferredRewards");
+            process_reclaim_deferred_rewards(program_id, accounts)
+        }
+        StakingInstruction::WithdrawFeeSplit => {
+            msg!("Instruction: WithdrawFeeSplit");
+            process_withdraw_fee_split(program_id, accounts)
+        }
+        StakingInstruction::CancelAuthorityProposal => {
+            msg!("Instruction: CancelAuthorityProposal");
+            process_cancel_authority_proposal(program_id, accounts)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_serialization() {
+        let settings = InitialPoolSettings {
+            min_stake_amount: 1_000,
+            lock_duration_seconds: 86_400,
+            unstake_cooldown_seconds: 3_600,
+        };
+        let instruction = StakingInstruction::InitializePool {
+            tau_seconds: 2592000,
+            enable_audit_log: TrailingOption(Some(true)),
+            initial_settings: TrailingOption(Some(settings)),
+        };
+        let serialized = borsh::to_vec(&instruction).unwrap();
+        let deserialized: StakingInstruction =
+            BorshDeserialize::try_from_slice(&serialized).unwrap();
+
+        match deserialized {
+            StakingInstruction::InitializePool {
+                tau_seconds,
+                enable_audit_log,
+                initial_settings,
+            } => {
+                assert_eq!(tau_seconds, 2592000);
+                assert_eq!(enable_audit_log.0, Some(true));
+                assert_eq!(initial_settings.0, Some(settings));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+
+        // Clients built before the audit log stop after tau_seconds
+        let mut data = vec![0u8];
+        data.extend_from_slice(&2592000u64.to_le_bytes());
+        match StakingInstruction::try_from_slice(&data).unwrap() {
+            StakingInstruction::InitializePool {
+                enable_audit_log,
+                initial_settings,
+                ..
+            } => {
+                assert_eq!(enable_audit_log, TrailingOption(None));
+                assert_eq!(initial_settings, TrailingOption(None));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+
+        // Omitted settings keep the defaults; given ones go through the
+        // UpdatePoolSettings validation
+        assert_eq!(PoolSettingsUpdate::from(settings).lock_duration_seconds, Some(86_400));
+        assert_eq!(PoolSettingsUpdate::from(settings).claim_fee_bps, None);
+    }
+
+    #[test]
+    fn test_set_pause_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::SetPause { paused: true }).unwrap();
+        assert_eq!(data, [61, 1]);
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&[61, 0]).unwrap(),
+            StakingInstruction::SetPause { paused: false }
+        ));
+    }
+
+    #[test]
+    fn test_two_step_authority_encoding() {
+        let proposed = Pubkey::new_unique();
+        let data = borsh::to_vec(&StakingInstruction::ProposeAuthority {
+            new_authority: proposed,
+        })
+        .unwrap();
+        assert_eq!(data[0], 63);
+        assert_eq!(&data[1..], proposed.as_ref());
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&[64]).unwrap(),
+            StakingInstruction::AcceptAuthority
+        ));
+        assert!(matches!(
+            StakingInstruction::try_from_slice(&[79]).unwrap(),
+            StakingInstruction::CancelAuthorityProposal
+        ));
+    }
+
+    #[test]
+    fn test_set_fee_shares_encoding() {
+        let pool = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+        let data = borsh::to_vec(&StakingInstruction::SetFeeShares {
+            shareholders: vec![(pool, 8_000), (treasury, 2_000)],
+        })
+        .unwrap();
+
+        // Tag, u32 length, then 34 bytes per entry, exactly as pfee lays out
+        // its own Vec<Shareholder>
+        assert_eq!(data.len(), 1 + 4 + 2 * 34);
+        assert_eq!(data[0], 66);
+        assert_eq!(
+            &data[1..],
+            &pfee::update_fee_shares_data(&[(pool, 8_000), (treasury, 2_000)]).unwrap()[8..]
+        );
+        match StakingInstruction::try_from_slice(&data).unwrap() {
+            StakingInstruction::SetFeeShares { shareholders } => {
+                assert_eq!(shareholders, [(pool, 8_000), (treasury, 2_000)])
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_sync_rewards_batch_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::SyncRewardsBatch { pool_count: 3 }).unwrap();
+        assert_eq!(data, [68, 3]);
+        assert!(instructions::MAX_SYNC_REWARDS_BATCH <= u8::MAX as usize);
+    }
+
+    #[test]
+    fn test_deposit_rewards_streamed_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::DepositRewardsStreamed {
+            amount: 1_000,
+            duration_seconds: 86_400,
+        })
+        .unwrap();
+        assert_eq!(data[0], 69);
+        assert_eq!(data.len(), 17);
+        match StakingInstruction::try_from_slice(&data).unwrap() {
+            StakingInstruction::DepositRewardsStreamed {
+                amount,
+                duration_seconds,
+            } => {
+                assert_eq!(amount, 1_000);
+                assert_eq!(duration_seconds, 86_400);
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_close_pool_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::ClosePool).unwrap();
+        assert_eq!(data, [70]);
+    }
+
+    #[test]
+    fn test_initialize_user_stake_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::InitializeUserStake { index: 258 }).unwrap();
+        assert_eq!(data, [71, 2, 1]);
+    }
+
+    #[test]
+    fn test_get_pool_stats_encoding() {
+        let data = borsh::to_vec(&StakingInstruction::GetPoolStats).unwrap();
+        assert_eq!(data, [72]);
+    }
+
+    #[test]
+    fn test_depositor_allowlist_encoding() {
+        let depositor = Pubkey::new_unique();
+        let data = borsh::to_vec(&StakingInstruction::AddDepositor { depositor }).unwrap();
+        assert_eq!(data[0], 73);
+        assert_eq!(&data[1..], depositor.as_ref());
+        let data = borsh::to_vec(&StakingInstruction::RemoveDepositor { depositor }).unwrap();
+        assert_eq!(data[0], 74);
+        assert_eq!(&data[1..], depositor.as_ref());
+    }
+
+    #[test]
+    fn test_settings_timelock_encoding() {
+        assert_eq!(borsh::to_vec(&StakingInstruction::ApplySettings).unwrap(), [75]);
+        assert_eq!(borsh::to_vec(&StakingInstruction::QueryPendingSettings).unwrap(), [76]);
+    }
+
+    #[test]
+    fn test_reclaim_deferred_rewards_encoding() {
+        assert_eq!(borsh::to_vec(&StakingInstruction::ReclaimDeferredRewards).unwrap(), [77]);
+    }
+
+    #[test]
+    fn test_withdraw_fee_split_encoding() {
+        assert_eq!(borsh::to_vec(&StakingInstruction::WithdrawFeeSplit).unwrap(), [78]);
+    }
+
+    #[test]
+    fn test_claim_rewards_amount() {
+        // Clients built before partial claims send only the tag
+        match StakingInstruction::try_from_slice(&[3u8]).unwrap() {
+            StakingInstruction::ClaimRewards { amount } => {
+                assert_eq!(amount, TrailingOption(None))
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+
+        let instruction = StakingInstruction::ClaimRewards {
+            amount: TrailingOption(Some(1_000)),
+        };
+        let serialized = borsh::to_vec(&instruction).unwrap();
+        assert_eq!(serialized, [&[3u8, 1][..], &1_000u64.to_le_bytes()].concat());
+        match StakingInstruction::try_from_slice(&serialized).unwrap() {
+            StakingInstruction::ClaimRewards { amount } => assert_eq!(amount.0, Some(1_000)),
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_deposit_rewards_display_value() {
+        // Clients built before nonces stop after the amount
+        let mut data = vec![4u8];
+        data.extend_from_slice(&500u64.to_le_bytes());
+        match StakingInstruction::try_from_slice(&data).unwrap() {
+            StakingInstruction::DepositRewards {
+                amount,
+                nonce,
+                display_value,
+                referral,
+            } => {
+                assert_eq!(amount, 500);
+                assert_eq!(nonce, TrailingOption(None));
+                assert_eq!(display_value, TrailingOption(None));
+                assert_eq!(referral, TrailingOption(None));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+
+        let instruction = StakingInstruction::DepositRewards {
+            amount: 500,
+            nonce: TrailingOption(None),
+            display_value: TrailingOption(Some(12_500_000)),
+            referral: TrailingOption(None),
+        };
+        let serialized = borsh::to_vec(&instruction).unwrap();
+        assert_eq!(serialized.len(), 1 + 8 + 1 + 1 + 8 + 1);
+        match StakingInstruction::try_from_slice(&serialized).unwrap() {
+            StakingInstruction::DepositRewards { display_value, .. } => {
+                assert_eq!(display_value.0, Some(12_500_000));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_deposit_rewards_referral() {
+        let referral = DepositReferral {
+            referrer: Pubkey::new_unique(),
+            referral_bps: 250,
+        };
+        let instruction = StakingInstruction::DepositRewards {
+            amount: 500,
+            nonce: TrailingOption(None),
+            display_value: TrailingOption(None),
+            referral: TrailingOption(Some(referral)),
+        };
+        let serialized = borsh::to_vec(&instruction).unwrap();
+        assert_eq!(serialized.len(), 1 + 8 + 1 + 1 + 1 + 32 + 8);
+        match StakingInstruction::try_from_slice(&serialized).unwrap() {
+            StakingInstruction::DepositRewards { referral: decoded, .. } => {
+                assert_eq!(decoded.0, Some(referral));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_stake_instruction() {
+        let referrer = Pubkey::new_unique();
+        let instruction = StakingInstruction::Stake {
+            params: StakeParams {
+                amount: 1_000_000,
+                referrer: TrailingOption(Some(referrer)),
+                lock_commit_seconds: TrailingOption(Some(86_400)),
+                top_up_mode: TrailingOption(Some(TopUpMode::KeepLock)),
+            },
+        };
+        let serialized = borsh::to_vec(&instruction).unwrap();
+        let deserialized: StakingInstruction =
+            BorshDeserialize::try_from_slice(&serialized).unwrap();
+
+        match deserialized {
+            StakingInstruction::Stake { params } => {
+                assert_eq!(params.amount, 1_000_000);
+                assert_eq!(params.referrer.0, Some(referrer));
+                assert_eq!(params.lock_commit_seconds.0, Some(86_400));
+                assert_eq!(params.top_up_mode.0, Some(TopUpMode::KeepLock));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+
+        // The original amount-only encoding is still accepted
+        let mut data = vec![1u8];
+        data.extend_from_slice(&1_000_000u64.to_le_bytes());
+        match StakingInstruction::try_from_slice(&data).unwrap() {
+            StakingInstruction::Stake { params } => assert_eq!(
+                params,
+                StakeParams {
+                    amount: 1_000_000,
+                    ..Default::default()
+                }
+            ),
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_update_settings_legacy_layout() {
+        // Clients built before max_deferred_lamports was added stop after
+        // the third Option
+        let mut data = vec![7u8];
+        data.extend_from_slice(&[1, 100, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0]);
+        let deserialized: StakingInstruction =
+            BorshDeserialize::try_from_slice(&data).unwrap();
+
+        match deserialized {
+            StakingInstruction::UpdatePoolSettings {
+                min_stake_amount,
+                lock_duration_seconds,
+                unstake_cooldown_seconds,
+                max_deferred_lamports,
+                loyalty_bonus_bps,
+                exclude_depositor,
+                per_stake_terms,
+                claim_fee_bps,
+                unstake_reversal_window_seconds,
+                min_distributable_per_event,
+                max_total_staked,
+                min_reward_deposit,
+                early_exit_penalty_bps,
+                lock_boost_bps,
+                require_allowlisted_depositors,
+                settings_timelock_seconds,
+            } => {
+                assert_eq!(min_stake_amount, Some(100));
+                assert_eq!(lock_duration_seconds, None);
+                assert_eq!(unstake_cooldown_seconds, None);
+                assert_eq!(max_deferred_lamports, TrailingOption(None));
+                assert_eq!(loyalty_bonus_bps, TrailingOption(None));
+                assert_eq!(exclude_depositor, TrailingOption(None));
+                assert_eq!(per_stake_terms, TrailingOption(None));
+                assert_eq!(claim_fee_bps, TrailingOption(None));
+                assert_eq!(unstake_reversal_window_seconds, TrailingOption(None));
+                assert_eq!(min_distributable_per_event, TrailingOption(None));
+                assert_eq!(max_total_staked, TrailingOption(None));
+                assert_eq!(min_reward_deposit, TrailingOption(None));
+                assert_eq!(early_exit_penalty_bps, TrailingOption(None));
+                assert_eq!(lock_boost_bps, TrailingOption(None));
+                assert_eq!(require_allowlisted_depositors, TrailingOption(None));
+                assert_eq!(settings_timelock_seconds, TrailingOption(None));
+            }
+            _ => panic!("Wrong instruction type"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_option_roundtrip() {
+        let instruction = StakingInstruction::UpdatePoolSettings {
+            min_stake_amount: None,
+            lock_duration_seconds: None,
+            unstake_cooldown_seconds: None,
+            max_deferred_lamports: TrailingOption(Some(5_000_000_000)),
+            loyalty_bonus_bps: TrailingOption(None),
+            exclude_depositor: TrailingOption(Some(true)),
+            per_stake_terms: TrailingOption(None),
+            claim_fee_bps: TrailingOption(None),
+            unstake_reversal_window_seconds: TrailingOption(None),
+            min_distributable_per_event: TrailingOption(Some(1_000_000)),
+            max_total_staked: TrailingOption(Some(u64::MAX)),
+            min_reward_deposit: TrailingOption(None),
+            early_exit_penalty_bps: TrailingOption(Some(500)),
+            lock_boost_bps: TrailingOption(Some(10_000)),
+            require_allowlisted_depositors: TrailingOption(Some(true)),
+            settings_timelock_seconds: TrailingOption(Some(86_400)),
+        };
         let serialized = borsh::to_vec(&instruction).unwrap();
         let deserialized: StakingInstruction =
             BorshDeserialize::try_from_slice(&serialized).unwrap();
 
         match deserialized {
-            StakingInstruction::Stake { amount } => {
-                assert_eq!(amount, 1_000_000);
+            StakingInstruction::UpdatePoolSettings {
+                max_deferred_lamports,
+                exclude_depositor,
+                min_distributable_per_event,
+                max_total_staked,
+                early_exit_penalty_bps,
+                lock_boost_bps,
+                require_allowlisted_depositors,
+                settings_timelock_seconds,
+                ..
+            } => {
+                assert_eq!(max_deferred_lamports.0, Some(5_000_000_000));
+                assert_eq!(exclude_depositor.0, Some(true));
+                assert_eq!(min_distributable_per_event.0, Some(1_000_000));
+                assert_eq!(max_total_staked.0, Some(u64::MAX));
+                assert_eq!(early_exit_penalty_bps.0, Some(500));
+                assert_eq!(lock_boost_bps.0, Some(10_000));
+                assert_eq!(require_allowlisted_depositors.0, Some(true));
+                assert_eq!(settings_timelock_seconds.0, Some(86_400));
             }
             _ => panic!("Wrong instruction type"),
         }
@@ -302,6 +302,67 @@ pub fn calculate_user_weighted_stake(
     wad_mul((amount as u128).checked_mul(WAD).ok_or(StakingError::MathOverflow)?, weight_factor)
 }
 
+/// Basis-point denominator (10000 bps = 100%)
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Apply the optional loyalty bonus to a user's weighted stake.
+///
+/// The bonus ramps linearly from 0 to `bonus_bps` over one tau since the
+/// user's last claim, and the result is capped at the max weight
+/// (`amount * WAD`). Since rewards are distributed against max weight, the
+/// bonus only speeds up maturation and never creates liability beyond what
+/// was deposited.
+pub fn apply_loyalty_bonus(
+    weighted: u128,
+    amount: u64,
+    bonus_bps: u64,
+    since_last_claim: i64,
+    tau: u64,
+) -> Result<u128, StakingError> {
+    if bonus_bps == 0 || since_last_claim <= 0 || tau == 0 {
+        return Ok(weighted);
+    }
+
+    let elapsed = (since_last_claim as u64).min(tau);
+    let effective_bps = (bonus_bps as u128) * (elapsed as u128) / (tau as u128);
+
+    apply_weight_boost(weighted, amount, effective_bps as u64)
+}
+
+/// Lock boost (bps) earned by a voluntary lock commitment of
+/// `lock_commit_seconds`: linear from 0 to `max_boost_bps` at
+/// `max_lock_seconds` (so 10000 bps doubles the weight at max lock).
+pub fn lock_boost_bps(lock_commit_seconds: u64, max_lock_seconds: u64, max_boost_bps: u64) -> u64 {
+    if max_boost_bps == 0 || max_lock_seconds == 0 {
+        return 0;
+    }
+    let commit = lock_commit_seconds.min(max_lock_seconds);
+    ((max_boost_bps as u128) * (commit as u128) / (max_lock_seconds as u128)) as u64
+}
+
+/// Multiply a weighted stake by `(10000 + boost_bps) / 10000`, capped at the
+/// max weight (`amount * WAD`) so a boost never promises more than was
+/// deposited.
+pub fn apply_weight_boost(
+    weighted: u128,
+    amount: u64,
+    boost_bps: u64,
+) -> Result<u128, StakingError> {
+    let max_weight = (amount as u128)
+        .checked_mul(WAD)
+        .ok_or(StakingError::MathOverflow)?;
+    if boost_bps == 0 {
+        return Ok(weighted);
+    }
+
+    let boosted = U256::from_u128(weighted)
+        .checked_mul(U256::from_u128(BPS_DENOMINATOR as u128 + boost_bps as u128))
+        .ok_or(StakingError::MathOverflow)?
+        / U256::from_u128(BPS_DENOMINATOR as u128);
+
+    Ok(boosted.to_u128().unwrap_or(max_weight).min(max_weight))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -665,4 +726,55 @@ mod tests {
             w_combined, w_sum, diff
         );
     }
+
+    #[test]
+    fn test_loyalty_bonus_ramps_and_caps() {
+        let tau = 1000u64;
+        let amount = 1_000u64;
+        let weighted = 500 * WAD;
+
+        // Disabled or just claimed: unchanged
+        assert_eq!(apply_loyalty_bonus(weighted, amount, 0, 500, tau).unwrap(), weighted);
+        assert_eq!(apply_loyalty_bonus(weighted, amount, 1000, 0, tau).unwrap(), weighted);
+
+        // Half a tau since last claim: half of the 10% bonus
+        assert_eq!(apply_loyalty_bonus(weighted, amount, 1000, 500, tau).unwrap(), 525 * WAD);
+
+        // Ramp stops at one tau
+        assert_eq!(apply_loyalty_bonus(weighted, amount, 1000, 5000, tau).unwrap(), 550 * WAD);
+
+        // Never exceeds max weight
+        let near_max = 990 * WAD;
+        assert_eq!(
+            apply_loyalty_bonus(near_max, amount, 1000, 5000, tau).unwrap(),
+            amount as u128 * WAD
+        );
+    }
+
+    #[test]
+    fn test_lock_boost_curve() {
+        let year = 365 * 24 * 60 * 60;
+
+        // Off, no commitment, and linear up to the max at a full year
+        assert_eq!(lock_boost_bps(year, year, 0), 0);
+        assert_eq!(lock_boost_bps(0, year, 10_000), 0);
+        assert_eq!(lock_boost_bps(90 * 24 * 60 * 60, year, 10_000), 2_465);
+        assert_eq!(lock_boost_bps(year / 2, year, 10_000), 5_000);
+        assert_eq!(lock_boost_bps(year, year, 10_000), 10_000);
+        assert_eq!(lock_boost_bps(u64::MAX, year, 10_000), 10_000);
+
+        // 2x weight, capped at max weight
+        let amount = 1_000u64;
+        assert_eq!(apply_weight_boost(100 * WAD, amount, 10_000).unwrap(), 200 * WAD);
+        assert_eq!(apply_weight_boost(100 * WAD, amount, 0).unwrap(), 100 * WAD);
+        assert_eq!(
+            apply_weight_boost(600 * WAD, amount, 10_000).unwrap(),
+            amount as u128 * WAD
+        );
+        let whale = u64::MAX;
+        assert_eq!(
+            apply_weight_boost(whale as u128 * WAD, whale, 10_000).unwrap(),
+            whale as u128 * WAD
+        );
+    }
 }
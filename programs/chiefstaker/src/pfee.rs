@@ -0,0 +1,459 @@
+//! pump.fun fee sharing (pfee) account layout used by TakeFeeOwnership,
+//! SetFeeShares and VerifyPfeeOwnership
+//!
+//! Every account the pfee CPIs touch is either a fixed program ID or a PDA
+//! derived from the mint, so the whole set can be checked before the first
+//! CPI runs.
+
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey, system_program};
+
+use crate::{
+    error::StakingError,
+    state::{
+        PFEE_PROGRAM_ID, PFEE_SHARING_CONFIG_DISC, PUMP_AMM_PROGRAM_ID, PUMP_PROGRAM_ID,
+        SPL_TOKEN_PROGRAM_ID,
+    },
+};
+
+/// Wrapped SOL mint (So11111111111111111111111111111111111111112)
+pub const WSOL_MINT: Pubkey = Pubkey::new_from_array([
+    0x06, 0x9b, 0x88, 0x57, 0xfe, 0xab, 0x81, 0x84,
+    0xfb, 0x68, 0x7f, 0x63, 0x46, 0x18, 0xc0, 0x35,
+    0xda, 0xc4, 0x39, 0xdc, 0x1a, 0xeb, 0x3b, 0x55,
+    0x98, 0xa0, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x01,
+]);
+
+/// Associated Token Account program ID (ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL)
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    0x8c, 0x97, 0x25, 0x8f, 0x4e, 0x24, 0x89, 0xf1,
+    0xbb, 0x3d, 0x10, 0x29, 0x14, 0x8e, 0x0d, 0x83,
+    0x0b, 0x5a, 0x13, 0x99, 0xda, 0xff, 0x10, 0x84,
+    0x04, 0x8e, 0x7b, 0xd8, 0xdb, 0xe9, 0xf8, 0x59,
+]);
+
+/// Anchor event authority seed (pfee, pump and PumpSwap)
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+/// pfee: ["sharing-config", mint]
+pub const SHARING_CONFIG_SEED: &[u8] = b"sharing-config";
+/// pump: ["global"]
+pub const PUMP_GLOBAL_SEED: &[u8] = b"global";
+/// pump: ["bonding-curve", mint]
+pub const BONDING_CURVE_SEED: &[u8] = b"bonding-curve";
+/// pump: ["creator-vault", creator]
+pub const PUMP_CREATOR_VAULT_SEED: &[u8] = b"creator-vault";
+/// PumpSwap: ["creator_vault", creator]
+pub const AMM_CREATOR_VAULT_SEED: &[u8] = b"creator_vault";
+
+/// update_fee_shares discriminator
+pub const UPDATE_FEE_SHARES_DISC: [u8; 8] = [189, 13, 136, 99, 187, 164, 237, 35];
+
+/// Most shareholders pfee's update_fee_shares accepts
+pub const MAX_SHAREHOLDERS: usize = 10;
+
+/// Borsh size of one pfee Shareholder { address: Pubkey, share_bps: u16 }
+const SHAREHOLDER_LEN: usize = 32 + 2;
+
+/// Instruction data for update_fee_shares: discriminator followed by
+/// Vec<Shareholder> (u32 LE length, then address and share_bps per entry).
+/// More than MAX_SHAREHOLDERS entries is rejected before any CPI is built.
+pub fn update_fee_shares_data(shareholders: &[(Pubkey, u16)]) -> Result<Vec<u8>, StakingError> {
+    if shareholders.len() > MAX_SHAREHOLDERS {
+        msg!(
+            "{} shareholders exceeds the pfee maximum of {}",
+            shareholders.len(),
+            MAX_SHAREHOLDERS
+        );
+        return Err(StakingError::TooManyShareholders);
+    }
+
+    let mut data = Vec::with_capacity(8 + 4 + shareholders.len() * SHAREHOLDER_LEN);
+    data.extend_from_slice(&UPDATE_FEE_SHARES_DISC);
+    data.extend_from_slice(&(shareholders.len() as u32).to_le_bytes());
+    for (address, share_bps) in shareholders {
+        data.extend_from_slice(address.as_ref());
+        data.extend_from_slice(&share_bps.to_le_bytes());
+    }
+    Ok(data)
+}
+
+/// Check a full share table for update_fee_shares: at most MAX_SHAREHOLDERS
+/// distinct, non-default addresses with non-zero shares summing to 10000 bps,
+/// one of them `pool` so the stakers keep a cut.
+pub fn validate_fee_shares(
+    shareholders: &[(Pubkey, u16)],
+    pool: &Pubkey,
+) -> Result<(), StakingError> {
+    if shareholders.len() > MAX_SHAREHOLDERS {
+        return Err(StakingError::TooManyShareholders);
+    }
+    for (i, (address, share_bps)) in shareholders.iter().enumerate() {
+        if *share_bps == 0
+            || *address == Pubkey::default()
+            || shareholders[..i].iter().any(|(other, _)| other == address)
+        {
+            msg!("Invalid fee share entry {}", i);
+            return Err(StakingError::InvalidFeeShares);
+        }
+    }
+    if !shareholders.iter().any(|(address, _)| address == pool) {
+        msg!("The pool must be one of the shareholders");
+        return Err(StakingError::InvalidFeeShares);
+    }
+    let total_bps: u32 = shareholders.iter().map(|(_, bps)| *bps as u32).sum();
+    if total_bps != 10_000 {
+        msg!("Fee shares sum to {} bps, expected 10000", total_bps);
+        return Err(StakingError::InvalidFeeShares);
+    }
+    Ok(())
+}
+
+/// SharingConfig offsets (Anchor account): discriminator (0..8), bump (8),
+/// version (9), status (10), mint (11..43), admin (43..75), admin_revoked
+/// (75), then shareholders as Vec<Shareholder>
+const SHARING_CONFIG_MINT_OFFSET: usize = 11;
+const SHARING_CONFIG_ADMIN_OFFSET: usize = 43;
+const SHARING_CONFIG_ADMIN_REVOKED_OFFSET: usize = 75;
+const SHARING_CONFIG_SHAREHOLDERS_OFFSET: usize = 76;
+
+/// The parts of a pfee SharingConfig that decide who owns the fees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharingConfig {
+    pub mint: Pubkey,
+    pub admin: Pubkey,
+    pub admin_revoked: bool,
+    pub shareholders: Vec<(Pubkey, u16)>,
+}
+
+impl SharingConfig {
+    /// Parse a SharingConfig account's data. Fails with `InvalidAccountData`
+    /// on a wrong discriminator, truncated data or more than
+    /// MAX_SHAREHOLDERS entries.
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < SHARING_CONFIG_SHAREHOLDERS_OFFSET + 4
+            || data[..8] != PFEE_SHARING_CONFIG_DISC
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let pubkey_at = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).unwrap();
+
+        let mut len_bytes = [0u8; 4];
+        len_bytes.copy_from_slice(
+            &data[SHARING_CONFIG_SHAREHOLDERS_OFFSET..SHARING_CONFIG_SHAREHOLDERS_OFFSET + 4],
+        );
+        let count = u32::from_le_bytes(len_bytes) as usize;
+        let start = SHARING_CONFIG_SHAREHOLDERS_OFFSET + 4;
+        if count > MAX_SHAREHOLDERS || data.len() < start + count * SHAREHOLDER_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let shareholders = (0..count)
+            .map(|i| {
+                let offset = start + i * SHAREHOLDER_LEN;
+                let share_bps = u16::from_le_bytes([data[offset + 32], data[offset + 33]]);
+                (pubkey_at(offset), share_bps)
+            })
+            .collect();
+
+        Ok(Self {
+            mint: pubkey_at(SHARING_CONFIG_MINT_OFFSET),
+            admin: pubkey_at(SHARING_CONFIG_ADMIN_OFFSET),
+            admin_revoked: data[SHARING_CONFIG_ADMIN_REVOKED_OFFSET] != 0,
+            shareholders,
+        })
+    }
+
+    /// Whether `recipient` is the only shareholder, with all 10000 bps
+    pub fn is_sole_recipient(&self, recipient: &Pubkey) -> bool {
+        self.shareholders == [(*recipient, 10_000)]
+    }
+}
+
+/// Addresses of the external accounts TakeFeeOwnership passes to pfee
+/// (accounts 3-17, in instruction order)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeOwnershipAccounts {
+    pub pfee_event_authority: Pubkey,
+    pub pump_global: Pubkey,
+    pub sharing_config: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub pump_creator_vault: Pubkey,
+    pub system_program: Pubkey,
+    pub pump_program: Pubkey,
+    pub pump_event_authority: Pubkey,
+    pub pump_amm_program: Pubkey,
+    pub amm_event_authority: Pubkey,
+    pub wsol_mint: Pubkey,
+    pub token_program: Pubkey,
+    pub associated_token_program: Pubkey,
+    pub coin_creator_vault_auth: Pubkey,
+    pub coin_creator_vault_ata: Pubkey,
+}
+
+impl FeeOwnershipAccounts {
+    /// Derive the expected set for `mint`
+    pub fn expected(mint: &Pubkey) -> Self {
+        let pda = |seeds: &[&[u8]], program: &Pubkey| Pubkey::find_program_address(seeds, program).0;
+
+        let sharing_config = pda(&[SHARING_CONFIG_SEED, mint.as_ref()], &PFEE_PROGRAM_ID);
+        let coin_creator_vault_auth =
+            pda(&[AMM_CREATOR_VAULT_SEED, sharing_config.as_ref()], &PUMP_AMM_PROGRAM_ID);
+        let coin_creator_vault_ata = pda(
+            &[
+                coin_creator_vault_auth.as_ref(),
+                SPL_TOKEN_PROGRAM_ID.as_ref(),
+                WSOL_MINT.as_ref(),
+            ],
+            &ASSOCIATED_TOKEN_PROGRAM_ID,
+        );
+
+        Self {
+            pfee_event_authority: pda(&[EVENT_AUTHORITY_SEED], &PFEE_PROGRAM_ID),
+            pump_global: pda(&[PUMP_GLOBAL_SEED], &PUMP_PROGRAM_ID),
+            sharing_config,
+            bonding_curve: pda(&[BONDING_CURVE_SEED, mint.as_ref()], &PUMP_PROGRAM_ID),
+            pump_creator_vault: pda(
+                &[PUMP_CREATOR_VAULT_SEED, sharing_config.as_ref()],
+                &PUMP_PROGRAM_ID,
+            ),
+            system_program: system_program::ID,
+            pump_program: PUMP_PROGRAM_ID,
+            pump_event_authority: pda(&[EVENT_AUTHORITY_SEED], &PUMP_PROGRAM_ID),
+            pump_amm_program: PUMP_AMM_PROGRAM_ID,
+            amm_event_authority: pda(&[EVENT_AUTHORITY_SEED], &PUMP_AMM_PROGRAM_ID),
+            wsol_mint: WSOL_MINT,
+            token_program: SPL_TOKEN_PROGRAM_ID,
+            associated_token_program: ASSOCIATED_TOKEN_PROGRAM_ID,
+            coin_creator_vault_auth,
+            coin_creator_vault_ata,
+        }
+    }
+
+    /// Compare against the expected set for `mint`, naming the first
+    /// mismatching account. Programs fail with `IncorrectProgramId`, PDAs
+    /// with `InvalidPDA` and the wSOL mint with `InvalidArgument`.
+    pub fn check(&self, mint: &Pubkey) -> Result<(), ProgramError> {
+        let expected = Self::expected(mint);
+        let pda = || ProgramError::from(StakingError::InvalidPDA);
+        let program = || ProgramError::IncorrectProgramId;
+        let checks: [(&str, &Pubkey, &Pubkey, ProgramError); 15] = [
+            ("pfee_event_authority", &self.pfee_event_authority, &expected.pfee_event_authority, pda()),
+            ("pump_global", &self.pump_global, &expected.pump_global, pda()),
+            ("sharing_config", &self.sharing_config, &expected.sharing_config, pda()),
+            ("bonding_curve", &self.bonding_curve, &expected.bonding_curve, pda()),
+            ("pump_creator_vault", &self.pump_creator_vault, &expected.pump_creator_vault, pda()),
+            ("system_program", &self.system_program, &expected.system_program, program()),
+            ("pump_program", &self.pump_program, &expected.pump_program, program()),
+            ("pump_event_authority", &self.pump_event_authority, &expected.pump_event_authority, pda()),
+            ("pump_amm_program", &self.pump_amm_program, &expected.pump_amm_program, program()),
+            ("amm_event_authority", &self.amm_event_authority, &expected.amm_event_authority, pda()),
+            ("wsol_mint", &self.wsol_mint, &expected.wsol_mint, ProgramError::InvalidArgument),
+            ("token_program", &self.token_program, &expected.token_program, program()),
+            ("associated_token_program", &self.associated_token_program, &expected.associated_token_program, program()),
+            ("coin_creator_vault_auth", &self.coin_creator_vault_auth, &expected.coin_creator_vault_auth, pda()),
+            ("coin_creator_vault_ata", &self.coin_creator_vault_ata, &expected.coin_creator_vault_ata, pda()),
+        ];
+        for (name, actual, expected, err) in checks {
+            if actual != expected {
+                msg!("Unexpected {} account: {} (expected {})", name, actual, expected);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wsol_and_ata_program_ids() {
+        let wsol: Pubkey = "So11111111111111111111111111111111111111112".parse().unwrap();
+        let ata: Pubkey = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL".parse().unwrap();
+        assert_eq!(WSOL_MINT, wsol);
+        assert_eq!(ASSOCIATED_TOKEN_PROGRAM_ID, ata);
+    }
+
+    #[test]
+    fn test_update_fee_shares_data() {
+        // Sole shareholder: the 46 bytes TakeFeeOwnership sends
+        let pool = Pubkey::new_unique();
+        let data = update_fee_shares_data(&[(pool, 10_000)]).unwrap();
+        assert_eq!(data.len(), 8 + 4 + 34);
+        assert_eq!(data[..8], UPDATE_FEE_SHARES_DISC);
+        assert_eq!(data[8..12], 1u32.to_le_bytes());
+        assert_eq!(data[12..44], pool.to_bytes());
+        assert_eq!(data[44..46], 10_000u16.to_le_bytes());
+
+        // At the maximum every entry lands at its exact offset
+        let shareholders: Vec<(Pubkey, u16)> = (0..MAX_SHAREHOLDERS)
+            .map(|i| (Pubkey::new_unique(), 1_000 + i as u16))
+            .collect();
+        let data = update_fee_shares_data(&shareholders).unwrap();
+        assert_eq!(data.len(), 8 + 4 + MAX_SHAREHOLDERS * 34);
+        assert_eq!(data.capacity(), data.len());
+        assert_eq!(data[8..12], (MAX_SHAREHOLDERS as u32).to_le_bytes());
+        for (i, (address, share_bps)) in shareholders.iter().enumerate() {
+            let offset = 12 + i * 34;
+            assert_eq!(data[offset..offset + 32], address.to_bytes());
+            assert_eq!(data[offset + 32..offset + 34], share_bps.to_le_bytes());
+        }
+
+        // One more is rejected
+        let mut too_many = shareholders;
+        too_many.push((Pubkey::new_unique(), 1));
+        assert_eq!(
+            update_fee_shares_data(&too_many),
+            Err(StakingError::TooManyShareholders)
+        );
+    }
+
+    #[test]
+    fn test_validate_fee_shares() {
+        let pool = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+
+        // TakeFeeOwnership's table and an 80/20 split both pass
+        assert_eq!(validate_fee_shares(&[(pool, 10_000)], &pool), Ok(()));
+        assert_eq!(validate_fee_shares(&[(pool, 8_000), (treasury, 2_000)], &pool), Ok(()));
+        let data = update_fee_shares_data(&[(pool, 8_000), (treasury, 2_000)]).unwrap();
+        assert_eq!(data.len(), 8 + 4 + 2 * 34);
+        assert_eq!(data[46..78], treasury.to_bytes());
+        assert_eq!(data[78..80], 2_000u16.to_le_bytes());
+
+        let invalid: [&[(Pubkey, u16)]; 7] = [
+            &[],
+            &[(pool, 8_000), (treasury, 1_999)],
+            &[(pool, 8_000), (treasury, 2_001)],
+            &[(pool, 10_000), (treasury, 0)],
+            &[(pool, 5_000), (pool, 5_000)],
+            &[(pool, 8_000), (Pubkey::default(), 2_000)],
+            &[(treasury, 10_000)],
+        ];
+        for (i, shares) in invalid.iter().enumerate() {
+            assert_eq!(
+                validate_fee_shares(shares, &pool),
+                Err(StakingError::InvalidFeeShares),
+                "invalid table {} accepted",
+                i
+            );
+        }
+
+        // u16 shares can't overflow the sum
+        assert_eq!(
+            validate_fee_shares(&[(pool, u16::MAX), (treasury, u16::MAX)], &pool),
+            Err(StakingError::InvalidFeeShares)
+        );
+
+        let too_many: Vec<(Pubkey, u16)> = (0..=MAX_SHAREHOLDERS)
+            .map(|i| (if i == 0 { pool } else { Pubkey::new_unique() }, 1))
+            .collect();
+        assert_eq!(
+            validate_fee_shares(&too_many, &pool),
+            Err(StakingError::TooManyShareholders)
+        );
+    }
+
+    /// SharingConfig account data as pfee lays it out
+    fn sharing_config_data(
+        mint: &Pubkey,
+        admin: &Pubkey,
+        admin_revoked: bool,
+        shareholders: &[(Pubkey, u16)],
+    ) -> Vec<u8> {
+        let mut data = PFEE_SHARING_CONFIG_DISC.to_vec();
+        data.extend_from_slice(&[255, 2, 0]); // bump, version, status
+        data.extend_from_slice(mint.as_ref());
+        data.extend_from_slice(admin.as_ref());
+        data.push(admin_revoked as u8);
+        data.extend_from_slice(&(shareholders.len() as u32).to_le_bytes());
+        for (address, share_bps) in shareholders {
+            data.extend_from_slice(address.as_ref());
+            data.extend_from_slice(&share_bps.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_sharing_config_ownership_states() {
+        let mint = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+
+        // Not taken: the creator still holds the shares and the admin role
+        let before =
+            SharingConfig::parse(&sharing_config_data(&mint, &creator, false, &[(creator, 10_000)]))
+                .unwrap();
+        assert_eq!(before.mint, mint);
+        assert_eq!(before.admin, creator);
+        assert!(!before.admin_revoked);
+        assert!(!before.is_sole_recipient(&pool));
+
+        // Half taken: the pool got 100% but the admin is not revoked yet
+        let shares_only =
+            SharingConfig::parse(&sharing_config_data(&mint, &pool, false, &[(pool, 10_000)]))
+                .unwrap();
+        assert!(shares_only.is_sole_recipient(&pool));
+        assert!(!shares_only.admin_revoked);
+
+        // Taken: sole recipient and revoked
+        let taken =
+            SharingConfig::parse(&sharing_config_data(&mint, &pool, true, &[(pool, 10_000)]))
+                .unwrap();
+        assert!(taken.is_sole_recipient(&pool));
+        assert!(taken.admin_revoked);
+
+        // Sharing the fees with anyone else is not sole ownership
+        let shared = SharingConfig::parse(&sharing_config_data(
+            &mint,
+            &pool,
+            true,
+            &[(pool, 9_000), (creator, 1_000)],
+        ))
+        .unwrap();
+        assert!(!shared.is_sole_recipient(&pool));
+
+        // Malformed accounts are rejected
+        let mut wrong_disc = sharing_config_data(&mint, &pool, true, &[(pool, 10_000)]);
+        wrong_disc[0] ^= 1;
+        assert!(SharingConfig::parse(&wrong_disc).is_err());
+        let full = sharing_config_data(&mint, &pool, true, &[(pool, 10_000)]);
+        assert!(SharingConfig::parse(&full[..full.len() - 1]).is_err());
+        let too_many: Vec<(Pubkey, u16)> =
+            (0..=MAX_SHAREHOLDERS).map(|_| (Pubkey::new_unique(), 1)).collect();
+        assert!(SharingConfig::parse(&sharing_config_data(&mint, &pool, true, &too_many)).is_err());
+    }
+
+    #[test]
+    fn test_fee_ownership_any_bad_account_rejected() {
+        let mint = Pubkey::new_unique();
+        let good = FeeOwnershipAccounts::expected(&mint);
+        assert!(good.check(&mint).is_ok());
+
+        // Another mint's accounts don't pass
+        assert!(good.check(&Pubkey::new_unique()).is_err());
+
+        let bad = Pubkey::new_unique();
+        let mutations: [fn(&mut FeeOwnershipAccounts, Pubkey); 15] = [
+            |a, k| a.pfee_event_authority = k,
+            |a, k| a.pump_global = k,
+            |a, k| a.sharing_config = k,
+            |a, k| a.bonding_curve = k,
+            |a, k| a.pump_creator_vault = k,
+            |a, k| a.system_program = k,
+            |a, k| a.pump_program = k,
+            |a, k| a.pump_event_authority = k,
+            |a, k| a.pump_amm_program = k,
+            |a, k| a.amm_event_authority = k,
+            |a, k| a.wsol_mint = k,
+            |a, k| a.token_program = k,
+            |a, k| a.associated_token_program = k,
+            |a, k| a.coin_creator_vault_auth = k,
+            |a, k| a.coin_creator_vault_ata = k,
+        ];
+        for (i, mutate) in mutations.iter().enumerate() {
+            let mut accounts = good.clone();
+            mutate(&mut accounts, bad);
+            assert!(accounts.check(&mint).is_err(), "bad account {} accepted", i);
+        }
+    }
+}
@@ -1,16 +1,152 @@
 //! Account state structures for the staking program
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::{account_info::AccountInfo, pubkey::Pubkey, sysvar::Sysvar};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, hash::hashv, msg,
+    program_error::ProgramError, pubkey::Pubkey, sysvar::Sysvar,
+};
 
 use crate::error::StakingError;
-use crate::math::{exp_neg_time_ratio, wad_mul, U256};
+use crate::math::{
+    apply_loyalty_bonus, apply_weight_boost, calculate_user_weighted_stake, lock_boost_bps,
+    BPS_DENOMINATOR, exp_neg_time_ratio, wad_div, wad_mul, wad_mul_u256, U256, WAD,
+};
+use crate::instructions::update_settings::MAX_LOCK_DURATION_SECONDS;
 
 /// Seed prefixes for PDAs
 pub const POOL_SEED: &[u8] = b"pool";
 pub const STAKE_SEED: &[u8] = b"stake";
 pub const TOKEN_VAULT_SEED: &[u8] = b"token_vault";
 pub const METADATA_SEED: &[u8] = b"metadata";
+pub const AUDIT_LOG_SEED: &[u8] = b"audit_log";
+pub const REWARD_VAULT_SEED: &[u8] = b"reward_vault";
+pub const DEPOSITOR_SEED: &[u8] = b"depositor";
+
+/// Pool PDA: ["pool", mint]
+pub fn derive_pool_pda(mint: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_SEED, mint.as_ref()], program_id)
+}
+
+/// Pool token vault PDA: ["token_vault", pool]
+pub fn derive_vault_pda(pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TOKEN_VAULT_SEED, pool.as_ref()], program_id)
+}
+
+/// User stake PDA: ["stake", pool, owner] (note the seed order differs from
+/// the argument order)
+pub fn derive_user_stake_pda(owner: &Pubkey, pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_SEED, pool.as_ref(), owner.as_ref()], program_id)
+}
+
+/// Stake position PDA: ["stake", pool, owner, index (u16 LE)], or the
+/// default stake PDA for position 0
+pub fn derive_user_stake_position_pda(
+    owner: &Pubkey,
+    pool: &Pubkey,
+    index: u16,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    if index == 0 {
+        return derive_user_stake_pda(owner, pool, program_id);
+    }
+    Pubkey::find_program_address(
+        &[STAKE_SEED, pool.as_ref(), owner.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Number of recent deposit nonces remembered per pool
+pub const DEPOSIT_NONCE_SLOTS: usize = 4;
+
+/// Maximum number of reward fee split recipients per pool
+pub const FEE_SPLIT_SLOTS: usize = 3;
+
+/// Maximum number of capability-scoped delegates per pool
+pub const DELEGATE_SLOTS: usize = 4;
+
+/// Top-ups per stake whose tokens keep their own lock (further top-ups
+/// merge into the newest)
+pub const TOP_UP_LOCK_SLOTS: usize = 4;
+
+/// Tau changes kept in the pool's history (each keeps the old tau so stakes
+/// that haven't synced since can still be brought forward). Older changes
+/// are compacted, see `StakingPool::change_tau`.
+pub const TAU_HISTORY_SLOTS: usize = 4;
+
+/// Stake positions per owner and pool, the default stake account included
+/// (position indices run from 0 to POSITION_SLOTS - 1)
+pub const POSITION_SLOTS: usize = 8;
+
+/// Distribution records per audit log page
+pub const AUDIT_LOG_PAGE_CAPACITY: usize = 32;
+
+/// Max bytes of the pool's display unit label (e.g. "USDC")
+pub const DISPLAY_UNIT_LABEL_LEN: usize = 8;
+
+/// Span the reward rate estimate covers: between one and two windows of the
+/// most recent distributions
+pub const REWARD_RATE_WINDOW_SECONDS: i64 = 60 * 60;
+
+/// QueryEffectiveSettings flag: lock/cooldown stamped on the stake apply
+pub const TERMS_FLAG_STAMPED: u8 = 1 << 0;
+/// QueryEffectiveSettings flag: prior staking time credited by GrandfatherStake
+pub const TERMS_FLAG_GRANDFATHERED: u8 = 1 << 1;
+/// QueryEffectiveSettings flag: the owner's lock commitment sets the lock
+pub const TERMS_FLAG_LOCK_COMMITTED: u8 = 1 << 2;
+/// QueryEffectiveSettings flag: anyone may trigger ClaimFor on the stake
+pub const TERMS_FLAG_CLAIM_FOR: u8 = 1 << 3;
+
+/// Delegate permission: ForceSyncWithBalance
+pub const DELEGATE_PERM_SYNC: u8 = 1 << 0;
+/// Delegate permission: UpdateSettings, SetUnstakeDestinationPolicy and
+/// SetRewardPrecision
+pub const DELEGATE_PERM_SETTINGS: u8 = 1 << 1;
+/// Delegate permission: SetRewardFeeSplit and CollectProtocolFees
+pub const DELEGATE_PERM_FEES: u8 = 1 << 2;
+/// Delegate permission: GrandfatherStake
+pub const DELEGATE_PERM_GRANDFATHER: u8 = 1 << 3;
+/// Delegate permission: SetDisplayUnit
+pub const DELEGATE_PERM_DISPLAY: u8 = 1 << 4;
+/// Every defined delegate permission bit
+pub const DELEGATE_PERM_ALL: u8 = DELEGATE_PERM_SYNC
+    | DELEGATE_PERM_SETTINGS
+    | DELEGATE_PERM_FEES
+    | DELEGATE_PERM_GRANDFATHER
+    | DELEGATE_PERM_DISPLAY;
+
+/// Pending settings bit: lock_duration_seconds raise waiting for the timelock
+pub const PENDING_LOCK_DURATION: u16 = 1 << 0;
+/// Pending settings bit: unstake_cooldown_seconds raise
+pub const PENDING_UNSTAKE_COOLDOWN: u16 = 1 << 1;
+/// Pending settings bit: claim_fee_bps raise
+pub const PENDING_CLAIM_FEE: u16 = 1 << 2;
+/// Pending settings bit: early_exit_penalty_bps raise
+pub const PENDING_EARLY_EXIT_PENALTY: u16 = 1 << 3;
+/// Pending settings bit: settings_timelock_seconds cut
+pub const PENDING_SETTINGS_TIMELOCK: u16 = 1 << 4;
+/// Pending settings bit: loyalty_bonus_bps change
+pub const PENDING_LOYALTY_BONUS: u16 = 1 << 5;
+/// Pending settings bit: lock_boost_bps change
+pub const PENDING_LOCK_BOOST: u16 = 1 << 6;
+/// Pending settings bit: min_distributable_per_event raise
+pub const PENDING_MIN_DISTRIBUTABLE: u16 = 1 << 7;
+/// Pending settings bit: fee split with a higher total share
+pub const PENDING_FEE_SPLIT: u16 = 1 << 8;
+/// Pending settings bit: tau_seconds raise (UpdateTau)
+pub const PENDING_TAU: u16 = 1 << 9;
+
+/// Largest total_staked * acc_reward_per_weighted_share (i.e. total reward
+/// debt) the pool allows before lowering accumulator precision. Leaves two
+/// bits of u128 headroom for sums of debts.
+pub const MAX_SCALED_REWARD_DEBT: u128 = 1 << 126;
+
+/// Upper bound on StakingPool::acc_scale_shift. Reaching it would need more
+/// rewards per token unit than SOL in existence, so it only guards the math.
+pub const MAX_ACC_SCALE_SHIFT: u8 = 96;
+
+/// Upper bound on the starting precision shift SetRewardPrecision may pick.
+/// 2^-32 of WAD still leaves ~2.3e8 units of precision per lamport per token.
+pub const MAX_REWARD_PRECISION_SHIFT: u8 = 32;
 
 /// The original SPL Token program ID (TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA)
 pub const SPL_TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
@@ -34,6 +170,7 @@ pub const METAPLEX_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
 ]);
 
 /// pfee program ID (pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ)
+#[cfg(not(feature = "devnet"))]
 pub const PFEE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
     0x0c, 0x35, 0xff, 0xa9, 0x05, 0x5a, 0x8e, 0x56,
     0x8d, 0xa8, 0xf7, 0xbc, 0x07, 0x56, 0x15, 0x27,
@@ -41,6 +178,47 @@ pub const PFEE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
     0x9c, 0x51, 0x6a, 0xa4, 0x14, 0xc2, 0x7c, 0x70,
 ]);
 
+/// pfee clone for devnet testing, read from CHIEFSTAKER_PFEE_PROGRAM_ID at
+/// build time. Every pfee PDA and check follows it; pump and PumpSwap keep
+/// their mainnet IDs, which are the same on devnet.
+#[cfg(feature = "devnet")]
+pub const PFEE_PROGRAM_ID: Pubkey = pubkey_from_base58(env!(
+    "CHIEFSTAKER_PFEE_PROGRAM_ID",
+    "the devnet feature needs CHIEFSTAKER_PFEE_PROGRAM_ID set to the pfee clone's program ID"
+));
+
+/// Decode a base58 program ID at compile time (panics, failing the build, on
+/// anything that is not 32 bytes of base58)
+#[cfg(any(feature = "devnet", test))]
+const fn pubkey_from_base58(encoded: &str) -> Pubkey {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    let chars = encoded.as_bytes();
+    assert!(chars.len() >= 32 && chars.len() <= 44, "not a base58 pubkey");
+    let mut bytes = [0u8; 32];
+    let mut i = 0;
+    while i < chars.len() {
+        let mut digit = 0;
+        while digit < ALPHABET.len() && ALPHABET[digit] != chars[i] {
+            digit += 1;
+        }
+        assert!(digit < ALPHABET.len(), "invalid base58 character");
+
+        // bytes = bytes * 58 + digit, big-endian
+        let mut carry = digit as u32;
+        let mut j = bytes.len();
+        while j > 0 {
+            j -= 1;
+            carry += bytes[j] as u32 * 58;
+            bytes[j] = carry as u8;
+            carry >>= 8;
+        }
+        assert!(carry == 0, "base58 pubkey longer than 32 bytes");
+        i += 1;
+    }
+    Pubkey::new_from_array(bytes)
+}
+
 /// Anchor discriminator for pfee SharingConfig account
 pub const PFEE_SHARING_CONFIG_DISC: [u8; 8] = [216, 74, 9, 0, 56, 140, 93, 75];
 
@@ -78,10 +256,36 @@ pub const METEORA_DBC_CREATOR_DISC: [u8; 8] = [36, 36, 123, 35, 158, 89, 75, 41]
 pub const POOL_DISCRIMINATOR: [u8; 8] = [0xc7, 0x5f, 0x7e, 0x2d, 0x3b, 0x1a, 0x9c, 0x4e];
 pub const USER_STAKE_DISCRIMINATOR: [u8; 8] = [0xa3, 0x8b, 0x5d, 0x2f, 0x7c, 0x4a, 0x1e, 0x9d];
 pub const METADATA_DISCRIMINATOR: [u8; 8] = [0xd4, 0x2a, 0x8f, 0x6b, 0x51, 0x3c, 0xe7, 0x90];
+pub const AUDIT_LOG_DISCRIMINATOR: [u8; 8] = [0x6e, 0x1b, 0xc3, 0x94, 0x2f, 0xa8, 0x57, 0x0d];
+pub const DEPOSITOR_ALLOWLIST_DISCRIMINATOR: [u8; 8] = [0x3f, 0xd2, 0x81, 0x5a, 0xc6, 0x0e, 0x74, 0xb9];
+
+/// Serialize `value` into `dst`, tolerating accounts created before trailing
+/// fields were appended. Bytes that don't fit must all be zero (i.e. the new
+/// fields still hold their defaults); otherwise the account has to be grown
+/// first and `AccountDataTooSmall` is returned.
+pub fn pack_into_slice<T: BorshSerialize>(value: &T, dst: &mut [u8]) -> Result<(), StakingError> {
+    let bytes = borsh::to_vec(value).map_err(|_| StakingError::InvalidInstruction)?;
+    let fit = bytes.len().min(dst.len());
+    if bytes[fit..].iter().any(|b| *b != 0) {
+        return Err(StakingError::AccountDataTooSmall);
+    }
+    dst[..fit].copy_from_slice(&bytes[..fit]);
+    Ok(())
+}
+
+/// Where unstaked tokens may be sent
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnstakeDestinationPolicy {
+    /// Any token account of the pool mint owned by the stake owner
+    #[default]
+    AnyOwnerAccount,
+    /// Only the owner's associated token account
+    OwnerAtaOnly,
+}
 
 /// Staking pool state account
 /// PDA: ["pool", mint]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+#[derive(BorshSerialize, Debug, Clone)]
 pub struct StakingPool {
     /// Discriminator for account type identification
     pub discriminator: [u8; 8],
@@ -92,8 +296,9 @@ pub struct StakingPool {
     /// PDA holding staked tokens
     pub token_vault: Pubkey,
 
-    /// DEPRECATED: No longer used (pool PDA holds SOL directly via lamports).
-    /// Retained for Borsh serialization layout compatibility.
+    /// Token account holding SPL-token rewards (PDA: ["reward_vault", pool])
+    /// once `reward_mint` is set. SOL rewards are held by the pool PDA itself,
+    /// which is what this holds for SOL pools.
     pub reward_vault: Pubkey,
 
     /// Admin authority who initialized the pool
@@ -151,6 +356,220 @@ pub struct StakingPool {
     /// debt in `total_reward_debt` would break the FixTotalRewardDebt formula.
     /// Starts at 0 for existing pools (binary-compatible with old `_reserved3`).
     pub total_residual_unpaid: u64,
+
+    // ── Fields below were appended after v4 ─────────────────────────
+    // Legacy (LEGACY_LEN) pools read them as 0 and only need to grow once
+    // one of them is set to a non-default value.
+
+    /// Total lamports credited to users' `claimed_balance` but not yet
+    /// withdrawn. These lamports stay in the pool and are reserved: they are
+    /// excluded from the balance other claims can draw from.
+    pub total_claimed_balance: u64,
+
+    /// Maximum lamports DepositRewards may leave undistributed while
+    /// total_staked is 0 (0 = unlimited)
+    pub max_deferred_lamports: u64,
+
+    /// Max weight bonus (bps) for leaving rewards unclaimed; ramps up over
+    /// one tau since the user's last claim, capped at max weight (0 = off)
+    pub loyalty_bonus_bps: u64,
+
+    /// When set, DepositRewards excludes the depositor's own stake from the
+    /// distribution of their deposit
+    pub exclude_depositor: bool,
+
+    /// Ring of recent DepositRewards idempotency keys (0 = empty slot)
+    pub recent_deposit_nonces: [u64; DEPOSIT_NONCE_SLOTS],
+
+    /// Next slot to overwrite in `recent_deposit_nonces`
+    pub deposit_nonce_cursor: u8,
+
+    /// When set, stakes record the lock duration and unstake cooldown in
+    /// effect when they are created (or topped up by their owner), so later
+    /// setting changes don't apply to them retroactively
+    pub per_stake_terms: bool,
+
+    /// Fee (bps) withheld from reward claims for the protocol (0 = none)
+    pub claim_fee_bps: u64,
+
+    /// Protocol fees withheld from claims and not yet collected. These
+    /// lamports stay in the pool and are reserved like claimed balances.
+    pub accrued_protocol_fees: u64,
+
+    /// Set by QuarantinePool when the mint gained a forbidden extension after
+    /// init: new stakes are blocked, exits keep working
+    pub quarantined: bool,
+
+    /// acc_reward_per_weighted_share (and every stake's reward_debt) is
+    /// stored divided by 2^acc_scale_shift. Starts at 0 (full precision) and
+    /// only grows when a larger total_staked would overflow reward_debt.
+    pub acc_scale_shift: u8,
+
+    /// Recipients of the reward fee split, paid directly on each claim
+    /// (default pubkey = empty slot)
+    pub fee_split_recipients: [Pubkey; FEE_SPLIT_SLOTS],
+
+    /// Share (bps) of each claim paid to the matching `fee_split_recipients`
+    pub fee_split_bps: [u16; FEE_SPLIT_SLOTS],
+
+    /// How long after CompleteUnstake the owner may undo it with
+    /// ReverseUnstake (0 = reversals disabled)
+    pub unstake_reversal_window_seconds: u64,
+
+    /// Set when InitializePool cached `mint_decimals` (pools created before
+    /// that read false, and a 0-decimal mint must not look uncached)
+    pub mint_decimals_cached: bool,
+
+    /// Mint decimals cached at init, for display purposes
+    pub mint_decimals: u8,
+
+    /// Set at init to record every reward distribution in AuditLogPage accounts
+    pub audit_log_enabled: bool,
+
+    /// Index of the audit log page the next distribution is written to
+    pub audit_log_page: u32,
+
+    /// Which token accounts Unstake and CompleteUnstake may pay out to
+    pub unstake_destination_policy: UnstakeDestinationPolicy,
+
+    /// Lamports a deposit or sync must distribute at once (0 = no minimum).
+    /// Smaller amounts stay above `last_synced_lamports` until they add up.
+    pub min_distributable_per_event: u64,
+
+    /// Set once by LockPoolSettings: economic settings can no longer change
+    /// (irreversible; the authority keeps its other powers)
+    pub settings_locked: bool,
+
+    /// Start of the reward rate window (0 = not started yet)
+    pub reward_rate_window_start: i64,
+
+    /// Lamports distributed since `reward_rate_window_start`
+    pub reward_rate_window_sum: u64,
+
+    /// UTF-8 label of the display unit rewards are also tracked in,
+    /// zero-padded (all zero = none). Display only.
+    pub display_unit_label: [u8; DISPLAY_UNIT_LABEL_LEN],
+
+    /// Decimals of `display_total_distributed`
+    pub display_unit_decimals: u8,
+
+    /// Sum of the display-unit values depositors attached to their deposits
+    /// (client-supplied, never used in reward math)
+    pub display_total_distributed: u64,
+
+    /// Keys the authority lets run some of its instructions
+    /// (default pubkey = empty slot)
+    pub delegates: [Pubkey; DELEGATE_SLOTS],
+
+    /// DELEGATE_PERM_* bitmask of the matching `delegates` entry
+    pub delegate_permissions: [u8; DELEGATE_SLOTS],
+
+    /// base_time of each UpdateTau, oldest first (0 = unused slot)
+    pub tau_change_times: [i64; TAU_HISTORY_SLOTS],
+
+    /// tau_seconds in effect before the matching `tau_change_times` entry
+    pub tau_change_previous: [u64; TAU_HISTORY_SLOTS],
+
+    /// Mint rewards are paid in (default pubkey = native SOL)
+    pub reward_mint: Pubkey,
+
+    /// Most tokens Stake and StakeOnBehalf may bring total_staked to
+    /// (0 = unlimited). Lowering it below total_staked only blocks new stakes.
+    pub max_total_staked: u64,
+
+    /// Smallest DepositRewards / DepositTokenRewards amount accepted
+    /// (0 = any), so deposits can't be spammed in amounts too small to
+    /// distribute
+    pub min_reward_deposit: u64,
+
+    /// Set by SetPause during an incident: new stakes and reward deposits
+    /// are blocked, exits and SyncRewards keep working
+    pub paused: bool,
+
+    /// Share (bps) of the tokens withheld by InstantUnstake for skipping the
+    /// unstake cooldown (0 = InstantUnstake disabled)
+    pub early_exit_penalty_bps: u64,
+
+    /// Tokens withheld by InstantUnstake. They stay in the vault but are no
+    /// longer part of total_staked, so vault balance = total_staked +
+    /// penalty_tokens
+    pub penalty_tokens: u64,
+
+    /// Authority proposed by ProposeAuthority, waiting for AcceptAuthority
+    /// (default pubkey = no transfer pending)
+    pub pending_authority: Pubkey,
+
+    /// Token program of the pool's mint (SPL Token or Token 2022), recorded
+    /// at init (default = pool created before it was recorded)
+    pub token_program: Pubkey,
+
+    /// Max weight boost (bps) for a voluntary lock commitment, reached at a
+    /// full-year commitment and applied until the lock ends, capped at max
+    /// weight (0 = off)
+    pub lock_boost_bps: u64,
+
+    /// Lamports of streamed deposits not yet released as of
+    /// reward_stream_start; they are released linearly until
+    /// reward_stream_end, and sync leaves the unreleased rest alone
+    pub reward_stream_lamports: u64,
+
+    /// Start of the current linear release (last streamed deposit)
+    pub reward_stream_start: i64,
+
+    /// When the last streamed lamports are released
+    pub reward_stream_end: i64,
+
+    /// Only depositors with an allowlist entry (PDA: ["depositor", pool,
+    /// depositor]) may deposit rewards
+    pub require_allowlisted_depositors: bool,
+
+    /// Seconds a staker-unfavorable settings change waits before
+    /// ApplySettings may apply it (0 = every change applies at once)
+    pub settings_timelock_seconds: u64,
+
+    /// PENDING_* bits of the proposed values waiting for the timelock
+    /// (0 = nothing pending)
+    pub pending_settings_mask: u16,
+
+    /// When ApplySettings may apply the pending values
+    pub pending_settings_effective_time: i64,
+
+    /// Proposed lock_duration_seconds (PENDING_LOCK_DURATION)
+    pub pending_lock_duration_seconds: u64,
+
+    /// Proposed unstake_cooldown_seconds (PENDING_UNSTAKE_COOLDOWN)
+    pub pending_unstake_cooldown_seconds: u64,
+
+    /// Proposed claim_fee_bps (PENDING_CLAIM_FEE)
+    pub pending_claim_fee_bps: u64,
+
+    /// Proposed early_exit_penalty_bps (PENDING_EARLY_EXIT_PENALTY)
+    pub pending_early_exit_penalty_bps: u64,
+
+    /// Proposed settings_timelock_seconds (PENDING_SETTINGS_TIMELOCK)
+    pub pending_settings_timelock_seconds: u64,
+
+    /// Proposed loyalty_bonus_bps (PENDING_LOYALTY_BONUS)
+    pub pending_loyalty_bonus_bps: u64,
+
+    /// Proposed lock_boost_bps (PENDING_LOCK_BOOST)
+    pub pending_lock_boost_bps: u64,
+
+    /// Proposed min_distributable_per_event (PENDING_MIN_DISTRIBUTABLE)
+    pub pending_min_distributable_per_event: u64,
+
+    /// Proposed fee split recipients (PENDING_FEE_SPLIT)
+    pub pending_fee_split_recipients: [Pubkey; FEE_SPLIT_SLOTS],
+
+    /// Proposed fee split shares (PENDING_FEE_SPLIT)
+    pub pending_fee_split_bps: [u16; FEE_SPLIT_SLOTS],
+
+    /// Fee split shares a claim couldn't pay because the matching recipient
+    /// could not take the lamports, reserved until WithdrawFeeSplit
+    pub fee_split_unpaid: [u64; FEE_SPLIT_SLOTS],
+
+    /// Proposed tau_seconds (PENDING_TAU)
+    pub pending_tau_seconds: u64,
 }
 
 impl StakingPool {
@@ -173,7 +592,68 @@ impl StakingPool {
         8 +  // unstake_cooldown_seconds
         8 +  // initial_base_time
         16 + // total_reward_debt
-        8;   // total_residual_unpaid
+        8 +  // total_residual_unpaid
+        8 +  // total_claimed_balance
+        8 +  // max_deferred_lamports
+        8 +  // loyalty_bonus_bps
+        1 +  // exclude_depositor
+        8 * DEPOSIT_NONCE_SLOTS + // recent_deposit_nonces
+        1 +  // deposit_nonce_cursor
+        1 +  // per_stake_terms
+        8 +  // claim_fee_bps
+        8 +  // accrued_protocol_fees
+        1 +  // quarantined
+        1 +  // acc_scale_shift
+        32 * FEE_SPLIT_SLOTS + // fee_split_recipients
+        2 * FEE_SPLIT_SLOTS +  // fee_split_bps
+        8 +  // unstake_reversal_window_seconds
+        1 +  // mint_decimals_cached
+        1 +  // mint_decimals
+        1 +  // audit_log_enabled
+        4 +  // audit_log_page
+        1 +  // unstake_destination_policy
+        8 +  // min_distributable_per_event
+        1 +  // settings_locked
+        8 +  // reward_rate_window_start
+        8 +  // reward_rate_window_sum
+        DISPLAY_UNIT_LABEL_LEN + // display_unit_label
+        1 +  // display_unit_decimals
+        8 +  // display_total_distributed
+        32 * DELEGATE_SLOTS + // delegates
+        DELEGATE_SLOTS +      // delegate_permissions
+        8 * TAU_HISTORY_SLOTS + // tau_change_times
+        8 * TAU_HISTORY_SLOTS + // tau_change_previous
+        32 + // reward_mint
+        8 +  // max_total_staked
+        8 +  // min_reward_deposit
+        1 +  // paused
+        8 +  // early_exit_penalty_bps
+        8 +  // penalty_tokens
+        32 + // pending_authority
+        32 + // token_program
+        8 +  // lock_boost_bps
+        8 +  // reward_stream_lamports
+        8 +  // reward_stream_start
+        8 +  // reward_stream_end
+        1 +  // require_allowlisted_depositors
+        8 +  // settings_timelock_seconds
+        2 +  // pending_settings_mask
+        8 +  // pending_settings_effective_time
+        8 +  // pending_lock_duration_seconds
+        8 +  // pending_unstake_cooldown_seconds
+        8 +  // pending_claim_fee_bps
+        8 +  // pending_early_exit_penalty_bps
+        8 +  // pending_settings_timelock_seconds
+        8 +  // pending_loyalty_bonus_bps
+        8 +  // pending_lock_boost_bps
+        8 +  // pending_min_distributable_per_event
+        32 * FEE_SPLIT_SLOTS + // pending_fee_split_recipients
+        2 * FEE_SPLIT_SLOTS +  // pending_fee_split_bps
+        8 * FEE_SPLIT_SLOTS + // fee_split_unpaid
+        8; // pending_tau_seconds
+
+    /// Size of pools created before trailing fields were appended (v4)
+    pub const LEGACY_LEN: usize = 289;
 
     /// Create a new staking pool
     pub fn new(
@@ -205,6 +685,64 @@ impl StakingPool {
             initial_base_time: 0,
             total_reward_debt: 0,
             total_residual_unpaid: 0,
+            total_claimed_balance: 0,
+            max_deferred_lamports: 0,
+            loyalty_bonus_bps: 0,
+            exclude_depositor: false,
+            recent_deposit_nonces: [0; DEPOSIT_NONCE_SLOTS],
+            deposit_nonce_cursor: 0,
+            per_stake_terms: false,
+            claim_fee_bps: 0,
+            accrued_protocol_fees: 0,
+            quarantined: false,
+            acc_scale_shift: 0,
+            fee_split_recipients: [Pubkey::default(); FEE_SPLIT_SLOTS],
+            fee_split_bps: [0; FEE_SPLIT_SLOTS],
+            unstake_reversal_window_seconds: 0,
+            mint_decimals_cached: false,
+            mint_decimals: 0,
+            audit_log_enabled: false,
+            audit_log_page: 0,
+            unstake_destination_policy: UnstakeDestinationPolicy::AnyOwnerAccount,
+            min_distributable_per_event: 0,
+            settings_locked: false,
+            reward_rate_window_start: 0,
+            reward_rate_window_sum: 0,
+            display_unit_label: [0; DISPLAY_UNIT_LABEL_LEN],
+            display_unit_decimals: 0,
+            display_total_distributed: 0,
+            delegates: [Pubkey::default(); DELEGATE_SLOTS],
+            delegate_permissions: [0; DELEGATE_SLOTS],
+            tau_change_times: [0; TAU_HISTORY_SLOTS],
+            tau_change_previous: [0; TAU_HISTORY_SLOTS],
+            reward_mint: Pubkey::default(),
+            max_total_staked: 0,
+            min_reward_deposit: 0,
+            paused: false,
+            early_exit_penalty_bps: 0,
+            penalty_tokens: 0,
+            pending_authority: Pubkey::default(),
+            token_program: Pubkey::default(),
+            lock_boost_bps: 0,
+            reward_stream_lamports: 0,
+            reward_stream_start: 0,
+            reward_stream_end: 0,
+            require_allowlisted_depositors: false,
+            settings_timelock_seconds: 0,
+            pending_settings_mask: 0,
+            pending_settings_effective_time: 0,
+            pending_lock_duration_seconds: 0,
+            pending_unstake_cooldown_seconds: 0,
+            pending_claim_fee_bps: 0,
+            pending_early_exit_penalty_bps: 0,
+            pending_settings_timelock_seconds: 0,
+            pending_loyalty_bonus_bps: 0,
+            pending_lock_boost_bps: 0,
+            pending_min_distributable_per_event: 0,
+            pending_fee_split_recipients: [Pubkey::default(); FEE_SPLIT_SLOTS],
+            pending_fee_split_bps: [0; FEE_SPLIT_SLOTS],
+            fee_split_unpaid: [0; FEE_SPLIT_SLOTS],
+            pending_tau_seconds: 0,
         }
     }
 
@@ -230,330 +768,3189 @@ impl StakingPool {
 
     /// Derive pool PDA
     pub fn derive_pda(mint: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[POOL_SEED, mint.as_ref()], program_id)
+        derive_pool_pda(mint, program_id)
     }
 
     /// Derive token vault PDA
     pub fn derive_token_vault_pda(pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[TOKEN_VAULT_SEED, pool.as_ref()], program_id)
+        derive_vault_pda(pool, program_id)
     }
 
-}
+    /// Derive reward token vault PDA (SPL-token reward pools)
+    pub fn derive_reward_vault_pda(pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[REWARD_VAULT_SEED, pool.as_ref()], program_id)
+    }
 
-/// User stake account
-/// PDA: ["stake", pool, owner]
-#[derive(BorshSerialize, Debug, Clone)]
-pub struct UserStake {
-    /// Discriminator for account type identification
-    pub discriminator: [u8; 8],
+    /// Mint rewards are paid in, or None for native SOL
+    pub fn reward_mint(&self) -> Option<Pubkey> {
+        if self.reward_mint == Pubkey::default() {
+            None
+        } else {
+            Some(self.reward_mint)
+        }
+    }
 
-    /// Owner of this stake
-    pub owner: Pubkey,
+    /// Reject SOL reward instructions (deposit, sync, claim) on a pool that
+    /// pays rewards in an SPL token
+    ///
+    /// SOL rewards are the pool's own lamports, so the reward vault of a SOL
+    /// pool (`pool`, the pool PDA) must be the pool itself; anything else
+    /// means a corrupted account and fails with `InvalidRewardVault`.
+    pub fn require_sol_rewards(&self, pool: &Pubkey) -> Result<(), StakingError> {
+        if self.reward_mint().is_some() {
+            return Err(StakingError::RewardModeMismatch);
+        }
+        if self.reward_vault != *pool {
+            msg!("Reward vault {} is not the pool {}", self.reward_vault, pool);
+            return Err(StakingError::InvalidRewardVault);
+        }
+        Ok(())
+    }
 
-    /// Pool this stake belongs to
-    pub pool: Pubkey,
+    /// Drop the pending (timelocked) values of `mask` (PENDING_* bits)
+    pub fn clear_pending_settings(&mut self, mask: u16) {
+        let clear = self.pending_settings_mask & mask;
+        if clear & PENDING_LOCK_DURATION != 0 {
+            self.pending_lock_duration_seconds = 0;
+        }
+        if clear & PENDING_UNSTAKE_COOLDOWN != 0 {
+            self.pending_unstake_cooldown_seconds = 0;
+        }
+        if clear & PENDING_CLAIM_FEE != 0 {
+            self.pending_claim_fee_bps = 0;
+        }
+        if clear & PENDING_EARLY_EXIT_PENALTY != 0 {
+            self.pending_early_exit_penalty_bps = 0;
+        }
+        if clear & PENDING_SETTINGS_TIMELOCK != 0 {
+            self.pending_settings_timelock_seconds = 0;
+        }
+        if clear & PENDING_LOYALTY_BONUS != 0 {
+            self.pending_loyalty_bonus_bps = 0;
+        }
+        if clear & PENDING_LOCK_BOOST != 0 {
+            self.pending_lock_boost_bps = 0;
+        }
+        if clear & PENDING_MIN_DISTRIBUTABLE != 0 {
+            self.pending_min_distributable_per_event = 0;
+        }
+        if clear & PENDING_FEE_SPLIT != 0 {
+            self.pending_fee_split_recipients = [Pubkey::default(); FEE_SPLIT_SLOTS];
+            self.pending_fee_split_bps = [0; FEE_SPLIT_SLOTS];
+        }
+        if clear & PENDING_TAU != 0 {
+            self.pending_tau_seconds = 0;
+        }
+        self.pending_settings_mask &= !mask;
+        if self.pending_settings_mask == 0 {
+            self.pending_settings_effective_time = 0;
+        }
+    }
 
-    /// Amount of tokens staked
-    pub amount: u64,
+    /// Whether `signer` may run an instruction gated on `permission`: the
+    /// authority always may, a delegate only if it holds the permission bit.
+    /// Callers check for a renounced authority first (delegates die with it).
+    pub fn is_authorized(&self, signer: &Pubkey, permission: u8) -> bool {
+        *signer == self.authority || self.permissions_of(signer) & permission == permission
+    }
 
-    /// Unix timestamp when stake began
-    pub stake_time: i64,
+    /// DELEGATE_PERM_* bitmask granted to `key` (0 = not a delegate)
+    pub fn permissions_of(&self, key: &Pubkey) -> u8 {
+        if *key == Pubkey::default() {
+            return 0;
+        }
+        self.delegates
+            .iter()
+            .position(|delegate| delegate == key)
+            .map_or(0, |i| self.delegate_permissions[i])
+    }
 
-    /// e^((stake_time - base_time) / tau) at time of staking, WAD-scaled
-    /// Used to track contribution to sum_stake_exp
-    pub exp_start_factor: u128,
+    /// Grant `permissions` to `delegate`, replacing what it had.
+    /// 0 removes the delegate and frees its slot.
+    pub fn set_delegate(&mut self, delegate: Pubkey, permissions: u8) -> Result<(), StakingError> {
+        if delegate == Pubkey::default() || permissions & !DELEGATE_PERM_ALL != 0 {
+            return Err(StakingError::InvalidDelegate);
+        }
+        let slot = match self.delegates.iter().position(|d| *d == delegate) {
+            Some(i) => i,
+            None if permissions == 0 => return Ok(()),
+            None => self
+                .delegates
+                .iter()
+                .position(|d| *d == Pubkey::default())
+                .ok_or(StakingError::TooManyDelegates)?,
+        };
+        if permissions == 0 {
+            self.delegates[slot] = Pubkey::default();
+            self.delegate_permissions[slot] = 0;
+        } else {
+            self.delegates[slot] = delegate;
+            self.delegate_permissions[slot] = permissions;
+        }
+        Ok(())
+    }
 
-    /// Reward debt encoding an acc_rps snapshot for pending reward calculation.
-    /// Encodes: reward_debt = wad_mul(amount * WAD, snapshot_acc_rps).
-    /// Pending = user_weighted * (current_acc_rps - snapshot_acc_rps).
-    /// When amount == 0 (post-full-unstake), reinterpreted as unclaimed WAD-scaled rewards.
-    pub reward_debt: u128,
+    /// Drop every delegate (e.g. when the authority changes hands)
+    pub fn clear_delegates(&mut self) {
+        self.delegates = [Pubkey::default(); DELEGATE_SLOTS];
+        self.delegate_permissions = [0; DELEGATE_SLOTS];
+    }
 
-    /// PDA bump seed
-    pub bump: u8,
+    /// Move base_time to `current_time`, decaying sum_stake_exp to match.
+    /// Returns the decay factor applied (WAD = no time passed).
+    pub fn rebase(&mut self, current_time: i64) -> Result<u128, StakingError> {
+        let time_delta = current_time.saturating_sub(self.base_time);
+        if time_delta <= 0 {
+            return Ok(WAD);
+        }
 
-    /// Pending unstake request amount (0 = no pending request)
-    pub unstake_request_amount: u64,
+        // Record the original base_time before first rebase so legacy UserStake
+        // accounts can be lazily adjusted (their exp_start_factor is relative
+        // to the initial base_time).
+        if self.initial_base_time == 0 {
+            self.initial_base_time = self.base_time;
+        }
 
-    /// Timestamp when unstake was requested
-    pub unstake_request_time: i64,
+        // For very large time_delta (> 87*tau), exp_neg_time_ratio returns 0,
+        // meaning all stakes are fully matured and sum_stake_exp zeroes out.
+        let decay_factor = exp_neg_time_ratio(time_delta, self.tau_seconds)?;
+        let new_sum_stake_exp =
+            wad_mul_u256(self.get_sum_stake_exp(), U256::from_u128(decay_factor))?;
 
-    /// Timestamp of most recent stake deposit (for lock duration checks)
-    /// Falls back to stake_time when 0 (for existing accounts)
-    pub last_stake_time: i64,
+        self.set_sum_stake_exp(new_sum_stake_exp);
+        self.base_time = current_time;
+        Ok(decay_factor)
+    }
 
-    /// Pool base_time when exp_start_factor was last calibrated.
-    /// 0 = legacy account (pre-rebase-aware); treated as matching the pool's
-    /// initial_base_time or current base_time if no rebase has occurred.
-    pub base_time_snapshot: i64,
+    /// Switch to `new_tau` from `current_time` on, keeping the old value for
+    /// stakes that still have to be brought forward. Returns the old tau.
+    ///
+    /// The pool is rebased first, so everything up to `current_time` decays
+    /// with the old tau and everything after with the new one: a stake keeps
+    /// exactly the weight it had, and only the rest of its maturation runs
+    /// at the new speed. A full tau history is compacted to make room (see
+    /// `compact_tau_history`).
+    pub fn change_tau(&mut self, new_tau: u64, current_time: i64) -> Result<u64, StakingError> {
+        self.rebase(current_time)?;
+        if self.tau_change_times[TAU_HISTORY_SLOTS - 1] != 0 {
+            self.compact_tau_history()?;
+        }
+        let slot = self
+            .tau_change_times
+            .iter()
+            .position(|time| *time == 0)
+            .ok_or(StakingError::TooManyTauChanges)?;
 
-    /// Cumulative SOL rewards claimed by this user (lamports).
-    /// Defaults to 0 for legacy 153-byte accounts (populated on first realloc).
-    pub total_rewards_claimed: u64,
+        let old_tau = self.tau_seconds;
+        self.tau_change_times[slot] = self.base_time;
+        self.tau_change_previous[slot] = old_tau;
+        self.tau_seconds = new_tau;
+        Ok(old_tau)
+    }
 
-    /// Cumulative WAD-scaled rewards already paid out for the current position.
-    /// Used to make claims frequency-independent: pending = full_entitlement - claimed_rewards_wad.
-    /// Preserved on add-stake (pending rewards unchanged). Reset to 0 on unstake
-    /// (partial/full) when the position is restructured and pending is settled.
-    /// Defaults to 0 for existing accounts (correct: first claim gets full pending).
-    pub claimed_rewards_wad: u128,
-}
+    /// Free at least one slot of a full tau history.
+    ///
+    /// Changes old enough that anything synced before them has fully matured
+    /// by base_time are dropped: the spans they describe can't change any
+    /// stake's weight any more, so this is exact. If none has, the two
+    /// oldest changes are merged into one whose span runs at the larger
+    /// (slower) of their taus. A stake that hasn't synced since the older of
+    /// them then matures a little slower than it should, never faster, so it
+    /// can only earn less, not claim weight the pool's sum doesn't hold.
+    fn compact_tau_history(&mut self) -> Result<(), StakingError> {
+        let mut expired = 0;
+        for (i, time) in self.tau_change_times.iter().enumerate().rev() {
+            if *time != 0 && self.decay_between(*time, self.base_time)? == 0 {
+                expired = i + 1;
+                break;
+            }
+        }
+        if expired == 0 {
+            self.tau_change_previous[1] =
+                self.tau_change_previous[1].max(self.tau_change_previous[0]);
+            expired = 1;
+        }
+        self.tau_change_times.copy_within(expired.., 0);
+        self.tau_change_previous.copy_within(expired.., 0);
+        for slot in TAU_HISTORY_SLOTS - expired..TAU_HISTORY_SLOTS {
+            self.tau_change_times[slot] = 0;
+            self.tau_change_previous[slot] = 0;
+        }
+        Ok(())
+    }
 
-impl UserStake {
-    /// Size of the account in bytes
-    pub const LEN: usize = 8 +  // discriminator
-        32 + // owner
-        32 + // pool
-        8 +  // amount
-        8 +  // stake_time
-        16 + // exp_start_factor
-        16 + // reward_debt
-        1 +  // bump
-        8 +  // unstake_request_amount
-        8 +  // unstake_request_time
-        8 +  // last_stake_time
-        8 +  // base_time_snapshot
-        8 +  // total_rewards_claimed
-        16;  // claimed_rewards_wad
+    /// e^-(integral of dt / tau from `from` to `to`), WAD-scaled: how much an
+    /// exp_start_factor relative to `from` shrinks when re-expressed relative
+    /// to `to`, with each span decayed at the tau in effect then.
+    pub fn decay_between(&self, from: i64, to: i64) -> Result<u128, StakingError> {
+        let mut factor = WAD;
+        let mut start = from;
+        for (time, tau) in self.tau_change_times.iter().zip(self.tau_change_previous.iter()) {
+            if *time == 0 || *time <= start {
+                continue;
+            }
+            // Changes always rebase, so none is later than base_time
+            let end = (*time).min(to);
+            factor = wad_mul(factor, exp_neg_time_ratio(end - start, *tau)?)?;
+            start = end;
+        }
+        if to > start {
+            factor = wad_mul(factor, exp_neg_time_ratio(to - start, self.tau_seconds)?)?;
+        }
+        Ok(factor)
+    }
 
-    /// Legacy account size (before claimed_rewards_wad was added)
-    pub const LEGACY_LEN: usize = Self::LEN - 16;
+    /// Lamports held by the pool that are owed to specific users, fee split
+    /// recipients or the protocol and must not be paid out as anyone else's
+    /// rewards.
+    pub fn reserved_lamports(&self) -> u64 {
+        self.total_claimed_balance
+            .saturating_add(self.accrued_protocol_fees)
+            .saturating_add(self.fee_split_unpaid.iter().fold(0u64, |sum, v| sum.saturating_add(*v)))
+    }
 
-    /// Create a new user stake
-    pub fn new(
-        owner: Pubkey,
-        pool: Pubkey,
-        amount: u64,
-        stake_time: i64,
-        exp_start_factor: u128,
-        bump: u8,
-        base_time_snapshot: i64,
-    ) -> Self {
-        Self {
-            discriminator: USER_STAKE_DISCRIMINATOR,
-            owner,
-            pool,
-            amount,
-            stake_time,
-            exp_start_factor,
-            reward_debt: 0,
-            bump,
-            unstake_request_amount: 0,
-            unstake_request_time: 0,
-            last_stake_time: stake_time,
-            base_time_snapshot,
-            total_rewards_claimed: 0,
-            claimed_rewards_wad: 0,
-        }
+    /// Protocol fee withheld from a claim of `amount` lamports (rounded down)
+    pub fn claim_fee(&self, amount: u64) -> u64 {
+        ((amount as u128) * (self.claim_fee_bps as u128) / (BPS_DENOMINATOR as u128)) as u64
     }
 
-    /// Check if stake is initialized
-    pub fn is_initialized(&self) -> bool {
-        self.discriminator == USER_STAKE_DISCRIMINATOR
+    /// Tokens InstantUnstake withholds from an exit of `amount` (rounded up,
+    /// so splitting an exit into small pieces doesn't avoid the penalty)
+    pub fn early_exit_penalty(&self, amount: u64) -> u64 {
+        let bps = self.early_exit_penalty_bps.min(BPS_DENOMINATOR) as u128;
+        ((amount as u128) * bps).div_ceil(BPS_DENOMINATOR as u128) as u64
     }
 
-    /// Derive user stake PDA
-    pub fn derive_pda(pool: &Pubkey, owner: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[STAKE_SEED, pool.as_ref(), owner.as_ref()], program_id)
+    /// Sum of the reward fee split shares (bps)
+    pub fn fee_split_total_bps(&self) -> u64 {
+        self.fee_split_bps.iter().map(|bps| *bps as u64).sum()
     }
 
-    /// Get the effective last stake time (falls back to stake_time for existing accounts)
-    pub fn effective_last_stake_time(&self) -> i64 {
-        if self.last_stake_time != 0 {
-            self.last_stake_time
-        } else {
-            self.stake_time
+    /// Replace the fee split. Unpaid shares follow their recipient if it
+    /// stays in the split; a dropped recipient's unpaid shares go back to
+    /// stakers with the next sync.
+    pub fn set_fee_split(
+        &mut self,
+        recipients: [Pubkey; FEE_SPLIT_SLOTS],
+        shares: [u16; FEE_SPLIT_SLOTS],
+    ) {
+        let mut unpaid = [0u64; FEE_SPLIT_SLOTS];
+        for (old, owed) in self.fee_split_recipients.iter().zip(self.fee_split_unpaid) {
+            if owed == 0 {
+                continue;
+            }
+            match recipients.iter().position(|recipient| recipient == old) {
+                Some(i) => unpaid[i] = owed,
+                None => {
+                    // Redistributed to stakers by the next sync
+                    self.last_synced_lamports = self.last_synced_lamports.saturating_sub(owed);
+                    msg!("Released {} unpaid lamports of dropped recipient {}", owed, old);
+                }
+            }
         }
+        self.fee_split_recipients = recipients;
+        self.fee_split_bps = shares;
+        self.fee_split_unpaid = unpaid;
     }
 
-    /// Check if there is a pending unstake request
-    pub fn has_pending_unstake_request(&self) -> bool {
-        self.unstake_request_amount > 0
+    /// Portion of a claim of `amount` lamports owed to each fee split
+    /// recipient (rounded down)
+    pub fn fee_split_amounts(&self, amount: u64) -> [u64; FEE_SPLIT_SLOTS] {
+        self.fee_split_bps.map(|bps| {
+            ((amount as u128) * (bps as u128) / (BPS_DENOMINATOR as u128)) as u64
+        })
     }
 
-    /// Lazily adjust exp_start_factor when pool has been rebased.
-    /// Must be called before any calculation that uses exp_start_factor.
-    /// Returns true if an adjustment was made.
-    pub fn sync_to_pool(&mut self, pool: &StakingPool) -> Result<bool, StakingError> {
-        if self.base_time_snapshot == pool.base_time {
-            return Ok(false);
+    /// Idempotency key for a depositor's nonce. Keyed by depositor so one
+    /// depositor can't burn another's nonce; never 0 (the empty slot marker).
+    pub fn deposit_nonce_key(depositor: &Pubkey, nonce: u64) -> u64 {
+        let hash = hashv(&[depositor.as_ref(), &nonce.to_le_bytes()]);
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&hash.as_ref()[..8]);
+        u64::from_le_bytes(bytes).max(1)
+    }
+
+    /// Remember a deposit idempotency key, evicting the oldest.
+    /// Returns false if the key is already in the ring.
+    pub fn record_deposit_nonce(&mut self, key: u64) -> bool {
+        if self.recent_deposit_nonces.contains(&key) {
+            return false;
         }
+        let slot = self.deposit_nonce_cursor as usize % DEPOSIT_NONCE_SLOTS;
+        self.recent_deposit_nonces[slot] = key;
+        self.deposit_nonce_cursor = ((slot + 1) % DEPOSIT_NONCE_SLOTS) as u8;
+        true
+    }
 
-        if self.base_time_snapshot == 0 {
-            // Legacy account (created before rebase-aware upgrade)
-            if pool.initial_base_time == 0 {
-                // No rebase has occurred since upgrade — exp_start_factor is still
-                // relative to the current pool.base_time, so no adjustment needed.
-                self.base_time_snapshot = pool.base_time;
-                return Ok(true);
-            }
-            // A rebase has occurred — adjust from the original base_time
-            let delta = pool.base_time.saturating_sub(pool.initial_base_time);
-            if delta > 0 {
-                let adjustment = exp_neg_time_ratio(delta, pool.tau_seconds)?;
-                self.exp_start_factor = wad_mul(self.exp_start_factor, adjustment)?;
-            }
-            self.base_time_snapshot = pool.base_time;
-            return Ok(true);
+    /// Convert WAD-scaled rewards computed from the stored (scaled-down)
+    /// accumulator back to real WAD-scaled rewards
+    pub fn unscale_rewards(&self, scaled: u128) -> Result<u128, StakingError> {
+        if self.acc_scale_shift == 0 {
+            return Ok(scaled);
         }
+        (U256::from_u128(scaled) << self.acc_scale_shift as usize)
+            .to_u128()
+            .ok_or(StakingError::MathOverflow)
+    }
 
-        // Standard case: adjust from the snapshot's base_time to the current one
-        let delta = pool.base_time.saturating_sub(self.base_time_snapshot);
-        if delta > 0 {
-            let adjustment = exp_neg_time_ratio(delta, pool.tau_seconds)?;
-            self.exp_start_factor = wad_mul(self.exp_start_factor, adjustment)?;
+    /// Whether staking `amount` more would take total_staked past
+    /// max_total_staked (never with the default 0 = unlimited)
+    pub fn exceeds_stake_cap(&self, amount: u64) -> bool {
+        if self.max_total_staked == 0 {
+            return false;
+        }
+        match self.total_staked.checked_add(amount as u128) {
+            Some(total) => total > self.max_total_staked as u128,
+            None => true,
         }
-        self.base_time_snapshot = pool.base_time;
-        Ok(true)
     }
-}
 
-impl BorshDeserialize for UserStake {
-    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
-        let discriminator = <[u8; 8]>::deserialize_reader(reader)?;
-        let owner = Pubkey::deserialize_reader(reader)?;
-        let pool = Pubkey::deserialize_reader(reader)?;
-        let amount = u64::deserialize_reader(reader)?;
-        let stake_time = i64::deserialize_reader(reader)?;
-        let exp_start_factor = u128::deserialize_reader(reader)?;
-        let reward_debt = u128::deserialize_reader(reader)?;
-        let bump = u8::deserialize_reader(reader)?;
-        let unstake_request_amount = u64::deserialize_reader(reader)?;
-        let unstake_request_time = i64::deserialize_reader(reader)?;
-        let last_stake_time = i64::deserialize_reader(reader)?;
-        let base_time_snapshot = i64::deserialize_reader(reader)?;
+    /// Whether `amount` is below min_distributable_per_event and should
+    /// wait for more lamports instead of being distributed now
+    pub fn below_min_distributable(&self, amount: u64) -> bool {
+        amount < self.min_distributable_per_event
+    }
 
-        // New fields — may not be present in legacy accounts
-        let total_rewards_claimed = u64::deserialize_reader(reader).unwrap_or(0);
-        let claimed_rewards_wad = u128::deserialize_reader(reader).unwrap_or(0);
+    /// Reject reward deposits below min_reward_deposit
+    pub fn check_min_reward_deposit(&self, amount: u64) -> Result<(), StakingError> {
+        if amount < self.min_reward_deposit {
+            msg!(
+                "Deposit of {} is below min_reward_deposit {}",
+                amount,
+                self.min_reward_deposit
+            );
+            return Err(StakingError::DepositBelowMinimum);
+        }
+        Ok(())
+    }
 
-        Ok(Self {
-            discriminator,
-            owner,
-            pool,
-            amount,
-            stake_time,
-            exp_start_factor,
-            reward_debt,
-            bump,
-            unstake_request_amount,
-            unstake_request_time,
-            last_stake_time,
-            base_time_snapshot,
-            total_rewards_claimed,
-            claimed_rewards_wad,
+    /// total_staked once `amount` tokens leave the pool. Fails with
+    /// `StakeAccountingUnderflow` rather than wrapping if the books hold
+    /// fewer staked tokens than a position is withdrawing.
+    pub fn total_staked_after_unstake(&self, amount: u64) -> Result<u128, StakingError> {
+        self.total_staked.checked_sub(amount as u128).ok_or_else(|| {
+            msg!(
+                "Unstaking {} tokens but the pool only records {} staked",
+                amount,
+                self.total_staked
+            );
+            StakingError::StakeAccountingUnderflow
         })
     }
-}
 
-impl UserStake {
-    /// Realloc account to current LEN if it's a legacy (smaller) account.
-    /// Transfers additional rent from payer to the account via system program CPI.
-    /// No-op if account is already at or above current LEN.
-    pub fn maybe_realloc<'a>(
+    /// Lamports SyncRewards would distribute now out of `available` (the pool
+    /// balance above rent): 0 without stakers or below the per-event minimum
+    pub fn syncable_rewards(&self, available: u64) -> u64 {
+        let new_rewards = available.saturating_sub(self.last_synced_lamports);
+        if self.total_staked == 0 || self.below_min_distributable(new_rewards) {
+            return 0;
+        }
+        new_rewards
+    }
+
+    /// Lamports that arrived while nothing was staked and were never
+    /// distributed (what sync would count as new rewards, released streams
+    /// only). Always 0 once tokens are staked: those lamports belong to the
+    /// stakers from the next sync on.
+    pub fn deferred_rewards(&self, pool_lamports: u64, rent_exempt: u64, current_time: i64) -> u64 {
+        if self.total_staked != 0 {
+            return 0;
+        }
+        self.reward_lamports(pool_lamports, rent_exempt, current_time)
+            .saturating_sub(self.last_synced_lamports)
+    }
+
+    /// Lamports of streamed deposits still held back at `current_time`.
+    /// Rounded up, so a stream never releases ahead of its schedule.
+    pub fn unreleased_stream_lamports(&self, current_time: i64) -> u64 {
+        if self.reward_stream_lamports == 0 || current_time >= self.reward_stream_end {
+            return 0;
+        }
+        let start = self.reward_stream_start.min(self.reward_stream_end);
+        let duration = self.reward_stream_end.saturating_sub(start) as u128;
+        let left = self.reward_stream_end.saturating_sub(current_time.max(start)) as u128;
+        ((self.reward_stream_lamports as u128) * left).div_ceil(duration) as u64
+    }
+
+    /// Pool lamports sync may count as rewards: the balance above rent,
+    /// less streamed deposits not yet released
+    pub fn reward_lamports(&self, pool_lamports: u64, rent_exempt: u64, current_time: i64) -> u64 {
+        pool_lamports
+            .saturating_sub(rent_exempt)
+            .saturating_sub(self.unreleased_stream_lamports(current_time))
+    }
+
+    /// Stream `amount` lamports out over `duration` seconds from
+    /// `current_time`. A stream still running is merged in at the sum of
+    /// both release rates; the merged stream ends once both amounts are
+    /// out, never before the shorter one would have ended.
+    pub fn add_reward_stream(
+        &mut self,
+        amount: u64,
+        duration: u64,
+        current_time: i64,
+    ) -> Result<(), StakingError> {
+        let unreleased = self.unreleased_stream_lamports(current_time) as u128;
+        let amount = amount as u128;
+        let duration = duration as u128;
+        let total = unreleased
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+
+        // Until the new end, (unreleased + amount) / (end - now) equals
+        // unreleased / remaining + amount / duration
+        let new_duration = if unreleased == 0 {
+            duration
+        } else {
+            let remaining = self.reward_stream_end.saturating_sub(current_time) as u128;
+            let rate_sum = unreleased
+                .checked_mul(duration)
+                .and_then(|v| v.checked_add(amount.checked_mul(remaining)?))
+                .ok_or(StakingError::MathOverflow)?;
+            total
+                .checked_mul(remaining)
+                .and_then(|v| v.checked_mul(duration))
+                .ok_or(StakingError::MathOverflow)?
+                .div_ceil(rate_sum)
+        };
+
+        self.reward_stream_lamports = u64::try_from(total).map_err(|_| StakingError::MathOverflow)?;
+        self.reward_stream_start = current_time;
+        self.reward_stream_end = current_time
+            .checked_add(i64::try_from(new_duration).map_err(|_| StakingError::MathOverflow)?)
+            .ok_or(StakingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Distribute the lamports SyncRewards would (sent straight to the pool,
+    /// deposits left pending, or released by a reward stream) and advance
+    /// last_synced_lamports past them.
+    ///
+    /// Both SyncRewards and DepositRewards sweep through here first, and a
+    /// deposit then adds only its own amount, so every lamport reaches the
+    /// accumulator exactly once. Returns the lamports distributed and the
+    /// reward-per-share increment, or None if they stay pending.
+    pub fn sync_accumulator(
+        &mut self,
+        pool_lamports: u64,
+        rent_exempt: u64,
+        current_time: i64,
+    ) -> Result<Option<(u64, u128)>, StakingError> {
+        let available = self.reward_lamports(pool_lamports, rent_exempt, current_time);
+        let new_rewards = self.syncable_rewards(available);
+        if new_rewards == 0 {
+            return Ok(None);
+        }
+
+        // Denominator: total_staked * WAD (max weight; each stake's time weight
+        // is applied to its share when it claims)
+        let total_staked_wad = self
+            .total_staked
+            .checked_mul(WAD)
+            .ok_or(StakingError::MathOverflow)?;
+        let (reward_per_share, distributed) =
+            self.reward_per_share_for(new_rewards, total_staked_wad)?;
+        if reward_per_share == 0 {
+            // Too little to register in the accumulator: keep it pending
+            return Ok(None);
+        }
+
+        self.add_reward_per_share(reward_per_share)?;
+        // Only what the increment pays out counts as synced; the rounding
+        // remainder stays pending for the next sync
+        self.last_synced_lamports = self
+            .last_synced_lamports
+            .checked_add(distributed)
+            .ok_or(StakingError::MathOverflow)?;
+
+        Ok(Some((distributed, reward_per_share)))
+    }
+
+    /// Reward-per-share increment for spreading `amount` over `shares_wad`
+    /// (total_staked * WAD, less any excluded stake), rounded down to what
+    /// the accumulator holds at its current precision, and the lamports
+    /// (rounded up) that increment pays out. The rest of `amount` is never
+    /// owed to anyone, so it must stay pending rather than count as synced.
+    ///
+    /// The increment must round down: rounded to nearest, it can pay out
+    /// more than `amount` once one accumulator unit is worth a lamport or so.
+    pub fn reward_per_share_for(
+        &self,
+        amount: u64,
+        shares_wad: u128,
+    ) -> Result<(u128, u64), StakingError> {
+        let amount_wad = (amount as u128)
+            .checked_mul(WAD)
+            .ok_or(StakingError::MathOverflow)?;
+        let reward_per_share = (wad_div(amount_wad, shares_wad)? >> self.acc_scale_shift)
+            << self.acc_scale_shift;
+        let paid_wad = wad_mul(reward_per_share, shares_wad)?;
+        Ok((reward_per_share, paid_wad.div_ceil(WAD) as u64))
+    }
+
+    /// Count a distribution of `amount` lamports at `current_time` toward the
+    /// reward rate estimate. A window longer than two REWARD_RATE_WINDOW_SECONDS
+    /// is shrunk back to one, keeping its average rate.
+    pub fn record_reward_rate(&mut self, amount: u64, current_time: i64) {
+        if self.reward_rate_window_start == 0 || self.reward_rate_window_start > current_time {
+            self.reward_rate_window_start = current_time;
+            self.reward_rate_window_sum = 0;
+        }
+        let span = current_time - self.reward_rate_window_start;
+        if span > 2 * REWARD_RATE_WINDOW_SECONDS {
+            self.reward_rate_window_sum = ((self.reward_rate_window_sum as u128)
+                * REWARD_RATE_WINDOW_SECONDS as u128
+                / span as u128) as u64;
+            self.reward_rate_window_start = current_time - REWARD_RATE_WINDOW_SECONDS;
+        }
+        self.reward_rate_window_sum = self.reward_rate_window_sum.saturating_add(amount);
+    }
+
+    /// The display unit label as a string (empty = none)
+    pub fn display_unit(&self) -> &str {
+        let len = self
+            .display_unit_label
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(DISPLAY_UNIT_LABEL_LEN);
+        std::str::from_utf8(&self.display_unit_label[..len]).unwrap_or("")
+    }
+
+    /// Estimated lamports distributed per second over the recent window
+    /// (0 before any distribution)
+    pub fn reward_rate(&self, current_time: i64) -> u64 {
+        if self.reward_rate_window_start == 0 {
+            return 0;
+        }
+        let span = current_time.saturating_sub(self.reward_rate_window_start).max(1) as u64;
+        self.reward_rate_window_sum / span
+    }
+
+    /// Add a real reward-per-share increment to the accumulator, then make
+    /// sure reward debts at the current total_staked still fit.
+    ///
+    /// This is the accumulator's rebase: it never runs into u128::MAX, since
+    /// the rescale keeps it at or below 2^126 and one increment (at most
+    /// u64::MAX lamports per token unit, WAD-scaled) is under 2^124.
+    pub fn add_reward_per_share(&mut self, reward_per_share: u128) -> Result<(), StakingError> {
+        self.acc_reward_per_weighted_share = self
+            .acc_reward_per_weighted_share
+            .checked_add(reward_per_share >> self.acc_scale_shift)
+            .ok_or(StakingError::MathOverflow)?;
+        self.rescale_for(self.total_staked)
+    }
+
+    /// Reward-per-share increment as stored in the accumulator
+    pub fn scale_reward_per_share(&self, reward_per_share: u128) -> u128 {
+        reward_per_share >> self.acc_scale_shift
+    }
+
+    /// Start the accumulator at a lower precision (SetRewardPrecision).
+    ///
+    /// Only possible before the first distribution, while the accumulator is
+    /// still zero, so no reward_debt has been recorded at the old scale.
+    pub fn set_reward_precision_shift(&mut self, shift: u8) -> Result<(), StakingError> {
+        if shift > MAX_REWARD_PRECISION_SHIFT {
+            return Err(StakingError::SettingExceedsMaximum);
+        }
+        if self.acc_reward_per_weighted_share != 0 {
+            return Err(StakingError::RewardsAlreadyDistributed);
+        }
+        self.acc_scale_shift = shift;
+        Ok(())
+    }
+
+    /// Lower the accumulator precision just enough that a reward_debt of
+    /// `total_staked * acc` stays below MAX_SCALED_REWARD_DEBT.
+    ///
+    /// Tiny pools can push the accumulator very high (one lamport per token
+    /// unit is already 1e18), which would overflow the reward_debt of a large
+    /// staker joining later. Each extra bit of shift halves the accumulator
+    /// and total_reward_debt; stakes catch up lazily in `sync_to_pool`.
+    pub fn rescale_for(&mut self, total_staked: u128) -> Result<(), StakingError> {
+        let product = U256::from_u128(total_staked)
+            .checked_mul(U256::from_u128(self.acc_reward_per_weighted_share))
+            .ok_or(StakingError::MathOverflow)?;
+        if product <= U256::from_u128(MAX_SCALED_REWARD_DEBT) {
+            return Ok(());
+        }
+        // product < 2^bits, so shifting by bits - 126 brings it to <= 2^126
+        let shift = product.bits() as u32 - MAX_SCALED_REWARD_DEBT.trailing_zeros();
+        let new_shift = (self.acc_scale_shift as u32) + shift;
+        if new_shift > MAX_ACC_SCALE_SHIFT as u32 {
+            return Err(StakingError::MathOverflow);
+        }
+        self.acc_reward_per_weighted_share >>= shift;
+        self.total_reward_debt >>= shift;
+        self.acc_scale_shift = new_shift as u8;
+        msg!("Reward accumulator rescaled by 2^{}", new_shift);
+        Ok(())
+    }
+
+    /// Fail with `InvalidTokenProgram` unless `token_program` is the one the
+    /// pool was created with. Pools from before it was recorded fall back to
+    /// the mint's owner, which never changes either.
+    pub fn check_token_program(
+        &self,
+        token_program: &Pubkey,
+        mint_owner: &Pubkey,
+    ) -> Result<(), StakingError> {
+        let expected = if self.token_program == Pubkey::default() {
+            mint_owner
+        } else {
+            &self.token_program
+        };
+        if token_program != expected {
+            msg!("Pool tokens belong to {}, not {}", expected, token_program);
+            return Err(StakingError::InvalidTokenProgram);
+        }
+        Ok(())
+    }
+
+    /// Mint decimals cached at init (None for pools created before caching)
+    pub fn cached_mint_decimals(&self) -> Option<u8> {
+        self.mint_decimals_cached.then_some(self.mint_decimals)
+    }
+
+    /// Cache the mint decimals (InitializePool)
+    pub fn set_mint_decimals(&mut self, decimals: u8) {
+        self.mint_decimals_cached = true;
+        self.mint_decimals = decimals;
+    }
+
+    /// Write the pool back to its account (legacy-size aware, see `pack_into_slice`)
+    pub fn save(&self, pool_info: &AccountInfo) -> ProgramResult {
+        let mut pool_data = pool_info.try_borrow_mut_data()?;
+        pack_into_slice(self, &mut pool_data).map_err(|e| {
+            msg!("Pool account is too small for the new state; it must be reallocated first");
+            e.into()
+        })
+    }
+
+    /// Grow a legacy pool account only if the current state no longer fits
+    /// (i.e. an appended field holds a non-default value).
+    pub fn ensure_capacity<'a>(
+        &self,
         account: &AccountInfo<'a>,
         payer: &AccountInfo<'a>,
         system_program: Option<&AccountInfo<'a>>,
-    ) -> Result<(), solana_program::program_error::ProgramError> {
+    ) -> Result<(), ProgramError> {
         if account.data_len() >= Self::LEN {
             return Ok(());
         }
+        let bytes = borsh::to_vec(self).map_err(|_| StakingError::InvalidInstruction)?;
+        if bytes[account.data_len().min(bytes.len())..].iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        Self::maybe_realloc(account, payer, system_program)
+    }
 
-        let rent = solana_program::rent::Rent::get()?;
-        let new_rent = rent.minimum_balance(Self::LEN);
-        let old_rent = rent.minimum_balance(account.data_len());
-        let rent_delta = new_rent.saturating_sub(old_rent);
+    /// Realloc a legacy (smaller) pool account to the current LEN.
+    /// Rent for the extra bytes is transferred from payer via system program CPI.
+    /// No-op if the account is already at or above current LEN.
+    pub fn maybe_realloc<'a>(
+        account: &AccountInfo<'a>,
+        payer: &AccountInfo<'a>,
+        system_program: Option<&AccountInfo<'a>>,
+    ) -> Result<(), ProgramError> {
+        realloc_with_rent(account, payer, system_program, Self::LEN)
+    }
+}
 
-        if rent_delta > 0 {
-            let sys_prog = system_program
-                .ok_or(StakingError::MissingSystemProgram)?;
-            solana_program::program::invoke(
-                &solana_program::system_instruction::transfer(
-                    payer.key,
-                    account.key,
-                    rent_delta,
-                ),
-                &[payer.clone(), account.clone(), sys_prog.clone()],
-            )?;
-        }
+impl BorshDeserialize for StakingPool {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let discriminator = <[u8; 8]>::deserialize_reader(reader)?;
+        let mint = Pubkey::deserialize_reader(reader)?;
+        let token_vault = Pubkey::deserialize_reader(reader)?;
+        let reward_vault = Pubkey::deserialize_reader(reader)?;
+        let authority = Pubkey::deserialize_reader(reader)?;
+        let total_staked = u128::deserialize_reader(reader)?;
+        let sum_stake_exp = <[u8; 32]>::deserialize_reader(reader)?;
+        let tau_seconds = u64::deserialize_reader(reader)?;
+        let base_time = i64::deserialize_reader(reader)?;
+        let acc_reward_per_weighted_share = u128::deserialize_reader(reader)?;
+        let last_update_time = i64::deserialize_reader(reader)?;
+        let bump = u8::deserialize_reader(reader)?;
+        let last_synced_lamports = u64::deserialize_reader(reader)?;
+        let min_stake_amount = u64::deserialize_reader(reader)?;
+        let lock_duration_seconds = u64::deserialize_reader(reader)?;
+        let unstake_cooldown_seconds = u64::deserialize_reader(reader)?;
+        let initial_base_time = i64::deserialize_reader(reader)?;
+        let total_reward_debt = u128::deserialize_reader(reader)?;
+        let total_residual_unpaid = u64::deserialize_reader(reader)?;
 
-        account.realloc(Self::LEN, false)?;
+        // Appended fields — absent in legacy pools
+        let total_claimed_balance = u64::deserialize_reader(reader).unwrap_or(0);
+        let max_deferred_lamports = u64::deserialize_reader(reader).unwrap_or(0);
+        let loyalty_bonus_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let exclude_depositor = bool::deserialize_reader(reader).unwrap_or(false);
+        let recent_deposit_nonces =
+            <[u64; DEPOSIT_NONCE_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let deposit_nonce_cursor = u8::deserialize_reader(reader).unwrap_or(0);
+        let per_stake_terms = bool::deserialize_reader(reader).unwrap_or(false);
+        let claim_fee_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let accrued_protocol_fees = u64::deserialize_reader(reader).unwrap_or(0);
+        let quarantined = bool::deserialize_reader(reader).unwrap_or(false);
+        let acc_scale_shift = u8::deserialize_reader(reader).unwrap_or(0);
+        let fee_split_recipients =
+            <[Pubkey; FEE_SPLIT_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let fee_split_bps =
+            <[u16; FEE_SPLIT_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let unstake_reversal_window_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let mint_decimals_cached = bool::deserialize_reader(reader).unwrap_or(false);
+        let mint_decimals = u8::deserialize_reader(reader).unwrap_or(0);
+        let audit_log_enabled = bool::deserialize_reader(reader).unwrap_or(false);
+        let audit_log_page = u32::deserialize_reader(reader).unwrap_or(0);
+        let unstake_destination_policy =
+            UnstakeDestinationPolicy::deserialize_reader(reader).unwrap_or_default();
+        let min_distributable_per_event = u64::deserialize_reader(reader).unwrap_or(0);
+        let settings_locked = bool::deserialize_reader(reader).unwrap_or(false);
+        let reward_rate_window_start = i64::deserialize_reader(reader).unwrap_or(0);
+        let reward_rate_window_sum = u64::deserialize_reader(reader).unwrap_or(0);
+        let display_unit_label =
+            <[u8; DISPLAY_UNIT_LABEL_LEN]>::deserialize_reader(reader).unwrap_or_default();
+        let display_unit_decimals = u8::deserialize_reader(reader).unwrap_or(0);
+        let display_total_distributed = u64::deserialize_reader(reader).unwrap_or(0);
+        let delegates =
+            <[Pubkey; DELEGATE_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let delegate_permissions =
+            <[u8; DELEGATE_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let tau_change_times =
+            <[i64; TAU_HISTORY_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let tau_change_previous =
+            <[u64; TAU_HISTORY_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let reward_mint = Pubkey::deserialize_reader(reader).unwrap_or_default();
+        let max_total_staked = u64::deserialize_reader(reader).unwrap_or(0);
+        let min_reward_deposit = u64::deserialize_reader(reader).unwrap_or(0);
+        let paused = bool::deserialize_reader(reader).unwrap_or(false);
+        let early_exit_penalty_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let penalty_tokens = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_authority = Pubkey::deserialize_reader(reader).unwrap_or_default();
+        let token_program = Pubkey::deserialize_reader(reader).unwrap_or_default();
+        let lock_boost_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let reward_stream_lamports = u64::deserialize_reader(reader).unwrap_or(0);
+        let reward_stream_start = i64::deserialize_reader(reader).unwrap_or(0);
+        let reward_stream_end = i64::deserialize_reader(reader).unwrap_or(0);
+        let require_allowlisted_depositors = bool::deserialize_reader(reader).unwrap_or(false);
+        let settings_timelock_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_settings_mask = u16::deserialize_reader(reader).unwrap_or(0);
+        let pending_settings_effective_time = i64::deserialize_reader(reader).unwrap_or(0);
+        let pending_lock_duration_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_unstake_cooldown_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_claim_fee_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_early_exit_penalty_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_settings_timelock_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_loyalty_bonus_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_lock_boost_bps = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_min_distributable_per_event = u64::deserialize_reader(reader).unwrap_or(0);
+        let pending_fee_split_recipients =
+            <[Pubkey; FEE_SPLIT_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let pending_fee_split_bps =
+            <[u16; FEE_SPLIT_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let fee_split_unpaid =
+            <[u64; FEE_SPLIT_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let pending_tau_seconds = u64::deserialize_reader(reader).unwrap_or(0);
 
-        Ok(())
+        Ok(Self {
+            discriminator,
+            mint,
+            token_vault,
+            reward_vault,
+            authority,
+            total_staked,
+            sum_stake_exp,
+            tau_seconds,
+            base_time,
+            acc_reward_per_weighted_share,
+            last_update_time,
+            bump,
+            last_synced_lamports,
+            min_stake_amount,
+            lock_duration_seconds,
+            unstake_cooldown_seconds,
+            initial_base_time,
+            total_reward_debt,
+            total_residual_unpaid,
+            total_claimed_balance,
+            max_deferred_lamports,
+            loyalty_bonus_bps,
+            exclude_depositor,
+            recent_deposit_nonces,
+            deposit_nonce_cursor,
+            per_stake_terms,
+            claim_fee_bps,
+            accrued_protocol_fees,
+            quarantined,
+            acc_scale_shift,
+            fee_split_recipients,
+            fee_split_bps,
+            unstake_reversal_window_seconds,
+            mint_decimals_cached,
+            mint_decimals,
+            audit_log_enabled,
+            audit_log_page,
+            unstake_destination_policy,
+            min_distributable_per_event,
+            settings_locked,
+            reward_rate_window_start,
+            reward_rate_window_sum,
+            display_unit_label,
+            display_unit_decimals,
+            display_total_distributed,
+            delegates,
+            delegate_permissions,
+            tau_change_times,
+            tau_change_previous,
+            reward_mint,
+            max_total_staked,
+            min_reward_deposit,
+            paused,
+            early_exit_penalty_bps,
+            penalty_tokens,
+            pending_authority,
+            token_program,
+            lock_boost_bps,
+            reward_stream_lamports,
+            reward_stream_start,
+            reward_stream_end,
+            require_allowlisted_depositors,
+            settings_timelock_seconds,
+            pending_settings_mask,
+            pending_settings_effective_time,
+            pending_lock_duration_seconds,
+            pending_unstake_cooldown_seconds,
+            pending_claim_fee_bps,
+            pending_early_exit_penalty_bps,
+            pending_settings_timelock_seconds,
+            pending_loyalty_bonus_bps,
+            pending_lock_boost_bps,
+            pending_min_distributable_per_event,
+            pending_fee_split_recipients,
+            pending_fee_split_bps,
+            fee_split_unpaid,
+            pending_tau_seconds,
+        })
     }
 }
 
-/// Pool metadata account for explorer display
-/// PDA: ["metadata", pool]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
-pub struct PoolMetadata {
+/// User stake account
+/// PDA: ["stake", pool, owner]
+#[derive(BorshSerialize, Debug, Clone)]
+pub struct UserStake {
     /// Discriminator for account type identification
     pub discriminator: [u8; 8],
 
-    /// Back-reference to staking pool
+    /// Owner of this stake
+    pub owner: Pubkey,
+
+    /// Pool this stake belongs to
     pub pool: Pubkey,
 
-    /// Actual byte length of name
-    pub name_len: u8,
+    /// Amount of tokens staked
+    pub amount: u64,
 
-    /// UTF-8 name, zero-padded
-    pub name: [u8; 64],
+    /// Unix timestamp when stake began
+    pub stake_time: i64,
 
-    /// Number of active tags (max 8)
-    pub num_tags: u8,
+    /// e^((stake_time - base_time) / tau) at time of staking, WAD-scaled
+    /// Used to track contribution to sum_stake_exp
+    pub exp_start_factor: u128,
 
-    /// Byte length of each tag
-    pub tag_lengths: [u8; 8],
+    /// Reward debt encoding an acc_rps snapshot for pending reward calculation.
+    /// Encodes: reward_debt = wad_mul(amount * WAD, snapshot_acc_rps).
+    /// Pending = user_weighted * (current_acc_rps - snapshot_acc_rps).
+    /// When amount == 0 (post-full-unstake), reinterpreted as unclaimed WAD-scaled rewards.
+    pub reward_debt: u128,
 
-    /// UTF-8 tags, zero-padded
-    pub tags: [[u8; 32]; 8],
+    /// PDA bump seed
+    pub bump: u8,
 
-    /// Actual byte length of url
-    pub url_len: u8,
+    /// Pending unstake request amount (0 = no pending request)
+    pub unstake_request_amount: u64,
 
-    /// UTF-8 URL, zero-padded
-    pub url: [u8; 128],
+    /// Timestamp when unstake was requested
+    pub unstake_request_time: i64,
 
-    /// Active staker count
-    pub member_count: u64,
+    /// Timestamp of most recent stake deposit (for lock duration checks)
+    /// Falls back to stake_time when 0 (for existing accounts)
+    pub last_stake_time: i64,
 
-    /// PDA bump seed
-    pub bump: u8,
+    /// Pool base_time when exp_start_factor was last calibrated.
+    /// 0 = legacy account (pre-rebase-aware); treated as matching the pool's
+    /// initial_base_time or current base_time if no rebase has occurred.
+    pub base_time_snapshot: i64,
+
+    /// Cumulative rewards claimed by this user (lamports, or reward token
+    /// units on an SPL-token reward pool), including rewards paid by unstakes
+    /// and credited by ClaimToBalance.
+    /// Defaults to 0 for legacy 153-byte accounts (populated on first realloc).
+    pub total_rewards_claimed: u64,
+
+    /// Cumulative WAD-scaled rewards already paid out for the current position.
+    /// Used to make claims frequency-independent: pending = full_entitlement - claimed_rewards_wad.
+    /// Preserved on add-stake (pending rewards unchanged). Reset to 0 on unstake
+    /// (partial/full) when the position is restructured and pending is settled.
+    /// Defaults to 0 for existing accounts (correct: first claim gets full pending).
+    pub claimed_rewards_wad: u128,
+
+    /// Rewards credited by ClaimToBalance and not yet withdrawn (lamports).
+    /// The lamports stay in the pool until WithdrawClaimed.
+    pub claimed_balance: u64,
+
+    /// Set once GrandfatherStake has credited prior staking time (one-shot)
+    pub grandfathered: bool,
+
+    /// Time of the last claim, even one that paid nothing, or of the last
+    /// unstake that paid rewards (0 = never). The loyalty bonus ramp restarts
+    /// from it (or from stake_time if later).
+    pub last_claim_time: i64,
+
+    /// Set when the pool's lock/cooldown terms were stamped on this stake;
+    /// the stamped values then apply instead of the pool's current ones
+    pub terms_stamped: bool,
+
+    /// Lock duration stamped from the pool (valid when `terms_stamped`)
+    pub lock_duration_seconds: u64,
+
+    /// Unstake cooldown stamped from the pool (valid when `terms_stamped`)
+    pub unstake_cooldown_seconds: u64,
+
+    /// Pool acc_scale_shift that reward_debt is expressed in
+    pub acc_scale_shift: u8,
+
+    /// Owner allows anyone to trigger ClaimFor (rewards still go to the owner)
+    pub claim_for_enabled: bool,
+
+    /// Time of the last CompleteUnstake that ReverseUnstake may undo
+    /// (0 = nothing to reverse)
+    pub last_unstake_completion_time: i64,
+
+    /// Tokens withdrawn by that CompleteUnstake
+    pub last_unstake_completion_amount: u64,
+
+    /// Lock the owner committed to when staking; the effective lock never
+    /// drops below it
+    pub lock_commit_seconds: u64,
+
+    /// Referrer given when the stake account was created (default = none)
+    pub referrer: Pubkey,
+
+    /// Position index in the PDA seeds (0 = the owner's default stake
+    /// account, others come from InitializeUserStake)
+    pub position_index: u16,
+
+    /// Tokens added by recent top-ups, each locked from the matching
+    /// `top_up_lock_times` entry instead of last_stake_time, oldest first
+    /// (0 = unused slot)
+    pub top_up_lock_amounts: [u64; TOP_UP_LOCK_SLOTS],
+
+    /// Lock start of the matching `top_up_lock_amounts` entry
+    pub top_up_lock_times: [i64; TOP_UP_LOCK_SLOTS],
+
+    /// Highest position index the owner opened with InitializeUserStake
+    /// (kept on the default stake account only), so every position can be
+    /// required when a deposit excludes the owner. Reset once none are open.
+    pub max_position_index: u16,
+
+    /// Positions above index 0 the owner holds open (kept on the default
+    /// stake account only, decremented as they close)
+    pub open_positions: u16,
 }
 
-impl PoolMetadata {
+impl UserStake {
     /// Size of the account in bytes
     pub const LEN: usize = 8 +  // discriminator
+        32 + // owner
         32 + // pool
-        1 +  // name_len
-        64 + // name
-        1 +  // num_tags
-        8 +  // tag_lengths
-        256 + // tags (8 * 32)
-        1 +  // url_len
-        128 + // url
-        8 +  // member_count
-        1;   // bump
+        8 +  // amount
+        8 +  // stake_time
+        16 + // exp_start_factor
+        16 + // reward_debt
+        1 +  // bump
+        8 +  // unstake_request_amount
+        8 +  // unstake_request_time
+        8 +  // last_stake_time
+        8 +  // base_time_snapshot
+        8 +  // total_rewards_claimed
+        16 + // claimed_rewards_wad
+        8 +  // claimed_balance
+        1 +  // grandfathered
+        8 +  // last_claim_time
+        1 +  // terms_stamped
+        8 +  // lock_duration_seconds
+        8 +  // unstake_cooldown_seconds
+        1 +  // acc_scale_shift
+        1 +  // claim_for_enabled
+        8 +  // last_unstake_completion_time
+        8 +  // last_unstake_completion_amount
+        8 +  // lock_commit_seconds
+        32 + // referrer
+        2 +  // position_index
+        8 * TOP_UP_LOCK_SLOTS + // top_up_lock_amounts
+        8 * TOP_UP_LOCK_SLOTS + // top_up_lock_times
+        2 +  // max_position_index
+        2; // open_positions
+
+    /// Legacy account size (before claimed_rewards_wad was added)
+    pub const LEGACY_LEN: usize = 161;
+
+    /// Size of v4 accounts. Fields appended after this read as 0, so such
+    /// accounts only need to grow once one of them becomes non-default.
+    pub const V4_LEN: usize = 177;
+
+    /// Create a new user stake
+    pub fn new(
+        owner: Pubkey,
+        pool: Pubkey,
+        amount: u64,
+        stake_time: i64,
+        exp_start_factor: u128,
+        bump: u8,
+        base_time_snapshot: i64,
+    ) -> Self {
+        Self {
+            discriminator: USER_STAKE_DISCRIMINATOR,
+            owner,
+            pool,
+            amount,
+            stake_time,
+            exp_start_factor,
+            reward_debt: 0,
+            bump,
+            unstake_request_amount: 0,
+            unstake_request_time: 0,
+            last_stake_time: stake_time,
+            base_time_snapshot,
+            total_rewards_claimed: 0,
+            claimed_rewards_wad: 0,
+            claimed_balance: 0,
+            grandfathered: false,
+            last_claim_time: 0,
+            terms_stamped: false,
+            lock_duration_seconds: 0,
+            unstake_cooldown_seconds: 0,
+            acc_scale_shift: 0,
+            claim_for_enabled: false,
+            last_unstake_completion_time: 0,
+            last_unstake_completion_amount: 0,
+            lock_commit_seconds: 0,
+            referrer: Pubkey::default(),
+            position_index: 0,
+            top_up_lock_amounts: [0; TOP_UP_LOCK_SLOTS],
+            top_up_lock_times: [0; TOP_UP_LOCK_SLOTS],
+            max_position_index: 0,
+            open_positions: 0,
+        }
+    }
+
+    /// Check if stake is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.discriminator == USER_STAKE_DISCRIMINATOR
+    }
+
+    /// Derive user stake PDA
+    pub fn derive_pda(pool: &Pubkey, owner: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        derive_user_stake_pda(owner, pool, program_id)
+    }
+
+    /// Derive the PDA of this stake's own position (owner and position_index)
+    pub fn derive_position_pda(&self, pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        derive_user_stake_position_pda(&self.owner, pool, self.position_index, program_id)
+    }
+
+    /// Whether this default stake account records other open positions of
+    /// its owner; it then stays open, or those positions would go untracked
+    pub fn tracks_positions(&self) -> bool {
+        self.position_index == 0 && self.open_positions > 0
+    }
+
+    /// Record a newly opened position on this default stake account
+    pub fn open_position(&mut self, index: u16) {
+        self.max_position_index = self.max_position_index.max(index);
+        self.open_positions = self.open_positions.saturating_add(1);
+    }
+
+    /// Record a closed position on this default stake account; once none
+    /// are left, indices start over from 1
+    pub fn close_position(&mut self) {
+        self.open_positions = self.open_positions.saturating_sub(1);
+        if self.open_positions == 0 {
+            self.max_position_index = 0;
+        }
+    }
+
+    /// Whether this is a position opened by InitializeUserStake that has
+    /// never been staked into (the first Stake starts it like a new stake)
+    pub fn is_unused_position(&self) -> bool {
+        self.stake_time == 0 && self.amount == 0 && !self.has_pending_unstake_request()
+    }
+
+    /// Get the effective last stake time (falls back to stake_time for existing accounts)
+    pub fn effective_last_stake_time(&self) -> i64 {
+        if self.last_stake_time != 0 {
+            self.last_stake_time
+        } else {
+            self.stake_time
+        }
+    }
+
+    /// Record the pool's current lock/cooldown on this stake if the pool
+    /// uses per-stake terms (no-op otherwise)
+    pub fn stamp_terms(&mut self, pool: &StakingPool) {
+        if pool.per_stake_terms {
+            self.terms_stamped = true;
+            self.lock_duration_seconds = pool.lock_duration_seconds;
+            self.unstake_cooldown_seconds = pool.unstake_cooldown_seconds;
+        }
+    }
+
+    /// Lock duration that applies to this stake (stamped, else the pool's,
+    /// but never shorter than the owner's commitment)
+    pub fn effective_lock_duration(&self, pool: &StakingPool) -> u64 {
+        let terms = if self.terms_stamped {
+            self.lock_duration_seconds
+        } else {
+            pool.lock_duration_seconds
+        };
+        terms.max(self.lock_commit_seconds)
+    }
+
+    /// Tokens still within their lock at `current_time`: all of them until
+    /// the lock from last_stake_time ends, then those of top-ups whose own
+    /// lock is still running
+    pub fn locked_amount(&self, pool: &StakingPool, current_time: i64) -> u64 {
+        let lock_duration = self.effective_lock_duration(pool) as i64;
+        if lock_duration == 0 {
+            return 0;
+        }
+        if current_time < self.effective_last_stake_time().saturating_add(lock_duration) {
+            return self.amount;
+        }
+        let mut locked = 0u64;
+        for (amount, time) in self.top_up_lock_amounts.iter().zip(self.top_up_lock_times.iter()) {
+            if *amount > 0 && current_time < time.saturating_add(lock_duration) {
+                locked = locked.saturating_add(*amount);
+            }
+        }
+        locked.min(self.amount)
+    }
+
+    /// Fail with `StakeLocked` unless `amount` tokens are out of their lock
+    /// at `current_time`
+    pub fn check_unlocked(
+        &self,
+        pool: &StakingPool,
+        amount: u64,
+        current_time: i64,
+    ) -> Result<(), StakingError> {
+        let unlocked = self.amount.saturating_sub(self.locked_amount(pool, current_time));
+        if amount > unlocked {
+            msg!("Only {} of {} staked tokens are out of their lock", unlocked, self.amount);
+            return Err(StakingError::StakeLocked);
+        }
+        Ok(())
+    }
+
+    /// Time the last of this stake's tokens comes out of its lock
+    pub fn lock_end(&self, pool: &StakingPool) -> i64 {
+        let start = self
+            .top_up_lock_amounts
+            .iter()
+            .zip(self.top_up_lock_times.iter())
+            .filter(|(amount, _)| **amount > 0)
+            .map(|(_, time)| *time)
+            .fold(self.effective_last_stake_time(), i64::max);
+        start.saturating_add(self.effective_lock_duration(pool) as i64)
+    }
+
+    /// Lock `amount` top-up tokens from `current_time` on their own, leaving
+    /// the lock of the tokens already staked as it is. Expired entries are
+    /// dropped first; with every slot still locked the top-up merges into
+    /// the newest one, which then restarts its lock (never unlocking early).
+    pub fn lock_top_up(&mut self, pool: &StakingPool, amount: u64, current_time: i64) {
+        let lock_duration = self.effective_lock_duration(pool) as i64;
+        if lock_duration == 0 {
+            return;
+        }
+        let mut kept = 0;
+        for slot in 0..TOP_UP_LOCK_SLOTS {
+            let slot_amount = self.top_up_lock_amounts[slot];
+            let slot_time = self.top_up_lock_times[slot];
+            if slot_amount > 0 && current_time < slot_time.saturating_add(lock_duration) {
+                self.top_up_lock_amounts[kept] = slot_amount;
+                self.top_up_lock_times[kept] = slot_time;
+                kept += 1;
+            }
+        }
+        self.top_up_lock_amounts[kept..].fill(0);
+        self.top_up_lock_times[kept..].fill(0);
+
+        let slot = kept.min(TOP_UP_LOCK_SLOTS - 1);
+        self.top_up_lock_amounts[slot] = self.top_up_lock_amounts[slot].saturating_add(amount);
+        self.top_up_lock_times[slot] = current_time;
+    }
+
+    /// Restart the lock of every staked token from `current_time`
+    pub fn reset_lock(&mut self, current_time: i64) {
+        self.last_stake_time = current_time;
+        self.top_up_lock_amounts = [0; TOP_UP_LOCK_SLOTS];
+        self.top_up_lock_times = [0; TOP_UP_LOCK_SLOTS];
+    }
+
+    /// Shrink the top-up entries to what is still staked after tokens left
+    /// the position, oldest first: entries run out of their lock oldest
+    /// first, and unstakes only take unlocked tokens
+    pub fn trim_top_up_locks(&mut self) {
+        let mut excess = self
+            .top_up_lock_amounts
+            .iter()
+            .fold(0u64, |sum, amount| sum.saturating_add(*amount))
+            .saturating_sub(self.amount);
+        for slot in 0..TOP_UP_LOCK_SLOTS {
+            let cut = excess.min(self.top_up_lock_amounts[slot]);
+            self.top_up_lock_amounts[slot] -= cut;
+            excess -= cut;
+            if self.top_up_lock_amounts[slot] == 0 {
+                self.top_up_lock_times[slot] = 0;
+            }
+        }
+    }
+
+    /// Unstake cooldown that applies to this stake (stamped, else the pool's)
+    pub fn effective_unstake_cooldown(&self, pool: &StakingPool) -> u64 {
+        if self.terms_stamped {
+            self.unstake_cooldown_seconds
+        } else {
+            pool.unstake_cooldown_seconds
+        }
+    }
+
+    /// TERMS_FLAG_* bits describing which per-stake overrides apply
+    pub fn effective_terms_flags(&self, pool: &StakingPool) -> u8 {
+        let mut flags = 0;
+        if self.terms_stamped {
+            flags |= TERMS_FLAG_STAMPED;
+        }
+        if self.grandfathered {
+            flags |= TERMS_FLAG_GRANDFATHERED;
+        }
+        let terms_lock = if self.terms_stamped {
+            self.lock_duration_seconds
+        } else {
+            pool.lock_duration_seconds
+        };
+        if self.lock_commit_seconds > terms_lock {
+            flags |= TERMS_FLAG_LOCK_COMMITTED;
+        }
+        if self.claim_for_enabled {
+            flags |= TERMS_FLAG_CLAIM_FOR;
+        }
+        flags
+    }
+
+    /// Check if there is a pending unstake request
+    pub fn has_pending_unstake_request(&self) -> bool {
+        self.unstake_request_amount > 0
+    }
+
+    /// Whether the account can be closed: no staked tokens, no pending
+    /// unstake request, no unwithdrawn claimed balance, and no residual
+    /// unclaimed rewards worth >= 1 lamport.
+    ///
+    /// reward_debt stores unclaimed WAD-scaled rewards after a full unstake
+    /// when the pool lacked SOL. Sub-WAD dust (< 1 lamport) is forgiven to
+    /// prevent permanent lock of the account.
+    pub fn is_empty(&self) -> bool {
+        self.close_blocker().is_none()
+    }
+
+    /// What keeps the account from being closed: AccountNotEmpty while tokens
+    /// are staked or an unstake request is pending, OutstandingRewards while
+    /// a claimed balance or residual rewards (>= 1 lamport) remain.
+    pub fn close_blocker(&self) -> Option<StakingError> {
+        if self.amount > 0 || self.has_pending_unstake_request() {
+            Some(StakingError::AccountNotEmpty)
+        } else if self.claimed_balance > 0 || self.reward_debt / WAD > 0 {
+            Some(StakingError::OutstandingRewards)
+        } else {
+            None
+        }
+    }
+
+    /// Seconds from `current_time` until this stake's tokens can be withdrawn:
+    /// the rest of the lock, plus the rest of a pending request's cooldown or,
+    /// with no request yet, the full cooldown
+    pub fn time_to_unlock(&self, pool: &StakingPool, current_time: i64) -> u64 {
+        let lock_end = self.lock_end(pool);
+        let lock_remaining = lock_end.saturating_sub(current_time).max(0) as u64;
+
+        let cooldown = self.effective_unstake_cooldown(pool);
+        let cooldown_remaining = if self.has_pending_unstake_request() {
+            let cooldown_end = self.unstake_request_time.saturating_add(cooldown as i64);
+            cooldown_end.saturating_sub(current_time).max(0) as u64
+        } else {
+            cooldown
+        };
+
+        lock_remaining.saturating_add(cooldown_remaining)
+    }
+
+    /// Time at which a pending unstake request's cooldown ends (0 with no
+    /// request pending)
+    pub fn unstake_cooldown_end(&self, pool: &StakingPool) -> i64 {
+        if !self.has_pending_unstake_request() {
+            return 0;
+        }
+        self.unstake_request_time
+            .saturating_add(self.effective_unstake_cooldown(pool) as i64)
+    }
+
+    /// Whether CompleteUnstake would accept this stake's pending request at
+    /// `current_time`: a request is pending and its cooldown has elapsed
+    pub fn is_unstake_completable(&self, pool: &StakingPool, current_time: i64) -> bool {
+        if !self.has_pending_unstake_request() {
+            return false;
+        }
+        let elapsed = current_time.saturating_sub(self.unstake_request_time).max(0) as u64;
+        elapsed >= self.effective_unstake_cooldown(pool)
+    }
+
+    /// Change the pending unstake request to `new_amount` at `current_time`
+    ///
+    /// Lowering keeps the request time, so the wait never gets longer.
+    /// Raising restarts the cooldown for the whole request: a request has a
+    /// single timestamp, and keeping the old one would let the added tokens
+    /// skip part of their cooldown.
+    pub fn amend_unstake_request(
+        &mut self,
+        new_amount: u64,
+        current_time: i64,
+    ) -> Result<(), StakingError> {
+        if !self.has_pending_unstake_request() {
+            return Err(StakingError::NoPendingUnstakeRequest);
+        }
+        if new_amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+        if new_amount > self.amount {
+            return Err(StakingError::InsufficientStakeBalance);
+        }
+        if new_amount > self.unstake_request_amount {
+            self.unstake_request_time = current_time;
+        }
+        self.unstake_request_amount = new_amount;
+        Ok(())
+    }
+
+    /// Amount of the last CompleteUnstake if it can still be reversed at
+    /// `current_time` under the pool's current reversal window
+    pub fn reversible_unstake_amount(&self, pool: &StakingPool, current_time: i64) -> Option<u64> {
+        if pool.unstake_reversal_window_seconds == 0
+            || self.last_unstake_completion_time == 0
+            || self.last_unstake_completion_amount == 0
+        {
+            return None;
+        }
+        let elapsed = current_time.saturating_sub(self.last_unstake_completion_time).max(0) as u64;
+        if elapsed > pool.unstake_reversal_window_seconds {
+            return None;
+        }
+        Some(self.last_unstake_completion_amount)
+    }
+
+    /// Current weighted stake, including the pool's loyalty bonus and lock
+    /// boost if enabled. Call `sync_to_pool` first.
+    pub fn weighted_stake(
+        &self,
+        pool: &StakingPool,
+        current_time: i64,
+    ) -> Result<u128, StakingError> {
+        let weighted = calculate_user_weighted_stake(
+            self.amount,
+            self.exp_start_factor,
+            current_time,
+            pool.base_time,
+            pool.tau_seconds,
+        )?;
+        // A claim made before the current position began doesn't count
+        let last_claim = self.last_claim_time.max(self.stake_time);
+        let weighted = apply_loyalty_bonus(
+            weighted,
+            self.amount,
+            pool.loyalty_bonus_bps,
+            current_time.saturating_sub(last_claim),
+            pool.tau_seconds,
+        )?;
+        apply_weight_boost(weighted, self.amount, self.active_lock_boost_bps(pool, current_time))
+    }
+
+    /// Lock boost (bps) the owner's lock commitment earns at `current_time`:
+    /// scaled by the committed length, and 0 once the commitment has run out
+    pub fn active_lock_boost_bps(&self, pool: &StakingPool, current_time: i64) -> u64 {
+        let commit = self.lock_commit_seconds.min(MAX_LOCK_DURATION_SECONDS);
+        let lock_end = self.effective_last_stake_time().saturating_add(commit as i64);
+        if current_time >= lock_end {
+            return 0;
+        }
+        lock_boost_bps(commit, MAX_LOCK_DURATION_SECONDS, pool.lock_boost_bps)
+    }
+
+    /// WAD-scaled rewards claimable with the given weighted stake:
+    /// `user_weighted * (acc_rps - snapshot) - claimed_rewards_wad`, where
+    /// snapshot = reward_debt / (amount * WAD). Only meaningful for amount > 0.
+    pub fn pending_rewards_wad(
+        &self,
+        pool: &StakingPool,
+        user_weighted: u128,
+    ) -> Result<u128, StakingError> {
+        let amount_wad = (self.amount as u128)
+            .checked_mul(WAD)
+            .ok_or(StakingError::MathOverflow)?;
+        let snapshot = wad_div(self.reward_debt, amount_wad)?;
+        let delta_rps = pool.acc_reward_per_weighted_share.saturating_sub(snapshot);
+        let full_entitlement = pool.unscale_rewards(wad_mul(user_weighted, delta_rps)?)?;
+        Ok(full_entitlement.saturating_sub(self.claimed_rewards_wad))
+    }
+
+    /// Record a claim (or a rewards-paying unstake) at `current_time`, which
+    /// also restarts the loyalty bonus ramp
+    pub fn record_claim(&mut self, current_time: i64) {
+        self.last_claim_time = current_time;
+    }
+
+    /// Bring reward_debt down to the pool's current accumulator precision.
+    /// A residual stake (amount 0) holds real unpaid rewards in reward_debt,
+    /// which are not scaled. Returns true if the snapshot changed.
+    pub fn sync_acc_scale(&mut self, pool: &StakingPool) -> bool {
+        if self.acc_scale_shift >= pool.acc_scale_shift {
+            return false;
+        }
+        if self.amount > 0 {
+            self.reward_debt >>= pool.acc_scale_shift - self.acc_scale_shift;
+        }
+        self.acc_scale_shift = pool.acc_scale_shift;
+        true
+    }
+
+    /// Lazily adjust exp_start_factor when pool has been rebased (and
+    /// reward_debt when the accumulator was rescaled).
+    /// Must be called before any calculation that uses exp_start_factor or reward_debt.
+    /// Returns true if an adjustment was made.
+    pub fn sync_to_pool(&mut self, pool: &StakingPool) -> Result<bool, StakingError> {
+        let rescaled = self.sync_acc_scale(pool);
+        if self.base_time_snapshot == pool.base_time {
+            return Ok(rescaled);
+        }
+
+        if self.base_time_snapshot == 0 {
+            // Legacy account (created before rebase-aware upgrade)
+            if pool.initial_base_time == 0 {
+                // No rebase has occurred since upgrade — exp_start_factor is still
+                // relative to the current pool.base_time, so no adjustment needed.
+                self.base_time_snapshot = pool.base_time;
+                return Ok(true);
+            }
+            // A rebase has occurred — adjust from the original base_time
+            if pool.base_time > pool.initial_base_time {
+                let adjustment = pool.decay_between(pool.initial_base_time, pool.base_time)?;
+                self.exp_start_factor = wad_mul(self.exp_start_factor, adjustment)?;
+            }
+            self.base_time_snapshot = pool.base_time;
+            return Ok(true);
+        }
+
+        // Standard case: adjust from the snapshot's base_time to the current
+        // one (at the old tau for any span before an UpdateTau)
+        if pool.base_time > self.base_time_snapshot {
+            let adjustment = pool.decay_between(self.base_time_snapshot, pool.base_time)?;
+            self.exp_start_factor = wad_mul(self.exp_start_factor, adjustment)?;
+        }
+        self.base_time_snapshot = pool.base_time;
+        Ok(true)
+    }
+}
+
+impl BorshDeserialize for UserStake {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let discriminator = <[u8; 8]>::deserialize_reader(reader)?;
+        let owner = Pubkey::deserialize_reader(reader)?;
+        let pool = Pubkey::deserialize_reader(reader)?;
+        let amount = u64::deserialize_reader(reader)?;
+        let stake_time = i64::deserialize_reader(reader)?;
+        let exp_start_factor = u128::deserialize_reader(reader)?;
+        let reward_debt = u128::deserialize_reader(reader)?;
+        let bump = u8::deserialize_reader(reader)?;
+        let unstake_request_amount = u64::deserialize_reader(reader)?;
+        let unstake_request_time = i64::deserialize_reader(reader)?;
+        let last_stake_time = i64::deserialize_reader(reader)?;
+        let base_time_snapshot = i64::deserialize_reader(reader)?;
+
+        // New fields — may not be present in legacy accounts
+        let total_rewards_claimed = u64::deserialize_reader(reader).unwrap_or(0);
+        let claimed_rewards_wad = u128::deserialize_reader(reader).unwrap_or(0);
+        let claimed_balance = u64::deserialize_reader(reader).unwrap_or(0);
+        let grandfathered = bool::deserialize_reader(reader).unwrap_or(false);
+        let last_claim_time = i64::deserialize_reader(reader).unwrap_or(0);
+        let terms_stamped = bool::deserialize_reader(reader).unwrap_or(false);
+        let lock_duration_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let unstake_cooldown_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let acc_scale_shift = u8::deserialize_reader(reader).unwrap_or(0);
+        let claim_for_enabled = bool::deserialize_reader(reader).unwrap_or(false);
+        let last_unstake_completion_time = i64::deserialize_reader(reader).unwrap_or(0);
+        let last_unstake_completion_amount = u64::deserialize_reader(reader).unwrap_or(0);
+        let lock_commit_seconds = u64::deserialize_reader(reader).unwrap_or(0);
+        let referrer = Pubkey::deserialize_reader(reader).unwrap_or_default();
+        let position_index = u16::deserialize_reader(reader).unwrap_or(0);
+        let top_up_lock_amounts =
+            <[u64; TOP_UP_LOCK_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let top_up_lock_times =
+            <[i64; TOP_UP_LOCK_SLOTS]>::deserialize_reader(reader).unwrap_or_default();
+        let max_position_index = u16::deserialize_reader(reader).unwrap_or(0);
+        let open_positions = u16::deserialize_reader(reader).unwrap_or(0);
+
+        Ok(Self {
+            discriminator,
+            owner,
+            pool,
+            amount,
+            stake_time,
+            exp_start_factor,
+            reward_debt,
+            bump,
+            unstake_request_amount,
+            unstake_request_time,
+            last_stake_time,
+            base_time_snapshot,
+            total_rewards_claimed,
+            claimed_rewards_wad,
+            claimed_balance,
+            grandfathered,
+            last_claim_time,
+            terms_stamped,
+            lock_duration_seconds,
+            unstake_cooldown_seconds,
+            acc_scale_shift,
+            claim_for_enabled,
+            last_unstake_completion_time,
+            last_unstake_completion_amount,
+            lock_commit_seconds,
+            referrer,
+            position_index,
+            top_up_lock_amounts,
+            top_up_lock_times,
+            max_position_index,
+            open_positions,
+        })
+    }
+}
+
+impl UserStake {
+    /// Realloc account to current LEN if it's a legacy (smaller) account.
+    /// Transfers additional rent from payer to the account via system program CPI.
+    /// No-op if account is already at or above current LEN.
+    ///
+    /// v4-sized accounts are left alone when no system program is supplied:
+    /// `save` accepts them as long as the appended fields stay at their defaults.
+    pub fn maybe_realloc<'a>(
+        account: &AccountInfo<'a>,
+        payer: &AccountInfo<'a>,
+        system_program: Option<&AccountInfo<'a>>,
+    ) -> Result<(), ProgramError> {
+        if system_program.is_none() && account.data_len() >= Self::V4_LEN {
+            return Ok(());
+        }
+        realloc_with_rent(account, payer, system_program, Self::LEN)
+    }
+
+    /// Write the stake back to its account (legacy-size aware, see `pack_into_slice`)
+    pub fn save(&self, user_stake_info: &AccountInfo) -> ProgramResult {
+        let mut stake_data = user_stake_info.try_borrow_mut_data()?;
+        pack_into_slice(self, &mut stake_data).map_err(|e| {
+            msg!("Stake account is too small; pass the system program to reallocate it");
+            e.into()
+        })
+    }
+}
+
+/// Grow `account` to `new_len`, funding the extra rent from `payer`.
+/// No-op if the account is already at least `new_len` bytes.
+fn realloc_with_rent<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: Option<&AccountInfo<'a>>,
+    new_len: usize,
+) -> Result<(), ProgramError> {
+    if account.data_len() >= new_len {
+        return Ok(());
+    }
+
+    let rent = solana_program::rent::Rent::get()?;
+    let new_rent = rent.minimum_balance(new_len);
+    let old_rent = rent.minimum_balance(account.data_len());
+    let rent_delta = new_rent.saturating_sub(old_rent);
+
+    if rent_delta > 0 {
+        let sys_prog = system_program
+            .ok_or(StakingError::MissingSystemProgram)?;
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(
+                payer.key,
+                account.key,
+                rent_delta,
+            ),
+            &[payer.clone(), account.clone(), sys_prog.clone()],
+        )?;
+    }
+
+    account.realloc(new_len, false)?;
+
+    Ok(())
+}
+
+/// Pool metadata account for explorer display
+/// PDA: ["metadata", pool]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct PoolMetadata {
+    /// Discriminator for account type identification
+    pub discriminator: [u8; 8],
+
+    /// Back-reference to staking pool
+    pub pool: Pubkey,
+
+    /// Actual byte length of name
+    pub name_len: u8,
+
+    /// UTF-8 name, zero-padded
+    pub name: [u8; 64],
+
+    /// Number of active tags (max 8)
+    pub num_tags: u8,
+
+    /// Byte length of each tag
+    pub tag_lengths: [u8; 8],
+
+    /// UTF-8 tags, zero-padded
+    pub tags: [[u8; 32]; 8],
+
+    /// Actual byte length of url
+    pub url_len: u8,
+
+    /// UTF-8 URL, zero-padded
+    pub url: [u8; 128],
+
+    /// Active staker count
+    pub member_count: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl PoolMetadata {
+    /// Size of the account in bytes
+    pub const LEN: usize = 8 +  // discriminator
+        32 + // pool
+        1 +  // name_len
+        64 + // name
+        1 +  // num_tags
+        8 +  // tag_lengths
+        256 + // tags (8 * 32)
+        1 +  // url_len
+        128 + // url
+        8 +  // member_count
+        1;   // bump
+
+    /// Derive metadata PDA
+    pub fn derive_pda(pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[METADATA_SEED, pool.as_ref()], program_id)
+    }
+
+    /// Check if metadata is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.discriminator == METADATA_DISCRIMINATOR
+    }
+}
+
+/// Depositor allowlist entry: while it exists, `depositor` may deposit
+/// rewards into a pool that requires allowlisted depositors
+/// PDA: ["depositor", pool, depositor]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct DepositorAllowlistEntry {
+    /// Discriminator for account type identification
+    pub discriminator: [u8; 8],
+
+    /// Back-reference to staking pool
+    pub pool: Pubkey,
+
+    /// Allowlisted depositor
+    pub depositor: Pubkey,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl DepositorAllowlistEntry {
+    /// Size of the account in bytes
+    pub const LEN: usize = 8 +  // discriminator
+        32 + // pool
+        32 + // depositor
+        1;   // bump
+
+    /// Create an entry for `depositor`
+    pub fn new(pool: Pubkey, depositor: Pubkey, bump: u8) -> Self {
+        Self {
+            discriminator: DEPOSITOR_ALLOWLIST_DISCRIMINATOR,
+            pool,
+            depositor,
+            bump,
+        }
+    }
+
+    /// Derive allowlist entry PDA
+    pub fn derive_pda(pool: &Pubkey, depositor: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[DEPOSITOR_SEED, pool.as_ref(), depositor.as_ref()],
+            program_id,
+        )
+    }
+
+    /// Check if entry is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.discriminator == DEPOSITOR_ALLOWLIST_DISCRIMINATOR
+    }
+}
+
+/// One reward distribution (DepositRewards or a rewards sync)
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Unix time of the distribution
+    pub timestamp: i64,
+
+    /// Lamports distributed
+    pub amount: u64,
+
+    /// Reward per share added to the accumulator (WAD-scaled, before
+    /// acc_scale_shift)
+    pub reward_per_share: u128,
+
+    /// Pool total_staked at the time
+    pub total_staked: u128,
+}
+
+/// Append-only page of the pool's distribution audit log
+/// PDA: ["audit_log", pool, page (u32 LE)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct AuditLogPage {
+    /// Discriminator for account type identification
+    pub discriminator: [u8; 8],
+
+    /// Back-reference to staking pool
+    pub pool: Pubkey,
+
+    /// Page index (0-based)
+    pub page: u32,
+
+    /// Number of records written
+    pub count: u16,
+
+    /// PDA bump seed
+    pub bump: u8,
+
+    /// Records in distribution order; only the first `count` are valid
+    pub records: [AuditRecord; AUDIT_LOG_PAGE_CAPACITY],
+}
+
+impl AuditLogPage {
+    /// Size of the account in bytes
+    pub const LEN: usize = 8 +  // discriminator
+        32 + // pool
+        4 +  // page
+        2 +  // count
+        1 +  // bump
+        48 * AUDIT_LOG_PAGE_CAPACITY; // records
+
+    /// Create an empty page
+    pub fn new(pool: Pubkey, page: u32, bump: u8) -> Self {
+        Self {
+            discriminator: AUDIT_LOG_DISCRIMINATOR,
+            pool,
+            page,
+            count: 0,
+            bump,
+            records: [AuditRecord::default(); AUDIT_LOG_PAGE_CAPACITY],
+        }
+    }
+
+    /// Derive audit log page PDA
+    pub fn derive_pda(pool: &Pubkey, page: u32, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[AUDIT_LOG_SEED, pool.as_ref(), &page.to_le_bytes()],
+            program_id,
+        )
+    }
+
+    /// Check if page is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.discriminator == AUDIT_LOG_DISCRIMINATOR
+    }
+
+    /// Check if every record slot is used
+    pub fn is_full(&self) -> bool {
+        self.count as usize >= AUDIT_LOG_PAGE_CAPACITY
+    }
+
+    /// Append a record; false if the page is full
+    pub fn push(&mut self, record: AuditRecord) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.records[self.count as usize] = record;
+        self.count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_size() {
+        // Verify the calculated size matches actual serialized size
+        let pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        assert_eq!(StakingPool::LEGACY_LEN, 289);
+    }
+
+    #[test]
+    fn test_pda_helpers_match_seeds() {
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let (pool, bump) = derive_pool_pda(&mint, &program_id);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"pool", mint.as_ref(), &[bump]], &program_id),
+            Ok(pool)
+        );
+        assert_eq!(StakingPool::derive_pda(&mint, &program_id), (pool, bump));
+
+        let (vault, bump) = derive_vault_pda(&pool, &program_id);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"token_vault", pool.as_ref(), &[bump]], &program_id),
+            Ok(vault)
+        );
+
+        let (stake, bump) = derive_user_stake_pda(&owner, &pool, &program_id);
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[b"stake", pool.as_ref(), owner.as_ref(), &[bump]],
+                &program_id
+            ),
+            Ok(stake)
+        );
+        assert_eq!(UserStake::derive_pda(&pool, &owner, &program_id), (stake, bump));
+    }
+
+    #[test]
+    fn test_pool_legacy_deserialize() {
+        let pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let full = borsh::to_vec(&pool).unwrap();
+
+        // v4 pools end at total_residual_unpaid
+        let legacy = &full[..StakingPool::LEGACY_LEN];
+        let deserialized = StakingPool::try_from_slice(legacy).unwrap();
+        assert_eq!(deserialized.tau_seconds, 2592000);
+        assert_eq!(deserialized.bump, 255);
+        assert_eq!(deserialized.total_claimed_balance, 0);
+        assert_eq!(deserialized.max_deferred_lamports, 0);
+        assert_eq!(deserialized.loyalty_bonus_bps, 0);
+        assert!(!deserialized.exclude_depositor);
+        assert_eq!(deserialized.recent_deposit_nonces, [0; DEPOSIT_NONCE_SLOTS]);
+        assert!(!deserialized.per_stake_terms);
+        assert_eq!(deserialized.accrued_protocol_fees, 0);
+        assert_eq!(deserialized.reserved_lamports(), 0);
+        assert!(!deserialized.quarantined);
+        assert!(!deserialized.paused);
+        assert_eq!(deserialized.acc_scale_shift, 0);
+        assert_eq!(deserialized.fee_split_total_bps(), 0);
+        assert_eq!(deserialized.cached_mint_decimals(), None);
+    }
+
+    #[test]
+    fn test_pool_mint_decimals_roundtrip() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        // A 0-decimal mint stays distinguishable from "not cached"
+        assert_eq!(pool.cached_mint_decimals(), None);
+        for decimals in [0, 9] {
+            pool.set_mint_decimals(decimals);
+            let serialized = borsh::to_vec(&pool).unwrap();
+            let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+            assert_eq!(deserialized.cached_mint_decimals(), Some(decimals));
+        }
+    }
+
+    #[test]
+    fn test_pool_unstake_destination_policy() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        assert_eq!(pool.unstake_destination_policy, UnstakeDestinationPolicy::AnyOwnerAccount);
+
+        pool.unstake_destination_policy = UnstakeDestinationPolicy::OwnerAtaOnly;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        assert_eq!(serialized[483], 1);
+        let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(deserialized.unstake_destination_policy, UnstakeDestinationPolicy::OwnerAtaOnly);
+
+        // Pools from before the policy existed allow any owner account
+        let legacy = StakingPool::try_from_slice(&serialized[..483]).unwrap();
+        assert_eq!(legacy.unstake_destination_policy, UnstakeDestinationPolicy::AnyOwnerAccount);
+    }
+
+    #[test]
+    fn test_pool_min_distributable_per_event() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        // Default: every amount is distributed
+        assert_eq!(pool.min_distributable_per_event, 0);
+        assert!(!pool.below_min_distributable(0));
+        assert!(!pool.below_min_distributable(1));
+
+        pool.min_distributable_per_event = 1_000_000;
+        assert!(pool.below_min_distributable(999_999));
+        assert!(!pool.below_min_distributable(1_000_000));
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(deserialized.min_distributable_per_event, 1_000_000);
+
+        // Pools from before the minimum existed distribute everything
+        let legacy = StakingPool::try_from_slice(&serialized[..484]).unwrap();
+        assert_eq!(legacy.min_distributable_per_event, 0);
+    }
+
+    #[test]
+    fn test_pool_stake_cap() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        // Default: unlimited
+        pool.total_staked = u64::MAX as u128;
+        assert!(!pool.exceeds_stake_cap(u64::MAX));
+
+        pool.total_staked = 900;
+        pool.max_total_staked = 1_000;
+        assert!(!pool.exceeds_stake_cap(100));
+        assert!(pool.exceeds_stake_cap(101));
+
+        // A cap lowered below total_staked blocks any new stake
+        pool.max_total_staked = 500;
+        assert!(pool.exceeds_stake_cap(1));
+
+        // No overflow near u64::MAX
+        pool.total_staked = u64::MAX as u128;
+        pool.max_total_staked = u64::MAX;
+        assert!(!pool.exceeds_stake_cap(0));
+        assert!(pool.exceeds_stake_cap(u64::MAX));
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(deserialized.max_total_staked, u64::MAX);
+
+        // Pools from before the cap existed (754 bytes) are unlimited
+        let legacy = StakingPool::try_from_slice(&serialized[..754]).unwrap();
+        assert_eq!(legacy.max_total_staked, 0);
+    }
+
+    #[test]
+    fn test_pool_settings_locked() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        assert!(!pool.settings_locked);
+
+        pool.settings_locked = true;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        assert_eq!(serialized[492], 1);
+        let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+        assert!(deserialized.settings_locked);
+
+        // Pools from before the lock existed are unlocked
+        let legacy = StakingPool::try_from_slice(&serialized[..492]).unwrap();
+        assert!(!legacy.settings_locked);
+    }
+
+    #[test]
+    fn test_reward_rate() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            1_000,
+            255,
+        );
+        // InitializePool starts the window at creation
+        pool.reward_rate_window_start = 1_000;
+        assert_eq!(pool.reward_rate(1_100), 0);
+
+        // 10 lamports/s, deposited in steps
+        for t in (1_100..=2_000).step_by(100) {
+            pool.record_reward_rate(1_000, t);
+        }
+        assert_eq!(pool.reward_rate(2_000), 10);
+
+        // Past two windows the sum is scaled down to one window, same rate
+        let t = 1_000 + 2 * REWARD_RATE_WINDOW_SECONDS + 1_000;
+        pool.record_reward_rate(0, t);
+        assert_eq!(pool.reward_rate_window_start, t - REWARD_RATE_WINDOW_SECONDS);
+        assert_eq!(pool.reward_rate(t), 10_000 / (2 * REWARD_RATE_WINDOW_SECONDS as u64 + 1_000));
+
+        // A rate change shows up within the window
+        let mut rate_before = pool.reward_rate(t);
+        for step in 1..=36 {
+            pool.record_reward_rate(10_000, t + step * 100);
+            let rate = pool.reward_rate(t + step * 100);
+            assert!(rate >= rate_before);
+            rate_before = rate;
+        }
+        assert!(pool.reward_rate(t + 3_600) >= 40);
+
+        // Legacy pools start their window on the first distribution
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let mut legacy = StakingPool::try_from_slice(&serialized[..493]).unwrap();
+        assert_eq!(legacy.reward_rate_window_start, 0);
+        assert_eq!(legacy.reward_rate(5_000), 0);
+        legacy.record_reward_rate(500, 5_000);
+        assert_eq!(legacy.reward_rate_window_start, 5_000);
+        assert_eq!(legacy.reward_rate(5_050), 10);
+    }
+
+    #[test]
+    fn test_display_unit() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        assert_eq!(pool.display_unit(), "");
+
+        pool.display_unit_label[..4].copy_from_slice(b"USDC");
+        pool.display_unit_decimals = 6;
+        pool.display_total_distributed = 12_500_000;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let deserialized = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(deserialized.display_unit(), "USDC");
+        assert_eq!(deserialized.display_unit_decimals, 6);
+        assert_eq!(deserialized.display_total_distributed, 12_500_000);
+
+        // A full-length label has no terminator
+        pool.display_unit_label = *b"USDCUSDT";
+        assert_eq!(pool.display_unit(), "USDCUSDT");
+
+        // Pools from before the display unit existed have none
+        let legacy = StakingPool::try_from_slice(&serialized[..509]).unwrap();
+        assert_eq!(legacy.display_unit(), "");
+        assert_eq!(legacy.display_total_distributed, 0);
+    }
+
+    #[test]
+    fn test_pool_extend_preserves_fields() {
+        let mut pool = StakingPool::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            2592000,
+            1_700_000_000,
+            254,
+        );
+        pool.total_staked = 5_000_000_000;
+        pool.set_sum_stake_exp(U256::from_u128(123_456_789 * WAD));
+        pool.acc_reward_per_weighted_share = 42 * WAD;
+        pool.last_synced_lamports = 9_000_000;
+        pool.min_stake_amount = 1_000;
+        pool.unstake_cooldown_seconds = 3_600;
+        pool.total_reward_debt = 7 * WAD;
+        pool.total_residual_unpaid = 11;
+        let full = borsh::to_vec(&pool).unwrap();
+
+        // A v4 pool grown by realloc: the original bytes plus zeroes
+        let mut extended = full[..StakingPool::LEGACY_LEN].to_vec();
+        extended.resize(StakingPool::LEN, 0);
+        let read = StakingPool::try_from_slice(&extended).unwrap();
+        assert_eq!(borsh::to_vec(&read).unwrap(), extended);
+        assert_eq!(extended, full);
+        assert_eq!(read.mint, pool.mint);
+        assert_eq!(read.authority, pool.authority);
+        assert_eq!(read.total_staked, pool.total_staked);
+        assert_eq!(read.get_sum_stake_exp(), pool.get_sum_stake_exp());
+        assert_eq!(read.acc_reward_per_weighted_share, pool.acc_reward_per_weighted_share);
+        assert_eq!(read.total_reward_debt, pool.total_reward_debt);
+        assert_eq!(read.bump, 254);
+    }
+
+    #[test]
+    fn test_fee_split_amounts() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        assert_eq!(pool.fee_split_amounts(1_000_000), [0; FEE_SPLIT_SLOTS]);
+
+        pool.fee_split_recipients = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        pool.fee_split_bps = [500, 300, 200];
+        assert_eq!(pool.fee_split_total_bps(), 1_000);
+        assert_eq!(pool.fee_split_amounts(1_000_000), [50_000, 30_000, 20_000]);
+        // Each portion rounds down on its own
+        assert_eq!(pool.fee_split_amounts(999), [49, 29, 19]);
+    }
+
+    #[test]
+    fn test_delegate_permissions() {
+        let authority = Pubkey::new_unique();
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            authority,
+            2592000,
+            0,
+            255,
+        );
+        let syncer = Pubkey::new_unique();
+        assert!(pool.is_authorized(&authority, DELEGATE_PERM_SETTINGS));
+        assert!(!pool.is_authorized(&syncer, DELEGATE_PERM_SYNC));
+
+        pool.set_delegate(syncer, DELEGATE_PERM_SYNC).unwrap();
+        assert!(pool.is_authorized(&syncer, DELEGATE_PERM_SYNC));
+        assert!(!pool.is_authorized(&syncer, DELEGATE_PERM_SETTINGS));
+        assert_eq!(pool.permissions_of(&Pubkey::default()), 0);
+
+        // Re-granting replaces the bitmask in place
+        pool.set_delegate(syncer, DELEGATE_PERM_SETTINGS).unwrap();
+        assert!(!pool.is_authorized(&syncer, DELEGATE_PERM_SYNC));
+        assert_eq!(pool.delegates.iter().filter(|d| **d == syncer).count(), 1);
+
+        assert!(matches!(
+            pool.set_delegate(Pubkey::default(), DELEGATE_PERM_SYNC),
+            Err(StakingError::InvalidDelegate)
+        ));
+        assert!(matches!(
+            pool.set_delegate(syncer, 1 << 7),
+            Err(StakingError::InvalidDelegate)
+        ));
+
+        for _ in 1..DELEGATE_SLOTS {
+            pool.set_delegate(Pubkey::new_unique(), DELEGATE_PERM_DISPLAY).unwrap();
+        }
+        assert!(matches!(
+            pool.set_delegate(Pubkey::new_unique(), DELEGATE_PERM_DISPLAY),
+            Err(StakingError::TooManyDelegates)
+        ));
+
+        // Removing frees the slot
+        pool.set_delegate(syncer, 0).unwrap();
+        assert_eq!(pool.permissions_of(&syncer), 0);
+        pool.set_delegate(Pubkey::new_unique(), DELEGATE_PERM_ALL).unwrap();
+
+        pool.clear_delegates();
+        assert!(pool.delegates.iter().all(|d| *d == Pubkey::default()));
+    }
+
+    #[test]
+    fn test_loyalty_ramp_starts_at_position() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            255,
+        );
+        pool.loyalty_bonus_bps = 2_000;
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000_000,
+            10_000,
+            WAD,
+            255,
+            10_000,
+        );
+        let never_claimed = stake.weighted_stake(&pool, 10_500).unwrap();
+
+        // A claim recorded before the position began (e.g. on an earlier
+        // position in the same account) doesn't lengthen the ramp
+        stake.record_claim(5_000);
+        assert_eq!(stake.last_claim_time, 5_000);
+        assert_eq!(stake.weighted_stake(&pool, 10_500).unwrap(), never_claimed);
+
+        // A later claim restarts it
+        stake.record_claim(10_400);
+        assert!(stake.weighted_stake(&pool, 10_500).unwrap() < never_claimed);
+    }
+
+    #[test]
+    fn test_lock_boost_until_lock_ends() {
+        let tau = 2_592_000u64;
+        let year = MAX_LOCK_DURATION_SECONDS;
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            tau,
+            0,
+            255,
+        );
+        pool.lock_boost_bps = 10_000;
+
+        // Equal stakes at the same time, one committed to a full-year lock
+        let base = UserStake::new(Pubkey::new_unique(), Pubkey::default(), 1_000_000, 0, WAD, 255, 0);
+        let mut locked = base.clone();
+        locked.lock_commit_seconds = year;
+        pool.total_staked = 2_000_000;
+        let deposit = 1_000_000_000u64;
+        pool.add_reward_per_share(wad_div(deposit as u128 * WAD, 2_000_000 * WAD).unwrap())
+            .unwrap();
+
+        let claimable = |stake: &UserStake, now: i64| {
+            let weighted = stake.weighted_stake(&pool, now).unwrap();
+            (stake.pending_rewards_wad(&pool, weighted).unwrap() / WAD) as u64
+        };
+
+        // While both are maturing the locked staker earns double
+        let early = tau as i64 / 10;
+        assert_eq!(locked.active_lock_boost_bps(&pool, early), 10_000);
+        let base_claim = claimable(&base, early);
+        assert!(base_claim > 0);
+        assert!(claimable(&locked, early).abs_diff(2 * base_claim) <= 1);
+
+        // Never more than its share of the deposit: the boost only speeds
+        // up maturation
+        let mature = 2 * tau as i64;
+        assert!(claimable(&locked, mature) <= deposit / 2);
+        assert!(claimable(&locked, mature) >= claimable(&base, mature));
+
+        // A shorter commitment earns a proportional boost, and none once the
+        // lock has run out
+        locked.lock_commit_seconds = year / 2;
+        assert_eq!(locked.active_lock_boost_bps(&pool, early), 5_000);
+        let lock_end = (year / 2) as i64;
+        assert_eq!(locked.active_lock_boost_bps(&pool, lock_end - 1), 5_000);
+        assert_eq!(locked.active_lock_boost_bps(&pool, lock_end), 0);
+        assert_eq!(
+            locked.weighted_stake(&pool, lock_end).unwrap(),
+            base.weighted_stake(&pool, lock_end).unwrap()
+        );
+
+        // Off by default
+        pool.lock_boost_bps = 0;
+        assert_eq!(locked.active_lock_boost_bps(&pool, early), 0);
+
+        // Pools from before the boost (851 bytes) have it off
+        pool.lock_boost_bps = 10_000;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        assert_eq!(StakingPool::try_from_slice(&serialized).unwrap().lock_boost_bps, 10_000);
+        let legacy = StakingPool::try_from_slice(&serialized[..851]).unwrap();
+        assert_eq!(legacy.lock_boost_bps, 0);
+    }
+
+    #[test]
+    fn test_change_tau_keeps_weight() {
+        let amount = 1_000_000u64;
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            255,
+        );
+        // Staked at base_time: exp_start_factor = WAD
+        let mut lazy = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            amount,
+            10_000,
+            WAD,
+            255,
+            10_000,
+        );
+        let mut eager = lazy.clone();
+        let as_f64 = |w: u128| w as f64 / (amount as f64 * WAD as f64);
+
+        // One tau in: 1 - e^-1 mature
+        let before = eager.weighted_stake(&pool, 11_000).unwrap();
+        assert_eq!(pool.change_tau(500, 11_000).unwrap(), 1_000);
+        assert_eq!(pool.base_time, 11_000);
+        assert_eq!(pool.tau_seconds, 500);
+        assert_eq!(pool.tau_change_times[0], 11_000);
+        assert_eq!(pool.tau_change_previous[0], 1_000);
+
+        // No jump at the change
+        eager.sync_to_pool(&pool).unwrap();
+        let after = eager.weighted_stake(&pool, 11_000).unwrap();
+        assert!((as_f64(before) - as_f64(after)).abs() < 1e-12);
+
+        // One new tau later the remaining gap shrank by e^-1 again. A stake
+        // brought forward across the change and a later rebase agrees.
+        pool.rebase(11_500).unwrap();
+        eager.sync_to_pool(&pool).unwrap();
+        lazy.sync_to_pool(&pool).unwrap();
+        let expected = 1.0 - (-2.0f64).exp();
+        for stake in [&eager, &lazy] {
+            let weight = as_f64(stake.weighted_stake(&pool, 11_500).unwrap());
+            assert!((weight - expected).abs() < 1e-7, "{} != {}", weight, expected);
+        }
+
+        // Each change takes a history slot
+        for (i, tau) in [600u64, 700, 800].iter().enumerate() {
+            pool.change_tau(*tau, 12_000 + i as i64).unwrap();
+            eager.sync_to_pool(&pool).unwrap();
+        }
+
+        // A full history merges its two oldest changes at the slower tau: a
+        // stake last synced before them ends up no more mature than exact
+        assert_eq!(pool.change_tau(900, 13_000).unwrap(), 800);
+        assert_eq!(pool.tau_seconds, 900);
+        assert_eq!(pool.tau_change_times, [12_000, 12_001, 12_002, 13_000]);
+        assert_eq!(pool.tau_change_previous, [1_000, 600, 700, 800]);
+        eager.sync_to_pool(&pool).unwrap();
+        lazy.sync_to_pool(&pool).unwrap();
+        let exact = eager.weighted_stake(&pool, 13_000).unwrap();
+        let merged = lazy.weighted_stake(&pool, 13_000).unwrap();
+        assert!(merged <= exact);
+        assert!(as_f64(exact) - as_f64(merged) < 0.1);
+
+        // Changes everything has fully matured past are dropped outright
+        let later = 13_000 + 100 * 900;
+        assert_eq!(pool.change_tau(1_000, later).unwrap(), 900);
+        assert_eq!(pool.tau_change_times, [later, 0, 0, 0]);
+        assert_eq!(pool.tau_change_previous, [900, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_reward_mint_mode() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        // Pools start (and legacy pools read back) in SOL mode
+        assert_eq!(pool.reward_mint(), None);
+        assert_eq!(pool.require_sol_rewards(&Pubkey::default()), Ok(()));
+        let legacy = borsh::to_vec(&pool).unwrap()[..StakingPool::LEGACY_LEN].to_vec();
+        assert_eq!(StakingPool::try_from_slice(&legacy).unwrap().reward_mint(), None);
+
+        let usdc = Pubkey::new_unique();
+        pool.reward_mint = usdc;
+        assert_eq!(pool.reward_mint(), Some(usdc));
+        assert_eq!(
+            pool.require_sol_rewards(&Pubkey::default()),
+            Err(StakingError::RewardModeMismatch)
+        );
+        let read = StakingPool::try_from_slice(&borsh::to_vec(&pool).unwrap()).unwrap();
+        assert_eq!(read.reward_mint(), Some(usdc));
+    }
+
+    #[test]
+    fn test_sol_reward_vault_is_pool() {
+        let pool_key = Pubkey::new_unique();
+        let mut pool = StakingPool::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            pool_key,
+            Pubkey::new_unique(),
+            2592000,
+            0,
+            255,
+        );
+        assert_eq!(pool.require_sol_rewards(&pool_key), Ok(()));
+
+        // A flipped reward_vault trips the guard, even read back from data
+        pool.reward_vault = Pubkey::new_unique();
+        assert_eq!(pool.require_sol_rewards(&pool_key), Err(StakingError::InvalidRewardVault));
+        let read = StakingPool::try_from_slice(&borsh::to_vec(&pool).unwrap()).unwrap();
+        assert_eq!(read.require_sol_rewards(&pool_key), Err(StakingError::InvalidRewardVault));
+
+        // The reward mode is reported first on SPL-token pools
+        pool.reward_mint = Pubkey::new_unique();
+        assert_eq!(pool.require_sol_rewards(&pool_key), Err(StakingError::RewardModeMismatch));
+    }
+
+    #[test]
+    fn test_syncable_rewards() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.last_synced_lamports = 1_000;
+
+        // No stakers: deferred
+        assert_eq!(pool.syncable_rewards(5_000), 0);
+
+        pool.total_staked = 1;
+        assert_eq!(pool.syncable_rewards(5_000), 4_000);
+        // Nothing new (or less than last synced after a payout)
+        assert_eq!(pool.syncable_rewards(1_000), 0);
+        assert_eq!(pool.syncable_rewards(500), 0);
+
+        // Below the per-event minimum it waits for more
+        pool.min_distributable_per_event = 5_000;
+        assert_eq!(pool.syncable_rewards(5_000), 0);
+        assert_eq!(pool.syncable_rewards(6_000), 5_000);
+    }
+
+    #[test]
+    fn test_deferred_rewards_reclaim() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let rent = 100u64;
+
+        // Earlier rewards were distributed and are still owed (synced)
+        pool.total_staked = 1_000;
+        let mut lamports = rent + 2_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), Some((2_000, 2 * WAD)));
+        assert_eq!(pool.deferred_rewards(lamports, rent, 0), 0);
+
+        // Everyone leaves; a deposit with nobody staked is deferred
+        pool.total_staked = 0;
+        lamports += 5_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), None);
+        assert_eq!(pool.deferred_rewards(lamports, rent, 0), 5_000);
+
+        // Unreleased streamed lamports aren't reclaimable yet
+        pool.reward_stream_lamports = 1_000;
+        pool.reward_stream_start = 0;
+        pool.reward_stream_end = 100;
+        assert_eq!(pool.deferred_rewards(lamports + 1_000, rent, 0), 5_000);
+        assert_eq!(pool.deferred_rewards(lamports + 1_000, rent, 100), 6_000);
+        pool.reward_stream_lamports = 0;
+
+        // Reclaim takes only the deferred lamports; the synced ones stay
+        lamports -= pool.deferred_rewards(lamports, rent, 0);
+        assert_eq!(pool.deferred_rewards(lamports, rent, 0), 0);
+        assert_eq!(pool.last_synced_lamports, 2_000);
+
+        // A later staker only sees what arrives after it stakes
+        pool.total_staked = 500;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 10).unwrap(), None);
+        lamports += 1_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 10).unwrap(), Some((1_000, 2 * WAD)));
+        assert_eq!(pool.acc_reward_per_weighted_share, 4 * WAD);
+        assert_eq!(pool.last_synced_lamports, 3_000);
+
+        // Nothing is reclaimable while tokens are staked
+        assert_eq!(pool.deferred_rewards(lamports + 7_000, rent, 10), 0);
+    }
+
+    #[test]
+    fn test_sync_accumulator_counts_each_lamport_once() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let rent = 100u64;
+        pool.total_staked = 1_000;
+
+        // 5_000 lamports sent straight to the pool, then synced
+        let mut lamports = rent + 5_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), Some((5_000, 5 * WAD)));
+        assert_eq!(pool.last_synced_lamports, 5_000);
+
+        // A deposit sweeps first (nothing new), then adds only its own amount
+        lamports += 2_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), Some((2_000, 2 * WAD)));
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), None);
+        let deposit = 3_000u64;
+        pool.add_reward_per_share(3 * WAD).unwrap();
+        pool.last_synced_lamports += deposit;
+        lamports += deposit;
+
+        // A later sync finds nothing left to count
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), None);
+        assert_eq!(pool.acc_reward_per_weighted_share, 10 * WAD);
+        assert_eq!(pool.last_synced_lamports, lamports - rent);
+
+        // Lamports left pending below the minimum are swept once they clear it
+        pool.min_distributable_per_event = 1_500;
+        lamports += 1_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), None);
+        lamports += 1_000;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), Some((2_000, 2 * WAD)));
+        assert_eq!(pool.acc_reward_per_weighted_share, 12 * WAD);
+    }
+
+    #[test]
+    fn test_reward_stream_releases_linearly() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let rent = 100u64;
+        pool.total_staked = 1_000;
+
+        // 10_000 lamports over 100s: nothing at the deposit, a tenth per 10s
+        let mut lamports = rent + 10_000;
+        pool.add_reward_stream(10_000, 100, 1_000).unwrap();
+        assert_eq!(pool.unreleased_stream_lamports(1_000), 10_000);
+        assert_eq!(pool.sync_accumulator(lamports, rent, 1_000).unwrap(), None);
+        assert_eq!(pool.sync_accumulator(lamports, rent, 1_010).unwrap(), Some((1_000, WAD)));
+        assert_eq!(pool.sync_accumulator(lamports, rent, 1_010).unwrap(), None);
+
+        // A second stream halfway through: 5_000 left over 50s plus 10_000
+        // over 200s release at 150/s together, so both are out after 100s
+        assert_eq!(pool.sync_accumulator(lamports, rent, 1_050).unwrap(), Some((4_000, 4 * WAD)));
+        pool.add_reward_stream(10_000, 200, 1_050).unwrap();
+        lamports += 10_000;
+        assert_eq!(pool.unreleased_stream_lamports(1_050), 15_000);
+        assert_eq!(pool.reward_stream_end, 1_150);
+        assert_eq!(pool.sync_accumulator(lamports, rent, 1_060).unwrap(), Some((1_500, 3 * WAD / 2)));
+        assert_eq!(pool.unreleased_stream_lamports(1_060), 13_500);
+
+        // Everything is out at the end, and never more than was deposited
+        assert_eq!(pool.sync_accumulator(lamports, rent, 5_000).unwrap(), Some((13_500, 27 * WAD / 2)));
+        assert_eq!(pool.last_synced_lamports, 20_000);
+        assert_eq!(pool.unreleased_stream_lamports(5_000), 0);
+
+        // Pools from before streams (859 bytes) have none running
+        pool.add_reward_stream(1, 1, 5_000).unwrap();
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let legacy = StakingPool::try_from_slice(&serialized[..859]).unwrap();
+        assert_eq!(legacy.unreleased_stream_lamports(5_000), 0);
+    }
+
+    #[test]
+    fn test_reward_streams_never_release_more_than_deposited() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.total_staked = 7;
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+
+        // Overlapping streams of odd sizes, synced at arbitrary times
+        let mut now = 0i64;
+        let mut deposited = 0u64;
+        for _ in 0..500 {
+            now += next(50) as i64;
+            if next(3) == 0 {
+                let amount = 1 + next(1_000_000);
+                pool.add_reward_stream(amount, 1 + next(1_000), now).unwrap();
+                deposited += amount;
+            }
+            pool.sync_accumulator(deposited, 0, now).unwrap();
+            assert!(pool.last_synced_lamports + pool.unreleased_stream_lamports(now) <= deposited);
+        }
+
+        // Once the last stream ends, all of it is out (up to rounding dust)
+        now = pool.reward_stream_end;
+        pool.sync_accumulator(deposited, 0, now).unwrap();
+        assert_eq!(pool.unreleased_stream_lamports(now), 0);
+        assert!(pool.last_synced_lamports <= deposited);
+        assert!(deposited - pool.last_synced_lamports < pool.total_staked as u64);
+    }
+
+    #[test]
+    fn test_early_exit_penalty() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        assert_eq!(pool.early_exit_penalty(1_000_000), 0);
+
+        pool.early_exit_penalty_bps = 500;
+        assert_eq!(pool.early_exit_penalty(1_000_000), 50_000);
+        // Rounded up: small exits still pay
+        assert_eq!(pool.early_exit_penalty(1), 1);
+        assert_eq!(pool.early_exit_penalty(21), 2);
+        assert_eq!(pool.early_exit_penalty(u64::MAX), u64::MAX / 20 + 1);
+
+        pool.early_exit_penalty_bps = BPS_DENOMINATOR;
+        assert_eq!(pool.early_exit_penalty(u64::MAX), u64::MAX);
+
+        pool.penalty_tokens = 50_000;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let restored = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(restored.early_exit_penalty_bps, BPS_DENOMINATOR);
+        assert_eq!(restored.penalty_tokens, 50_000);
+
+        // Pools from before instant unstake (771 bytes) have it disabled
+        let legacy = StakingPool::try_from_slice(&serialized[..771]).unwrap();
+        assert_eq!(legacy.early_exit_penalty_bps, 0);
+        assert_eq!(legacy.penalty_tokens, 0);
+    }
+
+    #[test]
+    fn test_pending_authority_roundtrip() {
+        let authority = Pubkey::new_unique();
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            authority,
+            2592000,
+            0,
+            255,
+        );
+        let proposed = Pubkey::new_unique();
+        pool.pending_authority = proposed;
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let restored = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(restored.pending_authority, proposed);
+        // The current authority stays in charge until the proposal is accepted
+        assert_eq!(restored.authority, authority);
+
+        // Pools from before two-step transfers (787 bytes) have none pending
+        let legacy = StakingPool::try_from_slice(&serialized[..787]).unwrap();
+        assert_eq!(legacy.pending_authority, Pubkey::default());
+    }
+
+    #[test]
+    fn test_pool_token_program() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let token_2022 = spl_token_2022::id();
+
+        // Pools from before it was recorded go by the mint's owner
+        assert!(pool.check_token_program(&SPL_TOKEN_PROGRAM_ID, &SPL_TOKEN_PROGRAM_ID).is_ok());
+        assert_eq!(
+            pool.check_token_program(&token_2022, &SPL_TOKEN_PROGRAM_ID),
+            Err(StakingError::InvalidTokenProgram)
+        );
+
+        // Once recorded, the other program is rejected
+        pool.token_program = SPL_TOKEN_PROGRAM_ID;
+        assert!(pool.check_token_program(&SPL_TOKEN_PROGRAM_ID, &SPL_TOKEN_PROGRAM_ID).is_ok());
+        assert_eq!(
+            pool.check_token_program(&token_2022, &token_2022),
+            Err(StakingError::InvalidTokenProgram)
+        );
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let restored = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(restored.token_program, SPL_TOKEN_PROGRAM_ID);
+        let legacy = StakingPool::try_from_slice(&serialized[..819]).unwrap();
+        assert_eq!(legacy.token_program, Pubkey::default());
+    }
+
+    #[test]
+    fn test_tau_weights_fresh_vs_matured_share() {
+        let tau = 1_000u64;
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            tau,
+            0,
+            255,
+        );
+
+        // Alice staked at base_time; Bob stakes the same amount 10 tau later
+        let bob_start = 10 * tau as i64;
+        let alice = UserStake::new(Pubkey::new_unique(), Pubkey::default(), 1_000, 0, WAD, 255, 0);
+        let bob_factor = crate::math::exp_time_ratio(bob_start, tau).unwrap();
+        let bob = UserStake::new(Pubkey::new_unique(), Pubkey::default(), 1_000, bob_start, bob_factor, 255, 0);
+        pool.total_staked = 2_000;
+
+        // One deposit of 2_000_000 lamports right after Bob joins
+        pool.add_reward_per_share(wad_div(2_000_000 * WAD, 2_000 * WAD).unwrap()).unwrap();
+
+        let claimable = |stake: &UserStake, t: i64| {
+            let weighted = stake.weighted_stake(&pool, t).unwrap();
+            (stake.pending_rewards_wad(&pool, weighted).unwrap() / WAD) as u64
+        };
+
+        // Claiming right away, the matured stake gets its full half while the
+        // fresh one gets almost nothing: the rest stays in the pool
+        let now = bob_start + 1;
+        assert!(claimable(&alice, now) >= 999_950);
+        assert!(claimable(&bob, now) <= 1_000);
+
+        // After one tau the fresh stake can claim 1 - 1/e of its half
+        let one_tau = bob_start + tau as i64;
+        let expected = 1_000_000 - 1_000_000 * crate::math::exp_neg_time_ratio(tau as i64, tau).unwrap() / WAD;
+        assert!(claimable(&bob, one_tau).abs_diff(expected as u64) <= 10);
+        assert!(claimable(&alice, one_tau) >= 999_950);
+
+        // The same deposit is never over-promised
+        assert!(claimable(&alice, one_tau) + claimable(&bob, one_tau) <= 2_000_000);
+    }
+
+    #[test]
+    fn test_flash_stake_around_deposit() {
+        // The shortest tau InitializePool allows, where a flash stake does best
+        let tau = crate::instructions::initialize::MIN_TAU_SECONDS;
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            tau,
+            0,
+            255,
+        );
+
+        // A long-term staker, then a whale with 1000x the stake right before
+        // a 1 SOL deposit
+        let t = 10 * tau as i64;
+        let holder = UserStake::new(Pubkey::new_unique(), Pubkey::default(), 1_000, 0, WAD, 255, 0);
+        let whale_factor = crate::math::exp_time_ratio(t, tau).unwrap();
+        let whale =
+            UserStake::new(Pubkey::new_unique(), Pubkey::default(), 1_000_000, t, whale_factor, 255, 0);
+        pool.total_staked = 1_001_000;
+        let deposit = 1_000_000_000u64;
+        pool.add_reward_per_share(wad_div(deposit as u128 * WAD, 1_001_000 * WAD).unwrap())
+            .unwrap();
+
+        let claimable = |stake: &UserStake, now: i64| {
+            let weighted = stake.weighted_stake(&pool, now).unwrap();
+            (stake.pending_rewards_wad(&pool, weighted).unwrap() / WAD) as u64
+        };
+
+        // Stake, deposit and claim in the same second: nothing captured
+        assert_eq!(claimable(&whale, t), 0);
+
+        // Claiming a few seconds later captures at most elapsed / tau of the
+        // whale's nominal share; the rest is forfeited on unstake and goes
+        // back to the pool
+        let whale_share = deposit as u128 * 1_000_000 / 1_001_000;
+        for elapsed in [1i64, 5, 10] {
+            let captured = claimable(&whale, t + elapsed) as u128;
+            assert!(captured <= whale_share * elapsed as u128 / tau as u128);
+        }
+
+        // The matured holder (10 tau old) still claims its share in full
+        assert!(claimable(&holder, t + 1) >= deposit / 1_001 * 9_999 / 10_000);
+    }
+
+    #[test]
+    fn test_sync_accumulator_keeps_dust_pending() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let rent = 100u64;
+        pool.total_staked = 100 * WAD;
+
+        // 1 lamport per 1e20 tokens rounds to nothing per share, however
+        // often it is synced: it stays pending instead of being counted
+        let mut lamports = rent;
+        for _ in 0..99 {
+            lamports += 1;
+            assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), None);
+        }
+        assert_eq!(pool.acc_reward_per_weighted_share, 0);
+        assert_eq!(pool.last_synced_lamports, 0);
+
+        // Once it adds up, only what the increment pays out is synced
+        lamports += 51;
+        assert_eq!(pool.sync_accumulator(lamports, rent, 0).unwrap(), Some((100, 1)));
+        assert_eq!(pool.last_synced_lamports, 100);
+        assert_eq!(pool.acc_reward_per_weighted_share, 1);
+        assert_eq!(pool.reward_per_share_for(50, pool.total_staked * WAD).unwrap(), (0, 0));
+
+        // Increments are truncated to the accumulator's precision
+        pool.acc_scale_shift = 4;
+        assert_eq!(
+            pool.reward_per_share_for(2_000, pool.total_staked * WAD).unwrap(),
+            (16, 1_600)
+        );
+
+        // Deposits below min_reward_deposit are rejected outright
+        pool.min_reward_deposit = 1_000;
+        assert_eq!(
+            pool.check_min_reward_deposit(999),
+            Err(StakingError::DepositBelowMinimum)
+        );
+        assert!(pool.check_min_reward_deposit(1_000).is_ok());
+
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        let restored = StakingPool::try_from_slice(&serialized).unwrap();
+        assert_eq!(restored.min_reward_deposit, 1_000);
+
+        // Pools from before the setting existed (762 bytes) accept any deposit
+        let legacy = StakingPool::try_from_slice(&serialized[..762]).unwrap();
+        assert_eq!(legacy.min_reward_deposit, 0);
+    }
+
+    #[test]
+    fn test_reward_rounding_error_is_bounded() {
+        // 3 tokens of 9 decimals, and a 1e9-token supply where one
+        // accumulator unit is worth about a lamport; neither divides evenly
+        for (total_staked, max_stranded) in [(3_000_000_007u128, 1), (1_000_000_000_000_000_007, 10_000)] {
+            let mut pool = StakingPool::new(
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+                2592000,
+                0,
+                255,
+            );
+            pool.total_staked = total_staked;
+            let shares_wad = total_staked * WAD;
+            let owed = |pool: &StakingPool| {
+                (wad_mul(pool.acc_reward_per_weighted_share, shares_wad).unwrap() / WAD) as u64
+            };
+
+            // 10_000 small, uneven syncs
+            let mut received = 0u64;
+            for i in 0..10_000u64 {
+                received += 1 + i * 7919 % 997;
+                pool.sync_accumulator(received, 0, 0).unwrap();
+                // Never promised more than was received, or synced past it
+                assert!(owed(&pool) <= pool.last_synced_lamports);
+                assert!(pool.last_synced_lamports <= received);
+            }
+
+            // Flooring loses less than a lamport per sync, and only when one
+            // accumulator unit is worth about a lamport
+            assert!(received - owed(&pool) <= max_stranded);
+        }
+
+        // Rounding the increment to nearest there would promise 501 lamports
+        // out of a 500-lamport deposit
+        let shares_wad = 1_000_000_000_000_000_007u128 * WAD;
+        let amount_wad = 500 * WAD;
+        let floored = wad_div(amount_wad, shares_wad).unwrap();
+        // 500 / (1 + 7e-18) = 499.99999999999999999650...
+        assert_eq!(floored, 499);
+        let nearest = floored + 1;
+        assert_eq!(wad_mul(floored, shares_wad).unwrap().div_ceil(WAD), 500);
+        assert_eq!(wad_mul(nearest, shares_wad).unwrap().div_ceil(WAD), 501);
+    }
+
+    #[test]
+    fn test_deposit_nonce_ring() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let depositor = Pubkey::new_unique();
+        let first = StakingPool::deposit_nonce_key(&depositor, 1);
+        assert_ne!(first, StakingPool::deposit_nonce_key(&Pubkey::new_unique(), 1));
+
+        assert!(pool.record_deposit_nonce(first));
+        assert!(!pool.record_deposit_nonce(first));
+
+        // The oldest key is evicted once the ring wraps
+        for nonce in 2..=DEPOSIT_NONCE_SLOTS as u64 + 1 {
+            assert!(pool.record_deposit_nonce(StakingPool::deposit_nonce_key(&depositor, nonce)));
+        }
+        assert!(pool.record_deposit_nonce(first));
+    }
+
+    /// Distribute `lamports` over the pool's total_staked (max-weight denominator)
+    fn distribute(pool: &mut StakingPool, lamports: u64) {
+        let amount_wad = lamports as u128 * WAD;
+        let reward_per_share = wad_div(amount_wad, pool.total_staked * WAD).unwrap();
+        pool.add_reward_per_share(reward_per_share).unwrap();
+    }
+
+    /// Add a fully matured stake the way Stake does
+    fn join(pool: &mut StakingPool, amount: u64) -> UserStake {
+        pool.rescale_for(pool.total_staked + amount as u128).unwrap();
+        let mut stake = UserStake::new(Pubkey::new_unique(), Pubkey::default(), amount, 0, WAD, 255, 0);
+        stake.acc_scale_shift = pool.acc_scale_shift;
+        stake.reward_debt = wad_mul(amount as u128 * WAD, pool.acc_reward_per_weighted_share).unwrap();
+        pool.total_staked += amount as u128;
+        stake
+    }
+
+    fn pending_lamports(pool: &StakingPool, stake: &mut UserStake) -> u128 {
+        stake.sync_acc_scale(pool);
+        stake.pending_rewards_wad(pool, stake.amount as u128 * WAD).unwrap() / WAD
+    }
+
+    #[test]
+    fn test_acc_scale_tiny_pool() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+
+        // total_staked = 1: a 1000 SOL deposit makes the accumulator 1e30
+        let mut tiny = join(&mut pool, 1);
+        distribute(&mut pool, 1_000_000_000_000);
+        assert_eq!(pool.acc_scale_shift, 0);
+        assert_eq!(pending_lamports(&pool, &mut tiny), 1_000_000_000_000);
+
+        // A 10^15 staker would need a reward_debt of 1e45: precision drops instead
+        let mut whale = join(&mut pool, 1_000_000_000_000_000);
+        assert!(pool.acc_scale_shift > 0 && pool.acc_scale_shift <= MAX_ACC_SCALE_SHIFT);
+        assert!(whale.reward_debt <= MAX_SCALED_REWARD_DEBT);
+        assert_eq!(pending_lamports(&pool, &mut whale), 0);
+
+        // The tiny staker keeps its rewards (to within a lamport)
+        let tiny_pending = pending_lamports(&pool, &mut tiny);
+        assert!((999_999_999_999..=1_000_000_000_000).contains(&tiny_pending));
+        assert_eq!(tiny.acc_scale_shift, pool.acc_scale_shift);
+
+        // Later deposits still distribute, with small relative rounding
+        distribute(&mut pool, 1_000_000_000);
+        let whale_pending = pending_lamports(&pool, &mut whale);
+        assert!(whale_pending <= 1_000_000_000);
+        assert!(whale_pending >= 1_000_000_000 - 1_000_000_000 / 10_000);
+    }
+
+    #[test]
+    fn test_acc_scale_large_pool() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+
+        // total_staked = 10^15 never needs rescaling at realistic reward totals
+        let mut a = join(&mut pool, 400_000_000_000_000);
+        let mut b = join(&mut pool, 600_000_000_000_000);
+        for _ in 0..100 {
+            distribute(&mut pool, 1_000_000_000_000);
+        }
+        assert_eq!(pool.acc_scale_shift, 0);
 
-    /// Derive metadata PDA
-    pub fn derive_pda(pool: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[METADATA_SEED, pool.as_ref()], program_id)
+        let total = pending_lamports(&pool, &mut a) + pending_lamports(&pool, &mut b);
+        assert!(total <= 100_000_000_000_000);
+        assert!(total >= 100_000_000_000_000 - 2);
     }
 
-    /// Check if metadata is initialized
-    pub fn is_initialized(&self) -> bool {
-        self.discriminator == METADATA_DISCRIMINATOR
+    #[test]
+    fn test_acc_scale_long_lived_tiny_pool() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+
+        // A single token unit staked forever, paid every lamport in existence
+        // 10_000 times over (it claims in between): the accumulator crosses
+        // MAX_SCALED_REWARD_DEBT long before the end and is rescaled instead
+        // of overflowing
+        let all_sol = 580_000_000_000_000_000u64;
+        let mut tiny = join(&mut pool, 1);
+        for _ in 0..10_000 {
+            distribute(&mut pool, all_sol);
+            let pending = pending_lamports(&pool, &mut tiny);
+            assert!(pending <= all_sol as u128);
+            assert!(pending >= all_sol as u128 - all_sol as u128 / 1_000_000);
+            // Claim
+            tiny.reward_debt = wad_mul(WAD, pool.acc_reward_per_weighted_share).unwrap();
+        }
+        assert!(pool.acc_scale_shift > 0);
+        assert!(pool.acc_reward_per_weighted_share <= MAX_SCALED_REWARD_DEBT);
+        // Less than a quarter of the precision budget is used
+        assert!(pool.acc_scale_shift < MAX_ACC_SCALE_SHIFT / 4);
+
+        // A 10^15 staker can still join; precision drops further, within bounds
+        let whale = join(&mut pool, 1_000_000_000_000_000);
+        assert!(whale.reward_debt <= MAX_SCALED_REWARD_DEBT);
+        assert!(pool.acc_scale_shift <= MAX_ACC_SCALE_SHIFT);
+        distribute(&mut pool, all_sol);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_reward_precision_shift() {
+        let new_pool = || {
+            StakingPool::new(
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+                2592000,
+                0,
+                255,
+            )
+        };
+        let mut full = new_pool();
+        let mut coarse = new_pool();
+        coarse.set_reward_precision_shift(20).unwrap();
+        assert_eq!(coarse.acc_scale_shift, 20);
+        assert_eq!(
+            coarse.set_reward_precision_shift(MAX_REWARD_PRECISION_SHIFT + 1),
+            Err(StakingError::SettingExceedsMaximum)
+        );
+
+        // Same stakes and deposits in both pools
+        let mut full_a = join(&mut full, 300_000_000);
+        let mut full_b = join(&mut full, 700_000_000);
+        let mut coarse_a = join(&mut coarse, 300_000_000);
+        let mut coarse_b = join(&mut coarse, 700_000_000);
+        for _ in 0..10 {
+            distribute(&mut full, 1_000_000_000);
+            distribute(&mut coarse, 1_000_000_000);
+        }
+        assert_eq!(coarse.acc_scale_shift, 20);
+
+        assert_eq!(pending_lamports(&full, &mut full_a), 3_000_000_000);
+        assert_eq!(pending_lamports(&full, &mut full_b), 7_000_000_000);
+        // Coarser precision rounds down by at most a lamport per distribution
+        let a = pending_lamports(&coarse, &mut coarse_a);
+        let b = pending_lamports(&coarse, &mut coarse_b);
+        assert!((2_999_999_990..=3_000_000_000).contains(&a));
+        assert!((6_999_999_990..=7_000_000_000).contains(&b));
+
+        // Too late once rewards are in the accumulator
+        assert_eq!(
+            full.set_reward_precision_shift(4),
+            Err(StakingError::RewardsAlreadyDistributed)
+        );
+        assert_eq!(full.acc_scale_shift, 0);
+    }
 
     #[test]
-    fn test_pool_size() {
-        // Verify the calculated size matches actual serialized size
-        let pool = StakingPool::new(
+    fn test_acc_scale_keeps_residual_debt() {
+        let mut pool = StakingPool::new(
             Pubkey::default(),
             Pubkey::default(),
             Pubkey::default(),
@@ -562,8 +3959,48 @@ mod tests {
             0,
             255,
         );
-        let serialized = borsh::to_vec(&pool).unwrap();
-        assert_eq!(serialized.len(), StakingPool::LEN);
+        pool.acc_scale_shift = 8;
+
+        // amount == 0: reward_debt holds real unpaid rewards
+        let mut residual = UserStake::new(Pubkey::default(), Pubkey::default(), 0, 0, WAD, 255, 0);
+        residual.reward_debt = 5 * WAD;
+        assert!(residual.sync_acc_scale(&pool));
+        assert_eq!(residual.reward_debt, 5 * WAD);
+
+        let mut active = UserStake::new(Pubkey::default(), Pubkey::default(), 1, 0, WAD, 255, 0);
+        active.reward_debt = 256 * WAD;
+        assert!(active.sync_acc_scale(&pool));
+        assert_eq!(active.reward_debt, WAD);
+        assert!(!active.sync_acc_scale(&pool));
+    }
+
+    #[test]
+    fn test_pack_into_legacy_slice() {
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1000,
+            12345,
+            1_000_000_000_000_000_000,
+            255,
+            12345,
+        );
+        let mut buf = vec![0u8; UserStake::V4_LEN];
+
+        // Appended fields at their defaults: the prefix fits
+        pack_into_slice(&stake, &mut buf).unwrap();
+        assert_eq!(UserStake::try_from_slice(&buf).unwrap().amount, 1000);
+
+        // Non-default appended field needs a realloc first
+        stake.claimed_balance = 1;
+        assert_eq!(
+            pack_into_slice(&stake, &mut buf),
+            Err(StakingError::AccountDataTooSmall)
+        );
+
+        let mut full = vec![0u8; UserStake::LEN];
+        pack_into_slice(&stake, &mut full).unwrap();
+        assert_eq!(UserStake::try_from_slice(&full).unwrap().claimed_balance, 1);
     }
 
     #[test]
@@ -586,6 +4023,61 @@ mod tests {
         assert_eq!(PoolMetadata::LEN, 508);
     }
 
+    #[test]
+    fn test_audit_log_page() {
+        let mut page = AuditLogPage::new(Pubkey::new_unique(), 3, 254);
+        assert_eq!(borsh::to_vec(&page).unwrap().len(), AuditLogPage::LEN);
+
+        for i in 0..AUDIT_LOG_PAGE_CAPACITY {
+            assert!(!page.is_full());
+            assert!(page.push(AuditRecord {
+                timestamp: 1_000 + i as i64,
+                amount: i as u64,
+                reward_per_share: WAD,
+                total_staked: 42,
+            }));
+        }
+        assert!(page.is_full());
+        assert!(!page.push(AuditRecord::default()));
+
+        let roundtrip = AuditLogPage::try_from_slice(&borsh::to_vec(&page).unwrap()).unwrap();
+        assert!(roundtrip.is_initialized());
+        assert_eq!(roundtrip.page, 3);
+        assert_eq!(roundtrip.count as usize, AUDIT_LOG_PAGE_CAPACITY);
+        assert_eq!(roundtrip.records[AUDIT_LOG_PAGE_CAPACITY - 1].timestamp, 1_031);
+    }
+
+    #[test]
+    fn test_depositor_allowlist_entry() {
+        let pool_key = Pubkey::new_unique();
+        let depositor = Pubkey::new_unique();
+        let entry = DepositorAllowlistEntry::new(pool_key, depositor, 253);
+        let serialized = borsh::to_vec(&entry).unwrap();
+        assert_eq!(serialized.len(), DepositorAllowlistEntry::LEN);
+        assert_eq!(DepositorAllowlistEntry::LEN, 73);
+        let roundtrip = DepositorAllowlistEntry::try_from_slice(&serialized).unwrap();
+        assert!(roundtrip.is_initialized());
+        assert_eq!(roundtrip.depositor, depositor);
+
+        let mut pool = StakingPool::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            pool_key,
+            Pubkey::new_unique(),
+            86_400,
+            0,
+            255,
+        );
+        pool.require_allowlisted_depositors = true;
+        let serialized = borsh::to_vec(&pool).unwrap();
+        assert_eq!(serialized.len(), StakingPool::LEN);
+        assert!(StakingPool::try_from_slice(&serialized).unwrap().require_allowlisted_depositors);
+
+        // Pools from before the flag existed (883 bytes) stay open to anyone
+        let legacy = StakingPool::try_from_slice(&serialized[..883]).unwrap();
+        assert!(!legacy.require_allowlisted_depositors);
+    }
+
     #[test]
     fn test_user_stake_size() {
         let stake = UserStake::new(
@@ -599,10 +4091,65 @@ mod tests {
         );
         let serialized = borsh::to_vec(&stake).unwrap();
         assert_eq!(serialized.len(), UserStake::LEN);
-        assert_eq!(UserStake::LEN, 177);
+        assert_eq!(UserStake::LEN, 339);
+        assert_eq!(UserStake::V4_LEN, 177);
         assert_eq!(UserStake::LEGACY_LEN, 161);
     }
 
+    #[test]
+    fn test_user_stake_positions() {
+        let program_id = crate::id();
+        let pool = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        // Position 0 is the default stake account; others are all distinct
+        let default = derive_user_stake_pda(&owner, &pool, &program_id);
+        assert_eq!(derive_user_stake_position_pda(&owner, &pool, 0, &program_id), default);
+        let first = derive_user_stake_position_pda(&owner, &pool, 1, &program_id).0;
+        let second = derive_user_stake_position_pda(&owner, &pool, 2, &program_id).0;
+        assert_ne!(first, default.0);
+        assert_ne!(first, second);
+        let other_owner = Pubkey::new_unique();
+        assert_ne!(derive_user_stake_position_pda(&other_owner, &pool, 1, &program_id).0, first);
+
+        // A stake derives its own position's PDA
+        let mut stake = UserStake::new(owner, pool, 0, 0, 0, 255, 0);
+        stake.position_index = 2;
+        assert_eq!(stake.derive_position_pda(&pool, &program_id).0, second);
+        let read = UserStake::try_from_slice(&borsh::to_vec(&stake).unwrap()).unwrap();
+        assert_eq!(read.position_index, 2);
+
+        // Accounts written before positions read back as position 0
+        let before = borsh::to_vec(&stake).unwrap()[..269].to_vec();
+        let legacy = UserStake::try_from_slice(&before).unwrap();
+        assert_eq!(legacy.position_index, 0);
+        assert_eq!(legacy.derive_position_pda(&pool, &program_id), default);
+
+        // Unused until the first stake; a fully unstaked position is not
+        assert!(stake.is_unused_position());
+        stake.stake_time = 1_000;
+        assert!(!stake.is_unused_position());
+
+        // Only the default stake account tracks the owner's other positions
+        stake.open_position(5);
+        assert!(!stake.tracks_positions());
+        stake.position_index = 0;
+        assert!(stake.tracks_positions());
+        stake.open_position(3);
+        assert_eq!((stake.max_position_index, stake.open_positions), (5, 2));
+        let read = UserStake::try_from_slice(&borsh::to_vec(&stake).unwrap()).unwrap();
+        assert_eq!(read.max_position_index, 5);
+        assert_eq!(read.open_positions, 2);
+
+        // Closing the last open position releases the default account
+        stake.close_position();
+        assert!(stake.tracks_positions());
+        assert_eq!(stake.max_position_index, 5);
+        stake.close_position();
+        assert!(!stake.tracks_positions());
+        assert_eq!(stake.max_position_index, 0);
+    }
+
     #[test]
     fn test_user_stake_legacy_deserialize() {
         // Create a new stake and serialize it
@@ -634,7 +4181,13 @@ mod tests {
         assert_eq!(deserialized_old.total_rewards_claimed, 0);
         assert_eq!(deserialized_old.claimed_rewards_wad, 0);
 
-        // Full 177-byte deserialization should also work
+        // v4 accounts have no stamped terms and follow the pool
+        let v4 = UserStake::try_from_slice(&full[..UserStake::V4_LEN]).unwrap();
+        assert!(!v4.terms_stamped);
+        assert_eq!(v4.unstake_cooldown_seconds, 0);
+        assert!(!v4.claim_for_enabled);
+
+        // Full-size deserialization should also work
         let deserialized_full = UserStake::try_from_slice(&full).unwrap();
         assert_eq!(deserialized_full.total_rewards_claimed, 0);
         assert_eq!(deserialized_full.claimed_rewards_wad, 0);
@@ -659,6 +4212,407 @@ mod tests {
         assert_eq!(deserialized.claimed_rewards_wad, 42_000_000_000_000_000_000);
     }
 
+    #[test]
+    fn test_reversible_unstake_window() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            0,
+            12345,
+            1_000_000_000_000_000_000,
+            255,
+            12345,
+        );
+        stake.last_unstake_completion_time = 20_000;
+        stake.last_unstake_completion_amount = 500;
+
+        // Disabled by default
+        assert_eq!(stake.reversible_unstake_amount(&pool, 20_000), None);
+
+        pool.unstake_reversal_window_seconds = 60;
+        assert_eq!(stake.reversible_unstake_amount(&pool, 20_000), Some(500));
+        assert_eq!(stake.reversible_unstake_amount(&pool, 20_060), Some(500));
+        assert_eq!(stake.reversible_unstake_amount(&pool, 20_061), None);
+
+        // Nothing recorded
+        stake.last_unstake_completion_time = 0;
+        assert_eq!(stake.reversible_unstake_amount(&pool, 20_000), None);
+    }
+
+    #[test]
+    fn test_user_stake_is_empty() {
+        let mut stake = UserStake::new(Pubkey::default(), Pubkey::default(), 0, 0, WAD, 255, 0);
+        assert!(stake.is_empty());
+
+        // Sub-lamport residual dust is forgiven
+        stake.reward_debt = WAD - 1;
+        assert!(stake.is_empty());
+        stake.reward_debt = WAD;
+        assert!(!stake.is_empty());
+        assert_eq!(stake.close_blocker(), Some(StakingError::OutstandingRewards));
+        stake.reward_debt = 0;
+
+        stake.claimed_balance = 1;
+        assert!(!stake.is_empty());
+        assert_eq!(stake.close_blocker(), Some(StakingError::OutstandingRewards));
+        stake.claimed_balance = 0;
+
+        stake.unstake_request_amount = 1;
+        assert!(!stake.is_empty());
+        assert_eq!(stake.close_blocker(), Some(StakingError::AccountNotEmpty));
+        stake.unstake_request_amount = 0;
+
+        // Staked tokens are reported before outstanding rewards
+        stake.amount = 1;
+        stake.claimed_balance = 1;
+        assert!(!stake.is_empty());
+        assert_eq!(stake.close_blocker(), Some(StakingError::AccountNotEmpty));
+    }
+
+    #[test]
+    fn test_is_unstake_completable() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.unstake_cooldown_seconds = 50;
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            1_000_000_000_000_000_000,
+            255,
+            10_000,
+        );
+
+        // No request pending
+        assert!(!stake.is_unstake_completable(&pool, 99_999));
+        assert_eq!(stake.unstake_cooldown_end(&pool), 0);
+
+        stake.unstake_request_amount = 1_000;
+        stake.unstake_request_time = 10_200;
+        assert_eq!(stake.unstake_cooldown_end(&pool), 10_250);
+        assert!(!stake.is_unstake_completable(&pool, 10_249));
+        assert!(stake.is_unstake_completable(&pool, 10_250));
+
+        // Stamped terms win over the pool's
+        stake.terms_stamped = true;
+        stake.unstake_cooldown_seconds = 100;
+        assert_eq!(stake.unstake_cooldown_end(&pool), 10_300);
+        assert!(!stake.is_unstake_completable(&pool, 10_250));
+        assert!(stake.is_unstake_completable(&pool, 10_300));
+    }
+
+    #[test]
+    fn test_amend_unstake_request() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.unstake_cooldown_seconds = 50;
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            1_000_000_000_000_000_000,
+            255,
+            10_000,
+        );
+
+        // Nothing to amend yet
+        assert_eq!(
+            stake.amend_unstake_request(500, 10_100),
+            Err(StakingError::NoPendingUnstakeRequest)
+        );
+
+        stake.unstake_request_amount = 600;
+        stake.unstake_request_time = 10_200;
+
+        // Lowering keeps the original cooldown end
+        stake.amend_unstake_request(400, 10_240).unwrap();
+        assert_eq!(stake.unstake_request_amount, 400);
+        assert_eq!(stake.unstake_cooldown_end(&pool), 10_250);
+        assert!(stake.is_unstake_completable(&pool, 10_250));
+
+        // Raising restarts it for the whole request
+        stake.amend_unstake_request(900, 10_260).unwrap();
+        assert_eq!(stake.unstake_request_amount, 900);
+        assert_eq!(stake.unstake_cooldown_end(&pool), 10_310);
+        assert!(!stake.is_unstake_completable(&pool, 10_309));
+
+        // Same amount: nothing changes
+        stake.amend_unstake_request(900, 10_300).unwrap();
+        assert_eq!(stake.unstake_request_time, 10_260);
+
+        // More than staked, or zero (use CancelUnstakeRequest), is rejected
+        assert_eq!(
+            stake.amend_unstake_request(1_001, 10_300),
+            Err(StakingError::InsufficientStakeBalance)
+        );
+        assert_eq!(stake.amend_unstake_request(0, 10_300), Err(StakingError::ZeroAmount));
+        assert_eq!(stake.unstake_request_amount, 900);
+        assert_eq!(stake.unstake_request_time, 10_260);
+    }
+
+    #[test]
+    fn test_time_to_unlock() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            1_000_000_000_000_000_000,
+            255,
+            10_000,
+        );
+
+        // No lock, no cooldown: available now
+        assert_eq!(stake.time_to_unlock(&pool, 10_000), 0);
+
+        pool.lock_duration_seconds = 100;
+        pool.unstake_cooldown_seconds = 50;
+
+        // Pre-lock: rest of the lock plus the full cooldown
+        assert_eq!(stake.time_to_unlock(&pool, 10_040), 60 + 50);
+
+        // Post-lock, no request: the full cooldown
+        assert_eq!(stake.time_to_unlock(&pool, 10_100), 50);
+        assert_eq!(stake.time_to_unlock(&pool, 99_999), 50);
+
+        // Pending request: the rest of its cooldown
+        stake.unstake_request_amount = 1_000;
+        stake.unstake_request_time = 10_200;
+        assert_eq!(stake.time_to_unlock(&pool, 10_220), 30);
+        assert_eq!(stake.time_to_unlock(&pool, 10_250), 0);
+        assert_eq!(stake.time_to_unlock(&pool, 20_000), 0);
+
+        // Stamped terms win over the pool's
+        stake.terms_stamped = true;
+        stake.lock_duration_seconds = 0;
+        stake.unstake_cooldown_seconds = 500;
+        assert_eq!(stake.time_to_unlock(&pool, 10_220), 480);
+
+        // A lock commitment outlasts a shorter lock
+        stake.unstake_request_amount = 0;
+        stake.lock_commit_seconds = 1_000;
+        assert_eq!(stake.effective_lock_duration(&pool), 1_000);
+        assert_eq!(stake.time_to_unlock(&pool, 10_500), 500 + 500);
+    }
+
+    #[test]
+    fn test_total_staked_returns_to_zero() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+
+        // Many users staking, topping up and partially unstaking in an
+        // arbitrary order: total_staked tracks the sum of positions exactly
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        let mut positions = [0u64; 64];
+        for _ in 0..10_000 {
+            let user = next(positions.len() as u64) as usize;
+            if next(3) == 0 || positions[user] == 0 {
+                let amount = 1 + next(1_000_000_000_000);
+                positions[user] += amount;
+                pool.total_staked += amount as u128;
+            } else {
+                let amount = 1 + next(positions[user]);
+                pool.total_staked = pool.total_staked_after_unstake(amount).unwrap();
+                positions[user] -= amount;
+            }
+            let sum: u128 = positions.iter().map(|amount| *amount as u128).sum();
+            assert_eq!(pool.total_staked, sum);
+        }
+
+        // Everyone exits: back to exactly zero
+        for amount in positions.iter_mut().filter(|amount| **amount > 0) {
+            pool.total_staked = pool.total_staked_after_unstake(*amount).unwrap();
+            *amount = 0;
+        }
+        assert_eq!(pool.total_staked, 0);
+
+        // One token more than recorded is refused instead of wrapping
+        assert_eq!(
+            pool.total_staked_after_unstake(1),
+            Err(StakingError::StakeAccountingUnderflow)
+        );
+        pool.total_staked = 10;
+        assert_eq!(pool.total_staked_after_unstake(10), Ok(0));
+        assert_eq!(
+            pool.total_staked_after_unstake(11),
+            Err(StakingError::StakeAccountingUnderflow)
+        );
+    }
+
+    #[test]
+    fn test_top_up_locks() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.lock_duration_seconds = 100;
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            1_000_000_000_000_000_000,
+            255,
+            10_000,
+        );
+
+        // Top-up mid-lock: the original tokens unlock on their own schedule
+        stake.amount += 500;
+        stake.lock_top_up(&pool, 500, 10_050);
+        assert_eq!(stake.locked_amount(&pool, 10_060), 1_500);
+        assert!(stake.check_unlocked(&pool, 1, 10_099).is_err());
+        assert_eq!(stake.locked_amount(&pool, 10_100), 500);
+        assert!(stake.check_unlocked(&pool, 1_000, 10_100).is_ok());
+        assert_eq!(
+            stake.check_unlocked(&pool, 1_001, 10_100),
+            Err(StakingError::StakeLocked)
+        );
+        assert_eq!(stake.lock_end(&pool), 10_150);
+        assert_eq!(stake.time_to_unlock(&pool, 10_100), 50);
+        assert_eq!(stake.locked_amount(&pool, 10_150), 0);
+
+        // Unstaking the unlocked tokens keeps the top-up's lock
+        stake.amount -= 1_000;
+        stake.trim_top_up_locks();
+        assert_eq!(stake.top_up_lock_amounts[0], 500);
+        assert_eq!(stake.locked_amount(&pool, 10_120), 500);
+
+        // Expired entries are dropped; once every slot is locked, further
+        // top-ups merge into the newest and restart its lock
+        for time in [10_200, 10_210, 10_220, 10_230, 10_240] {
+            stake.amount += 100;
+            stake.lock_top_up(&pool, 100, time);
+        }
+        assert_eq!(stake.top_up_lock_amounts, [100, 100, 100, 200]);
+        assert_eq!(stake.top_up_lock_times, [10_200, 10_210, 10_220, 10_240]);
+        assert_eq!(stake.locked_amount(&pool, 10_305), 400);
+        assert_eq!(stake.lock_end(&pool), 10_340);
+
+        // Tokens leaving past the lock (e.g. a split) trim the oldest first
+        stake.amount = 250;
+        stake.trim_top_up_locks();
+        assert_eq!(stake.top_up_lock_amounts, [0, 0, 50, 200]);
+        assert_eq!(stake.top_up_lock_times, [0, 0, 10_220, 10_240]);
+        stake.amount = 0;
+        stake.trim_top_up_locks();
+        assert_eq!(stake.top_up_lock_amounts, [0; TOP_UP_LOCK_SLOTS]);
+
+        // Without a lock nothing is tracked or locked
+        pool.lock_duration_seconds = 0;
+        stake.amount = 100;
+        stake.lock_top_up(&pool, 100, 20_000);
+        assert_eq!(stake.top_up_lock_amounts, [0; TOP_UP_LOCK_SLOTS]);
+        assert_eq!(stake.locked_amount(&pool, 20_000), 0);
+
+        // reset_lock relocks the whole position
+        pool.lock_duration_seconds = 100;
+        stake.lock_top_up(&pool, 50, 20_000);
+        stake.reset_lock(20_010);
+        assert_eq!(stake.top_up_lock_amounts, [0; TOP_UP_LOCK_SLOTS]);
+        assert_eq!(stake.locked_amount(&pool, 20_100), 100);
+        assert_eq!(stake.locked_amount(&pool, 20_110), 0);
+    }
+
+    #[test]
+    fn test_effective_terms_flags() {
+        let mut pool = StakingPool::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            2592000,
+            0,
+            255,
+        );
+        pool.lock_duration_seconds = 100;
+        pool.unstake_cooldown_seconds = 50;
+
+        let mut stake = UserStake::new(
+            Pubkey::default(),
+            Pubkey::default(),
+            1_000,
+            10_000,
+            1_000_000_000_000_000_000,
+            255,
+            10_000,
+        );
+        assert_eq!(stake.effective_terms_flags(&pool), 0);
+
+        // Grandfathered and stamped with looser terms than the pool's current ones
+        stake.grandfathered = true;
+        stake.terms_stamped = true;
+        stake.lock_duration_seconds = 10;
+        stake.unstake_cooldown_seconds = 5;
+        assert_eq!(
+            stake.effective_terms_flags(&pool),
+            TERMS_FLAG_STAMPED | TERMS_FLAG_GRANDFATHERED
+        );
+        assert_eq!(stake.effective_lock_duration(&pool), 10);
+        assert_eq!(stake.effective_unstake_cooldown(&pool), 5);
+
+        // A commitment only counts when it outlasts the terms
+        stake.lock_commit_seconds = 10;
+        assert_eq!(stake.effective_terms_flags(&pool) & TERMS_FLAG_LOCK_COMMITTED, 0);
+        stake.lock_commit_seconds = 60;
+        assert_ne!(stake.effective_terms_flags(&pool) & TERMS_FLAG_LOCK_COMMITTED, 0);
+        assert_eq!(stake.effective_lock_duration(&pool), 60);
+
+        stake.claim_for_enabled = true;
+        assert_eq!(stake.effective_terms_flags(&pool), 0b1111);
+    }
+
     #[test]
     fn test_spl_token_program_id() {
         // Verify our constant matches the canonical SPL Token program ID
@@ -684,6 +4638,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "devnet"))]
     fn test_pfee_program_id() {
         let expected: Pubkey = "pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ"
             .parse()
@@ -691,6 +4646,29 @@ mod tests {
         assert_eq!(PFEE_PROGRAM_ID, expected);
     }
 
+    #[test]
+    #[cfg(feature = "devnet")]
+    fn test_devnet_pfee_program_id() {
+        let expected: Pubkey = env!("CHIEFSTAKER_PFEE_PROGRAM_ID").parse().unwrap();
+        assert_eq!(PFEE_PROGRAM_ID, expected);
+    }
+
+    #[test]
+    fn test_pubkey_from_base58() {
+        for encoded in [
+            "pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ",
+            "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P",
+            "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+            "11111111111111111111111111111111",
+        ] {
+            assert_eq!(pubkey_from_base58(encoded), encoded.parse::<Pubkey>().unwrap());
+        }
+        for _ in 0..100 {
+            let key = Pubkey::new_unique();
+            assert_eq!(pubkey_from_base58(&key.to_string()), key);
+        }
+    }
+
     #[test]
     fn test_pump_program_id() {
         let expected: Pubkey = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P"
@@ -0,0 +1,72 @@
+//! Mock governance program (E2E tests only, never deployed to mainnet)
+//!
+//! Stands in for a DAO governance program that holds chiefstaker pool
+//! authority as a PDA. Every instruction is forwarded verbatim to the target
+//! program with the governance PDA signing via `invoke_signed`, the same way
+//! SPL Governance executes a passed proposal.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+#[cfg(not(feature = "no-entrypoint"))]
+use solana_program::entrypoint;
+
+/// Seed for the governance PDA: ["governance"]
+pub const GOVERNANCE_SEED: &[u8] = b"governance";
+
+/// Derive the governance PDA that signs forwarded instructions
+pub fn derive_governance_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GOVERNANCE_SEED], program_id)
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
+entrypoint!(process_instruction);
+
+/// Forward `instruction_data` to the target program, signed by the governance PDA
+///
+/// Accounts:
+/// 0. `[]` Target program
+/// 1. `[]` Governance PDA (PDA: ["governance"])
+/// 2. .. Accounts of the target instruction, in order
+///
+/// The governance PDA is passed as a signer wherever it appears among the
+/// forwarded accounts; writability is taken as given.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let [target_program_info, governance_info, forwarded @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let (governance_pda, bump) = derive_governance_pda(program_id);
+    if *governance_info.key != governance_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let metas = forwarded
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: *info.key,
+            is_signer: info.is_signer || *info.key == governance_pda,
+            is_writable: info.is_writable,
+        })
+        .collect();
+
+    invoke_signed(
+        &Instruction {
+            program_id: *target_program_info.key,
+            accounts: metas,
+            data: instruction_data.to_vec(),
+        },
+        forwarded,
+        &[&[GOVERNANCE_SEED, &[bump]]],
+    )
+}
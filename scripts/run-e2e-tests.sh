@@ -4,7 +4,7 @@
 # This script:
 # 1. Builds the program
 # 2. Starts a test validator
-# 3. Deploys the program
+# 3. Deploys the program (and the mock governance test program)
 # 4. Runs TypeScript E2E tests
 # 5. Cleans up
 
@@ -79,6 +79,12 @@ if [ ! -f "$PROGRAM_SO" ]; then
 fi
 
 $SOLANA_CLI program deploy "$PROGRAM_SO"
+
+# Mock governance program (holds pool authority as a PDA in the DAO tests)
+MOCK_GOVERNANCE_SO="$PROJECT_DIR/target/deploy/mock_governance.so"
+if [ -f "$MOCK_GOVERNANCE_SO" ]; then
+    $SOLANA_CLI program deploy "$MOCK_GOVERNANCE_SO"
+fi
 echo ""
 
 # Step 4: Run TypeScript tests
@@ -24,6 +24,7 @@ import {
   createAccount,
   mintTo,
   getAccount,
+  transferChecked,
   TOKEN_2022_PROGRAM_ID,
   TOKEN_PROGRAM_ID,
   ExtensionType,
@@ -36,6 +37,7 @@ import {
 import { createInitializeInstruction, pack } from '@solana/spl-token-metadata';
 import * as borsh from 'borsh';
 import BN from 'bn.js';
+import { createHash } from 'crypto';
 import * as fs from 'fs';
 import * as path from 'path';
 
@@ -46,7 +48,16 @@ const PROGRAM_ID = new PublicKey('3Ecf8gyRURyrBtGHS1XAVXyQik5PqgDch4VkxrH4ECcr')
 const POOL_SEED = Buffer.from('pool');
 const STAKE_SEED = Buffer.from('stake');
 const TOKEN_VAULT_SEED = Buffer.from('token_vault');
+const REWARD_VAULT_SEED = Buffer.from('reward_vault');
 const METADATA_SEED = Buffer.from('metadata');
+const AUDIT_LOG_SEED = Buffer.from('audit_log');
+const DEPOSITOR_SEED = Buffer.from('depositor');
+const GOVERNANCE_SEED = Buffer.from('governance');
+
+// Mock governance program (programs/mock-governance), deployed from its build keypair
+const MOCK_GOVERNANCE_KEYPAIR_PATH = path.join(
+  __dirname, '..', '..', 'target', 'deploy', 'mock_governance-keypair.json',
+);
 
 // Instruction discriminators (borsh enum indices)
 enum InstructionType {
@@ -68,6 +79,80 @@ enum InstructionType {
   TakeFeeOwnership = 15,
   StakeOnBehalf = 16,
   FixStakeAccount = 17,
+  VerifyVaultConsistency = 18,
+  ClaimToBalance = 19,
+  WithdrawClaimed = 20,
+  GrandfatherStake = 21,
+  SplitStake = 22,
+  QueryClaimableAt = 23,
+  CollectProtocolFees = 24,
+  QuarantinePool = 25,
+  ViewPoolHealth = 26,
+  BatchRequestUnstake = 27,
+  ClaimFor = 28,
+  SetClaimForOptIn = 29,
+  SetRewardFeeSplit = 30,
+  ExtendPoolAccount = 31,
+  ReverseUnstake = 32,
+  QueryMinStakeUi = 33,
+  ForceSyncWithBalance = 34,
+  SetUnstakeDestinationPolicy = 35,
+  QueryTimeToUnlock = 36,
+  QueryEffectiveSettings = 37,
+  QueryAccumulatorSnapshot = 38,
+  ForceUnstakeQuarantined = 39,
+  LockPoolSettings = 40,
+  QueryRewardRate = 41,
+  SetDisplayUnit = 42,
+  QueryDisplayUnit = 43,
+  QueryIsCompletableBatch = 44,
+  ReinitializeVault = 45,
+  DistributeBonus = 46,
+  QueryPendingUnstake = 47,
+  SetRewardPrecision = 48,
+  ClaimAndCloseIfEmpty = 49,
+  QueryWeightedShare = 50,
+  SetDelegate = 51,
+  VerifyPfeeOwnership = 52,
+  GetPendingRewards = 53,
+  ClaimRewardsTo = 54,
+  TransferStake = 55,
+  UpdateTau = 56,
+  SetRewardMint = 57,
+  DepositTokenRewards = 58,
+  ClaimTokenRewards = 59,
+  RenounceAuthority = 60,
+  SetPause = 61,
+  InstantUnstake = 62,
+  ProposeAuthority = 63,
+  AcceptAuthority = 64,
+  HarvestCreatorFees = 65,
+  SetFeeShares = 66,
+  AmendUnstakeRequest = 67,
+  SyncRewardsBatch = 68,
+  DepositRewardsStreamed = 69,
+  ClosePool = 70,
+  InitializeUserStake = 71,
+  GetPoolStats = 72,
+  AddDepositor = 73,
+  RemoveDepositor = 74,
+  ApplySettings = 75,
+  QueryPendingSettings = 76,
+  ReclaimDeferredRewards = 77,
+  WithdrawFeeSplit = 78,
+  CancelAuthorityProposal = 79,
+}
+
+// Borsh enum TopUpMode (lib.rs)
+enum TopUpMode {
+  ResetLock = 0,
+  KeepLock = 1,
+}
+
+// Borsh enum UnstakeDestinationPolicy (state.rs)
+enum UnstakeDestinationPolicy {
+  AnyOwnerAccount = 0,
+  OwnerAtaOnly = 1,
 }
 
 // Helper to derive PDAs
@@ -85,6 +170,13 @@ function deriveTokenVaultPDA(pool: PublicKey): [PublicKey, number] {
   );
 }
 
+function deriveRewardVaultPDA(pool: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [REWARD_VAULT_SEED, pool.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
 function deriveUserStakePDA(pool: PublicKey, user: PublicKey): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
     [STAKE_SEED, pool.toBuffer(), user.toBuffer()],
@@ -99,6 +191,50 @@ function deriveMetadataPDA(pool: PublicKey): [PublicKey, number] {
   );
 }
 
+function deriveAuditLogPagePDA(pool: PublicKey, page: number): [PublicKey, number] {
+  const pageBytes = Buffer.alloc(4);
+  pageBytes.writeUInt32LE(page, 0);
+  return PublicKey.findProgramAddressSync(
+    [AUDIT_LOG_SEED, pool.toBuffer(), pageBytes],
+    PROGRAM_ID
+  );
+}
+
+function deriveGovernancePDA(governanceProgram: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([GOVERNANCE_SEED], governanceProgram);
+}
+
+function loadMockGovernanceProgramId(): PublicKey | null {
+  if (!fs.existsSync(MOCK_GOVERNANCE_KEYPAIR_PATH)) return null;
+  return Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(MOCK_GOVERNANCE_KEYPAIR_PATH, 'utf8'))),
+  ).publicKey;
+}
+
+// Wrap a ChiefStaker instruction so the mock governance program executes it,
+// signing for its PDA via invoke_signed. The PDA is not a transaction signer.
+function createGovernanceExecuteInstruction(
+  governanceProgram: PublicKey,
+  inner: TransactionInstruction,
+): TransactionInstruction {
+  const [governancePDA] = deriveGovernancePDA(governanceProgram);
+  const forwarded = inner.keys.map(k => ({
+    pubkey: k.pubkey,
+    isSigner: k.pubkey.equals(governancePDA) ? false : k.isSigner,
+    isWritable: k.isWritable,
+  }));
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: inner.programId, isSigner: false, isWritable: false },
+      { pubkey: governancePDA, isSigner: false, isWritable: false },
+      ...forwarded,
+    ],
+    programId: governanceProgram,
+    data: inner.data,
+  });
+}
+
 async function airdropAndConfirm(connection: Connection, publicKey: PublicKey, lamports: number): Promise<void> {
   const sig = await connection.requestAirdrop(publicKey, lamports);
   await connection.confirmTransaction(sig);
@@ -112,27 +248,69 @@ function createInitializePoolInstruction(
   authority: PublicKey,
   tauSeconds: bigint,
   tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+  auditLogPage: PublicKey | null = null,
+  initialSettings: InitialPoolSettings | null = null,
 ): TransactionInstruction {
   // Borsh serialize: enum variant (u8) + tau_seconds (u64)
-  const data = Buffer.alloc(1 + 8);
-  data.writeUInt8(InstructionType.InitializePool, 0);
-  data.writeBigUInt64LE(tauSeconds, 1);
+  // [+ trailing Option<bool> enable_audit_log, omitted when unset]
+  // [+ trailing Option<InitialPoolSettings> (3x u64), omitted when unset]
+  const parts = [Buffer.alloc(9)];
+  parts[0].writeUInt8(InstructionType.InitializePool, 0);
+  parts[0].writeBigUInt64LE(tauSeconds, 1);
+  if (auditLogPage !== null || initialSettings !== null) {
+    parts.push(Buffer.from(auditLogPage !== null ? [1, 1] : [0]));
+  }
+  if (initialSettings !== null) {
+    const settings = Buffer.alloc(1 + 24);
+    settings.writeUInt8(1, 0);
+    settings.writeBigUInt64LE(initialSettings.minStakeAmount, 1);
+    settings.writeBigUInt64LE(initialSettings.lockDurationSeconds, 9);
+    settings.writeBigUInt64LE(initialSettings.unstakeCooldownSeconds, 17);
+    parts.push(settings);
+  }
+  const data = Buffer.concat(parts);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: mint, isSigner: false, isWritable: false },
+    { pubkey: tokenVault, isSigner: false, isWritable: true },
+    { pubkey: authority, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    { pubkey: new PublicKey('SysvarRent111111111111111111111111111111111'), isSigner: false, isWritable: false },
+  ];
+  if (auditLogPage) {
+    // Audit log page 0, created with the pool
+    keys.push({ pubkey: auditLogPage, isSigner: false, isWritable: true });
+  }
 
   return new TransactionInstruction({
-    keys: [
-      { pubkey: pool, isSigner: false, isWritable: true },
-      { pubkey: mint, isSigner: false, isWritable: false },
-      { pubkey: tokenVault, isSigner: false, isWritable: true },
-      { pubkey: authority, isSigner: true, isWritable: true },
-      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
-      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
-      { pubkey: new PublicKey('SysvarRent111111111111111111111111111111111'), isSigner: false, isWritable: false },
-    ],
+    keys,
     programId: PROGRAM_ID,
     data,
   });
 }
 
+// InitialPoolSettings (lib.rs), validated like UpdatePoolSettings
+interface InitialPoolSettings {
+  minStakeAmount: bigint;
+  lockDurationSeconds: bigint;
+  unstakeCooldownSeconds: bigint;
+}
+
+// DepositReferral (lib.rs): cut of a DepositRewards deposit paid to a referrer
+interface DepositReferral {
+  referrer: PublicKey;
+  referralBps: bigint;
+}
+
+// Optional StakeParams fields (lib.rs), appended after the amount
+interface StakeOptions {
+  referrer?: PublicKey;
+  lockCommitSeconds?: bigint;
+  topUpMode?: TopUpMode;
+}
+
 function createStakeInstruction(
   pool: PublicKey,
   userStake: PublicKey,
@@ -142,10 +320,29 @@ function createStakeInstruction(
   user: PublicKey,
   amount: bigint,
   tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+  options: StakeOptions = {},
 ): TransactionInstruction {
-  const data = Buffer.alloc(1 + 8);
-  data.writeUInt8(InstructionType.Stake, 0);
-  data.writeBigUInt64LE(amount, 1);
+  // variant (u8) + amount (u64), then the trailing Options up to the last one set
+  const header = Buffer.alloc(1 + 8);
+  header.writeUInt8(InstructionType.Stake, 0);
+  header.writeBigUInt64LE(amount, 1);
+  const fields: (Buffer | null)[] = [
+    options.referrer ? options.referrer.toBuffer() : null,
+    null,
+    options.topUpMode !== undefined ? Buffer.from([options.topUpMode]) : null,
+  ];
+  if (options.lockCommitSeconds !== undefined) {
+    fields[1] = Buffer.alloc(8);
+    fields[1].writeBigUInt64LE(options.lockCommitSeconds, 0);
+  }
+  let last = fields.length - 1;
+  while (last >= 0 && fields[last] === null) last--;
+  const parts = [header];
+  for (let i = 0; i <= last; i++) {
+    const field = fields[i];
+    parts.push(field === null ? Buffer.from([0]) : Buffer.concat([Buffer.from([1]), field]));
+  }
+  const data = Buffer.concat(parts);
 
   return new TransactionInstruction({
     keys: [
@@ -195,17 +392,33 @@ function createUnstakeInstruction(
 function createClaimRewardsInstruction(
   pool: PublicKey,
   userStake: PublicKey,
-  user: PublicKey
+  user: PublicKey,
+  feeRecipients: PublicKey[] = [],
+  amount: bigint | null = null,
 ): TransactionInstruction {
-  const data = Buffer.alloc(1);
+  // Trailing Option<u64> amount: omitted for a full claim
+  const data = Buffer.alloc(amount === null ? 1 : 1 + 1 + 8);
   data.writeUInt8(InstructionType.ClaimRewards, 0);
+  if (amount !== null) {
+    data.writeUInt8(1, 1);
+    data.writeBigUInt64LE(amount, 2);
+  }
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: userStake, isSigner: false, isWritable: true },
+    { pubkey: user, isSigner: true, isWritable: true },
+  ];
+  // Fee split recipients follow the (otherwise optional) system program
+  if (feeRecipients.length > 0) {
+    keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+    for (const recipient of feeRecipients) {
+      keys.push({ pubkey: recipient, isSigner: false, isWritable: true });
+    }
+  }
 
   return new TransactionInstruction({
-    keys: [
-      { pubkey: pool, isSigner: false, isWritable: true },
-      { pubkey: userStake, isSigner: false, isWritable: true },
-      { pubkey: user, isSigner: true, isWritable: true },
-    ],
+    keys,
     programId: PROGRAM_ID,
     data,
   });
@@ -214,18 +427,65 @@ function createClaimRewardsInstruction(
 function createDepositRewardsInstruction(
   pool: PublicKey,
   depositor: PublicKey,
-  amount: bigint
+  amount: bigint,
+  depositorStake: PublicKey | null = null,
+  nonce: bigint | null = null,
+  auditLogPage: PublicKey | null = null,
+  displayValue: bigint | null = null,
+  allowlistEntry: PublicKey | null = null,
+  referral: DepositReferral | null = null,
 ): TransactionInstruction {
-  const data = Buffer.alloc(1 + 8);
+  // variant (u8) + amount (u64) [+ trailing Option<u64> nonce,
+  // Option<u64> display_value, Option<DepositReferral> referral
+  // (referrer pubkey + referral_bps u64) — omitted when unset]
+  const trailing = referral !== null ? [nonce, displayValue]
+    : displayValue !== null ? [nonce, displayValue] : nonce !== null ? [nonce] : [];
+  let size = 1 + 8 + (referral !== null ? 1 + 32 + 8 : 0);
+  for (const val of trailing) {
+    size += val === null ? 1 : 9;
+  }
+  const data = Buffer.alloc(size);
   data.writeUInt8(InstructionType.DepositRewards, 0);
   data.writeBigUInt64LE(amount, 1);
+  let offset = 9;
+  for (const val of trailing) {
+    if (val === null) {
+      data.writeUInt8(0, offset); offset += 1;
+    } else {
+      data.writeUInt8(1, offset); offset += 1;
+      data.writeBigUInt64LE(val, offset); offset += 8;
+    }
+  }
+  if (referral !== null) {
+    data.writeUInt8(1, offset); offset += 1;
+    referral.referrer.toBuffer().copy(data, offset); offset += 32;
+    data.writeBigUInt64LE(referral.referralBps, offset); offset += 8;
+  }
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: depositor, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  if (allowlistEntry) {
+    // Required when the pool requires allowlisted depositors
+    keys.push({ pubkey: allowlistEntry, isSigner: false, isWritable: false });
+  }
+  if (depositorStake) {
+    // Required when the pool excludes depositors from their own deposits
+    keys.push({ pubkey: depositorStake, isSigner: false, isWritable: true });
+  }
+  if (auditLogPage) {
+    // Current audit log page, required when the pool keeps an audit log
+    keys.push({ pubkey: auditLogPage, isSigner: false, isWritable: true });
+  }
+  if (referral) {
+    // Receives the referral cut
+    keys.push({ pubkey: referral.referrer, isSigner: false, isWritable: true });
+  }
 
   return new TransactionInstruction({
-    keys: [
-      { pubkey: pool, isSigner: false, isWritable: true },
-      { pubkey: depositor, isSigner: true, isWritable: true },
-      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
-    ],
+    keys,
     programId: PROGRAM_ID,
     data,
   });
@@ -244,14 +504,28 @@ function createSyncPoolInstruction(pool: PublicKey): TransactionInstruction {
   });
 }
 
-function createSyncRewardsInstruction(pool: PublicKey): TransactionInstruction {
+function createSyncRewardsInstruction(
+  pool: PublicKey,
+  auditLogPage: PublicKey | null = null,
+  payer: PublicKey | null = null,
+): TransactionInstruction {
   const data = Buffer.alloc(1);
   data.writeUInt8(InstructionType.SyncRewards, 0);
 
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+  ];
+  if (auditLogPage) {
+    // Current audit log page; payer + system program create it when needed
+    keys.push({ pubkey: auditLogPage, isSigner: false, isWritable: true });
+    if (payer) {
+      keys.push({ pubkey: payer, isSigner: true, isWritable: true });
+      keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+    }
+  }
+
   return new TransactionInstruction({
-    keys: [
-      { pubkey: pool, isSigner: false, isWritable: true },
-    ],
+    keys,
     programId: PROGRAM_ID,
     data,
   });
@@ -293,39 +567,94 @@ function createFixStakeAccountInstruction(
   });
 }
 
+// Settings appended to UpdatePoolSettings after the original three.
+// Older clients omit them entirely; the program decodes missing bytes as None.
+interface PoolSettingsExtras {
+  maxDeferredLamports?: bigint | null;
+  loyaltyBonusBps?: bigint | null;
+  excludeDepositor?: boolean | null;
+  perStakeTerms?: boolean | null;
+  claimFeeBps?: bigint | null;
+  unstakeReversalWindowSeconds?: bigint | null;
+  minDistributablePerEvent?: bigint | null;
+  maxTotalStaked?: bigint | null;
+  minRewardDeposit?: bigint | null;
+  earlyExitPenaltyBps?: bigint | null;
+  lockBoostBps?: bigint | null;
+  requireAllowlistedDepositors?: boolean | null;
+  settingsTimelockSeconds?: bigint | null;
+}
+
 function createUpdatePoolSettingsInstruction(
   pool: PublicKey,
   authority: PublicKey,
   minStakeAmount: bigint | null,
   lockDurationSeconds: bigint | null,
   unstakeCooldownSeconds: bigint | null,
+  extras: PoolSettingsExtras = {},
 ): TransactionInstruction {
   // Borsh serialization: enum variant (u8) + 3x Option<u64>
-  // Option<u64> = 1 byte tag (0=None, 1=Some) + 8 bytes value if Some
+  // [+ trailing Option<u64> max_deferred_lamports, Option<u64> loyalty_bonus_bps,
+  //  Option<bool> exclude_depositor, Option<bool> per_stake_terms,
+  //  Option<u64> claim_fee_bps, Option<u64> unstake_reversal_window_seconds,
+  //  Option<u64> min_distributable_per_event, Option<u64> max_total_staked,
+  //  Option<u64> min_reward_deposit, Option<u64> early_exit_penalty_bps,
+  //  Option<u64> lock_boost_bps, Option<bool> require_allowlisted_depositors,
+  //  Option<u64> settings_timelock_seconds
+  //  — omitted when all unset]
+  // Option<T> = 1 byte tag (0=None, 1=Some) + value if Some
+  const u64s = [minStakeAmount, lockDurationSeconds, unstakeCooldownSeconds];
+  const trailing: (bigint | boolean | null)[] = [
+    extras.maxDeferredLamports ?? null,
+    extras.loyaltyBonusBps ?? null,
+    extras.excludeDepositor ?? null,
+    extras.perStakeTerms ?? null,
+    extras.claimFeeBps ?? null,
+    extras.unstakeReversalWindowSeconds ?? null,
+    extras.minDistributablePerEvent ?? null,
+    extras.maxTotalStaked ?? null,
+    extras.minRewardDeposit ?? null,
+    extras.earlyExitPenaltyBps ?? null,
+    extras.lockBoostBps ?? null,
+    extras.requireAllowlistedDepositors ?? null,
+    extras.settingsTimelockSeconds ?? null,
+  ];
+  const withTrailing = trailing.some(v => v !== null);
+  const fields: (bigint | boolean | null)[] = withTrailing ? [...u64s, ...trailing] : u64s;
+
   let size = 1; // variant
-  size += 1 + (minStakeAmount !== null ? 8 : 0);
-  size += 1 + (lockDurationSeconds !== null ? 8 : 0);
-  size += 1 + (unstakeCooldownSeconds !== null ? 8 : 0);
+  for (const val of fields) {
+    size += 1 + (val === null ? 0 : typeof val === 'boolean' ? 1 : 8);
+  }
 
   const data = Buffer.alloc(size);
   let offset = 0;
   data.writeUInt8(InstructionType.UpdatePoolSettings, offset); offset += 1;
 
-  // Write Option<u64> for each
-  for (const val of [minStakeAmount, lockDurationSeconds, unstakeCooldownSeconds]) {
-    if (val !== null) {
+  // Write Option<T> for each
+  for (const val of fields) {
+    if (val === null) {
+      data.writeUInt8(0, offset); offset += 1;
+    } else if (typeof val === 'boolean') {
       data.writeUInt8(1, offset); offset += 1;
-      data.writeBigUInt64LE(val, offset); offset += 8;
+      data.writeUInt8(val ? 1 : 0, offset); offset += 1;
     } else {
-      data.writeUInt8(0, offset); offset += 1;
+      data.writeUInt8(1, offset); offset += 1;
+      data.writeBigUInt64LE(val, offset); offset += 8;
     }
   }
 
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: authority, isSigner: true, isWritable: withTrailing },
+  ];
+  if (withTrailing) {
+    // Legacy pools grow to hold the new settings (authority pays)
+    keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+  }
+
   return new TransactionInstruction({
-    keys: [
-      { pubkey: pool, isSigner: false, isWritable: true },
-      { pubkey: authority, isSigner: true, isWritable: false },
-    ],
+    keys,
     programId: PROGRAM_ID,
     data,
   });
@@ -399,6 +728,33 @@ function createCompleteUnstakeInstruction(
   });
 }
 
+function createForceUnstakeQuarantinedInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  tokenVault: PublicKey,
+  userToken: PublicKey,
+  mint: PublicKey,
+  user: PublicKey,
+  tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.ForceUnstakeQuarantined, 0);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: tokenVault, isSigner: false, isWritable: true },
+      { pubkey: userToken, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: user, isSigner: true, isWritable: true },
+      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
 function createCancelUnstakeRequestInstruction(
   pool: PublicKey,
   userStake: PublicKey,
@@ -474,6 +830,7 @@ function createCloseStakeAccountInstruction(
   userStake: PublicKey,
   user: PublicKey,
   metadataPDA?: PublicKey,
+  defaultStakePDA?: PublicKey,
 ): TransactionInstruction {
   const data = Buffer.alloc(1);
   data.writeUInt8(InstructionType.CloseStakeAccount, 0);
@@ -486,6 +843,10 @@ function createCloseStakeAccountInstruction(
   if (metadataPDA) {
     keys.push({ pubkey: metadataPDA, isSigner: false, isWritable: true });
   }
+  // Closing a position above index 0 updates the default stake account's count
+  if (defaultStakePDA) {
+    keys.push({ pubkey: defaultStakePDA, isSigner: false, isWritable: true });
+  }
 
   return new TransactionInstruction({
     keys,
@@ -564,1659 +925,6987 @@ function createStakeWithMetadataInstruction(
   });
 }
 
-// Helper to read u128 little-endian from a Buffer
-function readU128LE(buf: Buffer, offset: number): bigint {
-  const lo = buf.readBigUInt64LE(offset);
-  const hi = buf.readBigUInt64LE(offset + 8);
-  return lo + (hi << 64n);
-}
+function createVerifyVaultConsistencyInstruction(
+  pool: PublicKey,
+  tokenVault: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.VerifyVaultConsistency, 0);
 
-// Decoded pool state fields relevant to reward accounting
-interface PoolState {
-  totalStaked: bigint;
-  accRewardPerWeightedShare: bigint;
-  lastSyncedLamports: bigint;
-  totalRewardDebt: bigint;
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: tokenVault, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
 }
 
-// Decoded user stake fields relevant to reward testing
-interface UserStakeState {
-  amount: bigint;
-  expStartFactor: bigint;
-  rewardDebt: bigint;
-  totalRewardsClaimed: bigint;
-  claimedRewardsWad: bigint;
+function createClaimToBalanceInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.ClaimToBalance, 0);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: user, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
 }
 
-// Test context
-class TestContext {
-  connection: Connection;
-  payer: Keypair;
-  programAuthority: Keypair;
-  mint!: PublicKey;
-  mintAuthority: Keypair;
-  poolPDA!: PublicKey;
-  tokenVaultPDA!: PublicKey;
-  tokenProgramId: PublicKey;
+function createWithdrawClaimedInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.WithdrawClaimed, 0);
 
-  constructor(connection: Connection, payer: Keypair, programAuthority: Keypair, tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID) {
-    this.connection = connection;
-    this.payer = payer;
-    this.programAuthority = programAuthority;
-    this.tokenProgramId = tokenProgramId;
-    this.mintAuthority = this.payer; // Pool creator must be mint authority
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: user, isSigner: true, isWritable: true },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async setup() {
-    // Airdrop SOL to payer
-    const airdropSig = await this.connection.requestAirdrop(
-      this.payer.publicKey,
-      10 * LAMPORTS_PER_SOL
-    );
-    await this.connection.confirmTransaction(airdropSig);
-  }
+function createGrandfatherStakeInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  authority: PublicKey,
+  effectiveStakeTime: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.GrandfatherStake, 0);
+  data.writeBigInt64LE(effectiveStakeTime, 1);
 
-  async createMint(decimals: number = 9): Promise<PublicKey> {
-    this.mint = await createMint(
-      this.connection,
-      this.payer,
-      this.mintAuthority.publicKey,
-      null,
-      decimals,
-      undefined,
-      undefined,
-      this.tokenProgramId
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    [this.poolPDA] = derivePoolPDA(this.mint);
-    [this.tokenVaultPDA] = deriveTokenVaultPDA(this.poolPDA);
+function createSplitStakeInstruction(
+  pool: PublicKey,
+  sourceStake: PublicKey,
+  newStake: PublicKey,
+  owner: PublicKey,
+  newOwner: PublicKey,
+  amount: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.SplitStake, 0);
+  data.writeBigUInt64LE(amount, 1);
 
-    return this.mint;
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: sourceStake, isSigner: false, isWritable: true },
+      { pubkey: newStake, isSigner: false, isWritable: true },
+      { pubkey: owner, isSigner: true, isWritable: true },
+      { pubkey: newOwner, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async initializePool(tauSeconds: bigint): Promise<string> {
-    const ix = createInitializePoolInstruction(
-      this.poolPDA,
-      this.mint,
-      this.tokenVaultPDA,
-      this.payer.publicKey,
-      tauSeconds,
-      this.tokenProgramId,
-    );
+function createQueryClaimableAtInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  futureTimestamp: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.QueryClaimableAt, 0);
+  data.writeBigInt64LE(futureTimestamp, 1);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async createUserTokenAccount(owner: PublicKey): Promise<PublicKey> {
-    return await createAccount(
-      this.connection,
-      this.payer,
-      this.mint,
-      owner,
-      undefined,
-      undefined,
-      this.tokenProgramId
-    );
-  }
+function createCollectProtocolFeesInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  recipient: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.CollectProtocolFees, 0);
 
-  async mintTokens(destination: PublicKey, amount: bigint): Promise<string> {
-    return await mintTo(
-      this.connection,
-      this.payer,
-      this.mint,
-      destination,
-      this.mintAuthority,
-      amount,
-      undefined,
-      undefined,
-      this.tokenProgramId
-    );
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+      { pubkey: recipient, isSigner: false, isWritable: true },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async stake(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+function createQuarantinePoolInstruction(
+  pool: PublicKey,
+  mint: PublicKey,
+  payer: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.QuarantinePool, 0);
 
-    const ix = createStakeInstruction(
-      this.poolPDA,
-      userStakePDA,
-      this.tokenVaultPDA,
-      userToken,
-      this.mint,
-      user.publicKey,
-      amount,
-      this.tokenProgramId,
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
-  }
+function createViewPoolHealthInstruction(
+  pool: PublicKey,
+  tokenVault: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.ViewPoolHealth, 0);
 
-  async stakeOnBehalf(staker: Keypair, stakerToken: PublicKey, beneficiary: PublicKey, amount: bigint): Promise<string> {
-    const [beneficiaryStakePDA] = deriveUserStakePDA(this.poolPDA, beneficiary);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: tokenVault, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const ix = createStakeOnBehalfInstruction(
-      this.poolPDA,
-      beneficiaryStakePDA,
-      this.tokenVaultPDA,
-      stakerToken,
-      this.mint,
-      staker.publicKey,
-      beneficiary,
-      amount,
-      undefined,
-      this.tokenProgramId,
-    );
+// ViewPoolHealth bits (view_pool_health.rs)
+const HEALTH_VAULT_MATCHES_TOTAL_STAKED = 1 << 0;
+const HEALTH_RENT_OK = 1 << 1;
+const HEALTH_LAST_SYNCED_CONSISTENT = 1 << 2;
+const HEALTH_QUARANTINED = 1 << 3;
+const HEALTH_PAUSED = 1 << 4;
+const HEALTH_ALL_GREEN = HEALTH_VAULT_MATCHES_TOTAL_STAKED | HEALTH_RENT_OK | HEALTH_LAST_SYNCED_CONSISTENT;
+
+function createBatchRequestUnstakeInstruction(
+  owner: PublicKey,
+  stakes: { pool: PublicKey; userStake: PublicKey }[],
+  failOnPending: boolean,
+): TransactionInstruction {
+  // Borsh: enum variant (u8) + bool
+  const data = Buffer.from([InstructionType.BatchRequestUnstake, failOnPending ? 1 : 0]);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, staker]);
+  const keys = [
+    { pubkey: owner, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  for (const { pool, userStake } of stakes) {
+    keys.push({ pubkey: pool, isSigner: false, isWritable: true });
+    keys.push({ pubkey: userStake, isSigner: false, isWritable: true });
   }
 
-  async unstake(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const ix = createUnstakeInstruction(
-      this.poolPDA,
-      userStakePDA,
-      this.tokenVaultPDA,
-      userToken,
-      this.mint,
-      user.publicKey,
-      amount,
-      this.tokenProgramId,
-    );
+function createQueryIsCompletableBatchInstruction(
+  stakes: { pool: PublicKey; userStake: PublicKey }[],
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryIsCompletableBatch]);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  const keys = [];
+  for (const { pool, userStake } of stakes) {
+    keys.push({ pubkey: pool, isSigner: false, isWritable: false });
+    keys.push({ pubkey: userStake, isSigner: false, isWritable: false });
   }
 
-  async claimRewards(user: Keypair): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
-
-    const ix = createClaimRewardsInstruction(
-      this.poolPDA,
-      userStakePDA,
-      user.publicKey
-    );
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+async function queryIsCompletableBatch(
+  connection: Connection,
+  payer: Keypair,
+  stakes: { pool: PublicKey; userStake: PublicKey }[],
+): Promise<number> {
+  const tx = new Transaction().add(createQueryIsCompletableBatchInstruction(stakes));
+  tx.feePayer = payer.publicKey;
+  tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+  tx.sign(payer);
+
+  const simulation = await connection.simulateTransaction(tx);
+  if (simulation.value.err) {
+    throw new Error(`QueryIsCompletableBatch failed: ${JSON.stringify(simulation.value.err)}`);
   }
-
-  async depositRewards(amount: bigint): Promise<string> {
-    const ix = createDepositRewardsInstruction(
-      this.poolPDA,
-      this.payer.publicKey,
-      amount
-    );
-
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  const returnData = simulation.value.returnData;
+  if (!returnData) {
+    throw new Error('QueryIsCompletableBatch returned no data');
   }
+  return Buffer.from(returnData.data[0], 'base64').readUInt32LE(0);
+}
 
-  async syncPool(): Promise<string> {
-    const ix = createSyncPoolInstruction(this.poolPDA);
+function createReinitializeVaultInstruction(
+  pool: PublicKey,
+  tokenVault: PublicKey,
+  mint: PublicKey,
+  authority: PublicKey,
+  tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ReinitializeVault]);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: tokenVault, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async syncRewards(): Promise<string> {
-    const ix = createSyncRewardsInstruction(this.poolPDA);
+function createDistributeBonusInstruction(
+  pool: PublicKey,
+  funder: PublicKey,
+  bonuses: { userStake: PublicKey; amount: bigint }[],
+): TransactionInstruction {
+  // Borsh: enum variant (u8) + Vec<u64> (u32 length + u64 LE each)
+  const data = Buffer.alloc(1 + 4 + 8 * bonuses.length);
+  data.writeUInt8(InstructionType.DistributeBonus, 0);
+  data.writeUInt32LE(bonuses.length, 1);
+  bonuses.forEach(({ amount }, i) => data.writeBigUInt64LE(amount, 5 + 8 * i));
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: funder, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  for (const { userStake } of bonuses) {
+    keys.push({ pubkey: userStake, isSigner: false, isWritable: true });
   }
 
-  async fixStakeAccount(authority: Keypair, user: PublicKey, newExpStartFactor: bigint, newRewardDebt: bigint): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
-    const ix = createFixStakeAccountInstruction(
-      this.poolPDA,
-      userStakePDA,
-      authority.publicKey,
-      newExpStartFactor,
-      newRewardDebt,
-    );
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    const signers = authority === this.payer ? [this.payer] : [this.payer, authority];
-    return await sendAndConfirmTransaction(this.connection, tx, signers);
-  }
+function createClaimForInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  owner: PublicKey,
+  caller: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ClaimFor]);
 
-  async sendSolToPool(amount: bigint): Promise<string> {
-    const ix = SystemProgram.transfer({
-      fromPubkey: this.payer.publicKey,
-      toPubkey: this.poolPDA,
-      lamports: amount,
-    });
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: owner, isSigner: false, isWritable: true },
+      { pubkey: caller, isSigner: true, isWritable: true },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
-  }
+function createSetClaimForOptInInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  owner: PublicKey,
+  enabled: boolean,
+): TransactionInstruction {
+  // Borsh: enum variant (u8) + bool
+  const data = Buffer.from([InstructionType.SetClaimForOptIn, enabled ? 1 : 0]);
 
-  async updatePoolSettings(
-    authority: Keypair,
-    minStakeAmount: bigint | null,
-    lockDurationSeconds: bigint | null,
-    unstakeCooldownSeconds: bigint | null,
-  ): Promise<string> {
-    const ix = createUpdatePoolSettingsInstruction(
-      this.poolPDA,
-      authority.publicKey,
-      minStakeAmount,
-      lockDurationSeconds,
-      unstakeCooldownSeconds,
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: owner, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
-  }
+function createSetRewardFeeSplitInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  splits: { recipient: PublicKey; bps: number }[],
+): TransactionInstruction {
+  // Borsh: enum variant (u8) + Vec<(Pubkey, u16)> (u32 length prefix)
+  const data = Buffer.alloc(1 + 4 + splits.length * 34);
+  data.writeUInt8(InstructionType.SetRewardFeeSplit, 0);
+  data.writeUInt32LE(splits.length, 1);
+  splits.forEach(({ recipient, bps }, i) => {
+    recipient.toBuffer().copy(data, 5 + i * 34);
+    data.writeUInt16LE(bps, 5 + i * 34 + 32);
+  });
 
-  async transferAuthority(currentAuthority: Keypair, newAuthority: PublicKey): Promise<string> {
-    const ix = createTransferAuthorityInstruction(
-      this.poolPDA,
-      currentAuthority.publicKey,
-      newAuthority,
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, currentAuthority]);
-  }
+function createExtendPoolAccountInstruction(pool: PublicKey, payer: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ExtendPoolAccount]);
 
-  async requestUnstake(user: Keypair, amount: bigint): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const ix = createRequestUnstakeInstruction(
-      this.poolPDA,
-      userStakePDA,
-      user.publicKey,
-      amount,
-    );
+function createReverseUnstakeInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  tokenVault: PublicKey,
+  userToken: PublicKey,
+  mint: PublicKey,
+  user: PublicKey,
+  tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ReverseUnstake]);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: tokenVault, isSigner: false, isWritable: true },
+      { pubkey: userToken, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: user, isSigner: true, isWritable: false },
+      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createQueryMinStakeUiInstruction(pool: PublicKey, mint?: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryMinStakeUi]);
+  const keys = [{ pubkey: pool, isSigner: false, isWritable: false }];
+  if (mint) {
+    keys.push({ pubkey: mint, isSigner: false, isWritable: false });
   }
 
-  async completeUnstake(user: Keypair, userToken: PublicKey): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+  return new TransactionInstruction({ keys, programId: PROGRAM_ID, data });
+}
 
-    const ix = createCompleteUnstakeInstruction(
-      this.poolPDA,
-      userStakePDA,
-      this.tokenVaultPDA,
-      userToken,
-      this.mint,
-      user.publicKey,
-      this.tokenProgramId,
-    );
+function createForceSyncWithBalanceInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  expectedBalance: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.ForceSyncWithBalance, 0);
+  data.writeBigUInt64LE(expectedBalance, 1);
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async cancelUnstakeRequest(user: Keypair): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+function createSetUnstakeDestinationPolicyInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  policy: UnstakeDestinationPolicy,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.SetUnstakeDestinationPolicy, policy]);
 
-    const ix = createCancelUnstakeRequestInstruction(
-      this.poolPDA,
-      userStakePDA,
-      user.publicKey,
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
-  }
+function createLockPoolSettingsInstruction(pool: PublicKey, authority: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.LockPoolSettings]);
 
-  async fixTotalRewardDebt(newDebt: bigint): Promise<string> {
-    const ix = createFixTotalRewardDebtInstruction(this.poolPDA, this.programAuthority.publicKey, newDebt);
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.programAuthority]);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createSetRewardPrecisionInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  shift: number,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.SetRewardPrecision, shift]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createClaimAndCloseIfEmptyInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey,
+  metadataPDA?: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ClaimAndCloseIfEmpty]);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: userStake, isSigner: false, isWritable: true },
+    { pubkey: user, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  if (metadataPDA) {
+    keys.push({ pubkey: metadataPDA, isSigner: false, isWritable: true });
   }
 
-  async createMintWithMetadata(decimals: number, tokenName: string, tokenSymbol: string): Promise<PublicKey> {
-    const mintKeypair = Keypair.generate();
-    this.mint = mintKeypair.publicKey;
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    // Calculate space: mint base + MetadataPointer extension
-    const mintLen = getMintLen([ExtensionType.MetadataPointer]);
+function createQueryRewardRateInstruction(pool: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryRewardRate]);
 
-    // Metadata to be stored in the mint (variable-length TLV)
-    const metadataData = {
-      mint: this.mint,
-      name: tokenName,
-      symbol: tokenSymbol,
-      uri: '',
-      updateAuthority: this.mintAuthority.publicKey,
-      additionalMetadata: [] as [string, string][],
-    };
-    const metadataExtensionLen = TYPE_SIZE + LENGTH_SIZE + pack(metadataData).length;
-    const totalLen = mintLen + metadataExtensionLen;
+  return new TransactionInstruction({
+    keys: [{ pubkey: pool, isSigner: false, isWritable: false }],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    // Allocate lamports for the full size (mint + metadata), but only
-    // set space to mintLen. Token 2022 will extend the account when
-    // the metadata is initialized (it uses the excess lamports for rent).
-    const lamports = await this.connection.getMinimumBalanceForRentExemption(totalLen);
+function createSetDisplayUnitInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  label: string,
+  decimals: number,
+): TransactionInstruction {
+  // variant (u8) + label (Borsh String: u32 length + UTF-8) + decimals (u8)
+  const labelBytes = Buffer.from(label, 'utf8');
+  const data = Buffer.alloc(1 + 4 + labelBytes.length + 1);
+  data.writeUInt8(InstructionType.SetDisplayUnit, 0);
+  data.writeUInt32LE(labelBytes.length, 1);
+  labelBytes.copy(data, 5);
+  data.writeUInt8(decimals, 5 + labelBytes.length);
 
-    const tx = new Transaction().add(
-      // Create account with space for mint + MetadataPointer only
-      SystemProgram.createAccount({
-        fromPubkey: this.payer.publicKey,
-        newAccountPubkey: this.mint,
-        space: mintLen,
-        lamports,
-        programId: TOKEN_2022_PROGRAM_ID,
-      }),
-      // Initialize MetadataPointer (points to the mint itself)
-      createInitializeMetadataPointerInstruction(
-        this.mint,
-        this.mintAuthority.publicKey,
-        this.mint,
-        TOKEN_2022_PROGRAM_ID,
-      ),
-      // Initialize the mint
-      createInitializeMintInstruction(
-        this.mint,
-        decimals,
-        this.mintAuthority.publicKey,
-        null,
-        TOKEN_2022_PROGRAM_ID,
-      ),
-      // Initialize token metadata
-      createInitializeInstruction({
-        programId: TOKEN_2022_PROGRAM_ID,
-        mint: this.mint,
-        metadata: this.mint,
-        mintAuthority: this.mintAuthority.publicKey,
-        name: tokenName,
-        symbol: tokenSymbol,
-        uri: '',
-        updateAuthority: this.mintAuthority.publicKey,
-      }),
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    await sendAndConfirmTransaction(this.connection, tx, [this.payer, mintKeypair, this.mintAuthority]);
+function createQueryDisplayUnitInstruction(pool: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryDisplayUnit]);
 
-    [this.poolPDA] = derivePoolPDA(this.mint);
-    [this.tokenVaultPDA] = deriveTokenVaultPDA(this.poolPDA);
+  return new TransactionInstruction({
+    keys: [{ pubkey: pool, isSigner: false, isWritable: false }],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    return this.mint;
-  }
+function createQueryPendingUnstakeInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryPendingUnstake]);
 
-  async setPoolMetadata(payer?: Keypair): Promise<string> {
-    const effectivePayer = payer || this.payer;
-    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const ix = createSetPoolMetadataInstruction(
-      this.poolPDA,
-      metadataPDA,
-      this.mint,
-      effectivePayer.publicKey,
-    );
+function createQueryTimeToUnlockInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryTimeToUnlock]);
 
-    const tx = new Transaction().add(ix);
-    const signers = effectivePayer === this.payer ? [this.payer] : [this.payer, effectivePayer];
-    return await sendAndConfirmTransaction(this.connection, tx, signers);
-  }
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-  async stakeWithMetadata(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
-    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+function createQueryEffectiveSettingsInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryEffectiveSettings]);
 
-    const ix = createStakeWithMetadataInstruction(
-      this.poolPDA,
-      userStakePDA,
-      this.tokenVaultPDA,
-      userToken,
-      this.mint,
-      user.publicKey,
-      amount,
-      metadataPDA,
-      this.tokenProgramId,
-    );
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
-  }
+function createQueryAccumulatorSnapshotInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryAccumulatorSnapshot]);
 
-  async closeStakeAccount(user: Keypair, withMetadata: boolean = false): Promise<string> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
-    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    const ix = createCloseStakeAccountInstruction(
-      this.poolPDA,
-      userStakePDA,
-      user.publicKey,
-      withMetadata ? metadataPDA : undefined,
-    );
+// TERMS_FLAG_* bits returned by QueryEffectiveSettings (state.rs)
+const TERMS_FLAG_STAMPED = 1 << 0;
+const TERMS_FLAG_GRANDFATHERED = 1 << 1;
+const TERMS_FLAG_LOCK_COMMITTED = 1 << 2;
 
-    const tx = new Transaction().add(ix);
-    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
-  }
+function createQueryWeightedShareInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.QueryWeightedShare]);
 
-  async readMetadata(): Promise<{
-    pool: PublicKey;
-    nameLen: number;
-    name: string;
-    numTags: number;
-    tags: string[];
-    urlLen: number;
-    url: string;
-    memberCount: bigint;
-    bump: number;
-  }> {
-    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
-    const info = await this.connection.getAccountInfo(metadataPDA);
-    if (!info) throw new Error('Metadata account not found');
-    const data = info.data;
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
 
-    // Parse PoolMetadata from Borsh layout:
-    // discriminator: [u8; 8] = 8
-    // pool: Pubkey = 32
-    // name_len: u8 = 1
-    // name: [u8; 64] = 64
-    // num_tags: u8 = 1
-    // tag_lengths: [u8; 8] = 8
-    // tags: [[u8; 32]; 8] = 256
-    // url_len: u8 = 1
-    // url: [u8; 128] = 128
-    // member_count: u64 = 8
-    // bump: u8 = 1
-    let offset = 8; // skip discriminator
-    const pool = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
-    const nameLen = data[offset]; offset += 1;
-    const name = data.subarray(offset, offset + nameLen).toString('utf8'); offset += 64;
-    const numTags = data[offset]; offset += 1;
-    const tagLengths = Array.from(data.subarray(offset, offset + 8)); offset += 8;
-    const tags: string[] = [];
-    for (let i = 0; i < numTags; i++) {
-      const tagData = data.subarray(offset + i * 32, offset + i * 32 + tagLengths[i]);
-      tags.push(tagData.toString('utf8'));
-    }
-    offset += 256;
-    const urlLen = data[offset]; offset += 1;
-    const url = data.subarray(offset, offset + urlLen).toString('utf8'); offset += 128;
-    const memberCount = data.readBigUInt64LE(offset); offset += 8;
-    const bump = data[offset];
+function createSetDelegateInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  delegate: PublicKey,
+  permissions: number,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 32 + 1);
+  data.writeUInt8(InstructionType.SetDelegate, 0);
+  delegate.toBuffer().copy(data, 1);
+  data.writeUInt8(permissions, 33);
 
-    return { pool, nameLen, name, numTags, tags, urlLen, url, memberCount, bump };
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createVerifyPfeeOwnershipInstruction(pool: PublicKey, sharingConfig: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.VerifyPfeeOwnership]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: sharingConfig, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createGetPendingRewardsInstruction(pool: PublicKey, userStake: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.GetPendingRewards]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createClaimRewardsToInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey,
+  recipient: PublicKey,
+  feeRecipients: PublicKey[] = [],
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.ClaimRewardsTo, 0);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: userStake, isSigner: false, isWritable: true },
+    { pubkey: user, isSigner: true, isWritable: true },
+    { pubkey: recipient, isSigner: false, isWritable: true },
+  ];
+  // Fee split recipients follow the (otherwise optional) system program
+  if (feeRecipients.length > 0) {
+    keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+    for (const feeRecipient of feeRecipients) {
+      keys.push({ pubkey: feeRecipient, isSigner: false, isWritable: true });
+    }
   }
 
-  async readPoolState(): Promise<PoolState> {
-    const info = await this.connection.getAccountInfo(this.poolPDA);
-    if (!info) throw new Error('Pool account not found');
-    const data = info.data;
-    // Offsets from Borsh serialization layout (state.rs):
-    // 136: total_staked (u128)
-    // 200: acc_reward_per_weighted_share (u128)
-    // 225: last_synced_lamports (u64)
-    // 265: total_reward_debt (u128)
-    return {
-      totalStaked: readU128LE(data, 136),
-      accRewardPerWeightedShare: readU128LE(data, 200),
-      lastSyncedLamports: BigInt(data.readBigUInt64LE(225)),
-      totalRewardDebt: readU128LE(data, 265),
-    };
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createTransferStakeInstruction(
+  pool: PublicKey,
+  sourceStake: PublicKey,
+  destStake: PublicKey,
+  owner: PublicKey,
+  newOwner: PublicKey,
+  feeRecipients: PublicKey[] = [],
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.TransferStake, 0);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: true },
+    { pubkey: sourceStake, isSigner: false, isWritable: true },
+    { pubkey: destStake, isSigner: false, isWritable: true },
+    { pubkey: owner, isSigner: true, isWritable: true },
+    { pubkey: newOwner, isSigner: false, isWritable: false },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  for (const recipient of feeRecipients) {
+    keys.push({ pubkey: recipient, isSigner: false, isWritable: true });
   }
 
-  async readUserStakeState(user: PublicKey): Promise<UserStakeState> {
-    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
-    const info = await this.connection.getAccountInfo(userStakePDA);
-    if (!info) throw new Error('User stake account not found');
-    const data = info.data;
-    // Offsets from Borsh serialization layout (state.rs UserStake):
-    // 0:   discriminator (8)
-    // 8:   owner (32)
-    // 40:  pool (32)
-    // 72:  amount (u64)
-    // 80:  stake_time (i64)
-    // 88:  exp_start_factor (u128)
-    // 104: reward_debt (u128)
-    // 120: bump (u8)
-    // 121: unstake_request_amount (u64)
-    // 129: unstake_request_time (i64)
-    // 137: last_stake_time (i64)
-    // 145: base_time_snapshot (i64)
-    // 153: total_rewards_claimed (u64) — may not exist on legacy 153-byte accounts
-    const amount = data.readBigUInt64LE(72);
-    const expStartFactor = readU128LE(data, 88);
-    const rewardDebt = readU128LE(data, 104);
-    const totalRewardsClaimed = data.length >= 161
-      ? data.readBigUInt64LE(153)
-      : 0n;
-    const claimedRewardsWad = data.length >= 177
-      ? readU128LE(data, 161)
-      : 0n;
-    return { amount, expStartFactor, rewardDebt, totalRewardsClaimed, claimedRewardsWad };
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createUpdateTauInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  tauSeconds: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(9);
+  data.writeUInt8(InstructionType.UpdateTau, 0);
+  data.writeBigUInt64LE(tauSeconds, 1);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createSetRewardMintInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  rewardMint: PublicKey,
+  rewardTokenProgram: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.SetRewardMint]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: rewardMint, isSigner: false, isWritable: false },
+      { pubkey: deriveRewardVaultPDA(pool)[0], isSigner: false, isWritable: true },
+      { pubkey: rewardTokenProgram, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createDepositTokenRewardsInstruction(
+  pool: PublicKey,
+  depositor: PublicKey,
+  depositorToken: PublicKey,
+  rewardMint: PublicKey,
+  rewardTokenProgram: PublicKey,
+  amount: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(9);
+  data.writeUInt8(InstructionType.DepositTokenRewards, 0);
+  data.writeBigUInt64LE(amount, 1);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: depositor, isSigner: true, isWritable: false },
+      { pubkey: depositorToken, isSigner: false, isWritable: true },
+      { pubkey: deriveRewardVaultPDA(pool)[0], isSigner: false, isWritable: true },
+      { pubkey: rewardMint, isSigner: false, isWritable: false },
+      { pubkey: rewardTokenProgram, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createClaimTokenRewardsInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey,
+  destination: PublicKey,
+  rewardMint: PublicKey,
+  rewardTokenProgram: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ClaimTokenRewards]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: user, isSigner: true, isWritable: true },
+      { pubkey: deriveRewardVaultPDA(pool)[0], isSigner: false, isWritable: true },
+      { pubkey: destination, isSigner: false, isWritable: true },
+      { pubkey: rewardMint, isSigner: false, isWritable: false },
+      { pubkey: rewardTokenProgram, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createRenounceAuthorityInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(1);
+  data.writeUInt8(InstructionType.RenounceAuthority, 0);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createSetPauseInstruction(pool: PublicKey, authority: PublicKey, paused: boolean): TransactionInstruction {
+  const data = Buffer.from([InstructionType.SetPause, paused ? 1 : 0]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createInstantUnstakeInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  tokenVault: PublicKey,
+  userToken: PublicKey,
+  mint: PublicKey,
+  user: PublicKey,
+  amount: bigint,
+  tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.InstantUnstake, 0);
+  data.writeBigUInt64LE(amount, 1);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: tokenVault, isSigner: false, isWritable: true },
+      { pubkey: userToken, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: user, isSigner: true, isWritable: true },
+      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createProposeAuthorityInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  newAuthority: PublicKey,
+): TransactionInstruction {
+  const data = Buffer.alloc(33);
+  data.writeUInt8(InstructionType.ProposeAuthority, 0);
+  newAuthority.toBuffer().copy(data, 1);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createAcceptAuthorityInstruction(pool: PublicKey, newAuthority: PublicKey): TransactionInstruction {
+  const data = Buffer.from([InstructionType.AcceptAuthority]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: newAuthority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createCancelAuthorityProposalInstruction(pool: PublicKey, authority: PublicKey): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.CancelAuthorityProposal]),
+  });
+}
+
+function createAmendUnstakeRequestInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  user: PublicKey,
+  amount: bigint,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 8);
+  data.writeUInt8(InstructionType.AmendUnstakeRequest, 0);
+  data.writeBigUInt64LE(amount, 1);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: false },
+      { pubkey: userStake, isSigner: false, isWritable: true },
+      { pubkey: user, isSigner: true, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createSyncRewardsBatchInstruction(pools: PublicKey[]): TransactionInstruction {
+  // Borsh serialize: enum variant (u8) + pool_count (u8)
+  const data = Buffer.from([InstructionType.SyncRewardsBatch, pools.length]);
+
+  return new TransactionInstruction({
+    keys: pools.map(pool => ({ pubkey: pool, isSigner: false, isWritable: true })),
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+// Simulate SyncRewardsBatch and return the number of pools it would sync
+async function simulateSyncRewardsBatch(
+  connection: Connection,
+  payer: Keypair,
+  pools: PublicKey[],
+): Promise<bigint> {
+  const tx = new Transaction().add(createSyncRewardsBatchInstruction(pools));
+  tx.feePayer = payer.publicKey;
+  tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+  tx.sign(payer);
+
+  const simulation = await connection.simulateTransaction(tx);
+  if (simulation.value.err) {
+    throw new Error(`SyncRewardsBatch failed: ${JSON.stringify(simulation.value.err)}`);
+  }
+  const returnData = simulation.value.returnData;
+  if (!returnData) {
+    throw new Error('SyncRewardsBatch returned no data');
+  }
+  return Buffer.from(returnData.data[0], 'base64').readBigUInt64LE(0);
+}
+
+function createDepositRewardsStreamedInstruction(
+  pool: PublicKey,
+  depositor: PublicKey,
+  amount: bigint,
+  durationSeconds: bigint,
+): TransactionInstruction {
+  // variant (u8) + amount (u64) + duration_seconds (u64)
+  const data = Buffer.alloc(1 + 8 + 8);
+  data.writeUInt8(InstructionType.DepositRewardsStreamed, 0);
+  data.writeBigUInt64LE(amount, 1);
+  data.writeBigUInt64LE(durationSeconds, 9);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: depositor, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createClosePoolInstruction(
+  pool: PublicKey,
+  tokenVault: PublicKey,
+  authority: PublicKey,
+  rentDestination: PublicKey,
+  tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID,
+): TransactionInstruction {
+  const data = Buffer.from([InstructionType.ClosePool]);
+
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: tokenVault, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+      { pubkey: rentDestination, isSigner: false, isWritable: true },
+      { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createInitializeUserStakeInstruction(
+  pool: PublicKey,
+  userStake: PublicKey,
+  owner: PublicKey,
+  index: number,
+): TransactionInstruction {
+  const data = Buffer.alloc(1 + 2);
+  data.writeUInt8(InstructionType.InitializeUserStake, 0);
+  data.writeUInt16LE(index, 1);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: false },
+    { pubkey: userStake, isSigner: false, isWritable: true },
+    { pubkey: owner, isSigner: true, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+  ];
+  // Above index 0 the default stake account counts the open positions
+  if (index > 0) {
+    keys.push({ pubkey: deriveUserStakePDA(pool, owner)[0], isSigner: false, isWritable: true });
   }
 
-  async getBalance(pubkey: PublicKey): Promise<number> {
-    return await this.connection.getBalance(pubkey);
-  }
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createGetPoolStatsInstruction(pool: PublicKey): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [{ pubkey: pool, isSigner: false, isWritable: false }],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.GetPoolStats]),
+  });
+}
+
+function createDepositorAllowlistInstruction(
+  variant: InstructionType.AddDepositor | InstructionType.RemoveDepositor,
+  pool: PublicKey,
+  allowlistEntry: PublicKey,
+  authority: PublicKey,
+  depositor: PublicKey,
+): TransactionInstruction {
+  // variant (u8) + depositor (Pubkey)
+  const data = Buffer.concat([Buffer.from([variant]), depositor.toBuffer()]);
+
+  const keys = [
+    { pubkey: pool, isSigner: false, isWritable: false },
+    { pubkey: allowlistEntry, isSigner: false, isWritable: true },
+    { pubkey: authority, isSigner: true, isWritable: true },
+  ];
+  if (variant === InstructionType.AddDepositor) {
+    keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId: PROGRAM_ID,
+    data,
+  });
+}
+
+function createApplySettingsInstruction(pool: PublicKey, authority: PublicKey): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.ApplySettings]),
+  });
+}
+
+function createQueryPendingSettingsInstruction(pool: PublicKey): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [{ pubkey: pool, isSigner: false, isWritable: false }],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.QueryPendingSettings]),
+  });
+}
+
+function createReclaimDeferredRewardsInstruction(
+  pool: PublicKey,
+  authority: PublicKey,
+  recipient: PublicKey,
+): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: authority, isSigner: true, isWritable: false },
+      { pubkey: recipient, isSigner: false, isWritable: true },
+    ],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.ReclaimDeferredRewards]),
+  });
+}
+
+function createWithdrawFeeSplitInstruction(pool: PublicKey, recipient: PublicKey): TransactionInstruction {
+  return new TransactionInstruction({
+    keys: [
+      { pubkey: pool, isSigner: false, isWritable: true },
+      { pubkey: recipient, isSigner: false, isWritable: true },
+    ],
+    programId: PROGRAM_ID,
+    data: Buffer.from([InstructionType.WithdrawFeeSplit]),
+  });
+}
+
+// Helper to read u128 little-endian from a Buffer
+function readU128LE(buf: Buffer, offset: number): bigint {
+  const lo = buf.readBigUInt64LE(offset);
+  const hi = buf.readBigUInt64LE(offset + 8);
+  return lo + (hi << 64n);
+}
+
+// Decoded reward distribution audit log page
+interface AuditRecord {
+  timestamp: bigint;
+  amount: bigint;
+  rewardPerShare: bigint;
+  totalStaked: bigint;
+}
+
+interface AuditLogPageState {
+  page: number;
+  count: number;
+  records: AuditRecord[];
+}
+
+function deriveUserStakePositionPDA(pool: PublicKey, user: PublicKey, index: number): [PublicKey, number] {
+  if (index === 0) return deriveUserStakePDA(pool, user);
+  const indexBuf = Buffer.alloc(2);
+  indexBuf.writeUInt16LE(index, 0);
+  return PublicKey.findProgramAddressSync(
+    [STAKE_SEED, pool.toBuffer(), user.toBuffer(), indexBuf],
+    PROGRAM_ID
+  );
+}
+
+function deriveDepositorAllowlistPDA(pool: PublicKey, depositor: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [DEPOSITOR_SEED, pool.toBuffer(), depositor.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
+// Decoded pool state fields relevant to reward accounting
+interface PoolState {
+  totalStaked: bigint;
+  accRewardPerWeightedShare: bigint;
+  lastSyncedLamports: bigint;
+  totalRewardDebt: bigint;
+  accruedProtocolFees: bigint;
+}
+
+// Decoded user stake fields relevant to reward testing
+interface UserStakeState {
+  amount: bigint;
+  stakeTime: bigint;
+  expStartFactor: bigint;
+  rewardDebt: bigint;
+  totalRewardsClaimed: bigint;
+  claimedRewardsWad: bigint;
+  claimedBalance: bigint;
+  unstakeRequestAmount: bigint;
+  unstakeRequestTime: bigint;
+  lastStakeTime: bigint;
+  lockCommitSeconds: bigint;
+  referrer: PublicKey | null;
+}
+
+// Test context
+class TestContext {
+  connection: Connection;
+  payer: Keypair;
+  programAuthority: Keypair;
+  mint!: PublicKey;
+  mintAuthority: Keypair;
+  poolPDA!: PublicKey;
+  tokenVaultPDA!: PublicKey;
+  tokenProgramId: PublicKey;
+
+  constructor(connection: Connection, payer: Keypair, programAuthority: Keypair, tokenProgramId: PublicKey = TOKEN_2022_PROGRAM_ID) {
+    this.connection = connection;
+    this.payer = payer;
+    this.programAuthority = programAuthority;
+    this.tokenProgramId = tokenProgramId;
+    this.mintAuthority = this.payer; // Pool creator must be mint authority
+  }
+
+  async setup() {
+    // Airdrop SOL to payer
+    const airdropSig = await this.connection.requestAirdrop(
+      this.payer.publicKey,
+      10 * LAMPORTS_PER_SOL
+    );
+    await this.connection.confirmTransaction(airdropSig);
+  }
+
+  async createMint(decimals: number = 9): Promise<PublicKey> {
+    this.mint = await createMint(
+      this.connection,
+      this.payer,
+      this.mintAuthority.publicKey,
+      null,
+      decimals,
+      undefined,
+      undefined,
+      this.tokenProgramId
+    );
+
+    [this.poolPDA] = derivePoolPDA(this.mint);
+    [this.tokenVaultPDA] = deriveTokenVaultPDA(this.poolPDA);
+
+    return this.mint;
+  }
+
+  async initializePool(
+    tauSeconds: bigint,
+    enableAuditLog: boolean = false,
+    initialSettings: InitialPoolSettings | null = null,
+  ): Promise<string> {
+    const ix = createInitializePoolInstruction(
+      this.poolPDA,
+      this.mint,
+      this.tokenVaultPDA,
+      this.payer.publicKey,
+      tauSeconds,
+      this.tokenProgramId,
+      enableAuditLog ? deriveAuditLogPagePDA(this.poolPDA, 0)[0] : null,
+      initialSettings,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async createUserTokenAccount(owner: PublicKey): Promise<PublicKey> {
+    return await createAccount(
+      this.connection,
+      this.payer,
+      this.mint,
+      owner,
+      undefined,
+      undefined,
+      this.tokenProgramId
+    );
+  }
+
+  async mintTokens(destination: PublicKey, amount: bigint): Promise<string> {
+    return await mintTo(
+      this.connection,
+      this.payer,
+      this.mint,
+      destination,
+      this.mintAuthority,
+      amount,
+      undefined,
+      undefined,
+      this.tokenProgramId
+    );
+  }
+
+  async stake(user: Keypair, userToken: PublicKey, amount: bigint, options: StakeOptions = {}): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createStakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      amount,
+      this.tokenProgramId,
+      options,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async stakeOnBehalf(staker: Keypair, stakerToken: PublicKey, beneficiary: PublicKey, amount: bigint): Promise<string> {
+    const [beneficiaryStakePDA] = deriveUserStakePDA(this.poolPDA, beneficiary);
+
+    const ix = createStakeOnBehalfInstruction(
+      this.poolPDA,
+      beneficiaryStakePDA,
+      this.tokenVaultPDA,
+      stakerToken,
+      this.mint,
+      staker.publicKey,
+      beneficiary,
+      amount,
+      undefined,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, staker]);
+  }
+
+  async unstake(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createUnstakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      amount,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async claimRewards(
+    user: Keypair,
+    feeRecipients: PublicKey[] = [],
+    amount: bigint | null = null,
+  ): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createClaimRewardsInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      feeRecipients,
+      amount,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async depositRewards(
+    amount: bigint,
+    nonce: bigint | null = null,
+    displayValue: bigint | null = null,
+  ): Promise<string> {
+    const ix = createDepositRewardsInstruction(
+      this.poolPDA,
+      this.payer.publicKey,
+      amount,
+      null,
+      nonce,
+      await this.currentAuditLogPage(),
+      displayValue,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async syncPool(): Promise<string> {
+    const ix = createSyncPoolInstruction(this.poolPDA);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async syncRewards(): Promise<string> {
+    const ix = createSyncRewardsInstruction(
+      this.poolPDA,
+      await this.currentAuditLogPage(),
+      this.payer.publicKey,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async fixStakeAccount(authority: Keypair, user: PublicKey, newExpStartFactor: bigint, newRewardDebt: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const ix = createFixStakeAccountInstruction(
+      this.poolPDA,
+      userStakePDA,
+      authority.publicKey,
+      newExpStartFactor,
+      newRewardDebt,
+    );
+
+    const tx = new Transaction().add(ix);
+    const signers = authority === this.payer ? [this.payer] : [this.payer, authority];
+    return await sendAndConfirmTransaction(this.connection, tx, signers);
+  }
+
+  async sendSolToPool(amount: bigint): Promise<string> {
+    const ix = SystemProgram.transfer({
+      fromPubkey: this.payer.publicKey,
+      toPubkey: this.poolPDA,
+      lamports: amount,
+    });
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async updatePoolSettings(
+    authority: Keypair,
+    minStakeAmount: bigint | null,
+    lockDurationSeconds: bigint | null,
+    unstakeCooldownSeconds: bigint | null,
+    extras: PoolSettingsExtras = {},
+  ): Promise<string> {
+    const ix = createUpdatePoolSettingsInstruction(
+      this.poolPDA,
+      authority.publicKey,
+      minStakeAmount,
+      lockDurationSeconds,
+      unstakeCooldownSeconds,
+      extras,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async transferAuthority(currentAuthority: Keypair, newAuthority: PublicKey): Promise<string> {
+    const ix = createTransferAuthorityInstruction(
+      this.poolPDA,
+      currentAuthority.publicKey,
+      newAuthority,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, currentAuthority]);
+  }
+
+  async requestUnstake(user: Keypair, amount: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createRequestUnstakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      amount,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async completeUnstake(user: Keypair, userToken: PublicKey): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createCompleteUnstakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async forceUnstakeQuarantined(user: Keypair, userToken: PublicKey): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createForceUnstakeQuarantinedInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async cancelUnstakeRequest(user: Keypair): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createCancelUnstakeRequestInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async fixTotalRewardDebt(newDebt: bigint): Promise<string> {
+    const ix = createFixTotalRewardDebtInstruction(this.poolPDA, this.programAuthority.publicKey, newDebt);
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.programAuthority]);
+  }
+
+  async createMintWithMetadata(decimals: number, tokenName: string, tokenSymbol: string): Promise<PublicKey> {
+    const mintKeypair = Keypair.generate();
+    this.mint = mintKeypair.publicKey;
+
+    // Calculate space: mint base + MetadataPointer extension
+    const mintLen = getMintLen([ExtensionType.MetadataPointer]);
+
+    // Metadata to be stored in the mint (variable-length TLV)
+    const metadataData = {
+      mint: this.mint,
+      name: tokenName,
+      symbol: tokenSymbol,
+      uri: '',
+      updateAuthority: this.mintAuthority.publicKey,
+      additionalMetadata: [] as [string, string][],
+    };
+    const metadataExtensionLen = TYPE_SIZE + LENGTH_SIZE + pack(metadataData).length;
+    const totalLen = mintLen + metadataExtensionLen;
+
+    // Allocate lamports for the full size (mint + metadata), but only
+    // set space to mintLen. Token 2022 will extend the account when
+    // the metadata is initialized (it uses the excess lamports for rent).
+    const lamports = await this.connection.getMinimumBalanceForRentExemption(totalLen);
+
+    const tx = new Transaction().add(
+      // Create account with space for mint + MetadataPointer only
+      SystemProgram.createAccount({
+        fromPubkey: this.payer.publicKey,
+        newAccountPubkey: this.mint,
+        space: mintLen,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      // Initialize MetadataPointer (points to the mint itself)
+      createInitializeMetadataPointerInstruction(
+        this.mint,
+        this.mintAuthority.publicKey,
+        this.mint,
+        TOKEN_2022_PROGRAM_ID,
+      ),
+      // Initialize the mint
+      createInitializeMintInstruction(
+        this.mint,
+        decimals,
+        this.mintAuthority.publicKey,
+        null,
+        TOKEN_2022_PROGRAM_ID,
+      ),
+      // Initialize token metadata
+      createInitializeInstruction({
+        programId: TOKEN_2022_PROGRAM_ID,
+        mint: this.mint,
+        metadata: this.mint,
+        mintAuthority: this.mintAuthority.publicKey,
+        name: tokenName,
+        symbol: tokenSymbol,
+        uri: '',
+        updateAuthority: this.mintAuthority.publicKey,
+      }),
+    );
+
+    await sendAndConfirmTransaction(this.connection, tx, [this.payer, mintKeypair, this.mintAuthority]);
+
+    [this.poolPDA] = derivePoolPDA(this.mint);
+    [this.tokenVaultPDA] = deriveTokenVaultPDA(this.poolPDA);
+
+    return this.mint;
+  }
+
+  async setPoolMetadata(payer?: Keypair): Promise<string> {
+    const effectivePayer = payer || this.payer;
+    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+
+    const ix = createSetPoolMetadataInstruction(
+      this.poolPDA,
+      metadataPDA,
+      this.mint,
+      effectivePayer.publicKey,
+    );
+
+    const tx = new Transaction().add(ix);
+    const signers = effectivePayer === this.payer ? [this.payer] : [this.payer, effectivePayer];
+    return await sendAndConfirmTransaction(this.connection, tx, signers);
+  }
+
+  async stakeWithMetadata(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+
+    const ix = createStakeWithMetadataInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      amount,
+      metadataPDA,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async closeStakeAccount(user: Keypair, withMetadata: boolean = false): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+
+    const ix = createCloseStakeAccountInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      withMetadata ? metadataPDA : undefined,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async claimAndCloseIfEmpty(user: Keypair, withMetadata: boolean = false): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+
+    const ix = createClaimAndCloseIfEmptyInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      withMetadata ? metadataPDA : undefined,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async readMetadata(): Promise<{
+    pool: PublicKey;
+    nameLen: number;
+    name: string;
+    numTags: number;
+    tags: string[];
+    urlLen: number;
+    url: string;
+    memberCount: bigint;
+    bump: number;
+  }> {
+    const [metadataPDA] = deriveMetadataPDA(this.poolPDA);
+    const info = await this.connection.getAccountInfo(metadataPDA);
+    if (!info) throw new Error('Metadata account not found');
+    const data = info.data;
+
+    // Parse PoolMetadata from Borsh layout:
+    // discriminator: [u8; 8] = 8
+    // pool: Pubkey = 32
+    // name_len: u8 = 1
+    // name: [u8; 64] = 64
+    // num_tags: u8 = 1
+    // tag_lengths: [u8; 8] = 8
+    // tags: [[u8; 32]; 8] = 256
+    // url_len: u8 = 1
+    // url: [u8; 128] = 128
+    // member_count: u64 = 8
+    // bump: u8 = 1
+    let offset = 8; // skip discriminator
+    const pool = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+    const nameLen = data[offset]; offset += 1;
+    const name = data.subarray(offset, offset + nameLen).toString('utf8'); offset += 64;
+    const numTags = data[offset]; offset += 1;
+    const tagLengths = Array.from(data.subarray(offset, offset + 8)); offset += 8;
+    const tags: string[] = [];
+    for (let i = 0; i < numTags; i++) {
+      const tagData = data.subarray(offset + i * 32, offset + i * 32 + tagLengths[i]);
+      tags.push(tagData.toString('utf8'));
+    }
+    offset += 256;
+    const urlLen = data[offset]; offset += 1;
+    const url = data.subarray(offset, offset + urlLen).toString('utf8'); offset += 128;
+    const memberCount = data.readBigUInt64LE(offset); offset += 8;
+    const bump = data[offset];
+
+    return { pool, nameLen, name, numTags, tags, urlLen, url, memberCount, bump };
+  }
+
+  async readPoolState(): Promise<PoolState> {
+    const info = await this.connection.getAccountInfo(this.poolPDA);
+    if (!info) throw new Error('Pool account not found');
+    const data = info.data;
+    // Offsets from Borsh serialization layout (state.rs):
+    // 136: total_staked (u128)
+    // 200: acc_reward_per_weighted_share (u128)
+    // 225: last_synced_lamports (u64)
+    // 265: total_reward_debt (u128)
+    // 356: accrued_protocol_fees (u64, absent in smaller legacy pools)
+    return {
+      totalStaked: readU128LE(data, 136),
+      accRewardPerWeightedShare: readU128LE(data, 200),
+      lastSyncedLamports: BigInt(data.readBigUInt64LE(225)),
+      totalRewardDebt: readU128LE(data, 265),
+      accruedProtocolFees: data.length >= 364 ? data.readBigUInt64LE(356) : 0n,
+    };
+  }
+
+  async readUserStakeState(user: PublicKey): Promise<UserStakeState> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const info = await this.connection.getAccountInfo(userStakePDA);
+    if (!info) throw new Error('User stake account not found');
+    const data = info.data;
+    // Offsets from Borsh serialization layout (state.rs UserStake):
+    // 0:   discriminator (8)
+    // 8:   owner (32)
+    // 40:  pool (32)
+    // 72:  amount (u64)
+    // 80:  stake_time (i64)
+    // 88:  exp_start_factor (u128)
+    // 104: reward_debt (u128)
+    // 120: bump (u8)
+    // 121: unstake_request_amount (u64)
+    // 129: unstake_request_time (i64)
+    // 137: last_stake_time (i64)
+    // 145: base_time_snapshot (i64)
+    // 153: total_rewards_claimed (u64) — may not exist on legacy 153-byte accounts
+    // 161: claimed_rewards_wad (u128) — may not exist on legacy 161-byte accounts
+    // 177: claimed_balance (u64) — may not exist on v4 177-byte accounts
+    // 185: grandfathered (bool)
+    // 229: lock_commit_seconds (u64) — absent before StakeParams
+    // 237: referrer (Pubkey)
+    const amount = data.readBigUInt64LE(72);
+    const stakeTime = data.readBigInt64LE(80);
+    const expStartFactor = readU128LE(data, 88);
+    const rewardDebt = readU128LE(data, 104);
+    const totalRewardsClaimed = data.length >= 161
+      ? data.readBigUInt64LE(153)
+      : 0n;
+    const claimedRewardsWad = data.length >= 177
+      ? readU128LE(data, 161)
+      : 0n;
+    const claimedBalance = data.length >= 185
+      ? data.readBigUInt64LE(177)
+      : 0n;
+    const unstakeRequestAmount = data.readBigUInt64LE(121);
+    const unstakeRequestTime = data.readBigInt64LE(129);
+    const lastStakeTime = data.readBigInt64LE(137);
+    const lockCommitSeconds = data.length >= 269 ? data.readBigUInt64LE(229) : 0n;
+    const referrerKey = data.length >= 269 ? new PublicKey(data.subarray(237, 269)) : PublicKey.default;
+    return {
+      amount, stakeTime, expStartFactor, rewardDebt, totalRewardsClaimed, claimedRewardsWad, claimedBalance,
+      unstakeRequestAmount, unstakeRequestTime, lastStakeTime, lockCommitSeconds,
+      referrer: referrerKey.equals(PublicKey.default) ? null : referrerKey,
+    };
+  }
+
+  async verifyVaultConsistency(): Promise<string> {
+    const ix = createVerifyVaultConsistencyInstruction(this.poolPDA, this.tokenVaultPDA);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async claimToBalance(user: Keypair): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const ix = createClaimToBalanceInstruction(this.poolPDA, userStakePDA, user.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async withdrawClaimed(user: Keypair): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const ix = createWithdrawClaimedInstruction(this.poolPDA, userStakePDA, user.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async grandfatherStake(authority: Keypair, user: PublicKey, effectiveStakeTime: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const ix = createGrandfatherStakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      authority.publicKey,
+      effectiveStakeTime,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async splitStake(owner: Keypair, newOwner: PublicKey, amount: bigint): Promise<string> {
+    const [sourceStakePDA] = deriveUserStakePDA(this.poolPDA, owner.publicKey);
+    const [newStakePDA] = deriveUserStakePDA(this.poolPDA, newOwner);
+    const ix = createSplitStakeInstruction(
+      this.poolPDA,
+      sourceStakePDA,
+      newStakePDA,
+      owner.publicKey,
+      newOwner,
+      amount,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, owner]);
+  }
+
+  // Simulates QueryClaimableAt and decodes the u64 return data
+  async queryClaimableAt(user: PublicKey, futureTimestamp: bigint): Promise<bigint> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(
+      createQueryClaimableAtInstruction(this.poolPDA, userStakePDA, futureTimestamp),
+    );
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryClaimableAt failed: ${JSON.stringify(simulation.value.err)} ${simulation.value.logs?.join('\n')}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryClaimableAt returned no data');
+    }
+    return Buffer.from(returnData.data[0], 'base64').readBigUInt64LE(0);
+  }
+
+  // GetPendingRewards also returns the stake's claim history after the pending amount
+  async getClaimHistory(user: PublicKey): Promise<{ lastClaimTime: bigint; totalClaimed: bigint }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createGetPendingRewardsInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err || !simulation.value.returnData) {
+      throw new Error(`GetPendingRewards failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const data = Buffer.from(simulation.value.returnData.data[0], 'base64');
+    return { lastClaimTime: data.readBigInt64LE(8), totalClaimed: data.readBigUInt64LE(16) };
+  }
+
+  async collectProtocolFees(authority: Keypair, recipient: PublicKey): Promise<string> {
+    const ix = createCollectProtocolFeesInstruction(this.poolPDA, authority.publicKey, recipient);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async quarantinePool(): Promise<string> {
+    const ix = createQuarantinePoolInstruction(this.poolPDA, this.mint, this.payer.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  // Simulates ViewPoolHealth and decodes the u8 bitmask from return data
+  async viewPoolHealth(): Promise<number> {
+    const tx = new Transaction().add(
+      createViewPoolHealthInstruction(this.poolPDA, this.tokenVaultPDA),
+    );
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`ViewPoolHealth failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('ViewPoolHealth returned no data');
+    }
+    return Buffer.from(returnData.data[0], 'base64').readUInt8(0);
+  }
+
+  async claimFor(owner: PublicKey, caller: Keypair): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, owner);
+    const ix = createClaimForInstruction(this.poolPDA, userStakePDA, owner, caller.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [caller]);
+  }
+
+  async setClaimForOptIn(owner: Keypair, enabled: boolean): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, owner.publicKey);
+    const ix = createSetClaimForOptInInstruction(this.poolPDA, userStakePDA, owner.publicKey, enabled);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, owner]);
+  }
+
+  async setRewardFeeSplit(authority: Keypair, splits: { recipient: PublicKey; bps: number }[]): Promise<string> {
+    const ix = createSetRewardFeeSplitInstruction(this.poolPDA, authority.publicKey, splits);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async reverseUnstake(user: Keypair, userToken: PublicKey): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createReverseUnstakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  // Simulates QueryMinStakeUi and decodes min_stake_amount (u64) + decimals (u8)
+  async queryMinStakeUi(): Promise<{ minStakeAmount: bigint; decimals: number }> {
+    const tx = new Transaction().add(createQueryMinStakeUiInstruction(this.poolPDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryMinStakeUi failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryMinStakeUi returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return { minStakeAmount: data.readBigUInt64LE(0), decimals: data.readUInt8(8) };
+  }
+
+  async forceSyncWithBalance(authority: Keypair, expectedBalance: bigint): Promise<string> {
+    const ix = createForceSyncWithBalanceInstruction(this.poolPDA, authority.publicKey, expectedBalance);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  // Simulates Stake and decodes the return data: amount (u64) + weighted stake (u128)
+  async simulateStake(user: Keypair, userToken: PublicKey, amount: bigint): Promise<{ amount: bigint; weight: bigint }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const tx = new Transaction().add(createStakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      amount,
+      this.tokenProgramId,
+    ));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer, user);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`Stake simulation failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('Stake returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return { amount: data.readBigUInt64LE(0), weight: readU128LE(data, 8) };
+  }
+
+  // Current audit log page PDA, or null if the pool keeps no audit log
+  async currentAuditLogPage(): Promise<PublicKey | null> {
+    const info = await this.connection.getAccountInfo(this.poolPDA);
+    if (!info) throw new Error('Pool account not found');
+    // 478: audit_log_enabled (bool), 479: audit_log_page (u32)
+    if (info.data.length < 483 || info.data.readUInt8(478) === 0) return null;
+    return deriveAuditLogPagePDA(this.poolPDA, info.data.readUInt32LE(479))[0];
+  }
+
+  async readAuditLogPage(page: number): Promise<AuditLogPageState> {
+    const info = await this.connection.getAccountInfo(deriveAuditLogPagePDA(this.poolPDA, page)[0]);
+    if (!info) throw new Error(`Audit log page ${page} not found`);
+    const data = info.data;
+    // 8: discriminator, 40: page (u32), 44: count (u16), 47: records (48 bytes each)
+    const count = data.readUInt16LE(44);
+    const records: AuditRecord[] = [];
+    for (let i = 0; i < count; i++) {
+      const offset = 47 + i * 48;
+      records.push({
+        timestamp: data.readBigInt64LE(offset),
+        amount: data.readBigUInt64LE(offset + 8),
+        rewardPerShare: readU128LE(data, offset + 16),
+        totalStaked: readU128LE(data, offset + 32),
+      });
+    }
+    return { page: data.readUInt32LE(40), count, records };
+  }
+
+  async lockPoolSettings(authority: Keypair): Promise<string> {
+    const ix = createLockPoolSettingsInstruction(this.poolPDA, authority.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async distributeBonus(funder: Keypair, bonuses: { user: PublicKey; amount: bigint }[]): Promise<string> {
+    const ix = createDistributeBonusInstruction(
+      this.poolPDA,
+      funder.publicKey,
+      bonuses.map(({ user, amount }) => ({ userStake: deriveUserStakePDA(this.poolPDA, user)[0], amount })),
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [funder]);
+  }
+
+  async reinitializeVault(authority: Keypair): Promise<string> {
+    const ix = createReinitializeVaultInstruction(
+      this.poolPDA,
+      this.tokenVaultPDA,
+      this.mint,
+      authority.publicKey,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async setRewardPrecision(authority: Keypair, shift: number): Promise<string> {
+    const ix = createSetRewardPrecisionInstruction(this.poolPDA, authority.publicKey, shift);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async setDisplayUnit(authority: Keypair, label: string, decimals: number): Promise<string> {
+    const ix = createSetDisplayUnitInstruction(this.poolPDA, authority.publicKey, label, decimals);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async queryDisplayUnit(): Promise<{ label: string; decimals: number; totalDistributed: bigint }> {
+    const tx = new Transaction().add(createQueryDisplayUnitInstruction(this.poolPDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryDisplayUnit failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryDisplayUnit returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    const labelBytes = data.subarray(0, 8);
+    const end = labelBytes.indexOf(0);
+    return {
+      label: labelBytes.subarray(0, end === -1 ? 8 : end).toString('utf8'),
+      decimals: data.readUInt8(8),
+      totalDistributed: data.readBigUInt64LE(9),
+    };
+  }
+
+  async setUnstakeDestinationPolicy(authority: Keypair, policy: UnstakeDestinationPolicy): Promise<string> {
+    const ix = createSetUnstakeDestinationPolicyInstruction(this.poolPDA, authority.publicKey, policy);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async queryTimeToUnlock(user: PublicKey): Promise<bigint> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createQueryTimeToUnlockInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryTimeToUnlock failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryTimeToUnlock returned no data');
+    }
+    return Buffer.from(returnData.data[0], 'base64').readBigUInt64LE(0);
+  }
+
+  async queryPendingUnstake(user: PublicKey): Promise<{
+    amount: bigint;
+    requestTime: bigint;
+    cooldownEnd: bigint;
+    secondsRemaining: bigint;
+    completable: boolean;
+  }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createQueryPendingUnstakeInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryPendingUnstake failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryPendingUnstake returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      amount: data.readBigUInt64LE(0),
+      requestTime: data.readBigInt64LE(8),
+      cooldownEnd: data.readBigInt64LE(16),
+      secondsRemaining: data.readBigUInt64LE(24),
+      completable: data.readUInt8(32) !== 0,
+    };
+  }
+
+  async queryEffectiveSettings(user: PublicKey): Promise<{
+    lockDurationSeconds: bigint;
+    unstakeCooldownSeconds: bigint;
+    lastStakeTime: bigint;
+    flags: number;
+  }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createQueryEffectiveSettingsInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryEffectiveSettings failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryEffectiveSettings returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      lockDurationSeconds: data.readBigUInt64LE(0),
+      unstakeCooldownSeconds: data.readBigUInt64LE(8),
+      lastStakeTime: data.readBigInt64LE(16),
+      flags: data.readUInt8(24),
+    };
+  }
+
+  async queryAccumulatorSnapshot(user: PublicKey): Promise<{
+    accRewardPerWeightedShare: bigint;
+    rewardDebt: bigint;
+    amount: bigint;
+    weightedStake: bigint;
+    claimedRewardsWad: bigint;
+    accScaleShift: number;
+    pending: bigint;
+  }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createQueryAccumulatorSnapshotInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryAccumulatorSnapshot failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryAccumulatorSnapshot returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      accRewardPerWeightedShare: readU128LE(data, 0),
+      rewardDebt: readU128LE(data, 16),
+      amount: data.readBigUInt64LE(32),
+      weightedStake: readU128LE(data, 40),
+      claimedRewardsWad: readU128LE(data, 56),
+      accScaleShift: data.readUInt8(72),
+      pending: data.readBigUInt64LE(73),
+    };
+  }
+
+  async queryRewardRate(): Promise<{ rate: bigint; windowSeconds: bigint; windowLamports: bigint }> {
+    const tx = new Transaction().add(createQueryRewardRateInstruction(this.poolPDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryRewardRate failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryRewardRate returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      rate: data.readBigUInt64LE(0),
+      windowSeconds: data.readBigUInt64LE(8),
+      windowLamports: data.readBigUInt64LE(16),
+    };
+  }
+
+  async queryWeightedShare(user: PublicKey): Promise<{
+    contribution: bigint;
+    weighted: bigint;
+    denominator: bigint;
+  }> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createQueryWeightedShareInstruction(this.poolPDA, userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryWeightedShare failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryWeightedShare returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      contribution: readU128LE(data, 0),
+      weighted: readU128LE(data, 16),
+      denominator: readU128LE(data, 32),
+    };
+  }
+
+  async setDelegate(authority: Keypair, delegate: PublicKey, permissions: number): Promise<string> {
+    const ix = createSetDelegateInstruction(this.poolPDA, authority.publicKey, delegate, permissions);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async verifyPfeeOwnership(sharingConfig: PublicKey): Promise<{ soleRecipient: boolean; authorityRevoked: boolean }> {
+    const tx = new Transaction().add(createVerifyPfeeOwnershipInstruction(this.poolPDA, sharingConfig));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`VerifyPfeeOwnership failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('VerifyPfeeOwnership returned no data');
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return { soleRecipient: data.readUInt8(0) !== 0, authorityRevoked: data.readUInt8(1) !== 0 };
+  }
+
+  async getPendingRewards(user: PublicKey, userStake?: PublicKey): Promise<bigint> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user);
+    const tx = new Transaction().add(createGetPendingRewardsInstruction(this.poolPDA, userStake ?? userStakePDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`GetPendingRewards failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('GetPendingRewards returned no data');
+    }
+    return Buffer.from(returnData.data[0], 'base64').readBigUInt64LE(0);
+  }
+
+  async claimRewardsTo(
+    user: Keypair,
+    recipient: PublicKey,
+    feeRecipients: PublicKey[] = [],
+  ): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createClaimRewardsToInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      recipient,
+      feeRecipients,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async transferStake(owner: Keypair, newOwner: PublicKey, feeRecipients: PublicKey[] = []): Promise<string> {
+    const [sourceStakePDA] = deriveUserStakePDA(this.poolPDA, owner.publicKey);
+    const [destStakePDA] = deriveUserStakePDA(this.poolPDA, newOwner);
+    const ix = createTransferStakeInstruction(
+      this.poolPDA,
+      sourceStakePDA,
+      destStakePDA,
+      owner.publicKey,
+      newOwner,
+      feeRecipients,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, owner]);
+  }
+
+  async updateTau(authority: Keypair, tauSeconds: bigint): Promise<string> {
+    const ix = createUpdateTauInstruction(this.poolPDA, authority.publicKey, tauSeconds);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async readTauSeconds(): Promise<bigint> {
+    const info = await this.connection.getAccountInfo(this.poolPDA);
+    if (!info) throw new Error('Pool account not found');
+    // 184: tau_seconds (u64)
+    return info.data.readBigUInt64LE(184);
+  }
+
+  async setRewardMint(authority: Keypair, rewardMint: PublicKey, rewardTokenProgram: PublicKey): Promise<string> {
+    const ix = createSetRewardMintInstruction(this.poolPDA, authority.publicKey, rewardMint, rewardTokenProgram);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async depositTokenRewards(
+    depositor: Keypair,
+    depositorToken: PublicKey,
+    rewardMint: PublicKey,
+    rewardTokenProgram: PublicKey,
+    amount: bigint,
+  ): Promise<string> {
+    const ix = createDepositTokenRewardsInstruction(
+      this.poolPDA,
+      depositor.publicKey,
+      depositorToken,
+      rewardMint,
+      rewardTokenProgram,
+      amount,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, depositor]);
+  }
+
+  async claimTokenRewards(
+    user: Keypair,
+    destination: PublicKey,
+    rewardMint: PublicKey,
+    rewardTokenProgram: PublicKey,
+  ): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+    const ix = createClaimTokenRewardsInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      destination,
+      rewardMint,
+      rewardTokenProgram,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async renounceAuthority(authority: Keypair): Promise<string> {
+    const ix = createRenounceAuthorityInstruction(this.poolPDA, authority.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async setPause(authority: Keypair, paused: boolean): Promise<string> {
+    const ix = createSetPauseInstruction(this.poolPDA, authority.publicKey, paused);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async instantUnstake(user: Keypair, userToken: PublicKey, amount: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createInstantUnstakeInstruction(
+      this.poolPDA,
+      userStakePDA,
+      this.tokenVaultPDA,
+      userToken,
+      this.mint,
+      user.publicKey,
+      amount,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async proposeAuthority(authority: Keypair, newAuthority: PublicKey): Promise<string> {
+    const ix = createProposeAuthorityInstruction(this.poolPDA, authority.publicKey, newAuthority);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async acceptAuthority(newAuthority: Keypair): Promise<string> {
+    const ix = createAcceptAuthorityInstruction(this.poolPDA, newAuthority.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, newAuthority]);
+  }
+
+  async cancelAuthorityProposal(authority: Keypair): Promise<string> {
+    const ix = createCancelAuthorityProposalInstruction(this.poolPDA, authority.publicKey);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async amendUnstakeRequest(user: Keypair, amount: bigint): Promise<string> {
+    const [userStakePDA] = deriveUserStakePDA(this.poolPDA, user.publicKey);
+
+    const ix = createAmendUnstakeRequestInstruction(
+      this.poolPDA,
+      userStakePDA,
+      user.publicKey,
+      amount,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async depositRewardsStreamed(amount: bigint, durationSeconds: bigint): Promise<string> {
+    const ix = createDepositRewardsStreamedInstruction(this.poolPDA, this.payer.publicKey, amount, durationSeconds);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async closePool(authority: Keypair, rentDestination: PublicKey): Promise<string> {
+    const ix = createClosePoolInstruction(
+      this.poolPDA,
+      this.tokenVaultPDA,
+      authority.publicKey,
+      rentDestination,
+      this.tokenProgramId,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async initializeUserStake(user: Keypair, index: number): Promise<string> {
+    const [positionPDA] = deriveUserStakePositionPDA(this.poolPDA, user.publicKey, index);
+    const ix = createInitializeUserStakeInstruction(this.poolPDA, positionPDA, user.publicKey, index);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, user]);
+  }
+
+  async getPoolStats(): Promise<{
+    version: number;
+    flags: number;
+    totalStaked: bigint;
+    accRewardPerWeightedShare: bigint;
+    accScaleShift: number;
+    undistributedLamports: bigint;
+    unreleasedStreamLamports: bigint;
+    rewardRate: bigint;
+    rewardRateWindowSeconds: bigint;
+    annualRewardsPerTokenWad: bigint;
+    lastUpdateTime: bigint;
+  }> {
+    const tx = new Transaction().add(createGetPoolStatsInstruction(this.poolPDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`GetPoolStats failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('GetPoolStats returned no data');
+    }
+    // Layout v1: see process_get_pool_stats (get_pool_stats.rs)
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      version: data.readUInt8(0),
+      flags: data.readUInt8(1),
+      totalStaked: readU128LE(data, 2),
+      accRewardPerWeightedShare: readU128LE(data, 18),
+      accScaleShift: data.readUInt8(34),
+      undistributedLamports: data.readBigUInt64LE(35),
+      unreleasedStreamLamports: data.readBigUInt64LE(43),
+      rewardRate: data.readBigUInt64LE(51),
+      rewardRateWindowSeconds: data.readBigUInt64LE(59),
+      annualRewardsPerTokenWad: readU128LE(data, 67),
+      lastUpdateTime: data.readBigInt64LE(83),
+    };
+  }
+
+  async addDepositor(authority: Keypair, depositor: PublicKey): Promise<string> {
+    const [entryPDA] = deriveDepositorAllowlistPDA(this.poolPDA, depositor);
+    const ix = createDepositorAllowlistInstruction(
+      InstructionType.AddDepositor, this.poolPDA, entryPDA, authority.publicKey, depositor,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async removeDepositor(authority: Keypair, depositor: PublicKey): Promise<string> {
+    const [entryPDA] = deriveDepositorAllowlistPDA(this.poolPDA, depositor);
+    const ix = createDepositorAllowlistInstruction(
+      InstructionType.RemoveDepositor, this.poolPDA, entryPDA, authority.publicKey, depositor,
+    );
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async applySettings(authority: Keypair): Promise<string> {
+    const tx = new Transaction().add(createApplySettingsInstruction(this.poolPDA, authority.publicKey));
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async queryPendingSettings(): Promise<{
+    mask: number;
+    effectiveTime: bigint;
+    settingsTimelockSeconds: bigint;
+    lockDurationSeconds: bigint;
+    unstakeCooldownSeconds: bigint;
+    claimFeeBps: bigint;
+    earlyExitPenaltyBps: bigint;
+    pendingSettingsTimelockSeconds: bigint;
+    loyaltyBonusBps: bigint;
+    lockBoostBps: bigint;
+    minDistributablePerEvent: bigint;
+    feeSplit: { recipient: PublicKey; bps: number }[];
+    tauSeconds: bigint;
+  }> {
+    const tx = new Transaction().add(createQueryPendingSettingsInstruction(this.poolPDA));
+    tx.feePayer = this.payer.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+    tx.sign(this.payer);
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`QueryPendingSettings failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('QueryPendingSettings returned no data');
+    }
+    // See process_query_pending_settings (query_pending_settings.rs)
+    const data = Buffer.from(returnData.data[0], 'base64');
+    return {
+      mask: data.readUInt16LE(0),
+      effectiveTime: data.readBigInt64LE(2),
+      settingsTimelockSeconds: data.readBigUInt64LE(10),
+      lockDurationSeconds: data.readBigUInt64LE(18),
+      unstakeCooldownSeconds: data.readBigUInt64LE(26),
+      claimFeeBps: data.readBigUInt64LE(34),
+      earlyExitPenaltyBps: data.readBigUInt64LE(42),
+      pendingSettingsTimelockSeconds: data.readBigUInt64LE(50),
+      loyaltyBonusBps: data.readBigUInt64LE(58),
+      lockBoostBps: data.readBigUInt64LE(66),
+      minDistributablePerEvent: data.readBigUInt64LE(74),
+      feeSplit: [0, 1, 2].map((i) => ({
+        recipient: new PublicKey(data.subarray(82 + 34 * i, 114 + 34 * i)),
+        bps: data.readUInt16LE(114 + 34 * i),
+      })),
+      tauSeconds: data.readBigUInt64LE(184),
+    };
+  }
+
+  async reclaimDeferredRewards(authority: Keypair, recipient: PublicKey): Promise<string> {
+    const ix = createReclaimDeferredRewardsInstruction(this.poolPDA, authority.publicKey, recipient);
+
+    const tx = new Transaction().add(ix);
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer, authority]);
+  }
+
+  async withdrawFeeSplit(recipient: PublicKey): Promise<string> {
+    const tx = new Transaction().add(createWithdrawFeeSplitInstruction(this.poolPDA, recipient));
+    return await sendAndConfirmTransaction(this.connection, tx, [this.payer]);
+  }
+
+  async getBalance(pubkey: PublicKey): Promise<number> {
+    return await this.connection.getBalance(pubkey);
+  }
+
+  async getTokenBalance(tokenAccount: PublicKey): Promise<bigint> {
+    const account = await getAccount(this.connection, tokenAccount, undefined, this.tokenProgramId);
+    return account.amount;
+  }
+}
+
+// Test runner
+async function runTests() {
+  console.log('=== ChiefStaker E2E Tests ===\n');
+
+  const connection = new Connection('http://localhost:8899', 'confirmed');
+  const payer = Keypair.generate();
+
+  // Load the program deployer keypair (upgrade authority)
+  const solanaKeypairPath = path.join(
+    process.env.HOME || require('os').homedir(),
+    '.config', 'solana', 'id.json',
+  );
+  const programAuthority = Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(solanaKeypairPath, 'utf8'))),
+  );
+
+  // Check connection
+  try {
+    await connection.getVersion();
+  } catch (e) {
+    console.error('ERROR: Cannot connect to test validator. Start it with:');
+    console.error('  ./scripts/start-validator.sh --reset');
+    process.exit(1);
+  }
+
+  // Check program is deployed
+  const programInfo = await connection.getAccountInfo(PROGRAM_ID);
+  if (!programInfo) {
+    console.error('ERROR: Program not deployed. Deploy it with:');
+    console.error('  ./scripts/deploy-program.sh target/deploy/chiefstaker.so');
+    process.exit(1);
+  }
+
+  let passed = 0;
+  let failed = 0;
+
+  async function test(name: string, fn: () => Promise<void>) {
+    try {
+      await fn();
+      console.log(`✓ ${name}`);
+      passed++;
+    } catch (e: any) {
+      console.log(`✗ ${name}`);
+      console.log(`  Error: ${e.message}`);
+      if (e.logs) {
+        console.log(`  Logs: ${e.logs.slice(-5).join('\n        ')}`);
+      }
+      failed++;
+    }
+  }
+
+  for (const [tokenProgramLabel, tokenProgramId] of [
+    ['Token2022', TOKEN_2022_PROGRAM_ID],
+    ['SPL Token', TOKEN_PROGRAM_ID],
+  ] as [string, PublicKey][]) {
+
+  console.log(`\n========== ${tokenProgramLabel} Tests ==========\n`);
+
+  // Test: Initialize Pool
+  await test(`[${tokenProgramLabel}] Initialize pool`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    const tauSeconds = BigInt(30 * 24 * 60 * 60); // 30 days
+    await ctx.initializePool(tauSeconds);
+
+    const poolInfo = await connection.getAccountInfo(ctx.poolPDA);
+    if (!poolInfo) throw new Error('Pool not created');
+    if (poolInfo.data.length === 0) throw new Error('Pool data empty');
+  });
+
+  // Test: InitializePool rejects non-authority signer
+  await test(`[${tokenProgramLabel}] InitializePool: non-authority signer rejected`, async () => {
+    // Create a context where mintAuthority != payer
+    const specialCtx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    specialCtx.mintAuthority = Keypair.generate();
+    await specialCtx.setup();
+    // Airdrop to separate mint authority so createMint works
+    await airdropAndConfirm(connection, specialCtx.mintAuthority.publicKey, LAMPORTS_PER_SOL);
+    await specialCtx.createMint(9);
+
+    // initializePool signs with payer, but mint_authority is specialCtx.mintAuthority
+    // Should fail with InvalidAuthority (error code 6 = 0x6)
+    try {
+      await specialCtx.initializePool(60n);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      if (!e.message.includes('custom program error: 0x6')) {
+        throw new Error(`Expected InvalidAuthority (0x6), got: ${e.message}`);
+      }
+    }
+  });
+
+  // Test: Stake tokens
+  await test(`[${tokenProgramLabel}] Stake tokens`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const stakeAmount = BigInt(1_000_000_000);
+    await ctx.mintTokens(userToken, stakeAmount);
+
+    await ctx.stake(user, userToken, stakeAmount);
+
+    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    if (vaultBalance !== stakeAmount) {
+      throw new Error(`Expected vault balance ${stakeAmount}, got ${vaultBalance}`);
+    }
+  });
+
+  // Test: Multiple stakers
+  await test(`[${tokenProgramLabel}] Multiple stakers`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user1 = Keypair.generate();
+    const user2 = Keypair.generate();
+    await airdropAndConfirm(connection, user1.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user2.publicKey, LAMPORTS_PER_SOL);
+
+    const user1Token = await ctx.createUserTokenAccount(user1.publicKey);
+    const user2Token = await ctx.createUserTokenAccount(user2.publicKey);
+
+    await ctx.mintTokens(user1Token, BigInt(1_000_000_000));
+    await ctx.mintTokens(user2Token, BigInt(2_000_000_000));
+
+    await ctx.stake(user1, user1Token, BigInt(1_000_000_000));
+    await ctx.stake(user2, user2Token, BigInt(2_000_000_000));
+
+    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    if (vaultBalance !== BigInt(3_000_000_000)) {
+      throw new Error(`Expected vault balance 3000000000, got ${vaultBalance}`);
+    }
+  });
+
+  // Test: Deposit and claim rewards
+  await test(`[${tokenProgramLabel}] Deposit and claim rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100)); // Short tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    const balanceBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const balanceAfter = await ctx.getBalance(user.publicKey);
+
+    // User should receive some rewards (payer covers tx fee, so diff = pure reward)
+    // Due to time-weighted calculation, might be small if just staked
+    const reward = balanceAfter - balanceBefore;
+    if (reward < 0) throw new Error(`Unexpected SOL loss: ${reward}`);
+    console.log(`    Reward claimed: ${reward} lamports`);
+  });
+
+  // Test: Unstake partial
+  await test(`[${tokenProgramLabel}] Unstake partial`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Unstake half
+    await ctx.unstake(user, userToken, BigInt(500_000_000));
+
+    const userBalance = await ctx.getTokenBalance(userToken);
+    if (userBalance !== BigInt(500_000_000)) {
+      throw new Error(`Expected user balance 500000000, got ${userBalance}`);
+    }
+  });
+
+  // Test: Unstake full
+  await test(`[${tokenProgramLabel}] Unstake full`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+
+    const userBalance = await ctx.getTokenBalance(userToken);
+    if (userBalance !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected user balance 1000000000, got ${userBalance}`);
+    }
+  });
+
+  // Test: SyncRewards (pump.fun simulation)
+  await test(`[${tokenProgramLabel}] SyncRewards (pump.fun simulation)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Send SOL directly to pool (simulating pump.fun)
+    await ctx.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 2));
+
+    // Sync rewards
+    await ctx.syncRewards();
+
+    const balanceBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const balanceAfter = await ctx.getBalance(user.publicKey);
+
+    const reward = balanceAfter - balanceBefore;
+    if (reward <= 0) throw new Error(`Expected positive reward from synced SOL, got: ${reward}`);
+    console.log(`    Direct SOL reward claimed: ${reward} lamports`);
+  });
+
+  // Test: Additional stake
+  await test(`[${tokenProgramLabel}] Additional stake (same user)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(500_000_000));
+
+    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    if (vaultBalance !== BigInt(1_500_000_000)) {
+      throw new Error(`Expected vault balance 1500000000, got ${vaultBalance}`);
+    }
+  });
+
+  // ============================================
+  // POOL SETTINGS / AUTHORITY TESTS
+  // ============================================
+
+  console.log('\n--- Pool Settings & Authority Tests ---\n');
+
+  // Test: Update pool settings
+  await test(`[${tokenProgramLabel}] UpdatePoolSettings: set min_stake, lock, cooldown`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set all three settings
+    await ctx.updatePoolSettings(
+      ctx.payer,
+      BigInt(1_000_000), // min stake
+      null, // no lock
+      null, // no cooldown
+    );
+
+    // Now update lock duration only
+    await ctx.updatePoolSettings(
+      ctx.payer,
+      null,
+      BigInt(10), // 10 second lock
+      null,
+    );
+  });
+
+  // Test: Update settings with wrong authority fails
+  await test(`[${tokenProgramLabel}] UpdatePoolSettings: wrong authority rejected`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const wrongAuth = Keypair.generate();
+    await airdropAndConfirm(connection, wrongAuth.publicKey, LAMPORTS_PER_SOL);
+
+    let failed = false;
+    try {
+      await ctx.updatePoolSettings(wrongAuth, BigInt(100), null, null);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x6')) {
+        throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Should reject wrong authority');
+  });
+
+  // Test: Transfer authority
+  await test(`[${tokenProgramLabel}] TransferAuthority: transfer and use new authority`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const newAuth = Keypair.generate();
+    await airdropAndConfirm(connection, newAuth.publicKey, LAMPORTS_PER_SOL);
+
+    // Transfer authority
+    await ctx.transferAuthority(ctx.payer, newAuth.publicKey);
+
+    // Old authority should fail
+    let oldFailed = false;
+    try {
+      await ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null);
+    } catch (e) {
+      oldFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x6')) {
+        throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
+      }
+    }
+    if (!oldFailed) throw new Error('Old authority should be rejected');
+
+    // New authority should work
+    await ctx.updatePoolSettings(newAuth, BigInt(100), null, null);
+  });
+
+  // Test: Renounce authority
+  await test(`[${tokenProgramLabel}] TransferAuthority: renounce (set to default pubkey)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Renounce authority (set to default pubkey = zero)
+    await ctx.transferAuthority(ctx.payer, PublicKey.default);
+
+    // Authority should now be rejected
+    let failed = false;
+    try {
+      await ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1b')) {
+        throw new Error(`Expected AuthorityRenounced (0x1b), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Renounced authority should be rejected');
+  });
+
+  // ===================================================================
+  // PDA authority (DAO governance via CPI)
+  // ===================================================================
+
+  // Test: a governance PDA holding authority can update settings via invoke_signed
+  await test(`[${tokenProgramLabel}] TransferAuthority: governance PDA updates settings via CPI`, async () => {
+    const governanceProgram = loadMockGovernanceProgramId();
+    if (!governanceProgram || !(await connection.getAccountInfo(governanceProgram))) {
+      console.log('  (mock governance program not deployed, skipping)');
+      return;
+    }
+    const [governancePDA] = deriveGovernancePDA(governanceProgram);
+
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    await ctx.transferAuthority(ctx.payer, governancePDA);
+
+    // The previous authority no longer controls the pool
+    let oldFailed = false;
+    try {
+      await ctx.updatePoolSettings(ctx.payer, BigInt(1_000_000_000), null, null);
+    } catch (e) {
+      oldFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x6')) {
+        throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
+      }
+    }
+    if (!oldFailed) throw new Error('Old authority should be rejected');
+
+    // Governance executes UpdatePoolSettings; only the fee payer signs the transaction
+    const inner = createUpdatePoolSettingsInstruction(
+      ctx.poolPDA, governancePDA, BigInt(1_000_000_000), null, null,
+    );
+    const tx = new Transaction().add(createGovernanceExecuteInstruction(governanceProgram, inner));
+    await sendAndConfirmTransaction(connection, tx, [ctx.payer]);
+
+    // The new minimum is in force
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(500_000_000));
+    let belowMinFailed = false;
+    try {
+      await ctx.stake(user, userToken, BigInt(500_000_000));
+    } catch (e) {
+      belowMinFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x15')) {
+        throw new Error(`Expected BelowMinimumStake (0x15), got: ${errMsg}`);
+      }
+    }
+    if (!belowMinFailed) throw new Error('Setting applied via governance should be enforced');
+  });
+
+  // Test: Min stake amount enforced on new stake
+  await test(`[${tokenProgramLabel}] MinStake: enforced on new stake`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set min stake to 1 billion (1 token with 9 decimals)
+    await ctx.updatePoolSettings(ctx.payer, BigInt(1_000_000_000), null, null);
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+
+    // Stake below minimum should fail
+    let belowMinFailed = false;
+    try {
+      await ctx.stake(user, userToken, BigInt(500_000_000));
+    } catch (e) {
+      belowMinFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x15')) {
+        throw new Error(`Expected BelowMinimumStake (0x15), got: ${errMsg}`);
+      }
+    }
+    if (!belowMinFailed) throw new Error('Below-minimum stake should fail');
+
+    // Stake at minimum should succeed
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+  });
+
+  // Test: Lock duration blocks early unstake
+  await test(`[${tokenProgramLabel}] LockDuration: blocks early unstake`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set 10-second lock duration
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(10), null);
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Try unstake immediately - should fail (locked)
+    let lockedFailed = false;
+    try {
+      await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    } catch (e) {
+      lockedFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x16')) {
+        throw new Error(`Expected StakeLocked (0x16), got: ${errMsg}`);
+      }
+    }
+    if (!lockedFailed) throw new Error('Should reject unstake during lock period');
+
+    // Wait for lock to expire
+    console.log('    Waiting 11s for lock to expire...');
+    await new Promise(r => setTimeout(r, 11000));
+
+    // Now unstake should succeed
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+  });
+
+  // Test: Cooldown flow - request → wait → complete
+  await test(`[${tokenProgramLabel}] Cooldown: request → wait → complete unstake`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set 5-second cooldown
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(5));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Direct unstake should fail (cooldown required)
+    let directFailed = false;
+    try {
+      await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    } catch (e) {
+      directFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x18')) {
+        throw new Error(`Expected CooldownRequired (0x18), got: ${errMsg}`);
+      }
+    }
+    if (!directFailed) throw new Error('Direct unstake should fail with cooldown');
+
+    // Request unstake
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+
+    // Complete immediately should fail (cooldown not elapsed)
+    let earlyFailed = false;
+    try {
+      await ctx.completeUnstake(user, userToken);
+    } catch (e) {
+      earlyFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x17')) {
+        throw new Error(`Expected CooldownNotElapsed (0x17), got: ${errMsg}`);
+      }
+    }
+    if (!earlyFailed) throw new Error('Complete unstake should fail before cooldown');
+
+    // Wait for cooldown
+    console.log('    Waiting 6s for cooldown...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    // Complete unstake should now succeed
+    await ctx.completeUnstake(user, userToken);
+
+    const balance = await ctx.getTokenBalance(userToken);
+    if (balance !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected 1000000000 tokens back, got ${balance}`);
+    }
+  });
+
+  // Test: Cancel unstake request
+  await test(`[${tokenProgramLabel}] Cooldown: cancel unstake request`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set 60-second cooldown (long, we cancel before)
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Request unstake
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+
+    // Cancel request: tokens stay staked, reward accounting is untouched
+    const before = await ctx.readUserStakeState(user.publicKey);
+    await ctx.cancelUnstakeRequest(user);
+    const cancelled = await ctx.readUserStakeState(user.publicKey);
+    if (cancelled.unstakeRequestAmount !== 0n || cancelled.unstakeRequestTime !== 0n) {
+      throw new Error('Cancel should clear the request');
+    }
+    if (cancelled.amount !== before.amount || cancelled.rewardDebt !== before.rewardDebt) {
+      throw new Error('Cancel should not change the stake or its reward debt');
+    }
+
+    // Cancel again should fail (no pending request)
+    let doubleCancelFailed = false;
+    try {
+      await ctx.cancelUnstakeRequest(user);
+    } catch (e) {
+      doubleCancelFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x19')) {
+        throw new Error(`Expected NoPendingUnstakeRequest (0x19), got: ${errMsg}`);
+      }
+    }
+    if (!doubleCancelFailed) throw new Error('Double cancel should fail');
+
+    // A fresh request can be made right away
+    await ctx.requestUnstake(user, BigInt(500_000_000));
+    if ((await ctx.readUserStakeState(user.publicKey)).unstakeRequestAmount !== BigInt(500_000_000)) {
+      throw new Error('New request after cancel not recorded');
+    }
+  });
+
+  // Test: Cannot stake while unstake request pending
+  await test(`[${tokenProgramLabel}] Cooldown: cannot stake while unstake pending`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // Set cooldown
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Request unstake
+    await ctx.requestUnstake(user, BigInt(500_000_000));
+
+    // Staking more should fail
+    let stakeFailed = false;
+    try {
+      await ctx.stake(user, userToken, BigInt(500_000_000));
+    } catch (e) {
+      stakeFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1a')) {
+        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${errMsg}`);
+      }
+    }
+    if (!stakeFailed) throw new Error('Should not stake while unstake pending');
+
+    // Cancel request, then stake should work
+    await ctx.cancelUnstakeRequest(user);
+    await ctx.stake(user, userToken, BigInt(500_000_000));
+  });
+
+  // Test: Cannot request unstake twice
+  await test(`[${tokenProgramLabel}] Cooldown: cannot request unstake twice`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // First request succeeds
+    await ctx.requestUnstake(user, BigInt(500_000_000));
+
+    // Second request should fail
+    let doubleRequestFailed = false;
+    try {
+      await ctx.requestUnstake(user, BigInt(500_000_000));
+    } catch (e) {
+      doubleRequestFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1a')) {
+        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${errMsg}`);
+      }
+    }
+    if (!doubleRequestFailed) throw new Error('Double request should fail');
+  });
+
+  // Test: Existing pools (zero reserved fields) work unchanged
+  await test(`[${tokenProgramLabel}] Backward compat: existing pool works with zero settings`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    // With default settings (all zeros), everything should work as before
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+
+    // Stake works (no min stake)
+    await ctx.stake(user, userToken, BigInt(100));
+
+    // Unstake works immediately (no lock, no cooldown)
+    await ctx.unstake(user, userToken, BigInt(100));
+  });
+
+  // ============================================
+  // MATHEMATICAL CORRECTNESS TESTS
+  // ============================================
+  // Verify stakers receive the correct proportional rewards based on the
+  // weight formula: weight = amount × (1 - e^(-age/τ))
+
+  console.log('\n--- Mathematical Correctness Tests ---\n');
+
+  // Test: Weight formula verification - new staker gains weight over old staker
+  await test(`[${tokenProgramLabel}] Math: New staker weight increases relative to old staker`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 (minimum allowed)
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Old staker stakes first and waits to accumulate weight
+    const oldStaker = Keypair.generate();
+    await airdropAndConfirm(connection, oldStaker.publicKey, 3 * LAMPORTS_PER_SOL);
+    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
+    await ctx.mintTokens(oldToken, BigInt(1_000_000_000));
+    await ctx.stake(oldStaker, oldToken, BigInt(1_000_000_000));
+
+    // Wait for old staker to accumulate weight (~28% at 20s/60s tau)
+    console.log(`    Waiting 20s for old staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 20000));
+
+    // New staker joins - starts with ~0% weight
+    const newStaker = Keypair.generate();
+    await airdropAndConfirm(connection, newStaker.publicKey, 3 * LAMPORTS_PER_SOL);
+    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
+    await ctx.mintTokens(newToken, BigInt(1_000_000_000));
+    await ctx.stake(newStaker, newToken, BigInt(1_000_000_000));
+
+    // Deposit and check shares (old should dominate)
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    let oldBal = await ctx.getBalance(oldStaker.publicKey);
+    await ctx.claimRewards(oldStaker);
+    const oldReward1 = (await ctx.getBalance(oldStaker.publicKey)) - oldBal;
+
+    // New staker has ~0 weight, so likely gets nothing or very little
+    let newBal = await ctx.getBalance(newStaker.publicKey);
+    try {
+      await ctx.claimRewards(newStaker);
+    } catch (e: any) {
+      if (!e.message?.includes('0xb')) throw e;
+    }
+    const newReward1 = (await ctx.getBalance(newStaker.publicKey)) - newBal;
+    if (newReward1 < 0) throw new Error(`Unexpected SOL loss: ${newReward1}`);
+
+    const total1 = oldReward1 + newReward1;
+    const newShare1 = total1 > 0 ? (newReward1 * 100) / total1 : 0;
+    console.log(`    New staker share at t=0: ${newShare1.toFixed(1)}%`);
+
+    // Wait 10s - new staker should gain relative share (~15% weight)
+    console.log(`    Waiting 10s for new staker weight...`);
+    await new Promise(r => setTimeout(r, 10000));
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    oldBal = await ctx.getBalance(oldStaker.publicKey);
+    await ctx.claimRewards(oldStaker);
+    const oldReward2 = (await ctx.getBalance(oldStaker.publicKey)) - oldBal;
+
+    newBal = await ctx.getBalance(newStaker.publicKey);
+    await ctx.claimRewards(newStaker);
+    const newReward2 = (await ctx.getBalance(newStaker.publicKey)) - newBal;
+
+    const newShare2 = (newReward2 * 100) / (oldReward2 + newReward2);
+    console.log(`    New staker share at t=10s: ${newShare2.toFixed(1)}%`);
+
+    // New staker's share should increase over time
+    if (newShare2 <= newShare1) {
+      throw new Error(`New staker share should increase: ${newShare1}% -> ${newShare2}%`);
+    }
+  });
+
+  // Test: Old staker gets more than new staker
+  await test(`[${tokenProgramLabel}] Math: Old staker gets more rewards than new staker`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 (minimum allowed)
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Old staker stakes first
+    const oldStaker = Keypair.generate();
+    await airdropAndConfirm(connection, oldStaker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
+    const stakeAmount = BigInt(1_000_000_000);
+    await ctx.mintTokens(oldToken, stakeAmount);
+    await ctx.stake(oldStaker, oldToken, stakeAmount);
+
+    // Wait 20s - old staker will have ~28% weight at tau=60
+    console.log(`    Waiting 20s for old staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 20000));
+
+    // New staker stakes now (will have ~0% weight)
+    const newStaker = Keypair.generate();
+    await airdropAndConfirm(connection, newStaker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
+    await ctx.mintTokens(newToken, stakeAmount);
+    await ctx.stake(newStaker, newToken, stakeAmount);
+
+    // Deposit rewards - use smaller amount to ensure pool has enough
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    // Claim for old staker first
+    const oldBalanceBefore = await ctx.getBalance(oldStaker.publicKey);
+    await ctx.claimRewards(oldStaker);
+    const oldBalanceAfter = await ctx.getBalance(oldStaker.publicKey);
+    const oldReward = oldBalanceAfter - oldBalanceBefore;
+
+    // New staker should have nearly 0 weight, so minimal/no rewards
+    const newBalanceBefore = await ctx.getBalance(newStaker.publicKey);
+    try {
+      await ctx.claimRewards(newStaker);
+    } catch (e: any) {
+      if (!e.message?.includes('0xb')) throw e;
+      console.log(`    New staker claim: insufficient (expected)`);
+    }
+    const newBalanceAfter = await ctx.getBalance(newStaker.publicKey);
+    const newReward = newBalanceAfter - newBalanceBefore;
+    if (newReward < 0) throw new Error(`Unexpected SOL loss: ${newReward}`);
+
+    console.log(`    Old staker (20s age) reward: ${oldReward} lamports`);
+    console.log(`    New staker (~0 age) reward: ${newReward} lamports`);
+
+    // Old staker should get significantly more
+    if (oldReward <= newReward) {
+      throw new Error(`Old staker should get more: old=${oldReward}, new=${newReward}`);
+    }
+
+    // Old staker should get >75% of rewards.
+    // With τ=60s and 20s wait, old has ~28% weight.
+    // New staker gains ~3% weight during ~2s of tx processing.
+    // Old share ≈ 28/(28+3) ≈ 90%.
+    const totalRewards = oldReward + newReward;
+    if (totalRewards > 0) {
+      const oldShare = (oldReward * 100) / totalRewards;
+      console.log(`    Old staker share: ${oldShare.toFixed(1)}%`);
+      if (oldShare < 75) {
+        throw new Error(`Old staker should get >75% of rewards, got ${oldShare}%`);
+      }
+    }
+  });
+
+  // Test: Equal stakers get equal rewards (when both matured)
+  await test(`[${tokenProgramLabel}] Math: Equal age stakers get equal rewards (matured)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 (minimum allowed)
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Two stakers stake same amount
+    const staker1 = Keypair.generate();
+    const staker2 = Keypair.generate();
+    await airdropAndConfirm(connection, staker1.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, staker2.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
+    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
+    const stakeAmount = BigInt(1_000_000_000);
+    await ctx.mintTokens(token1, stakeAmount);
+    await ctx.mintTokens(token2, stakeAmount);
+
+    // Stake sequentially (slight time difference)
+    await ctx.stake(staker1, token1, stakeAmount);
+    await ctx.stake(staker2, token2, stakeAmount);
+
+    // Wait 15s for both stakes to accumulate similar weight (~22% at tau=60)
+    // The ~1s staking difference is small relative to tau=60
+    console.log(`    Waiting 15s for both stakes to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 15000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim for both
+    const balance1Before = await ctx.getBalance(staker1.publicKey);
+    await ctx.claimRewards(staker1);
+    const balance1After = await ctx.getBalance(staker1.publicKey);
+    const reward1 = balance1After - balance1Before;
+
+    const balance2Before = await ctx.getBalance(staker2.publicKey);
+    await ctx.claimRewards(staker2);
+    const balance2After = await ctx.getBalance(staker2.publicKey);
+    const reward2 = balance2After - balance2Before;
+
+    console.log(`    Staker 1 reward: ${reward1} lamports`);
+    console.log(`    Staker 2 reward: ${reward2} lamports`);
+
+    // Should be approximately equal (within 15% - both at similar weight with tau=60)
+    const diff = Math.abs(reward1 - reward2);
+    const avg = (reward1 + reward2) / 2;
+    const diffPercent = (diff * 100) / avg;
+    console.log(`    Difference: ${diffPercent.toFixed(2)}%`);
+
+    if (diffPercent > 15) {
+      throw new Error(`Equal-age stakers should get ~equal rewards, diff=${diffPercent}%`);
+    }
+  });
+
+  // Test: 2x stake = 2x rewards (when both fully matured)
+  await test(`[${tokenProgramLabel}] Math: Double stake gets double rewards (matured)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 (minimum allowed)
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Staker 1: stakes 1 token
+    // Staker 2: stakes 2 tokens
+    const staker1 = Keypair.generate();
+    const staker2 = Keypair.generate();
+    await airdropAndConfirm(connection, staker1.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, staker2.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
+    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
+    await ctx.mintTokens(token1, BigInt(1_000_000_000));
+    await ctx.mintTokens(token2, BigInt(2_000_000_000));
+
+    // Stake both
+    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
+    await ctx.stake(staker2, token2, BigInt(2_000_000_000));
+
+    // Wait 15s for both stakes to accumulate similar weight (~22% at tau=60)
+    // The small timing difference between stakes is negligible relative to tau=60
+    console.log(`    Waiting 15s for both stakes to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 15000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim
+    const balance1Before = await ctx.getBalance(staker1.publicKey);
+    await ctx.claimRewards(staker1);
+    const balance1After = await ctx.getBalance(staker1.publicKey);
+    const reward1 = balance1After - balance1Before;
+
+    const balance2Before = await ctx.getBalance(staker2.publicKey);
+    await ctx.claimRewards(staker2);
+    const balance2After = await ctx.getBalance(staker2.publicKey);
+    const reward2 = balance2After - balance2Before;
+
+    console.log(`    1x stake reward: ${reward1} lamports`);
+    console.log(`    2x stake reward: ${reward2} lamports`);
+
+    // Staker 2 should get ~2x rewards
+    const ratio = reward2 / reward1;
+    console.log(`    Ratio: ${ratio.toFixed(2)}x`);
+
+    if (ratio < 1.8 || ratio > 2.2) {
+      throw new Error(`2x stake should get ~2x rewards, got ${ratio}x`);
+    }
+  });
+
+  // Test: Verify weight differentiation between old and new staker
+  await test(`[${tokenProgramLabel}] Math: Older staker gets proportionally more rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 (minimum allowed)
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Old staker stakes and waits to accumulate weight
+    const oldStaker = Keypair.generate();
+    await airdropAndConfirm(connection, oldStaker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
+    await ctx.mintTokens(oldToken, BigInt(1_000_000_000));
+    await ctx.stake(oldStaker, oldToken, BigInt(1_000_000_000));
+
+    // Wait 20s for old staker (~28.3% weight at tau=60)
+    console.log(`    Waiting 20s for old staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 20000));
+
+    // New staker stakes same amount
+    const newStaker = Keypair.generate();
+    await airdropAndConfirm(connection, newStaker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
+    await ctx.mintTokens(newToken, BigInt(1_000_000_000));
+    await ctx.stake(newStaker, newToken, BigInt(1_000_000_000));
+
+    // Wait 10s for new staker (~15.4% weight), old is now at ~39.3%
+    console.log(`    Waiting 10s for new staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Now: old staker has ~39% weight (30s age), new staker has ~15% weight (10s age)
+    // Old should get: 39/(39+15) = ~72%
+    // New should get: 15/(39+15) = ~28%
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    const oldBalBefore = await ctx.getBalance(oldStaker.publicKey);
+    await ctx.claimRewards(oldStaker);
+    const oldBalAfter = await ctx.getBalance(oldStaker.publicKey);
+    const oldReward = oldBalAfter - oldBalBefore;
+
+    const newBalBefore = await ctx.getBalance(newStaker.publicKey);
+    await ctx.claimRewards(newStaker);
+    const newBalAfter = await ctx.getBalance(newStaker.publicKey);
+    const newReward = newBalAfter - newBalBefore;
+
+    const total = oldReward + newReward;
+    const oldPercent = (oldReward * 100) / total;
+    const newPercent = (newReward * 100) / total;
+
+    console.log(`    Old staker (30s, ~39% weight): ${oldPercent.toFixed(1)}%`);
+    console.log(`    New staker (10s, ~15% weight): ${newPercent.toFixed(1)}%`);
+
+    // Expected: old ~72%, new ~28% (with some tolerance for timing)
+    if (oldPercent < 55 || oldPercent > 85) {
+      throw new Error(`Old staker should get ~72%, got ${oldPercent}%`);
+    }
+    if (newPercent < 15 || newPercent > 45) {
+      throw new Error(`New staker should get ~28%, got ${newPercent}%`);
+    }
+  });
+
+  // ============================================
+  // ABUSE / SECURITY TESTS
+  // ============================================
+  // Verify the system cannot be gamed or exploited
+
+  console.log('\n--- Abuse / Security Tests ---\n');
+
+  // Test: Sybil attack - splitting stake doesn't give advantage
+  await test(`[${tokenProgramLabel}] Abuse: Sybil attack (split stake) gives no advantage`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Honest staker: 2 tokens in one account
+    const honest = Keypair.generate();
+    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
+    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
+    await ctx.mintTokens(honestToken, BigInt(2_000_000_000));
+    await ctx.stake(honest, honestToken, BigInt(2_000_000_000));
+
+    // Sybil attacker: 2 tokens split across 2 accounts (1 each)
+    const sybil1 = Keypair.generate();
+    const sybil2 = Keypair.generate();
+    await airdropAndConfirm(connection, sybil1.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, sybil2.publicKey, 2 * LAMPORTS_PER_SOL);
+    const sybil1Token = await ctx.createUserTokenAccount(sybil1.publicKey);
+    const sybil2Token = await ctx.createUserTokenAccount(sybil2.publicKey);
+    await ctx.mintTokens(sybil1Token, BigInt(1_000_000_000));
+    await ctx.mintTokens(sybil2Token, BigInt(1_000_000_000));
+    await ctx.stake(sybil1, sybil1Token, BigInt(1_000_000_000));
+    await ctx.stake(sybil2, sybil2Token, BigInt(1_000_000_000));
+
+    // Wait for weight accumulation (~22% at 15s/60s tau)
+    console.log(`    Waiting 15s for stakes to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 15000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim for all
+    const honestBefore = await ctx.getBalance(honest.publicKey);
+    await ctx.claimRewards(honest);
+    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+
+    const sybil1Before = await ctx.getBalance(sybil1.publicKey);
+    await ctx.claimRewards(sybil1);
+    const sybil1Reward = (await ctx.getBalance(sybil1.publicKey)) - sybil1Before;
+
+    const sybil2Before = await ctx.getBalance(sybil2.publicKey);
+    await ctx.claimRewards(sybil2);
+    const sybil2Reward = (await ctx.getBalance(sybil2.publicKey)) - sybil2Before;
+
+    const sybilTotal = sybil1Reward + sybil2Reward;
+
+    console.log(`    Honest (2 tokens): ${honestReward} lamports`);
+    console.log(`    Sybil (1+1 tokens): ${sybilTotal} lamports`);
+
+    // Sybil should NOT get more than honest staker
+    // Allow small variance for timing differences
+    const ratio = sybilTotal / honestReward;
+    console.log(`    Sybil/Honest ratio: ${ratio.toFixed(3)}`);
+
+    if (ratio > 1.1) {
+      throw new Error(`Sybil attack should not be profitable: ratio=${ratio}`);
+    }
+  });
+
+  // Test: Flash stake attack - staking right before deposit
+  await test(`[${tokenProgramLabel}] Abuse: Flash stake attack (stake before deposit) fails`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 so tx processing time (~2s) gives attacker minimal weight
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Honest staker stakes early
+    const honest = Keypair.generate();
+    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
+    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
+    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
+    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+
+    // Wait 20s for honest staker to accumulate weight (~28% at tau=60)
+    console.log(`    Waiting 20s for honest staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 20000));
+
+    // Attacker stakes right before deposit (flash stake)
+    const attacker = Keypair.generate();
+    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
+    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
+    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
+
+    // Deposit immediately after attacker stakes
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim for both
+    const honestBefore = await ctx.getBalance(honest.publicKey);
+    await ctx.claimRewards(honest);
+    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+
+    const attackerBefore = await ctx.getBalance(attacker.publicKey);
+    try {
+      await ctx.claimRewards(attacker);
+    } catch (e) {
+      // Expected - attacker has ~0 weight
+    }
+    const attackerReward = Math.max(0, (await ctx.getBalance(attacker.publicKey)) - attackerBefore);
+
+    console.log(`    Honest staker (20s age) reward: ${honestReward} lamports`);
+    console.log(`    Flash attacker (new) reward: ${attackerReward} lamports`);
+
+    // Honest staker should get the vast majority (~28% weight vs ~3%)
+    const honestShare = (honestReward * 100) / (honestReward + attackerReward);
+    console.log(`    Honest staker share: ${honestShare.toFixed(1)}%`);
+
+    // With τ=60s and 20s wait, honest has ~28% weight, attacker ~3%
+    // Honest share ≈ 28/(28+3) ≈ 90%
+    if (honestShare < 75) {
+      throw new Error(`Flash stake should not be profitable: honest=${honestShare}%`);
+    }
+  });
+
+  // Test: Cannot unstake more than staked
+  await test(`[${tokenProgramLabel}] Abuse: Cannot unstake more than staked`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Try to unstake 2x what was staked
+    let failed = false;
+    try {
+      await ctx.unstake(user, userToken, BigInt(2_000_000_000));
+    } catch (e: any) {
+      failed = true;
+      console.log(`    Correctly rejected: ${e.message?.includes('0xa') ? 'InsufficientStakeBalance' : 'error'}`);
+    }
+
+    if (!failed) {
+      throw new Error('Should not be able to unstake more than staked');
+    }
+  });
+
+  // Test: Cannot profit from claim after full unstake
+  await test(`[${tokenProgramLabel}] Abuse: Cannot claim after full unstake`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait for weight, deposit rewards
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim rewards first
+    await ctx.claimRewards(user);
+
+    // Fully unstake
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+
+    // Deposit more rewards (user has amount=0, should not benefit)
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim again — should succeed silently (amount==0 path returns Ok)
+    // but should give the user 0 SOL from the new deposit
+    const balBefore = BigInt(await ctx.getBalance(user.publicKey));
+    await ctx.claimRewards(user);
+    const balAfter = BigInt(await ctx.getBalance(user.publicKey));
+
+    // User should gain nothing (or lose tx fee)
+    const gained = balAfter - balBefore;
+    if (gained > BigInt(0)) {
+      throw new Error(`User gained ${gained} lamports after full unstake — should be 0`);
+    }
+  });
+
+  // Test: Cannot double claim
+  await test(`[${tokenProgramLabel}] Abuse: Cannot double claim same rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait and deposit
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // First claim
+    const balance1 = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const reward1 = (await ctx.getBalance(user.publicKey)) - balance1;
+    console.log(`    First claim: ${reward1} lamports`);
+
+    // Second claim — with max-weight denominator, weight maturation between claims
+    // yields additional rewards. This is NOT double-claiming: the user is entitled
+    // to more as their stake matures. Verify conservation instead.
+    const balance2 = await ctx.getBalance(user.publicKey);
+    try {
+      await ctx.claimRewards(user);
+    } catch (e: any) {
+      if (!e.message?.includes('0xb')) throw e;
+    }
+    const reward2 = (await ctx.getBalance(user.publicKey)) - balance2;
+    console.log(`    Second claim: ${reward2} lamports`);
+
+    // Second claim should be strictly less than first (diminishing returns)
+    if (reward2 >= reward1) {
+      throw new Error(`Possible double-claim: reward2 (${reward2}) >= reward1 (${reward1})`);
+    }
+
+    // Conservation: total claimed must not exceed deposit
+    const totalClaimed = reward1 + reward2;
+    const depositAmount = LAMPORTS_PER_SOL;
+    if (totalClaimed > depositAmount) {
+      throw new Error(`Over-distribution: claimed ${totalClaimed} > deposited ${depositAmount}`);
+    }
+    console.log(`    Conservation OK: claimed ${reward1 + reward2} <= deposited ${depositAmount}`);
+  });
+
+  // Test: Stake/unstake cycling doesn't reset weight unfairly
+  await test(`[${tokenProgramLabel}] Abuse: Stake/unstake cycling resets weight`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Honest staker - stakes and holds
+    const honest = Keypair.generate();
+    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
+    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
+    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
+    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+
+    // Cycler - stakes, waits, unstakes, restakes (trying to game)
+    const cycler = Keypair.generate();
+    await airdropAndConfirm(connection, cycler.publicKey, 2 * LAMPORTS_PER_SOL);
+    const cyclerToken = await ctx.createUserTokenAccount(cycler.publicKey);
+    await ctx.mintTokens(cyclerToken, BigInt(1_000_000_000));
+    await ctx.stake(cycler, cyclerToken, BigInt(1_000_000_000));
+
+    // Wait 10s (honest gets ~15.4% weight at tau=60)
+    console.log(`    Waiting 10s...`);
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Cycler unstakes and restakes (resets their weight!)
+    await ctx.unstake(cycler, cyclerToken, BigInt(1_000_000_000));
+    await ctx.stake(cycler, cyclerToken, BigInt(1_000_000_000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim
+    const honestBefore = await ctx.getBalance(honest.publicKey);
+    await ctx.claimRewards(honest);
+    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+
+    const cyclerBefore = await ctx.getBalance(cycler.publicKey);
+    try {
+      await ctx.claimRewards(cycler);
+    } catch (e: any) {
+      if (!e.message?.includes('0xb')) throw e;
+    }
+    const cyclerReward = (await ctx.getBalance(cycler.publicKey)) - cyclerBefore;
+    if (cyclerReward < 0) throw new Error(`Unexpected SOL loss: ${cyclerReward}`);
+
+    console.log(`    Honest (held 10s): ${honestReward} lamports`);
+    console.log(`    Cycler (reset weight): ${cyclerReward} lamports`);
+
+    // Honest should get more since cycler reset their weight
+    if (honestReward <= cyclerReward) {
+      throw new Error(`Cycling should reset weight: honest=${honestReward}, cycler=${cyclerReward}`);
+    }
+  });
+
+  // Test: Zero amount operations fail
+  await test(`[${tokenProgramLabel}] Abuse: Zero amount operations rejected`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+
+    // Try zero stake
+    let zeroStakeFailed = false;
+    try {
+      await ctx.stake(user, userToken, BigInt(0));
+    } catch (e) {
+      zeroStakeFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0xe')) {
+        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
+      }
+    }
+
+    // Stake normally first
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Try zero unstake
+    let zeroUnstakeFailed = false;
+    try {
+      await ctx.unstake(user, userToken, BigInt(0));
+    } catch (e) {
+      zeroUnstakeFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0xe')) {
+        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
+      }
+    }
+
+    // Try zero deposit
+    let zeroDepositFailed = false;
+    try {
+      await ctx.depositRewards(BigInt(0));
+    } catch (e) {
+      zeroDepositFailed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0xe')) {
+        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
+      }
+    }
+
+    console.log(`    Zero stake rejected: ${zeroStakeFailed}`);
+    console.log(`    Zero unstake rejected: ${zeroUnstakeFailed}`);
+    console.log(`    Zero deposit rejected: ${zeroDepositFailed}`);
+
+    if (!zeroStakeFailed) {
+      throw new Error('Zero stake should be rejected');
+    }
+    if (!zeroUnstakeFailed) {
+      throw new Error('Zero unstake should be rejected');
+    }
+    if (!zeroDepositFailed) {
+      throw new Error('Zero deposit should be rejected');
+    }
+  });
+
+  // Test: Frontrunning deposit with equal stake
+  // Note: With vastly different stake amounts, the attacker may still win on absolute weight
+  // This test verifies that equal stakes favor the mature staker
+  await test(`[${tokenProgramLabel}] Abuse: Frontrunning deposit (equal stakes)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 so tx processing time (~2s) gives attacker minimal weight
+    const tauSeconds = BigInt(60);
+    await ctx.initializePool(tauSeconds);
+
+    // Honest staker
+    const honest = Keypair.generate();
+    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
+    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
+    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
+    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+
+    // Wait 20s for honest staker to accumulate weight (~28% at tau=60)
+    console.log(`    Waiting 20s for honest staker to accumulate weight...`);
+    await new Promise(r => setTimeout(r, 20000));
+
+    // Frontrunner stakes equal amount right before deposit
+    const attacker = Keypair.generate();
+    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
+    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
+    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
+
+    // Deposit immediately
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Claim
+    const honestBefore = await ctx.getBalance(honest.publicKey);
+    await ctx.claimRewards(honest);
+    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+
+    const attackerBefore = await ctx.getBalance(attacker.publicKey);
+    try {
+      await ctx.claimRewards(attacker);
+    } catch (e: any) {
+      if (!e.message?.includes('0xb')) throw e;
+    }
+    const attackerReward = (await ctx.getBalance(attacker.publicKey)) - attackerBefore;
+    if (attackerReward < 0) throw new Error(`Unexpected SOL loss: ${attackerReward}`);
+
+    const total = honestReward + attackerReward;
+    const honestShare = (honestReward * 100) / total;
+
+    console.log(`    Honest (1 token, 20s age): ${honestShare.toFixed(1)}% (${honestReward} lamports)`);
+    console.log(`    Attacker (1 token, new): ${(100-honestShare).toFixed(1)}% (${attackerReward} lamports)`);
+
+    // With τ=60s and 20s wait, honest has ~28% weight, attacker ~3%
+    // Honest share ≈ 28/(28+3) ≈ 90%
+    if (honestShare < 75) {
+      throw new Error(`Frontrunning should not be profitable: honest only got ${honestShare}%`);
+    }
+  });
+
+  // ============================================
+  // SECURITY REGRESSION TESTS
+  // ============================================
+  // Verify fixes for identified security audit findings
+
+  console.log('\n--- Security Regression Tests ---\n');
+
+  // Test: DepositRewards + SyncRewards does not double-count
+  await test(`[${tokenProgramLabel}] Security: DepositRewards + SyncRewards does not double-count`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait for weight to accumulate (~15% at 10s/60s tau)
+    console.log('    Waiting 10s for weight...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit via instruction
+    const depositAmount = BigInt(LAMPORTS_PER_SOL);
+    await ctx.depositRewards(depositAmount);
+
+    // Call sync_rewards - should find NO new rewards (deposit already accounted for)
+    await ctx.syncRewards();
+
+    // Claim - user should get approximately depositAmount, NOT 2x
+    const balanceBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const balanceAfter = await ctx.getBalance(user.publicKey);
+    const claimed = BigInt(balanceAfter - balanceBefore);
+
+    console.log(`    Deposited: ${depositAmount} lamports`);
+    console.log(`    Claimed: ${claimed} lamports`);
+
+    // Claimed should be <= depositAmount (accounting for tx fees and weight < 100%)
+    // If double-counted, claimed would be close to 2x depositAmount
+    if (claimed > depositAmount + BigInt(6000)) {
+      throw new Error(`Double-counting detected! Claimed ${claimed} > deposited ${depositAmount}`);
+    }
+  });
+
+  // Test: Additional stake does not allow reward theft
+  await test(`[${tokenProgramLabel}] Security: Additional stake does not allow reward theft`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const attacker = Keypair.generate();
+    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
+    const smallStake = BigInt(1_000); // tiny initial stake
+    const largeStake = BigInt(1_000_000_000); // 1 billion additional
+    await ctx.mintTokens(attackerToken, smallStake + largeStake);
+
+    // Stake small amount
+    await ctx.stake(attacker, attackerToken, smallStake);
+
+    // Wait for weight to accumulate
+    console.log('    Waiting 10s for weight...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit rewards
+    const depositAmount = BigInt(LAMPORTS_PER_SOL);
+    await ctx.depositRewards(depositAmount);
+
+    // Now add large stake (attack vector: reward_debt stays at old small-stake value)
+    await ctx.stake(attacker, attackerToken, largeStake);
+
+    // Claim - should get rewards proportional to the ORIGINAL small stake, not the new large stake
+    const balanceBefore = await ctx.getBalance(attacker.publicKey);
+    try {
+      await ctx.claimRewards(attacker);
+    } catch (e) {
+      // May fail if no pending rewards (correct behavior)
+    }
+    const balanceAfter = await ctx.getBalance(attacker.publicKey);
+    const claimed = BigInt(balanceAfter - balanceBefore);
+
+    console.log(`    Small stake: ${smallStake}, Large additional: ${largeStake}`);
+    console.log(`    Deposited: ${depositAmount} lamports`);
+    console.log(`    Claimed: ${claimed} lamports`);
+
+    // With the fix, reward_debt is recalculated on additional stake,
+    // so the attacker shouldn't be able to claim more than what the small stake earned.
+    // A reasonable upper bound: all rewards (since they were the only staker).
+    // But claimed should NOT be inflated by the large stake addition.
+    // The key check: claimed should be roughly proportional to the deposit, not vastly more.
+    if (claimed > depositAmount + BigInt(6000)) {
+      throw new Error(`Reward theft detected! Claimed ${claimed} >> deposited ${depositAmount}`);
+    }
+  });
+
+  // Test: Claim then SyncRewards works for new deposits
+  await test(`[${tokenProgramLabel}] Security: Claim then SyncRewards works for new deposits`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait for weight
+    console.log('    Waiting 10s for weight...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit and claim
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    const bal1Before = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const claim1 = BigInt((await ctx.getBalance(user.publicKey)) - bal1Before);
+    console.log(`    First claim: ${claim1} lamports`);
+
+    // Send SOL directly to pool (simulating pump.fun)
+    const directAmount = BigInt(LAMPORTS_PER_SOL / 2);
+    await ctx.sendSolToPool(directAmount);
+
+    // Sync rewards - should detect the new direct SOL
+    await ctx.syncRewards();
+
+    // Claim again - should get the new rewards
+    const bal2Before = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const claim2 = BigInt((await ctx.getBalance(user.publicKey)) - bal2Before);
+    console.log(`    Second claim (after direct SOL + sync): ${claim2} lamports`);
+
+    // Second claim should be > 0 (new rewards were synced)
+    if (claim2 <= BigInt(0)) {
+      throw new Error(`SyncRewards failed to detect new deposits after claim! Got ${claim2}`);
+    }
+  });
+
+  // Test: Unstake rewards then SyncRewards works
+  await test(`[${tokenProgramLabel}] Security: Unstake rewards then SyncRewards works`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    await ctx.stake(user, userToken, BigInt(2_000_000_000));
+
+    // Wait for weight
+    console.log('    Waiting 10s for weight...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit rewards
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    // Unstake half (this claims pending rewards and reduces last_synced_lamports)
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+
+    // Send more SOL directly to pool — needs to be large enough to overcome the
+    // immature weight deficit (reward_debt set at max weight during unstake).
+    // At tau=60 with ~15% weight, delta must exceed ~4.5x the old acc_rps.
+    const directAmount = BigInt(5 * LAMPORTS_PER_SOL);
+    await ctx.sendSolToPool(directAmount);
+
+    // Sync rewards - should detect the new direct SOL
+    await ctx.syncRewards();
+
+    // Claim - should get the new rewards
+    const balBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const claimed = BigInt((await ctx.getBalance(user.publicKey)) - balBefore);
+    console.log(`    Claim after unstake + new SOL + sync: ${claimed} lamports`);
+
+    // Claimed should be > 0
+    if (claimed <= BigInt(0)) {
+      throw new Error(`SyncRewards failed after unstake! Got ${claimed}`);
+    }
+  });
+
+  // ============================================
+  // STRESS TESTS: Simulate 1 million stakers
+  // ============================================
+  // Since operations are O(1), complexity depends on value magnitudes, not staker count.
+  // We test with values representing: 1M stakers × 1000 tokens each = 1 trillion tokens
+  // This verifies the math handles large aggregates without overflow or excessive compute.
+
+  console.log('\n--- Stress Tests (simulating 1M stakers) ---\n');
+
+  // Test: Large stake amount (simulating pool with many existing stakers)
+  await test(`[${tokenProgramLabel}] Stress: Stake with large existing total`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000)); // 30 days tau
+
+    // First, create a "whale" that represents aggregate of ~1M stakers
+    // 1M stakers × 1000 tokens × 10^9 decimals = 10^18 raw tokens
+    const whale = Keypair.generate();
+    await airdropAndConfirm(connection, whale.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
+    // Mint a large amount representing aggregate stake
+    // Using 10^15 (1 quadrillion) as a large but safe value
+    const largeAmount = BigInt('1000000000000000'); // 10^15
+    await ctx.mintTokens(whaleToken, largeAmount);
+
+    const startTime = Date.now();
+    await ctx.stake(whale, whaleToken, largeAmount);
+    const stakeTime = Date.now() - startTime;
+    console.log(`    Large stake (${largeAmount} tokens): ${stakeTime}ms`);
+
+    // Now test a regular user staking against this large pool
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const userAmount = BigInt(1_000_000_000); // 1 token (normal stake)
+    await ctx.mintTokens(userToken, userAmount);
+
+    const startTime2 = Date.now();
+    await ctx.stake(user, userToken, userAmount);
+    const stakeTime2 = Date.now() - startTime2;
+    console.log(`    Regular stake against large pool: ${stakeTime2}ms`);
+
+    // Verify both stakes exist
+    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    if (vaultBalance !== largeAmount + userAmount) {
+      throw new Error(`Expected ${largeAmount + userAmount}, got ${vaultBalance}`);
+    }
+  });
+
+  // Test: Deposit rewards with large total stake
+  await test(`[${tokenProgramLabel}] Stress: Deposit rewards with 1M staker simulation`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100)); // Short tau for weight accumulation
+
+    // Create large aggregate stake
+    const whale = Keypair.generate();
+    await airdropAndConfirm(connection, whale.publicKey, 5 * LAMPORTS_PER_SOL);
+
+    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
+    const largeAmount = BigInt('1000000000000000'); // 10^15
+    await ctx.mintTokens(whaleToken, largeAmount);
+    await ctx.stake(whale, whaleToken, largeAmount);
+
+    // Wait a bit for weight to accumulate
+    await new Promise(r => setTimeout(r, 1000));
+
+    // Deposit reasonable reward (5 SOL) - tests program handles large stake values
+    const rewardAmount = BigInt(5 * LAMPORTS_PER_SOL);
+    const startTime = Date.now();
+    await ctx.depositRewards(rewardAmount);
+    const depositTime = Date.now() - startTime;
+    console.log(`    Deposit ${rewardAmount} lamports to pool with 10^15 tokens: ${depositTime}ms`);
+  });
+
+  // Test: Claim rewards with large accumulated rewards
+  await test(`[${tokenProgramLabel}] Stress: Claim with large reward accumulator`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const stakeAmount = BigInt('100000000000000'); // 10^14
+    await ctx.mintTokens(userToken, stakeAmount);
+    await ctx.stake(user, userToken, stakeAmount);
+
+    // Wait for weight
+    await new Promise(r => setTimeout(r, 500));
+
+    // Multiple deposits to accumulate large reward per share
+    for (let i = 0; i < 5; i++) {
+      await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    }
+
+    // Claim rewards
+    const startTime = Date.now();
+    await ctx.claimRewards(user);
+    const claimTime = Date.now() - startTime;
+    console.log(`    Claim from large accumulator: ${claimTime}ms`);
+  });
+
+  // Test: Unstake with large pool values
+  await test(`[${tokenProgramLabel}] Stress: Unstake with large pool values`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const largeAmount = BigInt('500000000000000'); // 5×10^14
+    await ctx.mintTokens(userToken, largeAmount);
+    await ctx.stake(user, userToken, largeAmount);
+
+    // Partial unstake
+    const unstakeAmount = BigInt('250000000000000'); // Half
+    const startTime = Date.now();
+    await ctx.unstake(user, userToken, unstakeAmount);
+    const unstakeTime = Date.now() - startTime;
+    console.log(`    Large partial unstake (${unstakeAmount}): ${unstakeTime}ms`);
+
+    // Full unstake remaining
+    const startTime2 = Date.now();
+    await ctx.unstake(user, userToken, unstakeAmount);
+    const unstakeTime2 = Date.now() - startTime2;
+    console.log(`    Full unstake remaining: ${unstakeTime2}ms`);
+
+    const balance = await ctx.getTokenBalance(userToken);
+    if (balance !== largeAmount) {
+      throw new Error(`Expected ${largeAmount}, got ${balance}`);
+    }
+  });
+
+  // Test: SyncRewards with large pool
+  await test(`[${tokenProgramLabel}] Stress: SyncRewards with large pool`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const whale = Keypair.generate();
+    await airdropAndConfirm(connection, whale.publicKey, 3 * LAMPORTS_PER_SOL);
+
+    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
+    const largeAmount = BigInt('1000000000000000');
+    await ctx.mintTokens(whaleToken, largeAmount);
+    await ctx.stake(whale, whaleToken, largeAmount);
+
+    // Wait for weight
+    await new Promise(r => setTimeout(r, 1000));
+
+    // Send SOL directly to pool (5 SOL, simulating pump.fun fees)
+    await ctx.sendSolToPool(BigInt(5 * LAMPORTS_PER_SOL));
+
+    const startTime = Date.now();
+    await ctx.syncRewards();
+    const syncTime = Date.now() - startTime;
+    console.log(`    SyncRewards with 5 SOL on 10^15 token pool: ${syncTime}ms`);
+  });
+
+  // Test: Many sequential operations (verify no state bloat)
+  await test(`[${tokenProgramLabel}] Stress: Sequential operations (no state bloat)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // Minimum tau
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 5 * LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt('10000000000000')); // 10^13
+
+    // Initial stake that stays throughout to keep pool active
+    const baseStake = BigInt('1000000000000'); // 10^12
+    await ctx.stake(user, userToken, baseStake);
+
+    // Wait for weight to accumulate
+    await new Promise(r => setTimeout(r, 1000));
+
+    const iterations = 5; // Reduced for faster tests
+    const times: number[] = [];
+
+    for (let i = 0; i < iterations; i++) {
+      const additionalAmount = BigInt(100_000_000) * BigInt(i + 1);
+
+      const start = Date.now();
+      // Add more stake
+      await ctx.stake(user, userToken, additionalAmount);
+      // Deposit small reward
+      await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 100));
+      // Claim
+      await ctx.claimRewards(user);
+      // Remove only the additional stake (keep base stake)
+      await ctx.unstake(user, userToken, additionalAmount);
+      times.push(Date.now() - start);
+    }
+
+    // Unstake the base amount at the end
+    await ctx.unstake(user, userToken, baseStake);
+
+    const avgTime = times.reduce((a, b) => a + b, 0) / times.length;
+    const maxTime = Math.max(...times);
+    const minTime = Math.min(...times);
+
+    console.log(`    ${iterations} cycles - avg: ${avgTime.toFixed(0)}ms, min: ${minTime}ms, max: ${maxTime}ms`);
+
+    // Verify operations are O(1) - time should not grow significantly
+    if (maxTime > minTime * 3) {
+      console.log(`    Note: Variance may be due to network, not algorithm`);
+    }
+  });
+
+  // Test: Compute units estimation via simulation
+  await test(`[${tokenProgramLabel}] Stress: Compute units check`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(100));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt('1000000000000000'));
+    await ctx.stake(user, userToken, BigInt('1000000000000000'));
+
+    await new Promise(r => setTimeout(r, 500));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Create a transaction to simulate
+    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+    const claimIx = createClaimRewardsInstruction(ctx.poolPDA, userStakePDA, user.publicKey);
+    const tx = new Transaction().add(claimIx);
+    tx.feePayer = ctx.payer.publicKey;
+    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+    tx.sign(ctx.payer, user);
+
+    const simulation = await connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`Simulation failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const unitsUsed = simulation.value.unitsConsumed || 0;
+
+    console.log(`    Claim instruction compute units: ${unitsUsed}`);
+
+    // Solana limit is 200,000 CU per instruction
+    if (unitsUsed > 200000) {
+      throw new Error(`Compute units (${unitsUsed}) exceed instruction limit (200,000)`);
+    }
+
+    // Our target is under 100,000 CU for efficiency
+    if (unitsUsed > 100000) {
+      console.log(`    WARNING: High compute usage, consider optimization`);
+    }
+  });
+
+  // ============================================================
+  // Lifecycle Test: Multi-phase staking with full reconciliation
+  // ============================================================
+
+  await test(`[${tokenProgramLabel}] Lifecycle: Multi-phase staking with full reconciliation`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9); // 9 decimals
+    await ctx.initializePool(BigInt(60)); // tau = 60 seconds (minimum)
+
+    const BILLION = BigInt(1_000_000_000); // 1 token with 9 decimals
+
+    // --- Setup 4 stakers ---
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    const carol = Keypair.generate();
+    const dave = Keypair.generate();
+
+    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, bob.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, carol.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, dave.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
+    const carolToken = await ctx.createUserTokenAccount(carol.publicKey);
+    const daveToken = await ctx.createUserTokenAccount(dave.publicKey);
+
+    // Mint tokens: Alice=10B, Bob=5B, Carol=8B, Dave=3B
+    const aliceAmount = BigInt(10) * BILLION;
+    const bobAmount = BigInt(5) * BILLION;
+    const carolAmount = BigInt(8) * BILLION;
+    const daveAmount = BigInt(3) * BILLION;
+
+    await ctx.mintTokens(aliceToken, aliceAmount);
+    await ctx.mintTokens(bobToken, bobAmount);
+    await ctx.mintTokens(carolToken, carolAmount);
+    await ctx.mintTokens(daveToken, daveAmount);
+
+    // Track total SOL rewards deposited and received per user
+    let totalDeposited = BigInt(0);
+    const rewards: Record<string, bigint> = { alice: BigInt(0), bob: BigInt(0), carol: BigInt(0), dave: BigInt(0) };
+
+    // Helper: measure SOL reward from an operation
+    async function measureReward(name: string, user: Keypair, op: () => Promise<any>): Promise<bigint> {
+      const before = BigInt(await ctx.getBalance(user.publicKey));
+      await op();
+      const after = BigInt(await ctx.getBalance(user.publicKey));
+      // Payer covers tx fee, so balance diff = pure reward
+      const reward = after - before;
+      if (reward > BigInt(0)) {
+        rewards[name] += reward;
+      }
+      return reward;
+    }
+
+    // ========== PHASE 1 (t=0): Initial stakes + deposit ==========
+    console.log('    Phase 1: Initial stakes + deposit 1 SOL');
+    await ctx.stake(alice, aliceToken, aliceAmount); // Alice stakes 10B
+    await ctx.stake(bob, bobToken, BigInt(3) * BILLION); // Bob stakes 3B
+    await ctx.stake(carol, carolToken, carolAmount); // Carol stakes 8B
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL)); // Deposit 1 SOL
+    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+
+    // ========== PHASE 2 (t+6s): Send SOL + sync + Alice claims ==========
+    console.log('    Phase 2: Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    await ctx.syncPool(); // Rebase before weighted operations
+    await ctx.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 2)); // 0.5 SOL direct
+    await ctx.syncRewards();
+    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+
+    const aliceReward1 = await measureReward('alice', alice, () => ctx.claimRewards(alice));
+    console.log(`    Alice claim #1: ${aliceReward1} lamports`);
+
+    // ========== PHASE 3 (t+12s): Dave stakes, Bob adds stake (auto-claim), deposit, Carol partial unstake ==========
+    console.log('    Phase 3: Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    await ctx.syncPool(); // Rebase before weighted operations
+    await ctx.stake(dave, daveToken, daveAmount); // Dave stakes 3B
+
+    // Bob adds 2B more (no auto-claim — pending stays the same)
+    await ctx.stake(bob, bobToken, BigInt(2) * BILLION);
+    console.log(`    Bob added 2B more tokens`);
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL)); // Deposit 1 SOL
+    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+
+    // Carol partial unstake 3B (claims rewards too)
+    const carolReward1 = await measureReward('carol', carol, () =>
+      ctx.unstake(carol, carolToken, BigInt(3) * BILLION)
+    );
+    console.log(`    Carol partial unstake reward: ${carolReward1} lamports`);
+
+    // ========== PHASE 4 (t+18s): Deposit + Dave claims + Bob claims ==========
+    console.log('    Phase 4: Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    await ctx.syncPool(); // Rebase before weighted operations
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2)); // Deposit 0.5 SOL
+    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+
+    const daveReward1 = await measureReward('dave', dave, () => ctx.claimRewards(dave));
+    console.log(`    Dave claim: ${daveReward1} lamports`);
+
+    const bobReward2 = await measureReward('bob', bob, () => ctx.claimRewards(bob));
+    console.log(`    Bob claim: ${bobReward2} lamports`);
+
+    // ========== PHASE 5 (t+24s): Dave full unstake + deposit ==========
+    console.log('    Phase 5: Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    await ctx.syncPool(); // Rebase before weighted operations
+    const daveReward2 = await measureReward('dave', dave, () =>
+      ctx.unstake(dave, daveToken, daveAmount)
+    );
+    console.log(`    Dave full unstake reward: ${daveReward2} lamports`);
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2)); // Deposit 0.5 SOL
+    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+
+    // ========== PHASE 6 (t+30s): Everyone exits ==========
+    console.log('    Phase 6: Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    await ctx.syncPool(); // Rebase before weighted operations
+
+    // Alice: claim + unstake
+    const aliceReward2 = await measureReward('alice', alice, () => ctx.claimRewards(alice));
+    console.log(`    Alice claim #2: ${aliceReward2} lamports`);
+    const aliceReward3 = await measureReward('alice', alice, () =>
+      ctx.unstake(alice, aliceToken, aliceAmount)
+    );
+    console.log(`    Alice unstake reward: ${aliceReward3} lamports`);
+
+    // Bob: full unstake (5B = 3B + 2B)
+    const bobReward3 = await measureReward('bob', bob, () =>
+      ctx.unstake(bob, bobToken, BigInt(5) * BILLION)
+    );
+    console.log(`    Bob full unstake reward: ${bobReward3} lamports`);
+
+    // Carol: claim + unstake remaining 5B
+    const carolReward2 = await measureReward('carol', carol, () => ctx.claimRewards(carol));
+    console.log(`    Carol claim: ${carolReward2} lamports`);
+    const carolReward3 = await measureReward('carol', carol, () =>
+      ctx.unstake(carol, carolToken, BigInt(5) * BILLION)
+    );
+    console.log(`    Carol unstake reward: ${carolReward3} lamports`);
+
+    // ========== PHASE 7: Reconciliation ==========
+    console.log('    --- Reconciliation ---');
+
+    // 1. Token conservation: all tokens returned, vault empty
+    const aliceFinal = await ctx.getTokenBalance(aliceToken);
+    const bobFinal = await ctx.getTokenBalance(bobToken);
+    const carolFinal = await ctx.getTokenBalance(carolToken);
+    const daveFinal = await ctx.getTokenBalance(daveToken);
+    const vaultFinal = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+
+    if (aliceFinal !== aliceAmount) throw new Error(`Alice tokens: expected ${aliceAmount}, got ${aliceFinal}`);
+    if (bobFinal !== bobAmount) throw new Error(`Bob tokens: expected ${bobAmount}, got ${bobFinal}`);
+    if (carolFinal !== carolAmount) throw new Error(`Carol tokens: expected ${carolAmount}, got ${carolFinal}`);
+    if (daveFinal !== daveAmount) throw new Error(`Dave tokens: expected ${daveAmount}, got ${daveFinal}`);
+    if (vaultFinal !== BigInt(0)) throw new Error(`Vault should be empty, got ${vaultFinal}`);
+    console.log('    Token conservation: OK');
+
+    // 2. SOL conservation: totalReceived + poolRemaining ~= totalDeposited
+    const totalReceived = rewards.alice + rewards.bob + rewards.carol + rewards.dave;
+    const poolRemaining = BigInt(await ctx.getBalance(ctx.poolPDA));
+    // Pool has rent-exempt balance too, so we check: received + (poolRemaining - rent) ~= deposited
+    // More practically: received <= deposited (no SOL created from nothing)
+    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
+    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
+    const poolRewardsRemaining = poolRemaining - rentExempt;
+    const accounted = totalReceived + poolRewardsRemaining;
+    const tolerance = BigInt(100_000); // 100K lamports for WAD rounding
+
+    console.log(`    Total deposited:  ${totalDeposited} lamports (${Number(totalDeposited) / LAMPORTS_PER_SOL} SOL)`);
+    console.log(`    Total received:   ${totalReceived} lamports (${Number(totalReceived) / LAMPORTS_PER_SOL} SOL)`);
+    console.log(`    Pool remaining:   ${poolRewardsRemaining} lamports`);
+    console.log(`    Accounted:        ${accounted} lamports`);
+
+    const diff = accounted > totalDeposited ? accounted - totalDeposited : totalDeposited - accounted;
+    if (diff > tolerance) {
+      throw new Error(`SOL conservation violated: deposited=${totalDeposited}, accounted=${accounted}, diff=${diff} (tolerance=${tolerance})`);
+    }
+    console.log(`    SOL conservation: OK (diff=${diff} lamports, tolerance=${tolerance})`);
+
+    // 3. Sanity: every user received some rewards
+    for (const [name, amount] of Object.entries(rewards)) {
+      if (amount <= BigInt(0)) {
+        throw new Error(`${name} received zero rewards`);
+      }
+      console.log(`    ${name} total rewards: ${amount} lamports`);
+    }
+
+    // 4. Fairness: Alice (largest + longest) > Dave (smallest + shortest)
+    if (rewards.alice <= rewards.dave) {
+      throw new Error(`Fairness check failed: Alice (${rewards.alice}) should earn more than Dave (${rewards.dave})`);
+    }
+    console.log(`    Fairness check: OK (Alice=${rewards.alice} > Dave=${rewards.dave})`);
+  });
+
+  // ============================================
+  // SOL CONSERVATION & RECOVERY TESTS
+  // ============================================
+  // Verify no SOL is lost (dead/stranded) from additional stakes.
+
+  console.log('\n--- SOL Conservation & Recovery Tests ---\n');
+
+  // Test: Additional stakes don't create dead SOL (round 7 fix regression test)
+  await test(`[${tokenProgramLabel}] Conservation: additional stakes preserve all rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
+
+    const staker1 = Keypair.generate();
+    const staker2 = Keypair.generate();
+    await airdropAndConfirm(connection, staker1.publicKey, 3 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, staker2.publicKey, 3 * LAMPORTS_PER_SOL);
+
+    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
+    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
+    await ctx.mintTokens(token1, BigInt(2_000_000_000));
+    await ctx.mintTokens(token2, BigInt(2_000_000_000));
+
+    // Both stake 1B tokens
+    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
+    await ctx.stake(staker2, token2, BigInt(1_000_000_000));
+
+    // Wait for partial maturity (~15% weight at 10s/60s tau)
+    console.log('    Waiting 10s for partial weight accumulation...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit 1 SOL
+    const deposit1 = BigInt(LAMPORTS_PER_SOL);
+    await ctx.depositRewards(deposit1);
+
+    // Staker1 does additional stake (this was the bug vector)
+    // The auto-claim should pay actual_pending, and stranded should be returned to pool
+    let totalClaimed = BigInt(0);
+    let bal0 = BigInt(await ctx.getBalance(staker1.publicKey));
+    await ctx.stake(staker1, token1, BigInt(500_000_000));
+    let bal0After = BigInt(await ctx.getBalance(staker1.publicKey));
+    // Additional stake auto-claims rewards — track that SOL
+    totalClaimed += (bal0After - bal0);
+
+    // Wait for more weight accumulation
+    console.log('    Waiting 10s for more weight...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit 1 more SOL
+    const deposit2 = BigInt(LAMPORTS_PER_SOL);
+    await ctx.depositRewards(deposit2);
+
+    const totalDeposited = deposit1 + deposit2;
+
+    // Both claim and fully unstake
+
+    // Staker1: claim + unstake
+    let bal = BigInt(await ctx.getBalance(staker1.publicKey));
+    await ctx.claimRewards(staker1);
+    let balAfter = BigInt(await ctx.getBalance(staker1.publicKey));
+    totalClaimed += (balAfter - bal);
+
+    bal = BigInt(await ctx.getBalance(staker1.publicKey));
+    await ctx.unstake(staker1, token1, BigInt(1_500_000_000));
+    balAfter = BigInt(await ctx.getBalance(staker1.publicKey));
+    totalClaimed += (balAfter - bal); // reward portion from unstake
+
+    // Staker2: claim + unstake
+    bal = BigInt(await ctx.getBalance(staker2.publicKey));
+    await ctx.claimRewards(staker2);
+    balAfter = BigInt(await ctx.getBalance(staker2.publicKey));
+    totalClaimed += (balAfter - bal);
+
+    bal = BigInt(await ctx.getBalance(staker2.publicKey));
+    await ctx.unstake(staker2, token2, BigInt(1_000_000_000));
+    balAfter = BigInt(await ctx.getBalance(staker2.publicKey));
+    totalClaimed += (balAfter - bal);
+
+    // Check pool remaining
+    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
+    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
+    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
+    const poolRewardsRemaining = poolBalance - rentExempt;
+
+    const accounted = totalClaimed + poolRewardsRemaining;
+    const diff = accounted > totalDeposited
+      ? accounted - totalDeposited
+      : totalDeposited - accounted;
+
+    console.log(`    Total deposited:    ${totalDeposited} lamports`);
+    console.log(`    Total claimed:      ${totalClaimed} lamports`);
+    console.log(`    Pool remaining:     ${poolRewardsRemaining} lamports`);
+    console.log(`    Accounted:          ${accounted} lamports`);
+    console.log(`    Diff:               ${diff} lamports`);
+
+    // The deadSOL from the old bug would show up as a large poolRewardsRemaining
+    // (SOL stuck in pool that nobody can claim). With the fix, diff should be tiny.
+    const tolerance = BigInt(100_000); // 100K lamports for WAD rounding
+    if (diff > tolerance) {
+      throw new Error(`SOL conservation violated: deposited=${totalDeposited}, accounted=${accounted}, diff=${diff}`);
+    }
+
+    // Extra check: pool remaining should not exceed deposits.
+    // At tau=60 with short waits (~15% weight), most rewards remain in pool as
+    // stranded/unclaimed — this is expected behavior, not a bug. These rewards
+    // are eventually redistributed to all stakers. We just verify the pool
+    // isn't holding MORE than deposited (which would indicate SOL creation).
+    if (poolRewardsRemaining > totalDeposited + BigInt(100_000)) {
+      throw new Error(`Pool has more than deposited: ${poolRewardsRemaining} > ${totalDeposited}`);
+    }
+    console.log('    Conservation: OK');
+  });
+
+  // Test: total_reward_debt is tracked correctly through stake/claim/unstake lifecycle
+  await test(`[${tokenProgramLabel}] Recovery: total_reward_debt tracking across lifecycle`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
+
+    // Verify total_reward_debt starts at 0
+    let state = await ctx.readPoolState();
+    if (state.totalRewardDebt !== BigInt(0)) {
+      throw new Error(`Expected initial totalRewardDebt=0, got ${state.totalRewardDebt}`);
+    }
+
+    const staker1 = Keypair.generate();
+    const staker2 = Keypair.generate();
+    await airdropAndConfirm(connection, staker1.publicKey, 3 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, staker2.publicKey, 3 * LAMPORTS_PER_SOL);
+
+    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
+    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
+    await ctx.mintTokens(token1, BigInt(2_000_000_000));
+    await ctx.mintTokens(token2, BigInt(1_000_000_000));
+
+    // Stake staker1 first, deposit rewards, then stake staker2 (so staker2 has non-zero reward_debt)
+    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
+    state = await ctx.readPoolState();
+    const debtAfterStake1 = state.totalRewardDebt;
+    console.log(`    After staker1 stake: totalRewardDebt=${debtAfterStake1}`);
+
+    // Deposit rewards so acc_rps > 0 before staker2 stakes
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    console.log('    Waiting 10s for weight accumulation...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Sync so acc_rps reflects deposits
+    await ctx.syncRewards();
+
+    await ctx.stake(staker2, token2, BigInt(1_000_000_000));
+    state = await ctx.readPoolState();
+    const debtAfterStake2 = state.totalRewardDebt;
+    console.log(`    After staker2 stake: totalRewardDebt=${debtAfterStake2}`);
+
+    // total_reward_debt should increase (staker2 stakes with non-zero acc_rps → non-zero reward_debt)
+    if (debtAfterStake2 <= debtAfterStake1) {
+      throw new Error(`totalRewardDebt should increase after new stake with deposits: ${debtAfterStake1} -> ${debtAfterStake2}`);
+    }
+
+    // Claim — total_reward_debt stays unchanged (claim uses claimed_rewards_wad, not reward_debt)
+    const debtBeforeClaim = (await ctx.readPoolState()).totalRewardDebt;
+    await ctx.claimRewards(staker1);
+    const debtAfterClaim = (await ctx.readPoolState()).totalRewardDebt;
+    console.log(`    After staker1 claim: totalRewardDebt=${debtBeforeClaim} -> ${debtAfterClaim}`);
+
+    if (debtAfterClaim !== debtBeforeClaim) {
+      throw new Error(`totalRewardDebt should not change after claim: ${debtBeforeClaim} -> ${debtAfterClaim}`);
+    }
+
+    // Additional stake — should update total_reward_debt (subtract old, add new)
+    const debtBeforeAddStake = (await ctx.readPoolState()).totalRewardDebt;
+    await ctx.stake(staker1, token1, BigInt(500_000_000));
+    const debtAfterAddStake = (await ctx.readPoolState()).totalRewardDebt;
+    console.log(`    After staker1 additional stake: totalRewardDebt=${debtBeforeAddStake} -> ${debtAfterAddStake}`);
+
+    // Full unstake — should subtract staker2's reward_debt
+    const debtBeforeUnstake = (await ctx.readPoolState()).totalRewardDebt;
+    await ctx.unstake(staker2, token2, BigInt(1_000_000_000));
+    const debtAfterUnstake = (await ctx.readPoolState()).totalRewardDebt;
+    console.log(`    After staker2 full unstake: totalRewardDebt=${debtBeforeUnstake} -> ${debtAfterUnstake}`);
+
+    // After staker2 fully unstaked (reward_debt=0), total_reward_debt should decrease
+    if (debtAfterUnstake >= debtBeforeUnstake) {
+      throw new Error(`totalRewardDebt should decrease after full unstake: ${debtBeforeUnstake} -> ${debtAfterUnstake}`);
+    }
+
+    // Cleanup: staker1 unstakes
+    await ctx.unstake(staker1, token1, BigInt(1_500_000_000));
+
+    console.log('    total_reward_debt tracking: OK');
+  });
+
+  // Test: Multi-staker SOL accounting — sum of all claims never exceeds deposits
+  await test(`[${tokenProgramLabel}] Conservation: multi-staker claims never exceed deposits`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
+
+    const stakers: Keypair[] = [];
+    const tokens: PublicKey[] = [];
+    const numStakers = 4;
+
+    for (let i = 0; i < numStakers; i++) {
+      const s = Keypair.generate();
+      await airdropAndConfirm(connection, s.publicKey, 3 * LAMPORTS_PER_SOL);
+      const t = await ctx.createUserTokenAccount(s.publicKey);
+      const amount = BigInt((i + 1) * 1_000_000_000); // 1B, 2B, 3B, 4B
+      await ctx.mintTokens(t, amount);
+      await ctx.stake(s, t, amount);
+      stakers.push(s);
+      tokens.push(t);
+    }
+
+    let totalDeposited = BigInt(0);
+
+    // Phase 1: deposit, wait, claim
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+
+    console.log('    Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    // Staker 0 does additional stake (auto-claims rewards — track SOL)
+    await ctx.mintTokens(tokens[0], BigInt(500_000_000));
+    let autoClaimBal = BigInt(await ctx.getBalance(stakers[0].publicKey));
+    await ctx.stake(stakers[0], tokens[0], BigInt(500_000_000));
+    let autoClaimBalAfter = BigInt(await ctx.getBalance(stakers[0].publicKey));
+    let autoClaimedSOL = autoClaimBalAfter - autoClaimBal;
+    if (autoClaimedSOL < BigInt(0)) throw new Error(`Unexpected SOL loss on auto-claim: ${autoClaimedSOL}`);
+
+    // Phase 2: more deposits
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+
+    console.log('    Waiting 6s...');
+    await new Promise(r => setTimeout(r, 6000));
+
+    // Phase 3: everyone claims and unstakes
+    let totalClaimed = autoClaimedSOL;
+    for (let i = 0; i < numStakers; i++) {
+      // Claim
+      const bal = BigInt(await ctx.getBalance(stakers[i].publicKey));
+      try { await ctx.claimRewards(stakers[i]); } catch (e: any) { if (!e.message?.includes('0xb')) throw e; }
+      const balAfterClaim = BigInt(await ctx.getBalance(stakers[i].publicKey));
+      totalClaimed += (balAfterClaim - bal);
+
+      // Unstake — get the staked amount
+      const stakeAmount = i === 0
+        ? BigInt(1_500_000_000) // 1B + 500M
+        : BigInt((i + 1) * 1_000_000_000);
+      const balBeforeUnstake = BigInt(await ctx.getBalance(stakers[i].publicKey));
+      await ctx.unstake(stakers[i], tokens[i], stakeAmount);
+      const balAfterUnstake = BigInt(await ctx.getBalance(stakers[i].publicKey));
+      // Count only the reward portion of unstake (unstake also claims rewards)
+      totalClaimed += (balAfterUnstake - balBeforeUnstake);
+    }
+
+    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
+    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
+    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
+    const poolRemaining = poolBalance - rentExempt;
+    const accounted = totalClaimed + poolRemaining;
+
+    console.log(`    Deposited:     ${totalDeposited}`);
+    console.log(`    Total claimed: ${totalClaimed}`);
+    console.log(`    Pool remaining: ${poolRemaining}`);
+    console.log(`    Accounted:     ${accounted}`);
+
+    // Claims must never exceed deposits (no SOL created from nothing)
+    if (totalClaimed > totalDeposited + BigInt(50_000)) {
+      throw new Error(`Over-distribution! Claimed ${totalClaimed} > deposited ${totalDeposited}`);
+    }
+
+    // Conservation check
+    const diff = accounted > totalDeposited
+      ? accounted - totalDeposited
+      : totalDeposited - accounted;
+    const tolerance = BigInt(100_000);
+    if (diff > tolerance) {
+      throw new Error(`SOL conservation violated: diff=${diff} > tolerance=${tolerance}`);
+    }
+
+    // At tau=60 with short waits, most rewards remain as stranded (expected).
+    // Just verify pool doesn't hold more than deposited.
+    if (poolRemaining > totalDeposited + BigInt(100_000)) {
+      throw new Error(`Pool holds more than deposited: ${poolRemaining} > ${totalDeposited}`);
+    }
+
+    console.log(`    Conservation OK (diff=${diff} lamports)`);
+  });
+
+  // ==================== METADATA TESTS (Token 2022 only) ====================
+  if (tokenProgramId.equals(TOKEN_2022_PROGRAM_ID)) {
+
+  // Test: SetPoolMetadata creates metadata account
+  await test(`[${tokenProgramLabel}] SetPoolMetadata creates metadata account`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'Tibanne Thecat', 'ChiefPussy');
+    await ctx.initializePool(BigInt(2592000));
+
+    await ctx.setPoolMetadata();
+
+    const metadata = await ctx.readMetadata();
+    if (metadata.name !== 'Tibanne Thecat Staking Pool') {
+      throw new Error(`Expected name "Tibanne Thecat Staking Pool", got "${metadata.name}"`);
+    }
+    if (metadata.numTags !== 3) {
+      throw new Error(`Expected 3 tags, got ${metadata.numTags}`);
+    }
+    if (metadata.tags[0] !== '#stakingpool') {
+      throw new Error(`Expected tag 0 "#stakingpool", got "${metadata.tags[0]}"`);
+    }
+    if (metadata.tags[1] !== '#chiefstaker') {
+      throw new Error(`Expected tag 1 "#chiefstaker", got "${metadata.tags[1]}"`);
+    }
+    if (metadata.tags[2] !== '#chiefpussy') {
+      throw new Error(`Expected tag 2 "#chiefpussy", got "${metadata.tags[2]}"`);
+    }
+    const expectedUrl = `https://labs.chiefpussy.com/staking/${ctx.mint.toBase58()}`;
+    if (metadata.url !== expectedUrl) {
+      throw new Error(`Expected url "${expectedUrl}", got "${metadata.url}"`);
+    }
+    if (metadata.memberCount !== 0n) {
+      throw new Error(`Expected member_count 0, got ${metadata.memberCount}`);
+    }
+    if (metadata.pool.toBase58() !== ctx.poolPDA.toBase58()) {
+      throw new Error(`Pool mismatch in metadata`);
+    }
+  });
+
+  // Test: SetPoolMetadata is idempotent (re-calling updates without changing data)
+  await test(`[${tokenProgramLabel}] SetPoolMetadata is idempotent`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'TestToken', 'TEST');
+    await ctx.initializePool(BigInt(2592000));
+
+    await ctx.setPoolMetadata();
+    const meta1 = await ctx.readMetadata();
+
+    // Call again — should succeed and preserve same data
+    await ctx.setPoolMetadata();
+    const meta2 = await ctx.readMetadata();
+
+    if (meta1.name !== meta2.name) throw new Error('Name changed on re-call');
+    if (meta1.memberCount !== meta2.memberCount) throw new Error('member_count changed on re-call');
+    if (meta1.url !== meta2.url) throw new Error('URL changed on re-call');
+  });
+
+  // Test: SetPoolMetadata is permissionless
+  await test(`[${tokenProgramLabel}] SetPoolMetadata is permissionless`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'PermTest', 'PERM');
+    await ctx.initializePool(BigInt(2592000));
+
+    // Different payer than authority
+    const randomPayer = Keypair.generate();
+    await airdropAndConfirm(connection, randomPayer.publicKey, LAMPORTS_PER_SOL);
+
+    await ctx.setPoolMetadata(randomPayer);
+
+    const metadata = await ctx.readMetadata();
+    if (metadata.name !== 'PermTest Staking Pool') {
+      throw new Error(`Expected "PermTest Staking Pool", got "${metadata.name}"`);
+    }
+  });
+
+  // Test: SetPoolMetadata handles trailing spaces in token name
+  await test(`[${tokenProgramLabel}] SetPoolMetadata trims token name whitespace`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    // Note: Token 2022 metadata stores the name as-is (with trailing space)
+    await ctx.createMintWithMetadata(9, 'Tibanne Thecat ', 'ChiefPussy');
+    await ctx.initializePool(BigInt(2592000));
+
+    await ctx.setPoolMetadata();
+
+    const metadata = await ctx.readMetadata();
+    // .trim() in set_metadata.rs removes trailing space
+    if (metadata.name !== 'Tibanne Thecat Staking Pool') {
+      throw new Error(`Expected "Tibanne Thecat Staking Pool", got "${metadata.name}"`);
+    }
+  });
+
+  // Test: Stake with metadata increments member_count
+  await test(`[${tokenProgramLabel}] Stake with metadata increments member_count`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'MemberTest', 'MEM');
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.setPoolMetadata();
+
+    const meta0 = await ctx.readMetadata();
+    if (meta0.memberCount !== 0n) throw new Error(`Expected 0 members, got ${meta0.memberCount}`);
+
+    // Stake with metadata account
+    const user1 = Keypair.generate();
+    await airdropAndConfirm(connection, user1.publicKey, LAMPORTS_PER_SOL);
+    const user1Token = await ctx.createUserTokenAccount(user1.publicKey);
+    await ctx.mintTokens(user1Token, BigInt(1_000_000_000));
+    await ctx.stakeWithMetadata(user1, user1Token, BigInt(1_000_000_000));
+
+    const meta1 = await ctx.readMetadata();
+    if (meta1.memberCount !== 1n) throw new Error(`Expected 1 member, got ${meta1.memberCount}`);
+
+    // Second staker
+    const user2 = Keypair.generate();
+    await airdropAndConfirm(connection, user2.publicKey, LAMPORTS_PER_SOL);
+    const user2Token = await ctx.createUserTokenAccount(user2.publicKey);
+    await ctx.mintTokens(user2Token, BigInt(2_000_000_000));
+    await ctx.stakeWithMetadata(user2, user2Token, BigInt(2_000_000_000));
+
+    const meta2 = await ctx.readMetadata();
+    if (meta2.memberCount !== 2n) throw new Error(`Expected 2 members, got ${meta2.memberCount}`);
+  });
+
+  // Test: Additional stake does NOT increment member_count (not new)
+  await test(`[${tokenProgramLabel}] Additional stake does not increment member_count`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'AddStake', 'ADD');
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.setPoolMetadata();
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(5_000_000_000));
+
+    // First stake
+    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
+    const meta1 = await ctx.readMetadata();
+    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
+
+    // Additional stake (existing account, should NOT increment)
+    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
+    const meta2 = await ctx.readMetadata();
+    if (meta2.memberCount !== 1n) throw new Error(`Expected still 1, got ${meta2.memberCount}`);
+  });
+
+  // Test: CloseStakeAccount with metadata decrements member_count
+  await test(`[${tokenProgramLabel}] CloseStakeAccount with metadata decrements member_count`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'CloseTest', 'CLZ');
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.setPoolMetadata();
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+
+    // Stake with metadata
+    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
+    const meta1 = await ctx.readMetadata();
+    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
+
+    // Unstake everything
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+
+    // Close stake account with metadata
+    await ctx.closeStakeAccount(user, true);
+    const meta2 = await ctx.readMetadata();
+    if (meta2.memberCount !== 0n) throw new Error(`Expected 0 after close, got ${meta2.memberCount}`);
+  });
+
+  // Test: SetPoolMetadata preserves member_count on update
+  await test(`[${tokenProgramLabel}] SetPoolMetadata preserves member_count on update`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'PreserveCount', 'PRS');
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.setPoolMetadata();
+
+    // Stake to increment member_count
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
+
+    const meta1 = await ctx.readMetadata();
+    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
+
+    // Re-set metadata — member_count should be preserved
+    await ctx.setPoolMetadata();
+    const meta2 = await ctx.readMetadata();
+    if (meta2.memberCount !== 1n) throw new Error(`Expected 1 after re-set, got ${meta2.memberCount}`);
+  });
+
+  // Test: Stake without metadata account still works (backwards compatible)
+  await test(`[${tokenProgramLabel}] Stake without metadata account is backwards compatible`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMintWithMetadata(9, 'BackCompat', 'BCK');
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.setPoolMetadata();
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+
+    // Stake WITHOUT passing metadata account (old-style 8-account call)
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // member_count should remain 0 since we didn't pass metadata
+    const meta = await ctx.readMetadata();
+    if (meta.memberCount !== 0n) throw new Error(`Expected 0 (no metadata passed), got ${meta.memberCount}`);
+  });
+
+  } // end Token 2022-only metadata tests
+
+  // === Repeated Claim Exploit Tests (round 10b fix) ===
+
+  // Test: Repeated claims do NOT extract max-weight rewards
+  await test(`[${tokenProgramLabel}] Security: Repeated claims cannot extract max-weight rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+
+    // tau=60 so weight grows slowly (user will have ~15% weight after 10s)
+    await ctx.initializePool(BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait 10s to accrue some weight (~15% at tau=60)
+    console.log('    Waiting 10s for weight to accrue...');
+    await new Promise(r => setTimeout(r, 10000));
+
+    // Deposit a large reward
+    const depositAmount = LAMPORTS_PER_SOL;
+    await ctx.depositRewards(BigInt(depositAmount));
+
+    // Claim 5 times in rapid succession
+    let totalClaimed = 0;
+    for (let i = 0; i < 5; i++) {
+      const balBefore = await ctx.getBalance(user.publicKey);
+      try {
+        await ctx.claimRewards(user);
+      } catch (e) {
+        // Expected: "no pending rewards" after first claim
+      }
+      const balAfter = await ctx.getBalance(user.publicKey);
+      const reward = balAfter - balBefore;
+      if (reward > 0) {
+        totalClaimed += reward;
+        console.log(`    Claim ${i + 1}: ${reward} lamports`);
+      } else {
+        console.log(`    Claim ${i + 1}: 0 (no rewards / tx fee)`);
+      }
+    }
+
+    // The critical assertion: total claimed must be much less than the deposit.
+    // With ~15% weight, user should get ~15% of 1 SOL = ~0.15 SOL.
+    // Before fix: repeated claims would converge to ~1 SOL (100%).
+    // Allow generous margin: user should get < 50% of deposit.
+    const claimedPercent = (totalClaimed * 100) / depositAmount;
+    console.log(`    Total claimed: ${totalClaimed} lamports (${claimedPercent.toFixed(1)}% of deposit)`);
+
+    if (totalClaimed > depositAmount * 0.5) {
+      throw new Error(
+        `EXPLOIT: Repeated claims extracted ${claimedPercent.toFixed(1)}% of deposit ` +
+        `(expected <50% for ~15% weight). Total: ${totalClaimed} / ${depositAmount}`
+      );
+    }
+  });
+
+  // Test: Second claim returns 0 when no new rewards deposited
+  await test(`[${tokenProgramLabel}] Security: Second claim returns 0 without new rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait for weight
+    console.log('    Waiting 5s for weight...');
+    await new Promise(r => setTimeout(r, 5000));
+
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    // First claim should succeed
+    const bal1 = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const reward1 = (await ctx.getBalance(user.publicKey)) - bal1;
+    console.log(`    First claim: ${reward1} lamports`);
+    if (reward1 <= 0) throw new Error('First claim should get rewards');
+
+    // Second claim (no new deposits) — in a time-weighted system, weight grows
+    // between claims, so a small additional entitlement is normal. The security
+    // check is that the second claim is much smaller than the first (no double-claim).
+    const bal2 = await ctx.getBalance(user.publicKey);
+    try {
+      await ctx.claimRewards(user);
+    } catch (e) {
+      // May be rejected if pending rounds to 0
+      console.log('    Second claim: rejected (expected)');
+    }
+    const reward2 = (await ctx.getBalance(user.publicKey)) - bal2;
+    console.log(`    Second claim: ${reward2} lamports`);
+
+    // Second claim must be strictly less than first (weight growth, not double-claim)
+    if (reward2 >= reward1) {
+      throw new Error(
+        `EXPLOIT: Second claim got ${reward2} lamports (>= first claim ${reward1}), possible double-claim`
+      );
+    }
+  });
+
+  // Test: Claiming frequency doesn't affect total rewards (frequency-independent)
+  await test(`[${tokenProgramLabel}] Security: Claim frequency does not affect total rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    // Both stake at the same time
+    const multiClaimer = Keypair.generate();
+    const singleClaimer = Keypair.generate();
+    await airdropAndConfirm(connection, multiClaimer.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, singleClaimer.publicKey, 2 * LAMPORTS_PER_SOL);
+    const multiToken = await ctx.createUserTokenAccount(multiClaimer.publicKey);
+    const singleToken = await ctx.createUserTokenAccount(singleClaimer.publicKey);
+    const amount = BigInt(1_000_000_000);
+    await ctx.mintTokens(multiToken, amount);
+    await ctx.mintTokens(singleToken, amount);
+    await ctx.stake(multiClaimer, multiToken, amount);
+    await ctx.stake(singleClaimer, singleToken, amount);
+
+    // Wait 5s, deposit rewards
+    console.log('    Waiting 5s...');
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Multi-claimer claims immediately at low weight
+    const multiBal1 = await ctx.getBalance(multiClaimer.publicKey);
+    await ctx.claimRewards(multiClaimer);
+    const multiReward1 = (await ctx.getBalance(multiClaimer.publicKey)) - multiBal1;
+    console.log(`    Multi-claimer first claim (5s age): ${multiReward1}`);
+
+    // Wait another 15s for weight to grow
+    console.log('    Waiting 15s more...');
+    await new Promise(r => setTimeout(r, 15000));
+
+    // Multi-claimer claims again (picks up weight growth entitlement)
+    const multiBal2 = await ctx.getBalance(multiClaimer.publicKey);
+    try {
+      await ctx.claimRewards(multiClaimer);
+    } catch (e: any) { if (!e.message?.includes('0xb')) throw e; }
+    const multiReward2 = (await ctx.getBalance(multiClaimer.publicKey)) - multiBal2;
+    if (multiReward2 < 0) throw new Error(`Unexpected SOL loss: ${multiReward2}`);
+
+    // Single-claimer claims once at 20s age
+    const singleBal = await ctx.getBalance(singleClaimer.publicKey);
+    await ctx.claimRewards(singleClaimer);
+    const singleReward = (await ctx.getBalance(singleClaimer.publicKey)) - singleBal;
+
+    const multiTotal = multiReward1 + multiReward2;
+    console.log(`    Multi-claimer total: ${multiTotal} lamports`);
+    console.log(`    Single-claimer total: ${singleReward} lamports`);
+
+    // Frequency-independent: both should earn approximately the same.
+    // Allow tolerance for block-time jitter between claims (weight grows ~1.2%/s
+    // at t=20s with tau=60, so a few seconds of jitter → a few % difference).
+    const diff = Math.abs(singleReward - multiTotal);
+    const larger = Math.max(singleReward, multiTotal);
+    const pctDiff = larger > 0 ? (diff * 100) / larger : 0;
+    console.log(`    Difference: ${diff} lamports (${pctDiff.toFixed(1)}%)`);
+
+    if (pctDiff > 10) {
+      throw new Error(
+        `Claim totals diverged >10%: multi=${multiTotal}, single=${singleReward}, diff=${pctDiff.toFixed(1)}%`
+      );
+    }
+    console.log('    Frequency-independent claiming: OK');
+  });
 
-  async getTokenBalance(tokenAccount: PublicKey): Promise<bigint> {
-    const account = await getAccount(this.connection, tokenAccount, undefined, this.tokenProgramId);
-    return account.amount;
-  }
-}
+  // Test: total_rewards_claimed field tracks correctly
+  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on claim`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-// Test runner
-async function runTests() {
-  console.log('=== ChiefStaker E2E Tests ===\n');
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-  const connection = new Connection('http://localhost:8899', 'confirmed');
-  const payer = Keypair.generate();
+    // Check initial state
+    const state0 = await ctx.readUserStakeState(user.publicKey);
+    console.log(`    Initial total_rewards_claimed: ${state0.totalRewardsClaimed}`);
+    if (state0.totalRewardsClaimed !== 0n) {
+      throw new Error(`Expected 0 initial, got ${state0.totalRewardsClaimed}`);
+    }
 
-  // Load the program deployer keypair (upgrade authority)
-  const solanaKeypairPath = path.join(
-    process.env.HOME || require('os').homedir(),
-    '.config', 'solana', 'id.json',
-  );
-  const programAuthority = Keypair.fromSecretKey(
-    new Uint8Array(JSON.parse(fs.readFileSync(solanaKeypairPath, 'utf8'))),
-  );
+    // Wait and deposit
+    console.log('    Waiting 5s...');
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
 
-  // Check connection
-  try {
-    await connection.getVersion();
-  } catch (e) {
-    console.error('ERROR: Cannot connect to test validator. Start it with:');
-    console.error('  ./scripts/start-validator.sh --reset');
-    process.exit(1);
-  }
+    // Claim
+    const balBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user);
+    const balAfter = await ctx.getBalance(user.publicKey);
+    const reward = balAfter - balBefore;
+    console.log(`    Claimed: ${reward} lamports`);
 
-  // Check program is deployed
-  const programInfo = await connection.getAccountInfo(PROGRAM_ID);
-  if (!programInfo) {
-    console.error('ERROR: Program not deployed. Deploy it with:');
-    console.error('  ./scripts/deploy-program.sh target/deploy/chiefstaker.so');
-    process.exit(1);
-  }
+    // Check total_rewards_claimed updated
+    const state1 = await ctx.readUserStakeState(user.publicKey);
+    console.log(`    total_rewards_claimed after claim: ${state1.totalRewardsClaimed}`);
 
-  let passed = 0;
-  let failed = 0;
+    if (state1.totalRewardsClaimed === 0n) {
+      throw new Error('total_rewards_claimed should be > 0 after claim');
+    }
 
-  async function test(name: string, fn: () => Promise<void>) {
+    // Deposit more and claim again
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    // Wait a tiny bit for weight (otherwise snapshot delta is 0)
+    await new Promise(r => setTimeout(r, 2000));
     try {
-      await fn();
-      console.log(`✓ ${name}`);
-      passed++;
-    } catch (e: any) {
-      console.log(`✗ ${name}`);
-      console.log(`  Error: ${e.message}`);
-      if (e.logs) {
-        console.log(`  Logs: ${e.logs.slice(-5).join('\n        ')}`);
-      }
-      failed++;
-    }
-  }
+      await ctx.claimRewards(user);
+    } catch (e) { /* may fail if no new delta */ }
 
-  for (const [tokenProgramLabel, tokenProgramId] of [
-    ['Token2022', TOKEN_2022_PROGRAM_ID],
-    ['SPL Token', TOKEN_PROGRAM_ID],
-  ] as [string, PublicKey][]) {
+    const state2 = await ctx.readUserStakeState(user.publicKey);
+    console.log(`    total_rewards_claimed after second: ${state2.totalRewardsClaimed}`);
 
-  console.log(`\n========== ${tokenProgramLabel} Tests ==========\n`);
+    if (state2.totalRewardsClaimed < state1.totalRewardsClaimed) {
+      throw new Error('total_rewards_claimed should never decrease');
+    }
+  });
 
-  // Test: Initialize Pool
-  await test(`[${tokenProgramLabel}] Initialize pool`, async () => {
+  // Test: total_rewards_claimed increments on auto-claim during additional stake
+  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on auto-claim stake`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-    const tauSeconds = BigInt(30 * 24 * 60 * 60); // 30 days
-    await ctx.initializePool(tauSeconds);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const poolInfo = await connection.getAccountInfo(ctx.poolPDA);
-    if (!poolInfo) throw new Error('Pool not created');
-    if (poolInfo.data.length === 0) throw new Error('Pool data empty');
-  });
+    // Wait and deposit
+    console.log('    Waiting 5s...');
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
 
-  // Test: InitializePool rejects non-authority signer
-  await test(`[${tokenProgramLabel}] InitializePool: non-authority signer rejected`, async () => {
-    // Create a context where mintAuthority != payer
-    const specialCtx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    specialCtx.mintAuthority = Keypair.generate();
-    await specialCtx.setup();
-    // Airdrop to separate mint authority so createMint works
-    await airdropAndConfirm(connection, specialCtx.mintAuthority.publicKey, LAMPORTS_PER_SOL);
-    await specialCtx.createMint(9);
+    // Additional stake triggers auto-claim
+    const balBefore = await ctx.getBalance(user.publicKey);
+    await ctx.stake(user, userToken, BigInt(500_000_000));
+    const balAfter = await ctx.getBalance(user.publicKey);
 
-    // initializePool signs with payer, but mint_authority is specialCtx.mintAuthority
-    // Should fail with InvalidAuthority (error code 6 = 0x6)
-    try {
-      await specialCtx.initializePool(60n);
-      throw new Error('Should have failed');
-    } catch (e: any) {
-      if (!e.message.includes('custom program error: 0x6')) {
-        throw new Error(`Expected InvalidAuthority (0x6), got: ${e.message}`);
-      }
+    const state = await ctx.readUserStakeState(user.publicKey);
+    console.log(`    total_rewards_claimed after additional stake: ${state.totalRewardsClaimed}`);
+    console.log(`    Balance delta (includes tx fee): ${balAfter - balBefore}`);
+
+    // If auto-claim paid out anything, total_rewards_claimed should reflect it
+    if (state.totalRewardsClaimed > 0n) {
+      console.log('    Auto-claim rewards tracked successfully');
+    } else {
+      console.log('    No auto-claim payout (weight may be too low)');
     }
   });
 
-  // Test: Stake tokens
-  await test(`[${tokenProgramLabel}] Stake tokens`, async () => {
+  // Test: total_rewards_claimed increments on unstake with reward payout
+  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on unstake`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60));
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Wait and deposit
+    console.log('    Waiting 5s...');
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+
+    // Unstake (should auto-claim rewards)
+    await ctx.unstake(user, userToken, BigInt(500_000_000));
+
+    const state = await ctx.readUserStakeState(user.publicKey);
+    console.log(`    total_rewards_claimed after unstake: ${state.totalRewardsClaimed}`);
+
+    if (state.totalRewardsClaimed > 0n) {
+      console.log('    Unstake reward payout tracked successfully');
+    } else {
+      console.log('    No reward payout during unstake (weight may be too low)');
+    }
+  });
+
+  await test(`[${tokenProgramLabel}] Security: Large stake amounts don't overflow reward math`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    const stakeAmount = BigInt(1_000_000_000);
-    await ctx.mintTokens(userToken, stakeAmount);
 
-    await ctx.stake(user, userToken, stakeAmount);
+    // Mint near-u64-max tokens (9e18, close to u64::MAX of ~1.8e19)
+    const hugeAmount = BigInt('9000000000000000000'); // 9e18
+    await ctx.mintTokens(userToken, hugeAmount);
+    await ctx.stake(user, userToken, hugeAmount);
 
-    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
-    if (vaultBalance !== stakeAmount) {
-      throw new Error(`Expected vault balance ${stakeAmount}, got ${vaultBalance}`);
+    const staked = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    console.log(`    Staked ${staked} tokens (9e18)`);
+
+    // Deposit 2 SOL in rewards, wait for weight accumulation
+    const depositAmount = BigInt(2 * LAMPORTS_PER_SOL);
+    await ctx.depositRewards(depositAmount);
+
+    console.log('    Waiting 5s for weight accumulation...');
+    await new Promise(r => setTimeout(r, 5000));
+
+    // Claim — must succeed without overflow panic
+    const balBefore = BigInt(await ctx.getBalance(user.publicKey));
+    await ctx.claimRewards(user);
+    const balAfter = BigInt(await ctx.getBalance(user.publicKey));
+    const claimed = balAfter - balBefore;
+    console.log(`    Claimed: ${claimed} lamports`);
+
+    if (claimed > depositAmount) {
+      throw new Error(`Claimed ${claimed} exceeds deposited ${depositAmount}`);
+    }
+    console.log('    Claim <= deposited: OK');
+
+    // Unstake fully — must succeed, token balance restored
+    await ctx.unstake(user, userToken, hugeAmount);
+    const tokenBalAfter = await ctx.getTokenBalance(userToken);
+    if (tokenBalAfter !== hugeAmount) {
+      throw new Error(`Token balance mismatch after unstake: expected ${hugeAmount}, got ${tokenBalAfter}`);
     }
+    console.log('    Full unstake succeeded, token balance restored');
   });
 
-  // Test: Multiple stakers
-  await test(`[${tokenProgramLabel}] Multiple stakers`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+  await test(`[${tokenProgramLabel}] Security: Claim capped at available pool balance (exact drain)`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    const user1 = Keypair.generate();
+    const user2 = Keypair.generate();
+    await airdropAndConfirm(connection, user1.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user2.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    const token1 = await ctx.createUserTokenAccount(user1.publicKey);
+    const token2 = await ctx.createUserTokenAccount(user2.publicKey);
+    await ctx.mintTokens(token1, BigInt(1_000_000_000));
+    await ctx.mintTokens(token2, BigInt(1_000_000_000));
+
+    // Both stake equally
+    await ctx.stake(user1, token1, BigInt(1_000_000_000));
+    await ctx.stake(user2, token2, BigInt(1_000_000_000));
+
+    // Deposit tiny reward (50,000 lamports)
+    const tinyDeposit = BigInt(50_000);
+    await ctx.depositRewards(tinyDeposit);
 
-    const user1 = Keypair.generate();
-    const user2 = Keypair.generate();
-    await airdropAndConfirm(connection, user1.publicKey, LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, user2.publicKey, LAMPORTS_PER_SOL);
+    console.log('    Waiting 5s for weight accumulation...');
+    await new Promise(r => setTimeout(r, 5000));
 
-    const user1Token = await ctx.createUserTokenAccount(user1.publicKey);
-    const user2Token = await ctx.createUserTokenAccount(user2.publicKey);
+    // User1 claims first (gets a portion)
+    let totalClaimed = BigInt(0);
+    const bal1Before = BigInt(await ctx.getBalance(user1.publicKey));
+    try {
+      await ctx.claimRewards(user1);
+    } catch (e) {
+      // Claim may fail if entitlement is 0
+    }
+    const bal1After = BigInt(await ctx.getBalance(user1.publicKey));
+    const claim1 = bal1After - bal1Before;
+    if (claim1 > BigInt(0)) totalClaimed += claim1;
+    console.log(`    User1 claimed: ${claim1} lamports`);
 
-    await ctx.mintTokens(user1Token, BigInt(1_000_000_000));
-    await ctx.mintTokens(user2Token, BigInt(2_000_000_000));
+    // User2 claims — entitlement may exceed remaining balance
+    const bal2Before = BigInt(await ctx.getBalance(user2.publicKey));
+    try {
+      await ctx.claimRewards(user2);
+    } catch (e) {
+      console.log('    User2 claim failed (expected if pool drained)');
+    }
+    const bal2After = BigInt(await ctx.getBalance(user2.publicKey));
+    const claim2 = bal2After - bal2Before;
+    if (claim2 > BigInt(0)) totalClaimed += claim2;
+    console.log(`    User2 claimed: ${claim2} lamports`);
 
-    await ctx.stake(user1, user1Token, BigInt(1_000_000_000));
-    await ctx.stake(user2, user2Token, BigInt(2_000_000_000));
+    // Verify: pool balance >= rent-exempt minimum
+    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
+    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
+    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
+    console.log(`    Pool balance: ${poolBalance}, rent-exempt min: ${rentExempt}`);
 
-    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
-    if (vaultBalance !== BigInt(3_000_000_000)) {
-      throw new Error(`Expected vault balance 3000000000, got ${vaultBalance}`);
+    if (poolBalance < rentExempt) {
+      throw new Error(`Pool balance ${poolBalance} dropped below rent-exempt minimum ${rentExempt}`);
+    }
+    console.log('    Pool balance >= rent-exempt: OK');
+
+    // Verify: total claimed <= total deposited (with tolerance for rounding)
+    const tolerance = BigInt(100_000);
+    if (totalClaimed > tinyDeposit + tolerance) {
+      throw new Error(`Total claimed ${totalClaimed} exceeds deposited ${tinyDeposit} + tolerance`);
     }
+    console.log(`    Total claimed (${totalClaimed}) <= deposited (${tinyDeposit}): OK`);
   });
 
-  // Test: Deposit and claim rewards
-  await test(`[${tokenProgramLabel}] Deposit and claim rewards`, async () => {
+  await test(`[${tokenProgramLabel}] Security: Sandwich attack (stake between deposit and sync) gives no advantage`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100)); // Short tau
+    await ctx.initializePool(BigInt(60));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    // Honest user stakes and waits for weight
+    const honest = Keypair.generate();
+    await airdropAndConfirm(connection, honest.publicKey, 3 * LAMPORTS_PER_SOL);
+    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
+    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
+    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
 
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    console.log('    Waiting 10s for honest staker weight...');
+    await new Promise(r => setTimeout(r, 10000));
 
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    // Deposit rewards via direct SOL transfer (no auto-sync)
+    const depositAmount = BigInt(LAMPORTS_PER_SOL);
+    await ctx.sendSolToPool(depositAmount);
 
-    const balanceBefore = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const balanceAfter = await ctx.getBalance(user.publicKey);
+    // Attacker stakes immediately after deposit but BEFORE syncRewards
+    const attacker = Keypair.generate();
+    await airdropAndConfirm(connection, attacker.publicKey, 3 * LAMPORTS_PER_SOL);
+    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
+    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
+    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
 
-    // User should receive some rewards (payer covers tx fee, so diff = pure reward)
-    // Due to time-weighted calculation, might be small if just staked
-    const reward = balanceAfter - balanceBefore;
-    if (reward < 0) throw new Error(`Unexpected SOL loss: ${reward}`);
-    console.log(`    Reward claimed: ${reward} lamports`);
-  });
+    // Now sync rewards — attacker has ~0 weight at this point
+    await ctx.syncRewards();
 
-  // Test: Unstake partial
-  await test(`[${tokenProgramLabel}] Unstake partial`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    // Both claim
+    const honestBalBefore = BigInt(await ctx.getBalance(honest.publicKey));
+    await ctx.claimRewards(honest);
+    const honestBalAfter = BigInt(await ctx.getBalance(honest.publicKey));
+    const honestClaimed = honestBalAfter - honestBalBefore;
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    let attackerClaimed = BigInt(0);
+    const attackerBalBefore = BigInt(await ctx.getBalance(attacker.publicKey));
+    try {
+      await ctx.claimRewards(attacker);
+    } catch (e) {
+      console.log('    Attacker claim failed (expected — zero weight)');
+    }
+    const attackerBalAfter = BigInt(await ctx.getBalance(attacker.publicKey));
+    attackerClaimed = attackerBalAfter - attackerBalBefore;
 
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const totalClaimed = (honestClaimed > BigInt(0) ? honestClaimed : BigInt(0))
+                       + (attackerClaimed > BigInt(0) ? attackerClaimed : BigInt(0));
 
-    // Unstake half
-    await ctx.unstake(user, userToken, BigInt(500_000_000));
+    console.log(`    Honest claimed:   ${honestClaimed} lamports`);
+    console.log(`    Attacker claimed: ${attackerClaimed} lamports`);
 
-    const userBalance = await ctx.getTokenBalance(userToken);
-    if (userBalance !== BigInt(500_000_000)) {
-      throw new Error(`Expected user balance 500000000, got ${userBalance}`);
+    // Verify: attacker gets near-zero, honest gets >75% of rewards
+    if (honestClaimed <= BigInt(0)) {
+      throw new Error('Honest staker should have received rewards');
+    }
+
+    if (totalClaimed > BigInt(0)) {
+      const honestShare = Number(honestClaimed) * 100 / Number(totalClaimed);
+      console.log(`    Honest share: ${honestShare.toFixed(1)}%`);
+      if (honestShare < 75) {
+        throw new Error(`Honest staker share too low: ${honestShare.toFixed(1)}% (expected >75%)`);
+      }
+    }
+
+    // Verify: conservation holds
+    const tolerance = BigInt(100_000);
+    if (totalClaimed > depositAmount + tolerance) {
+      throw new Error(`Conservation violated: claimed ${totalClaimed} > deposited ${depositAmount} + tolerance`);
     }
+    console.log('    Sandwich attack mitigated: OK');
   });
 
-  // Test: Unstake full
-  await test(`[${tokenProgramLabel}] Unstake full`, async () => {
+  // ─── Add-Stake Tests ─────────────────────────────────────────────────────────
+  //
+  // On add-stake, maturity percentage is preserved (depends only on start time).
+  // exp_start_factor doesn't change, claimed_rewards_wad doesn't change.
+  // reward_debt gets a fresh snapshot added for new tokens only.
+  // Pending rewards stay exactly the same — user can claim separately.
+
+  console.log('\n--- Add-Stake Tests ---\n');
+
+  // Test: exp_start_factor and pending rewards unchanged after add-stake
+  await test(`[${tokenProgramLabel}] AddStake: maturity preserved — exp_start_factor unchanged`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // 60s tau
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const staker = Keypair.generate();
+    await airdropAndConfirm(connection, staker.publicKey, 3 * LAMPORTS_PER_SOL);
+    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
+    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
 
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
-    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    // Stake 1B tokens
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
 
-    const userBalance = await ctx.getTokenBalance(userToken);
-    if (userBalance !== BigInt(1_000_000_000)) {
-      throw new Error(`Expected user balance 1000000000, got ${userBalance}`);
-    }
-  });
+    // Wait for partial maturity
+    console.log('    Waiting 15s for partial weight...');
+    await new Promise(r => setTimeout(r, 15000));
 
-  // Test: SyncRewards (pump.fun simulation)
-  await test(`[${tokenProgramLabel}] SyncRewards (pump.fun simulation)`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100));
+    // Deposit 2 SOL of rewards
+    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    // Read state before add-stake
+    const stateBefore = await ctx.readUserStakeState(staker.publicKey);
+    const expBefore = stateBefore.expStartFactor;
+    const claimedBefore = stateBefore.claimedRewardsWad;
 
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    // Measure claimable before add-stake
+    let bal = BigInt(await ctx.getBalance(staker.publicKey));
+    await ctx.claimRewards(staker);
+    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
+    const claimBefore = balAfter - bal;
+    console.log(`    Claim before add-stake: ${claimBefore} lamports`);
 
-    // Send SOL directly to pool (simulating pump.fun)
-    await ctx.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 2));
+    // Add 1B more tokens
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
 
-    // Sync rewards
-    await ctx.syncRewards();
+    // Read state after add-stake
+    const stateAfter = await ctx.readUserStakeState(staker.publicKey);
+    console.log(`    exp_start_factor: before=${expBefore}, after=${stateAfter.expStartFactor}`);
+    console.log(`    amount: ${stateBefore.amount} → ${stateAfter.amount}`);
 
-    const balanceBefore = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const balanceAfter = await ctx.getBalance(user.publicKey);
+    // exp_start_factor MUST be unchanged
+    if (stateAfter.expStartFactor !== expBefore) {
+      throw new Error(`exp_start_factor changed! ${expBefore} → ${stateAfter.expStartFactor}`);
+    }
 
-    const reward = balanceAfter - balanceBefore;
-    if (reward <= 0) throw new Error(`Expected positive reward from synced SOL, got: ${reward}`);
-    console.log(`    Direct SOL reward claimed: ${reward} lamports`);
+    // Amount must have increased
+    if (stateAfter.amount !== BigInt(2_000_000_000)) {
+      throw new Error(`Expected 2B, got ${stateAfter.amount}`);
+    }
+
+    console.log('    Maturity preserved: OK');
   });
 
-  // Test: Additional stake
-  await test(`[${tokenProgramLabel}] Additional stake (same user)`, async () => {
+  // Test: Pending rewards unchanged by add-stake (claim before == claim after)
+  await test(`[${tokenProgramLabel}] AddStake: pending rewards unchanged by add-stake`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // 60s tau
+
+    const staker = Keypair.generate();
+    await airdropAndConfirm(connection, staker.publicKey, 5 * LAMPORTS_PER_SOL);
+    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
+    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
+
+    // Stake 1B tokens
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
+
+    // Wait for partial maturity
+    console.log('    Waiting 15s for partial weight...');
+    await new Promise(r => setTimeout(r, 15000));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    // Deposit 2 SOL of rewards
+    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
 
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    // Add 1B more tokens (pending should stay the same)
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
 
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(500_000_000));
+    // Claim — should get the same vested rewards as before the add-stake
+    let bal = BigInt(await ctx.getBalance(staker.publicKey));
+    await ctx.claimRewards(staker);
+    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
+    const claimAfterAdd = balAfter - bal;
+    console.log(`    Claim after add-stake: ${claimAfterAdd} lamports`);
 
-    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
-    if (vaultBalance !== BigInt(1_500_000_000)) {
-      throw new Error(`Expected vault balance 1500000000, got ${vaultBalance}`);
+    // Should be positive (rewards vested during the 15s maturity period)
+    if (claimAfterAdd < BigInt(10_000)) {
+      throw new Error(`Pending rewards lost on add-stake! Got ${claimAfterAdd}`);
     }
-  });
 
-  // ============================================
-  // POOL SETTINGS / AUTHORITY TESTS
-  // ============================================
-
-  console.log('\n--- Pool Settings & Authority Tests ---\n');
+    console.log('    Pending rewards preserved: OK');
+  });
 
-  // Test: Update pool settings
-  await test(`[${tokenProgramLabel}] UpdatePoolSettings: set min_stake, lock, cooldown`, async () => {
+  // Test: SOL continues to mature after add-stake
+  await test(`[${tokenProgramLabel}] AddStake: SOL continues to mature after add-stake`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // 60s tau
 
-    // Set all three settings
-    await ctx.updatePoolSettings(
-      ctx.payer,
-      BigInt(1_000_000), // min stake
-      null, // no lock
-      null, // no cooldown
-    );
+    const staker = Keypair.generate();
+    await airdropAndConfirm(connection, staker.publicKey, 5 * LAMPORTS_PER_SOL);
+    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
+    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
 
-    // Now update lock duration only
-    await ctx.updatePoolSettings(
-      ctx.payer,
-      null,
-      BigInt(10), // 10 second lock
-      null,
-    );
-  });
+    // Stake 1B tokens
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
 
-  // Test: Update settings with wrong authority fails
-  await test(`[${tokenProgramLabel}] UpdatePoolSettings: wrong authority rejected`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    // Wait for partial maturity
+    console.log('    Waiting 15s for partial maturity...');
+    await new Promise(r => setTimeout(r, 15000));
 
-    const wrongAuth = Keypair.generate();
-    await airdropAndConfirm(connection, wrongAuth.publicKey, LAMPORTS_PER_SOL);
+    // Deposit 2 SOL
+    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
 
-    let failed = false;
-    try {
-      await ctx.updatePoolSettings(wrongAuth, BigInt(100), null, null);
-    } catch (e) {
-      failed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x6')) {
-        throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
-      }
-    }
-    if (!failed) throw new Error('Should reject wrong authority');
-  });
+    // Claim vested rewards
+    await ctx.claimRewards(staker);
 
-  // Test: Transfer authority
-  await test(`[${tokenProgramLabel}] TransferAuthority: transfer and use new authority`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    // Add 1B more tokens
+    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
 
-    const newAuth = Keypair.generate();
-    await airdropAndConfirm(connection, newAuth.publicKey, LAMPORTS_PER_SOL);
+    // Wait 30s for more maturity
+    console.log('    Waiting 30s for maturation...');
+    await new Promise(r => setTimeout(r, 30000));
 
-    // Transfer authority
-    await ctx.transferAuthority(ctx.payer, newAuth.publicKey);
+    // Claim — should get newly matured rewards
+    let bal = BigInt(await ctx.getBalance(staker.publicKey));
+    await ctx.claimRewards(staker);
+    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
+    const matureClaim = balAfter - bal;
+    console.log(`    Claim after 30s maturation: ${matureClaim} lamports`);
 
-    // Old authority should fail
-    let oldFailed = false;
-    try {
-      await ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null);
-    } catch (e) {
-      oldFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x6')) {
-        throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
-      }
+    if (matureClaim < BigInt(100_000)) {
+      throw new Error(`SOL not maturing after add-stake! Got ${matureClaim}`);
     }
-    if (!oldFailed) throw new Error('Old authority should be rejected');
 
-    // New authority should work
-    await ctx.updatePoolSettings(newAuth, BigInt(100), null, null);
+    console.log('    SOL continues to mature: OK');
   });
 
-  // Test: Renounce authority
-  await test(`[${tokenProgramLabel}] TransferAuthority: renounce (set to default pubkey)`, async () => {
+  // Test: Conservation — add-stake doesn't create or destroy rewards
+  await test(`[${tokenProgramLabel}] AddStake: conservation — total claimed ≤ total deposited`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // 60s tau
 
-    // Renounce authority (set to default pubkey = zero)
-    await ctx.transferAuthority(ctx.payer, PublicKey.default);
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, 3 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, bob.publicKey, 3 * LAMPORTS_PER_SOL);
 
-    // Authority should now be rejected
-    let failed = false;
-    try {
-      await ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null);
-    } catch (e) {
-      failed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x1b')) {
-        throw new Error(`Expected AuthorityRenounced (0x1b), got: ${errMsg}`);
-      }
-    }
-    if (!failed) throw new Error('Renounced authority should be rejected');
-  });
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(2_000_000_000));
+    await ctx.mintTokens(bobToken, BigInt(1_000_000_000));
 
-  // Test: Min stake amount enforced on new stake
-  await test(`[${tokenProgramLabel}] MinStake: enforced on new stake`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    // Both stake 1B tokens
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(bob, bobToken, BigInt(1_000_000_000));
 
-    // Set min stake to 1 billion (1 token with 9 decimals)
-    await ctx.updatePoolSettings(ctx.payer, BigInt(1_000_000_000), null, null);
+    // Wait for partial maturity
+    console.log('    Waiting 15s for partial weight...');
+    await new Promise(r => setTimeout(r, 15000));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    // Deposit 2 SOL
+    const depositAmount = BigInt(2 * LAMPORTS_PER_SOL);
+    await ctx.depositRewards(depositAmount);
 
-    // Stake below minimum should fail
-    let belowMinFailed = false;
-    try {
-      await ctx.stake(user, userToken, BigInt(500_000_000));
-    } catch (e) {
-      belowMinFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x15')) {
-        throw new Error(`Expected BelowMinimumStake (0x15), got: ${errMsg}`);
-      }
-    }
-    if (!belowMinFailed) throw new Error('Below-minimum stake should fail');
+    // Alice stakes 1B more (no auto-claim — pending stays the same)
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
 
-    // Stake at minimum should succeed
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
-  });
+    // Wait for more maturity
+    console.log('    Waiting 30s for maturity...');
+    await new Promise(r => setTimeout(r, 30000));
 
-  // Test: Lock duration blocks early unstake
-  await test(`[${tokenProgramLabel}] LockDuration: blocks early unstake`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    // Both claim
+    let aliceTotal = BigInt(0);
+    let bal = BigInt(await ctx.getBalance(alice.publicKey));
+    await ctx.claimRewards(alice);
+    let balAfter = BigInt(await ctx.getBalance(alice.publicKey));
+    aliceTotal += (balAfter - bal);
 
-    // Set 10-second lock duration
-    await ctx.updatePoolSettings(ctx.payer, null, BigInt(10), null);
+    bal = BigInt(await ctx.getBalance(bob.publicKey));
+    await ctx.claimRewards(bob);
+    balAfter = BigInt(await ctx.getBalance(bob.publicKey));
+    const bobTotal = balAfter - bal;
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    // Both fully unstake
+    bal = BigInt(await ctx.getBalance(alice.publicKey));
+    await ctx.unstake(alice, aliceToken, BigInt(2_000_000_000));
+    balAfter = BigInt(await ctx.getBalance(alice.publicKey));
+    aliceTotal += (balAfter - bal);
 
-    // Try unstake immediately - should fail (locked)
-    let lockedFailed = false;
-    try {
-      await ctx.unstake(user, userToken, BigInt(1_000_000_000));
-    } catch (e) {
-      lockedFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x16')) {
-        throw new Error(`Expected StakeLocked (0x16), got: ${errMsg}`);
-      }
-    }
-    if (!lockedFailed) throw new Error('Should reject unstake during lock period');
+    bal = BigInt(await ctx.getBalance(bob.publicKey));
+    await ctx.unstake(bob, bobToken, BigInt(1_000_000_000));
+    balAfter = BigInt(await ctx.getBalance(bob.publicKey));
+    const bobFinal = bobTotal + (balAfter - bal);
 
-    // Wait for lock to expire
-    console.log('    Waiting 11s for lock to expire...');
-    await new Promise(r => setTimeout(r, 11000));
+    const totalClaimed = aliceTotal + bobFinal;
 
-    // Now unstake should succeed
-    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    console.log(`    Alice total received: ${aliceTotal} lamports`);
+    console.log(`    Bob total received:   ${bobFinal} lamports`);
+    console.log(`    Total claimed:        ${totalClaimed} lamports`);
+
+    // Conservation: total claimed must not exceed deposited
+    const tolerance = BigInt(100_000);
+    if (totalClaimed > depositAmount + tolerance) {
+      throw new Error(`Conservation violated: claimed ${totalClaimed} > deposited ${depositAmount}`);
+    }
+
+    console.log('    Conservation maintained: OK');
   });
 
-  // Test: Cooldown flow - request → wait → complete
-  await test(`[${tokenProgramLabel}] Cooldown: request → wait → complete unstake`, async () => {
+  // Test: Dust-stake provides no advantage over simply claiming
+  await test(`[${tokenProgramLabel}] AddStake: dust-stake yields no extra rewards vs plain claim`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // 60s tau
 
-    // Set 5-second cooldown
-    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(5));
+    // Two stakers: one will dust-stake+claim, the other will just claim
+    const dustStaker = Keypair.generate();
+    const plainStaker = Keypair.generate();
+    await airdropAndConfirm(connection, dustStaker.publicKey, 5 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, plainStaker.publicKey, 5 * LAMPORTS_PER_SOL);
+    const dustToken = await ctx.createUserTokenAccount(dustStaker.publicKey);
+    const plainToken = await ctx.createUserTokenAccount(plainStaker.publicKey);
+    await ctx.mintTokens(dustToken, BigInt(2_000_000_000));
+    await ctx.mintTokens(plainToken, BigInt(1_000_000_000));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    // Both stake the same amount at the same time
+    await ctx.stake(dustStaker, dustToken, BigInt(1_000_000_000));
+    await ctx.stake(plainStaker, plainToken, BigInt(1_000_000_000));
 
-    // Direct unstake should fail (cooldown required)
-    let directFailed = false;
-    try {
-      await ctx.unstake(user, userToken, BigInt(1_000_000_000));
-    } catch (e) {
-      directFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x18')) {
-        throw new Error(`Expected CooldownRequired (0x18), got: ${errMsg}`);
-      }
-    }
-    if (!directFailed) throw new Error('Direct unstake should fail with cooldown');
+    // Wait for maturity
+    console.log('    Waiting 30s for maturity...');
+    await new Promise(r => setTimeout(r, 30000));
 
-    // Request unstake
-    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    // Deposit 2 SOL
+    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
 
-    // Complete immediately should fail (cooldown not elapsed)
-    let earlyFailed = false;
-    try {
-      await ctx.completeUnstake(user, userToken);
-    } catch (e) {
-      earlyFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x17')) {
-        throw new Error(`Expected CooldownNotElapsed (0x17), got: ${errMsg}`);
-      }
-    }
-    if (!earlyFailed) throw new Error('Complete unstake should fail before cooldown');
+    // Both claim initial rewards
+    await ctx.claimRewards(dustStaker);
+    await ctx.claimRewards(plainStaker);
 
-    // Wait for cooldown
-    console.log('    Waiting 6s for cooldown...');
-    await new Promise(r => setTimeout(r, 6000));
+    // Dust-staker: stake dust then claim; plain-staker: just claim
+    let dustTotal = 0n;
+    let plainTotal = 0n;
+    for (let i = 0; i < 3; i++) {
+      await ctx.stake(dustStaker, dustToken, BigInt(1)); // dust
 
-    // Complete unstake should now succeed
-    await ctx.completeUnstake(user, userToken);
+      let bal = BigInt(await ctx.getBalance(dustStaker.publicKey));
+      await ctx.claimRewards(dustStaker);
+      let balAfter = BigInt(await ctx.getBalance(dustStaker.publicKey));
+      dustTotal += balAfter - bal;
 
-    const balance = await ctx.getTokenBalance(userToken);
-    if (balance !== BigInt(1_000_000_000)) {
-      throw new Error(`Expected 1000000000 tokens back, got ${balance}`);
+      bal = BigInt(await ctx.getBalance(plainStaker.publicKey));
+      await ctx.claimRewards(plainStaker);
+      balAfter = BigInt(await ctx.getBalance(plainStaker.publicKey));
+      plainTotal += balAfter - bal;
+
+      console.log(`    Round ${i + 1}: dust=${dustTotal}, plain=${plainTotal}`);
+    }
+
+    console.log(`    Dust-staker total: ${dustTotal}, Plain-staker total: ${plainTotal}`);
+
+    // Dust-staker should NOT get meaningfully more than plain-staker
+    // (small difference possible due to slightly different share from dust amount)
+    const diff = dustTotal - plainTotal;
+    const threshold = BigInt(LAMPORTS_PER_SOL / 100); // 0.01 SOL tolerance
+    if (diff > threshold) {
+      throw new Error(`Dust-stake advantage: ${diff} lamports — exploit not blocked!`);
     }
+
+    console.log('    Dust-stake provides no advantage: OK');
   });
 
-  // Test: Cancel unstake request
-  await test(`[${tokenProgramLabel}] Cooldown: cancel unstake request`, async () => {
+  // ========== StakeOnBehalf Tests ==========
+
+  await test(`[${tokenProgramLabel}] StakeOnBehalf: stake on behalf of another user`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // tau = 60s for fast maturity
 
-    // Set 60-second cooldown (long, we cancel before)
-    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+    // User A (staker) has tokens, User B (beneficiary) is just a pubkey
+    const userA = Keypair.generate();
+    const userB = Keypair.generate();
+    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
+    const stakeAmount = BigInt(1_000_000_000);
+    await ctx.mintTokens(userAToken, stakeAmount);
 
-    // Request unstake
-    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    // A stakes on behalf of B
+    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, stakeAmount);
 
-    // Cancel request
-    await ctx.cancelUnstakeRequest(user);
+    // Verify B's stake PDA exists with correct amount and owner=B
+    const [beneficiaryStakePDA] = deriveUserStakePDA(ctx.poolPDA, userB.publicKey);
+    const stakeInfo = await connection.getAccountInfo(beneficiaryStakePDA);
+    if (!stakeInfo) throw new Error('Beneficiary stake account not created');
+    if (stakeInfo.data.length === 0) throw new Error('Beneficiary stake data empty');
 
-    // Cancel again should fail (no pending request)
-    let doubleCancelFailed = false;
-    try {
-      await ctx.cancelUnstakeRequest(user);
-    } catch (e) {
-      doubleCancelFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x19')) {
-        throw new Error(`Expected NoPendingUnstakeRequest (0x19), got: ${errMsg}`);
-      }
+    // Read stake state: owner is at offset 8 (discriminator) = bytes 8..40
+    const ownerBytes = stakeInfo.data.slice(8, 40);
+    const ownerKey = new PublicKey(ownerBytes);
+    if (!ownerKey.equals(userB.publicKey)) {
+      throw new Error(`Stake owner should be B (${userB.publicKey}), got ${ownerKey}`);
     }
-    if (!doubleCancelFailed) throw new Error('Double cancel should fail');
+
+    // Read amount at offset 72 (u64 LE)
+    const stakedAmount = stakeInfo.data.readBigUInt64LE(72);
+    if (stakedAmount !== stakeAmount) {
+      throw new Error(`Staked amount should be ${stakeAmount}, got ${stakedAmount}`);
+    }
+
+    // Verify A's token account was debited
+    const aBalance = await ctx.getTokenBalance(userAToken);
+    if (aBalance !== 0n) {
+      throw new Error(`A should have 0 tokens, got ${aBalance}`);
+    }
+
+    console.log('    Stake on behalf created successfully, owner = B');
   });
 
-  // Test: Cannot stake while unstake request pending
-  await test(`[${tokenProgramLabel}] Cooldown: cannot stake while unstake pending`, async () => {
+  await test(`[${tokenProgramLabel}] StakeOnBehalf: beneficiary can claim rewards`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // tau = 60s
 
-    // Set cooldown
-    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+    const userA = Keypair.generate();
+    const userB = Keypair.generate();
+    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
+    const stakeAmount = BigInt(1_000_000_000);
+    await ctx.mintTokens(userAToken, stakeAmount);
 
-    // Request unstake
-    await ctx.requestUnstake(user, BigInt(500_000_000));
+    // A stakes on behalf of B
+    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, stakeAmount);
 
-    // Staking more should fail
-    let stakeFailed = false;
-    try {
-      await ctx.stake(user, userToken, BigInt(500_000_000));
-    } catch (e) {
-      stakeFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x1a')) {
-        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${errMsg}`);
-      }
-    }
-    if (!stakeFailed) throw new Error('Should not stake while unstake pending');
+    // Wait for some maturity
+    console.log('    Waiting 10s for maturity...');
+    await new Promise(r => setTimeout(r, 10000));
 
-    // Cancel request, then stake should work
-    await ctx.cancelUnstakeRequest(user);
-    await ctx.stake(user, userToken, BigInt(500_000_000));
+    // Deposit rewards
+    const rewardAmount = BigInt(1_000_000_000); // 1 SOL
+    await ctx.depositRewards(rewardAmount);
+
+    // B claims rewards directly (normal ClaimRewards)
+    const bBalBefore = BigInt(await ctx.getBalance(userB.publicKey));
+    await ctx.claimRewards(userB);
+    const bBalAfter = BigInt(await ctx.getBalance(userB.publicKey));
+
+    const rewardReceived = bBalAfter - bBalBefore;
+    if (rewardReceived <= 0n) {
+      throw new Error(`B should have received rewards, got ${rewardReceived}`);
+    }
+    console.log(`    B received ${rewardReceived} lamports in rewards`);
   });
 
-  // Test: Cannot request unstake twice
-  await test(`[${tokenProgramLabel}] Cooldown: cannot request unstake twice`, async () => {
+  await test(`[${tokenProgramLabel}] StakeOnBehalf: add-more preserves pending rewards for beneficiary`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.initializePool(BigInt(60)); // tau = 60s
 
-    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+    const userA = Keypair.generate();
+    const userB = Keypair.generate();
+    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
+    const totalTokens = BigInt(2_000_000_000);
+    await ctx.mintTokens(userAToken, totalTokens);
 
-    // First request succeeds
-    await ctx.requestUnstake(user, BigInt(500_000_000));
+    // A stakes first batch on behalf of B
+    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, BigInt(1_000_000_000));
 
-    // Second request should fail
-    let doubleRequestFailed = false;
-    try {
-      await ctx.requestUnstake(user, BigInt(500_000_000));
-    } catch (e) {
-      doubleRequestFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0x1a')) {
-        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${errMsg}`);
-      }
-    }
-    if (!doubleRequestFailed) throw new Error('Double request should fail');
-  });
+    // Wait for maturity and deposit rewards
+    console.log('    Waiting 10s for maturity...');
+    await new Promise(r => setTimeout(r, 10000));
 
-  // Test: Existing pools (zero reserved fields) work unchanged
-  await test(`[${tokenProgramLabel}] Backward compat: existing pool works with zero settings`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000));
+    const rewardAmount = BigInt(1_000_000_000);
+    await ctx.depositRewards(rewardAmount);
+
+    // A adds more stake on behalf of B — no auto-claim, pending preserved
+    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, BigInt(1_000_000_000));
 
-    // With default settings (all zeros), everything should work as before
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    // Verify combined stake amount
+    const userStakeState = await ctx.readUserStakeState(userB.publicKey);
+    if (userStakeState.amount !== totalTokens) {
+      throw new Error(`Expected combined stake ${totalTokens}, got ${userStakeState.amount}`);
+    }
 
-    // Stake works (no min stake)
-    await ctx.stake(user, userToken, BigInt(100));
+    // B can still claim accumulated rewards
+    const bBalBefore = BigInt(await ctx.getBalance(userB.publicKey));
+    await ctx.claimRewards(userB);
+    const bBalAfter = BigInt(await ctx.getBalance(userB.publicKey));
+    const bRewards = bBalAfter - bBalBefore;
 
-    // Unstake works immediately (no lock, no cooldown)
-    await ctx.unstake(user, userToken, BigInt(100));
+    if (bRewards <= 0n) {
+      throw new Error(`B should have claimable rewards after add-stake, got ${bRewards}`);
+    }
+    console.log(`    B claimed ${bRewards} lamports after add-stake on behalf`);
+    console.log('    Add-more preserves pending rewards for beneficiary: OK');
   });
 
-  // ============================================
-  // MATHEMATICAL CORRECTNESS TESTS
-  // ============================================
-  // Verify stakers receive the correct proportional rewards based on the
-  // weight formula: weight = amount × (1 - e^(-age/τ))
-
-  console.log('\n--- Mathematical Correctness Tests ---\n');
+  // =========================================================================
+  // FixStakeAccount tests
+  // =========================================================================
 
-  // Test: Weight formula verification - new staker gains weight over old staker
-  await test(`[${tokenProgramLabel}] Math: New staker weight increases relative to old staker`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+  // Test: FixStakeAccount corrects exp_start_factor and reward_debt
+  await test(`[${tokenProgramLabel}] FixStakeAccount: corrects user state and pool aggregates`, async () => {
+    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n); // tau = 60s (minimum)
 
-    // tau=60 (minimum allowed)
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
 
-    // Old staker stakes first and waits to accumulate weight
-    const oldStaker = Keypair.generate();
-    await airdropAndConfirm(connection, oldStaker.publicKey, 3 * LAMPORTS_PER_SOL);
-    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
-    await ctx.mintTokens(oldToken, BigInt(1_000_000_000));
-    await ctx.stake(oldStaker, oldToken, BigInt(1_000_000_000));
+    // Alice stakes
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
 
-    // Wait for old staker to accumulate weight (~28% at 20s/60s tau)
-    console.log(`    Waiting 20s for old staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 20000));
+    // Deposit rewards so acc_rps is non-zero
+    console.log('    Waiting 10s for weight...');
+    await new Promise(r => setTimeout(r, 10000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // New staker joins - starts with ~0% weight
-    const newStaker = Keypair.generate();
-    await airdropAndConfirm(connection, newStaker.publicKey, 3 * LAMPORTS_PER_SOL);
-    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
-    await ctx.mintTokens(newToken, BigInt(1_000_000_000));
-    await ctx.stake(newStaker, newToken, BigInt(1_000_000_000));
+    // Read current state
+    const stateBefore = await ctx.readUserStakeState(alice.publicKey);
+    const poolBefore = await ctx.readPoolState();
+    console.log(`    Before: exp_start_factor=${stateBefore.expStartFactor}, reward_debt=${stateBefore.rewardDebt}`);
 
-    // Deposit and check shares (old should dominate)
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    // Fix with new values (simulate correcting a blending bug)
+    const newExpStartFactor = stateBefore.expStartFactor + 1000n;
+    const newRewardDebt = stateBefore.rewardDebt + 500n;
+    await ctx.fixStakeAccount(programAuthority, alice.publicKey, newExpStartFactor, newRewardDebt);
 
-    let oldBal = await ctx.getBalance(oldStaker.publicKey);
-    await ctx.claimRewards(oldStaker);
-    const oldReward1 = (await ctx.getBalance(oldStaker.publicKey)) - oldBal;
+    // Verify user state was updated
+    const stateAfter = await ctx.readUserStakeState(alice.publicKey);
+    if (stateAfter.expStartFactor !== newExpStartFactor) {
+      throw new Error(`exp_start_factor not updated: expected ${newExpStartFactor}, got ${stateAfter.expStartFactor}`);
+    }
+    if (stateAfter.rewardDebt !== newRewardDebt) {
+      throw new Error(`reward_debt not updated: expected ${newRewardDebt}, got ${stateAfter.rewardDebt}`);
+    }
 
-    // New staker has ~0 weight, so likely gets nothing or very little
-    let newBal = await ctx.getBalance(newStaker.publicKey);
+    // Verify pool aggregates were adjusted
+    const poolAfter = await ctx.readPoolState();
+    const debtDelta = poolAfter.totalRewardDebt - poolBefore.totalRewardDebt;
+    if (debtDelta !== 500n) {
+      throw new Error(`Pool total_reward_debt delta: expected 500, got ${debtDelta}`);
+    }
+    console.log(`    After: exp_start_factor=${stateAfter.expStartFactor}, reward_debt=${stateAfter.rewardDebt}`);
+    console.log('    Pool aggregates correctly adjusted');
+  });
+
+  // Test: FixStakeAccount rejects non-upgrade-authority signer
+  await test(`[${tokenProgramLabel}] FixStakeAccount: rejects non-upgrade-authority`, async () => {
+    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n); // tau = 60s (minimum)
+
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+
+    // Try to fix using a random keypair (not upgrade authority)
+    const faker = Keypair.generate();
+    await airdropAndConfirm(connection, faker.publicKey, LAMPORTS_PER_SOL);
     try {
-      await ctx.claimRewards(newStaker);
+      await ctx.fixStakeAccount(faker, alice.publicKey, 0n, 0n);
+      throw new Error('Should have rejected non-upgrade-authority');
     } catch (e: any) {
-      if (!e.message?.includes('0xb')) throw e;
+      if (e.message === 'Should have rejected non-upgrade-authority') throw e;
+      console.log('    Correctly rejected non-upgrade-authority signer');
     }
-    const newReward1 = (await ctx.getBalance(newStaker.publicKey)) - newBal;
-    if (newReward1 < 0) throw new Error(`Unexpected SOL loss: ${newReward1}`);
+  });
 
-    const total1 = oldReward1 + newReward1;
-    const newShare1 = total1 > 0 ? (newReward1 * 100) / total1 : 0;
-    console.log(`    New staker share at t=0: ${newShare1.toFixed(1)}%`);
+  // Test: FixStakeAccount allows user to claim corrected rewards
+  await test(`[${tokenProgramLabel}] FixStakeAccount: user can claim corrected rewards after fix`, async () => {
+    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n); // tau = 60s (minimum)
 
-    // Wait 10s - new staker should gain relative share (~15% weight)
-    console.log(`    Waiting 10s for new staker weight...`);
-    await new Promise(r => setTimeout(r, 10000));
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
 
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    // Alice stakes
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
 
-    oldBal = await ctx.getBalance(oldStaker.publicKey);
-    await ctx.claimRewards(oldStaker);
-    const oldReward2 = (await ctx.getBalance(oldStaker.publicKey)) - oldBal;
+    // Wait for partial maturity and deposit rewards
+    console.log('    Waiting 15s for weight...');
+    await new Promise(r => setTimeout(r, 15000));
+    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
 
-    newBal = await ctx.getBalance(newStaker.publicKey);
-    await ctx.claimRewards(newStaker);
-    const newReward2 = (await ctx.getBalance(newStaker.publicKey)) - newBal;
+    // Read state before any claim — reward_debt is 0 (staked before deposits)
+    const stateBeforeFix = await ctx.readUserStakeState(alice.publicKey);
 
-    const newShare2 = (newReward2 * 100) / (oldReward2 + newReward2);
-    console.log(`    New staker share at t=10s: ${newShare2.toFixed(1)}%`);
+    // Simulate the add-stake bug: RAISE reward_debt so the snapshot is too high,
+    // which suppresses claimable rewards.
+    const WAD = 1_000_000_000_000_000_000n;
+    const inflatedDebt = stateBeforeFix.rewardDebt + BigInt(LAMPORTS_PER_SOL) * WAD;
+    await ctx.fixStakeAccount(programAuthority, alice.publicKey, stateBeforeFix.expStartFactor, inflatedDebt);
 
-    // New staker's share should increase over time
-    if (newShare2 <= newShare1) {
-      throw new Error(`New staker share should increase: ${newShare1}% -> ${newShare2}%`);
+    // Claim with inflated debt — gives reduced rewards (snapshot too high)
+    const balBefore = BigInt(await ctx.getBalance(alice.publicKey));
+    await ctx.claimRewards(alice);
+    const balAfterFirstClaim = BigInt(await ctx.getBalance(alice.publicKey));
+    const firstClaim = balAfterFirstClaim - balBefore;
+    console.log(`    First claim (with inflated debt): ${firstClaim} lamports`);
+
+    // Now fix: lower reward_debt back to the correct value
+    await ctx.fixStakeAccount(programAuthority, alice.publicKey, stateBeforeFix.expStartFactor, stateBeforeFix.rewardDebt);
+
+    // Alice should now be able to claim the rewards that were suppressed
+    const balBeforeSecondClaim = BigInt(await ctx.getBalance(alice.publicKey));
+    await ctx.claimRewards(alice);
+    const balAfterSecondClaim = BigInt(await ctx.getBalance(alice.publicKey));
+    const secondClaim = balAfterSecondClaim - balBeforeSecondClaim;
+    console.log(`    Second claim after fix: ${secondClaim} lamports`);
+
+    if (secondClaim <= 0n) {
+      throw new Error('Alice should have additional claimable rewards after fix');
     }
+    console.log('    User successfully claimed corrected rewards');
   });
 
-  // Test: Old staker gets more than new staker
-  await test(`[${tokenProgramLabel}] Math: Old staker gets more rewards than new staker`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+  // Test: FixStakeAccount rejects zero-balance stake
+  await test(`[${tokenProgramLabel}] FixStakeAccount: rejects zero-balance stake account`, async () => {
+    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n); // tau = 60s (minimum)
 
-    // tau=60 (minimum allowed)
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
 
-    // Old staker stakes first
-    const oldStaker = Keypair.generate();
-    await airdropAndConfirm(connection, oldStaker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
-    const stakeAmount = BigInt(1_000_000_000);
-    await ctx.mintTokens(oldToken, stakeAmount);
-    await ctx.stake(oldStaker, oldToken, stakeAmount);
+    // Alice stakes then fully unstakes
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await ctx.unstake(alice, aliceToken, BigInt(1_000_000_000));
 
-    // Wait 20s - old staker will have ~28% weight at tau=60
-    console.log(`    Waiting 20s for old staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 20000));
+    try {
+      await ctx.fixStakeAccount(programAuthority, alice.publicKey, 0n, 0n);
+      throw new Error('Should have rejected zero-balance stake');
+    } catch (e: any) {
+      if (e.message === 'Should have rejected zero-balance stake') throw e;
+      console.log('    Correctly rejected zero-balance stake account');
+    }
+  });
 
-    // New staker stakes now (will have ~0% weight)
-    const newStaker = Keypair.generate();
-    await airdropAndConfirm(connection, newStaker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
-    await ctx.mintTokens(newToken, stakeAmount);
-    await ctx.stake(newStaker, newToken, stakeAmount);
+  // =========================================================================
+  // VerifyVaultConsistency tests
+  // =========================================================================
 
-    // Deposit rewards - use smaller amount to ensure pool has enough
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+  await test(`[${tokenProgramLabel}] VerifyVaultConsistency: healthy pool passes`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // Claim for old staker first
-    const oldBalanceBefore = await ctx.getBalance(oldStaker.publicKey);
-    await ctx.claimRewards(oldStaker);
-    const oldBalanceAfter = await ctx.getBalance(oldStaker.publicKey);
-    const oldReward = oldBalanceAfter - oldBalanceBefore;
+    // Empty pool is consistent
+    await ctx.verifyVaultConsistency();
 
-    // New staker should have nearly 0 weight, so minimal/no rewards
-    const newBalanceBefore = await ctx.getBalance(newStaker.publicKey);
-    try {
-      await ctx.claimRewards(newStaker);
-    } catch (e: any) {
-      if (!e.message?.includes('0xb')) throw e;
-      console.log(`    New staker claim: insufficient (expected)`);
-    }
-    const newBalanceAfter = await ctx.getBalance(newStaker.publicKey);
-    const newReward = newBalanceAfter - newBalanceBefore;
-    if (newReward < 0) throw new Error(`Unexpected SOL loss: ${newReward}`);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await ctx.claimRewards(user);
+    await ctx.unstake(user, userToken, BigInt(400_000_000));
 
-    console.log(`    Old staker (20s age) reward: ${oldReward} lamports`);
-    console.log(`    New staker (~0 age) reward: ${newReward} lamports`);
+    // Still consistent after stake, deposit, claim and partial unstake
+    await ctx.verifyVaultConsistency();
+  });
 
-    // Old staker should get significantly more
-    if (oldReward <= newReward) {
-      throw new Error(`Old staker should get more: old=${oldReward}, new=${newReward}`);
-    }
+  await test(`[${tokenProgramLabel}] VerifyVaultConsistency: tokens sent directly to vault trip InvariantViolation`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // Old staker should get >75% of rewards.
-    // With τ=60s and 20s wait, old has ~28% weight.
-    // New staker gains ~3% weight during ~2s of tx processing.
-    // Old share ≈ 28/(28+3) ≈ 90%.
-    const totalRewards = oldReward + newReward;
-    if (totalRewards > 0) {
-      const oldShare = (oldReward * 100) / totalRewards;
-      console.log(`    Old staker share: ${oldShare.toFixed(1)}%`);
-      if (oldShare < 75) {
-        throw new Error(`Old staker should get >75% of rewards, got ${oldShare}%`);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Bypass the program: vault balance no longer matches total_staked
+    await transferChecked(
+      connection, ctx.payer, userToken, ctx.mint, ctx.tokenVaultPDA, user,
+      BigInt(1), 9, [], undefined, tokenProgramId,
+    );
+
+    try {
+      await ctx.verifyVaultConsistency();
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // InvariantViolation = error code 35 = 0x23
+      if (!e.message.includes('custom program error: 0x23')) {
+        throw new Error(`Expected InvariantViolation (0x23), got: ${e.message}`);
       }
     }
   });
 
-  // Test: Equal stakers get equal rewards (when both matured)
-  await test(`[${tokenProgramLabel}] Math: Equal age stakers get equal rewards (matured)`, async () => {
+  // =========================================================================
+  // ClaimToBalance / WithdrawClaimed tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ClaimToBalance: credit then withdraw pays exactly the claimed total`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // tau=60 (minimum allowed)
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
-
-    // Two stakers stake same amount
-    const staker1 = Keypair.generate();
-    const staker2 = Keypair.generate();
-    await airdropAndConfirm(connection, staker1.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, staker2.publicKey, 2 * LAMPORTS_PER_SOL);
-
-    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
-    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
-    const stakeAmount = BigInt(1_000_000_000);
-    await ctx.mintTokens(token1, stakeAmount);
-    await ctx.mintTokens(token2, stakeAmount);
-
-    // Stake sequentially (slight time difference)
-    await ctx.stake(staker1, token1, stakeAmount);
-    await ctx.stake(staker2, token2, stakeAmount);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait 15s for both stakes to accumulate similar weight (~22% at tau=60)
-    // The ~1s staking difference is small relative to tau=60
-    console.log(`    Waiting 15s for both stakes to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 15000));
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
 
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    const userBalanceBefore = await ctx.getBalance(user.publicKey);
 
-    // Claim for both
-    const balance1Before = await ctx.getBalance(staker1.publicKey);
-    await ctx.claimRewards(staker1);
-    const balance1After = await ctx.getBalance(staker1.publicKey);
-    const reward1 = balance1After - balance1Before;
+    // Two credits accumulate in the balance without moving SOL
+    await ctx.claimToBalance(user);
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.claimToBalance(user);
 
-    const balance2Before = await ctx.getBalance(staker2.publicKey);
-    await ctx.claimRewards(staker2);
-    const balance2After = await ctx.getBalance(staker2.publicKey);
-    const reward2 = balance2After - balance2Before;
+    const credited = await ctx.readUserStakeState(user.publicKey);
+    if (credited.claimedBalance === 0n) {
+      throw new Error('Expected a non-zero claimed balance');
+    }
+    if (credited.claimedBalance !== credited.totalRewardsClaimed) {
+      throw new Error(`claimed_balance ${credited.claimedBalance} != total_rewards_claimed ${credited.totalRewardsClaimed}`);
+    }
+    if (await ctx.getBalance(user.publicKey) !== userBalanceBefore) {
+      throw new Error('ClaimToBalance should not transfer SOL');
+    }
 
-    console.log(`    Staker 1 reward: ${reward1} lamports`);
-    console.log(`    Staker 2 reward: ${reward2} lamports`);
+    // Pool accounting stays consistent while lamports are reserved
+    await ctx.verifyVaultConsistency();
 
-    // Should be approximately equal (within 15% - both at similar weight with tau=60)
-    const diff = Math.abs(reward1 - reward2);
-    const avg = (reward1 + reward2) / 2;
-    const diffPercent = (diff * 100) / avg;
-    console.log(`    Difference: ${diffPercent.toFixed(2)}%`);
+    await ctx.withdrawClaimed(user);
 
-    if (diffPercent > 15) {
-      throw new Error(`Equal-age stakers should get ~equal rewards, diff=${diffPercent}%`);
+    const received = BigInt(await ctx.getBalance(user.publicKey) - userBalanceBefore);
+    if (received !== credited.claimedBalance) {
+      throw new Error(`Withdrew ${received} lamports, expected ${credited.claimedBalance}`);
+    }
+    const after = await ctx.readUserStakeState(user.publicKey);
+    if (after.claimedBalance !== 0n) {
+      throw new Error(`Expected claimed balance reset to 0, got ${after.claimedBalance}`);
     }
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: 2x stake = 2x rewards (when both fully matured)
-  await test(`[${tokenProgramLabel}] Math: Double stake gets double rewards (matured)`, async () => {
+  await test(`[${tokenProgramLabel}] ClaimToBalance: close blocked until balance withdrawn`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // tau=60 (minimum allowed)
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Staker 1: stakes 1 token
-    // Staker 2: stakes 2 tokens
-    const staker1 = Keypair.generate();
-    const staker2 = Keypair.generate();
-    await airdropAndConfirm(connection, staker1.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, staker2.publicKey, 2 * LAMPORTS_PER_SOL);
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await ctx.claimToBalance(user);
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
 
-    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
-    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
-    await ctx.mintTokens(token1, BigInt(1_000_000_000));
-    await ctx.mintTokens(token2, BigInt(2_000_000_000));
+    try {
+      await ctx.closeStakeAccount(user);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // OutstandingRewards = error code 62 = 0x3e
+      if (!e.message.includes('custom program error: 0x3e')) {
+        throw new Error(`Expected OutstandingRewards (0x3e), got: ${e.message}`);
+      }
+    }
 
-    // Stake both
-    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
-    await ctx.stake(staker2, token2, BigInt(2_000_000_000));
+    await ctx.withdrawClaimed(user);
+    await ctx.closeStakeAccount(user);
+  });
 
-    // Wait 15s for both stakes to accumulate similar weight (~22% at tau=60)
-    // The small timing difference between stakes is negligible relative to tau=60
-    console.log(`    Waiting 15s for both stakes to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 15000));
+  // =========================================================================
+  // Deferred deposit cap tests
+  // =========================================================================
 
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+  await test(`[${tokenProgramLabel}] max_deferred_lamports: deposits rejected past the cap with no stakers`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // Claim
-    const balance1Before = await ctx.getBalance(staker1.publicKey);
-    await ctx.claimRewards(staker1);
-    const balance1After = await ctx.getBalance(staker1.publicKey);
-    const reward1 = balance1After - balance1Before;
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { maxDeferredLamports: BigInt(LAMPORTS_PER_SOL / 10) });
 
-    const balance2Before = await ctx.getBalance(staker2.publicKey);
-    await ctx.claimRewards(staker2);
-    const balance2After = await ctx.getBalance(staker2.publicKey);
-    const reward2 = balance2After - balance2Before;
+    // Up to the cap is accepted and deferred
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 20));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 20));
 
-    console.log(`    1x stake reward: ${reward1} lamports`);
-    console.log(`    2x stake reward: ${reward2} lamports`);
+    try {
+      await ctx.depositRewards(1n);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // TooMuchDeferred = error code 36 = 0x24
+      if (!e.message.includes('custom program error: 0x24')) {
+        throw new Error(`Expected TooMuchDeferred (0x24), got: ${e.message}`);
+      }
+    }
 
-    // Staker 2 should get ~2x rewards
-    const ratio = reward2 / reward1;
-    console.log(`    Ratio: ${ratio.toFixed(2)}x`);
+    // Once someone stakes, deposits distribute again
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
 
-    if (ratio < 1.8 || ratio > 2.2) {
-      throw new Error(`2x stake should get ~2x rewards, got ${ratio}x`);
-    }
+    // 0 restores the unlimited default
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { maxDeferredLamports: 0n });
   });
 
-  // Test: Verify weight differentiation between old and new staker
-  await test(`[${tokenProgramLabel}] Math: Older staker gets proportionally more rewards`, async () => {
+  // =========================================================================
+  // GrandfatherStake tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] GrandfatherStake: earlier start earns more of later deposits`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // tau=60 (minimum allowed)
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    // Leave room between pool creation and the stakes
+    await new Promise(r => setTimeout(r, 20000));
 
-    // Old staker stakes and waits to accumulate weight
-    const oldStaker = Keypair.generate();
-    await airdropAndConfirm(connection, oldStaker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const oldToken = await ctx.createUserTokenAccount(oldStaker.publicKey);
-    await ctx.mintTokens(oldToken, BigInt(1_000_000_000));
-    await ctx.stake(oldStaker, oldToken, BigInt(1_000_000_000));
+    const migrated = Keypair.generate();
+    const fresh = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [migrated, fresh]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    await ctx.stake(migrated, tokens[0], BigInt(1_000_000_000));
+    await ctx.stake(fresh, tokens[1], BigInt(1_000_000_000));
 
-    // Wait 20s for old staker (~28.3% weight at tau=60)
-    console.log(`    Waiting 20s for old staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 20000));
+    // Future or too-early start times are rejected
+    const before = await ctx.readUserStakeState(migrated.publicKey);
+    for (const bad of [before.stakeTime + 3600n, before.stakeTime - 3600n]) {
+      try {
+        await ctx.grandfatherStake(ctx.payer, migrated.publicKey, bad);
+        throw new Error('Should have failed');
+      } catch (e: any) {
+        // InvalidStakeTime = error code 37 = 0x25
+        if (!e.message.includes('custom program error: 0x25')) {
+          throw new Error(`Expected InvalidStakeTime (0x25), got: ${e.message}`);
+        }
+      }
+    }
 
-    // New staker stakes same amount
-    const newStaker = Keypair.generate();
-    await airdropAndConfirm(connection, newStaker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const newToken = await ctx.createUserTokenAccount(newStaker.publicKey);
-    await ctx.mintTokens(newToken, BigInt(1_000_000_000));
-    await ctx.stake(newStaker, newToken, BigInt(1_000_000_000));
+    await ctx.grandfatherStake(ctx.payer, migrated.publicKey, before.stakeTime - 15n);
+    const after = await ctx.readUserStakeState(migrated.publicKey);
+    if (after.stakeTime !== before.stakeTime - 15n) {
+      throw new Error(`Expected stake_time ${before.stakeTime - 15n}, got ${after.stakeTime}`);
+    }
 
-    // Wait 10s for new staker (~15.4% weight), old is now at ~39.3%
-    console.log(`    Waiting 10s for new staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 10000));
+    // One-shot per stake account
+    try {
+      await ctx.grandfatherStake(ctx.payer, migrated.publicKey, before.stakeTime - 16n);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // AlreadyGrandfathered = error code 38 = 0x26
+      if (!e.message.includes('custom program error: 0x26')) {
+        throw new Error(`Expected AlreadyGrandfathered (0x26), got: ${e.message}`);
+      }
+    }
 
-    // Now: old staker has ~39% weight (30s age), new staker has ~15% weight (10s age)
-    // Old should get: 39/(39+15) = ~72%
-    // New should get: 15/(39+15) = ~28%
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await ctx.claimRewards(migrated);
+    await ctx.claimRewards(fresh);
 
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    const migratedClaimed = (await ctx.readUserStakeState(migrated.publicKey)).totalRewardsClaimed;
+    const freshClaimed = (await ctx.readUserStakeState(fresh.publicKey)).totalRewardsClaimed;
+    if (migratedClaimed <= freshClaimed) {
+      throw new Error(`Grandfathered stake should out-earn a fresh one: ${migratedClaimed} <= ${freshClaimed}`);
+    }
+    await ctx.verifyVaultConsistency();
+  });
 
-    const oldBalBefore = await ctx.getBalance(oldStaker.publicKey);
-    await ctx.claimRewards(oldStaker);
-    const oldBalAfter = await ctx.getBalance(oldStaker.publicKey);
-    const oldReward = oldBalAfter - oldBalBefore;
+  // =========================================================================
+  // SplitStake tests
+  // =========================================================================
 
-    const newBalBefore = await ctx.getBalance(newStaker.publicKey);
-    await ctx.claimRewards(newStaker);
-    const newBalAfter = await ctx.getBalance(newStaker.publicKey);
-    const newReward = newBalAfter - newBalBefore;
+  await test(`[${tokenProgramLabel}] SplitStake: both halves claim what the original would have`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    const total = oldReward + newReward;
-    const oldPercent = (oldReward * 100) / total;
-    const newPercent = (newReward * 100) / total;
+    // alice will split; bob holds an identical unsplit position as reference
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    const carol = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [alice, bob, carol]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    }
+    for (const user of [alice, bob]) {
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    // Stake both in one transaction so they share the same start time
+    const [aliceStake] = deriveUserStakePDA(ctx.poolPDA, alice.publicKey);
+    const [bobStake] = deriveUserStakePDA(ctx.poolPDA, bob.publicKey);
+    const [carolStake] = deriveUserStakePDA(ctx.poolPDA, carol.publicKey);
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createStakeInstruction(ctx.poolPDA, aliceStake, ctx.tokenVaultPDA, tokens[0], ctx.mint, alice.publicKey, BigInt(1_000_000_000), tokenProgramId),
+      createStakeInstruction(ctx.poolPDA, bobStake, ctx.tokenVaultPDA, tokens[1], ctx.mint, bob.publicKey, BigInt(1_000_000_000), tokenProgramId),
+    ), [ctx.payer, alice, bob]);
 
-    console.log(`    Old staker (30s, ~39% weight): ${oldPercent.toFixed(1)}%`);
-    console.log(`    New staker (10s, ~15% weight): ${newPercent.toFixed(1)}%`);
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
 
-    // Expected: old ~72%, new ~28% (with some tolerance for timing)
-    if (oldPercent < 55 || oldPercent > 85) {
-      throw new Error(`Old staker should get ~72%, got ${oldPercent}%`);
+    // alice claims part of her rewards before splitting so claimed_rewards_wad is non-zero
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createClaimRewardsInstruction(ctx.poolPDA, aliceStake, alice.publicKey),
+      createClaimRewardsInstruction(ctx.poolPDA, bobStake, bob.publicKey),
+    ), [ctx.payer, alice, bob]);
+
+    await ctx.splitStake(alice, carol.publicKey, BigInt(300_000_000));
+
+    const aliceState = await ctx.readUserStakeState(alice.publicKey);
+    const carolState = await ctx.readUserStakeState(carol.publicKey);
+    if (aliceState.amount !== 700_000_000n || carolState.amount !== 300_000_000n) {
+      throw new Error(`Unexpected split amounts: ${aliceState.amount} / ${carolState.amount}`);
     }
-    if (newPercent < 15 || newPercent > 45) {
-      throw new Error(`New staker should get ~28%, got ${newPercent}%`);
+    if (carolState.expStartFactor !== aliceState.expStartFactor) {
+      throw new Error('Split position should keep the same maturity');
+    }
+
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+
+    // Claim all three at the same timestamp
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createClaimRewardsInstruction(ctx.poolPDA, aliceStake, alice.publicKey),
+      createClaimRewardsInstruction(ctx.poolPDA, carolStake, carol.publicKey),
+      createClaimRewardsInstruction(ctx.poolPDA, bobStake, bob.publicKey),
+    ), [ctx.payer, alice, carol, bob]);
+
+    const aliceTotal = (await ctx.readUserStakeState(alice.publicKey)).totalRewardsClaimed;
+    const carolTotal = (await ctx.readUserStakeState(carol.publicKey)).totalRewardsClaimed;
+    const bobTotal = (await ctx.readUserStakeState(bob.publicKey)).totalRewardsClaimed;
+    const splitTotal = aliceTotal + carolTotal;
+    const diff = splitTotal > bobTotal ? splitTotal - bobTotal : bobTotal - splitTotal;
+    // Allow a couple of lamports of rounding across the two positions
+    if (diff > 2n) {
+      throw new Error(`Split halves claimed ${splitTotal}, unsplit position claimed ${bobTotal}`);
+    }
+    await ctx.verifyVaultConsistency();
+  });
+
+  await test(`[${tokenProgramLabel}] SplitStake: rejected while unstake request is pending`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.requestUnstake(user, BigInt(500_000_000));
+
+    try {
+      await ctx.splitStake(user, Keypair.generate().publicKey, BigInt(100_000_000));
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // PendingUnstakeRequestExists = error code 26 = 0x1a
+      if (!e.message.includes('custom program error: 0x1a')) {
+        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${e.message}`);
+      }
     }
   });
 
-  // ============================================
-  // ABUSE / SECURITY TESTS
-  // ============================================
-  // Verify the system cannot be gamed or exploited
-
-  console.log('\n--- Abuse / Security Tests ---\n');
+  // =========================================================================
+  // Loyalty bonus tests
+  // =========================================================================
 
-  // Test: Sybil attack - splitting stake doesn't give advantage
-  await test(`[${tokenProgramLabel}] Abuse: Sybil attack (split stake) gives no advantage`, async () => {
+  await test(`[${tokenProgramLabel}] Loyalty bonus: unclaimed position out-earns a frequent claimer`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { loyaltyBonusBps: 2000n });
 
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
-
-    // Honest staker: 2 tokens in one account
-    const honest = Keypair.generate();
-    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
-    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
-    await ctx.mintTokens(honestToken, BigInt(2_000_000_000));
-    await ctx.stake(honest, honestToken, BigInt(2_000_000_000));
+    const patient = Keypair.generate();
+    const eager = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [patient, eager]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    const [patientStake] = deriveUserStakePDA(ctx.poolPDA, patient.publicKey);
+    const [eagerStake] = deriveUserStakePDA(ctx.poolPDA, eager.publicKey);
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createStakeInstruction(ctx.poolPDA, patientStake, ctx.tokenVaultPDA, tokens[0], ctx.mint, patient.publicKey, BigInt(1_000_000_000), tokenProgramId),
+      createStakeInstruction(ctx.poolPDA, eagerStake, ctx.tokenVaultPDA, tokens[1], ctx.mint, eager.publicKey, BigInt(1_000_000_000), tokenProgramId),
+    ), [ctx.payer, patient, eager]);
+
+    // eager claims after every deposit, patient never does
+    for (let i = 0; i < 4; i++) {
+      await new Promise(r => setTimeout(r, 5000));
+      await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 20));
+      await ctx.claimRewards(eager);
+    }
 
-    // Sybil attacker: 2 tokens split across 2 accounts (1 each)
-    const sybil1 = Keypair.generate();
-    const sybil2 = Keypair.generate();
-    await airdropAndConfirm(connection, sybil1.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, sybil2.publicKey, 2 * LAMPORTS_PER_SOL);
-    const sybil1Token = await ctx.createUserTokenAccount(sybil1.publicKey);
-    const sybil2Token = await ctx.createUserTokenAccount(sybil2.publicKey);
-    await ctx.mintTokens(sybil1Token, BigInt(1_000_000_000));
-    await ctx.mintTokens(sybil2Token, BigInt(1_000_000_000));
-    await ctx.stake(sybil1, sybil1Token, BigInt(1_000_000_000));
-    await ctx.stake(sybil2, sybil2Token, BigInt(1_000_000_000));
+    // Final claim for both at the same timestamp
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createClaimRewardsInstruction(ctx.poolPDA, patientStake, patient.publicKey),
+      createClaimRewardsInstruction(ctx.poolPDA, eagerStake, eager.publicKey),
+    ), [ctx.payer, patient, eager]);
 
-    // Wait for weight accumulation (~22% at 15s/60s tau)
-    console.log(`    Waiting 15s for stakes to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 15000));
+    const patientTotal = (await ctx.readUserStakeState(patient.publicKey)).totalRewardsClaimed;
+    const eagerTotal = (await ctx.readUserStakeState(eager.publicKey)).totalRewardsClaimed;
+    if (patientTotal <= eagerTotal) {
+      throw new Error(`Unclaimed position should earn more: patient=${patientTotal}, eager=${eagerTotal}`);
+    }
 
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    // Bonus never pays out more than was deposited
+    if (patientTotal + eagerTotal > BigInt(LAMPORTS_PER_SOL / 5)) {
+      throw new Error(`Payouts ${patientTotal + eagerTotal} exceed deposits`);
+    }
+    await ctx.verifyVaultConsistency();
+  });
 
-    // Claim for all
-    const honestBefore = await ctx.getBalance(honest.publicKey);
-    await ctx.claimRewards(honest);
-    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+  // =========================================================================
+  // Lock boost tests
+  // =========================================================================
 
-    const sybil1Before = await ctx.getBalance(sybil1.publicKey);
-    await ctx.claimRewards(sybil1);
-    const sybil1Reward = (await ctx.getBalance(sybil1.publicKey)) - sybil1Before;
+  await test(`[${tokenProgramLabel}] Lock boost: a one-year lock earns double while maturing`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
 
-    const sybil2Before = await ctx.getBalance(sybil2.publicKey);
-    await ctx.claimRewards(sybil2);
-    const sybil2Reward = (await ctx.getBalance(sybil2.publicKey)) - sybil2Before;
+    // SettingExceedsMaximum = error code 29 = 0x1d
+    try {
+      await ctx.updatePoolSettings(ctx.payer, null, null, null, { lockBoostBps: BigInt(10_001) });
+      throw new Error('Boost above 2x should fail');
+    } catch (e: any) {
+      if (!e.message.includes('custom program error: 0x1d')) {
+        throw new Error(`Expected SettingExceedsMaximum (0x1d), got: ${e.message}`);
+      }
+    }
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { lockBoostBps: BigInt(10_000) });
 
-    const sybilTotal = sybil1Reward + sybil2Reward;
+    const locked = Keypair.generate();
+    const unlocked = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [locked, unlocked]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    const [lockedStake] = deriveUserStakePDA(ctx.poolPDA, locked.publicKey);
+    const [unlockedStake] = deriveUserStakePDA(ctx.poolPDA, unlocked.publicKey);
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createStakeInstruction(ctx.poolPDA, lockedStake, ctx.tokenVaultPDA, tokens[0], ctx.mint, locked.publicKey, BigInt(1_000_000_000), tokenProgramId,
+        { lockCommitSeconds: BigInt(365 * 24 * 60 * 60) }),
+      createStakeInstruction(ctx.poolPDA, unlockedStake, ctx.tokenVaultPDA, tokens[1], ctx.mint, unlocked.publicKey, BigInt(1_000_000_000), tokenProgramId),
+    ), [ctx.payer, locked, unlocked]);
+
+    // StakeLocked = error code 22 = 0x16
+    try {
+      await ctx.requestUnstake(locked, BigInt(1_000_000_000));
+      throw new Error('Locked stake should not request an unstake');
+    } catch (e: any) {
+      if (!e.message.includes('custom program error: 0x16')) {
+        throw new Error(`Expected StakeLocked (0x16), got: ${e.message}`);
+      }
+    }
 
-    console.log(`    Honest (2 tokens): ${honestReward} lamports`);
-    console.log(`    Sybil (1+1 tokens): ${sybilTotal} lamports`);
+    await new Promise(r => setTimeout(r, 10000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // Sybil should NOT get more than honest staker
-    // Allow small variance for timing differences
-    const ratio = sybilTotal / honestReward;
-    console.log(`    Sybil/Honest ratio: ${ratio.toFixed(3)}`);
+    // Claim both at the same timestamp
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createClaimRewardsInstruction(ctx.poolPDA, lockedStake, locked.publicKey),
+      createClaimRewardsInstruction(ctx.poolPDA, unlockedStake, unlocked.publicKey),
+    ), [ctx.payer, locked, unlocked]);
 
-    if (ratio > 1.1) {
-      throw new Error(`Sybil attack should not be profitable: ratio=${ratio}`);
+    const lockedTotal = (await ctx.readUserStakeState(locked.publicKey)).totalRewardsClaimed;
+    const unlockedTotal = (await ctx.readUserStakeState(unlocked.publicKey)).totalRewardsClaimed;
+    if (unlockedTotal === 0n) {
+      throw new Error('Unlocked stake should have earned something');
     }
+    const ratio = Number(lockedTotal) / Number(unlockedTotal);
+    if (ratio < 1.9 || ratio > 2.1) {
+      throw new Error(`Expected the locked stake to earn about double: locked=${lockedTotal}, unlocked=${unlockedTotal}`);
+    }
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: Flash stake attack - staking right before deposit
-  await test(`[${tokenProgramLabel}] Abuse: Flash stake attack (stake before deposit) fails`, async () => {
+  // =========================================================================
+  // exclude_depositor tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] exclude_depositor: staked depositor receives none of their own deposit`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { excludeDepositor: true });
 
-    // tau=60 so tx processing time (~2s) gives attacker minimal weight
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
-
-    // Honest staker stakes early
-    const honest = Keypair.generate();
-    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
-    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
-    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
-    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
-
-    // Wait 20s for honest staker to accumulate weight (~28% at tau=60)
-    console.log(`    Waiting 20s for honest staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 20000));
+    const depositor = Keypair.generate();
+    const other = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [depositor, other]) {
+      await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    await ctx.stake(depositor, tokens[0], BigInt(1_000_000_000));
+    await ctx.stake(other, tokens[1], BigInt(1_000_000_000));
+    const [depositorStake] = deriveUserStakePDA(ctx.poolPDA, depositor.publicKey);
 
-    // Attacker stakes right before deposit (flash stake)
-    const attacker = Keypair.generate();
-    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
-    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
-    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
+    // Without the depositor's stake account the deposit is rejected
+    try {
+      await sendAndConfirmTransaction(connection, new Transaction().add(
+        createDepositRewardsInstruction(ctx.poolPDA, depositor.publicKey, BigInt(LAMPORTS_PER_SOL / 10)),
+      ), [ctx.payer, depositor]);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // DepositorStakeRequired = error code 39 = 0x27
+      if (!e.message.includes('custom program error: 0x27')) {
+        throw new Error(`Expected DepositorStakeRequired (0x27), got: ${e.message}`);
+      }
+    }
 
-    // Deposit immediately after attacker stakes
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    await new Promise(r => setTimeout(r, 5000));
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createDepositRewardsInstruction(ctx.poolPDA, depositor.publicKey, BigInt(LAMPORTS_PER_SOL / 10), depositorStake),
+    ), [ctx.payer, depositor]);
 
-    // Claim for both
-    const honestBefore = await ctx.getBalance(honest.publicKey);
-    await ctx.claimRewards(honest);
-    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+    await ctx.claimRewards(depositor);
+    await ctx.claimRewards(other);
 
-    const attackerBefore = await ctx.getBalance(attacker.publicKey);
-    try {
-      await ctx.claimRewards(attacker);
-    } catch (e) {
-      // Expected - attacker has ~0 weight
+    const depositorState = await ctx.readUserStakeState(depositor.publicKey);
+    const otherState = await ctx.readUserStakeState(other.publicKey);
+    if (depositorState.totalRewardsClaimed !== 0n) {
+      throw new Error(`Depositor should earn nothing from own deposit, got ${depositorState.totalRewardsClaimed}`);
+    }
+    if (otherState.totalRewardsClaimed === 0n) {
+      throw new Error('Other staker should receive the deposit');
     }
-    const attackerReward = Math.max(0, (await ctx.getBalance(attacker.publicKey)) - attackerBefore);
 
-    console.log(`    Honest staker (20s age) reward: ${honestReward} lamports`);
-    console.log(`    Flash attacker (new) reward: ${attackerReward} lamports`);
+    // Deposits from non-stakers still reach everyone
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await ctx.claimRewards(depositor);
+    if ((await ctx.readUserStakeState(depositor.publicKey)).totalRewardsClaimed === 0n) {
+      throw new Error('Depositor should share in third-party deposits');
+    }
+    await ctx.verifyVaultConsistency();
+  });
 
-    // Honest staker should get the vast majority (~28% weight vs ~3%)
-    const honestShare = (honestReward * 100) / (honestReward + attackerReward);
-    console.log(`    Honest staker share: ${honestShare.toFixed(1)}%`);
+  await test(`[${tokenProgramLabel}] exclude_depositor: every position of the depositor is excluded`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { excludeDepositor: true });
 
-    // With τ=60s and 20s wait, honest has ~28% weight, attacker ~3%
-    // Honest share ≈ 28/(28+3) ≈ 90%
-    if (honestShare < 75) {
-      throw new Error(`Flash stake should not be profitable: honest=${honestShare}%`);
+    const depositor = Keypair.generate();
+    const other = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [depositor, other]) {
+      await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    await ctx.stake(other, tokens[1], BigInt(1_000_000_000));
+
+    // The depositor's stake sits in position 1, the default account is empty
+    await ctx.initializeUserStake(depositor, 0);
+    await ctx.initializeUserStake(depositor, 1);
+    const [defaultStake] = deriveUserStakePDA(ctx.poolPDA, depositor.publicKey);
+    const [position] = deriveUserStakePositionPDA(ctx.poolPDA, depositor.publicKey, 1);
+    await sendAndConfirmTransaction(connection, new Transaction().add(createStakeInstruction(
+      ctx.poolPDA, position, ctx.tokenVaultPDA, tokens[0], ctx.mint, depositor.publicKey,
+      BigInt(1_000_000_000), tokenProgramId,
+    )), [ctx.payer, depositor]);
+
+    // Passing only the default account no longer hides position 1
+    try {
+      await sendAndConfirmTransaction(connection, new Transaction().add(
+        createDepositRewardsInstruction(ctx.poolPDA, depositor.publicKey, BigInt(LAMPORTS_PER_SOL / 10), defaultStake),
+      ), [ctx.payer, depositor]);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // DepositorStakeRequired = error code 39 = 0x27
+      if (!e.message.includes('custom program error: 0x27')) {
+        throw new Error(`Expected DepositorStakeRequired (0x27), got: ${e.message}`);
+      }
     }
+
+    await new Promise(r => setTimeout(r, 5000));
+    const ix = createDepositRewardsInstruction(ctx.poolPDA, depositor.publicKey, BigInt(LAMPORTS_PER_SOL / 10), defaultStake);
+    ix.keys.push({ pubkey: position, isSigner: false, isWritable: true });
+    await sendAndConfirmTransaction(connection, new Transaction().add(ix), [ctx.payer, depositor]);
+
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      createClaimRewardsInstruction(ctx.poolPDA, position, depositor.publicKey),
+    ), [ctx.payer, depositor]);
+    const info = await connection.getAccountInfo(position);
+    if (!info) throw new Error('Position 1 not found');
+    const claimed = info.data.readBigUInt64LE(153);
+    if (claimed !== 0n) throw new Error(`Position 1 should earn nothing from the depositor's deposit, got ${claimed}`);
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: Cannot unstake more than staked
-  await test(`[${tokenProgramLabel}] Abuse: Cannot unstake more than staked`, async () => {
+  // =========================================================================
+  // QueryClaimableAt tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] QueryClaimableAt: projection matches the claim at that time`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100));
+    await ctx.initializePool(60n);
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // Try to unstake 2x what was staked
-    let failed = false;
+    const chainTime = async () => BigInt((await connection.getBlockTime(await connection.getSlot()))!);
+    const now = await chainTime();
+
+    // Past timestamps are rejected
     try {
-      await ctx.unstake(user, userToken, BigInt(2_000_000_000));
+      await ctx.queryClaimableAt(user.publicKey, now - 60n);
+      throw new Error('Should have failed');
     } catch (e: any) {
-      failed = true;
-      console.log(`    Correctly rejected: ${e.message?.includes('0xa') ? 'InsufficientStakeBalance' : 'error'}`);
+      // TimestampInPast = error code 41 = 0x29
+      if (!e.message.includes('"Custom":41')) {
+        throw new Error(`Expected TimestampInPast (41), got: ${e.message}`);
+      }
     }
 
-    if (!failed) {
-      throw new Error('Should not be able to unstake more than staked');
+    // The projection grows with maturity and never exceeds the deposit
+    const target = now + 10n;
+    const atTarget = await ctx.queryClaimableAt(user.publicKey, target);
+    const later = await ctx.queryClaimableAt(user.publicKey, target + 5n);
+    const matured = await ctx.queryClaimableAt(user.publicKey, now + 60n * 20n);
+    if (!(atTarget > 0n && atTarget <= later && later <= matured && matured <= BigInt(LAMPORTS_PER_SOL))) {
+      throw new Error(`Unexpected projections: ${atTarget}, ${later}, ${matured}`);
+    }
+
+    // Wait for the target time and claim: the payout falls between the
+    // projections for the target and a few seconds after it
+    while ((await chainTime()) < target) {
+      await new Promise(r => setTimeout(r, 500));
+    }
+    await ctx.claimRewards(user);
+    const claimed = (await ctx.readUserStakeState(user.publicKey)).totalRewardsClaimed;
+    if (claimed < atTarget || claimed > later) {
+      throw new Error(`Claimed ${claimed}, expected between ${atTarget} and ${later}`);
     }
   });
 
-  // Test: Cannot profit from claim after full unstake
-  await test(`[${tokenProgramLabel}] Abuse: Cannot claim after full unstake`, async () => {
+  // =========================================================================
+  // Deposit nonce (idempotency) tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] DepositRewards: repeated nonce is distributed once`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(60n);
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
@@ -2224,565 +7913,780 @@ async function runTests() {
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait for weight, deposit rewards
-    await new Promise(r => setTimeout(r, 2000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
-
-    // Claim rewards first
-    await ctx.claimRewards(user);
-
-    // Fully unstake
-    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
-
-    // Deposit more rewards (user has amount=0, should not benefit)
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    const amount = BigInt(LAMPORTS_PER_SOL / 10);
+    await ctx.depositRewards(amount, 42n);
+    const afterFirst = await ctx.readPoolState();
+    const lamportsAfterFirst = await connection.getBalance(ctx.poolPDA);
 
-    // Claim again — should succeed silently (amount==0 path returns Ok)
-    // but should give the user 0 SOL from the new deposit
-    const balBefore = BigInt(await ctx.getBalance(user.publicKey));
-    await ctx.claimRewards(user);
-    const balAfter = BigInt(await ctx.getBalance(user.publicKey));
+    // Retry with the same nonce: no transfer, no distribution (wait for a new
+    // blockhash so the runtime doesn't reject it as a duplicate transaction)
+    await new Promise(r => setTimeout(r, 1000));
+    await ctx.depositRewards(amount, 42n);
+    const afterRetry = await ctx.readPoolState();
+    if (afterRetry.accRewardPerWeightedShare !== afterFirst.accRewardPerWeightedShare) {
+      throw new Error('Repeated nonce should not distribute again');
+    }
+    if (await connection.getBalance(ctx.poolPDA) !== lamportsAfterFirst) {
+      throw new Error('Repeated nonce should not transfer SOL');
+    }
 
-    // User should gain nothing (or lose tx fee)
-    const gained = balAfter - balBefore;
-    if (gained > BigInt(0)) {
-      throw new Error(`User gained ${gained} lamports after full unstake — should be 0`);
+    // A new nonce deposits normally
+    await ctx.depositRewards(amount, 43n);
+    const afterNew = await ctx.readPoolState();
+    if (afterNew.accRewardPerWeightedShare <= afterFirst.accRewardPerWeightedShare) {
+      throw new Error('New nonce should distribute');
     }
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: Cannot double claim
-  await test(`[${tokenProgramLabel}] Abuse: Cannot double claim same rewards`, async () => {
+  // =========================================================================
+  // per_stake_terms tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] per_stake_terms: lock change applies only to later stakes`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { perStakeTerms: true });
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const early = Keypair.generate();
+    const late = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [early, late]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
 
-    // Wait and deposit
-    await new Promise(r => setTimeout(r, 2000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    // Staked with no lock
+    await ctx.stake(early, tokens[0], BigInt(1_000_000_000));
 
-    // First claim
-    const balance1 = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const reward1 = (await ctx.getBalance(user.publicKey)) - balance1;
-    console.log(`    First claim: ${reward1} lamports`);
+    // Authority adds a 1-hour lock; only new stakes get it
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(3600), null);
+    await ctx.stake(late, tokens[1], BigInt(1_000_000_000));
 
-    // Second claim — with max-weight denominator, weight maturation between claims
-    // yields additional rewards. This is NOT double-claiming: the user is entitled
-    // to more as their stake matures. Verify conservation instead.
-    const balance2 = await ctx.getBalance(user.publicKey);
     try {
-      await ctx.claimRewards(user);
+      await ctx.unstake(late, tokens[1], BigInt(1_000_000_000));
+      throw new Error('Should have failed');
     } catch (e: any) {
-      if (!e.message?.includes('0xb')) throw e;
+      if (!e.message.includes('custom program error: 0x16')) {
+        throw new Error(`Expected StakeLocked (0x16), got: ${e.message}`);
+      }
     }
-    const reward2 = (await ctx.getBalance(user.publicKey)) - balance2;
-    console.log(`    Second claim: ${reward2} lamports`);
 
-    // Second claim should be strictly less than first (diminishing returns)
-    if (reward2 >= reward1) {
-      throw new Error(`Possible double-claim: reward2 (${reward2}) >= reward1 (${reward1})`);
+    // The earlier staker keeps the terms they staked under
+    await ctx.unstake(early, tokens[0], BigInt(1_000_000_000));
+    const earlyState = await ctx.readUserStakeState(early.publicKey);
+    if (earlyState.amount !== 0n) {
+      throw new Error(`Early staker should have unstaked, amount=${earlyState.amount}`);
+    }
+  });
+
+  // =========================================================================
+  // Protocol fee tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] CollectProtocolFees: fees are separate from staker rewards`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { claimFeeBps: 1000n });
+
+    const first = Keypair.generate();
+    const second = Keypair.generate();
+    for (const user of [first, second]) {
+      await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    }
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // 10% of the first claim is withheld for the protocol
+    await ctx.claimRewards(first);
+    const received = (await ctx.readUserStakeState(first.publicKey)).totalRewardsClaimed;
+    const fees = (await ctx.readPoolState()).accruedProtocolFees;
+    if (received === 0n || fees === 0n) {
+      throw new Error(`Expected payout and fee, got ${received} / ${fees}`);
+    }
+    const gross = received + fees;
+    if (fees !== gross / 10n) {
+      throw new Error(`Fee ${fees} should be 10% of ${gross}`);
+    }
+
+    // What the second staker can claim is unaffected by collection
+    const now = BigInt((await connection.getBlockTime(await connection.getSlot()))!);
+    const claimableBefore = await ctx.queryClaimableAt(second.publicKey, now + 600n);
+
+    const treasury = Keypair.generate().publicKey;
+    await ctx.collectProtocolFees(ctx.payer, treasury);
+    if (BigInt(await connection.getBalance(treasury)) !== fees) {
+      throw new Error('Treasury should receive exactly the accrued fees');
+    }
+    if ((await ctx.readPoolState()).accruedProtocolFees !== 0n) {
+      throw new Error('Accrued fees should be zeroed');
+    }
+    const claimableAfter = await ctx.queryClaimableAt(second.publicKey, now + 600n);
+    if (claimableAfter !== claimableBefore) {
+      throw new Error(`Collection changed staker entitlement: ${claimableBefore} -> ${claimableAfter}`);
+    }
+    await ctx.verifyVaultConsistency();
+
+    // Second collection transfers nothing
+    await ctx.collectProtocolFees(ctx.payer, treasury);
+    if (BigInt(await connection.getBalance(treasury)) !== fees) {
+      throw new Error('Second collection should yield zero');
     }
 
-    // Conservation: total claimed must not exceed deposit
-    const totalClaimed = reward1 + reward2;
-    const depositAmount = LAMPORTS_PER_SOL;
-    if (totalClaimed > depositAmount) {
-      throw new Error(`Over-distribution: claimed ${totalClaimed} > deposited ${depositAmount}`);
+    await ctx.claimRewards(second);
+    if ((await ctx.readUserStakeState(second.publicKey)).totalRewardsClaimed === 0n) {
+      throw new Error('Second staker should still be able to claim');
     }
-    console.log(`    Conservation OK: claimed ${reward1 + reward2} <= deposited ${depositAmount}`);
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: Stake/unstake cycling doesn't reset weight unfairly
-  await test(`[${tokenProgramLabel}] Abuse: Stake/unstake cycling resets weight`, async () => {
+  // =========================================================================
+  // QuarantinePool tests
+  // =========================================================================
+
+  // Token 2022 only accepts mint extensions before InitializeMint, so a
+  // forbidden extension can't be injected into a live mint here; this checks
+  // that the crank leaves a pool with an acceptable mint untouched.
+  await test(`[${tokenProgramLabel}] QuarantinePool: acceptable mint is not quarantined`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    await ctx.quarantinePool();
 
-    // Honest staker - stakes and holds
-    const honest = Keypair.generate();
-    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
-    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
-    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
-    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+    const info = await connection.getAccountInfo(ctx.poolPDA);
+    // 364: quarantined (bool, absent in smaller legacy pools)
+    if (info!.data.length > 364 && info!.data[364] !== 0) {
+      throw new Error('Pool should not be quarantined');
+    }
 
-    // Cycler - stakes, waits, unstakes, restakes (trying to game)
-    const cycler = Keypair.generate();
-    await airdropAndConfirm(connection, cycler.publicKey, 2 * LAMPORTS_PER_SOL);
-    const cyclerToken = await ctx.createUserTokenAccount(cycler.publicKey);
-    await ctx.mintTokens(cyclerToken, BigInt(1_000_000_000));
-    await ctx.stake(cycler, cyclerToken, BigInt(1_000_000_000));
+    // Stakes still go through
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+  });
 
-    // Wait 10s (honest gets ~15.4% weight at tau=60)
-    console.log(`    Waiting 10s...`);
-    await new Promise(r => setTimeout(r, 10000));
+  // =========================================================================
+  // ViewPoolHealth tests
+  // =========================================================================
 
-    // Cycler unstakes and restakes (resets their weight!)
-    await ctx.unstake(cycler, cyclerToken, BigInt(1_000_000_000));
-    await ctx.stake(cycler, cyclerToken, BigInt(1_000_000_000));
+  await test(`[${tokenProgramLabel}] ViewPoolHealth: healthy pool is all green, vault drift flips its bit`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
 
-    // Claim
-    const honestBefore = await ctx.getBalance(honest.publicKey);
-    await ctx.claimRewards(honest);
-    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+    const healthy = await ctx.viewPoolHealth();
+    if (healthy !== HEALTH_ALL_GREEN) {
+      throw new Error(`Expected all green (${HEALTH_ALL_GREEN}), got ${healthy}`);
+    }
 
-    const cyclerBefore = await ctx.getBalance(cycler.publicKey);
+    // Bypass the program: vault balance no longer matches total_staked.
+    // (Rent and last_synced faults can't be induced from outside the program,
+    // and quarantine needs a mint extension that can't be added post-init.)
+    await transferChecked(
+      connection, ctx.payer, userToken, ctx.mint, ctx.tokenVaultPDA, user,
+      BigInt(1), 9, [], undefined, tokenProgramId,
+    );
+    const drifted = await ctx.viewPoolHealth();
+    if (drifted !== (HEALTH_ALL_GREEN & ~HEALTH_VAULT_MATCHES_TOTAL_STAKED)) {
+      throw new Error(`Expected only the vault bit cleared, got ${drifted}`);
+    }
+    if (drifted & HEALTH_QUARANTINED) {
+      throw new Error('Pool should not report quarantined');
+    }
+  });
+
+  // =========================================================================
+  // BatchRequestUnstake tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] BatchRequestUnstake: skips pending requests, or fails on them when asked`, async () => {
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    // Three pools with a cooldown, the user staked in each
+    const ctxs: TestContext[] = [];
+    for (let i = 0; i < 3; i++) {
+      const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+      await ctx.setup();
+      await ctx.createMint(9);
+      await ctx.initializePool(BigInt(2592000));
+      await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60));
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+      ctxs.push(ctx);
+    }
+
+    // Pool 0 already has a partial request pending
+    await ctxs[0].requestUnstake(user, BigInt(400_000_000));
+
+    const stakes = ctxs.map(ctx => ({
+      pool: ctx.poolPDA,
+      userStake: deriveUserStakePDA(ctx.poolPDA, user.publicKey)[0],
+    }));
+
+    // fail_on_pending: the whole batch is rejected and nothing changes
+    let failed = false;
     try {
-      await ctx.claimRewards(cycler);
-    } catch (e: any) {
-      if (!e.message?.includes('0xb')) throw e;
+      const tx = new Transaction().add(createBatchRequestUnstakeInstruction(user.publicKey, stakes, true));
+      await sendAndConfirmTransaction(connection, tx, [user]);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1a')) {
+        throw new Error(`Expected PendingUnstakeRequestExists (0x1a), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Batch with fail_on_pending should fail on a pending request');
+    if ((await ctxs[1].readUserStakeState(user.publicKey)).unstakeRequestAmount !== 0n) {
+      throw new Error('Failed batch should not create requests');
     }
-    const cyclerReward = (await ctx.getBalance(cycler.publicKey)) - cyclerBefore;
-    if (cyclerReward < 0) throw new Error(`Unexpected SOL loss: ${cyclerReward}`);
 
-    console.log(`    Honest (held 10s): ${honestReward} lamports`);
-    console.log(`    Cycler (reset weight): ${cyclerReward} lamports`);
+    // Default: pool 0 is skipped, the others request their full balance
+    const tx = new Transaction().add(createBatchRequestUnstakeInstruction(user.publicKey, stakes, false));
+    await sendAndConfirmTransaction(connection, tx, [user]);
 
-    // Honest should get more since cycler reset their weight
-    if (honestReward <= cyclerReward) {
-      throw new Error(`Cycling should reset weight: honest=${honestReward}, cycler=${cyclerReward}`);
+    const pending = await ctxs[0].readUserStakeState(user.publicKey);
+    if (pending.unstakeRequestAmount !== 400_000_000n) {
+      throw new Error(`Pending request should be untouched, got ${pending.unstakeRequestAmount}`);
+    }
+    for (const ctx of ctxs.slice(1)) {
+      const state = await ctx.readUserStakeState(user.publicKey);
+      if (state.unstakeRequestAmount !== 1_000_000_000n) {
+        throw new Error(`Expected full-balance request, got ${state.unstakeRequestAmount}`);
+      }
     }
   });
 
-  // Test: Zero amount operations fail
-  await test(`[${tokenProgramLabel}] Abuse: Zero amount operations rejected`, async () => {
+  await test(`[${tokenProgramLabel}] BatchRequestUnstake: lock is enforced per pool`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100));
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(3600), BigInt(60));
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Try zero stake
-    let zeroStakeFailed = false;
+    const [userStake] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+    let failed = false;
     try {
-      await ctx.stake(user, userToken, BigInt(0));
+      const tx = new Transaction().add(
+        createBatchRequestUnstakeInstruction(user.publicKey, [{ pool: ctx.poolPDA, userStake }], false),
+      );
+      await sendAndConfirmTransaction(connection, tx, [user]);
     } catch (e) {
-      zeroStakeFailed = true;
+      failed = true;
       const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0xe')) {
-        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
+      if (!errMsg.includes('0x16')) {
+        throw new Error(`Expected StakeLocked (0x16), got: ${errMsg}`);
       }
     }
+    if (!failed) throw new Error('Locked stake should fail the batch');
+  });
 
-    // Stake normally first
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+  // =========================================================================
+  // ClaimFor tests
+  // =========================================================================
 
-    // Try zero unstake
-    let zeroUnstakeFailed = false;
-    try {
-      await ctx.unstake(user, userToken, BigInt(0));
-    } catch (e) {
-      zeroUnstakeFailed = true;
-      const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0xe')) {
-        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
-      }
-    }
+  await test(`[${tokenProgramLabel}] ClaimFor: third party triggers claim, owner receives the SOL`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(10));
 
-    // Try zero deposit
-    let zeroDepositFailed = false;
+    const owner = Keypair.generate();
+    const keeper = Keypair.generate();
+    await airdropAndConfirm(connection, owner.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, keeper.publicKey, LAMPORTS_PER_SOL);
+
+    const ownerToken = await ctx.createUserTokenAccount(owner.publicKey);
+    await ctx.mintTokens(ownerToken, BigInt(1_000_000_000));
+    await ctx.stake(owner, ownerToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+
+    // Without the owner's opt-in the keeper is rejected
+    let failed = false;
     try {
-      await ctx.depositRewards(BigInt(0));
+      await ctx.claimFor(owner.publicKey, keeper);
     } catch (e) {
-      zeroDepositFailed = true;
+      failed = true;
       const errMsg = (e as any).message || '';
-      if (!errMsg.includes('0xe')) {
-        throw new Error(`Expected ZeroAmount (0xe), got: ${errMsg}`);
+      if (!errMsg.includes('0x2c')) {
+        throw new Error(`Expected ClaimForNotEnabled (0x2c), got: ${errMsg}`);
       }
     }
+    if (!failed) throw new Error('ClaimFor should require the owner opt-in');
 
-    console.log(`    Zero stake rejected: ${zeroStakeFailed}`);
-    console.log(`    Zero unstake rejected: ${zeroUnstakeFailed}`);
-    console.log(`    Zero deposit rejected: ${zeroDepositFailed}`);
+    await ctx.setClaimForOptIn(owner, true);
 
-    if (!zeroStakeFailed) {
-      throw new Error('Zero stake should be rejected');
+    const ownerBefore = await ctx.getBalance(owner.publicKey);
+    const keeperBefore = await ctx.getBalance(keeper.publicKey);
+    await ctx.claimFor(owner.publicKey, keeper);
+    const ownerAfter = await ctx.getBalance(owner.publicKey);
+    const keeperAfter = await ctx.getBalance(keeper.publicKey);
+
+    const state = await ctx.readUserStakeState(owner.publicKey);
+    if (ownerAfter - ownerBefore <= 0) {
+      throw new Error(`Owner should receive the rewards, got ${ownerAfter - ownerBefore}`);
     }
-    if (!zeroUnstakeFailed) {
-      throw new Error('Zero unstake should be rejected');
+    if (BigInt(ownerAfter - ownerBefore) !== state.totalRewardsClaimed) {
+      throw new Error(`Owner received ${ownerAfter - ownerBefore}, stake recorded ${state.totalRewardsClaimed}`);
     }
-    if (!zeroDepositFailed) {
-      throw new Error('Zero deposit should be rejected');
+    // The keeper only pays the transaction fee
+    if (keeperAfter >= keeperBefore) {
+      throw new Error(`Keeper should not gain lamports (${keeperBefore} -> ${keeperAfter})`);
+    }
+
+    // Opting out blocks third parties again
+    await ctx.setClaimForOptIn(owner, false);
+    failed = false;
+    try {
+      await ctx.claimFor(owner.publicKey, keeper);
+    } catch (e) {
+      failed = true;
     }
+    if (!failed) throw new Error('ClaimFor should fail after opting out');
   });
 
-  // Test: Frontrunning deposit with equal stake
-  // Note: With vastly different stake amounts, the attacker may still win on absolute weight
-  // This test verifies that equal stakes favor the mature staker
-  await test(`[${tokenProgramLabel}] Abuse: Frontrunning deposit (equal stakes)`, async () => {
+  // =========================================================================
+  // Unstake destination tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] CompleteUnstake: non-ATA destination owned by the user`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(2));
 
-    // tau=60 so tx processing time (~2s) gives attacker minimal weight
-    const tauSeconds = BigInt(60);
-    await ctx.initializePool(tauSeconds);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
 
-    // Honest staker
-    const honest = Keypair.generate();
-    await airdropAndConfirm(connection, honest.publicKey, 2 * LAMPORTS_PER_SOL);
-    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
-    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
-    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+    // A token account of another mint is rejected
+    const otherMint = await createMint(
+      connection, ctx.payer, ctx.payer.publicKey, null, 9, undefined, undefined, tokenProgramId,
+    );
+    const wrongMintToken = await createAccount(
+      connection, ctx.payer, otherMint, user.publicKey, Keypair.generate(), undefined, tokenProgramId,
+    );
+    let failed = false;
+    try {
+      await ctx.completeUnstake(user, wrongMintToken);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('custom program error: 0x3')) {
+        throw new Error(`Expected InvalidPoolMint (0x3), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Unstake to a different mint should fail');
 
-    // Wait 20s for honest staker to accumulate weight (~28% at tau=60)
-    console.log(`    Waiting 20s for honest staker to accumulate weight...`);
-    await new Promise(r => setTimeout(r, 20000));
+    // A keypair-addressed (non-ATA) account of the pool mint works
+    const nonAta = await createAccount(
+      connection, ctx.payer, ctx.mint, user.publicKey, Keypair.generate(), undefined, tokenProgramId,
+    );
+    await ctx.completeUnstake(user, nonAta);
 
-    // Frontrunner stakes equal amount right before deposit
-    const attacker = Keypair.generate();
-    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
-    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
-    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
-    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
+    const balance = await ctx.getTokenBalance(nonAta);
+    if (balance !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected 1000000000 tokens in the non-ATA account, got ${balance}`);
+    }
+  });
 
-    // Deposit immediately
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+  // =========================================================================
+  // SetRewardFeeSplit tests
+  // =========================================================================
 
-    // Claim
-    const honestBefore = await ctx.getBalance(honest.publicKey);
-    await ctx.claimRewards(honest);
-    const honestReward = (await ctx.getBalance(honest.publicKey)) - honestBefore;
+  await test(`[${tokenProgramLabel}] SetRewardFeeSplit: claim pays each split exactly, user gets the remainder`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(10));
 
-    const attackerBefore = await ctx.getBalance(attacker.publicKey);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+
+    // Funded recipients, so small credits never leave them below rent exemption
+    const recipients = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    for (const r of recipients) {
+      await airdropAndConfirm(connection, r.publicKey, LAMPORTS_PER_SOL);
+    }
+    const shares = [500, 300, 200];
+    const splits = recipients.map((r, i) => ({ recipient: r.publicKey, bps: shares[i] }));
+
+    // More than the 10% fee cap is rejected
+    let failed = false;
     try {
-      await ctx.claimRewards(attacker);
-    } catch (e: any) {
-      if (!e.message?.includes('0xb')) throw e;
+      await ctx.setRewardFeeSplit(ctx.payer, [{ recipient: recipients[0].publicKey, bps: 1001 }]);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1d')) {
+        throw new Error(`Expected SettingExceedsMaximum (0x1d), got: ${errMsg}`);
+      }
     }
-    const attackerReward = (await ctx.getBalance(attacker.publicKey)) - attackerBefore;
-    if (attackerReward < 0) throw new Error(`Unexpected SOL loss: ${attackerReward}`);
+    if (!failed) throw new Error('Fee split above the cap should fail');
 
-    const total = honestReward + attackerReward;
-    const honestShare = (honestReward * 100) / total;
+    await ctx.setRewardFeeSplit(ctx.payer, splits);
 
-    console.log(`    Honest (1 token, 20s age): ${honestShare.toFixed(1)}% (${honestReward} lamports)`);
-    console.log(`    Attacker (1 token, new): ${(100-honestShare).toFixed(1)}% (${attackerReward} lamports)`);
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // With τ=60s and 20s wait, honest has ~28% weight, attacker ~3%
-    // Honest share ≈ 28/(28+3) ≈ 90%
-    if (honestShare < 75) {
-      throw new Error(`Frontrunning should not be profitable: honest only got ${honestShare}%`);
+    // Claiming without the recipient accounts fails
+    failed = false;
+    try {
+      await ctx.claimRewards(user);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x2e')) {
+        throw new Error(`Expected FeeRecipientMismatch (0x2e), got: ${errMsg}`);
+      }
     }
-  });
-
-  // ============================================
-  // SECURITY REGRESSION TESTS
-  // ============================================
-  // Verify fixes for identified security audit findings
-
-  console.log('\n--- Security Regression Tests ---\n');
+    if (!failed) throw new Error('Claim without fee split recipients should fail');
+
+    const before = await Promise.all(recipients.map(r => ctx.getBalance(r.publicKey)));
+    const userBefore = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user, recipients.map(r => r.publicKey));
+    const after = await Promise.all(recipients.map(r => ctx.getBalance(r.publicKey)));
+    const userGain = BigInt((await ctx.getBalance(user.publicKey)) - userBefore);
+
+    const portions = after.map((a, i) => BigInt(a - before[i]));
+    const total = userGain + portions.reduce((a, b) => a + b, 0n);
+    if (total <= 0n) throw new Error('Expected a non-zero claim');
+    portions.forEach((portion, i) => {
+      const expected = total * BigInt(shares[i]) / 10_000n;
+      if (portion !== expected) {
+        throw new Error(`Recipient ${i}: expected ${expected} lamports, got ${portion}`);
+      }
+    });
+    const state = await ctx.readUserStakeState(user.publicKey);
+    if (state.totalRewardsClaimed !== userGain) {
+      throw new Error(`User received ${userGain}, stake recorded ${state.totalRewardsClaimed}`);
+    }
+  });
 
-  // Test: DepositRewards + SyncRewards does not double-count
-  await test(`[${tokenProgramLabel}] Security: DepositRewards + SyncRewards does not double-count`, async () => {
+  await test(`[${tokenProgramLabel}] SetRewardFeeSplit: a recipient that can't receive doesn't block claims`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
+    await ctx.initializePool(BigInt(10));
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait for weight to accumulate (~15% at 10s/60s tau)
-    console.log('    Waiting 10s for weight...');
-    await new Promise(r => setTimeout(r, 10000));
-
-    // Deposit via instruction
-    const depositAmount = BigInt(LAMPORTS_PER_SOL);
-    await ctx.depositRewards(depositAmount);
+    // One funded recipient, one empty account a small credit would leave
+    // below rent exemption
+    const good = Keypair.generate();
+    await airdropAndConfirm(connection, good.publicKey, LAMPORTS_PER_SOL);
+    const empty = Keypair.generate();
+    await ctx.setRewardFeeSplit(ctx.payer, [
+      { recipient: good.publicKey, bps: 500 },
+      { recipient: empty.publicKey, bps: 1 },
+    ]);
 
-    // Call sync_rewards - should find NO new rewards (deposit already accounted for)
-    await ctx.syncRewards();
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 1000));
 
-    // Claim - user should get approximately depositAmount, NOT 2x
-    const balanceBefore = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const balanceAfter = await ctx.getBalance(user.publicKey);
-    const claimed = BigInt(balanceAfter - balanceBefore);
+    // The claim succeeds; the empty recipient's share is reserved
+    const goodBefore = await ctx.getBalance(good.publicKey);
+    await ctx.claimRewards(user, [good.publicKey, empty.publicKey]);
+    if (await ctx.getBalance(good.publicKey) <= goodBefore) throw new Error('Funded recipient should be paid');
+    if (await ctx.getBalance(empty.publicKey) !== 0) throw new Error('Empty recipient should not be credited');
 
-    console.log(`    Deposited: ${depositAmount} lamports`);
-    console.log(`    Claimed: ${claimed} lamports`);
+    // Still unable to receive: WithdrawFeeSplit fails with InvalidRewardRecipient = error code 63 = 0x3f
+    try {
+      await ctx.withdrawFeeSplit(empty.publicKey);
+      throw new Error('WithdrawFeeSplit to an empty account should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x3f')) throw new Error(`Expected InvalidRewardRecipient (0x3f), got: ${errMsg}`);
+    }
 
-    // Claimed should be <= depositAmount (accounting for tx fees and weight < 100%)
-    // If double-counted, claimed would be close to 2x depositAmount
-    if (claimed > depositAmount + BigInt(6000)) {
-      throw new Error(`Double-counting detected! Claimed ${claimed} > deposited ${depositAmount}`);
+    // Once funded, the reserved share is paid out
+    await airdropAndConfirm(connection, empty.publicKey, LAMPORTS_PER_SOL);
+    await ctx.withdrawFeeSplit(empty.publicKey);
+    if (await ctx.getBalance(empty.publicKey) <= LAMPORTS_PER_SOL) {
+      throw new Error('Reserved fee split share should be paid out');
     }
   });
 
-  // Test: Additional stake does not allow reward theft
-  await test(`[${tokenProgramLabel}] Security: Additional stake does not allow reward theft`, async () => {
+  // =========================================================================
+  // DepositRewards overflow tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] DepositRewards: amount overflowing the pool balance is rejected`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
-
-    const attacker = Keypair.generate();
-    await airdropAndConfirm(connection, attacker.publicKey, 2 * LAMPORTS_PER_SOL);
-
-    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
-    const smallStake = BigInt(1_000); // tiny initial stake
-    const largeStake = BigInt(1_000_000_000); // 1 billion additional
-    await ctx.mintTokens(attackerToken, smallStake + largeStake);
-
-    // Stake small amount
-    await ctx.stake(attacker, attackerToken, smallStake);
-
-    // Wait for weight to accumulate
-    console.log('    Waiting 10s for weight...');
-    await new Promise(r => setTimeout(r, 10000));
-
-    // Deposit rewards
-    const depositAmount = BigInt(LAMPORTS_PER_SOL);
-    await ctx.depositRewards(depositAmount);
-
-    // Now add large stake (attack vector: reward_debt stays at old small-stake value)
-    await ctx.stake(attacker, attackerToken, largeStake);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // Claim - should get rewards proportional to the ORIGINAL small stake, not the new large stake
-    const balanceBefore = await ctx.getBalance(attacker.publicKey);
+    const poolBefore = await ctx.getBalance(ctx.poolPDA);
+    let failed = false;
     try {
-      await ctx.claimRewards(attacker);
+      await ctx.depositRewards(BigInt('18446744073709551615') - BigInt(LAMPORTS_PER_SOL));
     } catch (e) {
-      // May fail if no pending rewards (correct behavior)
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x2f')) {
+        throw new Error(`Expected RewardOverflow (0x2f), got: ${errMsg}`);
+      }
     }
-    const balanceAfter = await ctx.getBalance(attacker.publicKey);
-    const claimed = BigInt(balanceAfter - balanceBefore);
-
-    console.log(`    Small stake: ${smallStake}, Large additional: ${largeStake}`);
-    console.log(`    Deposited: ${depositAmount} lamports`);
-    console.log(`    Claimed: ${claimed} lamports`);
-
-    // With the fix, reward_debt is recalculated on additional stake,
-    // so the attacker shouldn't be able to claim more than what the small stake earned.
-    // A reasonable upper bound: all rewards (since they were the only staker).
-    // But claimed should NOT be inflated by the large stake addition.
-    // The key check: claimed should be roughly proportional to the deposit, not vastly more.
-    if (claimed > depositAmount + BigInt(6000)) {
-      throw new Error(`Reward theft detected! Claimed ${claimed} >> deposited ${depositAmount}`);
+    if (!failed) throw new Error('Deposit overflowing u64 lamports should fail');
+    if ((await ctx.getBalance(ctx.poolPDA)) !== poolBefore) {
+      throw new Error('Rejected deposit should not change the pool balance');
     }
   });
 
-  // Test: Claim then SyncRewards works for new deposits
-  await test(`[${tokenProgramLabel}] Security: Claim then SyncRewards works for new deposits`, async () => {
+  // =========================================================================
+  // ExtendPoolAccount tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ExtendPoolAccount: current-size pool is left unchanged`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
+    await ctx.initializePool(BigInt(2592000));
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
-
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // Wait for weight
-    console.log('    Waiting 10s for weight...');
-    await new Promise(r => setTimeout(r, 10000));
-
-    // Deposit and claim
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
-    const bal1Before = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const claim1 = BigInt((await ctx.getBalance(user.publicKey)) - bal1Before);
-    console.log(`    First claim: ${claim1} lamports`);
-
-    // Send SOL directly to pool (simulating pump.fun)
-    const directAmount = BigInt(LAMPORTS_PER_SOL / 2);
-    await ctx.sendSolToPool(directAmount);
-
-    // Sync rewards - should detect the new direct SOL
-    await ctx.syncRewards();
-
-    // Claim again - should get the new rewards
-    const bal2Before = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const claim2 = BigInt((await ctx.getBalance(user.publicKey)) - bal2Before);
-    console.log(`    Second claim (after direct SOL + sync): ${claim2} lamports`);
+    const before = await connection.getAccountInfo(ctx.poolPDA);
+    const cranker = Keypair.generate();
+    await airdropAndConfirm(connection, cranker.publicKey, LAMPORTS_PER_SOL);
+    const tx = new Transaction().add(createExtendPoolAccountInstruction(ctx.poolPDA, cranker.publicKey));
+    await sendAndConfirmTransaction(connection, tx, [cranker]);
+    const after = await connection.getAccountInfo(ctx.poolPDA);
 
-    // Second claim should be > 0 (new rewards were synced)
-    if (claim2 <= BigInt(0)) {
-      throw new Error(`SyncRewards failed to detect new deposits after claim! Got ${claim2}`);
+    if (!before || !after) throw new Error('Pool account not found');
+    if (!before.data.equals(after.data) || before.lamports !== after.lamports) {
+      throw new Error('Extending a current-size pool should not change it');
     }
   });
 
-  // Test: Unstake rewards then SyncRewards works
-  await test(`[${tokenProgramLabel}] Security: Unstake rewards then SyncRewards works`, async () => {
+  // =========================================================================
+  // ReverseUnstake tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ReverseUnstake: restores the stake within the window`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
+    await ctx.initializePool(BigInt(2592000));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
+    // 2-second cooldown, 60-second reversal window
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(2), {
+      unstakeReversalWindowSeconds: BigInt(60),
+    });
 
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
-    await ctx.stake(user, userToken, BigInt(2_000_000_000));
-
-    // Wait for weight
-    console.log('    Waiting 10s for weight...');
-    await new Promise(r => setTimeout(r, 10000));
-
-    // Deposit rewards
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
-
-    // Unstake half (this claims pending rewards and reduces last_synced_lamports)
-    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const before = await ctx.readUserStakeState(user.publicKey);
 
-    // Send more SOL directly to pool — needs to be large enough to overcome the
-    // immature weight deficit (reward_debt set at max weight during unstake).
-    // At tau=60 with ~15% weight, delta must exceed ~4.5x the old acc_rps.
-    const directAmount = BigInt(5 * LAMPORTS_PER_SOL);
-    await ctx.sendSolToPool(directAmount);
+    await ctx.requestUnstake(user, BigInt(400_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.completeUnstake(user, userToken);
+    if ((await ctx.getTokenBalance(userToken)) !== BigInt(400_000_000)) {
+      throw new Error('Complete unstake should return 400000000 tokens');
+    }
 
-    // Sync rewards - should detect the new direct SOL
-    await ctx.syncRewards();
+    await ctx.reverseUnstake(user, userToken);
 
-    // Claim - should get the new rewards
-    const balBefore = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const claimed = BigInt((await ctx.getBalance(user.publicKey)) - balBefore);
-    console.log(`    Claim after unstake + new SOL + sync: ${claimed} lamports`);
+    const after = await ctx.readUserStakeState(user.publicKey);
+    if (after.amount !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected stake of 1000000000 after reversal, got ${after.amount}`);
+    }
+    if (after.expStartFactor !== before.expStartFactor) {
+      throw new Error('Reversal should keep the original maturity');
+    }
+    if ((await ctx.getTokenBalance(userToken)) !== 0n) {
+      throw new Error('Reversed tokens should be back in the vault');
+    }
+    if ((await ctx.readPoolState()).totalStaked !== BigInt(1_000_000_000)) {
+      throw new Error('Pool total_staked should be restored');
+    }
 
-    // Claimed should be > 0
-    if (claimed <= BigInt(0)) {
-      throw new Error(`SyncRewards failed after unstake! Got ${claimed}`);
+    // The same exit can't be reversed twice
+    let failed = false;
+    try {
+      await ctx.reverseUnstake(user, userToken);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x30')) {
+        throw new Error(`Expected UnstakeNotReversible (0x30), got: ${errMsg}`);
+      }
     }
+    if (!failed) throw new Error('Second reversal should fail');
   });
 
-  // ============================================
-  // STRESS TESTS: Simulate 1 million stakers
-  // ============================================
-  // Since operations are O(1), complexity depends on value magnitudes, not staker count.
-  // We test with values representing: 1M stakers × 1000 tokens each = 1 trillion tokens
-  // This verifies the math handles large aggregates without overflow or excessive compute.
-
-  console.log('\n--- Stress Tests (simulating 1M stakers) ---\n');
-
-  // Test: Large stake amount (simulating pool with many existing stakers)
-  await test(`[${tokenProgramLabel}] Stress: Stake with large existing total`, async () => {
+  await test(`[${tokenProgramLabel}] ReverseUnstake: rejected after the window`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(2592000)); // 30 days tau
-
-    // First, create a "whale" that represents aggregate of ~1M stakers
-    // 1M stakers × 1000 tokens × 10^9 decimals = 10^18 raw tokens
-    const whale = Keypair.generate();
-    await airdropAndConfirm(connection, whale.publicKey, 2 * LAMPORTS_PER_SOL);
-
-    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
-    // Mint a large amount representing aggregate stake
-    // Using 10^15 (1 quadrillion) as a large but safe value
-    const largeAmount = BigInt('1000000000000000'); // 10^15
-    await ctx.mintTokens(whaleToken, largeAmount);
+    await ctx.initializePool(BigInt(2592000));
 
-    const startTime = Date.now();
-    await ctx.stake(whale, whaleToken, largeAmount);
-    const stakeTime = Date.now() - startTime;
-    console.log(`    Large stake (${largeAmount} tokens): ${stakeTime}ms`);
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(2), {
+      unstakeReversalWindowSeconds: BigInt(2),
+    });
 
-    // Now test a regular user staking against this large pool
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    const userAmount = BigInt(1_000_000_000); // 1 token (normal stake)
-    await ctx.mintTokens(userToken, userAmount);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const startTime2 = Date.now();
-    await ctx.stake(user, userToken, userAmount);
-    const stakeTime2 = Date.now() - startTime2;
-    console.log(`    Regular stake against large pool: ${stakeTime2}ms`);
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.completeUnstake(user, userToken);
 
-    // Verify both stakes exist
-    const vaultBalance = await ctx.getTokenBalance(ctx.tokenVaultPDA);
-    if (vaultBalance !== largeAmount + userAmount) {
-      throw new Error(`Expected ${largeAmount + userAmount}, got ${vaultBalance}`);
+    console.log('    Waiting 4s for the reversal window to pass...');
+    await new Promise(r => setTimeout(r, 4000));
+
+    let failed = false;
+    try {
+      await ctx.reverseUnstake(user, userToken);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x30')) {
+        throw new Error(`Expected UnstakeNotReversible (0x30), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Reversal after the window should fail');
+    if ((await ctx.getTokenBalance(userToken)) !== BigInt(1_000_000_000)) {
+      throw new Error('Tokens should stay with the user');
     }
   });
 
-  // Test: Deposit rewards with large total stake
-  await test(`[${tokenProgramLabel}] Stress: Deposit rewards with 1M staker simulation`, async () => {
+  // =========================================================================
+  // QueryMinStakeUi tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] QueryMinStakeUi: returns the raw minimum and cached decimals`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100)); // Short tau for weight accumulation
-
-    // Create large aggregate stake
-    const whale = Keypair.generate();
-    await airdropAndConfirm(connection, whale.publicKey, 5 * LAMPORTS_PER_SOL);
-
-    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
-    const largeAmount = BigInt('1000000000000000'); // 10^15
-    await ctx.mintTokens(whaleToken, largeAmount);
-    await ctx.stake(whale, whaleToken, largeAmount);
-
-    // Wait a bit for weight to accumulate
-    await new Promise(r => setTimeout(r, 1000));
-
-    // Deposit reasonable reward (5 SOL) - tests program handles large stake values
-    const rewardAmount = BigInt(5 * LAMPORTS_PER_SOL);
-    const startTime = Date.now();
-    await ctx.depositRewards(rewardAmount);
-    const depositTime = Date.now() - startTime;
-    console.log(`    Deposit ${rewardAmount} lamports to pool with 10^15 tokens: ${depositTime}ms`);
+    await ctx.createMint(6);
+    await ctx.initializePool(BigInt(2592000));
+
+    let result = await ctx.queryMinStakeUi();
+    if (result.minStakeAmount !== 0n || result.decimals !== 6) {
+      throw new Error(`Expected 0 / 6 decimals, got ${result.minStakeAmount} / ${result.decimals}`);
+    }
+
+    await ctx.updatePoolSettings(ctx.payer, BigInt(2_500_000), null, null);
+    result = await ctx.queryMinStakeUi();
+    if (result.minStakeAmount !== BigInt(2_500_000) || result.decimals !== 6) {
+      throw new Error(`Expected 2500000 / 6 decimals, got ${result.minStakeAmount} / ${result.decimals}`);
+    }
   });
 
-  // Test: Claim rewards with large accumulated rewards
-  await test(`[${tokenProgramLabel}] Stress: Claim with large reward accumulator`, async () => {
+  // =========================================================================
+  // ForceSyncWithBalance tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ForceSyncWithBalance: syncs only at the expected balance`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
+    await ctx.initializePool(BigInt(2592000));
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    const stakeAmount = BigInt('100000000000000'); // 10^14
-    await ctx.mintTokens(userToken, stakeAmount);
-    await ctx.stake(user, userToken, stakeAmount);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait for weight
-    await new Promise(r => setTimeout(r, 500));
+    // SOL sent straight to the pool, not yet synced
+    const transferTx = new Transaction().add(
+      SystemProgram.transfer({
+        fromPubkey: ctx.payer.publicKey,
+        toPubkey: ctx.poolPDA,
+        lamports: LAMPORTS_PER_SOL / 10,
+      }),
+    );
+    await sendAndConfirmTransaction(connection, transferTx, [ctx.payer]);
 
-    // Multiple deposits to accumulate large reward per share
-    for (let i = 0; i < 5; i++) {
-      await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    const balance = BigInt(await ctx.getBalance(ctx.poolPDA));
+    const before = await ctx.readPoolState();
+
+    // Wrong expectation: rejected, nothing synced
+    let failed = false;
+    try {
+      await ctx.forceSyncWithBalance(ctx.payer, balance - 1n);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x31')) {
+        throw new Error(`Expected BalanceMismatch (0x31), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Sync with a stale balance should fail');
+    if ((await ctx.readPoolState()).lastSyncedLamports !== before.lastSyncedLamports) {
+      throw new Error('Rejected sync should not change last_synced_lamports');
     }
 
-    // Claim rewards
-    const startTime = Date.now();
-    await ctx.claimRewards(user);
-    const claimTime = Date.now() - startTime;
-    console.log(`    Claim from large accumulator: ${claimTime}ms`);
+    // Matching expectation: synced
+    await ctx.forceSyncWithBalance(ctx.payer, balance);
+    const after = await ctx.readPoolState();
+    if (after.lastSyncedLamports <= before.lastSyncedLamports) {
+      throw new Error(`Expected the transfer to be synced, got last_synced_lamports ${after.lastSyncedLamports}`);
+    }
+    if (after.accRewardPerWeightedShare <= before.accRewardPerWeightedShare) {
+      throw new Error('Sync should raise the reward accumulator');
+    }
   });
 
-  // Test: Unstake with large pool values
-  await test(`[${tokenProgramLabel}] Stress: Unstake with large pool values`, async () => {
+  // =========================================================================
+  // Mint mismatch errors
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] Stake with another mint account fails with InvalidPoolMint`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
@@ -2790,1941 +8694,2954 @@ async function runTests() {
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    const largeAmount = BigInt('500000000000000'); // 5×10^14
-    await ctx.mintTokens(userToken, largeAmount);
-    await ctx.stake(user, userToken, largeAmount);
-
-    // Partial unstake
-    const unstakeAmount = BigInt('250000000000000'); // Half
-    const startTime = Date.now();
-    await ctx.unstake(user, userToken, unstakeAmount);
-    const unstakeTime = Date.now() - startTime;
-    console.log(`    Large partial unstake (${unstakeAmount}): ${unstakeTime}ms`);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
 
-    // Full unstake remaining
-    const startTime2 = Date.now();
-    await ctx.unstake(user, userToken, unstakeAmount);
-    const unstakeTime2 = Date.now() - startTime2;
-    console.log(`    Full unstake remaining: ${unstakeTime2}ms`);
+    const otherMint = await createMint(
+      connection, ctx.payer, ctx.payer.publicKey, null, 9, undefined, undefined, tokenProgramId,
+    );
+    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+    const ix = createStakeInstruction(
+      ctx.poolPDA, userStakePDA, ctx.tokenVaultPDA, userToken, otherMint, user.publicKey,
+      BigInt(1_000_000_000), tokenProgramId,
+    );
 
-    const balance = await ctx.getTokenBalance(userToken);
-    if (balance !== largeAmount) {
-      throw new Error(`Expected ${largeAmount}, got ${balance}`);
+    let failed = false;
+    try {
+      await sendAndConfirmTransaction(connection, new Transaction().add(ix), [ctx.payer, user]);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('custom program error: 0x3')) {
+        throw new Error(`Expected InvalidPoolMint (0x3), got: ${errMsg}`);
+      }
     }
+    if (!failed) throw new Error('Stake with a foreign mint account should fail');
   });
 
-  // Test: SyncRewards with large pool
-  await test(`[${tokenProgramLabel}] Stress: SyncRewards with large pool`, async () => {
+  // =========================================================================
+  // Stake return data tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] Stake: return data reports the new amount and weight`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
-
-    const whale = Keypair.generate();
-    await airdropAndConfirm(connection, whale.publicKey, 3 * LAMPORTS_PER_SOL);
-
-    const whaleToken = await ctx.createUserTokenAccount(whale.publicKey);
-    const largeAmount = BigInt('1000000000000000');
-    await ctx.mintTokens(whaleToken, largeAmount);
-    await ctx.stake(whale, whaleToken, largeAmount);
+    await ctx.initializePool(BigInt(60));
 
-    // Wait for weight
-    await new Promise(r => setTimeout(r, 1000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
 
-    // Send SOL directly to pool (5 SOL, simulating pump.fun fees)
-    await ctx.sendSolToPool(BigInt(5 * LAMPORTS_PER_SOL));
+    // New stake: maturation starts now, so (almost) no weight yet
+    const first = await ctx.simulateStake(user, userToken, BigInt(1_000_000_000));
+    if (first.amount !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected amount 1000000000, got ${first.amount}`);
+    }
+    const maxFirst = BigInt(1_000_000_000) * BigInt(10) ** BigInt(18);
+    if (first.weight > maxFirst / 10n) {
+      throw new Error(`Fresh stake should have little weight, got ${first.weight}`);
+    }
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const startTime = Date.now();
-    await ctx.syncRewards();
-    const syncTime = Date.now() - startTime;
-    console.log(`    SyncRewards with 5 SOL on 10^15 token pool: ${syncTime}ms`);
+    // Top-up keeps the original start: weight reflects the elapsed time on the new total
+    await new Promise(r => setTimeout(r, 3000));
+    const second = await ctx.simulateStake(user, userToken, BigInt(1_000_000_000));
+    if (second.amount !== BigInt(2_000_000_000)) {
+      throw new Error(`Expected amount 2000000000, got ${second.amount}`);
+    }
+    const maxSecond = BigInt(2_000_000_000) * BigInt(10) ** BigInt(18);
+    if (second.weight === 0n || second.weight >= maxSecond) {
+      throw new Error(`Expected a partial weight on the topped-up stake, got ${second.weight}`);
+    }
+    if (second.weight <= first.weight) {
+      throw new Error('Weight should grow with maturity and amount');
+    }
   });
 
-  // Test: Many sequential operations (verify no state bloat)
-  await test(`[${tokenProgramLabel}] Stress: Sequential operations (no state bloat)`, async () => {
+  // =========================================================================
+  // Distribution audit log tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] Audit log: deposits and syncs append records`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // Minimum tau
+    await ctx.initializePool(BigInt(2592000), true);
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 5 * LAMPORTS_PER_SOL);
+    let page = await ctx.readAuditLogPage(0);
+    if (page.page !== 0 || page.count !== 0) {
+      throw new Error(`Expected an empty page 0, got page ${page.page} with ${page.count} records`);
+    }
 
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt('10000000000000')); // 10^13
-
-    // Initial stake that stays throughout to keep pool active
-    const baseStake = BigInt('1000000000000'); // 10^12
-    await ctx.stake(user, userToken, baseStake);
-
-    // Wait for weight to accumulate
-    await new Promise(r => setTimeout(r, 1000));
-
-    const iterations = 5; // Reduced for faster tests
-    const times: number[] = [];
-
-    for (let i = 0; i < iterations; i++) {
-      const additionalAmount = BigInt(100_000_000) * BigInt(i + 1);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-      const start = Date.now();
-      // Add more stake
-      await ctx.stake(user, userToken, additionalAmount);
-      // Deposit small reward
-      await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 100));
-      // Claim
-      await ctx.claimRewards(user);
-      // Remove only the additional stake (keep base stake)
-      await ctx.unstake(user, userToken, additionalAmount);
-      times.push(Date.now() - start);
+    // The page is required once the pool keeps a log
+    let failed = false;
+    try {
+      const ix = createDepositRewardsInstruction(ctx.poolPDA, ctx.payer.publicKey, BigInt(1_000_000));
+      await sendAndConfirmTransaction(connection, new Transaction().add(ix), [ctx.payer]);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x33')) {
+        throw new Error(`Expected AuditLogPageRequired (0x33), got: ${errMsg}`);
+      }
     }
+    if (!failed) throw new Error('Deposit without the audit log page should fail');
 
-    // Unstake the base amount at the end
-    await ctx.unstake(user, userToken, baseStake);
-
-    const avgTime = times.reduce((a, b) => a + b, 0) / times.length;
-    const maxTime = Math.max(...times);
-    const minTime = Math.min(...times);
-
-    console.log(`    ${iterations} cycles - avg: ${avgTime.toFixed(0)}ms, min: ${minTime}ms, max: ${maxTime}ms`);
+    const amounts = [BigInt(1_000_000), BigInt(2_000_000), BigInt(3_000_000)];
+    for (const amount of amounts) {
+      await ctx.depositRewards(amount);
+    }
+    const transferTx = new Transaction().add(
+      SystemProgram.transfer({
+        fromPubkey: ctx.payer.publicKey,
+        toPubkey: ctx.poolPDA,
+        lamports: 4_000_000,
+      }),
+    );
+    await sendAndConfirmTransaction(connection, transferTx, [ctx.payer]);
+    await ctx.syncRewards();
 
-    // Verify operations are O(1) - time should not grow significantly
-    if (maxTime > minTime * 3) {
-      console.log(`    Note: Variance may be due to network, not algorithm`);
+    page = await ctx.readAuditLogPage(0);
+    if (page.count !== 4) throw new Error(`Expected 4 records, got ${page.count}`);
+    const expected = [...amounts, BigInt(4_000_000)];
+    const wad = BigInt(10) ** BigInt(18);
+    let lastTimestamp = 0n;
+    for (let i = 0; i < 4; i++) {
+      const record = page.records[i];
+      if (record.amount !== expected[i]) {
+        throw new Error(`Record ${i}: expected amount ${expected[i]}, got ${record.amount}`);
+      }
+      if (record.totalStaked !== BigInt(1_000_000_000)) {
+        throw new Error(`Record ${i}: expected total_staked 1000000000, got ${record.totalStaked}`);
+      }
+      // reward_per_share = amount * WAD / total_staked
+      const rps = (expected[i] * wad) / BigInt(1_000_000_000);
+      if (record.rewardPerShare !== rps) {
+        throw new Error(`Record ${i}: expected reward_per_share ${rps}, got ${record.rewardPerShare}`);
+      }
+      if (record.timestamp < lastTimestamp) {
+        throw new Error(`Record ${i}: timestamps should not go backwards`);
+      }
+      lastTimestamp = record.timestamp;
     }
   });
 
-  // Test: Compute units estimation via simulation
-  await test(`[${tokenProgramLabel}] Stress: Compute units check`, async () => {
+  await test(`[${tokenProgramLabel}] Audit log: a full page rolls over to the next`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(100));
+    await ctx.initializePool(BigInt(2592000), true);
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt('1000000000000000'));
-    await ctx.stake(user, userToken, BigInt('1000000000000000'));
-
-    await new Promise(r => setTimeout(r, 500));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
-
-    // Create a transaction to simulate
-    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
-    const claimIx = createClaimRewardsInstruction(ctx.poolPDA, userStakePDA, user.publicKey);
-    const tx = new Transaction().add(claimIx);
-    tx.feePayer = ctx.payer.publicKey;
-    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    tx.sign(ctx.payer, user);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const simulation = await connection.simulateTransaction(tx);
-    if (simulation.value.err) {
-      throw new Error(`Simulation failed: ${JSON.stringify(simulation.value.err)}`);
+    // 32 records fill page 0; the pool then points at page 1
+    for (let i = 1; i <= 32; i++) {
+      await ctx.depositRewards(BigInt(i * 1000));
     }
-    const unitsUsed = simulation.value.unitsConsumed || 0;
-
-    console.log(`    Claim instruction compute units: ${unitsUsed}`);
-
-    // Solana limit is 200,000 CU per instruction
-    if (unitsUsed > 200000) {
-      throw new Error(`Compute units (${unitsUsed}) exceed instruction limit (200,000)`);
+    const page0 = await ctx.readAuditLogPage(0);
+    if (page0.count !== 32) throw new Error(`Expected a full page 0, got ${page0.count} records`);
+    if (page0.records[31].amount !== BigInt(32_000)) {
+      throw new Error(`Expected last record of 32000, got ${page0.records[31].amount}`);
+    }
+    const current = await ctx.currentAuditLogPage();
+    if (!current || !current.equals(deriveAuditLogPagePDA(ctx.poolPDA, 1)[0])) {
+      throw new Error('Pool should move on to page 1 once page 0 is full');
+    }
+    if (await connection.getAccountInfo(current)) {
+      throw new Error('Page 1 should only be created by the next distribution');
     }
 
-    // Our target is under 100,000 CU for efficiency
-    if (unitsUsed > 100000) {
-      console.log(`    WARNING: High compute usage, consider optimization`);
+    // The 33rd distribution creates page 1
+    await ctx.depositRewards(BigInt(33_000));
+    const page1 = await ctx.readAuditLogPage(1);
+    if (page1.page !== 1 || page1.count !== 1 || page1.records[0].amount !== BigInt(33_000)) {
+      throw new Error(`Expected page 1 with the 33000 record, got ${JSON.stringify(page1, (_, v) => typeof v === 'bigint' ? v.toString() : v)}`);
+    }
+    if ((await ctx.readAuditLogPage(0)).count !== 32) {
+      throw new Error('Page 0 should be left untouched');
     }
   });
 
-  // ============================================================
-  // Lifecycle Test: Multi-phase staking with full reconciliation
-  // ============================================================
+  // =========================================================================
+  // SetUnstakeDestinationPolicy tests
+  // =========================================================================
 
-  await test(`[${tokenProgramLabel}] Lifecycle: Multi-phase staking with full reconciliation`, async () => {
+  await test(`[${tokenProgramLabel}] SetUnstakeDestinationPolicy: OwnerAtaOnly rejects a custom destination`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMint(9); // 9 decimals
-    await ctx.initializePool(BigInt(60)); // tau = 60 seconds (minimum)
-
-    const BILLION = BigInt(1_000_000_000); // 1 token with 9 decimals
-
-    // --- Setup 4 stakers ---
-    const alice = Keypair.generate();
-    const bob = Keypair.generate();
-    const carol = Keypair.generate();
-    const dave = Keypair.generate();
-
-    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, bob.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, carol.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, dave.publicKey, 2 * LAMPORTS_PER_SOL);
-
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
-    const carolToken = await ctx.createUserTokenAccount(carol.publicKey);
-    const daveToken = await ctx.createUserTokenAccount(dave.publicKey);
-
-    // Mint tokens: Alice=10B, Bob=5B, Carol=8B, Dave=3B
-    const aliceAmount = BigInt(10) * BILLION;
-    const bobAmount = BigInt(5) * BILLION;
-    const carolAmount = BigInt(8) * BILLION;
-    const daveAmount = BigInt(3) * BILLION;
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(2));
 
-    await ctx.mintTokens(aliceToken, aliceAmount);
-    await ctx.mintTokens(bobToken, bobAmount);
-    await ctx.mintTokens(carolToken, carolAmount);
-    await ctx.mintTokens(daveToken, daveAmount);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userAta = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userAta, BigInt(2_000_000_000));
+    await ctx.stake(user, userAta, BigInt(2_000_000_000));
+    const customDestination = await createAccount(
+      connection, ctx.payer, ctx.mint, user.publicKey, Keypair.generate(), undefined, tokenProgramId,
+    );
 
-    // Track total SOL rewards deposited and received per user
-    let totalDeposited = BigInt(0);
-    const rewards: Record<string, bigint> = { alice: BigInt(0), bob: BigInt(0), carol: BigInt(0), dave: BigInt(0) };
+    // Only the authority sets the policy
+    let failed = false;
+    try {
+      await ctx.setUnstakeDestinationPolicy(user, UnstakeDestinationPolicy.OwnerAtaOnly);
+    } catch (e) {
+      failed = true;
+    }
+    if (!failed) throw new Error('Non-authority should not set the destination policy');
 
-    // Helper: measure SOL reward from an operation
-    async function measureReward(name: string, user: Keypair, op: () => Promise<any>): Promise<bigint> {
-      const before = BigInt(await ctx.getBalance(user.publicKey));
-      await op();
-      const after = BigInt(await ctx.getBalance(user.publicKey));
-      // Payer covers tx fee, so balance diff = pure reward
-      const reward = after - before;
-      if (reward > BigInt(0)) {
-        rewards[name] += reward;
+    // OwnerAtaOnly: the custom account is refused, the ATA is paid
+    await ctx.setUnstakeDestinationPolicy(ctx.payer, UnstakeDestinationPolicy.OwnerAtaOnly);
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    failed = false;
+    try {
+      await ctx.completeUnstake(user, customDestination);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x34')) {
+        throw new Error(`Expected DestinationNotAllowed (0x34), got: ${errMsg}`);
       }
-      return reward;
+    }
+    if (!failed) throw new Error('Custom destination should be rejected under OwnerAtaOnly');
+    await ctx.completeUnstake(user, userAta);
+    if (await ctx.getTokenBalance(userAta) !== BigInt(1_000_000_000)) {
+      throw new Error('Expected the unstaked tokens in the ATA');
     }
 
-    // ========== PHASE 1 (t=0): Initial stakes + deposit ==========
-    console.log('    Phase 1: Initial stakes + deposit 1 SOL');
-    await ctx.stake(alice, aliceToken, aliceAmount); // Alice stakes 10B
-    await ctx.stake(bob, bobToken, BigInt(3) * BILLION); // Bob stakes 3B
-    await ctx.stake(carol, carolToken, carolAmount); // Carol stakes 8B
+    // AnyOwnerAccount: the custom account is accepted again
+    await ctx.setUnstakeDestinationPolicy(ctx.payer, UnstakeDestinationPolicy.AnyOwnerAccount);
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.completeUnstake(user, customDestination);
+    const balance = await ctx.getTokenBalance(customDestination);
+    if (balance !== BigInt(1_000_000_000)) {
+      throw new Error(`Expected 1000000000 tokens in the custom account, got ${balance}`);
+    }
+  });
 
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL)); // Deposit 1 SOL
-    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+  // =========================================================================
+  // QueryTimeToUnlock tests
+  // =========================================================================
 
-    // ========== PHASE 2 (t+6s): Send SOL + sync + Alice claims ==========
-    console.log('    Phase 2: Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+  await test(`[${tokenProgramLabel}] QueryTimeToUnlock: lock and cooldown across request states`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(4), BigInt(100));
 
-    await ctx.syncPool(); // Rebase before weighted operations
-    await ctx.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 2)); // 0.5 SOL direct
-    await ctx.syncRewards();
-    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const aliceReward1 = await measureReward('alice', alice, () => ctx.claimRewards(alice));
-    console.log(`    Alice claim #1: ${aliceReward1} lamports`);
+    // Pre-lock: remaining lock plus the full cooldown
+    const preLock = await ctx.queryTimeToUnlock(user.publicKey);
+    if (preLock <= 100n || preLock > 104n) {
+      throw new Error(`Expected 100 < t <= 104 before the lock ends, got ${preLock}`);
+    }
 
-    // ========== PHASE 3 (t+12s): Dave stakes, Bob adds stake (auto-claim), deposit, Carol partial unstake ==========
-    console.log('    Phase 3: Waiting 6s...');
+    // Post-lock, no request: exactly the full cooldown
     await new Promise(r => setTimeout(r, 6000));
+    const postLock = await ctx.queryTimeToUnlock(user.publicKey);
+    if (postLock !== 100n) {
+      throw new Error(`Expected the full 100s cooldown after the lock, got ${postLock}`);
+    }
 
-    await ctx.syncPool(); // Rebase before weighted operations
-    await ctx.stake(dave, daveToken, daveAmount); // Dave stakes 3B
-
-    // Bob adds 2B more (no auto-claim — pending stays the same)
-    await ctx.stake(bob, bobToken, BigInt(2) * BILLION);
-    console.log(`    Bob added 2B more tokens`);
-
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL)); // Deposit 1 SOL
-    totalDeposited += BigInt(LAMPORTS_PER_SOL);
-
-    // Carol partial unstake 3B (claims rewards too)
-    const carolReward1 = await measureReward('carol', carol, () =>
-      ctx.unstake(carol, carolToken, BigInt(3) * BILLION)
-    );
-    console.log(`    Carol partial unstake reward: ${carolReward1} lamports`);
+    // Pending request: the cooldown counts down
+    await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    const pending = await ctx.queryTimeToUnlock(user.publicKey);
+    if (pending >= 100n || pending < 90n) {
+      throw new Error(`Expected 90 <= t < 100 with a pending request, got ${pending}`);
+    }
+  });
 
-    // ========== PHASE 4 (t+18s): Deposit + Dave claims + Bob claims ==========
-    console.log('    Phase 4: Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+  // =========================================================================
+  // StakeParams tests
+  // =========================================================================
 
-    await ctx.syncPool(); // Rebase before weighted operations
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2)); // Deposit 0.5 SOL
-    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+  await test(`[${tokenProgramLabel}] StakeParams: referrer, lock commitment and KeepLock top-up`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
 
-    const daveReward1 = await measureReward('dave', dave, () => ctx.claimRewards(dave));
-    console.log(`    Dave claim: ${daveReward1} lamports`);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(3_000_000_000));
 
-    const bobReward2 = await measureReward('bob', bob, () => ctx.claimRewards(bob));
-    console.log(`    Bob claim: ${bobReward2} lamports`);
+    // Self-referral is rejected
+    let failed = false;
+    try {
+      await ctx.stake(user, userToken, BigInt(1_000_000_000), { referrer: user.publicKey });
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x35')) {
+        throw new Error(`Expected InvalidReferrer (0x35), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Self-referral should fail');
+
+    // Full params on a new stake: referrer and commitment are recorded
+    const referrer = Keypair.generate().publicKey;
+    await ctx.stake(user, userToken, BigInt(1_000_000_000), {
+      referrer,
+      lockCommitSeconds: BigInt(3600),
+      topUpMode: TopUpMode.KeepLock,
+    });
+    let state = await ctx.readUserStakeState(user.publicKey);
+    if (!state.referrer || !state.referrer.equals(referrer)) {
+      throw new Error(`Expected referrer ${referrer.toBase58()}, got ${state.referrer?.toBase58()}`);
+    }
+    if (state.lockCommitSeconds !== BigInt(3600)) {
+      throw new Error(`Expected a 3600s commitment, got ${state.lockCommitSeconds}`);
+    }
 
-    // ========== PHASE 5 (t+24s): Dave full unstake + deposit ==========
-    console.log('    Phase 5: Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+    // The commitment locks the stake although the pool has no lock
+    failed = false;
+    try {
+      await ctx.requestUnstake(user, BigInt(1_000_000_000));
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x16')) {
+        throw new Error(`Expected StakeLocked (0x16), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Committed stake should be locked');
+    const timeToUnlock = await ctx.queryTimeToUnlock(user.publicKey);
+    if (timeToUnlock > 3600n || timeToUnlock < 3590n) {
+      throw new Error(`Expected about 3600s to unlock, got ${timeToUnlock}`);
+    }
 
-    await ctx.syncPool(); // Rebase before weighted operations
-    const daveReward2 = await measureReward('dave', dave, () =>
-      ctx.unstake(dave, daveToken, daveAmount)
-    );
-    console.log(`    Dave full unstake reward: ${daveReward2} lamports`);
+    // KeepLock top-up: the lock keeps running from the first stake
+    const lockStart = state.lastStakeTime;
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000), { topUpMode: TopUpMode.KeepLock });
+    state = await ctx.readUserStakeState(user.publicKey);
+    if (state.lastStakeTime !== lockStart) {
+      throw new Error(`KeepLock top-up should not restart the lock (${lockStart} -> ${state.lastStakeTime})`);
+    }
 
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2)); // Deposit 0.5 SOL
-    totalDeposited += BigInt(LAMPORTS_PER_SOL / 2);
+    // A shorter commitment never shortens the lock; a default top-up locks
+    // its own tokens and leaves the running lock alone
+    await ctx.stake(user, userToken, BigInt(1_000_000_000), { lockCommitSeconds: BigInt(60) });
+    state = await ctx.readUserStakeState(user.publicKey);
+    if (state.lockCommitSeconds !== BigInt(3600)) {
+      throw new Error(`Commitment should stay at 3600s, got ${state.lockCommitSeconds}`);
+    }
+    if (state.lastStakeTime !== lockStart) {
+      throw new Error(`Default top-up should not restart the lock (${lockStart} -> ${state.lastStakeTime})`);
+    }
+    if (state.amount !== BigInt(3_000_000_000)) {
+      throw new Error(`Expected 3000000000 staked, got ${state.amount}`);
+    }
+  });
 
-    // ========== PHASE 6 (t+30s): Everyone exits ==========
-    console.log('    Phase 6: Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+  // =========================================================================
+  // QueryEffectiveSettings tests
+  // =========================================================================
 
-    await ctx.syncPool(); // Rebase before weighted operations
+  await test(`[${tokenProgramLabel}] QueryEffectiveSettings: stamped and committed terms, not the pool's`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(100), BigInt(50), { perStakeTerms: true });
 
-    // Alice: claim + unstake
-    const aliceReward2 = await measureReward('alice', alice, () => ctx.claimRewards(alice));
-    console.log(`    Alice claim #2: ${aliceReward2} lamports`);
-    const aliceReward3 = await measureReward('alice', alice, () =>
-      ctx.unstake(alice, aliceToken, aliceAmount)
-    );
-    console.log(`    Alice unstake reward: ${aliceReward3} lamports`);
+    const stamped = Keypair.generate();
+    const committed = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [stamped, committed]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    await ctx.stake(stamped, tokens[0], BigInt(1_000_000_000));
+    await ctx.stake(committed, tokens[1], BigInt(1_000_000_000), { lockCommitSeconds: BigInt(1000) });
 
-    // Bob: full unstake (5B = 3B + 2B)
-    const bobReward3 = await measureReward('bob', bob, () =>
-      ctx.unstake(bob, bobToken, BigInt(5) * BILLION)
-    );
-    console.log(`    Bob full unstake reward: ${bobReward3} lamports`);
+    // The pool loosens its defaults afterwards
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(0), BigInt(0));
 
-    // Carol: claim + unstake remaining 5B
-    const carolReward2 = await measureReward('carol', carol, () => ctx.claimRewards(carol));
-    console.log(`    Carol claim: ${carolReward2} lamports`);
-    const carolReward3 = await measureReward('carol', carol, () =>
-      ctx.unstake(carol, carolToken, BigInt(5) * BILLION)
-    );
-    console.log(`    Carol unstake reward: ${carolReward3} lamports`);
+    const first = await ctx.queryEffectiveSettings(stamped.publicKey);
+    if (first.lockDurationSeconds !== 100n || first.unstakeCooldownSeconds !== 50n) {
+      throw new Error(
+        `Expected the stamped 100s/50s, got ${first.lockDurationSeconds}s/${first.unstakeCooldownSeconds}s`,
+      );
+    }
+    if (first.flags !== TERMS_FLAG_STAMPED) {
+      throw new Error(`Expected only the stamped flag, got ${first.flags}`);
+    }
+    const state = await ctx.readUserStakeState(stamped.publicKey);
+    if (first.lastStakeTime !== state.lastStakeTime) {
+      throw new Error(`Expected the lock to run from ${state.lastStakeTime}, got ${first.lastStakeTime}`);
+    }
 
-    // ========== PHASE 7: Reconciliation ==========
-    console.log('    --- Reconciliation ---');
+    const second = await ctx.queryEffectiveSettings(committed.publicKey);
+    if (second.lockDurationSeconds !== 1000n || second.unstakeCooldownSeconds !== 50n) {
+      throw new Error(
+        `Expected the committed 1000s/50s, got ${second.lockDurationSeconds}s/${second.unstakeCooldownSeconds}s`,
+      );
+    }
+    if (second.flags !== (TERMS_FLAG_STAMPED | TERMS_FLAG_LOCK_COMMITTED)) {
+      throw new Error(`Expected the stamped and committed flags, got ${second.flags}`);
+    }
+    if ((second.flags & TERMS_FLAG_GRANDFATHERED) !== 0) {
+      throw new Error('Stake was never grandfathered');
+    }
+  });
 
-    // 1. Token conservation: all tokens returned, vault empty
-    const aliceFinal = await ctx.getTokenBalance(aliceToken);
-    const bobFinal = await ctx.getTokenBalance(bobToken);
-    const carolFinal = await ctx.getTokenBalance(carolToken);
-    const daveFinal = await ctx.getTokenBalance(daveToken);
-    const vaultFinal = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+  // =========================================================================
+  // min_distributable_per_event tests
+  // =========================================================================
 
-    if (aliceFinal !== aliceAmount) throw new Error(`Alice tokens: expected ${aliceAmount}, got ${aliceFinal}`);
-    if (bobFinal !== bobAmount) throw new Error(`Bob tokens: expected ${bobAmount}, got ${bobFinal}`);
-    if (carolFinal !== carolAmount) throw new Error(`Carol tokens: expected ${carolAmount}, got ${carolFinal}`);
-    if (daveFinal !== daveAmount) throw new Error(`Dave tokens: expected ${daveAmount}, got ${daveFinal}`);
-    if (vaultFinal !== BigInt(0)) throw new Error(`Vault should be empty, got ${vaultFinal}`);
-    console.log('    Token conservation: OK');
+  await test(`[${tokenProgramLabel}] min_distributable_per_event: small deposits accumulate, then distribute at once`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { minDistributablePerEvent: BigInt(100_000_000) });
 
-    // 2. SOL conservation: totalReceived + poolRemaining ~= totalDeposited
-    const totalReceived = rewards.alice + rewards.bob + rewards.carol + rewards.dave;
-    const poolRemaining = BigInt(await ctx.getBalance(ctx.poolPDA));
-    // Pool has rent-exempt balance too, so we check: received + (poolRemaining - rent) ~= deposited
-    // More practically: received <= deposited (no SOL created from nothing)
-    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
-    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
-    const poolRewardsRemaining = poolRemaining - rentExempt;
-    const accounted = totalReceived + poolRewardsRemaining;
-    const tolerance = BigInt(100_000); // 100K lamports for WAD rounding
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    console.log(`    Total deposited:  ${totalDeposited} lamports (${Number(totalDeposited) / LAMPORTS_PER_SOL} SOL)`);
-    console.log(`    Total received:   ${totalReceived} lamports (${Number(totalReceived) / LAMPORTS_PER_SOL} SOL)`);
-    console.log(`    Pool remaining:   ${poolRewardsRemaining} lamports`);
-    console.log(`    Accounted:        ${accounted} lamports`);
+    const before = await ctx.readPoolState();
 
-    const diff = accounted > totalDeposited ? accounted - totalDeposited : totalDeposited - accounted;
-    if (diff > tolerance) {
-      throw new Error(`SOL conservation violated: deposited=${totalDeposited}, accounted=${accounted}, diff=${diff} (tolerance=${tolerance})`);
+    // Three sub-threshold deposits (90M total) leave the accumulator alone
+    for (let i = 0; i < 3; i++) {
+      await ctx.depositRewards(BigInt(30_000_000));
     }
-    console.log(`    SOL conservation: OK (diff=${diff} lamports, tolerance=${tolerance})`);
-
-    // 3. Sanity: every user received some rewards
-    for (const [name, amount] of Object.entries(rewards)) {
-      if (amount <= BigInt(0)) {
-        throw new Error(`${name} received zero rewards`);
-      }
-      console.log(`    ${name} total rewards: ${amount} lamports`);
+    await ctx.syncRewards();
+    const pending = await ctx.readPoolState();
+    if (pending.accRewardPerWeightedShare !== before.accRewardPerWeightedShare) {
+      throw new Error('Sub-threshold deposits should not move the accumulator');
+    }
+    if (pending.lastSyncedLamports !== before.lastSyncedLamports) {
+      throw new Error('Sub-threshold deposits should stay pending above last_synced_lamports');
     }
 
-    // 4. Fairness: Alice (largest + longest) > Dave (smallest + shortest)
-    if (rewards.alice <= rewards.dave) {
-      throw new Error(`Fairness check failed: Alice (${rewards.alice}) should earn more than Dave (${rewards.dave})`);
+    // The fourth pushes the pending total over the minimum: one jump for all 120M
+    await ctx.depositRewards(BigInt(30_000_000));
+    const after = await ctx.readPoolState();
+    if (after.lastSyncedLamports - before.lastSyncedLamports !== BigInt(120_000_000)) {
+      throw new Error(
+        `Expected 120000000 lamports distributed, got ${after.lastSyncedLamports - before.lastSyncedLamports}`,
+      );
+    }
+    if (after.accRewardPerWeightedShare <= before.accRewardPerWeightedShare) {
+      throw new Error('Accumulated deposits should be distributed once over the minimum');
     }
-    console.log(`    Fairness check: OK (Alice=${rewards.alice} > Dave=${rewards.dave})`);
   });
 
-  // ============================================
-  // SOL CONSERVATION & RECOVERY TESTS
-  // ============================================
-  // Verify no SOL is lost (dead/stranded) from additional stakes.
-
-  console.log('\n--- SOL Conservation & Recovery Tests ---\n');
+  // =========================================================================
+  // QueryAccumulatorSnapshot tests
+  // =========================================================================
 
-  // Test: Additional stakes don't create dead SOL (round 7 fix regression test)
-  await test(`[${tokenProgramLabel}] Conservation: additional stakes preserve all rewards`, async () => {
+  await test(`[${tokenProgramLabel}] QueryAccumulatorSnapshot: pending matches the returned inputs`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
+    await ctx.initializePool(BigInt(60));
 
-    const staker1 = Keypair.generate();
-    const staker2 = Keypair.generate();
-    await airdropAndConfirm(connection, staker1.publicKey, 3 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, staker2.publicKey, 3 * LAMPORTS_PER_SOL);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.depositRewards(BigInt(500_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.claimRewards(user);
+    await ctx.depositRewards(BigInt(250_000_000));
 
-    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
-    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
-    await ctx.mintTokens(token1, BigInt(2_000_000_000));
-    await ctx.mintTokens(token2, BigInt(2_000_000_000));
+    const snap = await ctx.queryAccumulatorSnapshot(user.publicKey);
+    const pool = await ctx.readPoolState();
+    const state = await ctx.readUserStakeState(user.publicKey);
+    if (snap.accRewardPerWeightedShare !== pool.accRewardPerWeightedShare) {
+      throw new Error(`Accumulator ${snap.accRewardPerWeightedShare} != pool ${pool.accRewardPerWeightedShare}`);
+    }
+    if (snap.amount !== state.amount || snap.claimedRewardsWad !== state.claimedRewardsWad) {
+      throw new Error('Snapshot amount/claimed do not match the stake account');
+    }
+    const WAD = 1_000_000_000_000_000_000n;
+    if (snap.weightedStake === 0n || snap.weightedStake > snap.amount * WAD) {
+      throw new Error(`Weighted stake ${snap.weightedStake} out of range`);
+    }
 
-    // Both stake 1B tokens
-    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
-    await ctx.stake(staker2, token2, BigInt(1_000_000_000));
+    // pending = (unscale(weighted * (acc - debt / amount)) - claimed) / WAD
+    const snapshotRps = (snap.rewardDebt * WAD) / (snap.amount * WAD);
+    const deltaRps = snap.accRewardPerWeightedShare > snapshotRps ? snap.accRewardPerWeightedShare - snapshotRps : 0n;
+    const entitlement = ((snap.weightedStake * deltaRps) / WAD) << BigInt(snap.accScaleShift);
+    const expected = entitlement > snap.claimedRewardsWad ? (entitlement - snap.claimedRewardsWad) / WAD : 0n;
+    if (snap.pending !== expected) {
+      throw new Error(`Pending ${snap.pending} != ${expected} recomputed from the snapshot`);
+    }
+    if (snap.pending === 0n) {
+      throw new Error('Expected rewards pending after the second deposit');
+    }
+  });
 
-    // Wait for partial maturity (~15% weight at 10s/60s tau)
-    console.log('    Waiting 10s for partial weight accumulation...');
-    await new Promise(r => setTimeout(r, 10000));
+  // =========================================================================
+  // ForceUnstakeQuarantined tests
+  // =========================================================================
 
-    // Deposit 1 SOL
-    const deposit1 = BigInt(LAMPORTS_PER_SOL);
-    await ctx.depositRewards(deposit1);
+  // As with QuarantinePool, a live mint can't gain a forbidden extension
+  // here, so a pool can't be quarantined; this checks the emergency path
+  // stays shut on a healthy pool, lock and cooldown included.
+  await test(`[${tokenProgramLabel}] ForceUnstakeQuarantined: rejected on a healthy pool`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(3600), BigInt(3600));
 
-    // Staker1 does additional stake (this was the bug vector)
-    // The auto-claim should pay actual_pending, and stranded should be returned to pool
-    let totalClaimed = BigInt(0);
-    let bal0 = BigInt(await ctx.getBalance(staker1.publicKey));
-    await ctx.stake(staker1, token1, BigInt(500_000_000));
-    let bal0After = BigInt(await ctx.getBalance(staker1.publicKey));
-    // Additional stake auto-claims rewards — track that SOL
-    totalClaimed += (bal0After - bal0);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait for more weight accumulation
-    console.log('    Waiting 10s for more weight...');
-    await new Promise(r => setTimeout(r, 10000));
+    let failed = false;
+    try {
+      await ctx.forceUnstakeQuarantined(user, userToken);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // PoolNotQuarantined = error code 54 = 0x36
+      if (!errMsg.includes('0x36')) {
+        throw new Error(`Expected PoolNotQuarantined (0x36), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Forced exit should require a quarantined pool');
 
-    // Deposit 1 more SOL
-    const deposit2 = BigInt(LAMPORTS_PER_SOL);
-    await ctx.depositRewards(deposit2);
+    const state = await ctx.readUserStakeState(user.publicKey);
+    if (state.amount !== BigInt(1_000_000_000)) {
+      throw new Error(`Stake should be untouched, got ${state.amount}`);
+    }
+  });
 
-    const totalDeposited = deposit1 + deposit2;
+  // =========================================================================
+  // LockPoolSettings tests
+  // =========================================================================
 
-    // Both claim and fully unstake
+  await test(`[${tokenProgramLabel}] LockPoolSettings: settings frozen, other authority actions still work`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(60), null);
 
-    // Staker1: claim + unstake
-    let bal = BigInt(await ctx.getBalance(staker1.publicKey));
-    await ctx.claimRewards(staker1);
-    let balAfter = BigInt(await ctx.getBalance(staker1.publicKey));
-    totalClaimed += (balAfter - bal);
+    await ctx.lockPoolSettings(ctx.payer);
 
-    bal = BigInt(await ctx.getBalance(staker1.publicKey));
-    await ctx.unstake(staker1, token1, BigInt(1_500_000_000));
-    balAfter = BigInt(await ctx.getBalance(staker1.publicKey));
-    totalClaimed += (balAfter - bal); // reward portion from unstake
+    // SettingsLocked = error code 55 = 0x37
+    const expectLocked = async (label: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes('0x37')) {
+          throw new Error(`${label}: expected SettingsLocked (0x37), got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail once settings are locked`);
+    };
+    await expectLocked('UpdatePoolSettings', () => ctx.updatePoolSettings(ctx.payer, null, BigInt(0), null));
+    await expectLocked('SetUnstakeDestinationPolicy', () =>
+      ctx.setUnstakeDestinationPolicy(ctx.payer, UnstakeDestinationPolicy.OwnerAtaOnly));
+
+    // Locking again is a no-op
+    await ctx.lockPoolSettings(ctx.payer);
+
+    // The authority can still reconcile rewards and hand over control
+    const transferTx = new Transaction().add(
+      SystemProgram.transfer({
+        fromPubkey: ctx.payer.publicKey,
+        toPubkey: ctx.poolPDA,
+        lamports: LAMPORTS_PER_SOL / 10,
+      }),
+    );
+    await sendAndConfirmTransaction(connection, transferTx, [ctx.payer]);
+    const balance = BigInt(await ctx.getBalance(ctx.poolPDA));
+    await ctx.forceSyncWithBalance(ctx.payer, balance);
 
-    // Staker2: claim + unstake
-    bal = BigInt(await ctx.getBalance(staker2.publicKey));
-    await ctx.claimRewards(staker2);
-    balAfter = BigInt(await ctx.getBalance(staker2.publicKey));
-    totalClaimed += (balAfter - bal);
+    const newAuthority = Keypair.generate();
+    await airdropAndConfirm(connection, newAuthority.publicKey, LAMPORTS_PER_SOL);
+    await ctx.transferAuthority(ctx.payer, newAuthority.publicKey);
 
-    bal = BigInt(await ctx.getBalance(staker2.publicKey));
-    await ctx.unstake(staker2, token2, BigInt(1_000_000_000));
-    balAfter = BigInt(await ctx.getBalance(staker2.publicKey));
-    totalClaimed += (balAfter - bal);
+    // The lock survives the handover
+    await expectLocked('UpdatePoolSettings (new authority)', () =>
+      ctx.updatePoolSettings(newAuthority, null, BigInt(0), null));
+  });
 
-    // Check pool remaining
-    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
-    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
-    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
-    const poolRewardsRemaining = poolBalance - rentExempt;
+  // =========================================================================
+  // QueryRewardRate tests
+  // =========================================================================
 
-    const accounted = totalClaimed + poolRewardsRemaining;
-    const diff = accounted > totalDeposited
-      ? accounted - totalDeposited
-      : totalDeposited - accounted;
+  await test(`[${tokenProgramLabel}] QueryRewardRate: approximates the average of timed deposits`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    const start = Date.now();
 
-    console.log(`    Total deposited:    ${totalDeposited} lamports`);
-    console.log(`    Total claimed:      ${totalClaimed} lamports`);
-    console.log(`    Pool remaining:     ${poolRewardsRemaining} lamports`);
-    console.log(`    Accounted:          ${accounted} lamports`);
-    console.log(`    Diff:               ${diff} lamports`);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // The deadSOL from the old bug would show up as a large poolRewardsRemaining
-    // (SOL stuck in pool that nobody can claim). With the fix, diff should be tiny.
-    const tolerance = BigInt(100_000); // 100K lamports for WAD rounding
-    if (diff > tolerance) {
-      throw new Error(`SOL conservation violated: deposited=${totalDeposited}, accounted=${accounted}, diff=${diff}`);
+    for (let i = 0; i < 4; i++) {
+      await new Promise(r => setTimeout(r, 2000));
+      await ctx.depositRewards(BigInt(100_000_000));
     }
+    await new Promise(r => setTimeout(r, 2000));
 
-    // Extra check: pool remaining should not exceed deposits.
-    // At tau=60 with short waits (~15% weight), most rewards remain in pool as
-    // stranded/unclaimed — this is expected behavior, not a bug. These rewards
-    // are eventually redistributed to all stakers. We just verify the pool
-    // isn't holding MORE than deposited (which would indicate SOL creation).
-    if (poolRewardsRemaining > totalDeposited + BigInt(100_000)) {
-      throw new Error(`Pool has more than deposited: ${poolRewardsRemaining} > ${totalDeposited}`);
+    const { rate, windowSeconds, windowLamports } = await ctx.queryRewardRate();
+    if (windowLamports !== BigInt(400_000_000)) {
+      throw new Error(`Expected 400000000 lamports in the window, got ${windowLamports}`);
+    }
+    if (rate !== windowLamports / windowSeconds) {
+      throw new Error(`Rate ${rate} is not ${windowLamports} / ${windowSeconds}s`);
+    }
+
+    // The window runs from pool creation; allow for clock granularity
+    const elapsed = BigInt(Math.round((Date.now() - start) / 1000));
+    if (windowSeconds + 3n < elapsed || windowSeconds > elapsed + 3n) {
+      throw new Error(`Window of ${windowSeconds}s, expected about ${elapsed}s`);
+    }
+    const trueAverage = Number(windowLamports) / Number(elapsed);
+    if (Math.abs(Number(rate) - trueAverage) > trueAverage * 0.5) {
+      throw new Error(`Rate ${rate} too far from the true average ${trueAverage}`);
     }
-    console.log('    Conservation: OK');
   });
 
-  // Test: total_reward_debt is tracked correctly through stake/claim/unstake lifecycle
-  await test(`[${tokenProgramLabel}] Recovery: total_reward_debt tracking across lifecycle`, async () => {
+  // =========================================================================
+  // Display unit tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] SetDisplayUnit: label and display total don't affect distribution`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
+    await ctx.initializePool(BigInt(2592000));
 
-    // Verify total_reward_debt starts at 0
-    let state = await ctx.readPoolState();
-    if (state.totalRewardDebt !== BigInt(0)) {
-      throw new Error(`Expected initial totalRewardDebt=0, got ${state.totalRewardDebt}`);
+    // Too long for the 8-byte label
+    let failed = false;
+    try {
+      await ctx.setDisplayUnit(ctx.payer, 'USDCUSDTX', 6);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // InvalidDisplayUnit = error code 56 = 0x38
+      if (!errMsg.includes('0x38')) {
+        throw new Error(`Expected InvalidDisplayUnit (0x38), got: ${errMsg}`);
+      }
     }
+    if (!failed) throw new Error('A 9-byte label should be rejected');
 
-    const staker1 = Keypair.generate();
-    const staker2 = Keypair.generate();
-    await airdropAndConfirm(connection, staker1.publicKey, 3 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, staker2.publicKey, 3 * LAMPORTS_PER_SOL);
-
-    const token1 = await ctx.createUserTokenAccount(staker1.publicKey);
-    const token2 = await ctx.createUserTokenAccount(staker2.publicKey);
-    await ctx.mintTokens(token1, BigInt(2_000_000_000));
-    await ctx.mintTokens(token2, BigInt(1_000_000_000));
-
-    // Stake staker1 first, deposit rewards, then stake staker2 (so staker2 has non-zero reward_debt)
-    await ctx.stake(staker1, token1, BigInt(1_000_000_000));
-    state = await ctx.readPoolState();
-    const debtAfterStake1 = state.totalRewardDebt;
-    console.log(`    After staker1 stake: totalRewardDebt=${debtAfterStake1}`);
+    await ctx.setDisplayUnit(ctx.payer, 'USDC', 6);
 
-    // Deposit rewards so acc_rps > 0 before staker2 stakes
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
-    console.log('    Waiting 10s for weight accumulation...');
-    await new Promise(r => setTimeout(r, 10000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Sync so acc_rps reflects deposits
-    await ctx.syncRewards();
+    // Same lamports with and without a display value: same accumulator step
+    const before = await ctx.readPoolState();
+    await ctx.depositRewards(BigInt(100_000_000));
+    const plain = await ctx.readPoolState();
+    await ctx.depositRewards(BigInt(100_000_000), null, BigInt(15_000_000));
+    const priced = await ctx.readPoolState();
+    const plainStep = plain.accRewardPerWeightedShare - before.accRewardPerWeightedShare;
+    const pricedStep = priced.accRewardPerWeightedShare - plain.accRewardPerWeightedShare;
+    if (plainStep !== pricedStep) {
+      throw new Error(`Display value changed the distribution: ${plainStep} vs ${pricedStep}`);
+    }
 
-    await ctx.stake(staker2, token2, BigInt(1_000_000_000));
-    state = await ctx.readPoolState();
-    const debtAfterStake2 = state.totalRewardDebt;
-    console.log(`    After staker2 stake: totalRewardDebt=${debtAfterStake2}`);
+    await ctx.depositRewards(BigInt(100_000_000), BigInt(7), BigInt(16_000_000));
+    const unit = await ctx.queryDisplayUnit();
+    if (unit.label !== 'USDC' || unit.decimals !== 6) {
+      throw new Error(`Expected USDC/6, got ${unit.label}/${unit.decimals}`);
+    }
+    if (unit.totalDistributed !== BigInt(31_000_000)) {
+      throw new Error(`Expected 31000000 display units distributed, got ${unit.totalDistributed}`);
+    }
 
-    // total_reward_debt should increase (staker2 stakes with non-zero acc_rps → non-zero reward_debt)
-    if (debtAfterStake2 <= debtAfterStake1) {
-      throw new Error(`totalRewardDebt should increase after new stake with deposits: ${debtAfterStake1} -> ${debtAfterStake2}`);
+    // A retried (duplicate nonce) deposit isn't counted twice
+    await ctx.depositRewards(BigInt(100_000_000), BigInt(7), BigInt(16_000_000));
+    if ((await ctx.queryDisplayUnit()).totalDistributed !== BigInt(31_000_000)) {
+      throw new Error('Duplicate deposit should not add to the display total');
     }
+  });
 
-    // Claim — total_reward_debt stays unchanged (claim uses claimed_rewards_wad, not reward_debt)
-    const debtBeforeClaim = (await ctx.readPoolState()).totalRewardDebt;
-    await ctx.claimRewards(staker1);
-    const debtAfterClaim = (await ctx.readPoolState()).totalRewardDebt;
-    console.log(`    After staker1 claim: totalRewardDebt=${debtBeforeClaim} -> ${debtAfterClaim}`);
+  // =========================================================================
+  // QueryIsCompletableBatch tests
+  // =========================================================================
 
-    if (debtAfterClaim !== debtBeforeClaim) {
-      throw new Error(`totalRewardDebt should not change after claim: ${debtBeforeClaim} -> ${debtAfterClaim}`);
+  await test(`[${tokenProgramLabel}] QueryIsCompletableBatch: flags only elapsed cooldowns`, async () => {
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+
+    // Pool 0: 2s cooldown (will elapse), pool 1: 1h cooldown, pool 2: no request
+    const cooldowns = [BigInt(2), BigInt(3600), BigInt(2)];
+    const ctxs: TestContext[] = [];
+    for (const cooldown of cooldowns) {
+      const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+      await ctx.setup();
+      await ctx.createMint(9);
+      await ctx.initializePool(BigInt(2592000));
+      await ctx.updatePoolSettings(ctx.payer, null, null, cooldown);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+      ctxs.push(ctx);
     }
+    await ctxs[0].requestUnstake(user, BigInt(1_000_000_000));
+    await ctxs[1].requestUnstake(user, BigInt(1_000_000_000));
 
-    // Additional stake — should update total_reward_debt (subtract old, add new)
-    const debtBeforeAddStake = (await ctx.readPoolState()).totalRewardDebt;
-    await ctx.stake(staker1, token1, BigInt(500_000_000));
-    const debtAfterAddStake = (await ctx.readPoolState()).totalRewardDebt;
-    console.log(`    After staker1 additional stake: totalRewardDebt=${debtBeforeAddStake} -> ${debtAfterAddStake}`);
+    const stakes = ctxs.map(ctx => ({
+      pool: ctx.poolPDA,
+      userStake: deriveUserStakePDA(ctx.poolPDA, user.publicKey)[0],
+    }));
 
-    // Full unstake — should subtract staker2's reward_debt
-    const debtBeforeUnstake = (await ctx.readPoolState()).totalRewardDebt;
-    await ctx.unstake(staker2, token2, BigInt(1_000_000_000));
-    const debtAfterUnstake = (await ctx.readPoolState()).totalRewardDebt;
-    console.log(`    After staker2 full unstake: totalRewardDebt=${debtBeforeUnstake} -> ${debtAfterUnstake}`);
+    await new Promise(r => setTimeout(r, 4000));
 
-    // After staker2 fully unstaked (reward_debt=0), total_reward_debt should decrease
-    if (debtAfterUnstake >= debtBeforeUnstake) {
-      throw new Error(`totalRewardDebt should decrease after full unstake: ${debtBeforeUnstake} -> ${debtAfterUnstake}`);
+    const mask = await queryIsCompletableBatch(connection, user, stakes);
+    if (mask !== 0b001) {
+      throw new Error(`Expected only pair 0 completable (0b001), got 0b${mask.toString(2)}`);
     }
 
-    // Cleanup: staker1 unstakes
-    await ctx.unstake(staker1, token1, BigInt(1_500_000_000));
+    // Order follows the pairs
+    const reversed = await queryIsCompletableBatch(connection, user, [...stakes].reverse());
+    if (reversed !== 0b100) {
+      throw new Error(`Expected only pair 2 completable (0b100), got 0b${reversed.toString(2)}`);
+    }
 
-    console.log('    total_reward_debt tracking: OK');
+    // More than 32 pairs is rejected
+    let failed = false;
+    try {
+      await queryIsCompletableBatch(connection, user, Array(33).fill(stakes[0]));
+    } catch (e) {
+      failed = true;
+    }
+    if (!failed) throw new Error('A 33-pair batch should be rejected');
   });
 
-  // Test: Multi-staker SOL accounting — sum of all claims never exceeds deposits
-  await test(`[${tokenProgramLabel}] Conservation: multi-staker claims never exceed deposits`, async () => {
+  // =========================================================================
+  // ReinitializeVault tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ReinitializeVault: allowed on an empty pool, blocked with stake`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau (minimum)
-
-    const stakers: Keypair[] = [];
-    const tokens: PublicKey[] = [];
-    const numStakers = 4;
+    await ctx.initializePool(BigInt(2592000));
 
-    for (let i = 0; i < numStakers; i++) {
-      const s = Keypair.generate();
-      await airdropAndConfirm(connection, s.publicKey, 3 * LAMPORTS_PER_SOL);
-      const t = await ctx.createUserTokenAccount(s.publicKey);
-      const amount = BigInt((i + 1) * 1_000_000_000); // 1B, 2B, 3B, 4B
-      await ctx.mintTokens(t, amount);
-      await ctx.stake(s, t, amount);
-      stakers.push(s);
-      tokens.push(t);
+    // Empty pool: a valid vault is left in place
+    const vaultBefore = await connection.getAccountInfo(ctx.tokenVaultPDA);
+    await ctx.reinitializeVault(ctx.payer);
+    const vaultAfter = await connection.getAccountInfo(ctx.tokenVaultPDA);
+    if (!vaultBefore || !vaultAfter || !vaultAfter.owner.equals(tokenProgramId)) {
+      throw new Error('Vault should still be a token account');
+    }
+    if (!vaultBefore.data.equals(vaultAfter.data)) {
+      throw new Error('A valid vault should not be modified');
+    }
+    // 40: token_vault (after discriminator + mint)
+    const poolInfo = await connection.getAccountInfo(ctx.poolPDA);
+    if (!new PublicKey(poolInfo!.data.subarray(40, 72)).equals(ctx.tokenVaultPDA)) {
+      throw new Error('Pool should point at the canonical vault');
     }
 
-    let totalDeposited = BigInt(0);
-
-    // Phase 1: deposit, wait, claim
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
-    totalDeposited += BigInt(LAMPORTS_PER_SOL);
-
-    console.log('    Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+    // Only the authority
+    const stranger = Keypair.generate();
+    await airdropAndConfirm(connection, stranger.publicKey, LAMPORTS_PER_SOL);
+    let failed = false;
+    try {
+      await ctx.reinitializeVault(stranger);
+    } catch (e) {
+      failed = true;
+    }
+    if (!failed) throw new Error('Non-authority should not reinitialize the vault');
 
-    // Staker 0 does additional stake (auto-claims rewards — track SOL)
-    await ctx.mintTokens(tokens[0], BigInt(500_000_000));
-    let autoClaimBal = BigInt(await ctx.getBalance(stakers[0].publicKey));
-    await ctx.stake(stakers[0], tokens[0], BigInt(500_000_000));
-    let autoClaimBalAfter = BigInt(await ctx.getBalance(stakers[0].publicKey));
-    let autoClaimedSOL = autoClaimBalAfter - autoClaimBal;
-    if (autoClaimedSOL < BigInt(0)) throw new Error(`Unexpected SOL loss on auto-claim: ${autoClaimedSOL}`);
+    // With tokens staked the vault can't be touched
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Phase 2: more deposits
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
-    totalDeposited += BigInt(LAMPORTS_PER_SOL);
+    failed = false;
+    try {
+      await ctx.reinitializeVault(ctx.payer);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // PoolHasStake = error code 57 = 0x39
+      if (!errMsg.includes('0x39')) {
+        throw new Error(`Expected PoolHasStake (0x39), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('ReinitializeVault should be blocked while tokens are staked');
+  });
 
-    console.log('    Waiting 6s...');
-    await new Promise(r => setTimeout(r, 6000));
+  // =========================================================================
+  // DistributeBonus tests
+  // =========================================================================
 
-    // Phase 3: everyone claims and unstakes
-    let totalClaimed = autoClaimedSOL;
-    for (let i = 0; i < numStakers; i++) {
-      // Claim
-      const bal = BigInt(await ctx.getBalance(stakers[i].publicKey));
-      try { await ctx.claimRewards(stakers[i]); } catch (e: any) { if (!e.message?.includes('0xb')) throw e; }
-      const balAfterClaim = BigInt(await ctx.getBalance(stakers[i].publicKey));
-      totalClaimed += (balAfterClaim - bal);
+  await test(`[${tokenProgramLabel}] DistributeBonus: credits exactly the listed stakers`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
 
-      // Unstake — get the staked amount
-      const stakeAmount = i === 0
-        ? BigInt(1_500_000_000) // 1B + 500M
-        : BigInt((i + 1) * 1_000_000_000);
-      const balBeforeUnstake = BigInt(await ctx.getBalance(stakers[i].publicKey));
-      await ctx.unstake(stakers[i], tokens[i], stakeAmount);
-      const balAfterUnstake = BigInt(await ctx.getBalance(stakers[i].publicKey));
-      // Count only the reward portion of unstake (unstake also claims rewards)
-      totalClaimed += (balAfterUnstake - balBeforeUnstake);
+    const users: Keypair[] = [];
+    for (let i = 0; i < 3; i++) {
+      const user = Keypair.generate();
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+      users.push(user);
     }
 
-    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
-    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
-    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
-    const poolRemaining = poolBalance - rentExempt;
-    const accounted = totalClaimed + poolRemaining;
-
-    console.log(`    Deposited:     ${totalDeposited}`);
-    console.log(`    Total claimed: ${totalClaimed}`);
-    console.log(`    Pool remaining: ${poolRemaining}`);
-    console.log(`    Accounted:     ${accounted}`);
+    const before = await ctx.readPoolState();
+    await ctx.distributeBonus(ctx.payer, [
+      { user: users[0].publicKey, amount: BigInt(30_000_000) },
+      { user: users[1].publicKey, amount: BigInt(5_000_000) },
+    ]);
 
-    // Claims must never exceed deposits (no SOL created from nothing)
-    if (totalClaimed > totalDeposited + BigInt(50_000)) {
-      throw new Error(`Over-distribution! Claimed ${totalClaimed} > deposited ${totalDeposited}`);
+    const expected = [30_000_000n, 5_000_000n, 0n];
+    for (let i = 0; i < 3; i++) {
+      const state = await ctx.readUserStakeState(users[i].publicKey);
+      if (state.claimedBalance !== expected[i]) {
+        throw new Error(`User ${i}: expected bonus ${expected[i]}, got ${state.claimedBalance}`);
+      }
     }
 
-    // Conservation check
-    const diff = accounted > totalDeposited
-      ? accounted - totalDeposited
-      : totalDeposited - accounted;
-    const tolerance = BigInt(100_000);
-    if (diff > tolerance) {
-      throw new Error(`SOL conservation violated: diff=${diff} > tolerance=${tolerance}`);
+    // The bonus lamports are reserved, not distributed through the accumulator
+    await ctx.syncRewards();
+    const after = await ctx.readPoolState();
+    if (after.accRewardPerWeightedShare !== before.accRewardPerWeightedShare) {
+      throw new Error('Bonus should not move the reward accumulator');
     }
+    await ctx.verifyVaultConsistency();
 
-    // At tau=60 with short waits, most rewards remain as stranded (expected).
-    // Just verify pool doesn't hold more than deposited.
-    if (poolRemaining > totalDeposited + BigInt(100_000)) {
-      throw new Error(`Pool holds more than deposited: ${poolRemaining} > ${totalDeposited}`);
+    const balanceBefore = await ctx.getBalance(users[1].publicKey);
+    await ctx.withdrawClaimed(users[1]);
+    const received = BigInt(await ctx.getBalance(users[1].publicKey) - balanceBefore);
+    if (received !== 5_000_000n) {
+      throw new Error(`Withdrew ${received} lamports, expected 5000000`);
     }
 
-    console.log(`    Conservation OK (diff=${diff} lamports)`);
+    // A stake from another pool is rejected
+    const other = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await other.setup();
+    await other.createMint(9);
+    await other.initializePool(BigInt(2592000));
+    const otherToken = await other.createUserTokenAccount(users[2].publicKey);
+    await other.mintTokens(otherToken, BigInt(1_000_000_000));
+    await other.stake(users[2], otherToken, BigInt(1_000_000_000));
+
+    let failed = false;
+    try {
+      const tx = new Transaction().add(createDistributeBonusInstruction(ctx.poolPDA, ctx.payer.publicKey, [
+        { userStake: deriveUserStakePDA(other.poolPDA, users[2].publicKey)[0], amount: BigInt(1_000_000) },
+      ]));
+      await sendAndConfirmTransaction(connection, tx, [ctx.payer]);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // InvalidPool = error code 8 = 0x8
+      if (!errMsg.includes('0x8')) {
+        throw new Error(`Expected InvalidPool (0x8), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Bonus to a stake of another pool should fail');
   });
 
-  // ==================== METADATA TESTS (Token 2022 only) ====================
-  if (tokenProgramId.equals(TOKEN_2022_PROGRAM_ID)) {
+  // =========================================================================
+  // QueryPendingUnstake tests
+  // =========================================================================
 
-  // Test: SetPoolMetadata creates metadata account
-  await test(`[${tokenProgramLabel}] SetPoolMetadata creates metadata account`, async () => {
+  await test(`[${tokenProgramLabel}] QueryPendingUnstake: timestamps and remaining seconds as the cooldown runs`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'Tibanne Thecat', 'ChiefPussy');
+    await ctx.createMint(9);
     await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(8));
 
-    await ctx.setPoolMetadata();
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const metadata = await ctx.readMetadata();
-    if (metadata.name !== 'Tibanne Thecat Staking Pool') {
-      throw new Error(`Expected name "Tibanne Thecat Staking Pool", got "${metadata.name}"`);
+    // No request: all zeros
+    const none = await ctx.queryPendingUnstake(user.publicKey);
+    if (none.amount !== 0n || none.requestTime !== 0n || none.cooldownEnd !== 0n
+      || none.secondsRemaining !== 0n || none.completable) {
+      throw new Error(`Expected an all-zero reply, got ${JSON.stringify(none, (_, v) => typeof v === 'bigint' ? v.toString() : v)}`);
     }
-    if (metadata.numTags !== 3) {
-      throw new Error(`Expected 3 tags, got ${metadata.numTags}`);
-    }
-    if (metadata.tags[0] !== '#stakingpool') {
-      throw new Error(`Expected tag 0 "#stakingpool", got "${metadata.tags[0]}"`);
+
+    await ctx.requestUnstake(user, BigInt(400_000_000));
+    const state = await ctx.readUserStakeState(user.publicKey);
+
+    const early = await ctx.queryPendingUnstake(user.publicKey);
+    if (early.amount !== 400_000_000n || early.requestTime !== state.unstakeRequestTime) {
+      throw new Error(`Unexpected amount/request time: ${early.amount} at ${early.requestTime}`);
     }
-    if (metadata.tags[1] !== '#chiefstaker') {
-      throw new Error(`Expected tag 1 "#chiefstaker", got "${metadata.tags[1]}"`);
+    if (early.cooldownEnd !== early.requestTime + 8n) {
+      throw new Error(`Cooldown should end 8s after the request, got ${early.cooldownEnd}`);
     }
-    if (metadata.tags[2] !== '#chiefpussy') {
-      throw new Error(`Expected tag 2 "#chiefpussy", got "${metadata.tags[2]}"`);
+    if (early.secondsRemaining === 0n || early.secondsRemaining > 8n || early.completable) {
+      throw new Error(`Expected a running cooldown, got ${early.secondsRemaining}s left`);
     }
-    const expectedUrl = `https://labs.chiefpussy.com/staking/${ctx.mint.toBase58()}`;
-    if (metadata.url !== expectedUrl) {
-      throw new Error(`Expected url "${expectedUrl}", got "${metadata.url}"`);
+
+    await new Promise(r => setTimeout(r, 3000));
+    const mid = await ctx.queryPendingUnstake(user.publicKey);
+    if (mid.cooldownEnd !== early.cooldownEnd || mid.secondsRemaining >= early.secondsRemaining) {
+      throw new Error(`Remaining seconds should shrink: ${early.secondsRemaining} -> ${mid.secondsRemaining}`);
     }
-    if (metadata.memberCount !== 0n) {
-      throw new Error(`Expected member_count 0, got ${metadata.memberCount}`);
+
+    await new Promise(r => setTimeout(r, 8000));
+    const done = await ctx.queryPendingUnstake(user.publicKey);
+    if (done.secondsRemaining !== 0n || !done.completable) {
+      throw new Error(`Expected a completable request, got ${done.secondsRemaining}s left`);
     }
-    if (metadata.pool.toBase58() !== ctx.poolPDA.toBase58()) {
-      throw new Error(`Pool mismatch in metadata`);
+
+    // The query agrees with CompleteUnstake
+    await ctx.completeUnstake(user, userToken);
+    if ((await ctx.queryPendingUnstake(user.publicKey)).amount !== 0n) {
+      throw new Error('Completed request should no longer be reported');
     }
   });
 
-  // Test: SetPoolMetadata is idempotent (re-calling updates without changing data)
-  await test(`[${tokenProgramLabel}] SetPoolMetadata is idempotent`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'TestToken', 'TEST');
-    await ctx.initializePool(BigInt(2592000));
+  // =========================================================================
+  // SetRewardPrecision tests
+  // =========================================================================
 
-    await ctx.setPoolMetadata();
-    const meta1 = await ctx.readMetadata();
+  await test(`[${tokenProgramLabel}] SetRewardPrecision: pools at different precisions distribute alike`, async () => {
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
 
-    // Call again — should succeed and preserve same data
-    await ctx.setPoolMetadata();
-    const meta2 = await ctx.readMetadata();
+    const ctxs: TestContext[] = [];
+    for (const shift of [0, 20]) {
+      const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+      await ctx.setup();
+      await ctx.createMint(9);
+      await ctx.initializePool(BigInt(2592000));
+      await ctx.setRewardPrecision(ctx.payer, shift);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+      ctxs.push(ctx);
+    }
 
-    if (meta1.name !== meta2.name) throw new Error('Name changed on re-call');
-    if (meta1.memberCount !== meta2.memberCount) throw new Error('member_count changed on re-call');
-    if (meta1.url !== meta2.url) throw new Error('URL changed on re-call');
+    for (const ctx of ctxs) {
+      await ctx.depositRewards(BigInt(500_000_000));
+    }
+
+    const [full, coarse] = await Promise.all(ctxs.map(ctx => ctx.queryAccumulatorSnapshot(user.publicKey)));
+    if (full.accScaleShift !== 0 || coarse.accScaleShift !== 20) {
+      throw new Error(`Unexpected shifts: ${full.accScaleShift}, ${coarse.accScaleShift}`);
+    }
+    if (full.accRewardPerWeightedShare >> 20n !== coarse.accRewardPerWeightedShare) {
+      throw new Error('Coarse accumulator should be the full one divided by 2^20');
+    }
+    const diff = full.pending > coarse.pending ? full.pending - coarse.pending : coarse.pending - full.pending;
+    if (full.pending === 0n || diff > 1n) {
+      throw new Error(`Pending rewards should match: ${full.pending} vs ${coarse.pending}`);
+    }
+
+    // Too late once rewards are in the accumulator
+    let failed = false;
+    try {
+      await ctxs[0].setRewardPrecision(ctxs[0].payer, 8);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // RewardsAlreadyDistributed = error code 59 = 0x3b
+      if (!errMsg.includes('0x3b')) {
+        throw new Error(`Expected RewardsAlreadyDistributed (0x3b), got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('Changing precision after a distribution should fail');
   });
 
-  // Test: SetPoolMetadata is permissionless
-  await test(`[${tokenProgramLabel}] SetPoolMetadata is permissionless`, async () => {
+  // =========================================================================
+  // ClaimAndCloseIfEmpty tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ClaimAndCloseIfEmpty: closes an emptied stake, leaves an active one open`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'PermTest', 'PERM');
+    await ctx.createMint(9);
     await ctx.initializePool(BigInt(2592000));
 
-    // Different payer than authority
-    const randomPayer = Keypair.generate();
-    await airdropAndConfirm(connection, randomPayer.publicKey, LAMPORTS_PER_SOL);
-
-    await ctx.setPoolMetadata(randomPayer);
+    const leaver = Keypair.generate();
+    const stayer = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [leaver, stayer]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+      await ctx.stake(user, userToken, BigInt(1_000_000_000));
+      tokens.push(userToken);
+    }
+    await ctx.depositRewards(BigInt(1_000_000_000));
+
+    // Fully unstaked: claimed and closed, rent back to the owner
+    await ctx.unstake(leaver, tokens[0], BigInt(1_000_000_000));
+    const [leaverStake] = deriveUserStakePDA(ctx.poolPDA, leaver.publicKey);
+    const rent = (await connection.getAccountInfo(leaverStake))!.lamports;
+    const before = await ctx.getBalance(leaver.publicKey);
+    await ctx.claimAndCloseIfEmpty(leaver);
+    if (await connection.getAccountInfo(leaverStake) !== null) {
+      throw new Error('Empty stake account should be closed');
+    }
+    if (await ctx.getBalance(leaver.publicKey) - before < rent) {
+      throw new Error('Stake account rent should be returned to the owner');
+    }
 
-    const metadata = await ctx.readMetadata();
-    if (metadata.name !== 'PermTest Staking Pool') {
-      throw new Error(`Expected "PermTest Staking Pool", got "${metadata.name}"`);
+    // Still staked: just a claim, the account stays
+    const stakerBefore = await ctx.readUserStakeState(stayer.publicKey);
+    await ctx.claimAndCloseIfEmpty(stayer);
+    const stakerAfter = await ctx.readUserStakeState(stayer.publicKey);
+    if (stakerAfter.amount !== 1_000_000_000n) {
+      throw new Error('Active stake should be left open and intact');
     }
+    if (stakerAfter.totalRewardsClaimed < stakerBefore.totalRewardsClaimed) {
+      throw new Error('Claim counter should not go backwards');
+    }
+    await ctx.verifyVaultConsistency();
   });
 
-  // Test: SetPoolMetadata handles trailing spaces in token name
-  await test(`[${tokenProgramLabel}] SetPoolMetadata trims token name whitespace`, async () => {
+  // =========================================================================
+  // QueryWeightedShare tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] QueryWeightedShare: returned ratio matches the share of a deposit earned`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    // Note: Token 2022 metadata stores the name as-is (with trailing space)
-    await ctx.createMintWithMetadata(9, 'Tibanne Thecat ', 'ChiefPussy');
-    await ctx.initializePool(BigInt(2592000));
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-    await ctx.setPoolMetadata();
+    const WAD = 1_000_000_000_000_000_000n;
+    const users = [Keypair.generate(), Keypair.generate()];
+    const amounts = [BigInt(1_000_000_000), BigInt(3_000_000_000)];
+    for (let i = 0; i < users.length; i++) {
+      await airdropAndConfirm(connection, users[i].publicKey, LAMPORTS_PER_SOL);
+      const userToken = await ctx.createUserTokenAccount(users[i].publicKey);
+      await ctx.mintTokens(userToken, amounts[i]);
+      await ctx.stake(users[i], userToken, amounts[i]);
+    }
+    await new Promise(r => setTimeout(r, 3000));
 
-    const metadata = await ctx.readMetadata();
-    // .trim() in set_metadata.rs removes trailing space
-    if (metadata.name !== 'Tibanne Thecat Staking Pool') {
-      throw new Error(`Expected "Tibanne Thecat Staking Pool", got "${metadata.name}"`);
+    const deposit = BigInt(1_000_000_000);
+    await ctx.depositRewards(deposit);
+
+    const user = users[0];
+    // Weight only grows, so bracket the snapshot between two queries
+    const before = await ctx.queryWeightedShare(user.publicKey);
+    const snap = await ctx.queryAccumulatorSnapshot(user.publicKey);
+    const after = await ctx.queryWeightedShare(user.publicKey);
+
+    if (before.contribution !== amounts[0] * WAD) {
+      throw new Error(`Contribution ${before.contribution} != amount * WAD`);
+    }
+    if (before.denominator !== (amounts[0] + amounts[1]) * WAD) {
+      throw new Error(`Denominator ${before.denominator} != total_staked * WAD`);
+    }
+    if (before.weighted === 0n || before.weighted > before.contribution) {
+      throw new Error(`Weighted ${before.weighted} out of range`);
+    }
+
+    const low = (deposit * before.weighted) / before.denominator;
+    const high = (deposit * after.weighted) / after.denominator;
+    if (snap.pending + 1n < low || snap.pending > high + 1n) {
+      throw new Error(`Earned ${snap.pending} outside the queried share [${low}, ${high}]`);
     }
   });
 
-  // Test: Stake with metadata increments member_count
-  await test(`[${tokenProgramLabel}] Stake with metadata increments member_count`, async () => {
+  // =========================================================================
+  // SetDelegate tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] SetDelegate: sync-only delegate can force a sync but not change settings`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'MemberTest', 'MEM');
+    await ctx.createMint(9);
     await ctx.initializePool(BigInt(2592000));
-    await ctx.setPoolMetadata();
 
-    const meta0 = await ctx.readMetadata();
-    if (meta0.memberCount !== 0n) throw new Error(`Expected 0 members, got ${meta0.memberCount}`);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Stake with metadata account
-    const user1 = Keypair.generate();
-    await airdropAndConfirm(connection, user1.publicKey, LAMPORTS_PER_SOL);
-    const user1Token = await ctx.createUserTokenAccount(user1.publicKey);
-    await ctx.mintTokens(user1Token, BigInt(1_000_000_000));
-    await ctx.stakeWithMetadata(user1, user1Token, BigInt(1_000_000_000));
+    const DELEGATE_PERM_SYNC = 0x01;
+    const crank = Keypair.generate();
+    await airdropAndConfirm(connection, crank.publicKey, LAMPORTS_PER_SOL);
+    await ctx.setDelegate(ctx.payer, crank.publicKey, DELEGATE_PERM_SYNC);
+
+    const sendToPool = async () => {
+      const transferTx = new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: ctx.payer.publicKey,
+          toPubkey: ctx.poolPDA,
+          lamports: LAMPORTS_PER_SOL / 10,
+        }),
+      );
+      await sendAndConfirmTransaction(connection, transferTx, [ctx.payer]);
+      return BigInt(await ctx.getBalance(ctx.poolPDA));
+    };
 
-    const meta1 = await ctx.readMetadata();
-    if (meta1.memberCount !== 1n) throw new Error(`Expected 1 member, got ${meta1.memberCount}`);
+    // The granted permission works
+    const before = await ctx.readPoolState();
+    await ctx.forceSyncWithBalance(crank, await sendToPool());
+    if ((await ctx.readPoolState()).lastSyncedLamports <= before.lastSyncedLamports) {
+      throw new Error('Delegate sync should distribute the new lamports');
+    }
 
-    // Second staker
-    const user2 = Keypair.generate();
-    await airdropAndConfirm(connection, user2.publicKey, LAMPORTS_PER_SOL);
-    const user2Token = await ctx.createUserTokenAccount(user2.publicKey);
-    await ctx.mintTokens(user2Token, BigInt(2_000_000_000));
-    await ctx.stakeWithMetadata(user2, user2Token, BigInt(2_000_000_000));
+    // InvalidAuthority = error code 6 = 0x6
+    const expectInvalidAuthority = async (label: string, fn: () => Promise<unknown>) => {
+      let failed = false;
+      try {
+        await fn();
+      } catch (e) {
+        failed = true;
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes('0x6')) {
+          throw new Error(`${label}: expected InvalidAuthority (0x6), got: ${errMsg}`);
+        }
+      }
+      if (!failed) throw new Error(`${label} should be rejected for the sync delegate`);
+    };
 
-    const meta2 = await ctx.readMetadata();
-    if (meta2.memberCount !== 2n) throw new Error(`Expected 2 members, got ${meta2.memberCount}`);
+    await expectInvalidAuthority('UpdatePoolSettings', () => ctx.updatePoolSettings(crank, BigInt(100), null, null));
+    await expectInvalidAuthority('SetDisplayUnit', () => ctx.setDisplayUnit(crank, 'USDC', 6));
+    await expectInvalidAuthority('SetDelegate', () => ctx.setDelegate(crank, crank.publicKey, 0x1f));
+
+    // Revoked: the sync permission is gone too
+    await ctx.setDelegate(ctx.payer, crank.publicKey, 0);
+    const balance = await sendToPool();
+    await expectInvalidAuthority('Revoked ForceSyncWithBalance', () => ctx.forceSyncWithBalance(crank, balance));
   });
 
-  // Test: Additional stake does NOT increment member_count (not new)
-  await test(`[${tokenProgramLabel}] Additional stake does not increment member_count`, async () => {
+  // =========================================================================
+  // VerifyPfeeOwnership tests
+  // =========================================================================
+
+  // The taken / not-taken SharingConfig states are covered by the pfee unit
+  // tests; a local validator has no pfee accounts, so only the checks on the
+  // passed account run here.
+  await test(`[${tokenProgramLabel}] VerifyPfeeOwnership: rejects accounts other than pfee's SharingConfig`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'AddStake', 'ADD');
+    await ctx.createMint(9);
     await ctx.initializePool(BigInt(2592000));
-    await ctx.setPoolMetadata();
-
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(5_000_000_000));
 
-    // First stake
-    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
-    const meta1 = await ctx.readMetadata();
-    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
+    const PFEE_PROGRAM_ID = new PublicKey('pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ');
+    const [sharingConfig] = PublicKey.findProgramAddressSync(
+      [Buffer.from('sharing-config'), ctx.mint.toBuffer()],
+      PFEE_PROGRAM_ID,
+    );
 
-    // Additional stake (existing account, should NOT increment)
-    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
-    const meta2 = await ctx.readMetadata();
-    if (meta2.memberCount !== 1n) throw new Error(`Expected still 1, got ${meta2.memberCount}`);
+    // [error code, label, account]: InvalidPDA = 9, InvalidAccountOwner = 20
+    const cases: [number, string, PublicKey][] = [
+      [9, 'another key', Keypair.generate().publicKey],
+      [20, 'the unowned SharingConfig PDA', sharingConfig],
+    ];
+    for (const [code, label, account] of cases) {
+      let failed = false;
+      try {
+        await ctx.verifyPfeeOwnership(account);
+      } catch (e) {
+        failed = true;
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(`"Custom":${code}}`)) {
+          throw new Error(`${label}: expected custom error ${code}, got: ${errMsg}`);
+        }
+      }
+      if (!failed) throw new Error(`VerifyPfeeOwnership should reject ${label}`);
+    }
   });
 
-  // Test: CloseStakeAccount with metadata decrements member_count
-  await test(`[${tokenProgramLabel}] CloseStakeAccount with metadata decrements member_count`, async () => {
+  // =========================================================================
+  // GetPendingRewards tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] GetPendingRewards: includes unsynced lamports and matches the claim math`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'CloseTest', 'CLZ');
-    await ctx.initializePool(BigInt(2592000));
-    await ctx.setPoolMetadata();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+
+    await ctx.depositRewards(BigInt(500_000_000));
+    const synced = await ctx.getPendingRewards(user.publicKey);
+    if (synced === 0n) throw new Error('Expected pending rewards after a deposit');
+
+    // SOL sent straight to the pool counts before anyone syncs it
+    const transferTx = new Transaction().add(
+      SystemProgram.transfer({
+        fromPubkey: ctx.payer.publicKey,
+        toPubkey: ctx.poolPDA,
+        lamports: 500_000_000,
+      }),
+    );
+    await sendAndConfirmTransaction(connection, transferTx, [ctx.payer]);
+    const projected = await ctx.getPendingRewards(user.publicKey);
+    if (projected <= synced) {
+      throw new Error(`Unsynced lamports should raise pending: ${synced} -> ${projected}`);
+    }
 
-    // Stake with metadata
-    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
-    const meta1 = await ctx.readMetadata();
-    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
-
-    // Unstake everything
-    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
-
-    // Close stake account with metadata
-    await ctx.closeStakeAccount(user, true);
-    const meta2 = await ctx.readMetadata();
-    if (meta2.memberCount !== 0n) throw new Error(`Expected 0 after close, got ${meta2.memberCount}`);
+    // After the sync it agrees with the accumulator math (weight grows a
+    // little between the calls)
+    await ctx.syncRewards();
+    const snap = await ctx.queryAccumulatorSnapshot(user.publicKey);
+    const after = await ctx.getPendingRewards(user.publicKey);
+    if (snap.pending < projected || after < snap.pending || after - projected > projected / 10n) {
+      throw new Error(`Projection ${projected}, snapshot ${snap.pending}, after sync ${after} disagree`);
+    }
   });
 
-  // Test: SetPoolMetadata preserves member_count on update
-  await test(`[${tokenProgramLabel}] SetPoolMetadata preserves member_count on update`, async () => {
-    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'PreserveCount', 'PRS');
-    await ctx.initializePool(BigInt(2592000));
-    await ctx.setPoolMetadata();
+  await test(`[${tokenProgramLabel}] GetPendingRewards: rejects a stake from another pool`, async () => {
+    const ctxs: TestContext[] = [];
+    for (let i = 0; i < 2; i++) {
+      const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+      await ctx.setup();
+      await ctx.createMint(9);
+      await ctx.initializePool(BigInt(2592000));
+      ctxs.push(ctx);
+    }
 
-    // Stake to increment member_count
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stakeWithMetadata(user, userToken, BigInt(1_000_000_000));
-
-    const meta1 = await ctx.readMetadata();
-    if (meta1.memberCount !== 1n) throw new Error(`Expected 1, got ${meta1.memberCount}`);
+    const userToken = await ctxs[1].createUserTokenAccount(user.publicKey);
+    await ctxs[1].mintTokens(userToken, BigInt(1_000_000_000));
+    await ctxs[1].stake(user, userToken, BigInt(1_000_000_000));
+    const [otherStake] = deriveUserStakePDA(ctxs[1].poolPDA, user.publicKey);
+
+    // Empty pool, matching stake: 0 rather than an error
+    if ((await ctxs[1].getPendingRewards(user.publicKey)) !== 0n) {
+      throw new Error('A stake with no rewards should report 0');
+    }
 
-    // Re-set metadata — member_count should be preserved
-    await ctx.setPoolMetadata();
-    const meta2 = await ctx.readMetadata();
-    if (meta2.memberCount !== 1n) throw new Error(`Expected 1 after re-set, got ${meta2.memberCount}`);
+    let failed = false;
+    try {
+      await ctxs[0].getPendingRewards(user.publicKey, otherStake);
+    } catch (e) {
+      failed = true;
+      const errMsg = (e as any).message || '';
+      // InvalidPool = error code 8
+      if (!errMsg.includes('"Custom":8}')) {
+        throw new Error(`Expected InvalidPool, got: ${errMsg}`);
+      }
+    }
+    if (!failed) throw new Error('A stake from another pool should be rejected');
   });
 
-  // Test: Stake without metadata account still works (backwards compatible)
-  await test(`[${tokenProgramLabel}] Stake without metadata account is backwards compatible`, async () => {
+  // =========================================================================
+  // CloseStakeAccount checks
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] CloseStakeAccount: rejected after a partial unstake, allowed after a full exit`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
-    await ctx.createMintWithMetadata(9, 'BackCompat', 'BCK');
+    await ctx.createMint(9);
     await ctx.initializePool(BigInt(2592000));
-    await ctx.setPoolMetadata();
 
     const user = Keypair.generate();
     await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-
-    // Stake WITHOUT passing metadata account (old-style 8-account call)
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.unstake(user, userToken, BigInt(400_000_000));
 
-    // member_count should remain 0 since we didn't pass metadata
-    const meta = await ctx.readMetadata();
-    if (meta.memberCount !== 0n) throw new Error(`Expected 0 (no metadata passed), got ${meta.memberCount}`);
-  });
+    let failed = false;
+    try {
+      await ctx.closeStakeAccount(user);
+    } catch (e: any) {
+      failed = true;
+      // AccountNotEmpty = error code 30 = 0x1e
+      if (!e.message.includes('custom program error: 0x1e')) {
+        throw new Error(`Expected AccountNotEmpty (0x1e), got: ${e.message}`);
+      }
+      const logs: string[] = e.logs || [];
+      if (!logs.some(l => l.includes('600000000 tokens still staked'))) {
+        throw new Error(`Expected a log naming the staked amount, got: ${logs.join(' | ')}`);
+      }
+    }
+    if (!failed) throw new Error('Closing with tokens still staked should fail');
 
-  } // end Token 2022-only metadata tests
+    await ctx.unstake(user, userToken, BigInt(600_000_000));
+    await ctx.closeStakeAccount(user);
+    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+    if (await connection.getAccountInfo(userStakePDA)) {
+      throw new Error('Stake account should be closed');
+    }
+  });
 
-  // === Repeated Claim Exploit Tests (round 10b fix) ===
+  // =========================================================================
+  // ClaimRewardsTo
+  // =========================================================================
 
-  // Test: Repeated claims do NOT extract max-weight rewards
-  await test(`[${tokenProgramLabel}] Security: Repeated claims cannot extract max-weight rewards`, async () => {
+  await test(`[${tokenProgramLabel}] ClaimRewardsTo: pays the recipient, rejects program-owned accounts`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-
-    // tau=60 so weight grows slowly (user will have ~15% weight after 10s)
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(BigInt(100)); // Short tau
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Wait 10s to accrue some weight (~15% at tau=60)
-    console.log('    Waiting 10s for weight to accrue...');
-    await new Promise(r => setTimeout(r, 10000));
-
-    // Deposit a large reward
-    const depositAmount = LAMPORTS_PER_SOL;
-    await ctx.depositRewards(BigInt(depositAmount));
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
 
-    // Claim 5 times in rapid succession
-    let totalClaimed = 0;
-    for (let i = 0; i < 5; i++) {
-      const balBefore = await ctx.getBalance(user.publicKey);
-      try {
-        await ctx.claimRewards(user);
-      } catch (e) {
-        // Expected: "no pending rewards" after first claim
-      }
-      const balAfter = await ctx.getBalance(user.publicKey);
-      const reward = balAfter - balBefore;
-      if (reward > 0) {
-        totalClaimed += reward;
-        console.log(`    Claim ${i + 1}: ${reward} lamports`);
-      } else {
-        console.log(`    Claim ${i + 1}: 0 (no rewards / tx fee)`);
+    // The pool PDA is owned by this program, not the system program
+    try {
+      await ctx.claimRewardsTo(user, ctx.poolPDA);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // InvalidRewardRecipient = error code 63 = 0x3f
+      if (!e.message.includes('custom program error: 0x3f')) {
+        throw new Error(`Expected InvalidRewardRecipient (0x3f), got: ${e.message}`);
       }
     }
 
-    // The critical assertion: total claimed must be much less than the deposit.
-    // With ~15% weight, user should get ~15% of 1 SOL = ~0.15 SOL.
-    // Before fix: repeated claims would converge to ~1 SOL (100%).
-    // Allow generous margin: user should get < 50% of deposit.
-    const claimedPercent = (totalClaimed * 100) / depositAmount;
-    console.log(`    Total claimed: ${totalClaimed} lamports (${claimedPercent.toFixed(1)}% of deposit)`);
+    const treasury = Keypair.generate();
+    await airdropAndConfirm(connection, treasury.publicKey, LAMPORTS_PER_SOL);
 
-    if (totalClaimed > depositAmount * 0.5) {
-      throw new Error(
-        `EXPLOIT: Repeated claims extracted ${claimedPercent.toFixed(1)}% of deposit ` +
-        `(expected <50% for ~15% weight). Total: ${totalClaimed} / ${depositAmount}`
-      );
+    const userBefore = await ctx.getBalance(user.publicKey);
+    const treasuryBefore = await ctx.getBalance(treasury.publicKey);
+    await ctx.claimRewardsTo(user, treasury.publicKey);
+    const userAfter = await ctx.getBalance(user.publicKey);
+    const treasuryAfter = await ctx.getBalance(treasury.publicKey);
+
+    if (treasuryAfter <= treasuryBefore) {
+      throw new Error(`Recipient should have been paid: ${treasuryBefore} -> ${treasuryAfter}`);
+    }
+    // Payer covers the tx fee, so the signing owner's balance is untouched
+    if (userAfter !== userBefore) {
+      throw new Error(`Owner balance should not change: ${userBefore} -> ${userAfter}`);
     }
   });
 
-  // Test: Second claim returns 0 when no new rewards deposited
-  await test(`[${tokenProgramLabel}] Security: Second claim returns 0 without new rewards`, async () => {
+  // =========================================================================
+  // Structured events
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] Events: Stake logs a decodable StakeEvent`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(BigInt(2592000));
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
-
-    // Wait for weight
-    console.log('    Waiting 5s for weight...');
-    await new Promise(r => setTimeout(r, 5000));
-
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
-
-    // First claim should succeed
-    const bal1 = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const reward1 = (await ctx.getBalance(user.publicKey)) - bal1;
-    console.log(`    First claim: ${reward1} lamports`);
-    if (reward1 <= 0) throw new Error('First claim should get rewards');
+    const sig = await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Second claim (no new deposits) — in a time-weighted system, weight grows
-    // between claims, so a small additional entitlement is normal. The security
-    // check is that the second claim is much smaller than the first (no double-claim).
-    const bal2 = await ctx.getBalance(user.publicKey);
-    try {
-      await ctx.claimRewards(user);
-    } catch (e) {
-      // May be rejected if pending rounds to 0
-      console.log('    Second claim: rejected (expected)');
+    const tx = await connection.getTransaction(sig, {
+      commitment: 'confirmed',
+      maxSupportedTransactionVersion: 0,
+    });
+    const discriminator = createHash('sha256').update('event:StakeEvent').digest().subarray(0, 8);
+    const event = (tx?.meta?.logMessages || [])
+      .filter(l => l.startsWith('Program data: '))
+      .map(l => Buffer.from(l.slice('Program data: '.length), 'base64'))
+      .find(data => data.subarray(0, 8).equals(discriminator));
+    if (!event) throw new Error('No StakeEvent in the logs');
+
+    // 8 disc + 32 pool + 32 user + 8 amount + 8 position + 16 total + 16 acc + 1 shift
+    if (event.length !== 121) throw new Error(`Unexpected StakeEvent size ${event.length}`);
+    if (!new PublicKey(event.subarray(8, 40)).equals(ctx.poolPDA)) {
+      throw new Error('StakeEvent pool mismatch');
     }
-    const reward2 = (await ctx.getBalance(user.publicKey)) - bal2;
-    console.log(`    Second claim: ${reward2} lamports`);
-
-    // Second claim must be strictly less than first (weight growth, not double-claim)
-    if (reward2 >= reward1) {
-      throw new Error(
-        `EXPLOIT: Second claim got ${reward2} lamports (>= first claim ${reward1}), possible double-claim`
-      );
+    if (!new PublicKey(event.subarray(40, 72)).equals(user.publicKey)) {
+      throw new Error('StakeEvent user mismatch');
+    }
+    const amount = event.readBigUInt64LE(72);
+    const position = event.readBigUInt64LE(80);
+    if (amount !== BigInt(1_000_000_000) || position !== BigInt(1_000_000_000)) {
+      throw new Error(`StakeEvent amounts ${amount}/${position}`);
     }
   });
 
-  // Test: Claiming frequency doesn't affect total rewards (frequency-independent)
-  await test(`[${tokenProgramLabel}] Security: Claim frequency does not affect total rewards`, async () => {
+  // =========================================================================
+  // TransferStake tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] TransferStake: position keeps its maturity and request under the new owner`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
-
-    // Both stake at the same time
-    const multiClaimer = Keypair.generate();
-    const singleClaimer = Keypair.generate();
-    await airdropAndConfirm(connection, multiClaimer.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, singleClaimer.publicKey, 2 * LAMPORTS_PER_SOL);
-    const multiToken = await ctx.createUserTokenAccount(multiClaimer.publicKey);
-    const singleToken = await ctx.createUserTokenAccount(singleClaimer.publicKey);
-    const amount = BigInt(1_000_000_000);
-    await ctx.mintTokens(multiToken, amount);
-    await ctx.mintTokens(singleToken, amount);
-    await ctx.stake(multiClaimer, multiToken, amount);
-    await ctx.stake(singleClaimer, singleToken, amount);
-
-    // Wait 5s, deposit rewards
-    console.log('    Waiting 5s...');
-    await new Promise(r => setTimeout(r, 5000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    await ctx.initializePool(BigInt(100));
 
-    // Multi-claimer claims immediately at low weight
-    const multiBal1 = await ctx.getBalance(multiClaimer.publicKey);
-    await ctx.claimRewards(multiClaimer);
-    const multiReward1 = (await ctx.getBalance(multiClaimer.publicKey)) - multiBal1;
-    console.log(`    Multi-claimer first claim (5s age): ${multiReward1}`);
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, bob.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.mintTokens(bobToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(bob, bobToken, BigInt(1_000_000_000));
 
-    // Wait another 15s for weight to grow
-    console.log('    Waiting 15s more...');
-    await new Promise(r => setTimeout(r, 15000));
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await ctx.requestUnstake(alice, BigInt(400_000_000));
 
-    // Multi-claimer claims again (picks up weight growth entitlement)
-    const multiBal2 = await ctx.getBalance(multiClaimer.publicKey);
+    // Bob's stake is in use, so the positions can't be merged
     try {
-      await ctx.claimRewards(multiClaimer);
-    } catch (e: any) { if (!e.message?.includes('0xb')) throw e; }
-    const multiReward2 = (await ctx.getBalance(multiClaimer.publicKey)) - multiBal2;
-    if (multiReward2 < 0) throw new Error(`Unexpected SOL loss: ${multiReward2}`);
-
-    // Single-claimer claims once at 20s age
-    const singleBal = await ctx.getBalance(singleClaimer.publicKey);
-    await ctx.claimRewards(singleClaimer);
-    const singleReward = (await ctx.getBalance(singleClaimer.publicKey)) - singleBal;
+      await ctx.transferStake(alice, bob.publicKey);
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // AccountNotEmpty = error code 30 = 0x1e
+      if (!e.message.includes('custom program error: 0x1e')) {
+        throw new Error(`Expected AccountNotEmpty (0x1e), got: ${e.message}`);
+      }
+    }
 
-    const multiTotal = multiReward1 + multiReward2;
-    console.log(`    Multi-claimer total: ${multiTotal} lamports`);
-    console.log(`    Single-claimer total: ${singleReward} lamports`);
+    const carol = Keypair.generate();
+    const before = await ctx.readUserStakeState(alice.publicKey);
+    await ctx.transferStake(alice, carol.publicKey);
 
-    // Frequency-independent: both should earn approximately the same.
-    // Allow tolerance for block-time jitter between claims (weight grows ~1.2%/s
-    // at t=20s with tau=60, so a few seconds of jitter → a few % difference).
-    const diff = Math.abs(singleReward - multiTotal);
-    const larger = Math.max(singleReward, multiTotal);
-    const pctDiff = larger > 0 ? (diff * 100) / larger : 0;
-    console.log(`    Difference: ${diff} lamports (${pctDiff.toFixed(1)}%)`);
+    const source = await ctx.readUserStakeState(alice.publicKey);
+    const dest = await ctx.readUserStakeState(carol.publicKey);
+    if (source.amount !== 0n || source.unstakeRequestAmount !== 0n) {
+      throw new Error(`Source should be empty, has ${source.amount} / ${source.unstakeRequestAmount}`);
+    }
+    if (dest.amount !== before.amount || dest.stakeTime !== before.stakeTime) {
+      throw new Error('Destination should hold the same position');
+    }
+    if (dest.unstakeRequestAmount !== 400_000_000n
+      || dest.unstakeRequestTime !== before.unstakeRequestTime) {
+      throw new Error('Pending unstake request should move with the position');
+    }
+    // Pending rewards were claimed to the previous owner
+    if (source.totalRewardsClaimed === 0n) {
+      throw new Error('Pending rewards should be claimed to the previous owner');
+    }
 
-    if (pctDiff > 10) {
-      throw new Error(
-        `Claim totals diverged >10%: multi=${multiTotal}, single=${singleReward}, diff=${pctDiff.toFixed(1)}%`
-      );
+    // Nothing left to claim for either owner until new rewards arrive
+    const [carolStake] = deriveUserStakePDA(ctx.poolPDA, carol.publicKey);
+    const carolPending = await ctx.getPendingRewards(carol.publicKey, carolStake);
+    if (carolPending > 1n) {
+      throw new Error(`New owner should not inherit claimed rewards, pending ${carolPending}`);
     }
-    console.log('    Frequency-independent claiming: OK');
+
+    // The emptied source account can be closed
+    await ctx.closeStakeAccount(alice);
   });
 
-  // Test: total_rewards_claimed field tracks correctly
-  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on claim`, async () => {
+  // =========================================================================
+  // Partial claims
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ClaimRewards: partial amount leaves the rest claimable`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(BigInt(60)); // Short tau
 
     const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
     const userToken = await ctx.createUserTokenAccount(user.publicKey);
     await ctx.mintTokens(userToken, BigInt(1_000_000_000));
     await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 2000));
 
-    // Check initial state
-    const state0 = await ctx.readUserStakeState(user.publicKey);
-    console.log(`    Initial total_rewards_claimed: ${state0.totalRewardsClaimed}`);
-    if (state0.totalRewardsClaimed !== 0n) {
-      throw new Error(`Expected 0 initial, got ${state0.totalRewardsClaimed}`);
+    // Sent straight to the pool: the partial claim syncs it first
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      SystemProgram.transfer({
+        fromPubkey: ctx.payer.publicKey,
+        toPubkey: ctx.poolPDA,
+        lamports: LAMPORTS_PER_SOL / 10,
+      }),
+    ), [ctx.payer]);
+
+    const part = 1_000n;
+    const before = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user, [], part);
+    const after = await ctx.getBalance(user.publicKey);
+    if (after - before !== Number(part)) {
+      throw new Error(`Expected exactly ${part} lamports, got ${after - before}`);
     }
 
-    // Wait and deposit
-    console.log('    Waiting 5s...');
-    await new Promise(r => setTimeout(r, 5000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    const remaining = await ctx.getPendingRewards(user.publicKey);
+    if (remaining === 0n) throw new Error('The rest should stay claimable');
 
-    // Claim
-    const balBefore = await ctx.getBalance(user.publicKey);
-    await ctx.claimRewards(user);
-    const balAfter = await ctx.getBalance(user.publicKey);
-    const reward = balAfter - balBefore;
-    console.log(`    Claimed: ${reward} lamports`);
+    try {
+      // More than was ever deposited
+      await ctx.claimRewards(user, [], BigInt(LAMPORTS_PER_SOL));
+      throw new Error('Should have failed');
+    } catch (e: any) {
+      // InsufficientRewards = error code 64 = 0x40
+      if (!e.message.includes('custom program error: 0x40')) {
+        throw new Error(`Expected InsufficientRewards (0x40), got: ${e.message}`);
+      }
+    }
 
-    // Check total_rewards_claimed updated
-    const state1 = await ctx.readUserStakeState(user.publicKey);
-    console.log(`    total_rewards_claimed after claim: ${state1.totalRewardsClaimed}`);
+    // Asking for exactly what is pending (weight keeps maturing, so it is
+    // still available a slot later) pays exactly that
+    const pending = await ctx.getPendingRewards(user.publicKey);
+    const beforeAll = await ctx.getBalance(user.publicKey);
+    await ctx.claimRewards(user, [], pending);
+    const afterAll = await ctx.getBalance(user.publicKey);
+    if (BigInt(afterAll - beforeAll) !== pending) {
+      throw new Error(`Expected ${pending} lamports, got ${afterAll - beforeAll}`);
+    }
+  });
 
-    if (state1.totalRewardsClaimed === 0n) {
-      throw new Error('total_rewards_claimed should be > 0 after claim');
+  // =========================================================================
+  // UpdateTau tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] UpdateTau: authority changes tau, weight carries over`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
+
+    // InvalidAuthority = error code 6 = 0x6
+    await expectError('Non-authority', '0x6', () => ctx.updateTau(alice, BigInt(120)));
+    // InvalidTau = error code 15 = 0xf
+    await expectError('Tau below minimum', '0xf', () => ctx.updateTau(ctx.payer, BigInt(1)));
+
+    await ctx.updateTau(ctx.payer, BigInt(120));
+    const tau = await ctx.readTauSeconds();
+    if (tau !== BigInt(120)) {
+      throw new Error(`Expected tau 120, got ${tau}`);
     }
 
-    // Deposit more and claim again
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
-    // Wait a tiny bit for weight (otherwise snapshot delta is 0)
+    // The position survives the change: rewards still flow to it
     await new Promise(r => setTimeout(r, 2000));
-    try {
-      await ctx.claimRewards(user);
-    } catch (e) { /* may fail if no new delta */ }
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    const before = await ctx.getBalance(alice.publicKey);
+    await ctx.claimRewards(alice);
+    const after = await ctx.getBalance(alice.publicKey);
+    if (after <= before) {
+      throw new Error(`Expected rewards after the tau change, balance ${before} -> ${after}`);
+    }
 
-    const state2 = await ctx.readUserStakeState(user.publicKey);
-    console.log(`    total_rewards_claimed after second: ${state2.totalRewardsClaimed}`);
+    // TauChangeTooLarge = error code 87 = 0x57
+    await expectError('More than 2x at once', '0x57', () => ctx.updateTau(ctx.payer, BigInt(241)));
 
-    if (state2.totalRewardsClaimed < state1.totalRewardsClaimed) {
-      throw new Error('total_rewards_claimed should never decrease');
+    // With a settings timelock a raise waits (PENDING_TAU = 512); a cut applies at once and replaces it
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { settingsTimelockSeconds: BigInt(86400) });
+    await ctx.updateTau(ctx.payer, BigInt(240));
+    const pending = await ctx.queryPendingSettings();
+    if (pending.mask !== 512 || pending.tauSeconds !== BigInt(240) || await ctx.readTauSeconds() !== BigInt(120)) {
+      throw new Error(`Tau raise should be pending, got mask ${pending.mask}`);
+    }
+    await ctx.updateTau(ctx.payer, BigInt(90));
+    if ((await ctx.queryPendingSettings()).mask !== 0 || await ctx.readTauSeconds() !== BigInt(90)) {
+      throw new Error('Tau cut should apply at once and cancel the pending raise');
     }
+
+    // SettingsLocked = error code 55 = 0x37
+    await ctx.lockPoolSettings(ctx.payer);
+    await expectError('Locked settings', '0x37', () => ctx.updateTau(ctx.payer, BigInt(240)));
   });
 
-  // Test: total_rewards_claimed increments on auto-claim during additional stake
-  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on auto-claim stake`, async () => {
+  // =========================================================================
+  // Sync / deposit interleaving tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] DepositRewards: direct transfers, syncs and deposits count each lamport once`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
     await ctx.initializePool(BigInt(60));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(2_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
 
-    // Wait and deposit
-    console.log('    Waiting 5s...');
-    await new Promise(r => setTimeout(r, 5000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    const directTransfer = (lamports: number) => SystemProgram.transfer({
+      fromPubkey: ctx.payer.publicKey,
+      toPubkey: ctx.poolPDA,
+      lamports,
+    });
+    const before = await ctx.readPoolState();
 
-    // Additional stake triggers auto-claim
-    const balBefore = await ctx.getBalance(user.publicKey);
-    await ctx.stake(user, userToken, BigInt(500_000_000));
-    const balAfter = await ctx.getBalance(user.publicKey);
+    // Direct transfer, then a sync
+    await sendAndConfirmTransaction(connection, new Transaction().add(directTransfer(50_000_000)), [ctx.payer]);
+    await ctx.syncRewards();
 
-    const state = await ctx.readUserStakeState(user.publicKey);
-    console.log(`    total_rewards_claimed after additional stake: ${state.totalRewardsClaimed}`);
-    console.log(`    Balance delta (includes tx fee): ${balAfter - balBefore}`);
+    // Direct transfer, a sync and a deposit in the same transaction, then a
+    // deposit that has to sweep a direct transfer by itself
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      directTransfer(20_000_000),
+      createSyncRewardsInstruction(ctx.poolPDA),
+      createDepositRewardsInstruction(ctx.poolPDA, ctx.payer.publicKey, BigInt(30_000_000)),
+    ), [ctx.payer]);
+    await sendAndConfirmTransaction(connection, new Transaction().add(directTransfer(10_000_000)), [ctx.payer]);
+    await ctx.depositRewards(BigInt(40_000_000));
+    await ctx.syncRewards();
 
-    // If auto-claim paid out anything, total_rewards_claimed should reflect it
-    if (state.totalRewardsClaimed > 0n) {
-      console.log('    Auto-claim rewards tracked successfully');
-    } else {
-      console.log('    No auto-claim payout (weight may be too low)');
+    const after = await ctx.readPoolState();
+    const total = BigInt(150_000_000);
+    const synced = after.lastSyncedLamports - before.lastSyncedLamports;
+    if (synced !== total) {
+      throw new Error(`Expected ${total} lamports synced, got ${synced}`);
+    }
+    // 150_000_000 lamports over 1_000_000_000 tokens = 0.15 lamports per token
+    const expectedAcc = total * BigInt(1e18) / BigInt(1_000_000_000);
+    const acc = after.accRewardPerWeightedShare - before.accRewardPerWeightedShare;
+    if (acc !== expectedAcc) {
+      throw new Error(`Expected accumulator +${expectedAcc}, got +${acc}`);
     }
   });
 
-  // Test: total_rewards_claimed increments on unstake with reward payout
-  await test(`[${tokenProgramLabel}] Accounting: total_rewards_claimed increments on unstake`, async () => {
+  // =========================================================================
+  // SPL-token reward tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] Token rewards: deposit and claim in a reward mint, SOL paths rejected`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
     await ctx.initializePool(BigInt(60));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
-    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
-    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    // A USDC-like reward mint under the classic token program
+    const rewardMint = await createMint(
+      connection, ctx.payer, ctx.mintAuthority.publicKey, null, 6, undefined, undefined, TOKEN_PROGRAM_ID);
+    await ctx.setRewardMint(ctx.payer, rewardMint, TOKEN_PROGRAM_ID);
 
-    // Wait and deposit
-    console.log('    Waiting 5s...');
-    await new Promise(r => setTimeout(r, 5000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 2));
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Unstake (should auto-claim rewards)
-    await ctx.unstake(user, userToken, BigInt(500_000_000));
+    // RewardMintAlreadySet = error code 67 = 0x43
+    await expectError('Second SetRewardMint', '0x43', () =>
+      ctx.setRewardMint(ctx.payer, rewardMint, TOKEN_PROGRAM_ID));
 
-    const state = await ctx.readUserStakeState(user.publicKey);
-    console.log(`    total_rewards_claimed after unstake: ${state.totalRewardsClaimed}`);
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 2000));
 
-    if (state.totalRewardsClaimed > 0n) {
-      console.log('    Unstake reward payout tracked successfully');
-    } else {
-      console.log('    No reward payout during unstake (weight may be too low)');
+    const funderToken = await createAccount(
+      connection, ctx.payer, rewardMint, ctx.payer.publicKey, undefined, undefined, TOKEN_PROGRAM_ID);
+    await mintTo(
+      connection, ctx.payer, rewardMint, funderToken, ctx.mintAuthority, 1_000_000, [], undefined, TOKEN_PROGRAM_ID);
+    await ctx.depositTokenRewards(ctx.payer, funderToken, rewardMint, TOKEN_PROGRAM_ID, BigInt(1_000_000));
+
+    // RewardModeMismatch = error code 66 = 0x42
+    await expectError('DepositRewards', '0x42', () => ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10)));
+    await expectError('ClaimRewards', '0x42', () => ctx.claimRewards(alice));
+
+    const aliceRewards = await createAccount(
+      connection, ctx.payer, rewardMint, alice.publicKey, undefined, undefined, TOKEN_PROGRAM_ID);
+    await ctx.claimTokenRewards(alice, aliceRewards, rewardMint, TOKEN_PROGRAM_ID);
+    const claimed = (await getAccount(connection, aliceRewards, undefined, TOKEN_PROGRAM_ID)).amount;
+    if (claimed === 0n || claimed > 1_000_000n) {
+      throw new Error(`Expected a share of the 1_000_000 reward tokens, got ${claimed}`);
+    }
+    const vault = (await getAccount(connection, deriveRewardVaultPDA(ctx.poolPDA)[0], undefined, TOKEN_PROGRAM_ID)).amount;
+    if (vault + claimed !== 1_000_000n) {
+      throw new Error(`Vault should hold the unclaimed rest, got ${vault} + ${claimed}`);
     }
   });
 
-  await test(`[${tokenProgramLabel}] Security: Large stake amounts don't overflow reward math`, async () => {
+  // =========================================================================
+  // RenounceAuthority tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] RenounceAuthority: irreversible, settings frozen`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60));
+    await ctx.initializePool(BigInt(2592000));
 
-    const user = Keypair.generate();
-    await airdropAndConfirm(connection, user.publicKey, 3 * LAMPORTS_PER_SOL);
-    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Mint near-u64-max tokens (9e18, close to u64::MAX of ~1.8e19)
-    const hugeAmount = BigInt('9000000000000000000'); // 9e18
-    await ctx.mintTokens(userToken, hugeAmount);
-    await ctx.stake(user, userToken, hugeAmount);
+    // InvalidAuthority = error code 6 = 0x6
+    const mallory = Keypair.generate();
+    await airdropAndConfirm(connection, mallory.publicKey, LAMPORTS_PER_SOL);
+    await expectError('Non-authority', '0x6', () => ctx.renounceAuthority(mallory));
 
-    const staked = await ctx.getTokenBalance(ctx.tokenVaultPDA);
-    console.log(`    Staked ${staked} tokens (9e18)`);
+    const sig = await ctx.renounceAuthority(ctx.payer);
+    const tx = await connection.getTransaction(sig, {
+      commitment: 'confirmed',
+      maxSupportedTransactionVersion: 0,
+    });
+    const logs = tx?.meta?.logMessages || [];
+    if (!logs.some(l => l.includes('is now immutable'))) {
+      throw new Error(`Expected an immutability log, got: ${logs.join('\n')}`);
+    }
+    const discriminator = createHash('sha256').update('event:AuthorityRenouncedEvent').digest().subarray(0, 8);
+    const event = logs
+      .filter(l => l.startsWith('Program data: '))
+      .map(l => Buffer.from(l.slice('Program data: '.length), 'base64'))
+      .find(data => data.subarray(0, 8).equals(discriminator));
+    if (!event) throw new Error('No AuthorityRenouncedEvent in the logs');
+    // 8 disc + 32 pool + 32 previous authority
+    if (!new PublicKey(event.subarray(8, 40)).equals(ctx.poolPDA)
+      || !new PublicKey(event.subarray(40, 72)).equals(ctx.payer.publicKey)) {
+      throw new Error('AuthorityRenouncedEvent has the wrong pool or authority');
+    }
 
-    // Deposit 2 SOL in rewards, wait for weight accumulation
-    const depositAmount = BigInt(2 * LAMPORTS_PER_SOL);
-    await ctx.depositRewards(depositAmount);
+    // AuthorityRenounced = error code 27 = 0x1b
+    await expectError('Settings after renounce', '0x1b',
+      () => ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null));
+    await expectError('Second renounce', '0x1b', () => ctx.renounceAuthority(ctx.payer));
+    await expectError('Transfer after renounce', '0x1b',
+      () => ctx.transferAuthority(ctx.payer, ctx.payer.publicKey));
+  });
 
-    console.log('    Waiting 5s for weight accumulation...');
-    await new Promise(r => setTimeout(r, 5000));
+  // =========================================================================
+  // Stake cap tests
+  // =========================================================================
 
-    // Claim — must succeed without overflow panic
-    const balBefore = BigInt(await ctx.getBalance(user.publicKey));
-    await ctx.claimRewards(user);
-    const balAfter = BigInt(await ctx.getBalance(user.publicKey));
-    const claimed = balAfter - balBefore;
-    console.log(`    Claimed: ${claimed} lamports`);
+  await test(`[${tokenProgramLabel}] UpdatePoolSettings: max_total_staked caps new stakes`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-    if (claimed > depositAmount) {
-      throw new Error(`Claimed ${claimed} exceeds deposited ${depositAmount}`);
-    }
-    console.log('    Claim <= deposited: OK');
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(3_000_000_000));
 
-    // Unstake fully — must succeed, token balance restored
-    await ctx.unstake(user, userToken, hugeAmount);
-    const tokenBalAfter = await ctx.getTokenBalance(userToken);
-    if (tokenBalAfter !== hugeAmount) {
-      throw new Error(`Token balance mismatch after unstake: expected ${hugeAmount}, got ${tokenBalAfter}`);
-    }
-    console.log('    Full unstake succeeded, token balance restored');
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
+
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { maxTotalStaked: BigInt(1_500_000_000) });
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    // StakeCapExceeded = error code 69 = 0x45
+    await expectError('Stake over the cap', '0x45', () => ctx.stake(alice, aliceToken, BigInt(500_000_001)));
+    await ctx.stake(alice, aliceToken, BigInt(500_000_000));
+
+    // Lowering the cap below total_staked is allowed; it only blocks new stakes
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { maxTotalStaked: BigInt(1_000_000_000) });
+    await expectError('Stake over a lowered cap', '0x45', () => ctx.stake(alice, aliceToken, BigInt(1)));
+    await ctx.unstake(alice, aliceToken, BigInt(1_000_000_000));
+
+    // 0 removes the cap
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { maxTotalStaked: BigInt(0) });
+    await ctx.stake(alice, aliceToken, BigInt(2_500_000_000));
   });
 
-  await test(`[${tokenProgramLabel}] Security: Claim capped at available pool balance (exact drain)`, async () => {
+  // =========================================================================
+  // Claim history tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] ClaimRewards: records last_claim_time and total claimed, even for a zero claim`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
     await ctx.initializePool(BigInt(60));
 
-    const user1 = Keypair.generate();
-    const user2 = Keypair.generate();
-    await airdropAndConfirm(connection, user1.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, user2.publicKey, 2 * LAMPORTS_PER_SOL);
-
-    const token1 = await ctx.createUserTokenAccount(user1.publicKey);
-    const token2 = await ctx.createUserTokenAccount(user2.publicKey);
-    await ctx.mintTokens(token1, BigInt(1_000_000_000));
-    await ctx.mintTokens(token2, BigInt(1_000_000_000));
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
 
-    // Both stake equally
-    await ctx.stake(user1, token1, BigInt(1_000_000_000));
-    await ctx.stake(user2, token2, BigInt(1_000_000_000));
+    const initial = await ctx.getClaimHistory(alice.publicKey);
+    if (initial.lastClaimTime !== BigInt(0) || initial.totalClaimed !== BigInt(0)) {
+      throw new Error(`Expected no claim history, got ${JSON.stringify(initial, (_, v) => v.toString())}`);
+    }
 
-    // Deposit tiny reward (50,000 lamports)
-    const tinyDeposit = BigInt(50_000);
-    await ctx.depositRewards(tinyDeposit);
+    // Nothing has been deposited: the claim pays nothing but is recorded
+    await ctx.claimRewards(alice);
+    const zero = await ctx.getClaimHistory(alice.publicKey);
+    if (zero.lastClaimTime === BigInt(0)) throw new Error('Zero-reward claim should set last_claim_time');
+    if (zero.totalClaimed !== BigInt(0)) throw new Error(`Expected 0 claimed, got ${zero.totalClaimed}`);
 
-    console.log('    Waiting 5s for weight accumulation...');
-    await new Promise(r => setTimeout(r, 5000));
+    await new Promise(r => setTimeout(r, 2000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    const before = await ctx.getBalance(alice.publicKey);
+    await ctx.claimRewards(alice);
+    const paid = (await ctx.getBalance(alice.publicKey)) - before;
 
-    // User1 claims first (gets a portion)
-    let totalClaimed = BigInt(0);
-    const bal1Before = BigInt(await ctx.getBalance(user1.publicKey));
-    try {
-      await ctx.claimRewards(user1);
-    } catch (e) {
-      // Claim may fail if entitlement is 0
+    const after = await ctx.getClaimHistory(alice.publicKey);
+    if (after.lastClaimTime < zero.lastClaimTime) throw new Error('last_claim_time went backwards');
+    // The claim transaction's fee is paid by ctx.payer, so the balance change is the payout
+    if (after.totalClaimed !== BigInt(paid) || after.totalClaimed === BigInt(0)) {
+      throw new Error(`Expected total claimed ${paid}, got ${after.totalClaimed}`);
     }
-    const bal1After = BigInt(await ctx.getBalance(user1.publicKey));
-    const claim1 = bal1After - bal1Before;
-    if (claim1 > BigInt(0)) totalClaimed += claim1;
-    console.log(`    User1 claimed: ${claim1} lamports`);
+  });
 
-    // User2 claims — entitlement may exceed remaining balance
-    const bal2Before = BigInt(await ctx.getBalance(user2.publicKey));
-    try {
-      await ctx.claimRewards(user2);
-    } catch (e) {
-      console.log('    User2 claim failed (expected if pool drained)');
-    }
-    const bal2After = BigInt(await ctx.getBalance(user2.publicKey));
-    const claim2 = bal2After - bal2Before;
-    if (claim2 > BigInt(0)) totalClaimed += claim2;
-    console.log(`    User2 claimed: ${claim2} lamports`);
+  // =========================================================================
+  // Minimum reward deposit tests
+  // =========================================================================
 
-    // Verify: pool balance >= rent-exempt minimum
-    const poolBalance = BigInt(await ctx.getBalance(ctx.poolPDA));
-    const poolAccountInfo = await connection.getAccountInfo(ctx.poolPDA);
-    const rentExempt = BigInt(await connection.getMinimumBalanceForRentExemption(poolAccountInfo!.data.length));
-    console.log(`    Pool balance: ${poolBalance}, rent-exempt min: ${rentExempt}`);
+  await test(`[${tokenProgramLabel}] DepositRewards: min_reward_deposit rejects dust deposits`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-    if (poolBalance < rentExempt) {
-      throw new Error(`Pool balance ${poolBalance} dropped below rent-exempt minimum ${rentExempt}`);
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    // 2e18 base units staked: 1 lamport is half a WAD-unit per share
+    const staked = BigInt('2000000000000000000');
+    await ctx.mintTokens(aliceToken, staked);
+    await ctx.stake(alice, aliceToken, staked);
+
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
+
+    // Without a minimum, a deposit too small to register per share stays
+    // pending instead of counting as synced
+    const before = await ctx.readPoolState();
+    await ctx.depositRewards(BigInt(1));
+    const pending = await ctx.readPoolState();
+    if (pending.lastSyncedLamports !== before.lastSyncedLamports) {
+      throw new Error(`Expected the dust deposit to stay pending, got last_synced_lamports ${pending.lastSyncedLamports}`);
     }
-    console.log('    Pool balance >= rent-exempt: OK');
 
-    // Verify: total claimed <= total deposited (with tolerance for rounding)
-    const tolerance = BigInt(100_000);
-    if (totalClaimed > tinyDeposit + tolerance) {
-      throw new Error(`Total claimed ${totalClaimed} exceeds deposited ${tinyDeposit} + tolerance`);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { minRewardDeposit: BigInt(1_000_000) });
+    // DepositBelowMinimum = error code 70 = 0x46
+    await expectError('Deposit below the minimum', '0x46', () => ctx.depositRewards(BigInt(999_999)));
+    await ctx.depositRewards(BigInt(1_000_000));
+    const after = await ctx.readPoolState();
+    if (after.lastSyncedLamports <= pending.lastSyncedLamports) {
+      throw new Error(`Expected the deposit to be distributed, got last_synced_lamports ${after.lastSyncedLamports}`);
     }
-    console.log(`    Total claimed (${totalClaimed}) <= deposited (${tinyDeposit}): OK`);
   });
 
-  await test(`[${tokenProgramLabel}] Security: Sandwich attack (stake between deposit and sync) gives no advantage`, async () => {
+  // =========================================================================
+  // SetPause tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] SetPause: blocks inflows, exits keep working`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
     await ctx.initializePool(BigInt(60));
 
-    // Honest user stakes and waits for weight
-    const honest = Keypair.generate();
-    await airdropAndConfirm(connection, honest.publicKey, 3 * LAMPORTS_PER_SOL);
-    const honestToken = await ctx.createUserTokenAccount(honest.publicKey);
-    await ctx.mintTokens(honestToken, BigInt(1_000_000_000));
-    await ctx.stake(honest, honestToken, BigInt(1_000_000_000));
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(2_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(100_000_000));
 
-    console.log('    Waiting 10s for honest staker weight...');
-    await new Promise(r => setTimeout(r, 10000));
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Deposit rewards via direct SOL transfer (no auto-sync)
-    const depositAmount = BigInt(LAMPORTS_PER_SOL);
-    await ctx.sendSolToPool(depositAmount);
+    // InvalidAuthority = error code 6 = 0x6
+    await expectError('Non-authority pause', '0x6', () => ctx.setPause(alice, true));
+    await ctx.setPause(ctx.payer, true);
+    if ((await ctx.viewPoolHealth() & HEALTH_PAUSED) === 0) {
+      throw new Error('Expected the paused health bit');
+    }
 
-    // Attacker stakes immediately after deposit but BEFORE syncRewards
-    const attacker = Keypair.generate();
-    await airdropAndConfirm(connection, attacker.publicKey, 3 * LAMPORTS_PER_SOL);
-    const attackerToken = await ctx.createUserTokenAccount(attacker.publicKey);
-    await ctx.mintTokens(attackerToken, BigInt(1_000_000_000));
-    await ctx.stake(attacker, attackerToken, BigInt(1_000_000_000));
+    // PoolPaused = error code 71 = 0x47
+    await expectError('Stake while paused', '0x47', () => ctx.stake(alice, aliceToken, BigInt(1_000_000_000)));
+    await expectError('Deposit while paused', '0x47', () => ctx.depositRewards(BigInt(100_000_000)));
+    await expectError('Renounce while paused', '0x47', () => ctx.renounceAuthority(ctx.payer));
 
-    // Now sync rewards — attacker has ~0 weight at this point
+    // SOL sent straight to the pool is still synced, and stakers can exit
+    const before = await ctx.readPoolState();
+    await ctx.sendSolToPool(BigInt(50_000_000));
     await ctx.syncRewards();
-
-    // Both claim
-    const honestBalBefore = BigInt(await ctx.getBalance(honest.publicKey));
-    await ctx.claimRewards(honest);
-    const honestBalAfter = BigInt(await ctx.getBalance(honest.publicKey));
-    const honestClaimed = honestBalAfter - honestBalBefore;
-
-    let attackerClaimed = BigInt(0);
-    const attackerBalBefore = BigInt(await ctx.getBalance(attacker.publicKey));
-    try {
-      await ctx.claimRewards(attacker);
-    } catch (e) {
-      console.log('    Attacker claim failed (expected — zero weight)');
+    const after = await ctx.readPoolState();
+    if (after.lastSyncedLamports - before.lastSyncedLamports !== BigInt(50_000_000)) {
+      throw new Error(`Expected the transfer to be synced while paused, got ${after.lastSyncedLamports}`);
     }
-    const attackerBalAfter = BigInt(await ctx.getBalance(attacker.publicKey));
-    attackerClaimed = attackerBalAfter - attackerBalBefore;
+    await ctx.claimRewards(alice);
+    await ctx.unstake(alice, aliceToken, BigInt(1_000_000_000));
 
-    const totalClaimed = (honestClaimed > BigInt(0) ? honestClaimed : BigInt(0))
-                       + (attackerClaimed > BigInt(0) ? attackerClaimed : BigInt(0));
+    await ctx.setPause(ctx.payer, false);
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+  });
 
-    console.log(`    Honest claimed:   ${honestClaimed} lamports`);
-    console.log(`    Attacker claimed: ${attackerClaimed} lamports`);
+  // =========================================================================
+  // InstantUnstake tests
+  // =========================================================================
 
-    // Verify: attacker gets near-zero, honest gets >75% of rewards
-    if (honestClaimed <= BigInt(0)) {
-      throw new Error('Honest staker should have received rewards');
-    }
+  await test(`[${tokenProgramLabel}] InstantUnstake: 500 bps penalty skips the cooldown, vault stays consistent`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(86_400));
 
-    if (totalClaimed > BigInt(0)) {
-      const honestShare = Number(honestClaimed) * 100 / Number(totalClaimed);
-      console.log(`    Honest share: ${honestShare.toFixed(1)}%`);
-      if (honestShare < 75) {
-        throw new Error(`Honest staker share too low: ${honestShare.toFixed(1)}% (expected >75%)`);
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, bob.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    await ctx.mintTokens(bobToken, BigInt(1_000_000_000));
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    await ctx.stake(bob, bobToken, BigInt(1_000_000_000));
+
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
       }
+      throw new Error(`${label} should fail`);
+    };
+
+    // InstantUnstakeDisabled = error code 72 = 0x48
+    await expectError('Instant unstake while disabled', '0x48',
+      () => ctx.instantUnstake(alice, aliceToken, BigInt(400_000_000)));
+    // SettingExceedsMaximum = error code 29 = 0x1d
+    await expectError('Penalty above 100%', '0x1d',
+      () => ctx.updatePoolSettings(ctx.payer, null, null, null, { earlyExitPenaltyBps: BigInt(10_001) }));
+
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { earlyExitPenaltyBps: BigInt(500) });
+    const before = await ctx.getTokenBalance(aliceToken);
+    await ctx.instantUnstake(alice, aliceToken, BigInt(400_000_000));
+    const received = (await ctx.getTokenBalance(aliceToken)) - before;
+    if (received !== BigInt(380_000_000)) {
+      throw new Error(`Expected 380000000 tokens after a 5% penalty, got ${received}`);
     }
 
-    // Verify: conservation holds
-    const tolerance = BigInt(100_000);
-    if (totalClaimed > depositAmount + tolerance) {
-      throw new Error(`Conservation violated: claimed ${totalClaimed} > deposited ${depositAmount} + tolerance`);
+    // The whole amount left total_staked; the penalty stays in the vault
+    const pool = await ctx.readPoolState();
+    if (pool.totalStaked !== BigInt(1_600_000_000)) {
+      throw new Error(`Expected total_staked 1600000000, got ${pool.totalStaked}`);
+    }
+    const vault = await ctx.getTokenBalance(ctx.tokenVaultPDA);
+    if (vault !== BigInt(1_620_000_000)) {
+      throw new Error(`Expected 20000000 penalty tokens left in the vault, got ${vault}`);
+    }
+    await ctx.verifyVaultConsistency();
+    if (await ctx.viewPoolHealth() !== HEALTH_ALL_GREEN) {
+      throw new Error('Expected an all-green pool after an instant unstake');
     }
-    console.log('    Sandwich attack mitigated: OK');
   });
 
-  // ─── Add-Stake Tests ─────────────────────────────────────────────────────────
-  //
-  // On add-stake, maturity percentage is preserved (depends only on start time).
-  // exp_start_factor doesn't change, claimed_rewards_wad doesn't change.
-  // reward_debt gets a fresh snapshot added for new tokens only.
-  // Pending rewards stay exactly the same — user can claim separately.
-
-  console.log('\n--- Add-Stake Tests ---\n');
+  // =========================================================================
+  // Two-step authority transfer tests
+  // =========================================================================
 
-  // Test: exp_start_factor and pending rewards unchanged after add-stake
-  await test(`[${tokenProgramLabel}] AddStake: maturity preserved — exp_start_factor unchanged`, async () => {
+  await test(`[${tokenProgramLabel}] ProposeAuthority: only the proposed key can accept`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau
+    await ctx.initializePool(BigInt(2592000));
 
-    const staker = Keypair.generate();
-    await airdropAndConfirm(connection, staker.publicKey, 3 * LAMPORTS_PER_SOL);
-    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
-    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
+    const stale = Keypair.generate();
+    const newAuth = Keypair.generate();
+    await airdropAndConfirm(connection, stale.publicKey, LAMPORTS_PER_SOL);
+    await airdropAndConfirm(connection, newAuth.publicKey, LAMPORTS_PER_SOL);
 
-    // Stake 1B tokens
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Wait for partial maturity
-    console.log('    Waiting 15s for partial weight...');
-    await new Promise(r => setTimeout(r, 15000));
+    // NoPendingAuthority = error code 73 = 0x49
+    await expectError('Accept with nothing pending', '0x49', () => ctx.acceptAuthority(newAuth));
+    // InvalidAuthority = error code 6 = 0x6
+    await expectError('Non-authority proposal', '0x6', () => ctx.proposeAuthority(newAuth, newAuth.publicKey));
 
-    // Deposit 2 SOL of rewards
-    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
+    // A new proposal replaces the stale one
+    await ctx.proposeAuthority(ctx.payer, stale.publicKey);
+    await ctx.proposeAuthority(ctx.payer, newAuth.publicKey);
+    await expectError('Stale proposal accepts', '0x6', () => ctx.acceptAuthority(stale));
 
-    // Read state before add-stake
-    const stateBefore = await ctx.readUserStakeState(staker.publicKey);
-    const expBefore = stateBefore.expStartFactor;
-    const claimedBefore = stateBefore.claimedRewardsWad;
+    // Until accepted, the current authority stays in charge
+    await ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null);
 
-    // Measure claimable before add-stake
-    let bal = BigInt(await ctx.getBalance(staker.publicKey));
-    await ctx.claimRewards(staker);
-    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
-    const claimBefore = balAfter - bal;
-    console.log(`    Claim before add-stake: ${claimBefore} lamports`);
+    await ctx.acceptAuthority(newAuth);
+    await expectError('Old authority after accept', '0x6', () => ctx.updatePoolSettings(ctx.payer, BigInt(100), null, null));
+    await ctx.updatePoolSettings(newAuth, BigInt(100), null, null);
+    // Accepting clears the proposal
+    await expectError('Accept twice', '0x49', () => ctx.acceptAuthority(newAuth));
+
+    // Proposing the default pubkey cancels, it never renounces
+    await ctx.proposeAuthority(newAuth, stale.publicKey);
+    await ctx.proposeAuthority(newAuth, PublicKey.default);
+    await expectError('Accept after cancel', '0x49', () => ctx.acceptAuthority(stale));
+    await ctx.updatePoolSettings(newAuth, BigInt(200), null, null);
+
+    // CancelAuthorityProposal withdraws a proposal: only the authority may, and only while one is pending
+    await expectError('Cancel with nothing pending', '0x49', () => ctx.cancelAuthorityProposal(newAuth));
+    await ctx.proposeAuthority(newAuth, stale.publicKey);
+    await expectError('Non-authority cancel', '0x6', () => ctx.cancelAuthorityProposal(stale));
+    await ctx.cancelAuthorityProposal(newAuth);
+    await expectError('Accept after CancelAuthorityProposal', '0x49', () => ctx.acceptAuthority(stale));
+    await ctx.updatePoolSettings(newAuth, BigInt(300), null, null);
+  });
 
-    // Add 1B more tokens
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
+  // =========================================================================
+  // Pool token program tests
+  // =========================================================================
 
-    // Read state after add-stake
-    const stateAfter = await ctx.readUserStakeState(staker.publicKey);
-    console.log(`    exp_start_factor: before=${expBefore}, after=${stateAfter.expStartFactor}`);
-    console.log(`    amount: ${stateBefore.amount} → ${stateAfter.amount}`);
+  await test(`[${tokenProgramLabel}] Stake: the other token program is rejected`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(60));
 
-    // exp_start_factor MUST be unchanged
-    if (stateAfter.expStartFactor !== expBefore) {
-      throw new Error(`exp_start_factor changed! ${expBefore} → ${stateAfter.expStartFactor}`);
-    }
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
 
-    // Amount must have increased
-    if (stateAfter.amount !== BigInt(2_000_000_000)) {
-      throw new Error(`Expected 2B, got ${stateAfter.amount}`);
-    }
+    const otherProgram = tokenProgramId.equals(TOKEN_PROGRAM_ID) ? TOKEN_2022_PROGRAM_ID : TOKEN_PROGRAM_ID;
+    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, alice.publicKey);
+    const ix = createStakeInstruction(
+      ctx.poolPDA,
+      userStakePDA,
+      ctx.tokenVaultPDA,
+      aliceToken,
+      ctx.mint,
+      alice.publicKey,
+      BigInt(1_000_000_000),
+      otherProgram,
+    );
 
-    console.log('    Maturity preserved: OK');
+    // InvalidTokenProgram = error code 31 = 0x1f
+    try {
+      await sendAndConfirmTransaction(connection, new Transaction().add(ix), [alice]);
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1f')) {
+        throw new Error(`Expected InvalidTokenProgram (0x1f), got: ${errMsg}`);
+      }
+      await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+      return;
+    }
+    throw new Error('Stake through the other token program should fail');
   });
 
-  // Test: Pending rewards unchanged by add-stake (claim before == claim after)
-  await test(`[${tokenProgramLabel}] AddStake: pending rewards unchanged by add-stake`, async () => {
+  await test(`[${tokenProgramLabel}] InitializePool: initial settings apply with UpdatePoolSettings' limits`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau
-
-    const staker = Keypair.generate();
-    await airdropAndConfirm(connection, staker.publicKey, 5 * LAMPORTS_PER_SOL);
-    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
-    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
-
-    // Stake 1B tokens
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
-
-    // Wait for partial maturity
-    console.log('    Waiting 15s for partial weight...');
-    await new Promise(r => setTimeout(r, 15000));
 
-    // Deposit 2 SOL of rewards
-    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
+    // SettingExceedsMaximum = error code 29 = 0x1d (lock duration above 365 days)
+    try {
+      await ctx.initializePool(BigInt(60), false, {
+        minStakeAmount: BigInt(0),
+        lockDurationSeconds: BigInt(365 * 86400 + 1),
+        unstakeCooldownSeconds: BigInt(0),
+      });
+      throw new Error('Initial lock duration above the cap should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1d')) {
+        throw new Error(`Expected SettingExceedsMaximum (0x1d), got: ${errMsg}`);
+      }
+    }
 
-    // Add 1B more tokens (pending should stay the same)
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
+    await ctx.initializePool(BigInt(60), true, {
+      minStakeAmount: BigInt(1_000_000),
+      lockDurationSeconds: BigInt(0),
+      unstakeCooldownSeconds: BigInt(0),
+    });
 
-    // Claim — should get the same vested rewards as before the add-stake
-    let bal = BigInt(await ctx.getBalance(staker.publicKey));
-    await ctx.claimRewards(staker);
-    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
-    const claimAfterAdd = balAfter - bal;
-    console.log(`    Claim after add-stake: ${claimAfterAdd} lamports`);
+    const alice = Keypair.generate();
+    await airdropAndConfirm(connection, alice.publicKey, LAMPORTS_PER_SOL);
+    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
+    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
 
-    // Should be positive (rewards vested during the 15s maturity period)
-    if (claimAfterAdd < BigInt(10_000)) {
-      throw new Error(`Pending rewards lost on add-stake! Got ${claimAfterAdd}`);
+    // BelowMinimumStake = error code 21 = 0x15
+    try {
+      await ctx.stake(alice, aliceToken, BigInt(999_999));
+      throw new Error('Stake below the initial minimum should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x15')) {
+        throw new Error(`Expected BelowMinimumStake (0x15), got: ${errMsg}`);
+      }
     }
-
-    console.log('    Pending rewards preserved: OK');
+    await ctx.stake(alice, aliceToken, BigInt(1_000_000));
   });
 
-  // Test: SOL continues to mature after add-stake
-  await test(`[${tokenProgramLabel}] AddStake: SOL continues to mature after add-stake`, async () => {
+  await test(`[${tokenProgramLabel}] CompleteUnstake: pays rewards and tokens in one instruction, cheaper than claiming first`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau
-
-    const staker = Keypair.generate();
-    await airdropAndConfirm(connection, staker.publicKey, 5 * LAMPORTS_PER_SOL);
-    const stakerToken = await ctx.createUserTokenAccount(staker.publicKey);
-    await ctx.mintTokens(stakerToken, BigInt(2_000_000_000));
-
-    // Stake 1B tokens
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
-
-    // Wait for partial maturity
-    console.log('    Waiting 15s for partial maturity...');
-    await new Promise(r => setTimeout(r, 15000));
-
-    // Deposit 2 SOL
-    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
+    await ctx.initializePool(BigInt(10), false, {
+      minStakeAmount: BigInt(0),
+      lockDurationSeconds: BigInt(0),
+      unstakeCooldownSeconds: BigInt(5),
+    });
 
-    // Claim vested rewards
-    await ctx.claimRewards(staker);
+    const alice = Keypair.generate();
+    const bob = Keypair.generate();
+    const tokens: PublicKey[] = [];
+    for (const user of [alice, bob]) {
+      await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+      const token = await ctx.createUserTokenAccount(user.publicKey);
+      await ctx.mintTokens(token, BigInt(1_000_000_000));
+      await ctx.stake(user, token, BigInt(1_000_000_000));
+      tokens.push(token);
+    }
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    await ctx.requestUnstake(alice, BigInt(1_000_000_000));
+    await ctx.requestUnstake(bob, BigInt(1_000_000_000));
+    console.log('    Waiting 6s for cooldown...');
+    await new Promise(r => setTimeout(r, 6000));
 
-    // Add 1B more tokens
-    await ctx.stake(staker, stakerToken, BigInt(1_000_000_000));
+    const unitsOf = async (sig: string): Promise<number> => {
+      const tx = await connection.getTransaction(sig, {
+        commitment: 'confirmed',
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx?.meta?.computeUnitsConsumed ?? 0;
+    };
 
-    // Wait 30s for more maturity
-    console.log('    Waiting 30s for maturation...');
-    await new Promise(r => setTimeout(r, 30000));
+    // Alice claims, then completes: two transactions
+    const twoStep =
+      (await unitsOf(await ctx.claimRewards(alice))) +
+      (await unitsOf(await ctx.completeUnstake(alice, tokens[0])));
 
-    // Claim — should get newly matured rewards
-    let bal = BigInt(await ctx.getBalance(staker.publicKey));
-    await ctx.claimRewards(staker);
-    let balAfter = BigInt(await ctx.getBalance(staker.publicKey));
-    const matureClaim = balAfter - bal;
-    console.log(`    Claim after 30s maturation: ${matureClaim} lamports`);
+    // Bob only completes; the payer covers fees, so his lamports gain is the reward
+    const lamportsBefore = await ctx.getBalance(bob.publicKey);
+    const oneStep = await unitsOf(await ctx.completeUnstake(bob, tokens[1]));
+    const rewards = (await ctx.getBalance(bob.publicKey)) - lamportsBefore;
+    console.log(`    Claim + CompleteUnstake: ${twoStep} CU, CompleteUnstake alone: ${oneStep} CU`);
 
-    if (matureClaim < BigInt(100_000)) {
-      throw new Error(`SOL not maturing after add-stake! Got ${matureClaim}`);
+    if (rewards <= 0) throw new Error('CompleteUnstake did not pay the pending rewards');
+    if ((await ctx.getTokenBalance(tokens[1])) !== BigInt(1_000_000_000)) {
+      throw new Error('CompleteUnstake did not return the tokens');
     }
-
-    console.log('    SOL continues to mature: OK');
+    if (oneStep === 0 || twoStep === 0) throw new Error('Compute units not reported');
+    if (oneStep >= twoStep) {
+      throw new Error(`CompleteUnstake alone (${oneStep} CU) should cost less than ${twoStep} CU`);
+    }
+    const pool = await ctx.readPoolState();
+    if (pool.totalStaked !== 0n) throw new Error(`Expected total_staked 0, got ${pool.totalStaked}`);
   });
 
-  // Test: Conservation — add-stake doesn't create or destroy rewards
-  await test(`[${tokenProgramLabel}] AddStake: conservation — total claimed ≤ total deposited`, async () => {
+  await test(`[${tokenProgramLabel}] AmendUnstakeRequest: lowering keeps the wait, raising restarts it`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau
-
-    const alice = Keypair.generate();
-    const bob = Keypair.generate();
-    await airdropAndConfirm(connection, alice.publicKey, 3 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, bob.publicKey, 3 * LAMPORTS_PER_SOL);
+    await ctx.initializePool(BigInt(60), false, {
+      minStakeAmount: BigInt(0),
+      lockDurationSeconds: BigInt(0),
+      unstakeCooldownSeconds: BigInt(5),
+    });
 
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    const bobToken = await ctx.createUserTokenAccount(bob.publicKey);
-    await ctx.mintTokens(aliceToken, BigInt(2_000_000_000));
-    await ctx.mintTokens(bobToken, BigInt(1_000_000_000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Both stake 1B tokens
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
-    await ctx.stake(bob, bobToken, BigInt(1_000_000_000));
+    const expectError = async (label: string, code: string, action: () => Promise<unknown>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Wait for partial maturity
-    console.log('    Waiting 15s for partial weight...');
-    await new Promise(r => setTimeout(r, 15000));
+    // NoPendingUnstakeRequest = error code 25 = 0x19
+    await expectError('Amend without a request', 'custom program error: 0x19',
+      () => ctx.amendUnstakeRequest(user, BigInt(400_000_000)));
 
-    // Deposit 2 SOL
-    const depositAmount = BigInt(2 * LAMPORTS_PER_SOL);
-    await ctx.depositRewards(depositAmount);
+    await ctx.requestUnstake(user, BigInt(600_000_000));
+    const requested = await ctx.readUserStakeState(user.publicKey);
+    await new Promise(r => setTimeout(r, 2000));
 
-    // Alice stakes 1B more (no auto-claim — pending stays the same)
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    // Lower: same request time
+    await ctx.amendUnstakeRequest(user, BigInt(400_000_000));
+    let state = await ctx.readUserStakeState(user.publicKey);
+    if (state.unstakeRequestAmount !== BigInt(400_000_000)) {
+      throw new Error(`Expected 400000000 requested, got ${state.unstakeRequestAmount}`);
+    }
+    if (state.unstakeRequestTime !== requested.unstakeRequestTime) {
+      throw new Error('Lowering the request moved its time');
+    }
 
-    // Wait for more maturity
-    console.log('    Waiting 30s for maturity...');
-    await new Promise(r => setTimeout(r, 30000));
+    // InsufficientStakeBalance = error code 10 = 0xa
+    await expectError('Amend above the stake', 'custom program error: 0xa',
+      () => ctx.amendUnstakeRequest(user, BigInt(1_000_000_001)));
 
-    // Both claim
-    let aliceTotal = BigInt(0);
-    let bal = BigInt(await ctx.getBalance(alice.publicKey));
-    await ctx.claimRewards(alice);
-    let balAfter = BigInt(await ctx.getBalance(alice.publicKey));
-    aliceTotal += (balAfter - bal);
+    // Raise: the whole request starts a new cooldown
+    await ctx.amendUnstakeRequest(user, BigInt(900_000_000));
+    state = await ctx.readUserStakeState(user.publicKey);
+    if (state.unstakeRequestAmount !== BigInt(900_000_000)) {
+      throw new Error(`Expected 900000000 requested, got ${state.unstakeRequestAmount}`);
+    }
+    if (state.unstakeRequestTime <= requested.unstakeRequestTime) {
+      throw new Error('Raising the request kept the old cooldown');
+    }
 
-    bal = BigInt(await ctx.getBalance(bob.publicKey));
-    await ctx.claimRewards(bob);
-    balAfter = BigInt(await ctx.getBalance(bob.publicKey));
-    const bobTotal = balAfter - bal;
+    console.log('    Waiting 6s for cooldown...');
+    await new Promise(r => setTimeout(r, 6000));
+    await ctx.completeUnstake(user, userToken);
+    const balance = await ctx.getTokenBalance(userToken);
+    if (balance !== BigInt(900_000_000)) {
+      throw new Error(`Expected 900000000 tokens back, got ${balance}`);
+    }
+  });
 
-    // Both fully unstake
-    bal = BigInt(await ctx.getBalance(alice.publicKey));
-    await ctx.unstake(alice, aliceToken, BigInt(2_000_000_000));
-    balAfter = BigInt(await ctx.getBalance(alice.publicKey));
-    aliceTotal += (balAfter - bal);
+  await test(`[${tokenProgramLabel}] SyncRewardsBatch: syncs funded pools, skips pools without stakers`, async () => {
+    const payer = Keypair.generate();
+    const staked = new TestContext(connection, payer, programAuthority, tokenProgramId);
+    await staked.setup();
+    await staked.createMint(9);
+    await staked.initializePool(BigInt(60));
+    const empty = new TestContext(connection, payer, programAuthority, tokenProgramId);
+    await empty.createMint(9);
+    await empty.initializePool(BigInt(60));
 
-    bal = BigInt(await ctx.getBalance(bob.publicKey));
-    await ctx.unstake(bob, bobToken, BigInt(1_000_000_000));
-    balAfter = BigInt(await ctx.getBalance(bob.publicKey));
-    const bobFinal = bobTotal + (balAfter - bal);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await staked.createUserTokenAccount(user.publicKey);
+    await staked.mintTokens(userToken, BigInt(1_000_000_000));
+    await staked.stake(user, userToken, BigInt(1_000_000_000));
 
-    const totalClaimed = aliceTotal + bobFinal;
+    await staked.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 10));
+    await empty.sendSolToPool(BigInt(LAMPORTS_PER_SOL / 10));
 
-    console.log(`    Alice total received: ${aliceTotal} lamports`);
-    console.log(`    Bob total received:   ${bobFinal} lamports`);
-    console.log(`    Total claimed:        ${totalClaimed} lamports`);
+    const pools = [staked.poolPDA, empty.poolPDA];
+    const count = await simulateSyncRewardsBatch(connection, payer, pools);
+    if (count !== 1n) throw new Error(`Expected 1 pool synced, got ${count}`);
 
-    // Conservation: total claimed must not exceed deposited
-    const tolerance = BigInt(100_000);
-    if (totalClaimed > depositAmount + tolerance) {
-      throw new Error(`Conservation violated: claimed ${totalClaimed} > deposited ${depositAmount}`);
+    const emptyBefore = await empty.readPoolState();
+    await sendAndConfirmTransaction(connection, new Transaction().add(createSyncRewardsBatchInstruction(pools)), [payer]);
+    if ((await staked.readPoolState()).accRewardPerWeightedShare === 0n) {
+      throw new Error('Staked pool was not synced');
+    }
+    if ((await empty.readPoolState()).lastSyncedLamports !== emptyBefore.lastSyncedLamports) {
+      throw new Error('Pool without stakers should keep its rewards pending');
     }
 
-    console.log('    Conservation maintained: OK');
+    // Nothing new: the batch succeeds and syncs nothing
+    const again = await simulateSyncRewardsBatch(connection, payer, pools);
+    if (again !== 0n) throw new Error(`Expected 0 pools synced, got ${again}`);
+
+    // A non-pool account fails the whole batch
+    let failed = false;
+    try {
+      await simulateSyncRewardsBatch(connection, payer, [staked.poolPDA, staked.tokenVaultPDA]);
+    } catch (e) {
+      failed = true;
+    }
+    if (!failed) throw new Error('Batch with a non-pool account should fail');
   });
 
-  // Test: Dust-stake provides no advantage over simply claiming
-  await test(`[${tokenProgramLabel}] AddStake: dust-stake yields no extra rewards vs plain claim`, async () => {
+  // =========================================================================
+  // DepositRewardsStreamed tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] DepositRewardsStreamed: releases over time, never more than deposited`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // 60s tau
+    await ctx.initializePool(60n);
 
-    // Two stakers: one will dust-stake+claim, the other will just claim
-    const dustStaker = Keypair.generate();
-    const plainStaker = Keypair.generate();
-    await airdropAndConfirm(connection, dustStaker.publicKey, 5 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, plainStaker.publicKey, 5 * LAMPORTS_PER_SOL);
-    const dustToken = await ctx.createUserTokenAccount(dustStaker.publicKey);
-    const plainToken = await ctx.createUserTokenAccount(plainStaker.publicKey);
-    await ctx.mintTokens(dustToken, BigInt(2_000_000_000));
-    await ctx.mintTokens(plainToken, BigInt(1_000_000_000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Both stake the same amount at the same time
-    await ctx.stake(dustStaker, dustToken, BigInt(1_000_000_000));
-    await ctx.stake(plainStaker, plainToken, BigInt(1_000_000_000));
+    // InvalidStreamDuration = error code 78 = 0x4e
+    for (const duration of [0n, BigInt(365 * 24 * 60 * 60 + 1)]) {
+      try {
+        await ctx.depositRewardsStreamed(BigInt(LAMPORTS_PER_SOL / 10), duration);
+        throw new Error(`A ${duration}s stream should fail`);
+      } catch (e: any) {
+        if (!e.message.includes('custom program error: 0x4e')) {
+          throw new Error(`Expected InvalidStreamDuration (0x4e), got: ${e.message}`);
+        }
+      }
+    }
 
-    // Wait for maturity
-    console.log('    Waiting 30s for maturity...');
-    await new Promise(r => setTimeout(r, 30000));
+    const amount = BigInt(LAMPORTS_PER_SOL / 10);
+    const before = await ctx.readPoolState();
+    await ctx.depositRewardsStreamed(amount, 120n);
 
-    // Deposit 2 SOL
-    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
+    // Only part of it is out a few seconds in
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.syncRewards();
+    const partial = (await ctx.readPoolState()).lastSyncedLamports - before.lastSyncedLamports;
+    if (partial === 0n || partial >= amount / 2n) {
+      throw new Error(`Expected a small share released after 5s, got ${partial} of ${amount}`);
+    }
 
-    // Both claim initial rewards
-    await ctx.claimRewards(dustStaker);
-    await ctx.claimRewards(plainStaker);
+    // A later sync releases more, still within the deposit
+    await new Promise(r => setTimeout(r, 5000));
+    await ctx.syncRewards();
+    const later = (await ctx.readPoolState()).lastSyncedLamports - before.lastSyncedLamports;
+    if (later <= partial || later > amount) {
+      throw new Error(`Expected ${partial} < released <= ${amount}, got ${later}`);
+    }
+    await ctx.verifyVaultConsistency();
+  });
 
-    // Dust-staker: stake dust then claim; plain-staker: just claim
-    let dustTotal = 0n;
-    let plainTotal = 0n;
-    for (let i = 0; i < 3; i++) {
-      await ctx.stake(dustStaker, dustToken, BigInt(1)); // dust
+  // =========================================================================
+  // Claim return data tests
+  // =========================================================================
 
-      let bal = BigInt(await ctx.getBalance(dustStaker.publicKey));
-      await ctx.claimRewards(dustStaker);
-      let balAfter = BigInt(await ctx.getBalance(dustStaker.publicKey));
-      dustTotal += balAfter - bal;
+  await test(`[${tokenProgramLabel}] ClaimRewards: returns the lamports paid, 0 when nothing is pending`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(60n);
 
-      bal = BigInt(await ctx.getBalance(plainStaker.publicKey));
-      await ctx.claimRewards(plainStaker);
-      balAfter = BigInt(await ctx.getBalance(plainStaker.publicKey));
-      plainTotal += balAfter - bal;
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-      console.log(`    Round ${i + 1}: dust=${dustTotal}, plain=${plainTotal}`);
+    const claimedBy = async (sig: string): Promise<bigint> => {
+      const tx = await connection.getTransaction(sig, {
+        commitment: 'confirmed',
+        maxSupportedTransactionVersion: 0,
+      });
+      const returnData = tx?.meta?.returnData;
+      if (!returnData || returnData.programId !== PROGRAM_ID.toBase58()) {
+        throw new Error(`Expected return data from the program, got ${JSON.stringify(returnData)}`);
+      }
+      return Buffer.from(returnData.data[0], 'base64').readBigUInt64LE(0);
+    };
+
+    // Nothing pending yet: still returns 0
+    const empty = await claimedBy(await ctx.claimRewards(user));
+    if (empty !== 0n) {
+      throw new Error(`Expected 0 from an empty claim, got ${empty}`);
     }
 
-    console.log(`    Dust-staker total: ${dustTotal}, Plain-staker total: ${plainTotal}`);
+    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL / 10));
+    await new Promise(r => setTimeout(r, 5000));
 
-    // Dust-staker should NOT get meaningfully more than plain-staker
-    // (small difference possible due to slightly different share from dust amount)
-    const diff = dustTotal - plainTotal;
-    const threshold = BigInt(LAMPORTS_PER_SOL / 100); // 0.01 SOL tolerance
-    if (diff > threshold) {
-      throw new Error(`Dust-stake advantage: ${diff} lamports — exploit not blocked!`);
+    const balanceBefore = BigInt(await ctx.getBalance(user.publicKey));
+    const claimed = await claimedBy(await ctx.claimRewards(user));
+    const received = BigInt(await ctx.getBalance(user.publicKey)) - balanceBefore;
+    if (claimed === 0n || claimed !== received) {
+      throw new Error(`Return data ${claimed} should match the ${received} lamports received`);
+    }
+    const state = await ctx.readUserStakeState(user.publicKey);
+    if (state.totalRewardsClaimed !== claimed) {
+      throw new Error(`Expected total_rewards_claimed ${claimed}, got ${state.totalRewardsClaimed}`);
     }
-
-    console.log('    Dust-stake provides no advantage: OK');
   });
 
-  // ========== StakeOnBehalf Tests ==========
+  // =========================================================================
+  // ClosePool tests
+  // =========================================================================
 
-  await test(`[${tokenProgramLabel}] StakeOnBehalf: stake on behalf of another user`, async () => {
+  await test(`[${tokenProgramLabel}] ClosePool: only once drained, then stakes can still close`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // tau = 60s for fast maturity
+    await ctx.initializePool(60n);
 
-    // User A (staker) has tokens, User B (beneficiary) is just a pubkey
-    const userA = Keypair.generate();
-    const userB = Keypair.generate();
-    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
-    const stakeAmount = BigInt(1_000_000_000);
-    await ctx.mintTokens(userAToken, stakeAmount);
+    const destination = Keypair.generate().publicKey;
+    const expectError = async (label: string, code: string, fn: () => Promise<unknown>) => {
+      try {
+        await fn();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // A stakes on behalf of B
-    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, stakeAmount);
+    // PoolNotEmpty = error code 79 = 0x4f
+    await expectError('Close with stakers', '0x4f', () => ctx.closePool(ctx.payer, destination));
 
-    // Verify B's stake PDA exists with correct amount and owner=B
-    const [beneficiaryStakePDA] = deriveUserStakePDA(ctx.poolPDA, userB.publicKey);
-    const stakeInfo = await connection.getAccountInfo(beneficiaryStakePDA);
-    if (!stakeInfo) throw new Error('Beneficiary stake account not created');
-    if (stakeInfo.data.length === 0) throw new Error('Beneficiary stake data empty');
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
 
-    // Read stake state: owner is at offset 8 (discriminator) = bytes 8..40
-    const ownerBytes = stakeInfo.data.slice(8, 40);
-    const ownerKey = new PublicKey(ownerBytes);
-    if (!ownerKey.equals(userB.publicKey)) {
-      throw new Error(`Stake owner should be B (${userB.publicKey}), got ${ownerKey}`);
-    }
+    // InvalidAuthority = error code 6 = 0x6
+    const mallory = Keypair.generate();
+    await airdropAndConfirm(connection, mallory.publicKey, LAMPORTS_PER_SOL);
+    await expectError('Non-authority close', '0x6', () => ctx.closePool(mallory, destination));
 
-    // Read amount at offset 72 (u64 LE)
-    const stakedAmount = stakeInfo.data.readBigUInt64LE(72);
-    if (stakedAmount !== stakeAmount) {
-      throw new Error(`Staked amount should be ${stakeAmount}, got ${stakedAmount}`);
-    }
+    const poolRent = await ctx.getBalance(ctx.poolPDA);
+    const vaultRent = await ctx.getBalance(ctx.tokenVaultPDA);
+    await ctx.closePool(ctx.payer, destination);
 
-    // Verify A's token account was debited
-    const aBalance = await ctx.getTokenBalance(userAToken);
-    if (aBalance !== 0n) {
-      throw new Error(`A should have 0 tokens, got ${aBalance}`);
+    if (await connection.getAccountInfo(ctx.poolPDA) !== null) throw new Error('Pool should be closed');
+    if (await connection.getAccountInfo(ctx.tokenVaultPDA) !== null) throw new Error('Vault should be closed');
+    const received = await ctx.getBalance(destination);
+    if (received !== poolRent + vaultRent) {
+      throw new Error(`Destination got ${received}, expected ${poolRent + vaultRent}`);
     }
 
-    console.log('    Stake on behalf created successfully, owner = B');
+    // The empty stake account is not stranded
+    await ctx.closeStakeAccount(user);
+    const [userStakePDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+    if (await connection.getAccountInfo(userStakePDA) !== null) throw new Error('Stake should be closed');
   });
 
-  await test(`[${tokenProgramLabel}] StakeOnBehalf: beneficiary can claim rewards`, async () => {
+  // =========================================================================
+  // InitializeUserStake tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] InitializeUserStake: positions are independent`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // tau = 60s
+    await ctx.initializePool(60n);
 
-    const userA = Keypair.generate();
-    const userB = Keypair.generate();
-    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(3_000_000_000));
 
-    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
-    const stakeAmount = BigInt(1_000_000_000);
-    await ctx.mintTokens(userAToken, stakeAmount);
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.initializeUserStake(user, 1);
+    const [positionPDA] = deriveUserStakePositionPDA(ctx.poolPDA, user.publicKey, 1);
+    const [defaultPDA] = deriveUserStakePDA(ctx.poolPDA, user.publicKey);
+
+    const sendWithPosition = async (ix: TransactionInstruction) =>
+      sendAndConfirmTransaction(connection, new Transaction().add(ix), [ctx.payer, user]);
+    await sendWithPosition(createStakeInstruction(
+      ctx.poolPDA, positionPDA, ctx.tokenVaultPDA, userToken, ctx.mint, user.publicKey,
+      BigInt(2_000_000_000), tokenProgramId,
+    ));
+
+    const amountOf = async (pda: PublicKey) => {
+      const info = await connection.getAccountInfo(pda);
+      if (!info) throw new Error(`${pda.toBase58()} not found`);
+      return info.data.readBigUInt64LE(72);
+    };
+    if (await amountOf(positionPDA) !== BigInt(2_000_000_000)) throw new Error('Position 1 amount wrong');
+    if (await amountOf(defaultPDA) !== BigInt(1_000_000_000)) throw new Error('Default position amount wrong');
 
-    // A stakes on behalf of B
-    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, stakeAmount);
+    // Opening the same position twice fails: AlreadyInitialized = error code 1 = 0x1
+    try {
+      await ctx.initializeUserStake(user, 1);
+      throw new Error('Reopening a position should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x1')) throw new Error(`Expected AlreadyInitialized, got: ${errMsg}`);
+    }
 
-    // Wait for some maturity
-    console.log('    Waiting 10s for maturity...');
-    await new Promise(r => setTimeout(r, 10000));
+    // Empty the default position; position 1 is untouched
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    if (await amountOf(defaultPDA) !== 0n) throw new Error('Default position should be empty');
+    if (await amountOf(positionPDA) !== BigInt(2_000_000_000)) throw new Error('Position 1 should be unaffected');
+
+    // The default position records position 1, so it stays open
+    try {
+      await ctx.closeStakeAccount(user);
+      throw new Error('Closing a default position that tracks others should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      // StakeTracksPositions = error code 86 = 0x56
+      if (!errMsg.includes('0x56')) throw new Error(`Expected StakeTracksPositions, got: ${errMsg}`);
+    }
 
-    // Deposit rewards
-    const rewardAmount = BigInt(1_000_000_000); // 1 SOL
-    await ctx.depositRewards(rewardAmount);
+    await sendWithPosition(createUnstakeInstruction(
+      ctx.poolPDA, positionPDA, ctx.tokenVaultPDA, userToken, ctx.mint, user.publicKey,
+      BigInt(2_000_000_000), tokenProgramId,
+    ));
+    if (await amountOf(positionPDA) !== 0n) throw new Error('Position 1 should be empty');
 
-    // B claims rewards directly (normal ClaimRewards)
-    const bBalBefore = BigInt(await ctx.getBalance(userB.publicKey));
-    await ctx.claimRewards(userB);
-    const bBalAfter = BigInt(await ctx.getBalance(userB.publicKey));
+    // Positions open in order and below the limit:
+    // InvalidPositionIndex = error code 88 = 0x58
+    for (const index of [3, 8]) {
+      try {
+        await ctx.initializeUserStake(user, index);
+        throw new Error(`Opening position ${index} should fail`);
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes('0x58')) throw new Error(`Expected InvalidPositionIndex, got: ${errMsg}`);
+      }
+    }
 
-    const rewardReceived = bBalAfter - bBalBefore;
-    if (rewardReceived <= 0n) {
-      throw new Error(`B should have received rewards, got ${rewardReceived}`);
+    // Closing position 1 needs the default stake account:
+    // DefaultStakeRequired = error code 85 = 0x55
+    try {
+      await sendWithPosition(createCloseStakeAccountInstruction(ctx.poolPDA, positionPDA, user.publicKey));
+      throw new Error('Closing a position without the default stake account should fail');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x55')) throw new Error(`Expected DefaultStakeRequired, got: ${errMsg}`);
     }
-    console.log(`    B received ${rewardReceived} lamports in rewards`);
+    await sendWithPosition(createCloseStakeAccountInstruction(
+      ctx.poolPDA, positionPDA, user.publicKey, undefined, defaultPDA,
+    ));
+    if (await connection.getAccountInfo(positionPDA) !== null) throw new Error('Position 1 should be closed');
+
+    // With no positions left open, the default stake account closes too
+    await ctx.closeStakeAccount(user);
+    if (await connection.getAccountInfo(defaultPDA) !== null) throw new Error('Default position should be closed');
   });
 
-  await test(`[${tokenProgramLabel}] StakeOnBehalf: add-more preserves pending rewards for beneficiary`, async () => {
+  // =========================================================================
+  // GetPoolStats tests
+  // =========================================================================
+
+  await test(`[${tokenProgramLabel}] GetPoolStats: reports TVL, undistributed lamports and rate`, async () => {
     const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(BigInt(60)); // tau = 60s
-
-    const userA = Keypair.generate();
-    const userB = Keypair.generate();
-    await airdropAndConfirm(connection, userA.publicKey, 2 * LAMPORTS_PER_SOL);
-    await airdropAndConfirm(connection, userB.publicKey, LAMPORTS_PER_SOL);
+    await ctx.initializePool(BigInt(2592000));
 
-    const userAToken = await ctx.createUserTokenAccount(userA.publicKey);
-    const totalTokens = BigInt(2_000_000_000);
-    await ctx.mintTokens(userAToken, totalTokens);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await ctx.depositRewards(BigInt(100_000_000));
+
+    // Lamports sent straight to the pool wait for the next sync
+    await sendAndConfirmTransaction(connection, new Transaction().add(SystemProgram.transfer({
+      fromPubkey: ctx.payer.publicKey,
+      toPubkey: ctx.poolPDA,
+      lamports: 5_000_000,
+    })), [ctx.payer]);
+
+    const stats = await ctx.getPoolStats();
+    if (stats.version !== 1) throw new Error(`Expected version 1, got ${stats.version}`);
+    if (stats.flags !== 0) throw new Error(`Expected no flags, got ${stats.flags}`);
+    if (stats.totalStaked !== BigInt(1_000_000_000)) throw new Error(`Wrong totalStaked ${stats.totalStaked}`);
+    if (stats.accRewardPerWeightedShare === 0n) throw new Error('Accumulator should have grown');
+    if (stats.undistributedLamports !== BigInt(5_000_000)) {
+      throw new Error(`Expected 5000000 undistributed lamports, got ${stats.undistributedLamports}`);
+    }
+    const { rate } = await ctx.queryRewardRate();
+    if (stats.rewardRate !== rate) throw new Error(`Rate ${stats.rewardRate} differs from QueryRewardRate ${rate}`);
+    const expectedAnnual = rate * 31_536_000n * 10n ** 18n / BigInt(1_000_000_000);
+    if (stats.annualRewardsPerTokenWad !== expectedAnnual) {
+      throw new Error(`Annual per token ${stats.annualRewardsPerTokenWad}, expected ${expectedAnnual}`);
+    }
+  });
 
-    // A stakes first batch on behalf of B
-    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, BigInt(1_000_000_000));
+  // =========================================================================
+  // Per-top-up lock tests
+  // =========================================================================
 
-    // Wait for maturity and deposit rewards
-    console.log('    Waiting 10s for maturity...');
-    await new Promise(r => setTimeout(r, 10000));
+  await test(`[${tokenProgramLabel}] Top-up mid-lock: original tokens unlock on their own schedule`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(6), null);
 
-    const rewardAmount = BigInt(1_000_000_000);
-    await ctx.depositRewards(rewardAmount);
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_500_000_000));
 
-    // A adds more stake on behalf of B — no auto-claim, pending preserved
-    await ctx.stakeOnBehalf(userA, userAToken, userB.publicKey, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.stake(user, userToken, BigInt(500_000_000));
+    await new Promise(r => setTimeout(r, 4000));
 
-    // Verify combined stake amount
-    const userStakeState = await ctx.readUserStakeState(userB.publicKey);
-    if (userStakeState.amount !== totalTokens) {
-      throw new Error(`Expected combined stake ${totalTokens}, got ${userStakeState.amount}`);
-    }
+    // StakeLocked = error code 22 = 0x16
+    const expectLocked = async (label: string, amount: bigint) => {
+      try {
+        await ctx.unstake(user, userToken, amount);
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes('0x16')) throw new Error(`${label}: expected StakeLocked, got: ${errMsg}`);
+        return;
+      }
+      throw new Error(`${label} should be locked`);
+    };
 
-    // B can still claim accumulated rewards
-    const bBalBefore = BigInt(await ctx.getBalance(userB.publicKey));
-    await ctx.claimRewards(userB);
-    const bBalAfter = BigInt(await ctx.getBalance(userB.publicKey));
-    const bRewards = bBalAfter - bBalBefore;
+    // The first stake's lock is over, the top-up's is not
+    await expectLocked('Unstaking into the top-up', BigInt(1_000_000_001));
+    await ctx.unstake(user, userToken, BigInt(1_000_000_000));
+    await expectLocked('Unstaking the top-up', BigInt(500_000_000));
 
-    if (bRewards <= 0n) {
-      throw new Error(`B should have claimable rewards after add-stake, got ${bRewards}`);
-    }
-    console.log(`    B claimed ${bRewards} lamports after add-stake on behalf`);
-    console.log('    Add-more preserves pending rewards for beneficiary: OK');
+    await new Promise(r => setTimeout(r, 3000));
+    await ctx.unstake(user, userToken, BigInt(500_000_000));
+    const state = await ctx.readUserStakeState(user.publicKey);
+    if (state.amount !== 0n) throw new Error(`Expected an empty stake, got ${state.amount}`);
   });
 
   // =========================================================================
-  // FixStakeAccount tests
+  // Depositor allowlist tests
   // =========================================================================
 
-  // Test: FixStakeAccount corrects exp_start_factor and reward_debt
-  await test(`[${tokenProgramLabel}] FixStakeAccount: corrects user state and pool aggregates`, async () => {
-    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
+  await test(`[${tokenProgramLabel}] Depositor allowlist: only allowlisted depositors may deposit`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(60n); // tau = 60s (minimum)
+    await ctx.initializePool(BigInt(2592000));
 
-    const alice = Keypair.generate();
-    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    await ctx.mintTokens(userToken, BigInt(1_000_000_000));
+    await ctx.stake(user, userToken, BigInt(1_000_000_000));
 
-    // Alice stakes
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    const depositor = Keypair.generate();
+    await airdropAndConfirm(connection, depositor.publicKey, LAMPORTS_PER_SOL);
+    const [entryPDA] = deriveDepositorAllowlistPDA(ctx.poolPDA, depositor.publicKey);
+    const deposit = async (withEntry: boolean) => sendAndConfirmTransaction(connection, new Transaction().add(
+      createDepositRewardsInstruction(
+        ctx.poolPDA, depositor.publicKey, BigInt(10_000_000), null, null, null, null,
+        withEntry ? entryPDA : null,
+      ),
+    ), [depositor]);
 
-    // Deposit rewards so acc_rps is non-zero
-    console.log('    Waiting 10s for weight...');
-    await new Promise(r => setTimeout(r, 10000));
-    await ctx.depositRewards(BigInt(LAMPORTS_PER_SOL));
+    // Flag off: permissionless, no entry needed
+    await deposit(false);
 
-    // Read current state
-    const stateBefore = await ctx.readUserStakeState(alice.publicKey);
-    const poolBefore = await ctx.readPoolState();
-    console.log(`    Before: exp_start_factor=${stateBefore.expStartFactor}, reward_debt=${stateBefore.rewardDebt}`);
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { requireAllowlistedDepositors: true });
 
-    // Fix with new values (simulate correcting a blending bug)
-    const newExpStartFactor = stateBefore.expStartFactor + 1000n;
-    const newRewardDebt = stateBefore.rewardDebt + 500n;
-    await ctx.fixStakeAccount(programAuthority, alice.publicKey, newExpStartFactor, newRewardDebt);
+    // DepositorNotAllowlisted = error code 81 = 0x51
+    const expectNotAllowlisted = async (withEntry: boolean, what: string) => {
+      try {
+        await deposit(withEntry);
+        throw new Error(`${what} should fail`);
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes('0x51')) throw new Error(`Expected DepositorNotAllowlisted, got: ${errMsg}`);
+      }
+    };
+    await expectNotAllowlisted(false, 'Deposit without an entry');
+    await expectNotAllowlisted(true, 'Deposit before AddDepositor');
 
-    // Verify user state was updated
-    const stateAfter = await ctx.readUserStakeState(alice.publicKey);
-    if (stateAfter.expStartFactor !== newExpStartFactor) {
-      throw new Error(`exp_start_factor not updated: expected ${newExpStartFactor}, got ${stateAfter.expStartFactor}`);
-    }
-    if (stateAfter.rewardDebt !== newRewardDebt) {
-      throw new Error(`reward_debt not updated: expected ${newRewardDebt}, got ${stateAfter.rewardDebt}`);
+    // Only the authority (or a settings delegate) manages the allowlist:
+    // InvalidAuthority = error code 6 = 0x6
+    try {
+      await ctx.addDepositor(depositor, depositor.publicKey);
+      throw new Error('Depositor should not allowlist itself');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x6')) throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
     }
 
-    // Verify pool aggregates were adjusted
-    const poolAfter = await ctx.readPoolState();
-    const debtDelta = poolAfter.totalRewardDebt - poolBefore.totalRewardDebt;
-    if (debtDelta !== 500n) {
-      throw new Error(`Pool total_reward_debt delta: expected 500, got ${debtDelta}`);
+    await ctx.addDepositor(ctx.payer, depositor.publicKey);
+    const before = (await ctx.readPoolState()).accRewardPerWeightedShare;
+    await deposit(true);
+    if ((await ctx.readPoolState()).accRewardPerWeightedShare <= before) {
+      throw new Error('Allowlisted deposit should be distributed');
     }
-    console.log(`    After: exp_start_factor=${stateAfter.expStartFactor}, reward_debt=${stateAfter.rewardDebt}`);
-    console.log('    Pool aggregates correctly adjusted');
+
+    // Removing the entry returns its rent and blocks the depositor again
+    await ctx.removeDepositor(ctx.payer, depositor.publicKey);
+    if (await connection.getAccountInfo(entryPDA) !== null) throw new Error('Entry should be closed');
+    await expectNotAllowlisted(true, 'Deposit after RemoveDepositor');
   });
 
-  // Test: FixStakeAccount rejects non-upgrade-authority signer
-  await test(`[${tokenProgramLabel}] FixStakeAccount: rejects non-upgrade-authority`, async () => {
-    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
+  // ========================================
+  // Settings timelock tests
+  // ========================================
+  await test(`[${tokenProgramLabel}] Settings timelock: changes that hurt stakers wait for ApplySettings`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(60n); // tau = 60s (minimum)
+    await ctx.initializePool(BigInt(2592000));
 
-    const alice = Keypair.generate();
-    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    // Try to fix using a random keypair (not upgrade authority)
-    const faker = Keypair.generate();
-    await airdropAndConfirm(connection, faker.publicKey, LAMPORTS_PER_SOL);
-    try {
-      await ctx.fixStakeAccount(faker, alice.publicKey, 0n, 0n);
-      throw new Error('Should have rejected non-upgrade-authority');
-    } catch (e: any) {
-      if (e.message === 'Should have rejected non-upgrade-authority') throw e;
-      console.log('    Correctly rejected non-upgrade-authority signer');
+    // NoPendingSettings = error code 82 = 0x52
+    await expectError('ApplySettings with nothing queued', '0x52', () => ctx.applySettings(ctx.payer));
+
+    // Turning the timelock on is not delayed
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(60), { settingsTimelockSeconds: BigInt(86400) });
+    let pending = await ctx.queryPendingSettings();
+    if (pending.settingsTimelockSeconds !== BigInt(86400) || pending.mask !== 0) {
+      throw new Error(`Timelock should apply immediately: ${JSON.stringify(pending, (_, v) => typeof v === 'bigint' ? v.toString() : v)}`);
     }
-  });
 
-  // Test: FixStakeAccount allows user to claim corrected rewards
-  await test(`[${tokenProgramLabel}] FixStakeAccount: user can claim corrected rewards after fix`, async () => {
-    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
-    await ctx.setup();
-    await ctx.createMint(9);
-    await ctx.initializePool(60n); // tau = 60s (minimum)
+    // Raising the cooldown is queued (PENDING_UNSTAKE_COOLDOWN = 2)
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(3600));
+    pending = await ctx.queryPendingSettings();
+    if (pending.mask !== 2 || pending.unstakeCooldownSeconds !== BigInt(3600)) {
+      throw new Error(`Cooldown increase should be pending, got mask ${pending.mask}`);
+    }
+    const now = BigInt(Math.floor(Date.now() / 1000));
+    if (pending.effectiveTime < now + BigInt(86000)) {
+      throw new Error(`Effective time ${pending.effectiveTime} should be a day out`);
+    }
 
-    const alice = Keypair.generate();
-    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    // SettingsTimelockActive = error code 83 = 0x53
+    await expectError('Early ApplySettings', '0x53', () => ctx.applySettings(ctx.payer));
 
-    // Alice stakes
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
+    // Lowering the cooldown applies at once and replaces the queued raise
+    await ctx.updatePoolSettings(ctx.payer, null, null, BigInt(30));
+    pending = await ctx.queryPendingSettings();
+    if (pending.mask !== 0 || pending.effectiveTime !== BigInt(0)) {
+      throw new Error(`Lowering should cancel the pending raise, got mask ${pending.mask}`);
+    }
 
-    // Wait for partial maturity and deposit rewards
-    console.log('    Waiting 15s for weight...');
-    await new Promise(r => setTimeout(r, 15000));
-    await ctx.depositRewards(BigInt(2 * LAMPORTS_PER_SOL));
+    // Proposing the current value cancels a pending change as well
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(600), null);
+    if ((await ctx.queryPendingSettings()).mask !== 1) throw new Error('Lock increase should be pending');
+    await ctx.updatePoolSettings(ctx.payer, null, BigInt(0), null);
+    if ((await ctx.queryPendingSettings()).mask !== 0) throw new Error('Current value should cancel the lock increase');
+
+    // Reweighting and a higher fee split wait too (PENDING_LOYALTY_BONUS = 32, PENDING_FEE_SPLIT = 256)
+    const treasury = Keypair.generate().publicKey;
+    await ctx.updatePoolSettings(ctx.payer, null, null, null, { loyaltyBonusBps: 500n });
+    await ctx.setRewardFeeSplit(ctx.payer, [{ recipient: treasury, bps: 300 }]);
+    pending = await ctx.queryPendingSettings();
+    if (pending.mask !== (32 | 256) || pending.loyaltyBonusBps !== 500n) {
+      throw new Error(`Loyalty bonus and fee split should be pending, got mask ${pending.mask}`);
+    }
+    if (!pending.feeSplit[0].recipient.equals(treasury) || pending.feeSplit[0].bps !== 300) {
+      throw new Error('Pending fee split should be returned');
+    }
 
-    // Read state before any claim — reward_debt is 0 (staked before deposits)
-    const stateBeforeFix = await ctx.readUserStakeState(alice.publicKey);
+    // The claim fee is capped together with the pending split
+    // SettingExceedsMaximum = error code 29 = 0x1d
+    await expectError('Claim fee over the cap with the pending split', '0x1d', () =>
+      ctx.updatePoolSettings(ctx.payer, null, null, null, { claimFeeBps: 800n }));
+  });
 
-    // Simulate the add-stake bug: RAISE reward_debt so the snapshot is too high,
-    // which suppresses claimable rewards.
-    const WAD = 1_000_000_000_000_000_000n;
-    const inflatedDebt = stateBeforeFix.rewardDebt + BigInt(LAMPORTS_PER_SOL) * WAD;
-    await ctx.fixStakeAccount(programAuthority, alice.publicKey, stateBeforeFix.expStartFactor, inflatedDebt);
+  // ========================================
+  // ReclaimDeferredRewards tests
+  // ========================================
+  await test(`[${tokenProgramLabel}] ReclaimDeferredRewards: returns rewards deposited with nobody staked`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
+    await ctx.setup();
+    await ctx.createMint(9);
+    await ctx.initializePool(BigInt(2592000));
 
-    // Claim with inflated debt — gives reduced rewards (snapshot too high)
-    const balBefore = BigInt(await ctx.getBalance(alice.publicKey));
-    await ctx.claimRewards(alice);
-    const balAfterFirstClaim = BigInt(await ctx.getBalance(alice.publicKey));
-    const firstClaim = balAfterFirstClaim - balBefore;
-    console.log(`    First claim (with inflated debt): ${firstClaim} lamports`);
+    // Deposited with nobody staked: deferred
+    const deferred = BigInt(LAMPORTS_PER_SOL / 10);
+    await ctx.depositRewards(deferred);
 
-    // Now fix: lower reward_debt back to the correct value
-    await ctx.fixStakeAccount(programAuthority, alice.publicKey, stateBeforeFix.expStartFactor, stateBeforeFix.rewardDebt);
+    // Only the authority may reclaim: InvalidAuthority = error code 6 = 0x6
+    const recipient = Keypair.generate().publicKey;
+    const mallory = Keypair.generate();
+    await airdropAndConfirm(connection, mallory.publicKey, LAMPORTS_PER_SOL);
+    try {
+      await ctx.reclaimDeferredRewards(mallory, mallory.publicKey);
+      throw new Error('Non-authority should not reclaim');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x6')) throw new Error(`Expected InvalidAuthority (0x6), got: ${errMsg}`);
+    }
 
-    // Alice should now be able to claim the rewards that were suppressed
-    const balBeforeSecondClaim = BigInt(await ctx.getBalance(alice.publicKey));
-    await ctx.claimRewards(alice);
-    const balAfterSecondClaim = BigInt(await ctx.getBalance(alice.publicKey));
-    const secondClaim = balAfterSecondClaim - balBeforeSecondClaim;
-    console.log(`    Second claim after fix: ${secondClaim} lamports`);
+    await ctx.reclaimDeferredRewards(ctx.payer, recipient);
+    const reclaimed = BigInt(await ctx.getBalance(recipient));
+    if (reclaimed !== deferred) throw new Error(`Reclaimed ${reclaimed}, expected ${deferred}`);
 
-    if (secondClaim <= 0n) {
-      throw new Error('Alice should have additional claimable rewards after fix');
+    // A later staker only earns what is deposited after it stakes
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const staked = BigInt(1_000_000_000);
+    await ctx.mintTokens(userToken, staked);
+    await ctx.stake(user, userToken, staked);
+    const deposit = BigInt(LAMPORTS_PER_SOL / 20);
+    await ctx.depositRewards(deposit);
+    const WAD = 1_000_000_000_000_000_000n;
+    const acc = (await ctx.readPoolState()).accRewardPerWeightedShare;
+    if (acc !== deposit * WAD / staked) {
+      throw new Error(`Accumulator ${acc} should only reflect the ${deposit} lamport deposit`);
+    }
+
+    // Refused once tokens are staked: PoolHasStake = error code 57 = 0x39
+    await ctx.depositRewards(deposit);
+    try {
+      await ctx.reclaimDeferredRewards(ctx.payer, recipient);
+      throw new Error('Reclaim should fail while tokens are staked');
+    } catch (e) {
+      const errMsg = (e as any).message || '';
+      if (!errMsg.includes('0x39')) throw new Error(`Expected PoolHasStake (0x39), got: ${errMsg}`);
     }
-    console.log('    User successfully claimed corrected rewards');
   });
 
-  // Test: FixStakeAccount rejects zero-balance stake
-  await test(`[${tokenProgramLabel}] FixStakeAccount: rejects zero-balance stake account`, async () => {
-    const ctx = new TestContext(connection, payer, programAuthority, tokenProgramId);
+  // ========================================
+  // DepositRewards referral tests
+  // ========================================
+  await test(`[${tokenProgramLabel}] DepositRewards referral: referrer gets its cut, stakers the rest`, async () => {
+    const ctx = new TestContext(connection, Keypair.generate(), programAuthority, tokenProgramId);
     await ctx.setup();
     await ctx.createMint(9);
-    await ctx.initializePool(60n); // tau = 60s (minimum)
+    await ctx.initializePool(BigInt(2592000));
 
-    const alice = Keypair.generate();
-    await airdropAndConfirm(connection, alice.publicKey, 2 * LAMPORTS_PER_SOL);
-    const aliceToken = await ctx.createUserTokenAccount(alice.publicKey);
-    await ctx.mintTokens(aliceToken, BigInt(1_000_000_000));
+    const user = Keypair.generate();
+    await airdropAndConfirm(connection, user.publicKey, LAMPORTS_PER_SOL);
+    const userToken = await ctx.createUserTokenAccount(user.publicKey);
+    const staked = BigInt(1_000_000_000);
+    await ctx.mintTokens(userToken, staked);
+    await ctx.stake(user, userToken, staked);
+
+    const referrer = Keypair.generate().publicKey;
+    await airdropAndConfirm(connection, referrer, LAMPORTS_PER_SOL);
+    const deposit = (amount: bigint, referral: DepositReferral) => sendAndConfirmTransaction(connection, new Transaction().add(
+      createDepositRewardsInstruction(
+        ctx.poolPDA, ctx.payer.publicKey, amount, null, null, null, null, null, referral,
+      ),
+    ), [ctx.payer]);
+
+    // 2.5% of the deposit goes to the referrer, the rest to stakers
+    const amount = BigInt(LAMPORTS_PER_SOL / 10);
+    const cut = amount * BigInt(250) / BigInt(10000);
+    const referrerBefore = BigInt(await ctx.getBalance(referrer));
+    await deposit(amount, { referrer, referralBps: BigInt(250) });
+    const paid = BigInt(await ctx.getBalance(referrer)) - referrerBefore;
+    if (paid !== cut) throw new Error(`Referrer got ${paid}, expected ${cut}`);
+    const WAD = 1_000_000_000_000_000_000n;
+    const acc = (await ctx.readPoolState()).accRewardPerWeightedShare;
+    if (acc !== (amount - cut) * WAD / staked) {
+      throw new Error(`Accumulator ${acc} should only reflect the ${amount - cut} lamports left for stakers`);
+    }
 
-    // Alice stakes then fully unstakes
-    await ctx.stake(alice, aliceToken, BigInt(1_000_000_000));
-    await ctx.unstake(alice, aliceToken, BigInt(1_000_000_000));
+    const expectError = async (label: string, code: string, action: () => Promise<string>) => {
+      try {
+        await action();
+      } catch (e) {
+        const errMsg = (e as any).message || '';
+        if (!errMsg.includes(code)) {
+          throw new Error(`${label}: expected ${code}, got: ${errMsg}`);
+        }
+        return;
+      }
+      throw new Error(`${label} should fail`);
+    };
 
-    try {
-      await ctx.fixStakeAccount(programAuthority, alice.publicKey, 0n, 0n);
-      throw new Error('Should have rejected zero-balance stake');
-    } catch (e: any) {
-      if (e.message === 'Should have rejected zero-balance stake') throw e;
-      console.log('    Correctly rejected zero-balance stake account');
-    }
+    // ReferralExceedsMaximum = error code 84 = 0x54
+    await expectError('Referral above 1000 bps', '0x54', () => deposit(amount, { referrer, referralBps: BigInt(1001) }));
+    // InvalidReferrer = error code 53 = 0x35
+    await expectError('Self-referral', '0x35', () =>
+      deposit(amount, { referrer: ctx.payer.publicKey, referralBps: BigInt(250) }));
   });
 
   } // end of token program loop